@@ -0,0 +1,86 @@
+//! Queue of the blocks waiting for their chunks to be applied.
+//!
+//! Applying the chunks of a block is scheduled on the rayon thread pool, but a scheduled task
+//! doesn't apply the block it was scheduled for. It takes the next block from this queue instead,
+//! so that a block prioritized after it was scheduled, e.g. because the node is going to produce
+//! the next block on top of it, is applied as soon as a thread is free.
+use near_primitives::hash::CryptoHash;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+
+pub(crate) struct ApplyChunksQueue<T> {
+    inner: Mutex<ApplyChunksQueueInner<T>>,
+}
+
+struct ApplyChunksQueueInner<T> {
+    /// Blocks waiting for a thread, in the order they were scheduled.
+    jobs: VecDeque<(CryptoHash, T)>,
+    /// Blocks to apply before the others, whether they are queued yet or not.
+    prioritized: HashSet<CryptoHash>,
+}
+
+impl<T> ApplyChunksQueue<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: Mutex::new(ApplyChunksQueueInner {
+                jobs: VecDeque::new(),
+                prioritized: HashSet::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn push(&self, block_hash: CryptoHash, job: T) {
+        self.inner.lock().unwrap().jobs.push_back((block_hash, job));
+    }
+
+    /// Pops the oldest job of a prioritized block, or the oldest job if no prioritized block is
+    /// queued.
+    pub(crate) fn pop(&self) -> Option<(CryptoHash, T)> {
+        let mut inner = self.inner.lock().unwrap();
+        let index = inner
+            .jobs
+            .iter()
+            .position(|(block_hash, _)| inner.prioritized.contains(block_hash))
+            .unwrap_or(0);
+        inner.jobs.remove(index)
+    }
+
+    pub(crate) fn prioritize(&self, block_hash: CryptoHash) {
+        self.inner.lock().unwrap().prioritized.insert(block_hash);
+    }
+
+    pub(crate) fn is_prioritized(&self, block_hash: &CryptoHash) -> bool {
+        self.inner.lock().unwrap().prioritized.contains(block_hash)
+    }
+
+    /// Forgets the priority of a block which finished processing.
+    pub(crate) fn remove_priority(&self, block_hash: &CryptoHash) {
+        self.inner.lock().unwrap().prioritized.remove(block_hash);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApplyChunksQueue;
+    use near_primitives::hash::CryptoHash;
+
+    #[test]
+    fn test_prioritized_jobs_are_popped_first() {
+        let queue = ApplyChunksQueue::new();
+        let hashes: Vec<_> = (0..4u64).map(CryptoHash::hash_borsh).collect();
+        for (i, block_hash) in hashes.iter().enumerate() {
+            queue.push(*block_hash, i);
+        }
+        queue.prioritize(hashes[2]);
+        // A block can be prioritized before it's queued.
+        let late_hash = CryptoHash::hash_borsh(4u64);
+        queue.prioritize(late_hash);
+        queue.push(late_hash, 4);
+
+        let popped: Vec<_> = std::iter::from_fn(|| queue.pop()).map(|(_, job)| job).collect();
+        assert_eq!(popped, vec![2, 4, 0, 1, 3]);
+        assert!(queue.is_prioritized(&hashes[2]));
+        queue.remove_priority(&hashes[2]);
+        assert!(!queue.is_prioritized(&hashes[2]));
+    }
+}
@@ -0,0 +1,87 @@
+//! Resolution of block references against the local chain.
+//!
+//! The shard uid of a shard id depends on the shard layout of the epoch it is looked up in, so
+//! around a resharding the final head and the head may disagree about it. Tools and debug pages
+//! resolve the block they look at through these helpers so that they agree with each other on
+//! which epoch is used.
+use crate::{BlockHeader, ChainStoreAccess, Error};
+use near_epoch_manager::EpochManagerAdapter;
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::types::{BlockId, BlockReference, Finality, ShardId, SyncCheckpoint};
+
+/// Returns the header of the block `block_reference` points to.
+pub fn resolve_block_header(
+    chain_store: &dyn ChainStoreAccess,
+    block_reference: &BlockReference,
+) -> Result<BlockHeader, Error> {
+    let block_hash = match block_reference {
+        BlockReference::BlockId(BlockId::Height(height)) => {
+            return chain_store.get_block_header_by_height(*height);
+        }
+        BlockReference::BlockId(BlockId::Hash(block_hash)) => *block_hash,
+        BlockReference::Finality(Finality::None) => chain_store.head()?.last_block_hash,
+        BlockReference::Finality(Finality::DoomSlug) => {
+            *chain_store.head_header()?.last_ds_final_block()
+        }
+        BlockReference::Finality(Finality::Final) => chain_store.final_head()?.last_block_hash,
+        BlockReference::SyncCheckpoint(SyncCheckpoint::Genesis) => {
+            return chain_store.get_block_header_by_height(chain_store.get_genesis_height());
+        }
+        BlockReference::SyncCheckpoint(SyncCheckpoint::EarliestAvailable) => chain_store
+            .get_earliest_block_hash()?
+            .ok_or_else(|| Error::DBNotFoundErr("earliest available block".to_string()))?,
+    };
+    chain_store.get_block_header(&block_hash)
+}
+
+/// Returns the uid of `shard_id` in the shard layout of the epoch of the block `block_reference`
+/// points to.
+pub fn resolve_shard_uid_at(
+    chain_store: &dyn ChainStoreAccess,
+    epoch_manager: &dyn EpochManagerAdapter,
+    shard_id: ShardId,
+    block_reference: &BlockReference,
+) -> Result<ShardUId, Error> {
+    let header = resolve_block_header(chain_store, block_reference)?;
+    Ok(epoch_manager.shard_id_to_uid(shard_id, header.epoch_id())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_block_header, resolve_shard_uid_at};
+    use crate::test_utils::{setup_with_validators, ValidatorSchedule};
+    use near_primitives::test_utils::TestBlockBuilder;
+    use near_primitives::types::{BlockId, BlockReference, Finality};
+
+    /// At an epoch boundary the head can already be in an epoch with a new shard layout while the
+    /// final head is still in the previous one.
+    #[test]
+    fn test_resolve_shard_uid_at_epoch_boundary() {
+        let vs =
+            ValidatorSchedule::new().block_producers_per_epoch(vec![vec!["test".parse().unwrap()]]);
+        let (mut chain, epoch_manager, _, signers) = setup_with_validators(vs, 5, 100);
+        let signer = signers[0].clone();
+        while chain.head().unwrap().epoch_id == chain.final_head().unwrap().epoch_id {
+            assert!(chain.head().unwrap().height < 20, "no epoch boundary between heads");
+            let prev = chain.get_block(&chain.head().unwrap().last_block_hash).unwrap();
+            let block = TestBlockBuilder::new(&prev, signer.clone()).build();
+            chain.process_block_test(&None, block).unwrap();
+        }
+        let head = chain.head().unwrap();
+        let final_head = chain.final_head().unwrap();
+        epoch_manager.set_shard_layout_version(head.epoch_id.clone(), 1);
+
+        let store = chain.store();
+        let resolve = |block_reference: BlockReference| {
+            resolve_shard_uid_at(store, epoch_manager.as_ref(), 0, &block_reference).unwrap()
+        };
+        assert_eq!(resolve(Finality::None.into()).version, 1);
+        assert_eq!(resolve(BlockId::Hash(head.last_block_hash).into()).version, 1);
+        assert_eq!(resolve(Finality::Final.into()).version, 0);
+        assert_eq!(resolve(BlockId::Height(final_head.height).into()).version, 0);
+        assert_eq!(
+            resolve_block_header(store, &Finality::Final.into()).unwrap().hash(),
+            &final_head.last_block_hash
+        );
+    }
+}
@@ -1,5 +1,7 @@
+use crate::apply_chunks_queue::ApplyChunksQueue;
 use crate::block_processing_utils::{
     BlockPreprocessInfo, BlockProcessingArtifact, BlocksInProcessing, DoneApplyChunkCallback,
+    MAX_PROCESSING_BLOCKS,
 };
 use crate::blocks_delay_tracker::BlocksDelayTracker;
 use crate::crypto_hash_timer::CryptoHashTimer;
@@ -167,6 +169,14 @@ impl BlockLike for Orphan {
     fn height(&self) -> u64 {
         self.block.header().height()
     }
+
+    fn added(&self) -> Instant {
+        self.added
+    }
+
+    fn size_bytes(&self) -> usize {
+        borsh::object_length(self.block.get_inner()).unwrap_or_default()
+    }
 }
 
 impl Orphan {
@@ -478,6 +488,9 @@ pub struct Chain {
     apply_chunks_sender: Sender<BlockApplyChunksResult>,
     /// Used to receive apply chunks results
     apply_chunks_receiver: Receiver<BlockApplyChunksResult>,
+    /// Blocks waiting for their chunks to be applied on the rayon thread pool, and the blocks to
+    /// apply and postprocess before the others. See `prioritize_block`.
+    apply_chunks_queue: Arc<ApplyChunksQueue<Box<dyn FnOnce() + Send>>>,
     /// Time when head was updated most recently.
     last_time_head_updated: Instant,
     /// Prevents re-application of known-to-be-invalid blocks, so that in case of a
@@ -583,7 +596,7 @@ impl Chain {
             shard_tracker,
             runtime_adapter,
             orphans: OrphanBlockPool::new(),
-            blocks_with_missing_chunks: MissingChunksPool::new(),
+            blocks_with_missing_chunks: MissingChunksPool::default(),
             blocks_in_processing: BlocksInProcessing::new(),
             genesis,
             transaction_validity_period: chain_genesis.transaction_validity_period,
@@ -593,6 +606,7 @@ impl Chain {
             blocks_delay_tracker: BlocksDelayTracker::default(),
             apply_chunks_sender: sc,
             apply_chunks_receiver: rc,
+            apply_chunks_queue: Arc::new(ApplyChunksQueue::new()),
             last_time_head_updated: StaticClock::instant(),
             invalid_blocks: LruCache::new(INVALID_CHUNKS_POOL_SIZE),
             pending_state_patch: Default::default(),
@@ -759,7 +773,9 @@ impl Chain {
             shard_tracker,
             runtime_adapter,
             orphans: OrphanBlockPool::new(),
-            blocks_with_missing_chunks: MissingChunksPool::new(),
+            blocks_with_missing_chunks: MissingChunksPool::new(
+                chain_config.missing_chunks_pool_limits.clone(),
+            ),
             blocks_in_processing: BlocksInProcessing::new(),
             invalid_blocks: LruCache::new(INVALID_CHUNKS_POOL_SIZE),
             genesis: genesis.clone(),
@@ -770,6 +786,7 @@ impl Chain {
             blocks_delay_tracker: Default::default(),
             apply_chunks_sender: sc,
             apply_chunks_receiver: rc,
+            apply_chunks_queue: Arc::new(ApplyChunksQueue::new()),
             last_time_head_updated: StaticClock::instant(),
             pending_state_patch: Default::default(),
             requested_state_parts: StateRequestTracker::new(),
@@ -1842,7 +1859,12 @@ impl Chain {
         let _span = debug_span!(target: "chain", "postprocess_ready_blocks_chain").entered();
         let mut accepted_blocks = vec![];
         let mut errors = HashMap::new();
-        while let Ok((block_hash, apply_result)) = self.apply_chunks_receiver.try_recv() {
+        let mut ready_blocks: Vec<_> = self.apply_chunks_receiver.try_iter().collect();
+        // Prioritized blocks go first, the others keep the order in which they became ready.
+        ready_blocks
+            .sort_by_key(|(block_hash, _)| !self.apply_chunks_queue.is_prioritized(block_hash));
+        for (block_hash, apply_result) in ready_blocks {
+            self.apply_chunks_queue.remove_priority(&block_hash);
             match self.postprocess_block(
                 me,
                 block_hash,
@@ -1861,6 +1883,15 @@ impl Chain {
         (accepted_blocks, errors)
     }
 
+    /// Marks a block being processed as urgent, e.g. because this node is going to produce the
+    /// next block on top of it: its chunks are applied before the chunks of the blocks scheduled
+    /// earlier which haven't started yet, and it's postprocessed before the other ready blocks.
+    pub fn prioritize_block(&self, block_hash: &CryptoHash) {
+        if self.blocks_in_processing.contains(block_hash) {
+            self.apply_chunks_queue.prioritize(*block_hash);
+        }
+    }
+
     /// Process challenge to invalidate chain. This is done between blocks to unroll the chain as
     /// soon as possible and allow next block producer to skip invalid blocks.
     pub fn process_challenge(&mut self, challenge: &Challenge) {
@@ -2252,17 +2283,28 @@ impl Chain {
         apply_chunks_done_callback: DoneApplyChunkCallback,
     ) {
         let sc = self.apply_chunks_sender.clone();
+        self.apply_chunks_queue.push(
+            block_hash,
+            Box::new(move || {
+                // do_apply_chunks runs `work` in parallel, but still waits for all of them to finish
+                let res = do_apply_chunks(block_hash, block_height, work);
+                // If we encounter error here, that means the receiver is deallocated and the client
+                // thread is already shut down. The node is already crashed, so we can unwrap here
+                sc.send((block_hash, res)).unwrap();
+                if let Err(_) = apply_chunks_done_marker.set(()) {
+                    // This should never happen, if it does, it means there is a bug in our code.
+                    log_assert!(false, "apply chunks are called twice for block {block_hash:?}");
+                }
+                apply_chunks_done_callback(block_hash);
+            }),
+        );
+        // The task doesn't necessarily apply the block pushed above, but the oldest prioritized
+        // block if there is one. Each pushed block gets exactly one task, so all are applied.
+        let queue = self.apply_chunks_queue.clone();
         spawn(move || {
-            // do_apply_chunks runs `work` in parallel, but still waits for all of them to finish
-            let res = do_apply_chunks(block_hash, block_height, work);
-            // If we encounter error here, that means the receiver is deallocated and the client
-            // thread is already shut down. The node is already crashed, so we can unwrap here
-            sc.send((block_hash, res)).unwrap();
-            if let Err(_) = apply_chunks_done_marker.set(()) {
-                // This should never happen, if it does, it means there is a bug in our code.
-                log_assert!(false, "apply chunks are called twice for block {block_hash:?}");
+            if let Some((_, job)) = queue.pop() {
+                job();
             }
-            apply_chunks_done_callback(block_hash);
         });
 
         /// `rayon::spawn` decorated to propagate `tracing` context across
@@ -2773,13 +2815,17 @@ impl Chain {
     }
 
     /// Check if any block with missing chunk is ready to be processed and start processing these blocks
+    /// in ascending height order. Only as many blocks are started as the budget of the pool and
+    /// the free processing slots allow, the rest are started by the next calls.
     pub fn check_blocks_with_missing_chunks(
         &mut self,
         me: &Option<AccountId>,
         block_processing_artifact: &mut BlockProcessingArtifact,
         apply_chunks_done_callback: DoneApplyChunkCallback,
     ) {
-        let blocks = self.blocks_with_missing_chunks.ready_blocks();
+        let free_processing_slots =
+            MAX_PROCESSING_BLOCKS.saturating_sub(self.blocks_in_processing.len());
+        let blocks = self.blocks_with_missing_chunks.take_ready_blocks(free_processing_slots);
         if !blocks.is_empty() {
             debug!(target:"chain", "Got {} blocks that were missing chunks but now are ready.", blocks.len());
         }
@@ -4067,6 +4113,9 @@ impl Chain {
             return Ok(None);
         };
 
+        let record_storage = is_new_chunk
+            && should_apply_transactions
+            && self.produces_next_chunk(me, block, shard_id, will_shard_layout_change)?;
         let runtime = self.runtime_adapter.clone();
         let epoch_manager = self.epoch_manager.clone();
         let block_context = self.get_block_context_for_shard_update(
@@ -4082,12 +4131,40 @@ impl Chain {
                 epoch_manager.as_ref(),
                 shard_update_reason,
                 block_context,
-                ShardContext { shard_uid, will_shard_layout_change },
+                ShardContext { shard_uid, will_shard_layout_change, record_storage },
                 state_patch,
             )?)
         })))
     }
 
+    /// Whether `me` produces the next chunk of the shard on top of `block`, so that applying the
+    /// chunk of the block has to record the storage for the state witness of that chunk.
+    fn produces_next_chunk(
+        &self,
+        me: &Option<AccountId>,
+        block: &Block,
+        shard_id: ShardId,
+        will_shard_layout_change: bool,
+    ) -> Result<bool, Error> {
+        if !cfg!(feature = "protocol_feature_chunk_validation") {
+            return Ok(false);
+        }
+        let Some(me) = me else {
+            return Ok(false);
+        };
+        // After the shard layout changes the next chunk is of another shard.
+        if will_shard_layout_change && self.epoch_manager.is_next_block_epoch_start(block.hash())? {
+            return Ok(false);
+        }
+        let epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(block.hash())?;
+        let chunk_producer = self.epoch_manager.get_chunk_producer(
+            &epoch_id,
+            block.header().height() + 1,
+            shard_id,
+        )?;
+        Ok(&chunk_producer == me)
+    }
+
     /// Function to create a new snapshot if needed
     fn process_snapshot(&mut self) -> Result<(), Error> {
         let (make_snapshot, delete_snapshot) = self.should_make_or_delete_snapshot()?;
@@ -400,6 +400,11 @@ impl Doomslug {
         self.threshold_mode = DoomslugThresholdMode::NoApprovals
     }
 
+    /// Whether the block production waits for approvals.
+    pub fn threshold_mode(&self) -> DoomslugThresholdMode {
+        self.threshold_mode
+    }
+
     /// Returns the `(hash, height)` of the current tip. Currently is only used by tests.
     pub fn get_tip(&self) -> (CryptoHash, BlockHeight) {
         (self.tip.block_hash, self.tip.height)
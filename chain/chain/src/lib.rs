@@ -1,4 +1,5 @@
 pub use block_processing_utils::{BlockProcessingArtifact, DoneApplyChunkCallback};
+pub use block_reference::{resolve_block_header, resolve_shard_uid_at};
 pub use chain::{check_known, collect_receipts, Chain, ChainUpdate, MAX_ORPHAN_SIZE};
 pub use doomslug::{Doomslug, DoomslugBlockProductionReadiness, DoomslugThresholdMode};
 pub use lightclient::{create_light_client_block_view, get_epoch_block_producers_view};
@@ -8,7 +9,9 @@ pub use store::{ChainStore, ChainStoreAccess, ChainStoreUpdate};
 pub use store_validator::{ErrorMessage, StoreValidator};
 pub use types::{Block, BlockHeader, BlockStatus, ChainGenesis, Provenance};
 
+mod apply_chunks_queue;
 mod block_processing_utils;
+mod block_reference;
 pub mod blocks_delay_tracker;
 pub mod chain;
 pub mod chunks_store;
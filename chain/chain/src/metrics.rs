@@ -1,7 +1,8 @@
 use near_o11y::metrics::{
     exponential_buckets, processing_time_buckets, try_create_histogram, try_create_histogram_vec,
-    try_create_histogram_with_buckets, try_create_int_counter, try_create_int_gauge,
-    try_create_int_gauge_vec, Histogram, HistogramVec, IntCounter, IntGauge, IntGaugeVec,
+    try_create_histogram_with_buckets, try_create_int_counter, try_create_int_counter_vec,
+    try_create_int_gauge, try_create_int_gauge_vec, Histogram, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, IntGaugeVec,
 };
 use once_cell::sync::Lazy;
 
@@ -55,6 +56,42 @@ pub static VALIDATOR_ACTIVE_TOTAL: Lazy<IntGauge> = Lazy::new(|| {
 });
 pub static NUM_ORPHANS: Lazy<IntGauge> =
     Lazy::new(|| try_create_int_gauge("near_num_orphans", "Number of orphan blocks.").unwrap());
+pub(crate) static MISSING_CHUNKS_POOL_BLOCKS: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_missing_chunks_pool_blocks",
+        "Number of blocks waiting for their chunks in the missing chunks pool",
+    )
+    .unwrap()
+});
+pub(crate) static MISSING_CHUNKS_POOL_BYTES: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_missing_chunks_pool_bytes",
+        "Total serialized size of the blocks in the missing chunks pool",
+    )
+    .unwrap()
+});
+pub(crate) static MISSING_CHUNKS_POOL_READY_BLOCKS: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_missing_chunks_pool_ready_blocks",
+        "Number of blocks in the missing chunks pool whose chunks arrived, waiting to be processed",
+    )
+    .unwrap()
+});
+pub(crate) static MISSING_CHUNKS_POOL_DEFERRED_STARTS: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_missing_chunks_pool_deferred_starts_total",
+        "Number of times the processing of a block whose chunks arrived was deferred by the start budget",
+    )
+    .unwrap()
+});
+pub(crate) static MISSING_CHUNKS_POOL_EVICTED: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_missing_chunks_pool_evicted_total",
+        "Number of blocks removed from the missing chunks pool before their chunks arrived",
+        &["reason"],
+    )
+    .unwrap()
+});
 pub static HEADER_HEAD_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
     try_create_int_gauge("near_header_head_height", "Height of the header head").unwrap()
 });
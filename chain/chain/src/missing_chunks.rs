@@ -1,5 +1,8 @@
+use crate::metrics;
+use lru::LruCache;
 use near_primitives::hash::CryptoHash;
 use near_primitives::sharding::ChunkHash;
+use near_primitives::static_clock::StaticClock;
 use near_primitives::types::BlockHeight;
 use std::cmp::Ordering;
 use std::collections::{
@@ -7,15 +10,22 @@ use std::collections::{
     hash_map::{self, HashMap},
     BinaryHeap, HashSet,
 };
+use std::time::{Duration, Instant};
 use tracing::{debug, warn};
 
 type BlockHash = CryptoHash;
 
 const MAX_BLOCKS_MISSING_CHUNKS: usize = 1024;
+const MAX_BLOCK_MISSING_CHUNKS_AGE: Duration = Duration::from_secs(120);
+const MAX_READY_BLOCKS_STARTED: usize = 3;
 
 pub trait BlockLike {
     fn hash(&self) -> BlockHash;
     fn height(&self) -> BlockHeight;
+    /// When the block was added to the pool.
+    fn added(&self) -> Instant;
+    /// Size of the block, as reported in the metrics of the pool.
+    fn size_bytes(&self) -> usize;
 }
 
 #[derive(Debug)]
@@ -38,27 +48,85 @@ impl<T: BlockLike> Ord for HeightOrdered<T> {
     }
 }
 
+/// Limits of the blocks kept while waiting for their chunks.
+#[derive(Clone, Debug)]
+pub struct MissingChunksPoolLimits {
+    /// Max number of blocks in the pool. Above it, the highest blocks, i.e. the ones farthest
+    /// from the head, are evicted.
+    pub max_blocks: usize,
+    /// Blocks waiting for their chunks for longer than this are evicted.
+    pub max_block_age: Duration,
+    /// Max number of blocks whose chunks arrived taken from the pool at once. The rest are taken
+    /// by the next calls, once the processing of these has progressed.
+    pub max_ready_blocks_started: usize,
+}
+
+impl Default for MissingChunksPoolLimits {
+    fn default() -> Self {
+        Self {
+            max_blocks: MAX_BLOCKS_MISSING_CHUNKS,
+            max_block_age: MAX_BLOCK_MISSING_CHUNKS_AGE,
+            max_ready_blocks_started: MAX_READY_BLOCKS_STARTED,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EvictionReason {
+    /// The block can't become final anymore.
+    BelowFinalHeight,
+    MaxBlockAge,
+    MaxBlocks,
+}
+
+impl EvictionReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::BelowFinalHeight => "below_final_height",
+            Self::MaxBlockAge => "max_block_age",
+            Self::MaxBlocks => "max_blocks",
+        }
+    }
+}
+
 /// Structure for keeping track of missing chunks.
 /// The reason to have a Block type parameter instead of using the
 /// `block::Block` type is to make testing easier (`block::Block` is a complex structure and I
 /// don't care about most of it).
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct MissingChunksPool<Block: BlockLike> {
     missing_chunks: HashMap<ChunkHash, HashSet<BlockHash>>,
     blocks_missing_chunks: HashMap<BlockHash, HashSet<ChunkHash>>,
     blocks_waiting_for_chunks: HashMap<BlockHash, Block>,
     blocks_ready_to_process: BinaryHeap<HeightOrdered<Block>>,
     height_idx: BTreeMap<BlockHeight, HashSet<BlockHash>>,
+    limits: MissingChunksPoolLimits,
+    /// Total size of the blocks waiting for chunks.
+    size_bytes: usize,
+    /// Blocks evicted by the limits, by the chunks they were missing. The bodies are dropped, so
+    /// a block is requested again when one of its chunks arrives after all.
+    evicted_blocks_by_chunk: LruCache<ChunkHash, BlockHash>,
+    blocks_to_refetch: Vec<BlockHash>,
+}
+
+impl<Block: BlockLike> Default for MissingChunksPool<Block> {
+    fn default() -> Self {
+        Self::new(MissingChunksPoolLimits::default())
+    }
 }
 
 impl<Block: BlockLike> MissingChunksPool<Block> {
-    pub fn new() -> Self {
+    pub fn new(limits: MissingChunksPoolLimits) -> Self {
         Self {
             missing_chunks: Default::default(),
             blocks_missing_chunks: Default::default(),
             blocks_waiting_for_chunks: Default::default(),
             blocks_ready_to_process: BinaryHeap::new(),
             height_idx: Default::default(),
+            evicted_blocks_by_chunk: LruCache::new(limits.max_blocks),
+            limits,
+            size_bytes: 0,
+            blocks_to_refetch: vec![],
         }
     }
 
@@ -74,27 +142,39 @@ impl<Block: BlockLike> MissingChunksPool<Block> {
         self.blocks_waiting_for_chunks.len()
     }
 
-    pub fn ready_blocks(&mut self) -> Vec<Block> {
+    /// Number of blocks whose chunks arrived which weren't taken from the pool yet.
+    pub fn num_ready_blocks(&self) -> usize {
+        self.blocks_ready_to_process.len()
+    }
+
+    /// Takes the ready blocks with the lowest heights, at most `max_blocks` of them and at most
+    /// `max_ready_blocks_started` of the limits, in ascending height order. The rest stay ready
+    /// for the next call.
+    pub fn take_ready_blocks(&mut self, max_blocks: usize) -> Vec<Block> {
         if self.blocks_ready_to_process.is_empty() {
             return Vec::new();
         }
         let heap = std::mem::replace(&mut self.blocks_ready_to_process, BinaryHeap::new());
-        heap.into_sorted_vec().into_iter().map(|x| x.0).collect()
+        let mut blocks = heap.into_sorted_vec();
+        let num_taken = blocks.len().min(max_blocks).min(self.limits.max_ready_blocks_started);
+        let deferred = blocks.split_off(num_taken);
+        if !deferred.is_empty() {
+            debug!(target: "chunks", num_taken, num_deferred = deferred.len(), "Deferring the processing of ready blocks.");
+            metrics::MISSING_CHUNKS_POOL_DEFERRED_STARTS.inc_by(deferred.len() as u64);
+            self.blocks_ready_to_process.extend(deferred);
+        }
+        self.update_metrics();
+        blocks.into_iter().map(|x| x.0).collect()
+    }
+
+    /// Returns the evicted blocks one of whose missing chunks arrived since, which should be
+    /// requested again.
+    pub fn take_blocks_to_refetch(&mut self) -> Vec<BlockHash> {
+        std::mem::take(&mut self.blocks_to_refetch)
     }
 
     pub fn add_block_with_missing_chunks(&mut self, block: Block, missing_chunks: Vec<ChunkHash>) {
         let block_hash = block.hash();
-        // This case can only happen when missing chunks are not being eventually received and
-        // thus removing blocks from the HashMap. It means the this node has severely stalled out.
-        // It is ok to ignore further blocks because either (a) we will start receiving chunks
-        // again, work through the backlog of the pool, then naturally sync the later blocks
-        // which were not added initially, or (b) someone will restart the node because something
-        // has gone horribly wrong, in which case these HashMaps will be lost anyways.
-        if self.blocks_missing_chunks.len() >= MAX_BLOCKS_MISSING_CHUNKS {
-            warn!(target: "chunks", "Not recording block with hash {} even though it is missing chunks. The missing chunks pool is full.", block_hash);
-            return;
-        }
-
         for chunk_hash in missing_chunks.iter().cloned() {
             let blocks_for_chunk =
                 self.missing_chunks.entry(chunk_hash).or_insert_with(HashSet::new);
@@ -120,10 +200,35 @@ impl<Block: BlockLike> MissingChunksPool<Block> {
         let height = block.height();
         let blocks_at_height = self.height_idx.entry(height).or_insert_with(HashSet::new);
         blocks_at_height.insert(block_hash);
-        self.blocks_waiting_for_chunks.insert(block_hash, block);
+        self.size_bytes += block.size_bytes();
+        if let Some(previous_block) = self.blocks_waiting_for_chunks.insert(block_hash, block) {
+            self.size_bytes -= previous_block.size_bytes();
+        }
+
+        // The blocks far ahead of the head are the least likely to be processed soon, e.g. when
+        // this node has severely stalled out, and the closer ones are needed first anyway.
+        while self.blocks_waiting_for_chunks.len() > self.limits.max_blocks {
+            let Some(highest_block_hash) = self
+                .height_idx
+                .values()
+                .next_back()
+                .and_then(|block_hashes| block_hashes.iter().next().copied())
+            else {
+                break;
+            };
+            warn!(target: "chunks", %highest_block_hash, "The missing chunks pool is full, evicting the highest block.");
+            self.evict_block(&highest_block_hash, EvictionReason::MaxBlocks);
+        }
+        self.update_metrics();
     }
 
     pub fn accept_chunk(&mut self, chunk_hash: &ChunkHash) {
+        if let Some(block_hash) = self.evicted_blocks_by_chunk.pop(chunk_hash) {
+            debug!(target: "chunks", ?chunk_hash, %block_hash, "Chunk of an evicted block accepted, the block will be requested again.");
+            if !self.blocks_to_refetch.contains(&block_hash) {
+                self.blocks_to_refetch.push(block_hash);
+            }
+        }
         let block_hashes = self.missing_chunks.remove(chunk_hash).unwrap_or_else(HashSet::new);
         debug!(target: "chunks", ?chunk_hash, "Chunk accepted, {} blocks were waiting for it.", block_hashes.len());
         for block_hash in block_hashes {
@@ -146,54 +251,98 @@ impl<Block: BlockLike> MissingChunksPool<Block> {
                 }
             }
         }
+        self.update_metrics();
     }
 
     fn mark_block_as_ready(&mut self, block_hash: &BlockHash) {
-        if let Some(block) = self.blocks_waiting_for_chunks.remove(block_hash) {
-            let height = block.height();
-            if let btree_map::Entry::Occupied(mut entry) = self.height_idx.entry(height) {
-                let blocks_at_height = entry.get_mut();
-                blocks_at_height.remove(block_hash);
-                if blocks_at_height.is_empty() {
-                    entry.remove_entry();
-                }
-            }
+        if let Some(block) = self.remove_waiting_block(block_hash) {
             self.blocks_ready_to_process.push(HeightOrdered(block));
         }
     }
 
-    pub fn prune_blocks_below_height(&mut self, height: BlockHeight) {
-        let heights_to_remove: Vec<BlockHeight> =
-            self.height_idx.keys().copied().take_while(|h| *h < height).collect();
-        for h in heights_to_remove {
-            if let Some(block_hashes) = self.height_idx.remove(&h) {
-                for block_hash in block_hashes {
-                    self.blocks_waiting_for_chunks.remove(&block_hash);
-                    if let Some(chunk_hashes) = self.blocks_missing_chunks.remove(&block_hash) {
-                        for chunk_hash in chunk_hashes {
-                            if let hash_map::Entry::Occupied(mut entry) =
-                                self.missing_chunks.entry(chunk_hash)
-                            {
-                                let blocks_for_chunk = entry.get_mut();
-                                blocks_for_chunk.remove(&block_hash);
-                                if blocks_for_chunk.is_empty() {
-                                    entry.remove_entry();
-                                }
-                            }
-                        }
+    /// Removes the block from the blocks waiting for chunks and from the height index.
+    fn remove_waiting_block(&mut self, block_hash: &BlockHash) -> Option<Block> {
+        let block = self.blocks_waiting_for_chunks.remove(block_hash)?;
+        self.size_bytes -= block.size_bytes();
+        if let btree_map::Entry::Occupied(mut entry) = self.height_idx.entry(block.height()) {
+            let blocks_at_height = entry.get_mut();
+            blocks_at_height.remove(block_hash);
+            if blocks_at_height.is_empty() {
+                entry.remove_entry();
+            }
+        }
+        Some(block)
+    }
+
+    fn evict_block(&mut self, block_hash: &BlockHash, reason: EvictionReason) {
+        if self.remove_waiting_block(block_hash).is_none() {
+            return;
+        }
+        if let Some(chunk_hashes) = self.blocks_missing_chunks.remove(block_hash) {
+            for chunk_hash in chunk_hashes {
+                if let hash_map::Entry::Occupied(mut entry) =
+                    self.missing_chunks.entry(chunk_hash.clone())
+                {
+                    let blocks_for_chunk = entry.get_mut();
+                    blocks_for_chunk.remove(block_hash);
+                    if blocks_for_chunk.is_empty() {
+                        entry.remove_entry();
                     }
                 }
+                if reason != EvictionReason::BelowFinalHeight {
+                    self.evicted_blocks_by_chunk.put(chunk_hash, *block_hash);
+                }
             }
         }
+        metrics::MISSING_CHUNKS_POOL_EVICTED.with_label_values(&[reason.as_str()]).inc();
+    }
+
+    pub fn prune_blocks_below_height(&mut self, height: BlockHeight) {
+        let block_hashes: Vec<BlockHash> = self
+            .height_idx
+            .range(..height)
+            .flat_map(|(_, block_hashes)| block_hashes.iter().copied())
+            .collect();
+        for block_hash in block_hashes {
+            self.evict_block(&block_hash, EvictionReason::BelowFinalHeight);
+        }
+        self.update_metrics();
+    }
+
+    /// Evicts the blocks which have been waiting for their chunks for longer than allowed.
+    pub fn prune_old_blocks(&mut self) {
+        let now = StaticClock::instant();
+        let old_block_hashes: Vec<BlockHash> = self
+            .blocks_waiting_for_chunks
+            .values()
+            .filter(|block| {
+                now.saturating_duration_since(block.added()) > self.limits.max_block_age
+            })
+            .map(|block| block.hash())
+            .collect();
+        for block_hash in old_block_hashes {
+            debug!(target: "chunks", %block_hash, "Evicting a block which waited too long for its chunks.");
+            self.evict_block(&block_hash, EvictionReason::MaxBlockAge);
+        }
+        self.update_metrics();
+    }
+
+    fn update_metrics(&self) {
+        metrics::MISSING_CHUNKS_POOL_BLOCKS.set(self.blocks_waiting_for_chunks.len() as i64);
+        metrics::MISSING_CHUNKS_POOL_BYTES.set(self.size_bytes as i64);
+        metrics::MISSING_CHUNKS_POOL_READY_BLOCKS.set(self.blocks_ready_to_process.len() as i64);
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{BlockHash, BlockLike, MissingChunksPool, MAX_BLOCKS_MISSING_CHUNKS};
+    use super::{
+        BlockHash, BlockLike, MissingChunksPool, MissingChunksPoolLimits, MAX_BLOCKS_MISSING_CHUNKS,
+    };
     use near_primitives::hash::{hash, CryptoHash};
     use near_primitives::sharding::ChunkHash;
     use near_primitives::types::BlockHeight;
+    use std::time::{Duration, Instant};
 
     fn get_hash(idx: u64) -> CryptoHash {
         hash(&idx.to_le_bytes())
@@ -203,14 +352,19 @@ mod test {
         ChunkHash(get_hash(idx))
     }
 
-    #[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     struct MockBlock {
         hash: BlockHash,
         height: BlockHeight,
+        added: Instant,
     }
     impl MockBlock {
         fn new(height: BlockHeight) -> Self {
-            Self { hash: get_hash(height), height }
+            Self { hash: get_hash(height), height, added: Instant::now() }
+        }
+
+        fn added_ago(height: BlockHeight, age: Duration) -> Self {
+            Self { added: Instant::now() - age, ..Self::new(height) }
         }
     }
     impl BlockLike for MockBlock {
@@ -221,6 +375,14 @@ mod test {
         fn height(&self) -> u64 {
             self.height
         }
+
+        fn added(&self) -> Instant {
+            self.added
+        }
+
+        fn size_bytes(&self) -> usize {
+            100
+        }
     }
 
     #[test]
@@ -242,7 +404,7 @@ mod test {
         // after the last chunk is accepted the block is ready to process
         pool.accept_chunk(&chunk_hashes[0]);
         assert!(!pool.contains(&block.hash));
-        assert_eq!(pool.ready_blocks(), vec![block]);
+        assert_eq!(pool.take_ready_blocks(usize::MAX), vec![block]);
     }
 
     #[test]
@@ -289,8 +451,82 @@ mod test {
 
         pool.accept_chunk(&missing_chunk_hash);
         pool.prune_blocks_below_height(block_height);
-        assert_eq!(pool.ready_blocks(), vec![block]);
+        assert_eq!(pool.take_ready_blocks(usize::MAX), vec![block]);
         assert!(!pool.contains(&early_block_hash));
         assert!(pool.contains(&later_block_hash));
     }
+
+    #[test]
+    fn should_evict_highest_blocks_above_size_limit() {
+        let limits = MissingChunksPoolLimits { max_blocks: 2, ..Default::default() };
+        let mut pool: MissingChunksPool<MockBlock> = MissingChunksPool::new(limits);
+        let blocks: Vec<_> = [10, 30, 20].into_iter().map(MockBlock::new).collect();
+        for (i, block) in blocks.iter().enumerate() {
+            pool.add_block_with_missing_chunks(*block, vec![get_chunk_hash(100 + i as u64)]);
+        }
+        assert!(pool.contains(&blocks[0].hash));
+        assert!(!pool.contains(&blocks[1].hash));
+        assert!(pool.contains(&blocks[2].hash));
+        assert_eq!(pool.size_bytes, 200);
+    }
+
+    #[test]
+    fn should_evict_old_blocks_and_refetch_them_when_chunks_arrive() {
+        let max_block_age = Duration::from_secs(60);
+        let limits = MissingChunksPoolLimits { max_block_age, ..Default::default() };
+        let mut pool: MissingChunksPool<MockBlock> = MissingChunksPool::new(limits);
+
+        // The chunks of the block far ahead of the head are held back for long.
+        let far_block = MockBlock::added_ago(100, 2 * max_block_age);
+        let far_chunk_hash = get_chunk_hash(1000);
+        pool.add_block_with_missing_chunks(far_block, vec![far_chunk_hash.clone()]);
+        let near_block = MockBlock::added_ago(2, max_block_age / 2);
+        pool.add_block_with_missing_chunks(near_block, vec![get_chunk_hash(200)]);
+        assert_eq!(pool.len(), 2);
+
+        pool.prune_old_blocks();
+        assert!(!pool.contains(&far_block.hash));
+        assert!(pool.contains(&near_block.hash));
+        assert_eq!(pool.size_bytes, 100);
+        assert!(pool.take_blocks_to_refetch().is_empty());
+
+        pool.accept_chunk(&far_chunk_hash);
+        assert!(pool.take_ready_blocks(usize::MAX).is_empty());
+        assert_eq!(pool.take_blocks_to_refetch(), vec![far_block.hash]);
+        assert!(pool.take_blocks_to_refetch().is_empty());
+    }
+
+    #[test]
+    fn should_take_ready_blocks_in_height_order_within_budget() {
+        let limits = MissingChunksPoolLimits { max_ready_blocks_started: 4, ..Default::default() };
+        let mut pool: MissingChunksPool<MockBlock> = MissingChunksPool::new(limits);
+        let chunk_hash = get_chunk_hash(1000);
+        for height in (1..=30).rev() {
+            pool.add_block_with_missing_chunks(MockBlock::new(height), vec![chunk_hash.clone()]);
+        }
+        pool.accept_chunk(&chunk_hash);
+        assert_eq!(pool.num_ready_blocks(), 30);
+
+        let mut taken = vec![];
+        loop {
+            let blocks = pool.take_ready_blocks(usize::MAX);
+            if blocks.is_empty() {
+                break;
+            }
+            assert!(blocks.len() <= 4);
+            taken.extend(blocks.into_iter().map(|block| block.height));
+            assert_eq!(pool.num_ready_blocks(), 30 - taken.len());
+        }
+        assert_eq!(taken, (1..=30).collect::<Vec<_>>());
+
+        // The caller can take fewer blocks than the budget.
+        let chunk_hash = get_chunk_hash(2000);
+        for height in 31..=33 {
+            pool.add_block_with_missing_chunks(MockBlock::new(height), vec![chunk_hash.clone()]);
+        }
+        pool.accept_chunk(&chunk_hash);
+        let blocks = pool.take_ready_blocks(1);
+        assert_eq!(blocks.iter().map(|block| block.height).collect::<Vec<_>>(), vec![31]);
+        assert_eq!(pool.num_ready_blocks(), 2);
+    }
 }
@@ -44,9 +44,9 @@ use near_primitives::utils::{
 use near_primitives::version::ProtocolVersion;
 use near_primitives::views::LightClientBlockView;
 use near_store::{
-    DBCol, KeyForStateChanges, ShardTries, Store, StoreUpdate, WrappedTrieChanges, CHUNK_TAIL_KEY,
-    FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY, LARGEST_TARGET_HEIGHT_KEY,
-    LATEST_KNOWN_KEY, TAIL_KEY,
+    DBCol, KeyForStateChanges, ShardTries, Store, StoreUpdate, TrieChanges, WrappedTrieChanges,
+    CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY,
+    LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, TAIL_KEY,
 };
 
 use crate::byzantine_assert;
@@ -511,6 +511,12 @@ impl ChainStore {
         }
     }
 
+    /// Reads the head from the store again, in case the cached one doesn't match it anymore.
+    pub fn reload_head(&mut self) -> Result<Tip, Error> {
+        self.head = None;
+        self.head()
+    }
+
     pub fn new_read_only_chunks_store(&self) -> ReadOnlyChunksStore {
         ReadOnlyChunksStore::new(self.store.clone())
     }
@@ -574,6 +580,47 @@ impl ChainStore {
         shard_id: ShardId,
         last_included_height: BlockHeight,
     ) -> Result<Vec<Receipt>, Error> {
+        let shard_layout = epoch_manager.get_shard_layout_from_prev_block(&prev_block_hash)?;
+        let (receipts_block_hash, receipts_shard_id) = self.get_outgoing_receipts_source(
+            epoch_manager,
+            prev_block_hash,
+            shard_id,
+            last_included_height,
+        )?;
+        let receipts_epoch_id = self.get_block_header(&receipts_block_hash)?.epoch_id().clone();
+        let receipts_shard_layout = epoch_manager.get_shard_layout(&receipts_epoch_id)?;
+
+        let mut receipts = self
+            .get_outgoing_receipts(&receipts_block_hash, receipts_shard_id)
+            .map(|v| v.to_vec())
+            .unwrap_or_default();
+
+        if shard_layout != receipts_shard_layout {
+            // the shard layout has changed so we need to reassign the outgoing receipts
+            let epoch_id = epoch_manager.get_epoch_id_from_prev_block(&prev_block_hash)?;
+            let protocol_version = epoch_manager.get_epoch_protocol_version(&epoch_id)?;
+            Self::reassign_outgoing_receipts_for_resharding(
+                &mut receipts,
+                protocol_version,
+                &shard_layout,
+                shard_id,
+                receipts_shard_id,
+            )?;
+        }
+
+        Ok(receipts)
+    }
+
+    /// Returns the hash of the block at `last_included_height` on the chain of `prev_block_hash`
+    /// and the id, in the shard layout of that block, of the shard that generated the receipts
+    /// `get_outgoing_receipts_for_shard` returns. The receipts are stored under these two.
+    pub fn get_outgoing_receipts_source(
+        &self,
+        epoch_manager: &dyn EpochManagerAdapter,
+        prev_block_hash: CryptoHash,
+        shard_id: ShardId,
+        last_included_height: BlockHeight,
+    ) -> Result<(CryptoHash, ShardId), Error> {
         let shard_layout = epoch_manager.get_shard_layout_from_prev_block(&prev_block_hash)?;
         let mut receipts_block_hash = prev_block_hash;
         loop {
@@ -591,26 +638,7 @@ impl ChainStore {
             } else {
                 shard_id
             };
-
-            let mut receipts = self
-                .get_outgoing_receipts(&receipts_block_hash, receipts_shard_id)
-                .map(|v| v.to_vec())
-                .unwrap_or_default();
-
-            if shard_layout != receipts_shard_layout {
-                // the shard layout has changed so we need to reassign the outgoing receipts
-                let epoch_id = epoch_manager.get_epoch_id_from_prev_block(&prev_block_hash)?;
-                let protocol_version = epoch_manager.get_epoch_protocol_version(&epoch_id)?;
-                Self::reassign_outgoing_receipts_for_resharding(
-                    &mut receipts,
-                    protocol_version,
-                    &shard_layout,
-                    shard_id,
-                    receipts_shard_id,
-                )?;
-            }
-
-            return Ok(receipts);
+            return Ok((receipts_block_hash, receipts_shard_id));
         }
     }
 
@@ -803,6 +831,16 @@ impl ChainStore {
         )?)
     }
 
+    /// Returns the trie changes of applying the shard at the given block. They are only saved if
+    /// the node needs them for garbage collection, and are removed once the block is collected.
+    pub fn get_trie_changes(
+        &self,
+        block_hash: &CryptoHash,
+        shard_uid: &ShardUId,
+    ) -> Result<Option<TrieChanges>, Error> {
+        Ok(self.store.get_ser(DBCol::TrieChanges, &get_block_shard_uid(block_hash, shard_uid))?)
+    }
+
     /// Returns a vector of Outcome ids for given block and shard id
     pub fn get_outcomes_by_block_hash_and_shard_id(
         &self,
@@ -2853,6 +2891,11 @@ impl<'a> ChainStoreUpdate<'a> {
             | DBCol::EpochInfo
             | DBCol::EpochStart
             | DBCol::EpochValidatorInfo
+            | DBCol::EpochTransitionStats
+            | DBCol::EpochSlashDiscounts
+            | DBCol::BanHistory
+            | DBCol::BlockProductionInputs
+            | DBCol::BlockProvenance
             | DBCol::BlockOrdinal
             | DBCol::_ChunkPerHeightShard
             | DBCol::_NextBlockWithNewChunk
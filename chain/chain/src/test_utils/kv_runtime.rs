@@ -7,7 +7,7 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use near_chain_configs::{ProtocolConfig, DEFAULT_GC_NUM_EPOCHS_TO_KEEP};
 use near_chain_primitives::Error;
 use near_crypto::{KeyType, PublicKey, SecretKey, Signature};
-use near_epoch_manager::types::BlockHeaderInfo;
+use near_epoch_manager::types::{BlockHeaderInfo, EpochTransitionStats, ShardAssignmentsView};
 use near_epoch_manager::{EpochManagerAdapter, RngSeed};
 use near_pool::types::PoolIterator;
 use near_primitives::account::{AccessKey, Account};
@@ -22,7 +22,7 @@ use near_primitives::errors::{EpochError, InvalidTxError};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::receipt::{ActionReceipt, Receipt, ReceiptEnum};
 use near_primitives::shard_layout;
-use near_primitives::shard_layout::{ShardLayout, ShardUId};
+use near_primitives::shard_layout::{ShardLayout, ShardLayoutDiff, ShardUId, ShardVersion};
 use near_primitives::sharding::ChunkHash;
 use near_primitives::state_part::PartId;
 use near_primitives::transaction::{
@@ -33,7 +33,10 @@ use near_primitives::types::validator_stake::{ValidatorStake, ValidatorStakeIter
 use near_primitives::types::{
     AccountId, ApprovalStake, Balance, BlockHeight, EpochHeight, EpochId, Gas, Nonce, NumShards,
     ShardId, StateChangesForSplitStates, StateRoot, StateRootNode, ValidatorInfoIdentifier,
+    ValidatorKickoutReason,
 };
+#[cfg(feature = "protocol_feature_chunk_validation")]
+use near_primitives::validator_mandates::{ValidatorMandates, ValidatorMandatesConfig};
 use near_primitives::version::{ProtocolVersion, PROTOCOL_VERSION};
 use near_primitives::views::{
     AccessKeyInfoView, AccessKeyList, CallResult, ContractCodeView, EpochValidatorInfo,
@@ -45,9 +48,14 @@ use near_store::{
     Trie, TrieChanges, WrappedTrieChanges,
 };
 use num_rational::Ratio;
+#[cfg(feature = "protocol_feature_chunk_validation")]
+use rand::SeedableRng;
+#[cfg(feature = "protocol_feature_chunk_validation")]
+use rand_chacha::ChaCha20Rng;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap, HashSet};
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
 
 /// Simple key value runtime for tests.
 ///
@@ -75,6 +83,16 @@ pub struct KeyValueRuntime {
     state: RwLock<HashMap<StateRoot, KVState>>,
     state_size: RwLock<HashMap<StateRoot, u64>>,
     headers_cache: RwLock<HashMap<CryptoHash, BlockHeader>>,
+    // A mapping (block hash, shard id) => size of the state read to apply the chunk, filled when
+    // the storage is recorded.
+    recorded_storage_sizes: RwLock<HashMap<(CryptoHash, ShardId), usize>>,
+    /// If set, applying each chunk waits for a permit from the test, to simulate a slow runtime.
+    apply_transactions_permits: Mutex<Option<mpsc::Receiver<()>>>,
+    /// Heights of the chunks for which the next preparation of transactions fails.
+    failing_prepare_transactions: RwLock<HashSet<BlockHeight>>,
+    /// Number of transactions validated so far, counting the validations with and without the
+    /// state separately.
+    num_validate_tx_calls: AtomicUsize,
 }
 
 /// DEPRECATED. DO NOT USE for new tests. Use the real EpochManager, familiarize
@@ -92,6 +110,8 @@ pub struct MockEpochManager {
     /// Maps from account id to validator stake for all validators, both block producers and
     /// chunk producers
     validators: HashMap<AccountId, ValidatorStake>,
+    /// Chunk producers of every shard, for each of `validators_by_valset`.
+    shard_assignments: Vec<Arc<ShardAssignmentsView>>,
 
     headers_cache: RwLock<HashMap<CryptoHash, BlockHeader>>,
     hash_to_epoch: RwLock<HashMap<CryptoHash, EpochId>>,
@@ -100,6 +120,19 @@ pub struct MockEpochManager {
     /// Maps EpochId to index of `validators_by_valset` to determine validators for an epoch
     hash_to_valset: RwLock<HashMap<EpochId, u64>>,
     epoch_start: RwLock<HashMap<CryptoHash, u64>>,
+    /// Block producers returned for an epoch instead of the ones of its validator set.
+    block_producers_overrides: RwLock<HashMap<EpochId, Vec<ValidatorStake>>>,
+    /// Number of chunk parts returned instead of the one derived from the number of shards.
+    num_total_parts_override: RwLock<Option<usize>>,
+    /// Shard layout versions of the epochs whose layout differs from the default one.
+    shard_layout_version_overrides: RwLock<HashMap<EpochId, ShardVersion>>,
+    /// Validators reported as slashed in an epoch.
+    slashed_validators: RwLock<HashSet<(EpochId, AccountId)>>,
+    /// Heights at which the next lookup of a chunk producer fails.
+    failing_chunk_producer_lookups: RwLock<HashSet<BlockHeight>>,
+    /// Minimum number of chunk validator mandates per shard, None if the validators have none.
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    mandates_per_shard: Option<usize>,
 }
 
 /// Stores the validator information in an epoch.
@@ -194,25 +227,74 @@ impl MockEpochManager {
             }
         }
 
+        let shard_assignments = validators_by_valset
+            .iter()
+            .map(|valset| Arc::new(ShardAssignmentsView::new(valset.chunk_producers.clone())))
+            .collect();
+
         Arc::new(MockEpochManager {
             store,
             num_shards: vs.num_shards,
             epoch_length,
             validators,
             validators_by_valset,
+            shard_assignments,
             headers_cache: RwLock::new(HashMap::new()),
             hash_to_epoch: RwLock::new(HashMap::new()),
             hash_to_next_epoch_approvals_req: RwLock::new(HashMap::new()),
             hash_to_next_epoch: RwLock::new(map_with_default_hash1),
             hash_to_valset: RwLock::new(map_with_default_hash3),
             epoch_start: RwLock::new(map_with_default_hash2),
+            block_producers_overrides: RwLock::new(HashMap::new()),
+            num_total_parts_override: RwLock::new(None),
+            shard_layout_version_overrides: RwLock::new(HashMap::new()),
+            slashed_validators: RwLock::new(HashSet::new()),
+            failing_chunk_producer_lookups: RwLock::new(HashSet::new()),
+            #[cfg(feature = "protocol_feature_chunk_validation")]
+            mandates_per_shard: vs.mandates_per_shard,
         })
     }
 
+    /// Makes `get_epoch_block_producers_ordered` return `block_producers` for the given epoch,
+    /// e.g. to emulate an epoch manager which diverged from the rest of the network.
+    pub fn override_epoch_block_producers(
+        &self,
+        epoch_id: EpochId,
+        block_producers: Vec<ValidatorStake>,
+    ) {
+        self.block_producers_overrides.write().unwrap().insert(epoch_id, block_producers);
+    }
+
+    /// Makes the shard uids of the given epoch use `version`, as a change of the shard layout
+    /// would. The change is also reported by `shard_layout_diff` at the start of the epoch.
+    pub fn set_shard_layout_version(&self, epoch_id: EpochId, version: ShardVersion) {
+        self.shard_layout_version_overrides.write().unwrap().insert(epoch_id, version);
+    }
+
+    fn shard_layout_version(&self, epoch_id: &EpochId) -> ShardVersion {
+        self.shard_layout_version_overrides.read().unwrap().get(epoch_id).copied().unwrap_or(0)
+    }
+
+    /// Reports `account_id` as slashed in the given epoch, as a double signing challenge would.
+    pub fn set_slashed(&self, epoch_id: EpochId, account_id: AccountId) {
+        self.slashed_validators.write().unwrap().insert((epoch_id, account_id));
+    }
+
+    /// Makes the next lookup of a chunk producer at `height` fail, as a transient failure to read
+    /// the epoch info would. The lookups after it succeed again.
+    pub fn fail_next_chunk_producer_lookup(&self, height: BlockHeight) {
+        self.failing_chunk_producer_lookups.write().unwrap().insert(height);
+    }
+
+    /// Changes the number of chunk parts from now on, as a change of the number of block
+    /// producer seats by a protocol upgrade would.
+    pub fn set_num_total_parts(&self, num_total_parts: usize) {
+        *self.num_total_parts_override.write().unwrap() = Some(num_total_parts);
+    }
+
     /// Get epoch and index of validator set by the hash of previous block.
-    /// Note that it also fills in-memory chain info and there is some
-    /// assumption that it is called for all previous blocks.
-    /// TODO (#8269): should we call it recursively for previous blocks if info is not found?
+    /// Note that it also fills in-memory chain info. If the info of the previous blocks is missing,
+    /// e.g. because the chain was written to the store by another instance, it's filled first.
     fn get_epoch_and_valset(
         &self,
         prev_hash: CryptoHash,
@@ -223,6 +305,9 @@ impl MockEpochManager {
         let prev_block_header = self
             .get_block_header(&prev_hash)?
             .ok_or_else(|| EpochError::MissingBlock(prev_hash))?;
+        if !self.epoch_start.read().unwrap().contains_key(prev_block_header.prev_hash()) {
+            self.get_epoch_and_valset(*prev_block_header.prev_hash())?;
+        }
 
         let mut hash_to_epoch = self.hash_to_epoch.write().unwrap();
         let mut hash_to_next_epoch_approvals_req =
@@ -292,6 +377,35 @@ impl MockEpochManager {
         self.validators_by_valset[valset].chunk_producers[shard_id as usize].clone()
     }
 
+    /// Mandates of the validators of `valset`, along with the validators, ordered by their id in
+    /// the mandates.
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    fn get_validator_mandates(
+        &self,
+        valset: usize,
+        mandates_per_shard: usize,
+    ) -> (ValidatorMandates, Vec<ValidatorStake>) {
+        let validator_set = &self.validators_by_valset[valset];
+        let mut validators: Vec<ValidatorStake> = vec![];
+        for validator in validator_set
+            .block_producers
+            .iter()
+            .chain(validator_set.chunk_producers.iter().flatten())
+        {
+            if !validators.iter().any(|v| v.account_id() == validator.account_id()) {
+                validators.push(validator.clone());
+            }
+        }
+        let num_shards = self.num_shards as usize;
+        // Every validator gets its share of the required mandates, rounded up.
+        let mandates_per_validator = (mandates_per_shard * num_shards).div_ceil(validators.len());
+        let min_stake = validators.iter().map(|v| v.stake()).min().unwrap();
+        let stake_per_mandate = (min_stake / mandates_per_validator.max(1) as Balance).max(1);
+        let config =
+            ValidatorMandatesConfig::new(stake_per_mandate, mandates_per_shard, num_shards);
+        (ValidatorMandates::new(config, &validators), validators)
+    }
+
     fn get_valset_for_epoch(&self, epoch_id: &EpochId) -> Result<usize, EpochError> {
         // conveniently here if the prev_hash is passed mistakenly instead of the epoch_hash,
         // the `unwrap` will trigger
@@ -352,8 +466,14 @@ impl KeyValueRuntime {
         let data_len = data.len() as u64;
         // StateRoot is actually faked here.
         // We cannot do any reasonable validations of it in test_utils.
-        let state = HashMap::from([(Trie::EMPTY_ROOT, kv_state)]);
-        let state_size = HashMap::from([(Trie::EMPTY_ROOT, data_len)]);
+        // Applying chunks without transactions or receipts leaves the state unchanged, under the
+        // root derived from its data. It's known from the start, so that a runtime created over a
+        // store with such a chain can continue it.
+        let unchanged_state_root = hash(&data);
+        let state =
+            HashMap::from([(Trie::EMPTY_ROOT, kv_state.clone()), (unchanged_state_root, kv_state)]);
+        let state_size =
+            HashMap::from([(Trie::EMPTY_ROOT, data_len), (unchanged_state_root, data_len)]);
 
         let mut store_update = store.store_update();
         let genesis_roots: Vec<CryptoHash> = (0..num_shards).map(|_| Trie::EMPTY_ROOT).collect();
@@ -370,9 +490,31 @@ impl KeyValueRuntime {
             headers_cache: RwLock::new(HashMap::new()),
             state: RwLock::new(state),
             state_size: RwLock::new(state_size),
+            recorded_storage_sizes: RwLock::new(HashMap::new()),
+            apply_transactions_permits: Mutex::new(None),
+            failing_prepare_transactions: RwLock::new(HashSet::new()),
+            num_validate_tx_calls: AtomicUsize::new(0),
         })
     }
 
+    /// Makes applying each chunk from now on wait for a permit sent through the returned sender.
+    pub fn gate_apply_transactions(&self) -> mpsc::Sender<()> {
+        let (sender, receiver) = mpsc::channel();
+        *self.apply_transactions_permits.lock().unwrap() = Some(receiver);
+        sender
+    }
+
+    /// Makes the next preparation of transactions for a chunk at `height` fail, as it would while
+    /// the flat storage of the shard isn't ready. The preparations after it succeed again.
+    pub fn fail_next_prepare_transactions(&self, height: BlockHeight) {
+        self.failing_prepare_transactions.write().unwrap().insert(height);
+    }
+
+    /// Number of times `validate_tx` was called.
+    pub fn num_validate_tx_calls(&self) -> usize {
+        self.num_validate_tx_calls.load(AtomicOrdering::Relaxed)
+    }
+
     fn get_block_header(&self, hash: &CryptoHash) -> Result<Option<BlockHeader>, EpochError> {
         let mut headers_cache = self.headers_cache.write().unwrap();
         if headers_cache.get(hash).is_some() {
@@ -422,6 +564,9 @@ impl EpochManagerAdapter for MockEpochManager {
     }
 
     fn num_total_parts(&self) -> usize {
+        if let Some(num_total_parts) = *self.num_total_parts_override.read().unwrap() {
+            return num_total_parts;
+        }
         12 + (self.num_shards as usize + 1) % 50
     }
 
@@ -455,9 +600,10 @@ impl EpochManagerAdapter for MockEpochManager {
     fn shard_id_to_uid(
         &self,
         shard_id: ShardId,
-        _epoch_id: &EpochId,
+        epoch_id: &EpochId,
     ) -> Result<ShardUId, EpochError> {
-        Ok(ShardUId { version: 0, shard_id: shard_id as u32 })
+        let version = self.shard_layout_version(epoch_id);
+        Ok(ShardUId { version, shard_id: shard_id as u32 })
     }
 
     fn get_block_info(&self, _hash: &CryptoHash) -> Result<Arc<BlockInfo>, EpochError> {
@@ -564,9 +710,11 @@ impl EpochManagerAdapter for MockEpochManager {
 
     fn get_epoch_height_from_prev_block(
         &self,
-        _prev_block_hash: &CryptoHash,
+        prev_block_hash: &CryptoHash,
     ) -> Result<EpochHeight, EpochError> {
-        Ok(0)
+        // The epochs are numbered like their validator sets, before wrapping around the schedule.
+        let (epoch_id, _, _) = self.get_epoch_and_valset(*prev_block_hash)?;
+        Ok(self.hash_to_valset.read().unwrap().get(&epoch_id).copied().unwrap_or(0))
     }
 
     fn get_next_epoch_id(&self, block_hash: &CryptoHash) -> Result<EpochId, EpochError> {
@@ -596,6 +744,23 @@ impl EpochManagerAdapter for MockEpochManager {
         Ok(ShardLayout::v0(self.num_shards, 0))
     }
 
+    /// Only the versions set with `set_shard_layout_version` change, the rest of the mock keeps
+    /// using the same shard layout.
+    fn shard_layout_diff(
+        &self,
+        prev_hash: &CryptoHash,
+    ) -> Result<Option<ShardLayoutDiff>, EpochError> {
+        if !self.is_next_block_epoch_start(prev_hash)? {
+            return Ok(None);
+        }
+        let old_version = self.shard_layout_version(&self.get_epoch_id(prev_hash)?);
+        let new_version = self.shard_layout_version(&self.get_epoch_id_from_prev_block(prev_hash)?);
+        Ok(ShardLayoutDiff::new(
+            ShardLayout::v0(self.num_shards, old_version),
+            ShardLayout::v0(self.num_shards, new_version),
+        ))
+    }
+
     fn get_epoch_id(&self, block_hash: &CryptoHash) -> Result<EpochId, EpochError> {
         let (epoch_id, _, _) = self.get_epoch_and_valset(*block_hash)?;
         Ok(epoch_id)
@@ -651,6 +816,9 @@ impl EpochManagerAdapter for MockEpochManager {
         epoch_id: &EpochId,
         _last_known_block_hash: &CryptoHash,
     ) -> Result<Vec<(ValidatorStake, bool)>, EpochError> {
+        if let Some(validators) = self.block_producers_overrides.read().unwrap().get(epoch_id) {
+            return Ok(validators.iter().map(|x| (x.clone(), false)).collect());
+        }
         let validators = self.get_block_producers(self.get_valset_for_epoch(epoch_id)?);
         Ok(validators.iter().map(|x| (x.clone(), false)).collect())
     }
@@ -703,12 +871,66 @@ impl EpochManagerAdapter for MockEpochManager {
         height: BlockHeight,
         shard_id: ShardId,
     ) -> Result<AccountId, EpochError> {
+        if self.failing_chunk_producer_lookups.write().unwrap().remove(&height) {
+            return Err(EpochError::IOErr(format!("injected failure at height {height}")));
+        }
         let valset = self.get_valset_for_epoch(epoch_id)?;
         let chunk_producers = self.get_chunk_producers(valset, shard_id);
         let index = (shard_id + height + 1) as usize % chunk_producers.len();
         Ok(chunk_producers[index].account_id().clone())
     }
 
+    fn get_shard_validator_assignments(
+        &self,
+        epoch_id: &EpochId,
+    ) -> Result<Arc<ShardAssignmentsView>, EpochError> {
+        let valset = self.get_valset_for_epoch(epoch_id)?;
+        Ok(self.shard_assignments[valset].clone())
+    }
+
+    fn get_chunk_producer_shards(
+        &self,
+        epoch_id: &EpochId,
+        account_id: &AccountId,
+    ) -> Result<Vec<ShardId>, EpochError> {
+        let valset = self.get_valset_for_epoch(epoch_id)?;
+        Ok(self.validators_by_valset[valset]
+            .chunk_producers
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk_producers)| {
+                chunk_producers.iter().any(|validator| validator.account_id() == account_id)
+            })
+            .map(|(shard_id, _)| shard_id as ShardId)
+            .collect())
+    }
+
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    fn sample_chunk_validators(
+        &self,
+        epoch_id: &EpochId,
+        height: BlockHeight,
+    ) -> Result<Vec<HashMap<AccountId, u16>>, EpochError> {
+        let Some(mandates_per_shard) = self.mandates_per_shard else {
+            return Ok(vec![]);
+        };
+        let valset = self.get_valset_for_epoch(epoch_id)?;
+        let (mandates, validators) = self.get_validator_mandates(valset, mandates_per_shard);
+        let mut rng = ChaCha20Rng::seed_from_u64(height);
+        Ok(mandates
+            .sample(&mut rng)
+            .into_iter()
+            .map(|assignments| {
+                assignments
+                    .into_iter()
+                    .map(|(validator_id, mandates)| {
+                        (validators[validator_id as usize].account_id().clone(), mandates)
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
     fn get_validator_by_account_id(
         &self,
         epoch_id: &EpochId,
@@ -716,14 +938,19 @@ impl EpochManagerAdapter for MockEpochManager {
         account_id: &AccountId,
     ) -> Result<(ValidatorStake, bool), EpochError> {
         let validators = &self.validators_by_valset[self.get_valset_for_epoch(epoch_id)?];
+        let is_slashed = self
+            .slashed_validators
+            .read()
+            .unwrap()
+            .contains(&(epoch_id.clone(), account_id.clone()));
         for validator_stake in validators.block_producers.iter() {
             if validator_stake.account_id() == account_id {
-                return Ok((validator_stake.clone(), false));
+                return Ok((validator_stake.clone(), is_slashed));
             }
         }
         for validator_stake in validators.chunk_producers.iter().flatten() {
             if validator_stake.account_id() == account_id {
-                return Ok((validator_stake.clone(), false));
+                return Ok((validator_stake.clone(), is_slashed));
             }
         }
         Err(EpochError::NotAValidator(account_id.clone(), epoch_id.clone()))
@@ -761,6 +988,26 @@ impl EpochManagerAdapter for MockEpochManager {
         Ok(self.store.store_update())
     }
 
+    fn get_epoch_transition_stats(
+        &self,
+        epoch_id: &EpochId,
+    ) -> Result<EpochTransitionStats, EpochError> {
+        Err(EpochError::EpochOutOfBounds(epoch_id.clone()))
+    }
+
+    fn project_kickouts(
+        &self,
+        epoch_id: &EpochId,
+        _as_of_block_hash: &CryptoHash,
+    ) -> Result<HashMap<AccountId, Option<ValidatorKickoutReason>>, EpochError> {
+        let valset = self.get_valset_for_epoch(epoch_id)?;
+        Ok(self
+            .get_block_producers(valset)
+            .iter()
+            .map(|validator| (validator.account_id().clone(), None))
+            .collect())
+    }
+
     fn get_epoch_minted_amount(&self, _epoch_id: &EpochId) -> Result<Balance, EpochError> {
         Ok(0)
     }
@@ -952,6 +1199,8 @@ impl EpochManagerAdapter for MockEpochManager {
         Ok(shard_layout != next_shard_layout)
     }
 
+    fn clear_caches(&self) {}
+
     #[cfg(feature = "new_epoch_sync")]
     fn get_all_epoch_hashes(
         &self,
@@ -1011,6 +1260,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         _epoch_id: &EpochId,
         _current_protocol_version: ProtocolVersion,
     ) -> Result<Option<InvalidTxError>, Error> {
+        self.num_validate_tx_calls.fetch_add(1, AtomicOrdering::Relaxed);
         Ok(None)
     }
 
@@ -1021,14 +1271,22 @@ impl RuntimeAdapter for KeyValueRuntime {
         _epoch_id: &EpochId,
         _shard_id: ShardId,
         _state_root: StateRoot,
-        _next_block_height: BlockHeight,
+        next_block_height: BlockHeight,
         transactions: &mut dyn PoolIterator,
-        _chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
+        chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
         _current_protocol_version: ProtocolVersion,
     ) -> Result<Vec<SignedTransaction>, Error> {
+        if self.failing_prepare_transactions.write().unwrap().remove(&next_block_height) {
+            return Err(Error::StorageError(StorageError::FlatStorageBlockNotSupported(format!(
+                "injected failure at height {next_block_height}"
+            ))));
+        }
         let mut res = vec![];
         while let Some(iter) = transactions.next() {
-            res.push(iter.next().unwrap());
+            let tx = iter.next().unwrap();
+            if chain_validate(&tx) {
+                res.push(tx);
+            }
         }
         Ok(res)
     }
@@ -1051,7 +1309,19 @@ impl RuntimeAdapter for KeyValueRuntime {
         _is_new_chunk: bool,
         _is_first_block_with_chunk_of_version: bool,
     ) -> Result<ApplyTransactionResult, Error> {
-        assert!(!storage_config.record_storage);
+        if let Some(permits) = self.apply_transactions_permits.lock().unwrap().as_ref() {
+            // The chunks are applied one at a time, and without waiting once the test drops the
+            // sender of the permits.
+            let _ = permits.recv();
+        }
+        if storage_config.record_storage {
+            // The whole state of the shard is read, so all of it would be needed as the proof.
+            let state_size = self.state_size.read().unwrap()[&storage_config.state_root];
+            self.recorded_storage_sizes
+                .write()
+                .unwrap()
+                .insert((*block_hash, shard_id), state_size as usize);
+        }
         let mut tx_results = vec![];
 
         let mut state =
@@ -1182,12 +1452,14 @@ impl RuntimeAdapter for KeyValueRuntime {
         let state_root = hash(&data);
         self.state.write().unwrap().insert(state_root, state);
         self.state_size.write().unwrap().insert(state_root, state_size);
+        let mut trie_changes = TrieChanges::empty(storage_config.state_root);
+        trie_changes.new_root = state_root;
 
         Ok(ApplyTransactionResult {
             trie_changes: WrappedTrieChanges::new(
                 self.get_tries(),
                 ShardUId { version: 0, shard_id: shard_id as u32 },
-                TrieChanges::empty(state_root),
+                trie_changes,
                 Default::default(),
                 *block_hash,
                 height,
@@ -1203,6 +1475,14 @@ impl RuntimeAdapter for KeyValueRuntime {
         })
     }
 
+    fn get_recorded_storage_size(
+        &self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Option<usize> {
+        self.recorded_storage_sizes.read().unwrap().get(&(*block_hash, shard_id)).copied()
+    }
+
     fn query(
         &self,
         _shard_id: ShardUId,
@@ -15,6 +15,8 @@ pub struct ValidatorSchedule {
     pub(super) chunk_only_producers: Vec<Vec<Vec<AccountId>>>,
     pub(super) validator_groups: u64,
     pub(super) num_shards: NumShards,
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    pub(super) mandates_per_shard: Option<usize>,
 }
 
 impl ValidatorSchedule {
@@ -28,6 +30,8 @@ impl ValidatorSchedule {
             chunk_only_producers: Vec::new(),
             validator_groups: 1,
             num_shards,
+            #[cfg(feature = "protocol_feature_chunk_validation")]
+            mandates_per_shard: None,
         }
     }
 
@@ -76,6 +80,15 @@ impl ValidatorSchedule {
         self
     }
 
+    /// Gives the validators of every epoch mandates to validate chunks, at least
+    /// `mandates_per_shard` of them for each shard. All validators have the same
+    /// stake, so the mandates are split evenly between them.
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    pub fn validator_mandates_per_shard(mut self, mandates_per_shard: usize) -> Self {
+        self.mandates_per_shard = Some(mandates_per_shard);
+        self
+    }
+
     pub fn all_block_producers(&self) -> impl Iterator<Item = &AccountId> {
         self.block_producers.iter().flatten()
     }
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use crate::missing_chunks::MissingChunksPoolLimits;
 use borsh::{BorshDeserialize, BorshSerialize};
 use chrono::DateTime;
 use chrono::Utc;
@@ -58,7 +59,7 @@ impl BlockStatus {
 }
 
 /// Options for block origin.
-#[derive(Eq, PartialEq, Clone, Debug)]
+#[derive(Eq, PartialEq, Clone, Debug, BorshSerialize, BorshDeserialize)]
 pub enum Provenance {
     /// No provenance.
     NONE,
@@ -212,6 +213,7 @@ pub struct ChainConfig {
     /// Currently used for flat storage background creation.
     pub background_migration_threads: usize,
     pub state_split_config: StateSplitConfig,
+    pub missing_chunks_pool_limits: MissingChunksPoolLimits,
 }
 
 impl ChainConfig {
@@ -220,6 +222,7 @@ impl ChainConfig {
             save_trie_changes: true,
             background_migration_threads: 1,
             state_split_config: StateSplitConfig::default(),
+            missing_chunks_pool_limits: MissingChunksPoolLimits::default(),
         }
     }
 }
@@ -362,6 +365,16 @@ pub trait RuntimeAdapter: Send + Sync {
         is_first_block_with_chunk_of_version: bool,
     ) -> Result<ApplyTransactionResult, Error>;
 
+    /// Returns the size in bytes of the trie nodes and values recorded while applying the
+    /// transactions and receipts of shard `shard_id` in block `block_hash`, i.e. the size of the
+    /// state proof needed to apply them without the state.
+    /// Returns None if the storage wasn't recorded or the size is no longer remembered.
+    fn get_recorded_storage_size(
+        &self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Option<usize>;
+
     /// Query runtime with given `path` and `data`.
     fn query(
         &self,
@@ -91,6 +91,9 @@ pub(crate) struct ShardContext {
     pub shard_uid: ShardUId,
     /// Whether shard layout changes in the next epoch.
     pub will_shard_layout_change: bool,
+    /// Whether to record the storage read while applying a new chunk, for the state witness of
+    /// the next chunk of the shard.
+    pub record_storage: bool,
 }
 
 /// Processes shard update with given block and shard.
@@ -167,7 +170,7 @@ fn apply_new_chunk(
         use_flat_storage: true,
         source: crate::types::StorageDataSource::Db,
         state_patch,
-        record_storage: false,
+        record_storage: shard_info.record_storage,
     };
     match runtime.apply_transactions(
         shard_id,
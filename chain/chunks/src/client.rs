@@ -4,6 +4,7 @@ use actix::Message;
 
 use near_pool::types::PoolIterator;
 use near_pool::{InsertTransactionResult, PoolIteratorWrapper, TransactionPool};
+use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout::{account_id_to_shard_uid, ShardLayout, ShardUId};
 use near_primitives::{
     epoch_manager::RngSeed,
@@ -30,6 +31,12 @@ pub enum ShardsManagerResponse {
     /// block, so that if we are a block producer, we may create a block that contains
     /// this chunk now. The producer of this chunk is also provided.
     ChunkHeaderReadyForInclusion { chunk_header: ShardChunkHeader, chunk_producer: AccountId },
+    /// Same as `ChunkCompleted`, for several chunks completed in one processing pass
+    /// of the ShardsManager.
+    ChunksCompleted(Vec<(PartialEncodedChunk, Option<ShardChunk>)>),
+    /// Same as `ChunkHeaderReadyForInclusion`, for several chunk headers that became
+    /// ready in one processing pass of the ShardsManager.
+    ChunkHeadersReadyForInclusion(Vec<(ShardChunkHeader, AccountId)>),
 }
 
 pub struct ShardedTransactionPool {
@@ -62,6 +69,26 @@ impl ShardedTransactionPool {
         self.pool_for_shard(shard_uid).insert_transaction(tx)
     }
 
+    /// Same as `insert_transaction`, but keeps `reserved_size` bytes of the pool size limit free.
+    pub fn insert_transaction_with_reserve(
+        &mut self,
+        shard_uid: ShardUId,
+        tx: SignedTransaction,
+        reserved_size: u64,
+    ) -> InsertTransactionResult {
+        self.pool_for_shard(shard_uid).insert_transaction_with_reserve(tx, reserved_size)
+    }
+
+    /// Hashes of the transactions in the pool of the shard, in no particular order.
+    pub fn transaction_hashes(&self, shard_uid: ShardUId) -> impl Iterator<Item = &CryptoHash> {
+        self.tx_pools.get(&shard_uid).into_iter().flat_map(|pool| pool.transaction_hashes())
+    }
+
+    /// Limit of the size of the pool of each shard, in bytes.
+    pub fn pool_size_limit(&self) -> Option<u64> {
+        self.pool_size_limit
+    }
+
     pub fn remove_transactions(&mut self, shard_uid: ShardUId, transactions: &[SignedTransaction]) {
         if let Some(pool) = self.tx_pools.get_mut(&shard_uid) {
             pool.remove_transactions(transactions)
@@ -111,7 +138,12 @@ impl ShardedTransactionPool {
     /// the new shard layout.
     /// It works by emptying the pools for old shard uids and re-inserting the
     /// transactions back to the pool with the new shard uids.
-    pub fn reshard(&mut self, old_shard_layout: &ShardLayout, new_shard_layout: &ShardLayout) {
+    /// Returns the number of transactions moved into each of the new shards.
+    pub fn reshard(
+        &mut self,
+        old_shard_layout: &ShardLayout,
+        new_shard_layout: &ShardLayout,
+    ) -> HashMap<ShardUId, usize> {
         tracing::debug!(
             target: "client",
             old_shard_layout_version = old_shard_layout.version(),
@@ -134,11 +166,15 @@ impl ShardedTransactionPool {
             }
         }
 
+        let mut moved = HashMap::new();
         for tx in transactions {
             let signer_id = &tx.transaction.signer_id;
             let new_shard_uid = account_id_to_shard_uid(&signer_id, new_shard_layout);
-            self.insert_transaction(new_shard_uid, tx);
+            if let InsertTransactionResult::Success = self.insert_transaction(new_shard_uid, tx) {
+                *moved.entry(new_shard_uid).or_default() += 1;
+            }
         }
+        moved
     }
 }
 
@@ -234,7 +270,8 @@ mod tests {
         // reshard
 
         tracing::info!("resharding the pool");
-        pool.reshard(&old_shard_layout, &new_shard_layout);
+        let moved = pool.reshard(&old_shard_layout, &new_shard_layout);
+        assert_eq!(moved.values().sum::<usize>(), n);
 
         // check the pool is correctly resharded
 
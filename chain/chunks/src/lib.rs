@@ -96,6 +96,7 @@ use near_chain::byzantine_assert;
 use near_chain::chunks_store::ReadOnlyChunksStore;
 use near_chain::near_chain_primitives::error::Error::DBNotFoundErr;
 use near_chain::types::EpochManagerAdapter;
+use near_chain_configs::ChunkRequestRetryConfig;
 pub use near_chunks_primitives::Error;
 use near_epoch_manager::shard_tracker::ShardTracker;
 use near_network::shards_manager::ShardsManagerRequestFromNetwork;
@@ -179,10 +180,30 @@ struct ChunkRequestInfo {
     last_requested: time::Instant,
 }
 
+/// Timings of the requests of a chunk, see `ChunkRequestRetryConfig`.
+#[derive(Clone, Copy, Debug)]
+struct RequestRetryTimings {
+    retry: time::Duration,
+    switch_to_others: time::Duration,
+    switch_to_full_fetch: time::Duration,
+}
+
+impl From<&ChunkRequestRetryConfig> for RequestRetryTimings {
+    fn from(config: &ChunkRequestRetryConfig) -> Self {
+        let to_time = |duration: std::time::Duration| {
+            time::Duration::try_from(duration).unwrap_or(CHUNK_REQUEST_RETRY_MAX)
+        };
+        Self {
+            retry: to_time(config.retry_period),
+            switch_to_others: to_time(config.switch_to_others),
+            switch_to_full_fetch: to_time(config.switch_to_full_fetch),
+        }
+    }
+}
+
 struct RequestPool {
-    retry_duration: time::Duration,
-    switch_to_others_duration: time::Duration,
-    switch_to_full_fetch_duration: time::Duration,
+    default_timings: RequestRetryTimings,
+    shard_timings: HashMap<ShardId, RequestRetryTimings>,
     max_duration: time::Duration,
     requests: HashMap<ChunkHash, ChunkRequestInfo>,
 }
@@ -195,13 +216,21 @@ impl RequestPool {
         max_duration: time::Duration,
     ) -> Self {
         Self {
-            retry_duration,
-            switch_to_others_duration,
-            switch_to_full_fetch_duration,
+            default_timings: RequestRetryTimings {
+                retry: retry_duration,
+                switch_to_others: switch_to_others_duration,
+                switch_to_full_fetch: switch_to_full_fetch_duration,
+            },
+            shard_timings: HashMap::default(),
             max_duration,
             requests: HashMap::default(),
         }
     }
+
+    fn timings(&self, shard_id: ShardId) -> RequestRetryTimings {
+        self.shard_timings.get(&shard_id).copied().unwrap_or(self.default_timings)
+    }
+
     pub fn contains_key(&self, chunk_hash: &ChunkHash) -> bool {
         self.requests.contains_key(chunk_hash)
     }
@@ -225,12 +254,16 @@ impl RequestPool {
         let mut removed_requests = HashSet::<ChunkHash>::default();
         let mut requests = Vec::new();
         for (chunk_hash, chunk_request) in self.requests.iter_mut() {
+            let retry_duration = self
+                .shard_timings
+                .get(&chunk_request.shard_id)
+                .map_or(self.default_timings.retry, |timings| timings.retry);
             if current_time - chunk_request.added >= self.max_duration {
                 debug!(target: "chunks", "Evicted chunk requested that was never fetched {} (shard_id: {})", chunk_hash.0, chunk_request.shard_id);
                 removed_requests.insert(chunk_hash.clone());
                 continue;
             }
-            if current_time - chunk_request.last_requested >= self.retry_duration {
+            if current_time - chunk_request.last_requested >= retry_duration {
                 chunk_request.last_requested = current_time;
                 requests.push((chunk_hash.clone(), chunk_request.clone()));
             }
@@ -266,6 +299,16 @@ pub struct ShardsManager {
     // header_head is new, but we would only know that the older chunks are old because
     // header_head is much newer.
     chain_header_head: Tip,
+    // If set, notifications for the client are collected here instead of being sent one by one.
+    // See `start_client_batch` and `flush_client_batch`.
+    client_batch: Option<ClientNotificationBatch>,
+}
+
+/// Notifications for the client collected during a single processing pass.
+#[derive(Default)]
+struct ClientNotificationBatch {
+    chunk_headers_ready_for_inclusion: Vec<(ShardChunkHeader, AccountId)>,
+    completed_chunks: Vec<(PartialEncodedChunk, Option<ShardChunk>)>,
 }
 
 impl ShardsManager {
@@ -302,9 +345,22 @@ impl ShardsManager {
             chunk_forwards_cache: lru::LruCache::new(CHUNK_FORWARD_CACHE_SIZE),
             chain_head: initial_chain_head,
             chain_header_head: initial_chain_header_head,
+            client_batch: None,
         }
     }
 
+    /// Sets the timings of the chunk requests: the ones of the given shards, and `default` for the
+    /// other shards.
+    pub fn set_chunk_request_retry(
+        &mut self,
+        default: &ChunkRequestRetryConfig,
+        per_shard: &HashMap<ShardId, ChunkRequestRetryConfig>,
+    ) {
+        self.requested_partial_encoded_chunks.default_timings = default.into();
+        self.requested_partial_encoded_chunks.shard_timings =
+            per_shard.iter().map(|(shard_id, config)| (*shard_id, config.into())).collect();
+    }
+
     pub fn update_chain_heads(&mut self, head: Tip, header_head: Tip) {
         self.encoded_chunks.update_largest_seen_height(
             head.height,
@@ -735,17 +791,15 @@ impl ShardsManager {
             });
             let old_block = self.chain_header_head.last_block_hash != chunk_request.prev_block_hash
                 && self.chain_header_head.prev_block_hash != chunk_request.prev_block_hash;
+            let timings = self.requested_partial_encoded_chunks.timings(chunk_request.shard_id);
 
             match self.request_partial_encoded_chunk(
                 chunk_request.height,
                 &chunk_request.ancestor_hash,
                 chunk_request.shard_id,
                 &chunk_hash,
-                self.clock.now() - chunk_request.added
-                    >= self.requested_partial_encoded_chunks.switch_to_full_fetch_duration,
-                old_block
-                    || self.clock.now() - chunk_request.added
-                        >= self.requested_partial_encoded_chunks.switch_to_others_duration,
+                self.clock.now() - chunk_request.added >= timings.switch_to_full_fetch,
+                old_block || self.clock.now() - chunk_request.added >= timings.switch_to_others,
                 fetch_from_archival,
             ) {
                 Ok(()) => {}
@@ -1633,10 +1687,7 @@ impl ShardsManager {
 
         if have_all_parts {
             if self.encoded_chunks.mark_chunk_for_inclusion(&chunk_hash) {
-                self.client_adapter.send(ShardsManagerResponse::ChunkHeaderReadyForInclusion {
-                    chunk_header: header.clone(),
-                    chunk_producer,
-                });
+                self.notify_chunk_header_ready_for_inclusion(header.clone(), chunk_producer);
             }
         }
         // we can safely unwrap here because we already checked that chunk_hash exist in encoded_chunks
@@ -1711,8 +1762,58 @@ impl ShardsManager {
         self.encoded_chunks.remove_from_cache_if_outside_horizon(&chunk_hash);
         self.requested_partial_encoded_chunks.remove(&chunk_hash);
         debug!(target: "chunks", "Completed chunk {:?}", chunk_hash);
-        self.client_adapter
-            .send(ShardsManagerResponse::ChunkCompleted { partial_chunk, shard_chunk });
+        match &mut self.client_batch {
+            Some(batch) => batch.completed_chunks.push((partial_chunk, shard_chunk)),
+            None => self
+                .client_adapter
+                .send(ShardsManagerResponse::ChunkCompleted { partial_chunk, shard_chunk }),
+        }
+    }
+
+    fn notify_chunk_header_ready_for_inclusion(
+        &mut self,
+        chunk_header: ShardChunkHeader,
+        chunk_producer: AccountId,
+    ) {
+        match &mut self.client_batch {
+            Some(batch) => {
+                batch.chunk_headers_ready_for_inclusion.push((chunk_header, chunk_producer))
+            }
+            None => self.client_adapter.send(ShardsManagerResponse::ChunkHeaderReadyForInclusion {
+                chunk_header,
+                chunk_producer,
+            }),
+        }
+    }
+
+    /// Starts collecting notifications for the client, so that they can be sent in batches
+    /// by `flush_client_batch` instead of one message per chunk.
+    fn start_client_batch(&mut self) {
+        debug_assert!(self.client_batch.is_none());
+        self.client_batch = Some(ClientNotificationBatch::default());
+    }
+
+    /// Sends the notifications collected since `start_client_batch` to the client.
+    fn flush_client_batch(&mut self) {
+        let Some(batch) = self.client_batch.take() else { return };
+        let mut headers = batch.chunk_headers_ready_for_inclusion;
+        if headers.len() == 1 {
+            let (chunk_header, chunk_producer) = headers.pop().unwrap();
+            self.client_adapter.send(ShardsManagerResponse::ChunkHeaderReadyForInclusion {
+                chunk_header,
+                chunk_producer,
+            });
+        } else if !headers.is_empty() {
+            self.client_adapter.send(ShardsManagerResponse::ChunkHeadersReadyForInclusion(headers));
+        }
+        let mut completed_chunks = batch.completed_chunks;
+        if completed_chunks.len() == 1 {
+            let (partial_chunk, shard_chunk) = completed_chunks.pop().unwrap();
+            self.client_adapter
+                .send(ShardsManagerResponse::ChunkCompleted { partial_chunk, shard_chunk });
+        } else if !completed_chunks.is_empty() {
+            self.client_adapter.send(ShardsManagerResponse::ChunksCompleted(completed_chunks));
+        }
     }
 
     /// Try to process chunks in the chunk cache whose previous block hash is `prev_block_hash` and
@@ -1732,6 +1833,8 @@ impl ShardsManager {
                 }
             }
         }
+        // All the chunks completed in this pass are sent to the client together.
+        self.start_client_batch();
         for header in chunks_to_process {
             debug!(target: "chunks",
                 chunk_hash = ?header.chunk_hash(),
@@ -1741,6 +1844,7 @@ impl ShardsManager {
                 error!(target:"chunks", "unexpected error processing orphan chunk {:?}", err)
             }
         }
+        self.flush_client_batch();
     }
 
     /// Send the parts of the partial_encoded_chunk that are owned by `self.me` to the
@@ -2186,6 +2290,86 @@ mod test {
         };
     }
 
+    #[test]
+    fn test_chunk_request_retry_per_shard() {
+        let mock_tip = Tip {
+            height: 0,
+            last_block_hash: CryptoHash::default(),
+            prev_block_hash: CryptoHash::default(),
+            epoch_id: EpochId::default(),
+            next_epoch_id: EpochId::default(),
+        };
+        let store = create_test_store();
+        let epoch_manager = setup_epoch_manager_with_block_and_chunk_producers(
+            store.clone(),
+            vec!["test".parse().unwrap()],
+            vec![],
+            2,
+            2,
+        );
+        let epoch_manager = Arc::new(epoch_manager.into_handle());
+        let shard_tracker = ShardTracker::new(TrackedConfig::AllShards, epoch_manager.clone());
+        let network_adapter = Arc::new(MockPeerManagerAdapter::default());
+        let client_adapter = Arc::new(MockClientAdapterForShardsManager::default());
+        let clock = FakeClock::default();
+        let mut shards_manager = ShardsManager::new(
+            clock.clock(),
+            Some("test".parse().unwrap()),
+            epoch_manager,
+            shard_tracker,
+            network_adapter.as_sender(),
+            client_adapter.as_sender(),
+            ReadOnlyChunksStore::new(store),
+            mock_tip.clone(),
+            mock_tip,
+        );
+        let default_retry =
+            ChunkRequestRetryConfig::with_retry_period(std::time::Duration::from_millis(100));
+        let fast_retry = ChunkRequestRetryConfig {
+            retry_period: std::time::Duration::from_millis(20),
+            ..default_retry
+        };
+        shards_manager.set_chunk_request_retry(&default_retry, &HashMap::from([(1, fast_retry)]));
+
+        let added = clock.now().into();
+        for shard_id in [0, 1] {
+            shards_manager.requested_partial_encoded_chunks.insert(
+                ChunkHash(hash(&[shard_id as u8])),
+                ChunkRequestInfo {
+                    height: 0,
+                    ancestor_hash: Default::default(),
+                    prev_block_hash: Default::default(),
+                    shard_id,
+                    added,
+                    last_requested: added,
+                },
+            );
+        }
+        let mut resend_chunk_requests = |advance_ms: i64| {
+            clock.advance(time::Duration::milliseconds(advance_ms));
+            shards_manager.resend_chunk_requests();
+            let mut requested = vec![];
+            while let Some(request) = network_adapter.pop() {
+                if let NetworkRequests::PartialEncodedChunkRequest { request, .. } =
+                    request.as_network_requests_ref()
+                {
+                    requested.push(request.chunk_hash.clone());
+                }
+            }
+            requested.sort_by_key(|chunk_hash| chunk_hash.0);
+            requested.dedup();
+            requested
+        };
+
+        // Only the chunk of shard 1 is re-requested before the default retry period.
+        assert_eq!(resend_chunk_requests(50), vec![ChunkHash(hash(&[1]))]);
+        assert_eq!(resend_chunk_requests(10), vec![]);
+        assert_eq!(resend_chunk_requests(10), vec![ChunkHash(hash(&[1]))]);
+        let mut both = vec![ChunkHash(hash(&[0])), ChunkHash(hash(&[1]))];
+        both.sort_by_key(|chunk_hash| chunk_hash.0);
+        assert_eq!(resend_chunk_requests(30), both);
+    }
+
     #[test]
     fn test_resend_chunk_requests() {
         // Test that resending chunk requests won't request for parts the node already received
@@ -1,16 +1,17 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use actix::{Actor, Addr, Arbiter, ArbiterHandle, Context, Handler};
 use near_async::messaging::Sender;
 use near_async::time;
 use near_chain::{chunks_store::ReadOnlyChunksStore, types::Tip};
+use near_chain_configs::ChunkRequestRetryConfig;
 use near_epoch_manager::{shard_tracker::ShardTracker, EpochManagerAdapter};
 use near_network::{
     shards_manager::ShardsManagerRequestFromNetwork, types::PeerManagerMessageRequest,
 };
 use near_o11y::WithSpanContext;
 use near_performance_metrics_macros::perf;
-use near_primitives::types::AccountId;
+use near_primitives::types::{AccountId, ShardId};
 use near_store::{DBCol, Store, HEADER_HEAD_KEY, HEAD_KEY};
 
 use crate::{
@@ -82,6 +83,7 @@ pub fn start_shards_manager(
     me: Option<AccountId>,
     store: Store,
     chunk_request_retry_period: Duration,
+    chunk_request_retry_per_shard: HashMap<ShardId, ChunkRequestRetryConfig>,
 ) -> (Addr<ShardsManagerActor>, ArbiterHandle) {
     let shards_manager_arbiter = Arbiter::new();
     let shards_manager_arbiter_handle = shards_manager_arbiter.handle();
@@ -95,7 +97,7 @@ pub fn start_shards_manager(
         .unwrap()
         .expect("ShardsManager must be initialized after the chain is initialized");
     let chunks_store = ReadOnlyChunksStore::new(store);
-    let shards_manager = ShardsManager::new(
+    let mut shards_manager = ShardsManager::new(
         time::Clock::real(),
         me,
         epoch_manager,
@@ -106,6 +108,15 @@ pub fn start_shards_manager(
         chain_head,
         chain_header_head,
     );
+    shards_manager.set_chunk_request_retry(
+        &ChunkRequestRetryConfig::with_retry_period(chunk_request_retry_period),
+        &chunk_request_retry_per_shard,
+    );
+    // Check the requests often enough for the shards with the shortest retry period.
+    let chunk_request_retry_period = chunk_request_retry_per_shard
+        .values()
+        .map(|config| config.retry_period)
+        .fold(chunk_request_retry_period, Duration::min);
     let shards_manager_addr =
         ShardsManagerActor::start_in_arbiter(&shards_manager_arbiter_handle, move |_| {
             ShardsManagerActor::new(shards_manager, chunk_request_retry_period)
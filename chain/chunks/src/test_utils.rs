@@ -213,8 +213,10 @@ impl ChunkTestFixture {
     pub fn count_chunk_completion_messages(&self) -> usize {
         let mut chunks_completed = 0;
         while let Some(message) = self.mock_client_adapter.pop() {
-            if let ShardsManagerResponse::ChunkCompleted { .. } = message {
-                chunks_completed += 1;
+            match message {
+                ShardsManagerResponse::ChunkCompleted { .. } => chunks_completed += 1,
+                ShardsManagerResponse::ChunksCompleted(chunks) => chunks_completed += chunks.len(),
+                _ => {}
             }
         }
         chunks_completed
@@ -223,8 +225,12 @@ impl ChunkTestFixture {
     pub fn count_chunk_ready_for_inclusion_messages(&self) -> usize {
         let mut chunks_ready = 0;
         while let Some(message) = self.mock_client_adapter.pop() {
-            if let ShardsManagerResponse::ChunkHeaderReadyForInclusion { .. } = message {
-                chunks_ready += 1;
+            match message {
+                ShardsManagerResponse::ChunkHeaderReadyForInclusion { .. } => chunks_ready += 1,
+                ShardsManagerResponse::ChunkHeadersReadyForInclusion(headers) => {
+                    chunks_ready += headers.len()
+                }
+                _ => {}
             }
         }
         chunks_ready
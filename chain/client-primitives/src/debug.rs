@@ -1,5 +1,13 @@
-//! Structs in this module are used for debug purposes, and might change at any time
-//! without backwards compatibility of JSON encoding.
+//! Structs in this module are used for debug purposes, and are served as JSON by the debug API
+//! of the node to the debug pages and to external dashboards.
+//!
+//! The JSON encoding of the views is versioned by `DEBUG_VIEWS_VERSION`, which the debug API
+//! returns along with every view. Within a version, fields and variants may only be added:
+//! readers ignore the fields they don't know, and the fields added later are defaulted when
+//! missing, so that the views can be decoded across releases. Renaming or removing a field,
+//! changing its type or its meaning requires bumping the version. The views covered by the
+//! snapshot tests below encode their maps in key order, so that their encoding is deterministic
+//! and any change of it shows up in the snapshots.
 use crate::types::StatusError;
 use chrono::DateTime;
 use near_primitives::types::EpochId;
@@ -10,14 +18,21 @@ use near_primitives::views::{
 use near_primitives::{
     block_header::ApprovalInner,
     hash::CryptoHash,
+    network::PeerId,
+    shard_layout::{ShardUId, ShardVersion},
     sharding::ChunkHash,
-    types::{AccountId, BlockHeight},
+    types::{AccountId, Balance, BlockHeight, ShardId},
     views::ValidatorInfo,
 };
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+/// Version of the JSON encoding of the debug views, see the module documentation.
+pub const DEBUG_VIEWS_VERSION: u32 = 1;
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct TrackedShardsView {
+    /// Uids of the shards in the layout of the header head's epoch.
+    pub shard_uids: Vec<ShardUId>,
     pub shards_tracked_this_epoch: Vec<bool>,
     pub shards_tracked_next_epoch: Vec<bool>,
 }
@@ -99,6 +114,9 @@ pub struct ChunkProduction {
     // How long did the chunk production take (reed solomon encoding, preparing fragments etc.)
     // Doesn't include network latency.
     pub chunk_production_duration_millis: Option<u64>,
+    // Size of the trie nodes recorded while applying the previous chunk of the shard, i.e. the
+    // state needed to validate the chunk without tracking the shard.
+    pub state_witness_size: Option<u64>,
 }
 // Information about the block produced by this node.
 // For debug purposes only.
@@ -166,6 +184,213 @@ pub struct ValidatorStatus {
     pub banned_chunk_producers: Vec<(EpochId, Vec<AccountId>)>,
 }
 
+// Information about a peer that this node has banned.
+// For debug purposes only.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct BanHistoryEntry {
+    pub peer_id: PeerId,
+    // Reason for the ban, as sent to the peer manager.
+    pub reason: String,
+    // Hash of the block or chunk that triggered the ban, if there was one.
+    pub triggered_by: Option<CryptoHash>,
+    // Time when the ban was issued.
+    pub ban_time: DateTime<chrono::Utc>,
+}
+
+// Production of a block or chunk that this node was responsible for.
+// For debug purposes only.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProductionReportEntry {
+    pub height: BlockHeight,
+    // Shard of the chunk, None for the block.
+    pub shard_id: Option<ShardId>,
+    // Whether we produced the block or chunk.
+    pub produced: bool,
+    // Time when we produced it, if still known.
+    pub production_time: Option<DateTime<chrono::Utc>>,
+    // How long the production took, if still known. For blocks it's measured from the moment we
+    // had enough approvals.
+    pub production_duration_millis: Option<u64>,
+    // Whether it's on the canonical chain.
+    pub included: bool,
+    // Why it isn't on the canonical chain, None if it is.
+    pub missed_reason: Option<String>,
+}
+
+// Block and chunk production of this node in an epoch, for every height at which it was a
+// producer. For debug purposes only.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EpochProductionReport {
+    pub epoch_id: EpochId,
+    pub validator: AccountId,
+    pub first_height: BlockHeight,
+    pub last_height: BlockHeight,
+    // Whether the epoch is over. Reports for the current epoch only cover heights up to the head.
+    pub is_final: bool,
+    // Sorted by height, the block before the chunks of the same height.
+    pub entries: Vec<ProductionReportEntry>,
+}
+
+// The block this node would produce on top of its current head, built without being signed or
+// saved. For debug purposes only.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SimulatedBlockView {
+    pub height: BlockHeight,
+    pub prev_hash: CryptoHash,
+    // Which shards would get a new chunk.
+    pub chunk_mask: Vec<bool>,
+    // Block producers whose approvals would be included, sorted.
+    pub approvals: Vec<AccountId>,
+    // Stake of the approvals above, and of all the approvers of the block.
+    pub approvals_stake: Balance,
+    pub total_stake: Balance,
+    pub gas_price: Balance,
+    // Size of the borsh-serialized block.
+    pub estimated_size_bytes: u64,
+}
+
+// Outcome of simulating the production of the next block.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SimulatedBlockProduction {
+    Produced(SimulatedBlockView),
+    // The block wouldn't be produced at `height`, and why.
+    Skipped { height: BlockHeight, reason: String },
+}
+
+// A decision taken by header, block or state sync. For debug purposes only.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SyncEvent {
+    // Header sync asked `peer_id` for the headers following a locator of `locator_len` hashes.
+    HeadersRequested {
+        peer_id: PeerId,
+        peer_height: BlockHeight,
+        header_head_height: BlockHeight,
+        locator_len: usize,
+    },
+    // The header head didn't reach the height expected from the syncing peer in time.
+    HeaderSyncStalled {
+        header_head_height: BlockHeight,
+        expected_height: BlockHeight,
+    },
+    // The syncing peer was banned for claiming a height it didn't send headers for.
+    PeerBanned {
+        peer_id: PeerId,
+        claimed_height: BlockHeight,
+    },
+    // Block sync asked `peer_id` for the block at `height`.
+    BlockRequested {
+        peer_id: PeerId,
+        height: BlockHeight,
+        hash: CryptoHash,
+    },
+    // Block sync had no peer to ask for the block at `height`.
+    NoPeerForBlock {
+        height: BlockHeight,
+        hash: CryptoHash,
+        archival: bool,
+    },
+    // The head is too far behind the header head, block sync hands over to state sync.
+    StateSyncNeeded {
+        head_height: BlockHeight,
+        header_head_height: BlockHeight,
+    },
+    // State sync didn't receive the block before the sync block in time.
+    StateSyncBlockRequestTimedOut {
+        prev_hash: CryptoHash,
+    },
+    StateHeaderRequested {
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        peer_id: PeerId,
+    },
+    StatePartsRequested {
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        num_parts: u64,
+    },
+    // The state header or some state parts of the shard weren't downloaded in time.
+    StateDownloadTimedOut {
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SyncLogEntry {
+    pub time: DateTime<chrono::Utc>,
+    pub event: SyncEvent,
+}
+
+// A step of resharding taken by this node. For debug purposes only.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ReshardingEvent {
+    // The shard layout changes after the block `block_hash`, the last block of its epoch.
+    ShardLayoutChangeDetected {
+        block_hash: CryptoHash,
+        old_version: ShardVersion,
+        new_version: ShardVersion,
+    },
+    // The transaction pool was moved to the new shard layout. Number of the transactions moved
+    // into each shard of the new layout, shards which got none are left out.
+    PoolResharded {
+        new_version: ShardVersion,
+        moved_transactions: BTreeMap<ShardId, usize>,
+    },
+    // Checking whether the shard layout changes after the block `block_hash` failed.
+    ShardLayoutLookupFailed {
+        block_hash: CryptoHash,
+        error: String,
+    },
+    // Splitting the state of the shard was handed over to the sync jobs.
+    SplitScheduled {
+        sync_hash: CryptoHash,
+        shard_id: ShardId,
+    },
+    // The state of the shard was split and saved for the shards of the new layout.
+    SplitCompleted {
+        sync_hash: CryptoHash,
+        shard_id: ShardId,
+    },
+    SplitFailed {
+        sync_hash: CryptoHash,
+        shard_id: ShardId,
+        error: String,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReshardingLogEntry {
+    pub time: DateTime<chrono::Utc>,
+    pub event: ReshardingEvent,
+}
+
+// A dynamic update of the client config applied while the node is running.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ClientConfigUpdate {
+    pub time: DateTime<chrono::Utc>,
+    // New values of the fields changed by the update, by field name. Empty if the update didn't
+    // change anything.
+    pub changed_fields: BTreeMap<String, serde_json::Value>,
+}
+
+// The client config this node is running with and the one it started with, with the secrets
+// redacted.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ClientConfigState {
+    pub effective: serde_json::Value,
+    pub startup: serde_json::Value,
+    // Dynamic updates applied since the startup, oldest first.
+    pub updates: Vec<ClientConfigUpdate>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct SyncStatusDebugView {
+    pub status: SyncStatusView,
+    // The most recent sync decisions, oldest first.
+    #[serde(default)]
+    pub log: Vec<SyncLogEntry>,
+}
+
 // Different debug requests that can be sent by HTML pages, via GET.
 #[derive(Debug)]
 pub enum DebugStatus {
@@ -185,6 +410,18 @@ pub enum DebugStatus {
     ChainProcessingStatus,
     // The state parts already requested.
     RequestedStateParts,
+    // Peers banned by this node at or after the given time (all remembered bans if None).
+    BanHistory(Option<DateTime<chrono::Utc>>),
+    // Block and chunk production of this node in the last epoch.
+    ProductionReport,
+    // The block this node would produce right now.
+    SimulatedBlockProduction,
+    // Current values of the metrics.
+    MetricsSnapshot,
+    // Resharding steps recently taken by this node.
+    ReshardingLog,
+    // The client config this node is running with, compared to the one it started with.
+    ClientConfigState,
 }
 
 impl actix::Message for DebugStatus {
@@ -193,7 +430,7 @@ impl actix::Message for DebugStatus {
 
 #[derive(serde::Serialize, Debug)]
 pub enum DebugStatusResponse {
-    SyncStatus(SyncStatusView),
+    SyncStatus(SyncStatusDebugView),
     CatchupStatus(Vec<CatchupStatusView>),
     TrackedShards(TrackedShardsView),
     // List of epochs - in descending order (next epoch is first).
@@ -206,4 +443,85 @@ pub enum DebugStatusResponse {
     ChainProcessingStatus(ChainProcessingInfo),
     // The state parts already requested.
     RequestedStateParts(Vec<RequestedStatePartsView>),
+    // Peers banned by this node, oldest first.
+    BanHistory(Vec<BanHistoryEntry>),
+    // Block and chunk production of this node in the last epoch.
+    ProductionReport(EpochProductionReport),
+    // The block this node would produce right now, or why it wouldn't.
+    SimulatedBlockProduction(SimulatedBlockProduction),
+    // Current values of the metrics, keyed by metric name and labels.
+    MetricsSnapshot(HashMap<String, f64>),
+    // Resharding steps recently taken by this node, oldest first.
+    ReshardingLog(Vec<ReshardingLogEntry>),
+    ClientConfigState(ClientConfigState),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SyncEvent, SyncLogEntry, SyncStatusDebugView};
+    use near_primitives::hash::hash;
+    use near_primitives::utils::from_timestamp;
+    use near_primitives::views::{
+        BlockStatusView, CatchupStatusView, DownloadStatusView, ShardSyncDownloadView,
+        SyncStatusView,
+    };
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_catchup_status_view_encoding() {
+        let view = CatchupStatusView {
+            sync_block_hash: hash(b"sync"),
+            sync_block_height: 100,
+            shard_sync_status: BTreeMap::from([
+                (3, "StateDownloadParts".to_string()),
+                (0, "StateSyncDone".to_string()),
+            ]),
+            blocks_to_catchup: vec![
+                BlockStatusView::new(&101, &hash(b"101")),
+                BlockStatusView::new(&102, &hash(b"102")),
+            ],
+        };
+        insta::assert_json_snapshot!("catchup_status_view", view);
+
+        // Fields added later are defaulted, unknown fields are ignored.
+        let decoded: CatchupStatusView = serde_json::from_value(serde_json::json!({
+            "sync_block_height": 100,
+            "added_in_a_later_release": true,
+        }))
+        .unwrap();
+        assert_eq!(decoded, CatchupStatusView { sync_block_height: 100, ..Default::default() });
+    }
+
+    #[test]
+    fn test_sync_status_debug_view_encoding() {
+        let download = |done| ShardSyncDownloadView {
+            downloads: vec![DownloadStatusView { error: false, done }],
+            status: "parts".to_string(),
+        };
+        let view = SyncStatusDebugView {
+            status: SyncStatusView::StateSync(
+                hash(b"sync"),
+                BTreeMap::from([(1, download(false)), (0, download(true))]),
+            ),
+            log: vec![
+                SyncLogEntry {
+                    time: from_timestamp(1_700_000_000_000_000_000),
+                    event: SyncEvent::StateSyncNeeded { head_height: 10, header_head_height: 200 },
+                },
+                SyncLogEntry {
+                    time: from_timestamp(1_700_000_001_000_000_000),
+                    event: SyncEvent::StatePartsRequested {
+                        shard_id: 0,
+                        sync_hash: hash(b"sync"),
+                        num_parts: 4,
+                    },
+                },
+            ],
+        };
+        insta::assert_json_snapshot!("sync_status_debug_view", view);
+
+        let decoded: SyncStatusDebugView =
+            serde_json::from_value(serde_json::json!({ "status": "NoSync" })).unwrap();
+        assert_eq!(decoded, SyncStatusDebugView { status: SyncStatusView::NoSync, log: vec![] });
+    }
 }
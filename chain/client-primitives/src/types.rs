@@ -7,8 +7,8 @@ use near_primitives::merkle::{MerklePath, PartialMerkleTree};
 use near_primitives::network::PeerId;
 use near_primitives::sharding::ChunkHash;
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockReference, EpochId, EpochReference, MaybeBlockId, ShardId,
-    TransactionOrReceiptId,
+    AccountId, BlockHeight, BlockReference, EpochId, EpochReference, MaybeBlockId, ProtocolVersion,
+    ShardId, StateRoot, TransactionOrReceiptId,
 };
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
@@ -32,10 +32,29 @@ pub enum Error {
     Chain(#[from] near_chain_primitives::Error),
     #[error("Chunk: {0}")]
     Chunk(#[from] near_chunks_primitives::Error),
-    #[error("Block Producer: {0}")]
-    BlockProducer(String),
-    #[error("Chunk Producer: {0}")]
-    ChunkProducer(String),
+    #[error("Block Producer: {kind}")]
+    BlockProducer { height: BlockHeight, kind: BlockProducerErrorKind },
+    #[error("Chunk Producer: {kind}")]
+    ChunkProducer { shard_id: ShardId, height: BlockHeight, kind: ChunkProducerErrorKind },
+    #[error("Production report is only available for the current and the last epoch, not {0:?}")]
+    ProductionReportUnavailable(EpochId),
+    #[error("No block production inputs recorded at height {0}")]
+    MissingProductionInputs(BlockHeight),
+    /// The block rebuilt from the persisted production inputs differs from the produced one, i.e.
+    /// the block production isn't deterministic.
+    #[error(
+        "Rebuilt block at height {height} has hash {rebuilt_hash}, but the produced block has \
+         hash {produced_hash}"
+    )]
+    RebuiltBlockMismatch {
+        height: BlockHeight,
+        rebuilt_hash: CryptoHash,
+        produced_hash: CryptoHash,
+    },
+    #[error("Unsupported: fast_forward with an epoch length of 3 or less")]
+    UnsupportedFastForward,
+    #[error("IO Error: {0}")]
+    IOError(#[from] std::io::Error),
     #[error("Other: {0}")]
     Other(String),
 }
@@ -46,6 +65,142 @@ impl From<near_primitives::errors::EpochError> for Error {
     }
 }
 
+impl Error {
+    /// Error of the production of the block at `height`. Chain and epoch errors can be passed
+    /// as `kind` to attach the height to them.
+    pub fn block_producer(height: BlockHeight, kind: impl Into<BlockProducerErrorKind>) -> Self {
+        Error::BlockProducer { height, kind: kind.into() }
+    }
+
+    /// Error of the production of the chunk of `shard_id` at `height`. Chain and epoch errors can
+    /// be passed as `kind` to attach the shard and the height to them.
+    pub fn chunk_producer(
+        shard_id: ShardId,
+        height: BlockHeight,
+        kind: impl Into<ChunkProducerErrorKind>,
+    ) -> Self {
+        Error::ChunkProducer { shard_id, height, kind: kind.into() }
+    }
+
+    pub fn severity(&self) -> ErrorSeverity {
+        match self {
+            Error::Chain(err) => ErrorSeverity::of_chain_error(err),
+            Error::Chunk(_)
+            | Error::ProductionReportUnavailable(_)
+            | Error::MissingProductionInputs(_)
+            | Error::UnsupportedFastForward
+            | Error::Other(_) => ErrorSeverity::Drop,
+            Error::RebuiltBlockMismatch { .. } | Error::IOError(_) => ErrorSeverity::Alert,
+            Error::BlockProducer { kind, .. } => match kind {
+                BlockProducerErrorKind::NoValidatorSigner
+                | BlockProducerErrorKind::IncompatibleProtocolVersion { .. } => {
+                    ErrorSeverity::Alert
+                }
+                BlockProducerErrorKind::Chain(err) => ErrorSeverity::of_chain_error(err),
+            },
+            Error::ChunkProducer { kind, .. } => match kind {
+                ChunkProducerErrorKind::NoValidatorSigner
+                | ChunkProducerErrorKind::InconsistentChunkExtra { .. } => ErrorSeverity::Alert,
+                ChunkProducerErrorKind::PrevBlockNotCaughtUp
+                | ChunkProducerErrorKind::MissingChunkExtra(_)
+                | ChunkProducerErrorKind::StateUnavailable(_)
+                | ChunkProducerErrorKind::MissingOutgoingReceipts { .. } => ErrorSeverity::Retry,
+                ChunkProducerErrorKind::Chain(err) => ErrorSeverity::of_chain_error(err),
+            },
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlockProducerErrorKind {
+    #[error("Called without block producer info.")]
+    NoValidatorSigner,
+    #[error("Network protocol version {network} is newer than client protocol version {ours}")]
+    IncompatibleProtocolVersion { network: ProtocolVersion, ours: ProtocolVersion },
+    #[error("{0}")]
+    Chain(#[from] near_chain_primitives::Error),
+}
+
+impl From<near_primitives::errors::EpochError> for BlockProducerErrorKind {
+    fn from(err: near_primitives::errors::EpochError) -> Self {
+        BlockProducerErrorKind::Chain(err.into())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChunkProducerErrorKind {
+    #[error("Called without block producer info.")]
+    NoValidatorSigner,
+    #[error("State for the epoch is not downloaded yet, skipping chunk production")]
+    PrevBlockNotCaughtUp,
+    #[error("No chunk extra available: {0}")]
+    MissingChunkExtra(near_chain_primitives::Error),
+    /// The state of the shard at the previous block can't be read yet to select the transactions,
+    /// e.g. because the flat storage or the in-memory trie of the shard isn't ready.
+    #[error("State of the shard is not available: {0}")]
+    StateUnavailable(near_chain_primitives::Error),
+    /// The chunk extra stored for the previous block disagrees with the trie changes recorded when
+    /// the shard was applied at that block, e.g. because it was written for another fork. A chunk
+    /// produced on top of it would have an invalid state root.
+    #[error(
+        "Chunk extra of block {prev_block_hash} doesn't match the applied state: {field} is \
+         {found}, expected {expected}"
+    )]
+    InconsistentChunkExtra {
+        prev_block_hash: CryptoHash,
+        /// Which root disagrees.
+        field: &'static str,
+        found: StateRoot,
+        expected: StateRoot,
+    },
+    /// The outgoing receipts of the last chunk included for the shard are not stored, because the
+    /// shard wasn't tracked when it was applied. `resume_height` is the earliest height at which
+    /// another chunk producer's chunk could have been applied, if known.
+    #[error(
+        "Outgoing receipts of the chunk included at height {last_included_height} are not \
+         available, the shard wasn't tracked when it was applied. Can't produce chunks for the \
+         shard until {}",
+        .resume_height.map_or_else(
+            || "another chunk producer produces a chunk for the shard".to_string(),
+            |height| format!("height {} at the earliest", height),
+        )
+    )]
+    MissingOutgoingReceipts {
+        last_included_height: BlockHeight,
+        resume_height: Option<BlockHeight>,
+    },
+    #[error("{0}")]
+    Chain(#[from] near_chain_primitives::Error),
+}
+
+impl From<near_primitives::errors::EpochError> for ChunkProducerErrorKind {
+    fn from(err: near_primitives::errors::EpochError) -> Self {
+        ChunkProducerErrorKind::Chain(err.into())
+    }
+}
+
+/// How a client error should be handled, see `Error::severity`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorSeverity {
+    /// The condition is expected to resolve by itself, the operation can be retried later.
+    Retry,
+    /// The operation is dropped, e.g. because of bad data received from a peer.
+    Drop,
+    /// The node can't do its job until an operator intervenes.
+    Alert,
+}
+
+impl ErrorSeverity {
+    fn of_chain_error(err: &near_chain_primitives::Error) -> Self {
+        if err.is_error() {
+            ErrorSeverity::Alert
+        } else {
+            ErrorSeverity::Drop
+        }
+    }
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct DownloadStatus {
     pub start_time: DateTime<Utc>,
@@ -561,6 +716,21 @@ pub enum StatusError {
     NoNewBlocks { elapsed: std::time::Duration },
     #[error("Epoch Out Of Bounds {epoch_id:?}")]
     EpochOutOfBounds { epoch_id: near_primitives::types::EpochId },
+    #[error("Network protocol version {network} is newer than node protocol version {ours}")]
+    IncompatibleProtocolVersion {
+        network: near_primitives::version::ProtocolVersion,
+        ours: near_primitives::version::ProtocolVersion,
+    },
+    #[error("Most peers are on chain {peers_chain_id} with genesis {peers_genesis_hash}, while this node is on chain {chain_id} with genesis {genesis_hash}. Peers on a different chain: {mismatched_peers:?}")]
+    GenesisMismatch {
+        chain_id: String,
+        genesis_hash: near_primitives::hash::CryptoHash,
+        /// Genesis advertised by most of the peers on a different chain.
+        peers_chain_id: String,
+        peers_genesis_hash: near_primitives::hash::CryptoHash,
+        /// All the highest height peers which advertise a genesis different from ours.
+        mismatched_peers: Vec<near_primitives::network::PeerId>,
+    },
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
     InternalError { error_message: String },
     // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
@@ -7,6 +7,8 @@ use near_network::types::{
 use near_o11y::WithSpanContextExt;
 use near_primitives::block::{Approval, Block, BlockHeader};
 use near_primitives::challenge::Challenge;
+#[cfg(feature = "protocol_feature_chunk_validation")]
+use near_primitives::chunk_validation::ChunkEndorsement;
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
@@ -46,6 +48,12 @@ pub struct BlockResponse {
 #[rtype(result = "()")]
 pub struct BlockApproval(pub Approval, pub PeerId);
 
+/// Endorsement of a chunk, sent by its chunk validator to the next block producer.
+#[cfg(feature = "protocol_feature_chunk_validation")]
+#[derive(actix::Message, Debug)]
+#[rtype(result = "()")]
+pub struct ChunkEndorsementMessage(pub ChunkEndorsement);
+
 /// Request headers.
 #[derive(actix::Message, Debug)]
 #[rtype(result = "Option<Vec<BlockHeader>>")]
@@ -133,6 +141,15 @@ pub enum ProcessTxResponse {
     /// The node being queried does not track the shard needed and therefore cannot provide userful
     /// response.
     DoesNotTrackShard,
+    /// The transaction was forwarded to a node which hasn't caught up with the state of the shard
+    /// yet. Depending on the config, the node may add it to the pool once it catches up.
+    NotCaughtUp,
+    /// The transaction was rejected by the transaction admission policy of the node, for the
+    /// given reason.
+    RejectedByPolicy(String),
+    /// The transaction was dropped because the node received more transactions of its kind,
+    /// directly submitted or forwarded, than the configured rate allows.
+    Throttled,
 }
 
 pub struct Adapter {
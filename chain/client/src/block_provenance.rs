@@ -0,0 +1,145 @@
+//! Provenance of the recently accepted blocks, for the analysis of the block propagation.
+//!
+//! For every accepted block the client records whether it produced the block itself, received it
+//! unsolicited or received it in response to its own request, the peer which sent it, and how long
+//! after the block timestamp it was accepted. Only the records of the most recent blocks are kept.
+//! They are persisted in `DBCol::BlockProvenance`, so that they survive restarts.
+use crate::metrics;
+use lru::LruCache;
+use near_chain::Provenance;
+use near_primitives::block::Block;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use near_primitives::static_clock::StaticClock;
+use near_primitives::types::BlockHeight;
+use near_store::{DBCol, Store};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Number of the most recently accepted blocks whose provenance is kept.
+const BLOCK_PROVENANCE_WINDOW: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockProvenanceRecord {
+    pub hash: CryptoHash,
+    pub height: BlockHeight,
+    pub provenance: Provenance,
+    /// Peer the block was received from, `None` if this node produced it.
+    pub peer_id: Option<PeerId>,
+    /// Time from the block timestamp until the block was accepted. Zero if the timestamp is ahead
+    /// of the local clock.
+    pub latency: Duration,
+}
+
+/// A record as stored in `DBCol::BlockProvenance`.
+#[derive(borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct StoredBlockProvenance {
+    pub hash: CryptoHash,
+    pub height: BlockHeight,
+    pub provenance: Provenance,
+    pub peer_id: Option<PeerId>,
+    /// Latency, in nanoseconds.
+    pub latency: u64,
+}
+
+fn provenance_label(provenance: &Provenance) -> &'static str {
+    match provenance {
+        Provenance::NONE => "broadcast",
+        Provenance::SYNC => "requested",
+        Provenance::PRODUCED => "produced",
+    }
+}
+
+pub(crate) struct BlockProvenanceTracker {
+    store: Store,
+    /// Peers which sent the blocks not accepted yet.
+    senders: LruCache<CryptoHash, PeerId>,
+    /// Records of the accepted blocks with their index in the column, in the order of acceptance.
+    records: VecDeque<(u64, BlockProvenanceRecord)>,
+    next_index: u64,
+}
+
+impl BlockProvenanceTracker {
+    /// Loads the records persisted in `store`.
+    pub(crate) fn new(store: Store) -> Self {
+        let mut records = VecDeque::with_capacity(BLOCK_PROVENANCE_WINDOW);
+        for item in store.iter_prefix_ser::<StoredBlockProvenance>(DBCol::BlockProvenance, &[]) {
+            let (key, stored) = match item {
+                Ok(item) => item,
+                Err(err) => {
+                    tracing::warn!(target: "client", ?err, "Failed to read the block provenance");
+                    continue;
+                }
+            };
+            let Ok(index) = <[u8; 8]>::try_from(key.as_ref()).map(u64::from_be_bytes) else {
+                continue;
+            };
+            records.push_back((
+                index,
+                BlockProvenanceRecord {
+                    hash: stored.hash,
+                    height: stored.height,
+                    provenance: stored.provenance,
+                    peer_id: stored.peer_id,
+                    latency: Duration::from_nanos(stored.latency),
+                },
+            ));
+        }
+        let next_index = records.back().map_or(0, |(index, _)| index + 1);
+        Self { store, senders: LruCache::new(BLOCK_PROVENANCE_WINDOW), records, next_index }
+    }
+
+    /// Remembers the peer which sent the block `block_hash`, until the block is accepted. Only the
+    /// first sender of a block is kept.
+    pub(crate) fn record_received(&mut self, block_hash: CryptoHash, peer_id: PeerId) {
+        if !self.senders.contains(&block_hash) {
+            self.senders.put(block_hash, peer_id);
+        }
+    }
+
+    /// Records and persists the provenance of an accepted block. The oldest records are dropped
+    /// once the window is full.
+    pub(crate) fn record_accepted(&mut self, block: &Block, provenance: Provenance) {
+        let peer_id = self.senders.pop(block.hash());
+        let peer_id = if provenance == Provenance::PRODUCED { None } else { peer_id };
+        let latency =
+            (StaticClock::utc() - block.header().timestamp()).to_std().unwrap_or_default();
+        metrics::BLOCK_ACCEPTED_BY_PROVENANCE
+            .with_label_values(&[provenance_label(&provenance)])
+            .inc();
+        let record = BlockProvenanceRecord {
+            hash: *block.hash(),
+            height: block.header().height(),
+            provenance,
+            peer_id,
+            latency,
+        };
+        let index = self.next_index;
+        self.next_index += 1;
+        let mut store_update = self.store.store_update();
+        while self.records.len() >= BLOCK_PROVENANCE_WINDOW {
+            let (oldest, _) = self.records.pop_front().unwrap();
+            store_update.delete(DBCol::BlockProvenance, &oldest.to_be_bytes());
+        }
+        let stored = StoredBlockProvenance {
+            hash: record.hash,
+            height: record.height,
+            provenance: record.provenance.clone(),
+            peer_id: record.peer_id.clone(),
+            latency: record.latency.as_nanos() as u64,
+        };
+        let result = store_update
+            .set_ser(DBCol::BlockProvenance, &index.to_be_bytes(), &stored)
+            .and_then(|()| store_update.commit());
+        if let Err(err) = result {
+            tracing::warn!(target: "client", ?err, "Failed to persist the block provenance");
+        }
+        self.records.push_back((index, record));
+    }
+
+    /// Records of the last `last_n` accepted blocks, from the oldest to the most recent.
+    pub(crate) fn last(&self, last_n: usize) -> Vec<BlockProvenanceRecord> {
+        let skip = self.records.len().saturating_sub(last_n);
+        self.records.iter().skip(skip).map(|(_, record)| record.clone()).collect()
+    }
+}
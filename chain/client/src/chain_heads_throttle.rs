@@ -0,0 +1,136 @@
+//! Coalescing of the chain heads updates sent to the ShardsManager.
+//!
+//! The ShardsManager only needs the latest head and header head, but the client notifies it on
+//! every head change. During sync that's once per processed block or header batch, which adds
+//! up to hundreds of messages per second carrying two `Tip`s each. While syncing, the changes
+//! are therefore batched and only the latest heads are sent.
+use near_primitives::block::Tip;
+use near_primitives::static_clock::StaticClock;
+use std::time::{Duration, Instant};
+
+/// During sync, the heads are sent at least once every this many changes...
+const MAX_UNSENT_CHANGES: u64 = 100;
+/// ...and at least this often.
+const MAX_SEND_INTERVAL: Duration = Duration::from_secs(1);
+
+pub(crate) struct ChainHeadsThrottle {
+    /// Head and header head last sent to the ShardsManager.
+    last_sent: (Tip, Tip),
+    last_sent_time: Instant,
+    /// Number of changes of the heads since they were last sent.
+    num_unsent_changes: u64,
+    max_unsent_changes: u64,
+    max_send_interval: Duration,
+}
+
+impl ChainHeadsThrottle {
+    /// `head` and `header_head` are the heads the ShardsManager was created with.
+    pub(crate) fn new(head: Tip, header_head: Tip) -> Self {
+        Self::with_limits(head, header_head, MAX_UNSENT_CHANGES, MAX_SEND_INTERVAL)
+    }
+
+    pub(crate) fn with_limits(
+        head: Tip,
+        header_head: Tip,
+        max_unsent_changes: u64,
+        max_send_interval: Duration,
+    ) -> Self {
+        Self {
+            last_sent: (head, header_head),
+            last_sent_time: StaticClock::instant(),
+            num_unsent_changes: 0,
+            max_unsent_changes,
+            max_send_interval,
+        }
+    }
+
+    /// Returns whether `head` and `header_head` should be sent now. Heads which were already sent
+    /// never are. Otherwise they are, unless `batch` is set and neither the number of unsent
+    /// changes nor the time since the last send reached its limit.
+    pub(crate) fn should_send(&mut self, head: &Tip, header_head: &Tip, batch: bool) -> bool {
+        if (head, header_head) == (&self.last_sent.0, &self.last_sent.1) {
+            return false;
+        }
+        self.num_unsent_changes += 1;
+        if batch
+            && self.num_unsent_changes < self.max_unsent_changes
+            && StaticClock::instant().saturating_duration_since(self.last_sent_time)
+                < self.max_send_interval
+        {
+            return false;
+        }
+        self.last_sent = (head.clone(), header_head.clone());
+        self.last_sent_time = StaticClock::instant();
+        self.num_unsent_changes = 0;
+        true
+    }
+
+    /// Whether some changes of the heads weren't sent because of batching.
+    pub(crate) fn has_unsent_changes(&self) -> bool {
+        self.num_unsent_changes > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChainHeadsThrottle;
+    use chrono::TimeZone;
+    use near_primitives::block::Tip;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::static_clock::MockClockGuard;
+    use near_primitives::types::EpochId;
+    use std::time::Duration;
+
+    fn tip(height: u64) -> Tip {
+        Tip {
+            height,
+            last_block_hash: CryptoHash::hash_borsh(height),
+            prev_block_hash: CryptoHash::hash_borsh(height - 1),
+            epoch_id: EpochId::default(),
+            next_epoch_id: EpochId::default(),
+        }
+    }
+
+    #[test]
+    fn test_unchanged_heads_are_not_sent() {
+        let mut throttle = ChainHeadsThrottle::new(tip(1), tip(1));
+        assert!(!throttle.should_send(&tip(1), &tip(1), false));
+        assert!(throttle.should_send(&tip(1), &tip(2), false));
+        assert!(!throttle.should_send(&tip(1), &tip(2), false));
+        assert!(!throttle.has_unsent_changes());
+    }
+
+    #[test]
+    fn test_batched_changes() {
+        let mut throttle =
+            ChainHeadsThrottle::with_limits(tip(1), tip(1), 3, Duration::from_secs(3600));
+        let sent: Vec<_> =
+            (2..=8).map(|height| throttle.should_send(&tip(height), &tip(8), true)).collect();
+        assert_eq!(sent, vec![false, false, true, false, false, true, false]);
+        assert!(throttle.has_unsent_changes());
+        // Without batching the latest heads are sent right away.
+        assert!(throttle.should_send(&tip(8), &tip(8), false));
+        assert!(!throttle.has_unsent_changes());
+    }
+
+    #[test]
+    fn test_batched_changes_sent_after_interval() {
+        let clock = MockClockGuard::default();
+        let start = chrono::Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        clock.set_time(start);
+        let mut throttle =
+            ChainHeadsThrottle::with_limits(tip(1), tip(1), 1000, Duration::from_secs(1));
+        assert!(!throttle.should_send(&tip(2), &tip(2), true));
+        clock.set_time(start + chrono::Duration::milliseconds(999));
+        assert!(!throttle.should_send(&tip(3), &tip(3), true));
+        clock.set_time(start + chrono::Duration::seconds(1));
+        assert!(throttle.should_send(&tip(4), &tip(4), true));
+        assert!(!throttle.has_unsent_changes());
+
+        // The interval starts over from the last send.
+        clock.set_time(start + chrono::Duration::milliseconds(1500));
+        assert!(!throttle.should_send(&tip(5), &tip(5), true));
+        clock.set_time(start + chrono::Duration::seconds(2));
+        assert!(throttle.should_send(&tip(5), &tip(5), true));
+    }
+}
@@ -0,0 +1,226 @@
+//! Persistence of the chunks completed by the ShardsManager.
+//!
+//! Writing a chunk of several megabytes can take a while, so it's done on dedicated writer
+//! threads rather than on the client actor thread. The client only marks a chunk as completed,
+//! and thus unblocks the blocks waiting for it, once the write is reported as done.
+//!
+//! All chunks of a shard are written by the same thread, in the order in which they were
+//! submitted. A failed write is put aside and retried after a delay, while the thread goes on
+//! with the other chunks.
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use near_chain::{ChainStore, DoneApplyChunkCallback};
+use near_chain_configs::ClientConfig;
+use near_chunks::logic::persist_chunk;
+use near_chunks::Error;
+use near_primitives::sharding::{PartialEncodedChunk, ShardChunk, ShardChunkHeader};
+use near_primitives::static_clock::StaticClock;
+use near_primitives::types::BlockHeight;
+use near_store::Store;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+
+/// Number of times writing a chunk is attempted before giving up on it.
+const MAX_PERSIST_ATTEMPTS: u32 = 3;
+
+/// Writes a chunk to the store. Tests can replace it, e.g. to simulate a slow or failing store.
+pub type PersistChunkFn = Arc<
+    dyn Fn(PartialEncodedChunk, Option<ShardChunk>, &mut ChainStore) -> Result<(), Error>
+        + Send
+        + Sync,
+>;
+
+struct PersistChunkRequest {
+    partial_chunk: PartialEncodedChunk,
+    shard_chunk: Option<ShardChunk>,
+    done_callback: DoneApplyChunkCallback,
+}
+
+/// A write which failed, to be attempted again at `retry_at`.
+struct FailedWrite {
+    request: PersistChunkRequest,
+    /// Number of the failed attempts.
+    attempts: u32,
+    retry_at: Instant,
+}
+
+/// Outcome of writing a chunk, as reported back to the client.
+#[derive(Debug)]
+pub struct PersistedChunk {
+    pub chunk_header: ShardChunkHeader,
+    pub result: Result<(), Error>,
+}
+
+pub struct ChunkPersister {
+    /// Queue of each writer thread.
+    request_senders: Vec<Sender<PersistChunkRequest>>,
+    /// Used to report the chunks which couldn't even be handed to a writer thread.
+    results_sender: Sender<PersistedChunk>,
+    results_receiver: Receiver<PersistedChunk>,
+    /// Number of chunks submitted whose result has not been taken yet.
+    num_pending: usize,
+}
+
+impl ChunkPersister {
+    pub fn new(store: Store, genesis_height: BlockHeight, config: &ClientConfig) -> Self {
+        Self::new_with_persist_fn(store, genesis_height, config, Arc::new(persist_chunk))
+    }
+
+    /// Starts `config.chunk_writer_threads` writer threads, at least one.
+    pub fn new_with_persist_fn(
+        store: Store,
+        genesis_height: BlockHeight,
+        config: &ClientConfig,
+        persist_fn: PersistChunkFn,
+    ) -> Self {
+        let (results_sender, results_receiver) = unbounded();
+        let save_trie_changes = config.save_trie_changes;
+        let retry_delay = config.chunk_persist_retry_delay;
+        let request_senders = (0..config.chunk_writer_threads.max(1))
+            .map(|i| {
+                let (request_sender, request_receiver) = unbounded();
+                let results_sender = results_sender.clone();
+                let persist_fn = persist_fn.clone();
+                let store = store.clone();
+                std::thread::Builder::new()
+                    .name(format!("chunk_writer_{}", i))
+                    .spawn(move || {
+                        let mut writer = ChunkWriter {
+                            results: results_sender,
+                            persist_fn,
+                            chain_store: ChainStore::new(store, genesis_height, save_trie_changes),
+                            retry_delay,
+                            failed_writes: Vec::new(),
+                        };
+                        writer.run(request_receiver)
+                    })
+                    .expect("Failed to spawn chunk writer thread");
+                request_sender
+            })
+            .collect();
+        Self { request_senders, results_sender, results_receiver, num_pending: 0 }
+    }
+
+    /// Queues the chunk to be written. `done_callback` is called once the result is available
+    /// through `take_results`.
+    pub fn persist(
+        &mut self,
+        partial_chunk: PartialEncodedChunk,
+        shard_chunk: Option<ShardChunk>,
+        done_callback: DoneApplyChunkCallback,
+    ) {
+        let shard_id = partial_chunk.shard_id();
+        let request = PersistChunkRequest { partial_chunk, shard_chunk, done_callback };
+        self.num_pending += 1;
+        // The writer threads only stop on their own if the write panicked.
+        let writer_index = shard_id as usize % self.request_senders.len();
+        if let Err(err) = self.request_senders[writer_index].send(request) {
+            let PersistChunkRequest { partial_chunk, done_callback, .. } = err.into_inner();
+            let chunk_header = partial_chunk.cloned_header();
+            error!(target: "client", chunk_hash = ?chunk_header.chunk_hash(), shard_id, "Chunk writer thread stopped");
+            let result = Err(Error::IOError(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("chunk writer thread of shard {} stopped", shard_id),
+            )));
+            let prev_block_hash = *chunk_header.prev_block_hash();
+            // The receiver is owned by `self`, so this can't fail.
+            let _ = self.results_sender.send(PersistedChunk { chunk_header, result });
+            done_callback(prev_block_hash);
+        }
+    }
+
+    /// Returns the results of the writes finished since the last call.
+    pub fn take_results(&mut self) -> Vec<PersistedChunk> {
+        let results: Vec<_> = self.results_receiver.try_iter().collect();
+        self.num_pending -= results.len();
+        results
+    }
+
+    /// Blocks until all the queued chunks are written and returns their results.
+    pub fn wait_for_all(&mut self) -> Vec<PersistedChunk> {
+        let mut results = Vec::with_capacity(self.num_pending);
+        while self.num_pending > 0 {
+            results.push(self.results_receiver.recv().expect("Chunk writer thread stopped"));
+            self.num_pending -= 1;
+        }
+        results
+    }
+
+    pub fn num_pending(&self) -> usize {
+        self.num_pending
+    }
+}
+
+/// State of a writer thread.
+struct ChunkWriter {
+    results: Sender<PersistedChunk>,
+    persist_fn: PersistChunkFn,
+    chain_store: ChainStore,
+    retry_delay: Duration,
+    /// Failed writes waiting for their retry.
+    failed_writes: Vec<FailedWrite>,
+}
+
+impl ChunkWriter {
+    /// Writes the requested chunks and retries the failed writes once they are due, until the
+    /// client is gone.
+    fn run(&mut self, requests: Receiver<PersistChunkRequest>) {
+        loop {
+            let now = StaticClock::instant();
+            let next_retry_at = self.failed_writes.iter().map(|failed| failed.retry_at).min();
+            let received = match next_retry_at {
+                Some(retry_at) if retry_at <= now => {
+                    let due = self.failed_writes.iter().position(|failed| failed.retry_at <= now);
+                    let failed = self.failed_writes.swap_remove(due.unwrap());
+                    if !self.write(failed.request, failed.attempts) {
+                        return;
+                    }
+                    continue;
+                }
+                Some(retry_at) => match requests.recv_timeout(retry_at - now) {
+                    Ok(request) => Some(request),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                },
+                None => match requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return,
+                },
+            };
+            if let Some(request) = received {
+                if !self.write(request, 0) {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Attempts the write of a chunk which failed `attempts` times before. Reports the result
+    /// unless the write is to be retried. Returns false if the client is gone.
+    fn write(&mut self, request: PersistChunkRequest, attempts: u32) -> bool {
+        let chunk_header = request.partial_chunk.cloned_header();
+        let result = (self.persist_fn)(
+            request.partial_chunk.clone(),
+            request.shard_chunk.clone(),
+            &mut self.chain_store,
+        );
+        let attempts = attempts + 1;
+        let result = match result {
+            Err(err) if attempts < MAX_PERSIST_ATTEMPTS => {
+                let chunk_hash = chunk_header.chunk_hash();
+                warn!(target: "client", ?chunk_hash, ?err, attempts, "Failed to persist chunk, retrying");
+                let retry_at = StaticClock::instant() + self.retry_delay * attempts;
+                self.failed_writes.push(FailedWrite { request, attempts, retry_at });
+                return true;
+            }
+            result => result,
+        };
+        let prev_block_hash = *chunk_header.prev_block_hash();
+        if self.results.send(PersistedChunk { chunk_header, result }).is_err() {
+            // The client is gone, there is no one to report to.
+            return false;
+        }
+        (request.done_callback)(prev_block_hash);
+        true
+    }
+}
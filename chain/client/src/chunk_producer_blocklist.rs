@@ -0,0 +1,100 @@
+//! Chunk producers whose chunks aren't included in the blocks this node produces.
+//!
+//! A chunk producer which produced an invalid chunk is banned for the rest of the epoch. The bans
+//! are kept per epoch, grouped by the epoch height, and dropped once their epoch is older than the
+//! previous one, as no more blocks are produced in it. Epochs of different forks may share the
+//! height, so the bans of each epoch at a height are kept apart.
+use near_primitives::types::{AccountId, EpochHeight, EpochId};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+#[derive(Default)]
+pub struct ChunkProducerBlocklist {
+    epochs: BTreeMap<EpochHeight, HashMap<EpochId, HashSet<AccountId>>>,
+}
+
+impl ChunkProducerBlocklist {
+    /// Bans `chunk_producer` for the epoch `epoch_id` at `epoch_height`.
+    pub fn ban(&mut self, epoch_height: EpochHeight, epoch_id: EpochId, chunk_producer: AccountId) {
+        self.epochs
+            .entry(epoch_height)
+            .or_default()
+            .entry(epoch_id)
+            .or_default()
+            .insert(chunk_producer);
+    }
+
+    pub fn is_banned(&self, epoch_id: &EpochId, chunk_producer: &AccountId) -> bool {
+        // Bans are kept for two epoch heights at most, so the lookup by epoch id is a short scan.
+        self.epochs.values().any(|epochs| {
+            epochs
+                .get(epoch_id)
+                .map_or(false, |chunk_producers| chunk_producers.contains(chunk_producer))
+        })
+    }
+
+    /// Drops the bans of the epochs older than the one before the epoch at `epoch_height`.
+    pub fn prune(&mut self, epoch_height: EpochHeight) {
+        self.epochs = self.epochs.split_off(&epoch_height.saturating_sub(1));
+    }
+
+    /// The banned chunk producers of every epoch, from the oldest epoch height.
+    pub fn iter(&self) -> impl Iterator<Item = (&EpochId, &HashSet<AccountId>)> {
+        self.epochs.values().flat_map(|epochs| epochs.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChunkProducerBlocklist;
+    use near_primitives::hash::hash;
+    use near_primitives::types::{AccountId, EpochId};
+
+    fn epoch_id(epoch_height: u64) -> EpochId {
+        EpochId(hash(&epoch_height.to_le_bytes()))
+    }
+
+    #[test]
+    fn test_prune_on_epoch_change() {
+        let test0: AccountId = "test0".parse().unwrap();
+        let test1: AccountId = "test1".parse().unwrap();
+        let mut blocklist = ChunkProducerBlocklist::default();
+        blocklist.ban(1, epoch_id(1), test0.clone());
+        blocklist.ban(2, epoch_id(2), test1.clone());
+        assert!(blocklist.is_banned(&epoch_id(1), &test0));
+        assert!(!blocklist.is_banned(&epoch_id(2), &test0));
+
+        // The bans of the previous epoch are kept.
+        blocklist.prune(2);
+        assert!(blocklist.is_banned(&epoch_id(1), &test0));
+        blocklist.prune(3);
+        assert!(!blocklist.is_banned(&epoch_id(1), &test0));
+        assert!(blocklist.is_banned(&epoch_id(2), &test1));
+        assert_eq!(
+            blocklist.iter().map(|(epoch_id, _)| epoch_id).collect::<Vec<_>>(),
+            vec![&epoch_id(2)]
+        );
+        blocklist.prune(4);
+        assert!(!blocklist.is_banned(&epoch_id(2), &test1));
+        assert_eq!(blocklist.iter().count(), 0);
+    }
+
+    /// The bans of an epoch don't apply to another epoch at the same height, from another fork.
+    #[test]
+    fn test_epochs_at_same_height() {
+        let test0: AccountId = "test0".parse().unwrap();
+        let test1: AccountId = "test1".parse().unwrap();
+        let fork_epoch_id = EpochId(hash(b"fork"));
+        let mut blocklist = ChunkProducerBlocklist::default();
+        blocklist.ban(2, epoch_id(2), test0.clone());
+        blocklist.ban(2, fork_epoch_id.clone(), test1.clone());
+        assert!(blocklist.is_banned(&epoch_id(2), &test0));
+        assert!(!blocklist.is_banned(&epoch_id(2), &test1));
+        assert!(blocklist.is_banned(&fork_epoch_id, &test1));
+        assert!(!blocklist.is_banned(&fork_epoch_id, &test0));
+        assert_eq!(blocklist.iter().count(), 2);
+
+        blocklist.prune(4);
+        assert!(!blocklist.is_banned(&epoch_id(2), &test0));
+        assert!(!blocklist.is_banned(&fork_epoch_id, &test1));
+    }
+}
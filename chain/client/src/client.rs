@@ -2,45 +2,71 @@
 //! This client works completely synchronously and must be operated by some async actor outside.
 
 use crate::adapter::ProcessTxResponse;
-use crate::debug::BlockProductionTracker;
-use crate::debug::PRODUCTION_TIMES_CACHE_SIZE;
+use crate::block_provenance::{BlockProvenanceRecord, BlockProvenanceTracker};
+use crate::chain_heads_throttle::ChainHeadsThrottle;
+use crate::chunk_persister::{ChunkPersister, PersistedChunk};
+use crate::chunk_producer_blocklist::ChunkProducerBlocklist;
+use crate::debug::BanHistory;
+use crate::debug::ProductionSkipTracker;
+use crate::debug::{
+    load_produced_block_inputs, BlockProductionInputs, PRODUCED_BLOCK_INPUTS_HORIZON,
+};
+use crate::debug::{BlockProductionTracker, ChunkProductionTracker};
+use crate::health::{HealthTracker, APPROVAL_RECENCY_HEIGHTS, STATUS_WAIT_TIME_MULTIPLIER};
+use crate::production_report::{write_production_report, ProductionReportJob};
+use crate::resharding_log::ReshardingLog;
+use crate::store_audit::{StoreAuditLog, StoreWrite};
 use crate::sync::adapter::SyncShardInfo;
 use crate::sync::block::BlockSync;
+use crate::sync::debug_log::SyncDebugLog;
 use crate::sync::epoch::EpochSync;
 use crate::sync::header::HeaderSync;
 use crate::sync::state::{StateSync, StateSyncResult};
+use crate::tx_admission_policy::{
+    NoopTxAdmissionPolicy, TxAdmissionContext, TxAdmissionPolicy, TxAdmissionStage,
+};
+use crate::tx_lanes::{TxLane, TxLanes};
+use crate::tx_reintroduction::TxReintroductionBudget;
+use crate::verified_tx_cache::{StatefulValidationContext, VerifiedTxCache};
 use crate::SyncAdapter;
 use crate::SyncMessage;
 use crate::{metrics, SyncStatus};
-use actix_rt::ArbiterHandle;
 use itertools::Itertools;
 use lru::LruCache;
+use near_async::futures::FutureSpawner;
 use near_async::messaging::{CanSend, Sender};
 use near_chain::chain::VerifyBlockHashAndSignatureResult;
 use near_chain::chain::{
-    ApplyStatePartsRequest, BlockCatchUpRequest, BlockMissingChunks, BlocksCatchUpState,
-    OrphanMissingChunks, TX_ROUTING_HEIGHT_HORIZON,
+    ApplyStatePartsRequest, BlockCatchUpRequest, BlockCatchUpResponse, BlockMissingChunks,
+    BlocksCatchUpState, OrphanMissingChunks, TX_ROUTING_HEIGHT_HORIZON,
 };
 use near_chain::flat_storage_creator::FlatStorageCreator;
-use near_chain::resharding::StateSplitRequest;
+use near_chain::missing_chunks::MissingChunksPoolLimits;
+use near_chain::resharding::{StateSplitRequest, StateSplitResponse};
 use near_chain::state_snapshot_actor::SnapshotCallbacks;
 use near_chain::test_utils::format_hash;
 use near_chain::types::RuntimeAdapter;
 use near_chain::types::{ChainConfig, LatestKnown};
 use near_chain::{
-    BlockProcessingArtifact, BlockStatus, Chain, ChainGenesis, ChainStoreAccess,
+    BlockProcessingArtifact, BlockStatus, Chain, ChainGenesis, ChainStore, ChainStoreAccess,
     DoneApplyChunkCallback, Doomslug, DoomslugThresholdMode, Provenance,
 };
-use near_chain_configs::{ClientConfig, LogSummaryStyle, UpdateableClientConfig};
+use near_chain_configs::{
+    ClientConfig, DelegateActionValidity, LogSummaryStyle, UpdateableClientConfig,
+};
 use near_chunks::adapter::ShardsManagerRequestFromClient;
 use near_chunks::client::ShardedTransactionPool;
 use near_chunks::logic::{
     cares_about_shard_this_or_next_epoch, decode_encoded_chunk, persist_chunk,
 };
 use near_chunks::ShardsManager;
-use near_client_primitives::debug::ChunkProduction;
+use near_client_primitives::debug::{
+    BanHistoryEntry, ChunkProduction, ClientConfigState, ClientConfigUpdate, EpochProductionReport,
+    ReshardingEvent, SimulatedBlockProduction, SimulatedBlockView,
+};
 use near_client_primitives::types::{
-    format_shard_sync_phase_per_shard, Error, ShardSyncDownload, ShardSyncStatus,
+    format_shard_sync_phase_per_shard, BlockProducerErrorKind, ChunkProducerErrorKind, Error,
+    ErrorSeverity, ShardSyncDownload, ShardSyncStatus,
 };
 use near_epoch_manager::shard_tracker::ShardTracker;
 use near_epoch_manager::EpochManagerAdapter;
@@ -54,8 +80,10 @@ use near_pool::InsertTransactionResult;
 use near_primitives::block::{Approval, ApprovalInner, ApprovalMessage, Block, BlockHeader, Tip};
 use near_primitives::block_header::ApprovalType;
 use near_primitives::challenge::{Challenge, ChallengeBody};
+#[cfg(feature = "protocol_feature_chunk_validation")]
+use near_primitives::chunk_validation::{ChunkEndorsement, ChunkEndorsementInner};
 use near_primitives::epoch_manager::RngSeed;
-use near_primitives::errors::EpochError;
+use near_primitives::errors::{EpochError, InvalidTxError, StorageError};
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{merklize, MerklePath, PartialMerkleTree};
 use near_primitives::network::PeerId;
@@ -66,19 +94,26 @@ use near_primitives::sharding::{
     ShardChunkHeader, ShardInfo,
 };
 use near_primitives::static_clock::StaticClock;
-use near_primitives::transaction::SignedTransaction;
+use near_primitives::transaction::{Action, SignedTransaction};
+use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::Gas;
 use near_primitives::types::StateRoot;
-use near_primitives::types::{AccountId, ApprovalStake, BlockHeight, EpochId, NumBlocks, ShardId};
+use near_primitives::types::{
+    AccountId, ApprovalStake, BlockHeight, EpochHeight, EpochId, NumBlocks, ShardId,
+};
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::MaybeValidated;
-use near_primitives::validator_signer::ValidatorSigner;
+use near_primitives::validator_signer::{EmptyValidatorSigner, ValidatorSigner};
+use near_primitives::version::ProtocolVersion;
 use near_primitives::version::PROTOCOL_VERSION;
-use near_primitives::views::{CatchupStatusView, DroppedReason};
+use near_primitives::views::{
+    CatchupStatusView, DroppedReason, NodeHealthFactor, NodeHealthView, ShardChunkAvailabilityView,
+};
 use near_store::metadata::DbKind;
-use near_store::ShardUId;
+use near_store::{DBCol, ShardUId};
 use std::cmp::max;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::RwLock;
 use std::time::{Duration, Instant};
@@ -86,7 +121,15 @@ use tracing::{debug, debug_span, error, info, trace, warn};
 
 const NUM_REBROADCAST_BLOCKS: usize = 30;
 const CHUNK_HEADERS_FOR_INCLUSION_CACHE_SIZE: usize = 2048;
-const NUM_EPOCH_CHUNK_PRODUCERS_TO_KEEP_IN_BLOCKLIST: usize = 1000;
+/// Number of epochs for which to keep the resolved chunk producer assignment of this node.
+const NUM_EPOCHS_TO_KEEP_CHUNK_PRODUCER_ASSIGNMENT: usize = 3;
+/// Number of finished epochs for which to keep the production report.
+const NUM_EPOCH_PRODUCTION_REPORTS_TO_KEEP: usize = 3;
+/// Number of the latest dynamic updates of the client config to keep.
+const NUM_CLIENT_CONFIG_UPDATES_TO_KEEP: usize = 100;
+/// Number of the latest chunks for which the endorsements received are kept.
+#[cfg(feature = "protocol_feature_chunk_validation")]
+const NUM_CHUNKS_WITH_ENDORSEMENTS_TO_KEEP: usize = 1024;
 
 /// The time we wait for the response to a Epoch Sync request before retrying
 // TODO #3488 set 30_000
@@ -109,6 +152,43 @@ pub enum AdvProduceBlocksMode {
     OnlyValid,
 }
 
+/// Whether the client can keep up with the protocol version used by the network.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClientState {
+    Running,
+    /// The network switched to a protocol version newer than the one this binary supports.
+    /// The client stops processing new blocks and, if it is a validator, stops producing them,
+    /// but keeps serving the data it already has.
+    IncompatibleProtocolVersion {
+        network: ProtocolVersion,
+        ours: ProtocolVersion,
+    },
+}
+
+/// How the client garbage collects the store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GcMode {
+    /// Regular garbage collection of a non-archival node.
+    Regular,
+    /// Archival node with legacy storage, or in the midst of the migration to split storage.
+    /// Only the data of forks is cleared.
+    Archive,
+    /// Archival node with split storage. The hot storage is garbage collected as for a
+    /// non-archival node.
+    SplitStorageHot,
+}
+
+impl GcMode {
+    /// The kind of the store is only set to hot once the migration to split storage is finished.
+    fn new(archive: bool, db_kind: Option<DbKind>) -> Self {
+        match (archive, db_kind) {
+            (false, _) => Self::Regular,
+            (true, Some(DbKind::Hot)) => Self::SplitStorageHot,
+            (true, _) => Self::Archive,
+        }
+    }
+}
+
 pub struct Client {
     /// Adversarial controls - should be enabled only to test disruptive
     /// behaviour on chain.
@@ -137,7 +217,8 @@ pub struct Client {
         CryptoHash,
         HashMap<ShardId, (ShardChunkHeader, chrono::DateTime<chrono::Utc>, AccountId)>,
     >,
-    pub do_not_include_chunks_from: LruCache<(EpochId, AccountId), ()>,
+    /// Chunk producers banned for producing invalid chunks.
+    pub do_not_include_chunks_from: ChunkProducerBlocklist,
     /// Network adapter.
     network_adapter: PeerManagerAdapter,
     /// Signer for block producer (if present).
@@ -157,6 +238,10 @@ pub struct Client {
     pub block_sync: BlockSync,
     /// Keeps track of syncing state.
     pub state_sync: StateSync,
+    /// Decisions taken by header, block and state sync, shared with them.
+    pub sync_debug_log: SyncDebugLog,
+    /// Resharding steps taken by the client and by state sync, shared with the latter.
+    pub resharding_log: ReshardingLog,
     /// List of currently accumulated challenges.
     pub challenges: HashMap<CryptoHash, Challenge>,
     /// A ReedSolomon instance to reconstruct shard.
@@ -166,23 +251,122 @@ pub struct Client {
     /// Last time the head was updated, or our head was rebroadcasted. Used to re-broadcast the head
     /// again to prevent network from stalling if a large percentage of the network missed a block
     last_time_head_progress_made: Instant,
+    /// Height of the head when its progress was detected as stalled. Cleared once the head
+    /// progresses again, which triggers the recovery burst.
+    stalled_head_height: Option<BlockHeight>,
+    /// Time the last recovery burst was sent, to send at most one per `recovery_burst_interval`.
+    last_recovery_burst: Option<Instant>,
 
     /// Block production timing information. Used only for debug purposes.
     /// Stores approval information and production time of the block
     pub block_production_info: BlockProductionTracker,
     /// Chunk production timing information. Used only for debug purposes.
-    pub chunk_production_info: lru::LruCache<(BlockHeight, ShardId), ChunkProduction>,
+    pub chunk_production_info: ChunkProductionTracker,
+    /// Peers banned by this node, with the reason and the offending block or chunk.
+    /// Used only for debug purposes.
+    ban_history: BanHistory,
+    /// Provenance of the recently accepted blocks, persisted in `DBCol::BlockProvenance`.
+    /// Used only for debug purposes.
+    block_provenance: BlockProvenanceTracker,
+    /// Why blocks and chunks this node was responsible for weren't produced.
+    /// Used only for debug purposes.
+    production_skip_reasons: ProductionSkipTracker,
+    /// Production reports of the last finished epochs.
+    production_reports: lru::LruCache<EpochId, EpochProductionReport>,
+    /// Thread writing the production report of the last finished epoch to the configured file.
+    pub(crate) production_report_writer: Option<std::thread::JoinHandle<()>>,
 
     /// Cached precomputed set of TIER1 accounts.
     /// See send_network_chain_info().
     tier1_accounts_cache: Option<(EpochId, Arc<AccountKeys>)>,
     /// Used when it is needed to create flat storage in background for some shards.
     flat_storage_creator: Option<FlatStorageCreator>,
+    /// Set to `IncompatibleProtocolVersion` once the network upgrades past our protocol version.
+    pub client_state: ClientState,
+    /// Follows the kind of the store, which changes under the running client when the migration
+    /// to split storage finishes.
+    gc_mode: GcMode,
+    /// Shards this node may produce chunks for, by epoch. Resolved once per epoch from the
+    /// chunk producer settlement, so that we don't look up the chunk producer of every shard at
+    /// every height.
+    chunk_producer_assignments: lru::LruCache<EpochId, Vec<ShardId>>,
+    /// Writes the chunks completed by the ShardsManager off the client thread.
+    pub chunk_persister: ChunkPersister,
+    /// Set when a completed chunk couldn't be persisted even after retrying, and cleared once a
+    /// chunk is persisted successfully again. Reported by the health check.
+    chunk_persistence_error: Option<String>,
+    /// Set when the epoch of the chain head doesn't match the epoch manager, even after
+    /// reloading both, and cleared once they match again. Reported by the health check.
+    head_epoch_mismatch: Option<String>,
+    /// Errors of the block (`None`) or chunk production of a shard which need an operator, each
+    /// cleared once a block or a chunk of the shard is produced again. Reported by the health
+    /// check.
+    production_alerts: BTreeMap<Option<ShardId>, String>,
+    /// Epoch in which this validator was warned that it's on track to be kicked out.
+    projected_kickout_warned_epoch: Option<EpochId>,
+    /// Epoch in which this validator is slashed, with a description of the slashing. It doesn't
+    /// produce blocks or chunks nor sends approvals for the blocks of that epoch. Reported by the
+    /// health check.
+    slashed_in_epoch: Option<(EpochId, String)>,
+    /// Batches the chain heads updates sent to the ShardsManager during sync.
+    pub(crate) chain_heads_throttle: ChainHeadsThrottle,
+    /// Forwarded transactions received before the node caught up with their shard, by shard,
+    /// with the epoch they were received in. They are processed again once the catchup of the
+    /// shard completes, and dropped if the catchup is dropped or the epoch ends before.
+    pub(crate) not_caught_up_txs: HashMap<ShardId, (EpochId, VecDeque<SignedTransaction>)>,
+    /// Chunk availability of the tracked shards, with the head it was computed for.
+    chunk_availability: Option<(Tip, Vec<ShardChunkAvailabilityView>)>,
+    /// Chain-specific rules checked before a transaction is added to the pool or included in a
+    /// chunk.
+    pub(crate) tx_admission_policy: Arc<dyn TxAdmissionPolicy>,
+    /// Rate budgets and pool reservations of the local and forwarded transactions.
+    pub(crate) tx_lanes: TxLanes,
+    /// Health reported by the status endpoint, updated on every new head.
+    health: HealthTracker,
+    /// Verdicts of the recent validations of the submitted transactions, reused when the same
+    /// transaction is submitted again.
+    verified_tx_cache: VerifiedTxCache,
+    /// The client config at startup, with the secrets redacted.
+    startup_config: serde_json::Value,
+    /// Dynamic updates of the client config applied since the startup, oldest first.
+    config_updates: VecDeque<ClientConfigUpdate>,
+    /// Writes to the store made by the client directly rather than through the chain.
+    pub(crate) store_audit_log: StoreAuditLog,
+    /// Endorsements of the recent chunks received from their chunk validators, by chunk.
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    chunk_endorsements: lru::LruCache<ChunkHash, HashMap<AccountId, ChunkEndorsement>>,
 }
 
 impl Client {
-    pub(crate) fn update_client_config(&self, update_client_config: UpdateableClientConfig) {
+    pub(crate) fn update_client_config(&mut self, update_client_config: UpdateableClientConfig) {
+        let old_config = self.config.to_redacted_json();
         self.config.expected_shutdown.update(update_client_config.expected_shutdown);
+        let new_config = self.config.to_redacted_json();
+
+        let changed_fields = match (old_config, new_config) {
+            (serde_json::Value::Object(old_fields), serde_json::Value::Object(new_fields)) => {
+                new_fields
+                    .into_iter()
+                    .filter(|(field, value)| old_fields.get(field) != Some(value))
+                    .collect()
+            }
+            _ => BTreeMap::new(),
+        };
+        if self.config_updates.len() == NUM_CLIENT_CONFIG_UPDATES_TO_KEEP {
+            self.config_updates.pop_front();
+        }
+        self.config_updates
+            .push_back(ClientConfigUpdate { time: StaticClock::utc(), changed_fields });
+    }
+
+    /// The client config this node is running with, the one it started with, and the dynamic
+    /// updates applied in between.
+    pub fn config_state(&self) -> ClientConfigState {
+        ClientConfigState {
+            effective: self.config.to_redacted_json(),
+            startup: self.startup_config.clone(),
+            updates: self.config_updates.iter().cloned().collect(),
+        }
     }
 }
 
@@ -224,6 +408,7 @@ impl Client {
         enable_doomslug: bool,
         rng_seed: RngSeed,
         snapshot_callbacks: Option<SnapshotCallbacks>,
+        tx_admission_policy: Option<Arc<dyn TxAdmissionPolicy>>,
     ) -> Result<Self, Error> {
         let doomslug_threshold_mode = if enable_doomslug {
             DoomslugThresholdMode::TwoThirds
@@ -234,6 +419,11 @@ impl Client {
             save_trie_changes: config.save_trie_changes,
             background_migration_threads: config.client_background_migration_threads,
             state_split_config: config.state_split_config,
+            missing_chunks_pool_limits: MissingChunksPoolLimits {
+                max_blocks: config.max_blocks_with_missing_chunks,
+                max_block_age: config.max_block_with_missing_chunks_age,
+                max_ready_blocks_started: config.max_blocks_with_missing_chunks_started,
+            },
         };
         let chain = Chain::new(
             epoch_manager.clone(),
@@ -251,8 +441,20 @@ impl Client {
             chain.store(),
             chain_config.background_migration_threads,
         )?;
+        let chunk_persister =
+            ChunkPersister::new(chain.store().store().clone(), chain_genesis.height, &config);
         let sharded_tx_pool =
             ShardedTransactionPool::new(rng_seed, config.transaction_pool_size_limit);
+        let tx_lanes = TxLanes::new(&config);
+        let health = HealthTracker::new(config.health_hysteresis_blocks);
+        let gc_mode = GcMode::new(
+            config.archive,
+            chain.store().store().get_db_kind().map_err(near_chain::Error::from)?,
+        );
+        let block_production_info =
+            BlockProductionTracker::new(config.block_production_info_capacity);
+        let chunk_production_info =
+            ChunkProductionTracker::new(config.chunk_production_info_capacity_per_shard);
         let sync_status = SyncStatus::AwaitingPeers;
         let genesis_block = chain.genesis_block();
         let epoch_sync = EpochSync::new(
@@ -270,18 +472,27 @@ impl Client {
             EPOCH_SYNC_REQUEST_TIMEOUT,
             EPOCH_SYNC_PEER_TIMEOUT,
         );
+        let sync_debug_log = SyncDebugLog::default();
+        let ban_history = BanHistory::new(chain.store().store().clone());
+        let block_provenance = BlockProvenanceTracker::new(chain.store().store().clone());
+        let resharding_log = ReshardingLog::default();
         let header_sync = HeaderSync::new(
             network_adapter.clone(),
             config.header_sync_initial_timeout,
             config.header_sync_progress_timeout,
             config.header_sync_stall_ban_timeout,
             config.header_sync_expected_height_per_second,
+            config.sync_until_height,
+            sync_debug_log.clone(),
+            ban_history.clone(),
         );
         let block_sync = BlockSync::new(
             network_adapter.clone(),
             config.block_fetch_horizon,
             config.archive,
             config.state_sync_enabled,
+            config.sync_until_height,
+            sync_debug_log.clone(),
         );
         // Start one actor per shard.
         if config.state_sync_enabled {
@@ -304,11 +515,15 @@ impl Client {
             &config.chain_id,
             &config.state_sync.sync,
             false,
+            sync_debug_log.clone(),
+            resharding_log.clone(),
         );
         let num_block_producer_seats = config.num_block_producer_seats as usize;
         let data_parts = epoch_manager.num_data_parts();
         let parity_parts = epoch_manager.num_total_parts() - data_parts;
 
+        // The ShardsManager starts with the heads in the store too.
+        let chain_heads_throttle = ChainHeadsThrottle::new(chain.head()?, chain.header_head()?);
         let doomslug = Doomslug::new(
             chain.store().largest_target_height()?,
             config.min_block_production_delay,
@@ -318,7 +533,8 @@ impl Client {
             validator_signer.clone(),
             doomslug_threshold_mode,
         );
-        Ok(Self {
+        let startup_config = config.to_redacted_json();
+        let mut client = Self {
             #[cfg(feature = "test_features")]
             adv_produce_blocks: None,
             #[cfg(feature = "test_features")]
@@ -340,9 +556,7 @@ impl Client {
             prev_block_to_chunk_headers_ready_for_inclusion: LruCache::new(
                 CHUNK_HEADERS_FOR_INCLUSION_CACHE_SIZE,
             ),
-            do_not_include_chunks_from: LruCache::new(
-                NUM_EPOCH_CHUNK_PRODUCERS_TO_KEEP_IN_BLOCKLIST,
-            ),
+            do_not_include_chunks_from: ChunkProducerBlocklist::default(),
             network_adapter,
             validator_signer,
             pending_approvals: lru::LruCache::new(num_block_producer_seats),
@@ -351,15 +565,96 @@ impl Client {
             header_sync,
             block_sync,
             state_sync,
+            sync_debug_log,
+            resharding_log,
             challenges: Default::default(),
             rs_for_chunk_production: ReedSolomonWrapper::new(data_parts, parity_parts),
             rebroadcasted_blocks: lru::LruCache::new(NUM_REBROADCAST_BLOCKS),
             last_time_head_progress_made: StaticClock::instant(),
-            block_production_info: BlockProductionTracker::new(),
-            chunk_production_info: lru::LruCache::new(PRODUCTION_TIMES_CACHE_SIZE),
+            stalled_head_height: None,
+            last_recovery_burst: None,
+            block_production_info,
+            ban_history,
+            block_provenance,
+            production_skip_reasons: ProductionSkipTracker::new(),
+            production_reports: lru::LruCache::new(NUM_EPOCH_PRODUCTION_REPORTS_TO_KEEP),
+            production_report_writer: None,
+            chunk_production_info,
             tier1_accounts_cache: None,
             flat_storage_creator,
-        })
+            client_state: ClientState::Running,
+            gc_mode,
+            chunk_producer_assignments: lru::LruCache::new(
+                NUM_EPOCHS_TO_KEEP_CHUNK_PRODUCER_ASSIGNMENT,
+            ),
+            chunk_persister,
+            chunk_persistence_error: None,
+            projected_kickout_warned_epoch: None,
+            slashed_in_epoch: None,
+            head_epoch_mismatch: None,
+            production_alerts: BTreeMap::new(),
+            chain_heads_throttle,
+            not_caught_up_txs: HashMap::new(),
+            chunk_availability: None,
+            tx_admission_policy: tx_admission_policy
+                .unwrap_or_else(|| Arc::new(NoopTxAdmissionPolicy)),
+            tx_lanes,
+            health,
+            verified_tx_cache: VerifiedTxCache::new(),
+            startup_config,
+            config_updates: VecDeque::new(),
+            store_audit_log: StoreAuditLog::default(),
+            #[cfg(feature = "protocol_feature_chunk_validation")]
+            chunk_endorsements: lru::LruCache::new(NUM_CHUNKS_WITH_ENDORSEMENTS_TO_KEEP),
+        };
+        // The network may have upgraded while this node was down.
+        if let Ok(head) = client.chain.head() {
+            client.check_next_epoch_protocol_version(&head.last_block_hash);
+        }
+        Ok(client)
+    }
+
+    /// Returns the network and our protocol versions if the client was stopped because
+    /// the network uses a newer protocol version.
+    pub fn incompatible_protocol_version(&self) -> Option<(ProtocolVersion, ProtocolVersion)> {
+        match self.client_state {
+            ClientState::Running => None,
+            ClientState::IncompatibleProtocolVersion { network, ours } => Some((network, ours)),
+        }
+    }
+
+    /// Checks that the epoch of the block following `prev_hash` uses a protocol version that we
+    /// support, and stops block processing otherwise.
+    fn check_next_epoch_protocol_version(&mut self, prev_hash: &CryptoHash) {
+        let protocol_version = match self
+            .epoch_manager
+            .get_epoch_id_from_prev_block(prev_hash)
+            .and_then(|epoch_id| self.epoch_manager.get_epoch_protocol_version(&epoch_id))
+        {
+            Ok(protocol_version) => protocol_version,
+            Err(err) => {
+                debug!(target: "client", ?err, ?prev_hash, "Failed to get next epoch protocol version");
+                return;
+            }
+        };
+        if protocol_version > PROTOCOL_VERSION {
+            self.set_incompatible_protocol_version(protocol_version);
+        }
+    }
+
+    fn set_incompatible_protocol_version(&mut self, network: ProtocolVersion) {
+        if self.client_state != ClientState::Running {
+            return;
+        }
+        error!(
+            target: "client",
+            client_protocol_version = PROTOCOL_VERSION,
+            network_protocol_version = network,
+            is_validator = self.validator_signer.is_some(),
+            "The client protocol version is older than the protocol version of the network. Please update nearcore. Processing of new blocks is stopped.");
+        metrics::INCOMPATIBLE_NETWORK_PROTOCOL_VERSION.set(network as i64);
+        self.client_state =
+            ClientState::IncompatibleProtocolVersion { network, ours: PROTOCOL_VERSION };
     }
 
     // Checks if it's been at least `stall_timeout` since the last time the head was updated, or
@@ -368,11 +663,67 @@ impl Client {
         if StaticClock::instant() > self.last_time_head_progress_made + stall_timeout
             && !self.sync_status.is_syncing()
         {
-            let block = self.chain.get_block(&self.chain.head()?.last_block_hash)?;
+            let head = self.chain.head()?;
+            let block = self.chain.get_block(&head.last_block_hash)?;
             self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
                 NetworkRequests::Block { block: block },
             ));
             self.last_time_head_progress_made = StaticClock::instant();
+            self.stalled_head_height.get_or_insert(head.height);
+        }
+        Ok(())
+    }
+
+    /// Once the head progresses after a stall, broadcasts the `recovery_burst_blocks` blocks
+    /// before the new head, oldest first, so that peers which missed the blocks produced around
+    /// the stall don't have to request the ancestors of the head one by one. The head itself was
+    /// broadcast already when it was produced or received. Blocks broadcast before are skipped,
+    /// and the burst is sent once per stall and at most once per `recovery_burst_interval`.
+    /// Unless `relay_recovery_burst` is set, it's only sent if this node produced the new head.
+    fn maybe_send_recovery_burst(&mut self) -> Result<(), Error> {
+        let Some(stalled_head_height) = self.stalled_head_height else {
+            return Ok(());
+        };
+        let head = self.chain.head()?;
+        if head.height <= stalled_head_height {
+            return Ok(());
+        }
+        self.stalled_head_height = None;
+        if !self.config.relay_recovery_burst {
+            let Some(validator_signer) = &self.validator_signer else {
+                return Ok(());
+            };
+            let block_producer =
+                self.epoch_manager.get_block_producer(&head.epoch_id, head.height)?;
+            if &block_producer != validator_signer.validator_id() {
+                return Ok(());
+            }
+        }
+        let now = StaticClock::instant();
+        if let Some(last_recovery_burst) = self.last_recovery_burst {
+            if now < last_recovery_burst + self.config.recovery_burst_interval {
+                debug!(target: "client", stalled_head_height, head_height = head.height, "Recovery burst skipped, the last one was sent too recently");
+                return Ok(());
+            }
+        }
+        self.last_recovery_burst = Some(now);
+
+        let mut blocks = vec![];
+        let mut block_hash = head.prev_block_hash;
+        for _ in 0..self.config.recovery_burst_blocks {
+            if &block_hash == self.chain.genesis().hash() {
+                break;
+            }
+            let block = self.chain.get_block(&block_hash)?;
+            block_hash = *block.header().prev_hash();
+            if !self.rebroadcasted_blocks.contains(block.hash()) {
+                blocks.push(block);
+            }
+        }
+        debug!(target: "client", stalled_head_height, head_height = head.height, num_blocks = blocks.len(), "Sending recovery burst");
+        metrics::RECOVERY_BURST_BLOCKS_TOTAL.inc_by(blocks.len() as u64);
+        for block in blocks.iter().rev() {
+            self.rebroadcast_block(block);
         }
         Ok(())
     }
@@ -397,6 +748,7 @@ impl Client {
                     // By now the chunk must be in store, otherwise the block would have been orphaned
                     let chunk = self.chain.get_chunk(&chunk_header.chunk_hash()).unwrap();
                     let transactions = chunk.transactions();
+                    self.verified_tx_cache.remove(transactions);
                     self.sharded_tx_pool.remove_transactions(shard_uid, transactions);
                 }
             }
@@ -407,10 +759,32 @@ impl Client {
         Ok(())
     }
 
-    pub fn reintroduce_transactions_for_block(
+    /// Reintroduces the transactions of the blocks abandoned by a reorg to the pool, as long as
+    /// they are still valid on top of `new_head` and fit into the reintroduction budget.
+    /// `block_hashes` are expected to start at the abandoned head, so that the transactions of the
+    /// most recent blocks are preferred.
+    pub(crate) fn reintroduce_transactions_for_blocks(
+        &mut self,
+        me: AccountId,
+        block_hashes: &[CryptoHash],
+        new_head: &BlockHeader,
+    ) {
+        let mut budget = TxReintroductionBudget::new(&self.config);
+        for block_hash in block_hashes {
+            if let Ok(block) = self.chain.get_block(block_hash) {
+                let block = block.clone();
+                self.reintroduce_transactions_for_block(me.clone(), &block, new_head, &mut budget)
+                    .unwrap_or_default();
+            }
+        }
+    }
+
+    fn reintroduce_transactions_for_block(
         &mut self,
         me: AccountId,
         block: &Block,
+        new_head: &BlockHeader,
+        budget: &mut TxReintroductionBudget,
     ) -> Result<(), Error> {
         let epoch_id = self.epoch_manager.get_epoch_id(block.hash())?;
         for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
@@ -427,9 +801,29 @@ impl Client {
                 ) {
                     // By now the chunk must be in store, otherwise the block would have been orphaned
                     let chunk = self.chain.get_chunk(&chunk_header.chunk_hash()).unwrap();
-                    let reintroduced_count = self
-                        .sharded_tx_pool
-                        .reintroduce_transactions(shard_uid, &chunk.transactions());
+                    // Expired transactions are dropped before they take any of the budget.
+                    let transactions: Vec<SignedTransaction> = chunk
+                        .transactions()
+                        .iter()
+                        .filter(|tx| {
+                            if !self.is_tx_valid_on(new_head, tx) {
+                                metrics::TRANSACTION_REINTRODUCTION_SKIPPED
+                                    .with_label_values(&["invalid"])
+                                    .inc();
+                                false
+                            } else if !budget.try_take(tx) {
+                                metrics::TRANSACTION_REINTRODUCTION_SKIPPED
+                                    .with_label_values(&["over_budget"])
+                                    .inc();
+                                false
+                            } else {
+                                true
+                            }
+                        })
+                        .cloned()
+                        .collect();
+                    let reintroduced_count =
+                        self.sharded_tx_pool.reintroduce_transactions(shard_uid, &transactions);
                     if reintroduced_count < chunk.transactions().len() {
                         debug!(target: "client",
                             reintroduced_count,
@@ -445,13 +839,34 @@ impl Client {
         Ok(())
     }
 
+    /// Whether `tx` can still be included in a chunk on top of `header`.
+    fn is_tx_valid_on(&self, header: &BlockHeader, tx: &SignedTransaction) -> bool {
+        self.chain
+            .store()
+            .check_transaction_validity_period(
+                header,
+                &tx.transaction.block_hash,
+                self.chain.transaction_validity_period,
+            )
+            .and_then(|_| {
+                check_delegate_actions_validity(
+                    self.chain.store(),
+                    tx,
+                    self.config.delegate_action_validity,
+                    header.height() + 1,
+                    self.chain.transaction_validity_period,
+                )
+            })
+            .is_ok()
+    }
+
     /// Checks couple conditions whether Client can produce new block on height
     /// `height` on top of block with `prev_header`.
     /// Needed to skip several checks in case of adversarial controls enabled.
     /// TODO: consider returning `Result<(), Error>` as `Ok(false)` looks like
     /// faulty logic.
     fn can_produce_block(
-        &self,
+        &mut self,
         prev_header: &BlockHeader,
         height: BlockHeight,
         account_id: &AccountId,
@@ -477,10 +892,28 @@ impl Client {
             }
         }
 
+        if let Some(reason) = self.block_production_skip_reason(prev_header, height)? {
+            self.production_skip_reasons.record(height, None, reason);
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// Checks the conditions of `can_produce_block` which don't depend on who the producer is.
+    /// Returns why the block at `height` can't be produced on top of `prev_header`, if it can't.
+    fn block_production_skip_reason(
+        &self,
+        prev_header: &BlockHeader,
+        height: BlockHeight,
+    ) -> Result<Option<String>, Error> {
         // If height is known already, don't produce new block for this height.
         let known_height = self.chain.store().get_latest_known()?.height;
         if height <= known_height {
-            return Ok(false);
+            return Ok(Some(format!(
+                "Height is not above the latest known height {}",
+                known_height
+            )));
         }
 
         // If we are to start new epoch with this block, check if the previous
@@ -491,11 +924,11 @@ impl Client {
             let prev_prev_hash = prev_header.prev_hash();
             if !self.chain.prev_block_is_caught_up(prev_prev_hash, prev_hash)? {
                 debug!(target: "client", height, "Skipping block production, prev block is not caught up");
-                return Ok(false);
+                return Ok(Some("Previous block is not caught up".to_string()));
             }
         }
 
-        Ok(true)
+        Ok(None)
     }
 
     pub fn get_chunk_headers_ready_for_inclusion(
@@ -505,13 +938,10 @@ impl Client {
     ) -> HashMap<ShardId, (ShardChunkHeader, chrono::DateTime<chrono::Utc>, AccountId)> {
         self.prev_block_to_chunk_headers_ready_for_inclusion
             .peek(prev_block_hash)
-            .cloned()
-            .unwrap_or_default()
             .into_iter()
+            .flatten()
             .filter(|(_, (chunk_header, _, chunk_producer))| {
-                let banned = self
-                    .do_not_include_chunks_from
-                    .contains(&(epoch_id.clone(), chunk_producer.clone()));
+                let banned = self.is_chunk_producer_banned(epoch_id, chunk_producer);
                 if banned {
                     warn!(
                         target: "client",
@@ -522,6 +952,7 @@ impl Client {
                 }
                 !banned
             })
+            .map(|(shard_id, entry)| (*shard_id, entry.clone()))
             .collect()
     }
 
@@ -538,27 +969,92 @@ impl Client {
         entries
             .values()
             .filter(|(_, _, chunk_producer)| {
-                !self
-                    .do_not_include_chunks_from
-                    .contains(&(epoch_id.clone(), chunk_producer.clone()))
+                !self.is_chunk_producer_banned(epoch_id, chunk_producer)
             })
             .count()
     }
 
+    fn is_chunk_producer_banned(&self, epoch_id: &EpochId, chunk_producer: &AccountId) -> bool {
+        self.do_not_include_chunks_from.is_banned(epoch_id, chunk_producer)
+    }
+
     /// Produce block if we are block producer for given block `height`.
     /// Either returns produced block (not applied) or error.
     pub fn produce_block(&mut self, height: BlockHeight) -> Result<Option<Block>, Error> {
         let _span = tracing::debug_span!(target: "client", "produce_block", height).entered();
 
-        let head = self.chain.head()?;
-        assert_eq!(
-            head.epoch_id,
-            self.epoch_manager.get_epoch_id_from_prev_block(&head.prev_block_hash).unwrap()
-        );
+        if self.config.sync_until_height.is_some() {
+            self.production_skip_reasons.record(
+                height,
+                None,
+                "The node only syncs until a target height".to_string(),
+            );
+            return Ok(None);
+        }
+
+        let Some(head) = self.head_consistent_with_epoch_manager()? else {
+            self.production_skip_reasons.record(
+                height,
+                None,
+                "Epoch of the chain head doesn't match the epoch manager".to_string(),
+            );
+            return Ok(None);
+        };
 
         self.produce_block_on(height, head.last_block_hash)
     }
 
+    /// Returns the chain head, unless its epoch differs from the one the epoch manager assigns
+    /// to it. This has been seen after partial store restores, so on a mismatch the head and the
+    /// epoch manager caches are reloaded from the store and the check is repeated once. If they
+    /// still disagree the mismatch is reported by the health check until they agree again.
+    fn head_consistent_with_epoch_manager(&mut self) -> Result<Option<Tip>, Error> {
+        let mut reloaded = false;
+        loop {
+            let head = self.chain.head()?;
+            let epoch_id =
+                self.epoch_manager.get_epoch_id_from_prev_block(&head.prev_block_hash)?;
+            if head.epoch_id == epoch_id {
+                if self.head_epoch_mismatch.take().is_some() {
+                    info!(target: "client", head_height = head.height, "Epoch of the chain head matches the epoch manager again");
+                }
+                return Ok(Some(head));
+            }
+            error!(
+                target: "client",
+                head_height = head.height,
+                head_hash = ?head.last_block_hash,
+                head_epoch_id = ?head.epoch_id,
+                epoch_manager_epoch_id = ?epoch_id,
+                reloaded,
+                "Epoch of the chain head doesn't match the epoch manager");
+            if reloaded {
+                self.head_epoch_mismatch = Some(format!(
+                    "Epoch {:?} of the head {:?} doesn't match the epoch {:?} of the epoch manager",
+                    head.epoch_id, head.last_block_hash, epoch_id
+                ));
+                return Ok(None);
+            }
+            metrics::HEAD_EPOCH_MISMATCH_TOTAL.inc();
+            self.chain.mut_store().reload_head()?;
+            self.epoch_manager.clear_caches();
+            reloaded = true;
+        }
+    }
+
+    /// Whether the node is configured to sync until a height and its head reached it.
+    pub fn synced_to_target(&self) -> Result<bool, near_chain::Error> {
+        Ok(match self.config.sync_until_height {
+            Some(height) => self.chain.head()?.height >= height,
+            None => false,
+        })
+    }
+
+    /// Why the chain head was found inconsistent with the epoch manager, if it still is.
+    pub fn head_epoch_mismatch(&self) -> Option<&str> {
+        self.head_epoch_mismatch.as_deref()
+    }
+
     /// Produce block for given `height` on top of block `prev_hash`.
     /// Should be called either from `produce_block` or in tests.
     pub fn produce_block_on(
@@ -569,21 +1065,40 @@ impl Client {
         let validator_signer = self
             .validator_signer
             .as_ref()
-            .ok_or_else(|| Error::BlockProducer("Called without block producer info.".to_string()))?
+            .ok_or_else(|| {
+                Error::block_producer(height, BlockProducerErrorKind::NoValidatorSigner)
+            })?
             .clone();
 
+        if let Some((network, ours)) = self.incompatible_protocol_version() {
+            return Err(Error::block_producer(
+                height,
+                BlockProducerErrorKind::IncompatibleProtocolVersion { network, ours },
+            ));
+        }
+
         // Check that we are were called at the block that we are producer for.
-        let epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(&prev_hash).unwrap();
+        let epoch_id = self
+            .epoch_manager
+            .get_epoch_id_from_prev_block(&prev_hash)
+            .map_err(|err| Error::block_producer(height, err))?;
+        if self.is_slashed_in(&epoch_id) {
+            self.production_skip_reasons.record(
+                height,
+                None,
+                "This validator is slashed in the epoch".to_string(),
+            );
+            return Ok(None);
+        }
         let next_block_proposer = self.epoch_manager.get_block_producer(&epoch_id, height)?;
 
         let prev = self.chain.get_block_header(&prev_hash)?;
         let prev_height = prev.height();
-        let prev_epoch_id = prev.epoch_id().clone();
-        let prev_next_bp_hash = *prev.next_bp_hash();
 
         // Check and update the doomslug tip here. This guarantees that our endorsement will be in the
         // doomslug witness. Have to do it before checking the ability to produce a block.
-        let _ = self.check_and_update_doomslug_tip()?;
+        let head = self.chain.head()?;
+        self.check_and_update_doomslug_tip(&head)?;
 
         if !self.can_produce_block(
             &prev,
@@ -606,6 +1121,11 @@ impl Client {
                 local_validator_key = ?validator_signer.public_key(),
                 ?validator_pk,
                 "Local validator key does not match expected validator key, skipping block production");
+            self.production_skip_reasons.record(
+                height,
+                None,
+                "Local validator key does not match the expected key".to_string(),
+            );
             #[cfg(not(feature = "test_features"))]
             return Ok(None);
             #[cfg(feature = "test_features")]
@@ -630,24 +1150,272 @@ impl Client {
         // If we are producing empty blocks and there are no transactions.
         if !self.config.produce_empty_blocks && new_chunks.is_empty() {
             debug!(target: "client", "Empty blocks, skipping block production");
+            self.production_skip_reasons.record(
+                height,
+                None,
+                "No new chunks and empty blocks are disabled".to_string(),
+            );
             return Ok(None);
         }
 
-        let mut approvals_map = self.doomslug.get_witness(&prev_hash, prev_height, height);
+        let approvals = self.doomslug.get_witness(&prev_hash, prev_height, height);
 
-        // At this point, the previous epoch hash must be available
-        let epoch_id = self
-            .epoch_manager
-            .get_epoch_id_from_prev_block(&prev_hash)
-            .expect("Epoch hash should exist at this point");
         let protocol_version = self
             .epoch_manager
             .get_epoch_protocol_version(&epoch_id)
-            .expect("Epoch info should be ready at this point");
+            .map_err(|err| Error::block_producer(height, err))?;
+        if protocol_version > PROTOCOL_VERSION {
+            self.set_incompatible_protocol_version(protocol_version);
+            return Err(Error::block_producer(
+                height,
+                BlockProducerErrorKind::IncompatibleProtocolVersion {
+                    network: protocol_version,
+                    ours: PROTOCOL_VERSION,
+                },
+            ));
+        }
+
+        // Add debug information about the block production (and info on when did the chunks arrive).
+        self.block_production_info.record_block_production(
+            height,
+            BlockProductionTracker::construct_chunk_collection_info(
+                height,
+                &epoch_id,
+                self.epoch_manager.num_shards(&epoch_id)?,
+                &new_chunks,
+                self.epoch_manager.as_ref(),
+            )?,
+        );
+
+        let excluded_chunks = self
+            .prev_block_to_chunk_headers_ready_for_inclusion
+            .peek(&prev_hash)
+            .map(|ready| {
+                ready
+                    .iter()
+                    .filter(|(shard_id, _)| !new_chunks.contains_key(*shard_id))
+                    .map(|(_, (chunk_header, _, _))| chunk_header.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let inputs = self.block_production_inputs(
+            prev_hash,
+            new_chunks
+                .into_iter()
+                .map(|(shard_id, (chunk_header, _, _))| (shard_id, chunk_header))
+                .collect(),
+            excluded_chunks,
+            approvals,
+            protocol_version,
+        );
+        let block = self.produce_block_from_inputs(height, &inputs, &*validator_signer)?;
+        self.save_produced_block_inputs(height, *block.hash(), &inputs);
+
+        // Update latest known even before returning block out, to prevent race conditions.
+        let latest_known = LatestKnown { height, seen: block.header().raw_timestamp() };
+        self.store_audit_log
+            .audited(StoreWrite::LatestKnown { height, block_hash: *block.hash() }, || {
+                self.chain.mut_store().save_latest_known(latest_known)
+            })?;
+
+        metrics::BLOCK_PRODUCED_TOTAL.inc();
+        self.production_alerts.remove(&None);
+
+        Ok(Some(block))
+    }
+
+    /// Collects the inputs of the block production which depend on the time of production.
+    fn block_production_inputs(
+        &self,
+        prev_hash: CryptoHash,
+        new_chunks: HashMap<ShardId, ShardChunkHeader>,
+        excluded_chunks: Vec<ShardChunkHeader>,
+        approvals: HashMap<AccountId, (Approval, chrono::DateTime<chrono::Utc>)>,
+        protocol_version: ProtocolVersion,
+    ) -> BlockProductionInputs {
+        #[cfg(feature = "sandbox")]
+        let timestamp = StaticClock::utc() + self.sandbox_delta_time();
+        #[cfg(not(feature = "sandbox"))]
+        let timestamp = StaticClock::utc();
+
+        BlockProductionInputs {
+            prev_hash,
+            new_chunks,
+            excluded_chunks,
+            approvals: approvals
+                .into_iter()
+                .map(|(account_id, (approval, _))| (account_id, approval))
+                .collect(),
+            timestamp,
+            gas_price_adjustment_rate: self
+                .chain
+                .block_economics_config
+                .gas_price_adjustment_rate(protocol_version),
+            min_gas_price: self.chain.block_economics_config.min_gas_price(protocol_version),
+            max_gas_price: self.chain.block_economics_config.max_gas_price(protocol_version),
+        }
+    }
+
+    /// Persists the inputs the block at `height` was produced from, and drops the ones of the
+    /// blocks below the horizon. Failing to persist them doesn't fail block production.
+    fn save_produced_block_inputs(
+        &mut self,
+        height: BlockHeight,
+        block_hash: CryptoHash,
+        inputs: &BlockProductionInputs,
+    ) {
+        let store = self.chain.store().store().clone();
+        let write = StoreWrite::BlockProductionInputs { height, block_hash };
+        let result = self.store_audit_log.audited(write, || -> std::io::Result<()> {
+            let mut store_update = store.store_update();
+            store_update.set_ser(
+                DBCol::BlockProductionInputs,
+                &height.to_be_bytes(),
+                &(block_hash, inputs),
+            )?;
+            store_update.delete_range(
+                DBCol::BlockProductionInputs,
+                &0u64.to_be_bytes(),
+                &height.saturating_sub(PRODUCED_BLOCK_INPUTS_HORIZON).to_be_bytes(),
+            );
+            store_update.commit()
+        });
+        if let Err(err) = result {
+            warn!(target: "client", height, ?err, "Failed to persist the block production inputs");
+        }
+    }
+
+    /// Returns the hash of the block this node produced at `height` and the inputs it was produced
+    /// from, if they are still kept.
+    pub fn produced_block_inputs(
+        &self,
+        height: BlockHeight,
+    ) -> Result<Option<(CryptoHash, BlockProductionInputs)>, Error> {
+        Ok(load_produced_block_inputs(self.chain.store().store(), height)
+            .map_err(near_chain::Error::from)?)
+    }
+
+    /// Goes through `produce_block_on` for the height after the head, without signing the block
+    /// or changing any state. Lets operators check that their node is ready to produce blocks
+    /// before its turn comes.
+    pub fn simulate_block_production(&self) -> Result<SimulatedBlockProduction, Error> {
+        let head = self.chain.head()?;
+        let height = head.height + 1;
+        let prev_hash = head.last_block_hash;
+        let skipped = |reason: String| Ok(SimulatedBlockProduction::Skipped { height, reason });
+
+        let Some(validator_signer) = self.validator_signer.as_ref() else {
+            return skipped("No block producer info".to_string());
+        };
+        if let Some((network, ours)) = self.incompatible_protocol_version() {
+            return skipped(format!(
+                "Network protocol version {} is newer than client protocol version {}",
+                network, ours
+            ));
+        }
+
+        let epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(&prev_hash)?;
+        let next_block_proposer = self.epoch_manager.get_block_producer(&epoch_id, height)?;
+        if validator_signer.validator_id() != &next_block_proposer {
+            return skipped(format!("{} is the block producer", next_block_proposer));
+        }
+        let prev_header = self.chain.get_block_header(&prev_hash)?;
+        if let Some(reason) = self.block_production_skip_reason(&prev_header, height)? {
+            return skipped(reason);
+        }
+        let (validator_stake, _) = self.epoch_manager.get_validator_by_account_id(
+            &epoch_id,
+            &prev_hash,
+            &next_block_proposer,
+        )?;
+        if validator_stake.take_public_key() != validator_signer.public_key() {
+            return skipped("Local validator key does not match the expected key".to_string());
+        }
+
+        // Same as `get_chunk_headers_ready_for_inclusion`, without counting the dropped chunks.
+        let (new_chunks, excluded_chunks): (Vec<_>, Vec<_>) = self
+            .prev_block_to_chunk_headers_ready_for_inclusion
+            .peek(&prev_hash)
+            .into_iter()
+            .flatten()
+            .partition(|(_, (_, _, chunk_producer))| {
+                !self.is_chunk_producer_banned(&epoch_id, chunk_producer)
+            });
+        let new_chunks: HashMap<_, _> = new_chunks
+            .into_iter()
+            .map(|(shard_id, (chunk_header, _, _))| (*shard_id, chunk_header.clone()))
+            .collect();
+        let excluded_chunks = excluded_chunks
+            .into_iter()
+            .map(|(_, (chunk_header, _, _))| chunk_header.clone())
+            .collect();
+        if !self.config.produce_empty_blocks && new_chunks.is_empty() {
+            return skipped("No new chunks and empty blocks are disabled".to_string());
+        }
+
+        let protocol_version = self.epoch_manager.get_epoch_protocol_version(&epoch_id)?;
         if protocol_version > PROTOCOL_VERSION {
-            panic!("The client protocol version is older than the protocol version of the network. Please update nearcore. Client protocol version:{}, network protocol version {}", PROTOCOL_VERSION, protocol_version);
+            return skipped(format!(
+                "Network protocol version {} is newer than client protocol version {}",
+                protocol_version, PROTOCOL_VERSION
+            ));
         }
 
+        let approvals = self.doomslug.get_witness(&prev_hash, prev_header.height(), height);
+        let inputs = self.block_production_inputs(
+            prev_hash,
+            new_chunks,
+            excluded_chunks,
+            approvals,
+            protocol_version,
+        );
+        // The block is never sent anywhere, so its signature doesn't matter. An empty signature
+        // has the same size as a real one.
+        let block =
+            self.produce_block_from_inputs(height, &inputs, &EmptyValidatorSigner::default())?;
+
+        let approvers = self.epoch_manager.get_epoch_block_approvers_ordered(&prev_hash)?;
+        let mut approvals = vec![];
+        let mut approvals_stake = 0;
+        let mut total_stake = 0;
+        for ((approver, _), approval) in approvers.into_iter().zip(block.header().approvals()) {
+            total_stake += approver.stake_this_epoch;
+            if approval.is_some() {
+                approvals_stake += approver.stake_this_epoch;
+                approvals.push(approver.account_id);
+            }
+        }
+        approvals.sort();
+        let estimated_size_bytes =
+            borsh::object_length(&block).map_err(near_chain::Error::from)? as u64;
+
+        Ok(SimulatedBlockProduction::Produced(SimulatedBlockView {
+            height,
+            prev_hash,
+            chunk_mask: block.header().chunk_mask().to_vec(),
+            approvals,
+            approvals_stake,
+            total_stake,
+            gas_price: block.header().next_gas_price(),
+            estimated_size_bytes,
+        }))
+    }
+
+    /// Builds the block at `height` from the inputs which can't be recovered from the chain.
+    /// Everything else is read from the chain and the epoch manager, so calling this again with
+    /// the same inputs must result in the same block.
+    pub(crate) fn produce_block_from_inputs(
+        &self,
+        height: BlockHeight,
+        inputs: &BlockProductionInputs,
+        validator_signer: &dyn ValidatorSigner,
+    ) -> Result<Block, Error> {
+        let prev_hash = inputs.prev_hash;
+        let epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(&prev_hash)?;
+        let next_epoch_id = self.epoch_manager.get_next_epoch_id_from_prev_block(&prev_hash)?;
+
+        let mut approvals_map = inputs.approvals.clone();
         let approvals = self
             .epoch_manager
             .get_epoch_block_approvers_ordered(&prev_hash)?
@@ -656,40 +1424,26 @@ impl Client {
                 if is_slashed {
                     None
                 } else {
-                    approvals_map.remove(&account_id).map(|x| x.0.signature.into())
+                    approvals_map.remove(&account_id).map(|x| x.signature.into())
                 }
             })
             .collect();
 
         debug_assert_eq!(approvals_map.len(), 0);
 
-        let next_epoch_id = self
-            .epoch_manager
-            .get_next_epoch_id_from_prev_block(&prev_hash)
-            .expect("Epoch hash should exist at this point");
-
-        let protocol_version = self.epoch_manager.get_epoch_protocol_version(&epoch_id)?;
-        let gas_price_adjustment_rate =
-            self.chain.block_economics_config.gas_price_adjustment_rate(protocol_version);
-        let min_gas_price = self.chain.block_economics_config.min_gas_price(protocol_version);
-        let max_gas_price = self.chain.block_economics_config.max_gas_price(protocol_version);
-
-        let next_bp_hash = if prev_epoch_id != epoch_id {
+        let prev_block = self.chain.get_block(&prev_hash)?;
+        let prev_header = prev_block.header();
+        let next_bp_hash = if prev_header.epoch_id() != &epoch_id {
             Chain::compute_bp_hash(
                 self.epoch_manager.as_ref(),
-                next_epoch_id,
+                next_epoch_id.clone(),
                 epoch_id.clone(),
                 &prev_hash,
             )?
         } else {
-            prev_next_bp_hash
+            *prev_header.next_bp_hash()
         };
 
-        #[cfg(feature = "sandbox")]
-        let timestamp_override = Some(StaticClock::utc() + self.sandbox_delta_time());
-        #[cfg(not(feature = "sandbox"))]
-        let timestamp_override = None;
-
         // Get block extra from previous block.
         let block_merkle_tree = self.chain.store().get_block_merkle_tree(&prev_hash)?;
         let mut block_merkle_tree = PartialMerkleTree::clone(&block_merkle_tree);
@@ -699,31 +1453,15 @@ impl Client {
         // The ordinal of the next Block will be equal to this amount plus one.
         let block_ordinal: NumBlocks = block_merkle_tree.size() + 1;
         let prev_block_extra = self.chain.get_block_extra(&prev_hash)?;
-        let prev_block = self.chain.get_block(&prev_hash)?;
         let mut chunks = Chain::get_prev_chunk_headers(self.epoch_manager.as_ref(), &prev_block)?;
 
-        // Add debug information about the block production (and info on when did the chunks arrive).
-        self.block_production_info.record_block_production(
-            height,
-            BlockProductionTracker::construct_chunk_collection_info(
-                height,
-                &epoch_id,
-                chunks.len() as ShardId,
-                &new_chunks,
-                self.epoch_manager.as_ref(),
-            )?,
-        );
-
         // Collect new chunks.
-        for (shard_id, (mut chunk_header, _, _)) in new_chunks {
+        for (shard_id, chunk_header) in &inputs.new_chunks {
+            let mut chunk_header = chunk_header.clone();
             *chunk_header.height_included_mut() = height;
-            chunks[shard_id as usize] = chunk_header;
+            chunks[*shard_id as usize] = chunk_header;
         }
 
-        let prev_header = &prev_block.header();
-
-        let next_epoch_id = self.epoch_manager.get_next_epoch_id_from_prev_block(&prev_hash)?;
-
         let minted_amount = if self.epoch_manager.is_next_block_epoch_start(&prev_hash)? {
             Some(self.epoch_manager.get_epoch_minted_amount(&next_epoch_id)?)
         } else {
@@ -748,7 +1486,7 @@ impl Client {
         let next_epoch_protocol_version =
             self.epoch_manager.get_epoch_protocol_version(&next_epoch_id)?;
 
-        let block = Block::produce(
+        Ok(Block::produce(
             this_epoch_protocol_version,
             next_epoch_protocol_version,
             prev_header,
@@ -759,26 +1497,17 @@ impl Client {
             next_epoch_id,
             epoch_sync_data_hash,
             approvals,
-            gas_price_adjustment_rate,
-            min_gas_price,
-            max_gas_price,
+            inputs.gas_price_adjustment_rate,
+            inputs.min_gas_price,
+            inputs.max_gas_price,
             minted_amount,
             prev_block_extra.challenges_result.clone(),
             vec![],
-            &*validator_signer,
+            validator_signer,
             next_bp_hash,
             block_merkle_root,
-            timestamp_override,
-        );
-
-        // Update latest known even before returning block out, to prevent race conditions.
-        self.chain
-            .mut_store()
-            .save_latest_known(LatestKnown { height, seen: block.header().raw_timestamp() })?;
-
-        metrics::BLOCK_PRODUCED_TOTAL.inc();
-
-        Ok(Some(block))
+            Some(inputs.timestamp),
+        ))
     }
 
     pub fn produce_chunk(
@@ -796,11 +1525,19 @@ impl Client {
         let validator_signer = self
             .validator_signer
             .as_ref()
-            .ok_or_else(|| Error::ChunkProducer("Called without block producer info.".to_string()))?
+            .ok_or_else(|| {
+                Error::chunk_producer(
+                    shard_id,
+                    next_height,
+                    ChunkProducerErrorKind::NoValidatorSigner,
+                )
+            })?
             .clone();
 
-        let chunk_proposer =
-            self.epoch_manager.get_chunk_producer(epoch_id, next_height, shard_id).unwrap();
+        let chunk_proposer = self
+            .epoch_manager
+            .get_chunk_producer(epoch_id, next_height, shard_id)
+            .map_err(|err| Error::chunk_producer(shard_id, next_height, err))?;
         if validator_signer.validator_id() != &chunk_proposer {
             debug!(target: "client",
                 me = ?validator_signer.validator_id(),
@@ -810,14 +1547,23 @@ impl Client {
                 "Not producing chunk. Not chunk producer for next chunk.");
             return Ok(None);
         }
+        if self.is_slashed_in(epoch_id) {
+            self.production_skip_reasons.record(
+                next_height,
+                Some(shard_id),
+                "This validator is slashed in the epoch".to_string(),
+            );
+            return Ok(None);
+        }
         if self.epoch_manager.is_next_block_epoch_start(&prev_block_hash)? {
             let prev_prev_hash = *self.chain.get_block_header(&prev_block_hash)?.prev_hash();
             if !self.chain.prev_block_is_caught_up(&prev_prev_hash, &prev_block_hash)? {
                 // See comment in similar snipped in `produce_block`
                 debug!(target: "client", shard_id, next_height, "Produce chunk: prev block is not caught up");
-                return Err(Error::ChunkProducer(
-                    "State for the epoch is not downloaded yet, skipping chunk production"
-                        .to_string(),
+                return Err(Error::chunk_producer(
+                    shard_id,
+                    next_height,
+                    ChunkProducerErrorKind::PrevBlockNotCaughtUp,
                 ));
             }
         }
@@ -825,18 +1571,48 @@ impl Client {
         debug!(target: "client", me = ?validator_signer.validator_id(), next_height, shard_id, "Producing chunk");
 
         let shard_uid = self.epoch_manager.shard_id_to_uid(shard_id, epoch_id)?;
-        let chunk_extra = self
-            .chain
-            .get_chunk_extra(&prev_block_hash, &shard_uid)
-            .map_err(|err| Error::ChunkProducer(format!("No chunk extra available: {}", err)))?;
+        let chunk_extra =
+            self.chain.get_chunk_extra(&prev_block_hash, &shard_uid).map_err(|err| {
+                Error::chunk_producer(
+                    shard_id,
+                    next_height,
+                    ChunkProducerErrorKind::MissingChunkExtra(err),
+                )
+            })?;
 
         let prev_block_header = self.chain.get_block_header(&prev_block_hash)?;
-        let transactions = self.prepare_transactions(
-            shard_uid,
-            chunk_extra.gas_limit(),
-            *chunk_extra.state_root(),
+        if let Some(kind) = self.chunk_extra_mismatch(
             &prev_block_header,
-        )?;
+            &shard_uid,
+            shard_id,
+            &last_header,
+            &chunk_extra,
+        )? {
+            error!(target: "client", shard_id, next_height, %kind, "Produce chunk: refusing to produce on top of an inconsistent chunk extra");
+            metrics::CHUNK_EXTRA_MISMATCH.with_label_values(&[&shard_id.to_string()]).inc();
+            return Err(Error::chunk_producer(shard_id, next_height, kind));
+        }
+        let transactions = self
+            .prepare_transactions(
+                shard_uid,
+                chunk_extra.gas_limit(),
+                *chunk_extra.state_root(),
+                &prev_block_header,
+                &last_header,
+            )
+            .map_err(|err| match err {
+                Error::Chain(
+                    err @ near_chain::Error::StorageError(
+                        StorageError::FlatStorageBlockNotSupported(_)
+                        | StorageError::MemTrieLoadingError(_),
+                    ),
+                ) => Error::chunk_producer(
+                    shard_id,
+                    next_height,
+                    ChunkProducerErrorKind::StateUnavailable(err),
+                ),
+                err => err,
+            })?;
         #[cfg(feature = "test_features")]
         let transactions = Self::maybe_insert_invalid_transaction(
             transactions,
@@ -844,7 +1620,69 @@ impl Client {
             self.produce_invalid_tx_in_chunks,
         );
         let num_filtered_transactions = transactions.len();
+        let (encoded_chunk, merkle_paths, outgoing_receipts) = self.encode_chunk(
+            &*validator_signer,
+            prev_block_hash,
+            epoch_id,
+            &last_header,
+            next_height,
+            shard_id,
+            &chunk_extra,
+            transactions,
+        )?;
+
+        debug!(target: "client",
+            me = %validator_signer.validator_id(),
+            chunk_hash = ?encoded_chunk.chunk_hash(),
+            %prev_block_hash,
+            num_filtered_transactions,
+            num_outgoing_receipts = outgoing_receipts.len(),
+            "Produced chunk");
+
+        metrics::CHUNK_PRODUCED_TOTAL.inc();
+        #[cfg(feature = "protocol_feature_chunk_validation")]
+        let state_witness_size =
+            self.track_state_witness_size(&prev_block_hash, &last_header, shard_id);
+        #[cfg(not(feature = "protocol_feature_chunk_validation"))]
+        let state_witness_size = None;
+        self.chunk_production_info.record(
+            next_height,
+            shard_id,
+            ChunkProduction {
+                chunk_production_time: Some(StaticClock::utc()),
+                chunk_production_duration_millis: Some(timer.elapsed().as_millis() as u64),
+                state_witness_size,
+            },
+        );
+
+        Ok(Some((encoded_chunk, merkle_paths, outgoing_receipts)))
+    }
+
+    /// Encodes the chunk with `transactions` on top of `chunk_extra`, the chunk extra of the shard
+    /// at the previous block, along with the outgoing receipts of the last chunk of the shard.
+    fn encode_chunk(
+        &mut self,
+        validator_signer: &dyn ValidatorSigner,
+        prev_block_hash: CryptoHash,
+        epoch_id: &EpochId,
+        last_header: &ShardChunkHeader,
+        next_height: BlockHeight,
+        shard_id: ShardId,
+        chunk_extra: &ChunkExtra,
+        transactions: Vec<SignedTransaction>,
+    ) -> Result<(EncodedShardChunk, Vec<MerklePath>, Vec<Receipt>), Error> {
         let (tx_root, _) = merklize(&transactions);
+        if let Some(kind) = self.missing_outgoing_receipts(
+            validator_signer.validator_id(),
+            &prev_block_hash,
+            epoch_id,
+            last_header,
+            next_height,
+            shard_id,
+        )? {
+            debug!(target: "client", shard_id, next_height, ?kind, "Produce chunk: outgoing receipts are missing");
+            return Err(Error::chunk_producer(shard_id, next_height, kind));
+        }
         let outgoing_receipts = self.chain.get_outgoing_receipts_for_shard(
             prev_block_hash,
             shard_id,
@@ -856,6 +1694,7 @@ impl Client {
         let gas_used = chunk_extra.gas_used();
         #[cfg(feature = "test_features")]
         let gas_used = if self.produce_invalid_chunks { gas_used + 1 } else { gas_used };
+        self.update_rs_for_chunk_production();
         let (encoded_chunk, merkle_paths) = ShardsManager::create_encoded_shard_chunk(
             prev_block_hash,
             *chunk_extra.state_root(),
@@ -870,29 +1709,224 @@ impl Client {
             &outgoing_receipts,
             outgoing_receipts_root,
             tx_root,
-            &*validator_signer,
+            validator_signer,
             &mut self.rs_for_chunk_production,
             protocol_version,
         )?;
+        Ok((encoded_chunk, merkle_paths, outgoing_receipts))
+    }
+
+    /// Produces a chunk without transactions in place of a chunk whose production failed with a
+    /// retryable error, see `ClientConfig::produce_empty_chunk_on_failure`. The state root, the
+    /// gas and the validator proposals are carried over from the chunk extra of the previous
+    /// block. Without it there is no state root to carry over, so no chunk is produced and
+    /// `None` is returned. The chunk still fails if the outgoing receipts are missing.
+    pub(crate) fn produce_empty_chunk(
+        &mut self,
+        prev_block_hash: CryptoHash,
+        epoch_id: &EpochId,
+        last_header: &ShardChunkHeader,
+        next_height: BlockHeight,
+        shard_id: ShardId,
+    ) -> Result<Option<(EncodedShardChunk, Vec<MerklePath>, Vec<Receipt>)>, Error> {
+        let validator_signer = self.validator_signer.clone().ok_or_else(|| {
+            Error::chunk_producer(shard_id, next_height, ChunkProducerErrorKind::NoValidatorSigner)
+        })?;
+        let shard_uid = self.epoch_manager.shard_id_to_uid(shard_id, epoch_id)?;
+        let chunk_extra = match self.chain.get_chunk_extra(&prev_block_hash, &shard_uid) {
+            Ok(chunk_extra) => chunk_extra,
+            Err(near_chain::Error::DBNotFoundErr(_)) => {
+                warn!(target: "client", shard_id, next_height, ?prev_block_hash, "No chunk extra for the previous block, skipping the empty chunk");
+                return Ok(None);
+            }
+            Err(err) => {
+                return Err(Error::chunk_producer(
+                    shard_id,
+                    next_height,
+                    ChunkProducerErrorKind::MissingChunkExtra(err),
+                ))
+            }
+        };
+        let prev_block_header = self.chain.get_block_header(&prev_block_hash)?;
+        if let Some(kind) = self.chunk_extra_mismatch(
+            &prev_block_header,
+            &shard_uid,
+            shard_id,
+            last_header,
+            &chunk_extra,
+        )? {
+            return Err(Error::chunk_producer(shard_id, next_height, kind));
+        }
+        self.encode_chunk(
+            &*validator_signer,
+            prev_block_hash,
+            epoch_id,
+            last_header,
+            next_height,
+            shard_id,
+            &chunk_extra,
+            vec![],
+        )
+        .map(Some)
+    }
+
+    /// Checks whether the outgoing receipts of the last chunk included for `shard_id` are
+    /// available locally. This happens when this node started tracking the shard after that chunk
+    /// was applied, e.g. after it was assigned the shard mid-epoch. The receipts only get stored
+    /// once a chunk of the shard included while we track it is applied, so until another chunk
+    /// producer produces one, this node can't produce chunks with the right outgoing receipts.
+    fn missing_outgoing_receipts(
+        &self,
+        me: &AccountId,
+        prev_block_hash: &CryptoHash,
+        epoch_id: &EpochId,
+        last_header: &ShardChunkHeader,
+        next_height: BlockHeight,
+        shard_id: ShardId,
+    ) -> Result<Option<ChunkProducerErrorKind>, Error> {
+        let last_included_height = last_header.height_included();
+        let (receipts_block_hash, receipts_shard_id) =
+            self.chain.store().get_outgoing_receipts_source(
+                self.epoch_manager.as_ref(),
+                *prev_block_hash,
+                shard_id,
+                last_included_height,
+            )?;
+        // The receipts of the genesis chunks are never stored, there are none.
+        if &receipts_block_hash == self.chain.genesis().hash() {
+            return Ok(None);
+        }
+        match self.chain.store().get_outgoing_receipts(&receipts_block_hash, receipts_shard_id) {
+            Ok(_) => return Ok(None),
+            Err(near_chain::Error::DBNotFoundErr(_)) => {}
+            Err(err) => return Err(err.into()),
+        }
+        // The chunk of the first height with another chunk producer is applied by this node, so
+        // it can produce chunks again from the next height on.
+        let other_producer_height =
+            (next_height + 1..next_height + self.config.epoch_length).find(|height| {
+                self.epoch_manager
+                    .get_chunk_producer(epoch_id, *height, shard_id)
+                    .map_or(false, |chunk_producer| &chunk_producer != me)
+            });
+        Ok(Some(ChunkProducerErrorKind::MissingOutgoingReceipts {
+            last_included_height,
+            resume_height: other_producer_height.map(|height| height + 1),
+        }))
+    }
+
+    /// Checks that the chunk extra of the previous block is the one computed when the shard was
+    /// applied at that block: its state root must be the root the recorded trie changes lead to,
+    /// and if the previous block has a new chunk of the shard, the trie changes must start from
+    /// the prev state root of that chunk. Nothing is checked unless
+    /// `verify_chunk_extra_before_production` is set, or if the trie changes aren't stored, e.g.
+    /// because the node doesn't keep them or the state was synced.
+    fn chunk_extra_mismatch(
+        &self,
+        prev_block_header: &BlockHeader,
+        shard_uid: &ShardUId,
+        shard_id: ShardId,
+        last_header: &ShardChunkHeader,
+        chunk_extra: &ChunkExtra,
+    ) -> Result<Option<ChunkProducerErrorKind>, Error> {
+        if !self.config.verify_chunk_extra_before_production {
+            return Ok(None);
+        }
+        let prev_block_hash = *prev_block_header.hash();
+        let Some(trie_changes) =
+            self.chain.store().get_trie_changes(&prev_block_hash, shard_uid)?
+        else {
+            return Ok(None);
+        };
+        if chunk_extra.state_root() != &trie_changes.new_root {
+            return Ok(Some(ChunkProducerErrorKind::InconsistentChunkExtra {
+                prev_block_hash,
+                field: "state root",
+                found: *chunk_extra.state_root(),
+                expected: trie_changes.new_root,
+            }));
+        }
+        // Around a resharding the chunk of the previous block belongs to the parent shard.
+        let prev_shard_uid =
+            self.epoch_manager.shard_id_to_uid(shard_id, prev_block_header.epoch_id())?;
+        if last_header.height_included() == prev_block_header.height()
+            && &prev_shard_uid == shard_uid
+            && last_header.prev_state_root() != trie_changes.old_root
+        {
+            return Ok(Some(ChunkProducerErrorKind::InconsistentChunkExtra {
+                prev_block_hash,
+                field: "state root the shard was applied from",
+                found: trie_changes.old_root,
+                expected: last_header.prev_state_root(),
+            }));
+        }
+        Ok(None)
+    }
 
-        debug!(target: "client",
-            me = %validator_signer.validator_id(),
-            chunk_hash = ?encoded_chunk.chunk_hash(),
-            %prev_block_hash,
-            num_filtered_transactions,
-            num_outgoing_receipts = outgoing_receipts.len(),
-            "Produced chunk");
+    /// Rebuilds `rs_for_chunk_production` if the number of parts expected by the epoch manager
+    /// changed since it was created, which happens when a protocol upgrade changes the number of
+    /// block producer seats. Chunks encoded with the old number of parts wouldn't be accepted.
+    fn update_rs_for_chunk_production(&mut self) {
+        let data_parts = self.epoch_manager.num_data_parts();
+        let total_parts = self.epoch_manager.num_total_parts();
+        if self.rs_for_chunk_production.data_shard_count() == data_parts
+            && self.rs_for_chunk_production.total_shard_count() == total_parts
+        {
+            return;
+        }
+        info!(target: "client",
+            old_data_parts = self.rs_for_chunk_production.data_shard_count(),
+            old_total_parts = self.rs_for_chunk_production.total_shard_count(),
+            data_parts,
+            total_parts,
+            "Number of chunk parts changed, rebuilding the chunk encoder");
+        self.rs_for_chunk_production =
+            ReedSolomonWrapper::new(data_parts, total_parts - data_parts);
+    }
 
-        metrics::CHUNK_PRODUCED_TOTAL.inc();
-        self.chunk_production_info.put(
-            (next_height, shard_id),
-            ChunkProduction {
-                chunk_production_time: Some(StaticClock::utc()),
-                chunk_production_duration_millis: Some(timer.elapsed().as_millis() as u64),
-            },
-        );
+    /// Returns the size of the storage recorded while applying the previous chunk of the shard,
+    /// which is what a validator not tracking the shard would need to validate the produced
+    /// chunk. The size is reported in the metrics, with a warning if it exceeds the soft limit.
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    fn track_state_witness_size(
+        &self,
+        prev_block_hash: &CryptoHash,
+        prev_chunk_header: &ShardChunkHeader,
+        shard_id: ShardId,
+    ) -> Option<u64> {
+        let applied_in = match self.chain.get_block_header_on_chain_by_height(
+            prev_block_hash,
+            prev_chunk_header.height_included(),
+        ) {
+            Ok(header) => header,
+            Err(err) => {
+                debug!(target: "client", ?err, shard_id, "Can't find the block the previous chunk was applied in");
+                return None;
+            }
+        };
+        let size = self
+            .runtime_adapter
+            .get_recorded_storage_size(applied_in.hash(), prev_chunk_header.shard_id())?
+            as u64;
+        let shard_label = shard_id.to_string();
+        metrics::CHUNK_STATE_WITNESS_SIZE.with_label_values(&[&shard_label]).observe(size as f64);
+        if let Some(soft_limit) = self.config.state_witness_size_soft_limit {
+            if size > soft_limit {
+                warn!(target: "client", shard_id, size, soft_limit, "State witness of the produced chunk exceeds the soft limit");
+                metrics::CHUNK_STATE_WITNESS_SIZE_OVER_SOFT_LIMIT
+                    .with_label_values(&[&shard_label])
+                    .inc();
+            }
+        }
+        Some(size)
+    }
 
-        Ok(Some((encoded_chunk, merkle_paths, outgoing_receipts)))
+    /// Returns the size of the state witness of the chunk this node produced at `height` for
+    /// `shard_id`, if it's known.
+    pub fn state_witness_size(&self, height: BlockHeight, shard_id: ShardId) -> Option<u64> {
+        self.chunk_production_info
+            .get(height, shard_id)
+            .and_then(|production| production.state_witness_size)
     }
 
     /// Calculates the root of receipt proofs.
@@ -938,22 +1972,51 @@ impl Client {
         txs
     }
 
-    /// Prepares an ordered list of valid transactions from the pool up the limits.
+    /// Prepares an ordered list of valid transactions from the pool up the limits. The
+    /// transactions of `prev_chunk_header`, the previous chunk of the shard, are never selected.
     fn prepare_transactions(
         &mut self,
         shard_uid: ShardUId,
         gas_limit: Gas,
         state_root: StateRoot,
         prev_block_header: &BlockHeader,
+        prev_chunk_header: &ShardChunkHeader,
     ) -> Result<Vec<SignedTransaction>, Error> {
-        let Self { chain, sharded_tx_pool, epoch_manager, runtime_adapter: runtime, .. } = self;
+        let Self {
+            chain,
+            sharded_tx_pool,
+            epoch_manager,
+            runtime_adapter: runtime,
+            config,
+            tx_admission_policy,
+            ..
+        } = self;
 
         let shard_id = shard_uid.shard_id as ShardId;
         let next_epoch_id = epoch_manager.get_epoch_id_from_prev_block(prev_block_header.hash())?;
         let protocol_version = epoch_manager.get_epoch_protocol_version(&next_epoch_id)?;
+        // The transactions of the previous chunk are removed from the pool only once the block
+        // including it is accepted, and including them again would make the chunk invalid.
+        let prev_chunk_tx_hashes: HashSet<CryptoHash> = match chain
+            .get_chunk(&prev_chunk_header.chunk_hash())
+        {
+            Ok(prev_chunk) => prev_chunk.transactions().iter().map(|tx| tx.get_hash()).collect(),
+            Err(err) => {
+                debug!(target: "client", shard_id, ?err, "Can't exclude the transactions of the previous chunk");
+                HashSet::new()
+            }
+        };
+        let mut num_in_prev_chunk = 0;
 
         let transactions = if let Some(mut iter) = sharded_tx_pool.get_pool_iterator(shard_uid) {
             let transaction_validity_period = chain.transaction_validity_period;
+            let delegate_action_validity = config.delegate_action_validity;
+            let admission_context = TxAdmissionContext {
+                stage: TxAdmissionStage::Chunk,
+                shard_id,
+                epoch_id: &next_epoch_id,
+                height: prev_block_header.height() + 1,
+            };
             runtime.prepare_transactions(
                 prev_block_header.next_gas_price(),
                 gas_limit,
@@ -966,6 +2029,10 @@ impl Client {
                 prev_block_header.height() + 1,
                 &mut iter,
                 &mut |tx: &SignedTransaction| -> bool {
+                    if prev_chunk_tx_hashes.contains(&tx.get_hash()) {
+                        num_in_prev_chunk += 1;
+                        return false;
+                    }
                     chain
                         .store()
                         .check_transaction_validity_period(
@@ -973,13 +2040,27 @@ impl Client {
                             &tx.transaction.block_hash,
                             transaction_validity_period,
                         )
+                        .and_then(|_| {
+                            check_delegate_actions_validity(
+                                chain.store(),
+                                tx,
+                                delegate_action_validity,
+                                prev_block_header.height() + 1,
+                                transaction_validity_period,
+                            )
+                        })
                         .is_ok()
+                        && check_tx_admission(&**tx_admission_policy, tx, &admission_context)
+                            .is_ok()
                 },
                 protocol_version,
             )?
         } else {
             vec![]
         };
+        if num_in_prev_chunk > 0 {
+            debug!(target: "client", shard_id, num_in_prev_chunk, "Skipped transactions already included in the previous chunk");
+        }
         // Reintroduce valid transactions back to the pool. They will be removed when the chunk is
         // included into the block.
         let reintroduced_count = sharded_tx_pool.reintroduce_transactions(shard_uid, &transactions);
@@ -1067,6 +2148,13 @@ impl Client {
             StaticClock::instant(),
             StaticClock::utc(),
         );
+        if self.config.sync_until_height.map_or(false, |height| block.header().height() > height) {
+            debug!(target: "client", height = block.header().height(), "Dropping a block above the sync target.");
+            self.chain
+                .blocks_delay_tracker
+                .mark_block_dropped(block.hash(), DroppedReason::AboveSyncTarget);
+            return Ok(());
+        }
         // To protect ourselves from spamming, we do some pre-check on block height before we do any
         // real processing.
         if !self.check_block_height(&block, was_requested)? {
@@ -1082,7 +2170,7 @@ impl Client {
         if self.chain.verify_block_hash_and_signature(&block)?
             == VerifyBlockHashAndSignatureResult::Incorrect
         {
-            self.ban_peer(peer_id, ReasonForBan::BadBlockHeader);
+            self.ban_peer(peer_id, ReasonForBan::BadBlockHeader, Some(*block.hash()));
             return Err(near_chain::Error::InvalidSignature);
         }
 
@@ -1091,6 +2179,7 @@ impl Client {
         self.verify_and_rebroadcast_block(&block, was_requested, &peer_id)?;
         let provenance =
             if was_requested { near_chain::Provenance::SYNC } else { near_chain::Provenance::NONE };
+        self.block_provenance.record_received(*block.hash(), peer_id.clone());
         let res = self.start_process_block(block, provenance, apply_chunks_done_callback);
         match &res {
             Err(near_chain::Error::Orphan) => {
@@ -1172,7 +2261,11 @@ impl Client {
                 // that a block is considered valid in one machine and invalid in another machine when their
                 // clocks are not synced.
                 if !matches!(e, near_chain::Error::InvalidBlockFutureTime(_)) {
-                    self.ban_peer(peer_id.clone(), ReasonForBan::BadBlockHeader);
+                    self.ban_peer(
+                        peer_id.clone(),
+                        ReasonForBan::BadBlockHeader,
+                        Some(*block.hash()),
+                    );
                 }
                 Err(e)
             }
@@ -1203,7 +2296,20 @@ impl Client {
                 ?provenance,
                 block_height = block.header().height())
         .entered();
+        if let Some((network, ours)) = self.incompatible_protocol_version() {
+            debug!(target: "client", network, ours, "Incompatible protocol version, dropping block");
+            return Ok(());
+        }
+        if let Err(err) = self.check_next_bp_hash(block.header()) {
+            // The header validation rejects blocks of other producers anyway, but ours would have
+            // been processed as already validated. It's the only check of our blocks before they
+            // are sent out.
+            if provenance == Provenance::PRODUCED {
+                return Err(err);
+            }
+        }
         let mut block_processing_artifacts = BlockProcessingArtifact::default();
+        let header = block.header().clone();
 
         let result = {
             let me = self
@@ -1220,6 +2326,9 @@ impl Client {
         };
 
         self.process_block_processing_artifact(block_processing_artifacts);
+        if result.is_ok() && self.config.prioritize_block_before_production {
+            self.prioritize_if_producing_next(&header);
+        }
 
         // Send out challenge if the block was found to be invalid.
         if let Some(validator_signer) = self.validator_signer.as_ref() {
@@ -1249,6 +2358,30 @@ impl Client {
         result
     }
 
+    /// If this node is the producer of the block at the height after a block whose processing
+    /// just started, asks the chain to finish processing that block before the others, as the
+    /// block can't be produced before its prev block is applied.
+    fn prioritize_if_producing_next(&self, header: &BlockHeader) {
+        let Some(validator_signer) = self.validator_signer.as_ref() else {
+            return;
+        };
+        // Only a block which becomes the head can be the prev block of our next block.
+        if self.chain.head().map_or(false, |head| head.height >= header.height()) {
+            return;
+        }
+        // Whether the next block starts a new epoch is only known once the block is applied, so
+        // the producers of both epochs are checked.
+        let height = header.height() + 1;
+        let is_next_block_producer = [header.epoch_id(), header.next_epoch_id()]
+            .into_iter()
+            .filter_map(|epoch_id| self.epoch_manager.get_block_producer(epoch_id, height).ok())
+            .any(|block_producer| &block_producer == validator_signer.validator_id());
+        if is_next_block_producer {
+            debug!(target: "client", block_hash = ?header.hash(), height = header.height(), "Prioritizing the prev block of our next block");
+            self.chain.prioritize_block(header.hash());
+        }
+    }
+
     /// Check if there are any blocks that has finished applying chunks, run post processing on these
     /// blocks.
     pub fn postprocess_ready_blocks(
@@ -1266,13 +2399,10 @@ impl Client {
         let (accepted_blocks, errors) = self.chain.postprocess_ready_blocks(
             &me,
             &mut block_processing_artifacts,
-            apply_chunks_done_callback,
+            apply_chunks_done_callback.clone(),
         );
         if accepted_blocks.iter().any(|accepted_block| accepted_block.status.is_new_head()) {
-            self.shards_manager_adapter.send(ShardsManagerRequestFromClient::UpdateChainHeads {
-                head: self.chain.head().unwrap(),
-                header_head: self.chain.header_head().unwrap(),
-            });
+            self.update_shards_manager_chain_heads(false);
         }
         self.process_block_processing_artifact(block_processing_artifacts);
         let accepted_blocks_hashes =
@@ -1287,6 +2417,14 @@ impl Client {
         }
         self.last_time_head_progress_made =
             max(self.chain.get_last_time_head_updated(), self.last_time_head_progress_made);
+        if let Err(err) = self.maybe_send_recovery_burst() {
+            warn!(target: "client", ?err, "Failed to send recovery burst");
+        }
+        // Start the blocks whose chunks arrived which were deferred to limit the number of blocks
+        // started at once.
+        if self.chain.blocks_with_missing_chunks.num_ready_blocks() > 0 {
+            self.process_blocks_with_missing_chunks(apply_chunks_done_callback);
+        }
         (accepted_blocks_hashes, errors)
     }
 
@@ -1333,6 +2471,8 @@ impl Client {
     ) -> Result<(), Error> {
         let epoch_id =
             self.epoch_manager.get_epoch_id_from_prev_block(chunk_header.prev_block_hash())?;
+        let epoch_height =
+            self.epoch_manager.get_epoch_height_from_prev_block(chunk_header.prev_block_hash())?;
         let chunk_producer = self.epoch_manager.get_chunk_producer(
             &epoch_id,
             chunk_header.height_created(),
@@ -1345,7 +2485,38 @@ impl Client {
             chunk_hash = ?chunk_header.chunk_hash(),
             "Banning chunk producer for producing invalid chunk");
         metrics::CHUNK_PRODUCER_BANNED_FOR_EPOCH.inc();
-        self.do_not_include_chunks_from.put((epoch_id, chunk_producer), ());
+        self.do_not_include_chunks_from.ban(epoch_height, epoch_id, chunk_producer);
+        Ok(())
+    }
+
+    /// Checks the hash of the next epoch block producers of an epoch-boundary block against the
+    /// epoch manager. A mismatch means that the epoch manager of the block producer diverged from
+    /// ours, and if we produced the block, that the rest of the network is going to reject it.
+    fn check_next_bp_hash(&self, header: &BlockHeader) -> Result<(), near_chain::Error> {
+        let Ok(prev_header) = self.chain.get_block_header(header.prev_hash()) else {
+            // Orphans are validated once their previous block is known.
+            return Ok(());
+        };
+        if prev_header.epoch_id() == header.epoch_id() {
+            return Ok(());
+        }
+        let expected_next_bp_hash = Chain::compute_bp_hash(
+            self.epoch_manager.as_ref(),
+            header.next_epoch_id().clone(),
+            header.epoch_id().clone(),
+            header.prev_hash(),
+        )?;
+        if header.next_bp_hash() != &expected_next_bp_hash {
+            error!(
+                target: "client",
+                height = header.height(),
+                block_hash = ?header.hash(),
+                next_bp_hash = ?header.next_bp_hash(),
+                ?expected_next_bp_hash,
+                "Next block producers hash of epoch-boundary block doesn't match the epoch manager");
+            metrics::NEXT_BP_HASH_MISMATCH_TOTAL.inc();
+            return Err(near_chain::Error::InvalidNextBPHash);
+        }
         Ok(())
     }
 
@@ -1365,24 +2536,119 @@ impl Client {
         shard_chunk: Option<ShardChunk>,
         apply_chunks_done_callback: DoneApplyChunkCallback,
     ) {
-        let chunk_header = partial_chunk.cloned_header();
-        self.chain.blocks_delay_tracker.mark_chunk_completed(&chunk_header, StaticClock::utc());
-        self.block_production_info
-            .record_chunk_collected(partial_chunk.height_created(), partial_chunk.shard_id());
-        persist_chunk(partial_chunk, shard_chunk, self.chain.mut_store())
-            .expect("Could not persist chunk");
-        // We're marking chunk as accepted.
-        self.chain.blocks_with_missing_chunks.accept_chunk(&chunk_header.chunk_hash());
-        // If this was the last chunk that was missing for a block, it will be processed now.
+        self.on_chunks_completed(vec![(partial_chunk, shard_chunk)], apply_chunks_done_callback)
+    }
+
+    /// Called asynchronously when the ShardsManager finishes processing several chunks at once.
+    /// The chunks are persisted in the background and `apply_chunks_done_callback` is called
+    /// once each of them is written. Blocks waiting for these chunks are only processed after
+    /// that, see `process_persisted_chunks`.
+    pub fn on_chunks_completed(
+        &mut self,
+        chunks: Vec<(PartialEncodedChunk, Option<ShardChunk>)>,
+        apply_chunks_done_callback: DoneApplyChunkCallback,
+    ) {
+        for (partial_chunk, shard_chunk) in chunks {
+            self.chunk_persister.persist(
+                partial_chunk,
+                shard_chunk,
+                apply_chunks_done_callback.clone(),
+            );
+        }
+    }
+
+    /// Marks the chunks persisted since the last call as completed and processes the blocks which
+    /// were waiting for them.
+    pub fn process_persisted_chunks(&mut self, apply_chunks_done_callback: DoneApplyChunkCallback) {
+        let persisted_chunks = self.chunk_persister.take_results();
+        self.on_chunks_persisted(persisted_chunks, apply_chunks_done_callback)
+    }
+
+    pub(crate) fn on_chunks_persisted(
+        &mut self,
+        persisted_chunks: Vec<PersistedChunk>,
+        apply_chunks_done_callback: DoneApplyChunkCallback,
+    ) {
+        if persisted_chunks.is_empty() {
+            return;
+        }
+        for PersistedChunk { chunk_header, result } in persisted_chunks {
+            let chunk_hash = chunk_header.chunk_hash();
+            let shard_id = chunk_header.shard_id();
+            let height_created = chunk_header.height_created();
+            self.store_audit_log.record(
+                StoreWrite::Chunk { chunk_hash: chunk_hash.clone(), shard_id, height_created },
+                result.as_ref().err(),
+            );
+            match result {
+                Ok(()) => {
+                    self.chunk_persistence_error = None;
+                    self.chain
+                        .blocks_delay_tracker
+                        .mark_chunk_completed(&chunk_header, StaticClock::utc());
+                    self.block_production_info.record_chunk_collected(height_created, shard_id);
+                    // We're marking chunk as accepted.
+                    self.chain.blocks_with_missing_chunks.accept_chunk(&chunk_hash);
+                }
+                Err(err) => {
+                    // The blocks waiting for the chunk stay blocked on it, and the node reports
+                    // itself as unhealthy until a chunk is persisted again.
+                    error!(target: "client", ?chunk_hash, shard_id, height_created, ?err, "Failed to persist chunk");
+                    self.chunk_persistence_error =
+                        Some(format!("Failed to persist chunk {:?}: {}", chunk_hash, err));
+                }
+            }
+        }
+        // If these were the last chunks that were missing for a block, it will be processed now.
         self.process_blocks_with_missing_chunks(apply_chunks_done_callback)
     }
 
+    /// The latest writes to the store made by the client directly, oldest first.
+    pub fn store_audit_log(&self) -> &StoreAuditLog {
+        &self.store_audit_log
+    }
+
+    /// Error of the last chunk write which failed, unless a chunk was persisted since then.
+    pub fn chunk_persistence_error(&self) -> Option<&str> {
+        self.chunk_persistence_error.as_deref()
+    }
+
+    /// Production error with `ErrorSeverity::Alert` of the block or the chunk of a shard, unless
+    /// a block or a chunk of that shard was produced since then.
+    pub fn production_alert(&self) -> Option<&str> {
+        self.production_alerts.values().next().map(String::as_str)
+    }
+
+    /// Records why the block (`shard_id` is None) or chunk at `height` wasn't produced, and counts
+    /// the error by severity.
+    pub(crate) fn record_production_error(
+        &mut self,
+        height: BlockHeight,
+        shard_id: Option<ShardId>,
+        err: &Error,
+    ) {
+        let severity = err.severity();
+        let producer = if shard_id.is_some() { "chunk" } else { "block" };
+        let severity_label: &'static str = severity.into();
+        metrics::PRODUCTION_ERRORS_TOTAL.with_label_values(&[producer, severity_label]).inc();
+        if severity == ErrorSeverity::Alert {
+            self.production_alerts.insert(shard_id, err.to_string());
+        }
+        self.production_skip_reasons.record(height, shard_id, err.to_string());
+    }
+
     /// Called asynchronously when the ShardsManager finishes processing a chunk but the chunk
     /// is invalid.
     pub fn on_invalid_chunk(&mut self, encoded_chunk: EncodedShardChunk) {
+        let chunk_header = encoded_chunk.cloned_header();
+        let write = StoreWrite::InvalidChunk {
+            chunk_hash: chunk_header.chunk_hash(),
+            shard_id: chunk_header.shard_id(),
+            height_created: chunk_header.height_created(),
+        };
         let mut update = self.chain.mut_store().store_update();
         update.save_invalid_chunk(encoded_chunk);
-        if let Err(err) = update.commit() {
+        if let Err(err) = self.store_audit_log.audited(write, || update.commit()) {
             error!(target: "client", ?err, "Error saving invalid chunk");
         }
     }
@@ -1392,33 +2658,64 @@ impl Client {
         chunk_header: ShardChunkHeader,
         chunk_producer: AccountId,
     ) {
+        // The previous block is below the height the chunk was created at.
+        if self.is_below_gc_tail(chunk_header.height_created().saturating_sub(1)) {
+            debug!(target: "client", chunk_hash = ?chunk_header.chunk_hash(), "Dropping a chunk header building on a garbage collected block");
+            metrics::DROPPED_BELOW_GC_TAIL_TOTAL.with_label_values(&["chunk_header"]).inc();
+            return;
+        }
         let prev_block_hash = chunk_header.prev_block_hash();
         self.prev_block_to_chunk_headers_ready_for_inclusion
             .get_or_insert(*prev_block_hash, || HashMap::new());
         self.prev_block_to_chunk_headers_ready_for_inclusion
             .get_mut(prev_block_hash)
             .unwrap()
-            .insert(chunk_header.shard_id(), (chunk_header, chrono::Utc::now(), chunk_producer));
+            .insert(chunk_header.shard_id(), (chunk_header, StaticClock::utc(), chunk_producer));
     }
 
     pub fn sync_block_headers(
         &mut self,
-        headers: Vec<BlockHeader>,
+        mut headers: Vec<BlockHeader>,
     ) -> Result<(), near_chain::Error> {
+        if let Some(sync_until_height) = self.config.sync_until_height {
+            headers.retain(|header| header.height() <= sync_until_height);
+            if headers.is_empty() {
+                return Ok(());
+            }
+        }
         let mut challenges = vec![];
         self.chain.sync_block_headers(headers, &mut challenges)?;
         self.send_challenges(challenges);
-        self.shards_manager_adapter.send(ShardsManagerRequestFromClient::UpdateChainHeads {
-            head: self.chain.head().unwrap(),
-            header_head: self.chain.header_head().unwrap(),
-        });
+        self.update_shards_manager_chain_heads(false);
         Ok(())
     }
 
-    /// Checks if the latest hash known to Doomslug matches the current head, and updates it if not.
-    pub fn check_and_update_doomslug_tip(&mut self) -> Result<(), Error> {
-        let tip = self.chain.head()?;
+    /// Sends the current chain heads to the ShardsManager, unless it already has them. During
+    /// sync, changes are batched unless `flush` is set.
+    fn update_shards_manager_chain_heads(&mut self, flush: bool) {
+        let head = self.chain.head().unwrap();
+        let header_head = self.chain.header_head().unwrap();
+        let batch = !flush && self.sync_status.is_syncing();
+        if !self.chain_heads_throttle.should_send(&head, &header_head, batch) {
+            metrics::UPDATE_CHAIN_HEADS_SUPPRESSED_TOTAL.inc();
+            return;
+        }
+        self.shards_manager_adapter
+            .send(ShardsManagerRequestFromClient::UpdateChainHeads { head, header_head });
+    }
+
+    /// Sends the chain heads changes held back during sync to the ShardsManager. Should be called
+    /// periodically, so that the latest heads are sent even if they don't change anymore.
+    pub fn flush_chain_heads_update(&mut self) {
+        if self.chain_heads_throttle.has_unsent_changes() {
+            self.update_shards_manager_chain_heads(true);
+        }
+    }
 
+    /// Checks if the latest hash known to Doomslug matches `tip`, and updates it if not.
+    /// The caller passes the head it read, so that the approvals it creates next are for the same
+    /// head even if the chain head changes in the meantime.
+    pub fn check_and_update_doomslug_tip(&mut self, tip: &Tip) -> Result<(), Error> {
         if tip.last_block_hash != self.doomslug.get_tip().0 {
             // We need to update the doomslug tip
             let last_final_hash =
@@ -1481,9 +2778,29 @@ impl Client {
         parent_hash: &CryptoHash,
         approval: Approval,
     ) -> Result<(), Error> {
+        // An endorsement of another block than the doomslug tip was created before the tip moved to
+        // a newer head, so the endorsed block is already superseded.
+        if let ApprovalInner::Endorsement(endorsed_hash) = &approval.inner {
+            let tip_hash = self.doomslug.get_tip().0;
+            if endorsed_hash != &tip_hash {
+                warn!(target: "client",
+                    ?endorsed_hash,
+                    ?tip_hash,
+                    target_height = approval.target_height,
+                    "Not sending an endorsement of a block which isn't the doomslug tip");
+                return Ok(());
+            }
+        }
         let next_epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(parent_hash)?;
+        if self.is_slashed_in(&next_epoch_id) {
+            debug!(target: "client",
+                target_height = approval.target_height,
+                "Not sending an approval, this validator is slashed in the epoch");
+            return Ok(());
+        }
         let next_block_producer =
             self.epoch_manager.get_block_producer(&next_epoch_id, approval.target_height)?;
+        self.health.record_approval(approval.target_height);
         if Some(&next_block_producer) == self.validator_signer.as_ref().map(|x| x.validator_id()) {
             self.collect_block_approval(&approval, ApprovalType::SelfApproval);
         } else {
@@ -1502,6 +2819,256 @@ impl Client {
         Ok(())
     }
 
+    /// Logs how the validators and the chunk producer assignment change in the epoch starting
+    /// after `last_block_hash`.
+    fn log_epoch_transition_stats(&self, last_block_hash: &CryptoHash) {
+        let stats = self
+            .epoch_manager
+            .get_epoch_id_from_prev_block(last_block_hash)
+            .and_then(|epoch_id| self.epoch_manager.get_epoch_transition_stats(&epoch_id));
+        match stats {
+            Ok(stats) => info!(
+                target: "client",
+                chunk_producer_shard_change_ratio = stats.chunk_producer_shard_change_ratio(),
+                num_new_validators = stats.num_new_validators,
+                num_dropped_validators = stats.num_dropped_validators,
+                stake_weighted_churn = stats.stake_weighted_churn(),
+                "Validator churn in the next epoch"),
+            // Not known for the first epochs.
+            Err(err) => debug!(target: "client", ?err, "No epoch transition stats"),
+        }
+    }
+
+    /// Warns, at most once per epoch, when this validator would be kicked out at the end of the
+    /// epoch if it kept producing blocks and chunks at its current rates. Only checked in the
+    /// second half of the epoch, for the rates to be meaningful.
+    fn warn_if_projected_kickout(&mut self, account_id: &AccountId, block: &Block) {
+        let epoch_id = block.header().epoch_id();
+        if self.projected_kickout_warned_epoch.as_ref() == Some(epoch_id) {
+            return;
+        }
+        let Ok(epoch_start_height) = self.epoch_manager.get_epoch_start_height(block.hash()) else {
+            return;
+        };
+        if (block.header().height() - epoch_start_height) * 2 < self.config.epoch_length {
+            return;
+        }
+        let kickout = match self.epoch_manager.project_kickouts(epoch_id, block.hash()) {
+            Ok(mut kickouts) => kickouts.remove(account_id).flatten(),
+            Err(err) => {
+                debug!(target: "client", ?err, "Failed to project kickouts");
+                return;
+            }
+        };
+        if let Some(reason) = kickout {
+            warn!(target: "client", ?epoch_id, ?reason, "This validator is on track to be kicked out at the end of the epoch");
+            self.projected_kickout_warned_epoch = Some(epoch_id.clone());
+        }
+    }
+
+    /// Checks whether this validator is slashed in the epoch of the new head `block`. The network
+    /// ignores the blocks, chunks and approvals of a slashed validator, so they aren't produced
+    /// until the blocks are in an epoch in which it isn't slashed.
+    fn check_slashed(&mut self, account_id: &AccountId, block: &Block) {
+        let epoch_id = block.header().epoch_id();
+        let is_slashed = match self.epoch_manager.get_validator_by_account_id(
+            epoch_id,
+            block.hash(),
+            account_id,
+        ) {
+            Ok((_, is_slashed)) => is_slashed,
+            Err(EpochError::NotAValidator(_, _)) => false,
+            Err(err) => {
+                debug!(target: "client", ?err, "Failed to check whether this validator is slashed");
+                return;
+            }
+        };
+        if !is_slashed {
+            if let Some((slashed_epoch_id, _)) = self.slashed_in_epoch.take() {
+                info!(target: "client", ?slashed_epoch_id, ?epoch_id, "This validator is no longer slashed, resuming production");
+            }
+            return;
+        }
+        if self.is_slashed_in(epoch_id) {
+            return;
+        }
+        let height = block.header().height();
+        error!(
+            target: "client",
+            %account_id,
+            ?epoch_id,
+            block_hash = ?block.hash(),
+            height,
+            "THIS VALIDATOR IS SLASHED. Not producing blocks and chunks nor sending approvals until the next epoch it is a validator in");
+        self.slashed_in_epoch = Some((
+            epoch_id.clone(),
+            format!(
+                "Validator {} is slashed in epoch {:?} as of block {:?} at height {}",
+                account_id,
+                epoch_id,
+                block.hash(),
+                height
+            ),
+        ));
+    }
+
+    fn is_slashed_in(&self, epoch_id: &EpochId) -> bool {
+        matches!(&self.slashed_in_epoch, Some((slashed_epoch_id, _)) if slashed_epoch_id == epoch_id)
+    }
+
+    /// Why this validator doesn't produce blocks and chunks, if it's slashed.
+    pub fn slashed_in_epoch(&self) -> Option<&str> {
+        self.slashed_in_epoch.as_ref().map(|(_, context)| context.as_str())
+    }
+
+    /// Summarized health of the node, with the conditions it's derived from.
+    pub fn health(&self) -> NodeHealthView {
+        self.health_at(StaticClock::utc())
+    }
+
+    /// Health of the node at `now`. Conditions which change without new heads, such as the age
+    /// of the head, are evaluated at `now`, the others are the ones of the last new head.
+    pub fn health_at(&self, now: chrono::DateTime<chrono::Utc>) -> NodeHealthView {
+        self.health.report(self.current_health(now))
+    }
+
+    fn current_health(&self, now: chrono::DateTime<chrono::Utc>) -> NodeHealthView {
+        let factors = self.health_factors(now).unwrap_or_else(|err| {
+            vec![NodeHealthFactor::InternalError { message: err.to_string() }]
+        });
+        NodeHealthView::new(factors)
+    }
+
+    fn health_factors(
+        &self,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<NodeHealthFactor>, Error> {
+        let mut factors = vec![];
+        if let Some((network, ours)) = self.incompatible_protocol_version() {
+            factors.push(NodeHealthFactor::IncompatibleProtocolVersion { network, ours });
+        }
+        if let Some(context) = self.slashed_in_epoch() {
+            factors.push(NodeHealthFactor::Slashed { context: context.to_string() });
+        }
+        for message in
+            [self.chunk_persistence_error(), self.head_epoch_mismatch(), self.production_alert()]
+                .into_iter()
+                .flatten()
+        {
+            factors.push(NodeHealthFactor::InternalError { message: message.to_string() });
+        }
+
+        let head = self.chain.head()?;
+        let head_time = self.chain.get_block_header(&head.last_block_hash)?.timestamp();
+        let max_head_age =
+            self.config.max_block_production_delay * STATUS_WAIT_TIME_MULTIPLIER as u32;
+        match (now - head_time).to_std() {
+            Ok(head_age) => {
+                // A node which synced to its target height doesn't expect new blocks.
+                if head_age > max_head_age && !self.synced_to_target()? {
+                    let head_age_millis = head_age.as_millis() as u64;
+                    factors.push(NodeHealthFactor::HeadStale { head_age_millis });
+                }
+            }
+            Err(_) => {
+                // The head comes from the future, the local clock is likely behind.
+                let skew = (head_time - now).to_std().unwrap_or_default();
+                if skew > max_head_age {
+                    factors
+                        .push(NodeHealthFactor::ClockSkew { skew_millis: skew.as_millis() as u64 });
+                }
+            }
+        }
+        if self.sync_status.is_syncing() {
+            factors.push(NodeHealthFactor::Syncing);
+        }
+        if !self.chain.store().iterate_state_sync_infos()?.is_empty() {
+            factors.push(NodeHealthFactor::CatchupPending);
+        }
+
+        let Some(validator_signer) = &self.validator_signer else {
+            factors.push(NodeHealthFactor::NotValidator);
+            return Ok(factors);
+        };
+        let account_id = validator_signer.validator_id();
+        match self.epoch_manager.get_validator_by_account_id(
+            &head.epoch_id,
+            &head.last_block_hash,
+            account_id,
+        ) {
+            Ok(_) => {}
+            Err(EpochError::NotAValidator(..)) => {
+                factors.push(NodeHealthFactor::NotValidator);
+                return Ok(factors);
+            }
+            Err(err) => return Err(err.into()),
+        }
+        factors.extend(self.health.production_factors());
+
+        // Without doomslug, the blocks are produced without waiting for approvals.
+        if self.doomslug.threshold_mode() != DoomslugThresholdMode::NoApprovals {
+            let is_block_producer = self
+                .epoch_manager
+                .get_epoch_block_producers_ordered(&head.epoch_id, &head.last_block_hash)?
+                .iter()
+                .any(|(validator, _)| validator.account_id() == account_id);
+            let last_approval_height = self.health.last_approval_height();
+            if is_block_producer
+                && last_approval_height
+                    .map_or(true, |height| height + APPROVAL_RECENCY_HEIGHTS <= head.height)
+            {
+                factors.push(NodeHealthFactor::NoRecentApprovals { last_approval_height });
+            }
+        }
+        Ok(factors)
+    }
+
+    /// Records whether this node produced the block and the chunks it was scheduled to produce
+    /// on the chain up to the new head `block`, and updates the health.
+    fn update_health(&mut self, block: &Block) {
+        if let Err(err) = self.record_production_health(block) {
+            warn!(target: "client", ?err, "Failed to check the production of the new head");
+        }
+        let current = self.current_health(StaticClock::utc());
+        self.health.update(current);
+    }
+
+    fn record_production_health(&mut self, block: &Block) -> Result<(), Error> {
+        let Some(validator_signer) = &self.validator_signer else {
+            return Ok(());
+        };
+        let account_id = validator_signer.validator_id().clone();
+        let epoch_id = block.header().epoch_id();
+        let height = block.header().height();
+        let prev_height = self.chain.get_block_header(block.header().prev_hash())?.height();
+        // The heights skipped since the previous block belong to the epoch of the new head. Only
+        // the latest one this node was scheduled at matters.
+        let lowest_height = (prev_height + 1).max(height.saturating_sub(self.config.epoch_length));
+        for scheduled_height in (lowest_height..=height).rev() {
+            if self.epoch_manager.get_block_producer(epoch_id, scheduled_height)? == account_id {
+                self.health.record_block_production(scheduled_height, scheduled_height == height);
+                break;
+            }
+        }
+
+        let mut scheduled = false;
+        let mut missed_shard_ids = vec![];
+        for chunk in block.chunks().iter() {
+            let shard_id = chunk.shard_id();
+            if self.epoch_manager.get_chunk_producer(epoch_id, height, shard_id)? != account_id {
+                continue;
+            }
+            scheduled = true;
+            if chunk.height_included() != height {
+                missed_shard_ids.push(shard_id);
+            }
+        }
+        if scheduled {
+            self.health.record_chunk_production(height, missed_shard_ids);
+        }
+        Ok(())
+    }
+
     /// Gets called when block got accepted.
     /// Only produce chunk if `skip_produce_chunk` is false.
     /// `skip_produce_chunk` is set to true to simulate when there are missing chunks in a block
@@ -1529,8 +3096,13 @@ impl Client {
                 return;
             }
         };
+        self.block_provenance.record_accepted(&block, provenance.clone());
 
-        let _ = self.check_and_update_doomslug_tip();
+        // Doomslug has to know the new head before the approvals pending for the block are
+        // collected below.
+        if let Ok(head) = self.chain.head() {
+            let _ = self.check_and_update_doomslug_tip(&head);
+        }
 
         // If we produced the block, then it should have already been broadcasted.
         // If received the block from another node then broadcast "header first" to minimize network traffic.
@@ -1583,26 +3155,81 @@ impl Client {
             // TODO make sure transactions don't get added for the old shard
             // layout after the pool resharding
             if self.epoch_manager.is_next_block_epoch_start(&block_hash).unwrap_or(false) {
-                let new_shard_layout =
-                    self.epoch_manager.get_shard_layout_from_prev_block(&block_hash);
-                let old_shard_layout =
-                    self.epoch_manager.get_shard_layout_from_prev_block(block.header().prev_hash());
-                match (old_shard_layout, new_shard_layout) {
-                    (Ok(old_shard_layout), Ok(new_shard_layout)) => {
-                        if old_shard_layout != new_shard_layout {
-                            self.sharded_tx_pool.reshard(&old_shard_layout, &new_shard_layout);
-                        }
+                self.check_next_epoch_protocol_version(&block_hash);
+                if let Some(path) = self.config.production_report_path.clone() {
+                    if let Err(err) = self.spawn_production_report_writer(&block_hash, path) {
+                        warn!(target: "client", ?err, "Failed to generate epoch production report");
+                    }
+                }
+                self.log_epoch_transition_stats(&block_hash);
+                match self.epoch_manager.get_epoch_height_from_prev_block(&block_hash) {
+                    Ok(epoch_height) => self.do_not_include_chunks_from.prune(epoch_height),
+                    Err(err) => {
+                        warn!(target: "client", ?err, "Failed to prune the chunk producer blocklist")
+                    }
+                }
+                // Resolve the shards we produce chunks for once for the whole next epoch.
+                if let Err(err) = self
+                    .epoch_manager
+                    .get_epoch_id_from_prev_block(&block_hash)
+                    .map_err(Error::from)
+                    .and_then(|epoch_id| self.chunk_producer_shards(&epoch_id))
+                {
+                    warn!(target: "client", ?err, "Failed to resolve chunk producer assignment");
+                }
+                match self.epoch_manager.shard_layout_diff(&block_hash) {
+                    Ok(Some(diff)) => {
+                        let me = self.validator_signer.as_ref().map(|signer| signer.validator_id());
+                        let prev_hash = block.header().prev_hash();
+                        let affected_shards = diff.affected_shards(|shard_id| {
+                            self.shard_tracker.care_about_shard(me, prev_hash, shard_id, true)
+                        });
+                        let old_version = diff.old_shard_layout.version();
+                        let new_version = diff.new_shard_layout.version();
+                        info!(target: "client", old_version, new_version, ?affected_shards, "Shard layout changes in the next epoch");
+                        self.resharding_log.record(ReshardingEvent::ShardLayoutChangeDetected {
+                            block_hash,
+                            old_version,
+                            new_version,
+                        });
+                        let moved_transactions = self
+                            .sharded_tx_pool
+                            .reshard(&diff.old_shard_layout, &diff.new_shard_layout)
+                            .into_iter()
+                            .map(|(shard_uid, count)| (shard_uid.shard_id(), count))
+                            .collect();
+                        self.resharding_log.record(ReshardingEvent::PoolResharded {
+                            new_version,
+                            moved_transactions,
+                        });
                     }
-                    (old_shard_layout, new_shard_layout) => {
-                        tracing::warn!(target: "client", ?old_shard_layout, ?new_shard_layout, "failed to check if shard layout is changing");
+                    Ok(None) => {}
+                    Err(err) => {
+                        tracing::warn!(target: "client", ?err, "failed to check if shard layout is changing");
+                        self.resharding_log.record(ReshardingEvent::ShardLayoutLookupFailed {
+                            block_hash,
+                            error: err.to_string(),
+                        });
                     }
                 }
             }
+            if let Some(validator_signer) = self.validator_signer.clone() {
+                self.warn_if_projected_kickout(validator_signer.validator_id(), &block);
+                self.check_slashed(validator_signer.validator_id(), &block);
+            }
+            self.update_health(&block);
         }
 
         if let Some(validator_signer) = self.validator_signer.clone() {
             let validator_id = validator_signer.validator_id().clone();
 
+            #[cfg(feature = "protocol_feature_chunk_validation")]
+            if provenance != Provenance::SYNC && !self.sync_status.is_syncing() {
+                if let Err(err) = self.send_chunk_endorsements(&block, validator_signer.as_ref()) {
+                    warn!(target: "client", ?err, block_hash = ?block.hash(), "Failed to send chunk endorsements");
+                }
+            }
+
             if !self.reconcile_transaction_pool(validator_id.clone(), status, &block) {
                 return;
             }
@@ -1617,8 +3244,108 @@ impl Client {
             }
         }
 
-        self.shards_manager_adapter
-            .send(ShardsManagerRequestFromClient::CheckIncompleteChunks(*block.hash()));
+        self.shards_manager_adapter
+            .send(ShardsManagerRequestFromClient::CheckIncompleteChunks(*block.hash()));
+    }
+
+    /// Endorses the new chunks of `block` of the shards this node was sampled to validate at the
+    /// height of the block, and sends the endorsements to the producer of the next block. Until
+    /// the state witnesses are distributed, a chunk is endorsed once the block including it is
+    /// accepted.
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    fn send_chunk_endorsements(
+        &mut self,
+        block: &Block,
+        validator_signer: &dyn ValidatorSigner,
+    ) -> Result<(), Error> {
+        let height = block.header().height();
+        let chunk_validators =
+            self.epoch_manager.sample_chunk_validators(block.header().epoch_id(), height)?;
+        let next_epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(block.hash())?;
+        let block_producer = self.epoch_manager.get_block_producer(&next_epoch_id, height + 1)?;
+        for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
+            let is_chunk_validator = chunk_validators.get(shard_id).map_or(false, |validators| {
+                validators.contains_key(validator_signer.validator_id())
+            });
+            if chunk_header.height_included() != height || !is_chunk_validator {
+                continue;
+            }
+            let inner = ChunkEndorsementInner {
+                chunk_hash: chunk_header.chunk_hash(),
+                height,
+                shard_id: shard_id as ShardId,
+            };
+            let endorsement = ChunkEndorsement::new(inner, validator_signer);
+            if &block_producer == validator_signer.validator_id() {
+                self.process_chunk_endorsement(endorsement);
+            } else {
+                self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
+                    NetworkRequests::ChunkEndorsement(block_producer.clone(), endorsement),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects the endorsement of a chunk of a block on the canonical chain, if it's signed by
+    /// one of the chunk validators sampled for the shard at the height of the block. Endorsements
+    /// of the chunks of blocks not processed yet are dropped.
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    pub fn process_chunk_endorsement(&mut self, endorsement: ChunkEndorsement) {
+        if let Err(reason) = self.check_chunk_endorsement(&endorsement) {
+            debug!(target: "client", ?endorsement, reason, "Dropping chunk endorsement");
+            return;
+        }
+        self.chunk_endorsements
+            .get_or_insert(endorsement.inner.chunk_hash.clone(), HashMap::new)
+            .insert(endorsement.account_id.clone(), endorsement);
+    }
+
+    /// Returns why `endorsement` is not valid, if it isn't.
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    fn check_chunk_endorsement(&self, endorsement: &ChunkEndorsement) -> Result<(), String> {
+        let ChunkEndorsementInner { chunk_hash, height, shard_id } = &endorsement.inner;
+        let block = self
+            .chain
+            .get_block_by_height(*height)
+            .map_err(|err| format!("No block at height {}: {}", height, err))?;
+        let chunk_header = block.chunks().get(*shard_id as usize).cloned();
+        if chunk_header.map_or(true, |header| {
+            &header.chunk_hash() != chunk_hash || header.height_included() != *height
+        }) {
+            return Err(format!("Chunk is not a new chunk of block {}", block.hash()));
+        }
+        let epoch_id = block.header().epoch_id();
+        let chunk_validators = self
+            .epoch_manager
+            .sample_chunk_validators(epoch_id, *height)
+            .map_err(|err| err.to_string())?;
+        if !chunk_validators
+            .get(*shard_id as usize)
+            .map_or(false, |validators| validators.contains_key(&endorsement.account_id))
+        {
+            return Err(format!(
+                "{} is not a chunk validator of the shard",
+                endorsement.account_id
+            ));
+        }
+        let (validator, _) = self
+            .epoch_manager
+            .get_validator_by_account_id(epoch_id, block.hash(), &endorsement.account_id)
+            .map_err(|err| err.to_string())?;
+        if !endorsement.verify(validator.public_key()) {
+            return Err("Invalid signature".to_string());
+        }
+        Ok(())
+    }
+
+    /// Endorsements received for the chunk, by chunk validator.
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    pub fn chunk_endorsements(
+        &self,
+        chunk_hash: &ChunkHash,
+    ) -> Option<&HashMap<AccountId, ChunkEndorsement>> {
+        self.chunk_endorsements.peek(chunk_hash)
     }
 
     /// Reconcile the transaction pool after processing a block.
@@ -1669,13 +3396,11 @@ impl Client {
                     }
                 }
 
-                for to_reintroduce_hash in to_reintroduce {
-                    if let Ok(block) = self.chain.get_block(&to_reintroduce_hash) {
-                        let block = block.clone();
-                        self.reintroduce_transactions_for_block(validator_id.clone(), &block)
-                            .unwrap_or_default();
-                    }
-                }
+                self.reintroduce_transactions_for_blocks(
+                    validator_id.clone(),
+                    &to_reintroduce,
+                    block.header(),
+                );
 
                 for to_remove_hash in to_remove {
                     if let Ok(block) = self.chain.get_block(&to_remove_hash) {
@@ -1689,21 +3414,70 @@ impl Client {
         true
     }
 
+    /// Returns the shards this node may produce chunks for in `epoch_id`. The assignment is
+    /// resolved from the epoch's chunk producer settlement the first time it's needed and cached
+    /// for the rest of the epoch.
+    pub fn chunk_producer_shards(&mut self, epoch_id: &EpochId) -> Result<Vec<ShardId>, Error> {
+        if let Some(shards) = self.chunk_producer_assignments.get(epoch_id) {
+            return Ok(shards.clone());
+        }
+        let Some(validator_signer) = &self.validator_signer else {
+            return Ok(vec![]);
+        };
+        let account_id = validator_signer.validator_id();
+        let shards = self.epoch_manager.get_chunk_producer_shards(epoch_id, account_id)?;
+        if !shards.is_empty() {
+            info!(target: "client", ?account_id, ?epoch_id, "Chunk producer for shards {:?} in this epoch", shards);
+        }
+        self.chunk_producer_assignments.put(epoch_id.clone(), shards.clone());
+        Ok(shards)
+    }
+
     // Produce new chunks
-    fn produce_chunks(&mut self, block: &Block, validator_id: AccountId) {
+    pub(crate) fn produce_chunks(&mut self, block: &Block, validator_id: AccountId) {
         let _span = debug_span!(
             target: "client",
             "produce_chunks",
             ?validator_id,
             block_height = block.header().height())
         .entered();
-        let epoch_id =
-            self.epoch_manager.get_epoch_id_from_prev_block(block.header().hash()).unwrap();
-        for shard_id in self.epoch_manager.shard_ids(&epoch_id).unwrap() {
-            let next_height = block.header().height() + 1;
-            let epoch_manager = self.epoch_manager.as_ref();
-            let chunk_proposer =
-                epoch_manager.get_chunk_producer(&epoch_id, next_height, shard_id).unwrap();
+        // Epoch lookups may fail transiently, e.g. while the epoch manager catches up with the
+        // chain. Chunk production is then skipped, and attempted again on the next block.
+        let next_height = block.header().height() + 1;
+        let epoch_id = match self.epoch_manager.get_epoch_id_from_prev_block(block.hash()) {
+            Ok(epoch_id) => epoch_id,
+            Err(err) => {
+                error!(target: "client", ?err, prev_block_hash = ?block.hash(), next_height, "Failed to get the epoch of the next block, skipping chunk production");
+                let err = Error::from(err);
+                let severity: &'static str = err.severity().into();
+                metrics::PRODUCTION_ERRORS_TOTAL.with_label_values(&["chunk", severity]).inc();
+                return;
+            }
+        };
+        let assigned_shards = match self.chunk_producer_shards(&epoch_id) {
+            Ok(shards) => shards,
+            Err(err) => {
+                error!(target: "client", ?err, ?epoch_id, next_height, "Failed to get chunk producer assignment, skipping chunk production");
+                let severity: &'static str = err.severity().into();
+                metrics::PRODUCTION_ERRORS_TOTAL.with_label_values(&["chunk", severity]).inc();
+                return;
+            }
+        };
+        // We can't be the chunk producer for the shards we're not assigned to in this epoch.
+        for shard_id in assigned_shards {
+            let chunk_proposer = match self.epoch_manager.get_chunk_producer(
+                &epoch_id,
+                next_height,
+                shard_id,
+            ) {
+                Ok(chunk_proposer) => chunk_proposer,
+                Err(err) => {
+                    error!(target: "client", ?err, ?epoch_id, next_height, shard_id, "Failed to get the chunk producer, skipping chunk production");
+                    let err = Error::chunk_producer(shard_id, next_height, err);
+                    self.record_production_error(next_height, Some(shard_id), &err);
+                    continue;
+                }
+            };
             if &chunk_proposer != &validator_id {
                 continue;
             }
@@ -1717,9 +3491,28 @@ impl Client {
             let _timer = metrics::PRODUCE_AND_DISTRIBUTE_CHUNK_TIME
                 .with_label_values(&[&shard_id.to_string()])
                 .start_timer();
-            let last_header = Chain::get_prev_chunk_header(epoch_manager, block, shard_id).unwrap();
-            match self.produce_chunk(*block.hash(), &epoch_id, last_header, next_height, shard_id) {
+            let last_header = match Chain::get_prev_chunk_header(
+                self.epoch_manager.as_ref(),
+                block,
+                shard_id,
+            ) {
+                Ok(last_header) => last_header,
+                Err(err) => {
+                    error!(target: "client", ?err, prev_block_hash = ?block.hash(), next_height, shard_id, "Failed to get the previous chunk header, skipping chunk production");
+                    let err = Error::chunk_producer(shard_id, next_height, err);
+                    self.record_production_error(next_height, Some(shard_id), &err);
+                    continue;
+                }
+            };
+            match self.produce_chunk(
+                *block.hash(),
+                &epoch_id,
+                last_header.clone(),
+                next_height,
+                shard_id,
+            ) {
                 Ok(Some((encoded_chunk, merkle_paths, receipts))) => {
+                    self.production_alerts.remove(&Some(shard_id));
                     self.persist_and_distribute_encoded_chunk(
                         encoded_chunk,
                         merkle_paths,
@@ -1731,11 +3524,56 @@ impl Client {
                 Ok(None) => {}
                 Err(err) => {
                     error!(target: "client", ?err, "Error producing chunk");
+                    self.record_production_error(next_height, Some(shard_id), &err);
+                    if self.config.produce_empty_chunk_on_failure
+                        && err.severity() == ErrorSeverity::Retry
+                    {
+                        self.substitute_empty_chunk(
+                            block,
+                            &epoch_id,
+                            &last_header,
+                            next_height,
+                            shard_id,
+                            validator_id.clone(),
+                        );
+                    }
                 }
             }
         }
     }
 
+    fn substitute_empty_chunk(
+        &mut self,
+        block: &Block,
+        epoch_id: &EpochId,
+        last_header: &ShardChunkHeader,
+        next_height: BlockHeight,
+        shard_id: ShardId,
+        validator_id: AccountId,
+    ) {
+        let result =
+            self.produce_empty_chunk(*block.hash(), epoch_id, last_header, next_height, shard_id);
+        match result {
+            Ok(Some((encoded_chunk, merkle_paths, receipts))) => {
+                warn!(target: "client", shard_id, next_height, chunk_hash = ?encoded_chunk.chunk_hash(), "Produced an empty chunk in place of the failed one");
+                metrics::EMPTY_CHUNKS_SUBSTITUTED_TOTAL
+                    .with_label_values(&[&shard_id.to_string()])
+                    .inc();
+                self.persist_and_distribute_encoded_chunk(
+                    encoded_chunk,
+                    merkle_paths,
+                    receipts,
+                    validator_id,
+                )
+                .expect("Failed to process produced chunk");
+            }
+            Ok(None) => {}
+            Err(err) => {
+                error!(target: "client", ?err, shard_id, next_height, "Failed to produce an empty chunk in place of the failed one");
+            }
+        }
+    }
+
     pub fn persist_and_distribute_encoded_chunk(
         &mut self,
         encoded_chunk: EncodedShardChunk,
@@ -1750,7 +3588,14 @@ impl Client {
             self.epoch_manager.as_ref(),
             &self.shard_tracker,
         )?;
-        persist_chunk(partial_chunk.clone(), Some(shard_chunk), self.chain.mut_store())?;
+        let write = StoreWrite::Chunk {
+            chunk_hash: partial_chunk.chunk_hash(),
+            shard_id: partial_chunk.shard_id(),
+            height_created: partial_chunk.height_created(),
+        };
+        self.store_audit_log.audited(write, || {
+            persist_chunk(partial_chunk.clone(), Some(shard_chunk), self.chain.mut_store())
+        })?;
         self.on_chunk_header_ready_for_inclusion(encoded_chunk.cloned_header(), validator_id);
         self.shards_manager_adapter.send(ShardsManagerRequestFromClient::DistributeEncodedChunk {
             partial_chunk,
@@ -1887,6 +3732,12 @@ impl Client {
     pub fn collect_block_approval(&mut self, approval: &Approval, approval_type: ApprovalType) {
         let Approval { inner, account_id, target_height, signature } = approval;
 
+        if self.is_below_gc_tail(approval_parent_height(inner, *target_height)) {
+            debug!(target: "client", ?approval, "Dropping an approval building on a garbage collected block");
+            metrics::DROPPED_BELOW_GC_TAIL_TOTAL.with_label_values(&["approval"]).inc();
+            return;
+        }
+
         let parent_hash = match inner {
             ApprovalInner::Endorsement(parent_hash) => *parent_hash,
             ApprovalInner::Skip(parent_height) => {
@@ -1999,6 +3850,36 @@ impl Client {
         self.doomslug.on_approval_message(StaticClock::instant(), approval, &block_producer_stakes);
     }
 
+    /// Returns the chunk producer of the shard `horizon` heights ahead of the head to forward a
+    /// transaction to. A banned producer wouldn't get its chunks included, so it's replaced by
+    /// the first producer of the following heights which isn't banned, if there is one within
+    /// the routing horizon.
+    fn find_unbanned_chunk_producer_for_forwarding(
+        &self,
+        epoch_id: &EpochId,
+        shard_id: ShardId,
+        horizon: BlockHeight,
+    ) -> Result<AccountId, Error> {
+        let chunk_producer =
+            self.chain.find_chunk_producer_for_forwarding(epoch_id, shard_id, horizon)?;
+        if !self.is_chunk_producer_banned(epoch_id, &chunk_producer) {
+            return Ok(chunk_producer);
+        }
+        for next_horizon in horizon + 1..=horizon + TX_ROUTING_HEIGHT_HORIZON {
+            let next_chunk_producer =
+                self.chain.find_chunk_producer_for_forwarding(epoch_id, shard_id, next_horizon)?;
+            if !self.is_chunk_producer_banned(epoch_id, &next_chunk_producer) {
+                debug!(target: "client", ?chunk_producer, ?next_chunk_producer, shard_id, "Forwarding a transaction past a banned chunk producer");
+                metrics::TRANSACTION_FORWARD_REROUTED
+                    .with_label_values(&[&shard_id.to_string()])
+                    .inc();
+                return Ok(next_chunk_producer);
+            }
+        }
+        // Forwarding to the banned producer still beats dropping the transaction.
+        Ok(chunk_producer)
+    }
+
     /// Forwards given transaction to upcoming validators.
     fn forward_tx(&self, epoch_id: &EpochId, tx: &SignedTransaction) -> Result<(), Error> {
         let shard_id =
@@ -2011,13 +3892,13 @@ impl Client {
             (2..=TX_ROUTING_HEIGHT_HORIZON).chain(vec![TX_ROUTING_HEIGHT_HORIZON * 2].into_iter())
         {
             let validator =
-                self.chain.find_chunk_producer_for_forwarding(epoch_id, shard_id, horizon)?;
+                self.find_unbanned_chunk_producer_for_forwarding(epoch_id, shard_id, horizon)?;
             validators.insert(validator);
             if let Some(next_epoch_id) = &maybe_next_epoch_id {
                 let next_shard_id = self
                     .epoch_manager
                     .account_id_to_shard_id(&tx.transaction.signer_id, next_epoch_id)?;
-                let validator = self.chain.find_chunk_producer_for_forwarding(
+                let validator = self.find_unbanned_chunk_producer_for_forwarding(
                     next_epoch_id,
                     next_shard_id,
                     horizon,
@@ -2059,16 +3940,24 @@ impl Client {
         })
     }
 
-    /// If we are close to epoch boundary, return next epoch id, otherwise return None.
-    fn get_next_epoch_id_if_at_boundary(&self, head: &Tip) -> Result<Option<EpochId>, Error> {
-        let next_epoch_started =
-            self.epoch_manager.is_next_block_epoch_start(&head.last_block_hash)?;
-        if next_epoch_started {
-            return Ok(None);
+    /// If one of the next few blocks may belong to the epoch following the epoch of `head`, returns
+    /// the id of that epoch, otherwise returns None.
+    pub(crate) fn get_next_epoch_id_if_at_boundary(
+        &self,
+        head: &Tip,
+    ) -> Result<Option<EpochId>, Error> {
+        if self.epoch_manager.is_next_block_epoch_start(&head.last_block_hash)? {
+            // The next block is the first block of the next epoch, so its chunks are produced by
+            // the chunk producers of that epoch already.
+            return Ok(Some(
+                self.epoch_manager.get_epoch_id_from_prev_block(&head.last_block_hash)?,
+            ));
         }
+        // The next epoch starts at this height at the earliest, later if the blocks before it
+        // aren't final in time. The length of the epoch may differ from the genesis one.
+        let epoch_length = self.epoch_manager.get_epoch_config(&head.epoch_id)?.epoch_length;
         let next_epoch_estimated_height =
-            self.epoch_manager.get_epoch_start_height(&head.last_block_hash)?
-                + self.config.epoch_length;
+            self.epoch_manager.get_epoch_start_height(&head.last_block_hash)? + epoch_length;
 
         let epoch_boundary_possible =
             head.height + TX_ROUTING_HEIGHT_HORIZON >= next_epoch_estimated_height;
@@ -2113,17 +4002,35 @@ impl Client {
             debug!(target: "client", ?tx, "Invalid tx: expired or from a different fork");
             return Ok(ProcessTxResponse::InvalidTx(e));
         }
+        if let Err(e) = check_delegate_actions_validity(
+            self.chain.store(),
+            tx,
+            self.config.delegate_action_validity,
+            cur_block_header.height() + 1,
+            transaction_validity_period,
+        ) {
+            debug!(target: "client", ?tx, "Invalid tx: expired delegate action");
+            return Ok(ProcessTxResponse::InvalidTx(e));
+        }
         let gas_price = cur_block_header.next_gas_price();
         let epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(&head.last_block_hash)?;
 
         let protocol_version = self.epoch_manager.get_epoch_protocol_version(&epoch_id)?;
 
-        if let Some(err) = self
-            .runtime_adapter
-            .validate_tx(gas_price, None, tx, true, &epoch_id, protocol_version)
-            .expect("no storage errors")
-        {
-            debug!(target: "client", tx_hash = ?tx.get_hash(), ?err, "Invalid tx during basic validation");
+        let tx_hash = tx.get_hash();
+        let basic_verdict = match self.verified_tx_cache.basic_verdict(tx) {
+            Some(verdict) => verdict,
+            None => {
+                let verdict = self
+                    .runtime_adapter
+                    .validate_tx(gas_price, None, tx, true, &epoch_id, protocol_version)
+                    .expect("no storage errors");
+                self.verified_tx_cache.record_basic(tx, verdict.clone());
+                verdict
+            }
+        };
+        if let Some(err) = basic_verdict {
+            debug!(target: "client", ?tx_hash, ?err, "Invalid tx during basic validation");
             return Ok(ProcessTxResponse::InvalidTx(err));
         }
 
@@ -2142,35 +4049,85 @@ impl Client {
                 Err(_) => {
                     // Not being able to fetch a state root most likely implies that we haven't
                     //     caught up with the next epoch yet.
+                    if !check_only && !self.try_take_tx_budget(tx, is_forwarded) {
+                        return Ok(ProcessTxResponse::Throttled);
+                    }
                     if is_forwarded {
-                        return Err(Error::Other("Node has not caught up yet".to_string()));
+                        return Ok(self.buffer_not_caught_up_tx(&epoch_id, shard_id, tx));
                     } else {
                         self.forward_tx(&epoch_id, tx)?;
                         return Ok(ProcessTxResponse::RequestRouted);
                     }
                 }
             };
-            if let Some(err) = self
-                .runtime_adapter
-                .validate_tx(gas_price, Some(state_root), tx, false, &epoch_id, protocol_version)
-                .expect("no storage errors")
-            {
+            let context = StatefulValidationContext {
+                state_root,
+                gas_price,
+                epoch_id: epoch_id.clone(),
+                protocol_version,
+            };
+            let stateful_verdict = match self.verified_tx_cache.stateful_verdict(tx, &context) {
+                Some(verdict) => verdict,
+                None => {
+                    let verdict = self
+                        .runtime_adapter
+                        .validate_tx(
+                            gas_price,
+                            Some(state_root),
+                            tx,
+                            false,
+                            &epoch_id,
+                            protocol_version,
+                        )
+                        .expect("no storage errors");
+                    self.verified_tx_cache.record_stateful(tx, context, verdict.clone());
+                    verdict
+                }
+            };
+            if let Some(err) = stateful_verdict {
                 debug!(target: "client", ?err, "Invalid tx");
                 Ok(ProcessTxResponse::InvalidTx(err))
+            } else if let Err(reason) = check_tx_admission(
+                &*self.tx_admission_policy,
+                tx,
+                &TxAdmissionContext {
+                    stage: TxAdmissionStage::Pool,
+                    shard_id,
+                    epoch_id: &epoch_id,
+                    height: head.height + 1,
+                },
+            ) {
+                Ok(ProcessTxResponse::RejectedByPolicy(reason))
             } else if check_only {
                 Ok(ProcessTxResponse::ValidTx)
+            } else if !self.try_take_tx_budget(tx, is_forwarded) {
+                Ok(ProcessTxResponse::Throttled)
             } else {
                 // Transactions only need to be recorded if the node is a validator.
                 if me.is_some() {
-                    match self.sharded_tx_pool.insert_transaction(shard_uid, tx.clone()) {
+                    let lane = TxLane::new(is_forwarded);
+                    let reserved_size = self
+                        .tx_lanes
+                        .reserved_pool_size(lane, self.sharded_tx_pool.pool_size_limit());
+                    match self.sharded_tx_pool.insert_transaction_with_reserve(
+                        shard_uid,
+                        tx.clone(),
+                        reserved_size,
+                    ) {
                         InsertTransactionResult::Success => {
                             trace!(target: "client", ?shard_uid, tx_hash = ?tx.get_hash(), "Recorded a transaction.");
+                            metrics::TRANSACTION_LANE_ADMISSION
+                                .with_label_values(&[lane.as_str(), "accepted"])
+                                .inc();
                         }
                         InsertTransactionResult::Duplicate => {
                             trace!(target: "client", ?shard_uid, tx_hash = ?tx.get_hash(), "Duplicate transaction, not forwarding it.");
                             return Ok(ProcessTxResponse::ValidTx);
                         }
                         InsertTransactionResult::NoSpaceLeft => {
+                            metrics::TRANSACTION_LANE_ADMISSION
+                                .with_label_values(&[lane.as_str(), "pool_full"])
+                                .inc();
                             if is_forwarded {
                                 trace!(target: "client", ?shard_uid, tx_hash = ?tx.get_hash(), "Transaction pool is full, dropping the transaction.");
                             } else {
@@ -2210,6 +4167,8 @@ impl Client {
             // Received forwarded transaction but we are not tracking the shard
             debug!(target: "client", ?me, shard_id, tx_hash = ?tx.get_hash(), "Received forwarded transaction but no tracking shard");
             Ok(ProcessTxResponse::NoResponse)
+        } else if !self.try_take_tx_budget(tx, is_forwarded) {
+            Ok(ProcessTxResponse::Throttled)
         } else {
             // We are not tracking this shard, so there is no way to validate this tx. Just rerouting.
             self.forward_tx(&epoch_id, tx)?;
@@ -2217,6 +4176,81 @@ impl Client {
         }
     }
 
+    /// Takes a transaction from the rate budget of its lane. Only called for the transactions
+    /// which passed the validation this node can do and are submitted, not just checked, so that
+    /// invalid transactions and queries don't use up the budget. Returns false if the budget is
+    /// exhausted.
+    fn try_take_tx_budget(&mut self, tx: &SignedTransaction, is_forwarded: bool) -> bool {
+        let lane = TxLane::new(is_forwarded);
+        if self.tx_lanes.try_take_budget(lane) {
+            return true;
+        }
+        trace!(target: "client", lane = lane.as_str(), tx_hash = ?tx.get_hash(), "Transaction rate budget exhausted, dropping the transaction.");
+        metrics::TRANSACTION_LANE_ADMISSION
+            .with_label_values(&[lane.as_str(), "rate_limited"])
+            .inc();
+        false
+    }
+
+    /// Keeps a forwarded transaction received before the node caught up with its shard, unless
+    /// the buffer of the shard is full.
+    fn buffer_not_caught_up_tx(
+        &mut self,
+        epoch_id: &EpochId,
+        shard_id: ShardId,
+        tx: &SignedTransaction,
+    ) -> ProcessTxResponse {
+        if self
+            .not_caught_up_txs
+            .get(&shard_id)
+            .map_or(false, |(buffer_epoch_id, _)| buffer_epoch_id != epoch_id)
+        {
+            self.drop_not_caught_up_txs(shard_id);
+        }
+        let (_, buffer) = self
+            .not_caught_up_txs
+            .entry(shard_id)
+            .or_insert_with(|| (epoch_id.clone(), VecDeque::new()));
+        let status = if buffer.len() < self.config.not_caught_up_tx_buffer_size {
+            buffer.push_back(tx.clone());
+            "buffered"
+        } else {
+            "dropped"
+        };
+        debug!(target: "client", shard_id, tx_hash = ?tx.get_hash(), status, "Received forwarded transaction but node has not caught up yet");
+        metrics::TRANSACTION_NOT_CAUGHT_UP
+            .with_label_values(&[&shard_id.to_string(), status])
+            .inc();
+        ProcessTxResponse::NotCaughtUp
+    }
+
+    /// Processes again the forwarded transactions buffered until the catchup of the given shards.
+    fn process_not_caught_up_txs(&mut self, shard_ids: &[ShardId]) {
+        for shard_id in shard_ids {
+            let Some((_, txs)) = self.not_caught_up_txs.remove(shard_id) else {
+                continue;
+            };
+            metrics::TRANSACTION_NOT_CAUGHT_UP
+                .with_label_values(&[&shard_id.to_string(), "reprocessed"])
+                .inc_by(txs.len() as u64);
+            for tx in txs {
+                let response = self.process_tx(tx, true, false);
+                trace!(target: "client", shard_id, ?response, "Processed transaction received before catchup");
+            }
+        }
+    }
+
+    /// Drops the forwarded transactions buffered until the catchup of the shard.
+    fn drop_not_caught_up_txs(&mut self, shard_id: ShardId) {
+        let Some((epoch_id, txs)) = self.not_caught_up_txs.remove(&shard_id) else {
+            return;
+        };
+        debug!(target: "client", shard_id, ?epoch_id, num_txs = txs.len(), "Dropping transactions received before catchup");
+        metrics::TRANSACTION_NOT_CAUGHT_UP
+            .with_label_values(&[&shard_id.to_string(), "dropped"])
+            .inc_by(txs.len() as u64);
+    }
+
     /// Determine if I am a validator in next few blocks for specified shard, assuming epoch doesn't change.
     fn active_validator(&self, shard_id: ShardId) -> Result<bool, Error> {
         let head = self.chain.head()?;
@@ -2238,6 +4272,30 @@ impl Client {
         Ok(false)
     }
 
+    /// Hands the results of applying the chunks of a block, scheduled by `run_catchup`, to the
+    /// catchup of the epoch.
+    pub fn on_block_catch_up_done(&mut self, response: BlockCatchUpResponse) {
+        if let Some((_, _, blocks_catch_up_state)) =
+            self.catchup_state_syncs.get_mut(&response.sync_hash)
+        {
+            assert!(blocks_catch_up_state.scheduled_blocks.remove(&response.block_hash));
+            blocks_catch_up_state.processed_blocks.insert(response.block_hash, response.results);
+        } else {
+            panic!("block catch up processing result from unknown sync hash");
+        }
+    }
+
+    /// Hands the state roots of the split shards, built as scheduled by `run_catchup` or by the
+    /// state sync, to the sync which scheduled them.
+    pub fn on_state_split_done(&mut self, response: StateSplitResponse) {
+        if let Some((sync, _, _)) = self.catchup_state_syncs.get_mut(&response.sync_hash) {
+            // We are doing catchup
+            sync.set_split_result(response.shard_id, response.new_state_roots);
+        } else {
+            self.state_sync.set_split_result(response.shard_id, response.new_state_roots);
+        }
+    }
+
     /// Walks through all the ongoing state syncs for future epochs and processes them
     pub fn run_catchup(
         &mut self,
@@ -2246,18 +4304,23 @@ impl Client {
         block_catch_up_task_scheduler: &dyn Fn(BlockCatchUpRequest),
         state_split_scheduler: &dyn Fn(StateSplitRequest),
         apply_chunks_done_callback: DoneApplyChunkCallback,
-        state_parts_arbiter_handle: &ArbiterHandle,
+        state_parts_future_spawner: &dyn FutureSpawner,
     ) -> Result<(), Error> {
         let _span = debug_span!(target: "sync", "run_catchup").entered();
         let mut notify_state_sync = false;
         let me = &self.validator_signer.as_ref().map(|x| x.validator_id().clone());
+        // Drops the stale catchups along with their StateSyncInfo, so all the remaining ones are
+        // still to be finished.
+        self.sweep_catchup_state_syncs()?;
         for (sync_hash, state_sync_info) in self.chain.store().iterate_state_sync_infos()? {
             assert_eq!(sync_hash, state_sync_info.epoch_tail_hash);
+            let epoch_id = self.chain.get_block(&sync_hash)?.header().epoch_id().clone();
             let network_adapter = self.network_adapter.clone();
 
             let shards_to_split = self.get_shards_to_split(sync_hash, &state_sync_info, me)?;
             let state_sync_timeout = self.config.state_sync_timeout;
-            let epoch_id = self.chain.get_block(&sync_hash)?.header().epoch_id().clone();
+            let sync_debug_log = self.sync_debug_log.clone();
+            let resharding_log = self.resharding_log.clone();
 
             let (state_sync, shards_to_split, blocks_catch_up_state) =
                 self.catchup_state_syncs.entry(sync_hash).or_insert_with(|| {
@@ -2270,11 +4333,14 @@ impl Client {
                             &self.config.chain_id,
                             &self.config.state_sync.sync,
                             true,
+                            sync_debug_log,
+                            resharding_log,
                         ),
                         shards_to_split,
                         BlocksCatchUpState::new(sync_hash, epoch_id.clone()),
                     )
                 });
+            metrics::CATCHUP_STATE_SYNCS.set(self.catchup_state_syncs.len() as i64);
 
             // For colour decorators to work, they need to printed directly. Otherwise the decorators get escaped, garble output and don't add colours.
             debug!(target: "catchup", ?me, ?sync_hash, progress_per_shard = ?format_shard_sync_phase_per_shard(&shards_to_split, false), "Catchup");
@@ -2282,6 +4348,7 @@ impl Client {
 
             let tracking_shards: Vec<u64> =
                 state_sync_info.shards.iter().map(|tuple| tuple.0).collect();
+            let caught_up_shards = tracking_shards.clone();
             // Notify each shard to sync.
             if notify_state_sync {
                 let shard_layout = self
@@ -2317,7 +4384,7 @@ impl Client {
                 tracking_shards,
                 state_parts_task_scheduler,
                 state_split_scheduler,
-                state_parts_arbiter_handle,
+                state_parts_future_spawner,
                 use_colour,
                 self.runtime_adapter.clone(),
             )? {
@@ -2347,8 +4414,11 @@ impl Client {
                             apply_chunks_done_callback.clone(),
                             &blocks_catch_up_state.done_blocks,
                         )?;
+                        self.catchup_state_syncs.remove(&sync_hash);
+                        metrics::CATCHUP_STATE_SYNCS.set(self.catchup_state_syncs.len() as i64);
 
                         self.process_block_processing_artifact(block_processing_artifacts);
+                        self.process_not_caught_up_txs(&caught_up_shards);
                     }
                 }
             }
@@ -2357,6 +4427,79 @@ impl Client {
         Ok(())
     }
 
+    /// Drops the catchup state syncs which are never going to finish: the ones whose
+    /// `StateSyncInfo` is no longer in the store, e.g. because of a reorg, and the ones for epochs
+    /// more than `catchup_state_sync_max_age_epochs` behind the head. The `StateSyncInfo` of the
+    /// latter is deleted from the store, so that they are not started again.
+    pub fn sweep_catchup_state_syncs(&mut self) -> Result<(), Error> {
+        let head = self.chain.head()?;
+        let head_epoch_height = self.epoch_manager.get_epoch_info(&head.epoch_id)?.epoch_height();
+        let mut pending_sync_hashes = HashSet::new();
+        let mut stale_sync_hashes = HashSet::new();
+        let mut stale_shards = HashSet::new();
+        for (sync_hash, state_sync_info) in self.chain.store().iterate_state_sync_infos()? {
+            let is_stale = match self.chain.get_block_header(&sync_hash) {
+                Ok(header) => self.is_catchup_stale(header.epoch_id(), head_epoch_height)?,
+                // The block is only missing if it was garbage collected.
+                Err(near_chain::Error::DBNotFoundErr(_)) => true,
+                Err(err) => return Err(err.into()),
+            };
+            if is_stale {
+                stale_sync_hashes.insert(sync_hash);
+                stale_shards.extend(state_sync_info.shards.iter().map(|shard| shard.0));
+            } else {
+                pending_sync_hashes.insert(sync_hash);
+            }
+        }
+        stale_sync_hashes.extend(
+            self.catchup_state_syncs
+                .keys()
+                .filter(|sync_hash| !pending_sync_hashes.contains(sync_hash)),
+        );
+        if !stale_sync_hashes.is_empty() {
+            let mut store_update = self.chain.mut_store().store_update();
+            for sync_hash in &stale_sync_hashes {
+                let is_running = self.catchup_state_syncs.remove(sync_hash).is_some();
+                warn!(target: "catchup", ?sync_hash, is_running, head_epoch_height, "Dropping stale catchup state sync");
+                store_update.remove_state_sync_info(*sync_hash);
+                metrics::CATCHUP_STATE_SYNCS_STALE.inc();
+            }
+            store_update.commit()?;
+        }
+        metrics::CATCHUP_STATE_SYNCS.set(self.catchup_state_syncs.len() as i64);
+
+        // The transactions buffered for the shards of the dropped catchups, or in an epoch which
+        // ended, are never going to be processed.
+        let epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(&head.last_block_hash)?;
+        let outdated_shards: Vec<_> = self
+            .not_caught_up_txs
+            .iter()
+            .filter(|(shard_id, (buffer_epoch_id, _))| {
+                buffer_epoch_id != &epoch_id || stale_shards.contains(*shard_id)
+            })
+            .map(|(shard_id, _)| *shard_id)
+            .collect();
+        for shard_id in outdated_shards {
+            self.drop_not_caught_up_txs(shard_id);
+        }
+        Ok(())
+    }
+
+    /// Whether the catchup for the epoch following `epoch_id` is too old to be continued.
+    fn is_catchup_stale(
+        &self,
+        epoch_id: &EpochId,
+        head_epoch_height: EpochHeight,
+    ) -> Result<bool, Error> {
+        match self.epoch_manager.get_epoch_info(epoch_id) {
+            Ok(epoch_info) => Ok(head_epoch_height.saturating_sub(epoch_info.epoch_height())
+                > self.config.catchup_state_sync_max_age_epochs),
+            // The epoch info is only missing for epochs which were garbage collected.
+            Err(EpochError::EpochOutOfBounds(_)) => Ok(true),
+            Err(err) => Err(err.into()),
+        }
+    }
+
     /// This method checks which of the shards requested for state sync are already present.
     /// Any shard that is currently tracked needs not to be downloaded again.
     ///
@@ -2447,27 +4590,66 @@ impl Client {
         Ok(result)
     }
 
-    fn clear_data(&mut self) -> Result<(), near_chain::Error> {
-        // A RPC node should do regular garbage collection.
+    pub fn gc_mode(&self) -> GcMode {
+        self.gc_mode
+    }
+
+    /// Switches the garbage collection mode if the kind of the store changed since it was last
+    /// checked, i.e. if the migration of this archival node to split storage finished.
+    pub fn update_gc_mode(&mut self) -> Result<(), near_chain::Error> {
         if !self.config.archive {
-            let tries = self.runtime_adapter.get_tries();
-            return self.chain.clear_data(tries, &self.config.gc);
+            return Ok(());
         }
-
-        // An archival node with split storage should perform garbage collection
-        // on the hot storage. In order to determine if split storage is enabled
-        // *and* that the migration to split storage is finished we can check
-        // the store kind. It's only set to hot after the migration is finished.
-        let store = self.chain.store().store();
-        let kind = store.get_db_kind()?;
-        if kind == Some(DbKind::Hot) {
-            let tries = self.runtime_adapter.get_tries();
-            return self.chain.clear_data(tries, &self.config.gc);
+        let db_kind = self.chain.store().store().get_db_kind()?;
+        let gc_mode = GcMode::new(self.config.archive, db_kind);
+        if gc_mode != self.gc_mode {
+            info!(target: "client", ?db_kind, from = ?self.gc_mode, to = ?gc_mode, "Store kind changed, switching garbage collection mode");
+            self.gc_mode = gc_mode;
         }
+        Ok(())
+    }
 
-        // An archival node with legacy storage or in the midst of migration to split
-        // storage should do the legacy clear_archive_data.
-        self.chain.clear_archive_data(self.config.gc.gc_blocks_limit)
+    fn clear_data(&mut self) -> Result<(), near_chain::Error> {
+        self.update_gc_mode()?;
+        let result = match self.gc_mode {
+            GcMode::Regular | GcMode::SplitStorageHot => {
+                let tries = self.runtime_adapter.get_tries();
+                self.chain.clear_data(tries, &self.config.gc)
+            }
+            GcMode::Archive => self.chain.clear_archive_data(self.config.gc.gc_blocks_limit),
+        };
+        self.purge_pending_approvals_below_gc_tail();
+        result
+    }
+
+    /// Whether the block at `height` is below the tail, i.e. was garbage collected.
+    fn is_below_gc_tail(&self, height: BlockHeight) -> bool {
+        self.chain.tail().map_or(false, |tail| height < tail)
+    }
+
+    /// Drops the pending approvals building on garbage collected blocks. These blocks will never
+    /// be processed, so the approvals would wait for them forever.
+    fn purge_pending_approvals_below_gc_tail(&mut self) {
+        let Ok(tail) = self.chain.tail() else {
+            return;
+        };
+        let stale: Vec<_> = self
+            .pending_approvals
+            .iter()
+            .filter(|(inner, approvals)| {
+                approvals.values().all(|(approval, _)| {
+                    approval_parent_height(inner, approval.target_height) < tail
+                })
+            })
+            .map(|(inner, _)| inner.clone())
+            .collect();
+        for inner in stale {
+            if let Some(approvals) = self.pending_approvals.pop(&inner) {
+                metrics::DROPPED_BELOW_GC_TAIL_TOTAL
+                    .with_label_values(&["pending_approval"])
+                    .inc_by(approvals.len() as u64);
+            }
+        }
     }
 }
 
@@ -2490,11 +4672,152 @@ impl Client {
         }
     }
 
-    pub fn ban_peer(&self, peer_id: PeerId, ban_reason: ReasonForBan) {
+    /// Bans the peer and records the ban in the ban history.
+    /// `triggered_by` is the hash of the block or chunk that caused the ban, if any.
+    pub fn ban_peer(
+        &mut self,
+        peer_id: PeerId,
+        ban_reason: ReasonForBan,
+        triggered_by: Option<CryptoHash>,
+    ) {
+        self.ban_history.record(peer_id.clone(), ban_reason, triggered_by);
         self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
             NetworkRequests::BanPeer { peer_id, ban_reason },
         ));
     }
+
+    /// Records a ban of `peer_id` in the ban history, for the bans the peer manager is asked for
+    /// by other means than `ban_peer`.
+    pub(crate) fn record_ban(
+        &self,
+        peer_id: PeerId,
+        ban_reason: ReasonForBan,
+        triggered_by: Option<CryptoHash>,
+    ) {
+        self.ban_history.record(peer_id, ban_reason, triggered_by);
+    }
+
+    /// Returns the block and chunk production report of this node for `epoch_id`, which must be
+    /// the current or the last finished epoch. The report is built when asked for, and reports of
+    /// finished epochs are cached.
+    pub fn epoch_production_report(
+        &mut self,
+        epoch_id: &EpochId,
+    ) -> Result<EpochProductionReport, Error> {
+        if let Some(report) = self.production_reports.get(epoch_id) {
+            return Ok(report.clone());
+        }
+        let head = self.chain.head()?;
+        if &head.epoch_id == epoch_id {
+            let job = self.production_report_job(epoch_id, &head.last_block_hash, false)?;
+            return job.build(self.chain.store(), self.epoch_manager.as_ref());
+        }
+        let last_block_hash = self.last_block_of_previous_epoch(&head.last_block_hash)?;
+        if &self.epoch_manager.get_epoch_id(&last_block_hash)? != epoch_id {
+            return Err(Error::ProductionReportUnavailable(epoch_id.clone()));
+        }
+        let job = self.production_report_job(epoch_id, &last_block_hash, true)?;
+        let report = job.build(self.chain.store(), self.epoch_manager.as_ref())?;
+        self.production_reports.put(epoch_id.clone(), report.clone());
+        Ok(report)
+    }
+
+    /// Returns the block and chunk production report of this node for the last finished epoch.
+    pub fn last_epoch_production_report(&mut self) -> Result<EpochProductionReport, Error> {
+        let head = self.chain.head()?;
+        let last_block_hash = self.last_block_of_previous_epoch(&head.last_block_hash)?;
+        let epoch_id = self.epoch_manager.get_epoch_id(&last_block_hash)?;
+        self.epoch_production_report(&epoch_id)
+    }
+
+    /// Builds the production report of the epoch ending with `last_block_hash` on a background
+    /// thread and writes it to `path`.
+    fn spawn_production_report_writer(
+        &mut self,
+        last_block_hash: &CryptoHash,
+        path: PathBuf,
+    ) -> Result<(), Error> {
+        if self.validator_signer.is_none() {
+            return Ok(());
+        }
+        let epoch_id = self.epoch_manager.get_epoch_id(last_block_hash)?;
+        let job = self.production_report_job(&epoch_id, last_block_hash, true)?;
+        let store = self.chain.store().store().clone();
+        let genesis_height = self.chain.genesis().height();
+        let save_trie_changes = self.config.save_trie_changes;
+        let epoch_manager = self.epoch_manager.clone();
+        let handle = std::thread::Builder::new()
+            .name("production_report".to_string())
+            .spawn(move || {
+                let chain_store = ChainStore::new(store, genesis_height, save_trie_changes);
+                let result = job.build(&chain_store, epoch_manager.as_ref()).and_then(|report| {
+                    let missed = report.entries.iter().filter(|entry| !entry.included).count();
+                    info!(target: "client", ?epoch_id, num_slots = report.entries.len(), missed, "Epoch production report");
+                    write_production_report(&path, &report)
+                });
+                if let Err(err) = result {
+                    warn!(target: "client", ?epoch_id, ?err, "Failed to write epoch production report");
+                }
+            })?;
+        self.production_report_writer = Some(handle);
+        Ok(())
+    }
+
+    fn last_block_of_previous_epoch(&self, block_hash: &CryptoHash) -> Result<CryptoHash, Error> {
+        let epoch_start_height = self.epoch_manager.get_epoch_start_height(block_hash)?;
+        Ok(*self.chain.get_block_header_by_height(epoch_start_height)?.prev_hash())
+    }
+
+    /// Copies what this node recorded about the heights of `epoch_id` up to the canonical block
+    /// `last_block_hash`, to build the production report of the epoch from.
+    fn production_report_job(
+        &self,
+        epoch_id: &EpochId,
+        last_block_hash: &CryptoHash,
+        is_final: bool,
+    ) -> Result<ProductionReportJob, Error> {
+        let last_height = self.chain.get_block_header(last_block_hash)?.height();
+        let validator = self
+            .validator_signer
+            .as_ref()
+            .ok_or_else(|| {
+                Error::block_producer(last_height, BlockProducerErrorKind::NoValidatorSigner)
+            })?
+            .validator_id()
+            .clone();
+        // Heights skipped right before the first block of the epoch belong to the epoch as well.
+        let epoch_start_height = self.epoch_manager.get_epoch_start_height(last_block_hash)?;
+        let first_block = self.chain.get_block_header_by_height(epoch_start_height)?;
+        let first_height = match self.chain.get_block_header(first_block.prev_hash()) {
+            Ok(prev_header) => prev_header.height() + 1,
+            Err(_) => first_block.height(),
+        };
+        let heights = first_height..=last_height;
+        Ok(ProductionReportJob {
+            epoch_id: epoch_id.clone(),
+            validator,
+            first_height,
+            last_height,
+            is_final,
+            blocks: self.block_production_info.records_in(&heights),
+            chunks: self.chunk_production_info.records_in(&heights),
+            skip_reasons: self.production_skip_reasons.records_in(&heights),
+        })
+    }
+
+    /// Returns peers banned by this node at or after `since` (all remembered bans if None),
+    /// oldest first.
+    pub fn ban_history(
+        &self,
+        since: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Vec<BanHistoryEntry> {
+        self.ban_history.get(since)
+    }
+
+    /// Returns the provenance of the last `last_n` blocks accepted by this node, oldest first.
+    pub fn block_provenance(&self, last_n: usize) -> Vec<BlockProvenanceRecord> {
+        self.block_provenance.last(last_n)
+    }
 }
 
 impl Client {
@@ -2537,16 +4860,19 @@ impl Client {
         // till almost the end of this epoch.
         let mut account_keys = AccountKeys::new();
         for epoch_id in [&tip.epoch_id, &tip.next_epoch_id] {
-            // We assume here that calls to get_epoch_chunk_producers and get_epoch_block_producers_ordered
+            // We assume here that calls to get_shard_validator_assignments and get_epoch_block_producers_ordered
             // are cheaper than block processing (and that they will work with both this and
             // the next epoch). The caching on top of that (in tier1_accounts_cache field) is just
             // a defence in depth, based on the previous experience with expensive
             // EpochManagerAdapter::get_validators_info call.
-            for cp in self.epoch_manager.get_epoch_chunk_producers(epoch_id)? {
-                account_keys
-                    .entry(cp.account_id().clone())
-                    .or_default()
-                    .insert(cp.public_key().clone());
+            let assignments = self.epoch_manager.get_shard_validator_assignments(epoch_id)?;
+            for (_, chunk_producers) in assignments.iter() {
+                for cp in chunk_producers {
+                    account_keys
+                        .entry(cp.account_id().clone())
+                        .or_default()
+                        .insert(cp.public_key().clone());
+                }
             }
             for (bp, _) in self
                 .epoch_manager
@@ -2608,7 +4934,7 @@ impl Client {
             self.catchup_state_syncs.iter()
         {
             let sync_block_height = self.chain.get_block_header(sync_hash)?.height();
-            let shard_sync_status: HashMap<_, _> = shard_sync_state
+            let shard_sync_status: BTreeMap<_, _> = shard_sync_state
                 .iter()
                 .map(|(shard_id, state)| (*shard_id, state.status.to_string()))
                 .collect();
@@ -2621,6 +4947,95 @@ impl Client {
         }
         Ok(ret)
     }
+
+    /// Returns, for every shard tracked in the epoch of the head, since which height the node
+    /// holds every chunk of the shard, looking at most `chunk_availability_window` blocks below
+    /// the head. The result is cached until the head changes, and then only the blocks on top
+    /// of the previous head are checked, unless the epoch changed.
+    pub fn chunk_availability(
+        &mut self,
+    ) -> Result<Vec<ShardChunkAvailabilityView>, near_chain::Error> {
+        let head = self.chain.head()?;
+        let previous = match &self.chunk_availability {
+            Some((last_head, availability))
+                if last_head.last_block_hash == head.last_block_hash =>
+            {
+                return Ok(availability.clone());
+            }
+            Some((last_head, availability)) if last_head.epoch_id == head.epoch_id => {
+                Some((last_head, availability))
+            }
+            _ => None,
+        };
+        let me = self.validator_signer.as_ref().map(|signer| signer.validator_id());
+        let mut availability = vec![];
+        for shard_id in self.epoch_manager.shard_ids(&head.epoch_id)? {
+            if self.shard_tracker.care_about_shard(me, &head.prev_block_hash, shard_id, true) {
+                let previous = previous.and_then(|(last_head, availability)| {
+                    let shard = availability.iter().find(|shard| shard.shard_id == shard_id)?;
+                    Some((&last_head.last_block_hash, shard))
+                });
+                availability.push(self.shard_chunk_availability(&head, shard_id, previous)?);
+            }
+        }
+        self.chunk_availability = Some((head, availability.clone()));
+        Ok(availability)
+    }
+
+    /// Walks back from the head until a new chunk of the shard isn't in the store, the window
+    /// ends, or the block itself isn't available, e.g. because it was garbage collected. If the
+    /// walk reaches the block the availability was `previous`ly computed for, it stops there and
+    /// the previous availability is moved along with the window.
+    fn shard_chunk_availability(
+        &self,
+        head: &Tip,
+        shard_id: ShardId,
+        previous: Option<(&CryptoHash, &ShardChunkAvailabilityView)>,
+    ) -> Result<ShardChunkAvailabilityView, near_chain::Error> {
+        let min_height = head.height.saturating_sub(self.config.chunk_availability_window);
+        let mut available_since_height = None;
+        let mut block_hash = head.last_block_hash;
+        while let Ok(block) = self.chain.get_block(&block_hash) {
+            if let Some((previous_block_hash, previous)) = previous {
+                if &block_hash == previous_block_hash {
+                    return Ok(match previous.available_since_height {
+                        // Even the chunk of the previous head is missing.
+                        None => ShardChunkAvailabilityView {
+                            shard_id,
+                            available_since_height,
+                            has_gap: previous.has_gap,
+                        },
+                        // The gap, if any, is no longer in the window.
+                        Some(height) if height <= min_height => ShardChunkAvailabilityView {
+                            shard_id,
+                            available_since_height: Some(min_height),
+                            has_gap: false,
+                        },
+                        Some(_) => previous.clone(),
+                    });
+                }
+            }
+            let height = block.header().height();
+            let Some(chunk_header) = block.chunks().get(shard_id as usize).cloned() else {
+                break;
+            };
+            if chunk_header.height_included() == height
+                && !self.chain.store().chunk_exists(&chunk_header.chunk_hash())?
+            {
+                return Ok(ShardChunkAvailabilityView {
+                    shard_id,
+                    available_since_height,
+                    has_gap: true,
+                });
+            }
+            available_since_height = Some(height);
+            if height <= min_height {
+                break;
+            }
+            block_hash = *block.header().prev_hash();
+        }
+        Ok(ShardChunkAvailabilityView { shard_id, available_since_height, has_gap: false })
+    }
 }
 
 impl Drop for Client {
@@ -2634,3 +5049,65 @@ impl Drop for Client {
             .stop_all();
     }
 }
+
+/// Checks `tx` against the transaction admission policy, reporting the rejections.
+fn check_tx_admission(
+    policy: &dyn TxAdmissionPolicy,
+    tx: &SignedTransaction,
+    context: &TxAdmissionContext,
+) -> Result<(), String> {
+    policy.check(tx, context).map_err(|reason| {
+        debug!(target: "client", tx_hash = ?tx.get_hash(), stage = context.stage.as_str(), %reason, "Transaction rejected by the admission policy");
+        metrics::TRANSACTION_REJECTED_BY_POLICY.with_label_values(&[context.stage.as_str()]).inc();
+        reason
+    })
+}
+
+/// Checks the inner `max_block_height` anchors of all delegate actions in `tx` according to
+/// `rule`, assuming the transaction can be included at `height` at the earliest. The outer
+/// transaction anchor is checked separately with `check_transaction_validity_period`.
+fn check_delegate_actions_validity(
+    chain_store: &ChainStore,
+    tx: &SignedTransaction,
+    rule: DelegateActionValidity,
+    height: BlockHeight,
+    transaction_validity_period: NumBlocks,
+) -> Result<(), InvalidTxError> {
+    let min_max_block_height = tx
+        .transaction
+        .actions
+        .iter()
+        .filter_map(|action| match action {
+            Action::Delegate(signed_delegate_action) => {
+                Some(signed_delegate_action.delegate_action.max_block_height)
+            }
+            _ => None,
+        })
+        .min();
+    let Some(max_block_height) = min_max_block_height else { return Ok(()) };
+    let required_height = match rule {
+        DelegateActionValidity::Runtime => return Ok(()),
+        DelegateActionValidity::Lenient => height,
+        // The last height the outer transaction can be included at.
+        DelegateActionValidity::Strict => {
+            let base_height = chain_store
+                .get_block_header(&tx.transaction.block_hash)
+                .map_err(|_| InvalidTxError::Expired)?
+                .height();
+            max(height, base_height + transaction_validity_period)
+        }
+    };
+    if max_block_height < required_height {
+        return Err(InvalidTxError::Expired);
+    }
+    Ok(())
+}
+
+/// Height of the block an approval builds on. Endorsements only carry the hash of that block, so
+/// for them it's the highest height the block can be at.
+fn approval_parent_height(inner: &ApprovalInner, target_height: BlockHeight) -> BlockHeight {
+    match inner {
+        ApprovalInner::Endorsement(_) => target_height.saturating_sub(1),
+        ApprovalInner::Skip(parent_height) => *parent_height,
+    }
+}
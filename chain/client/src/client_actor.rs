@@ -5,6 +5,8 @@
 //! Unfortunately, this is not the case today. We are in the process of refactoring ClientActor
 //! https://github.com/near/nearcore/issues/7899
 
+#[cfg(feature = "protocol_feature_chunk_validation")]
+use crate::adapter::ChunkEndorsementMessage;
 use crate::adapter::{
     BlockApproval, BlockHeadersResponse, BlockResponse, ProcessTxRequest, ProcessTxResponse,
     RecvChallenge, SetNetworkInfo, StateResponse,
@@ -14,10 +16,13 @@ use crate::client::AdvProduceBlocksMode;
 use crate::client::{Client, EPOCH_START_INFO_BLOCKS};
 use crate::config_updater::ConfigUpdater;
 use crate::debug::new_network_info_view;
+use crate::health::STATUS_WAIT_TIME_MULTIPLIER;
 use crate::info::{display_sync_status, InfoHelper};
+use crate::store_audit::StoreWrite;
 use crate::sync::adapter::{SyncMessage, SyncShardInfo};
 use crate::sync::state::{StateSync, StateSyncResult};
 use crate::sync_jobs_actor::{create_sync_job_scheduler, SyncJobsActor};
+use crate::tx_admission_policy::TxAdmissionPolicy;
 use crate::{metrics, StatusResponse, SyncAdapter};
 use actix::{Actor, Addr, Arbiter, AsyncContext, Context, Handler};
 use actix_rt::ArbiterHandle;
@@ -54,7 +59,7 @@ use near_network::types::{
 use near_o11y::{handler_debug_span, OpenTelemetrySpanExt, WithSpanContext, WithSpanContextExt};
 use near_performance_metrics;
 use near_performance_metrics_macros::perf;
-use near_primitives::block::Tip;
+use near_primitives::block::{GenesisId, Tip};
 use near_primitives::block_header::ApprovalType;
 use near_primitives::epoch_manager::RngSeed;
 use near_primitives::hash::CryptoHash;
@@ -65,7 +70,7 @@ use near_primitives::unwrap_or_return;
 use near_primitives::utils::{from_timestamp, MaybeValidated};
 use near_primitives::validator_signer::ValidatorSigner;
 use near_primitives::version::PROTOCOL_VERSION;
-use near_primitives::views::{DetailedDebugStatus, ValidatorInfo};
+use near_primitives::views::{DetailedDebugStatus, ShardChunkRequestRetryView, ValidatorInfo};
 #[cfg(feature = "test_features")]
 use near_store::DBCol;
 use near_store::ShardUId;
@@ -80,8 +85,6 @@ use std::time::{Duration, Instant};
 use tokio::sync::broadcast;
 use tracing::{debug, debug_span, error, info, trace, warn};
 
-/// Multiplier on `max_block_time` to wait until deciding that chain stalled.
-const STATUS_WAIT_TIME_MULTIPLIER: u64 = 10;
 /// `max_block_production_time` times this multiplier is how long we wait before rebroadcasting
 /// the current `head`
 const HEAD_STALL_MULTIPLIER: u32 = 4;
@@ -95,6 +98,9 @@ pub struct ClientActor {
     pub(crate) client: Client,
     network_adapter: PeerManagerAdapter,
     network_info: NetworkInfo,
+    /// Set if a supermajority of the highest height peers advertise a genesis different from
+    /// ours. Syncing is suspended while it is set, since it would only get us banned.
+    genesis_mismatch: Option<GenesisMismatch>,
     /// Identity that represents this Client at the network level.
     /// It is used as part of the messages that identify this client.
     node_id: PeerId,
@@ -185,6 +191,7 @@ impl ClientActor {
             client,
             network_adapter,
             node_id,
+            genesis_mismatch: None,
             network_info: NetworkInfo {
                 connected_peers: vec![],
                 tier1_connections: vec![],
@@ -355,10 +362,13 @@ impl Handler<WithSpanContext<NetworkAdversarialMessage>> for ClientActor {
                 info!(target: "adversary", "Switching to height {:?}", height);
                 let mut chain_store_update = this.client.chain.mut_store().store_update();
                 chain_store_update.save_largest_target_height(height);
-                chain_store_update
-                    .adv_save_latest_known(height)
+                this.client
+                    .store_audit_log
+                    .audited(StoreWrite::AdvSwitchToHeight { height }, || {
+                        chain_store_update.adv_save_latest_known(height)?;
+                        chain_store_update.commit()
+                    })
                     .expect("adv method should not fail");
-                chain_store_update.commit().expect("adv method should not fail");
                 None
             }
             NetworkAdversarialMessage::AdvGetSavedBlocks => {
@@ -481,10 +491,11 @@ impl Handler<WithSpanContext<BlockHeadersResponse>> for ClientActor {
     ) -> Self::Result {
         self.wrap(msg, ctx, "BlockHeadersResponse", |this, msg| {
             let BlockHeadersResponse(headers, peer_id) = msg;
-            if this.receive_headers(headers, peer_id) {
+            if this.receive_headers(headers, peer_id.clone()) {
                 Ok(())
             } else {
                 warn!(target: "client", "Banning node for sending invalid block headers");
+                this.client.record_ban(peer_id, ReasonForBan::BadBlockHeader, None);
                 Err(ReasonForBan::BadBlockHeader)
             }
         })
@@ -504,6 +515,19 @@ impl Handler<WithSpanContext<BlockApproval>> for ClientActor {
     }
 }
 
+#[cfg(feature = "protocol_feature_chunk_validation")]
+impl Handler<WithSpanContext<ChunkEndorsementMessage>> for ClientActor {
+    type Result = ();
+
+    #[perf]
+    fn handle(&mut self, msg: WithSpanContext<ChunkEndorsementMessage>, ctx: &mut Context<Self>) {
+        self.wrap(msg, ctx, "ChunkEndorsementMessage", |this, msg| {
+            let ChunkEndorsementMessage(endorsement) = msg;
+            this.client.process_chunk_endorsement(endorsement);
+        })
+    }
+}
+
 /// StateResponse is used during StateSync and catchup.
 /// It contains either StateSync header information (that tells us how many parts there are etc) or a single part.
 impl Handler<WithSpanContext<StateResponse>> for ClientActor {
@@ -575,6 +599,7 @@ impl Handler<WithSpanContext<SetNetworkInfo>> for ClientActor {
         self.wrap(msg, ctx, "SetNetworkInfo", |this, msg| {
             let SetNetworkInfo(network_info) = msg;
             this.network_info = network_info;
+            this.check_genesis_mismatch();
         })
     }
 }
@@ -632,10 +657,28 @@ impl Handler<WithSpanContext<Status>> for ClientActor {
         let head_header = self.client.chain.get_block_header(&head.last_block_hash)?;
         let latest_block_time = head_header.raw_timestamp();
         let latest_state_root = *head_header.prev_state_root();
-        if msg.is_health_check {
+        if let (true, Some(mismatch)) = (msg.is_health_check, &self.genesis_mismatch) {
+            return Err(StatusError::GenesisMismatch {
+                chain_id: self.client.config.chain_id.clone(),
+                genesis_hash: *self.client.chain.genesis().hash(),
+                peers_chain_id: mismatch.peers_genesis_id.chain_id.clone(),
+                peers_genesis_hash: mismatch.peers_genesis_id.hash,
+                mismatched_peers: mismatch.mismatched_peers.clone(),
+            });
+        }
+        let incompatible_protocol_version = self.client.incompatible_protocol_version();
+        if let Some((network, ours)) = incompatible_protocol_version {
+            // A validator which cannot produce blocks is critically unhealthy. Other nodes stop
+            // following the chain on purpose and keep serving the data they already have.
+            if msg.is_health_check && self.client.validator_signer.is_some() {
+                return Err(StatusError::IncompatibleProtocolVersion { network, ours });
+            }
+        }
+        if msg.is_health_check && incompatible_protocol_version.is_none() {
             let now = Utc::now();
             let block_timestamp = from_timestamp(latest_block_time);
-            if now > block_timestamp {
+            // A node which synced to its target height doesn't expect new blocks.
+            if now > block_timestamp && !self.client.synced_to_target()? {
                 let elapsed = (now - block_timestamp).to_std().unwrap();
                 if elapsed
                     > Duration::from_millis(
@@ -650,6 +693,30 @@ impl Handler<WithSpanContext<Status>> for ClientActor {
             if self.client.sync_status.is_syncing() {
                 return Err(StatusError::NodeIsSyncing);
             }
+
+            if let Some(error_message) = self.client.chunk_persistence_error() {
+                return Err(StatusError::InternalError {
+                    error_message: error_message.to_string(),
+                });
+            }
+
+            if let Some(error_message) = self.client.head_epoch_mismatch() {
+                return Err(StatusError::InternalError {
+                    error_message: error_message.to_string(),
+                });
+            }
+
+            if let Some(error_message) = self.client.production_alert() {
+                return Err(StatusError::InternalError {
+                    error_message: error_message.to_string(),
+                });
+            }
+
+            if let Some(error_message) = self.client.slashed_in_epoch() {
+                return Err(StatusError::InternalError {
+                    error_message: error_message.to_string(),
+                });
+            }
         }
         let validators: Vec<ValidatorInfo> = self
             .client
@@ -678,6 +745,27 @@ impl Handler<WithSpanContext<Status>> for ClientActor {
             None => (None, None),
         };
         let node_key = validator_public_key.clone();
+        let chunk_producer_shards = if self.client.validator_signer.is_some() {
+            self.client.chunk_producer_shards(&head.epoch_id).ok()
+        } else {
+            None
+        };
+        let chunk_availability = self.client.chunk_availability().ok();
+        let chunk_request_retry =
+            self.client.epoch_manager.num_shards(&head.epoch_id).ok().map(|num_shards| {
+                (0..num_shards)
+                    .map(|shard_id| {
+                        let config = self.client.config.chunk_request_retry(shard_id);
+                        ShardChunkRequestRetryView {
+                            shard_id,
+                            retry_period_millis: config.retry_period.as_millis() as u64,
+                            switch_to_others_millis: config.switch_to_others.as_millis() as u64,
+                            switch_to_full_fetch_millis: config.switch_to_full_fetch.as_millis()
+                                as u64,
+                        }
+                    })
+                    .collect()
+            });
 
         let mut earliest_block_hash = None;
         let mut earliest_block_height = None;
@@ -736,13 +824,19 @@ impl Handler<WithSpanContext<Status>> for ClientActor {
                 earliest_block_time,
                 epoch_id: Some(head.epoch_id),
                 epoch_start_height,
+                sync_until_height: self.client.config.sync_until_height,
+                synced_to_target: self.client.synced_to_target()?,
             },
             validator_account_id,
             validator_public_key,
+            chunk_producer_shards,
             node_public_key,
             node_key,
             uptime_sec,
             detailed_debug_status,
+            chunk_availability,
+            chunk_request_retry,
+            health: Some(self.client.health()),
         })
     }
 }
@@ -819,12 +913,20 @@ impl Handler<WithSpanContext<ApplyChunksDoneMessage>> for ClientActor {
     }
 }
 
+/// Highest height peers on a different chain than ours.
+struct GenesisMismatch {
+    /// Genesis advertised by most of the peers.
+    peers_genesis_id: GenesisId,
+    mismatched_peers: Vec<PeerId>,
+}
+
 #[derive(Debug)]
 enum SyncRequirement {
     SyncNeeded { peer_id: PeerId, highest_height: BlockHeight, head: Tip },
     AlreadyCaughtUp { peer_id: PeerId, highest_height: BlockHeight, head: Tip },
     NoPeers,
     AdvHeaderSyncDisabled,
+    GenesisMismatch,
 }
 
 impl SyncRequirement {
@@ -856,6 +958,7 @@ impl fmt::Display for SyncRequirement {
             Self::AdvHeaderSyncDisabled => {
                 write!(f, "syncing disabled via adv_disable_header_sync")
             }
+            Self::GenesisMismatch => write!(f, "peers are on a different chain"),
         }
     }
 }
@@ -930,9 +1033,7 @@ impl ClientActor {
 
         let epoch_length = self.client.config.epoch_length;
         if epoch_length <= 3 {
-            return Err(Error::Other(
-                "Unsupported: fast_forward with an epoch length of 3 or less".to_string(),
-            ));
+            return Err(Error::UnsupportedFastForward);
         }
 
         // Check if we are at epoch boundary. If we are, do not fast forward until new
@@ -975,7 +1076,10 @@ impl ClientActor {
             if let Some(new_latest_known) =
                 self.sandbox_process_fast_forward(latest_known.height)?
             {
-                self.client.chain.mut_store().save_latest_known(new_latest_known.clone())?;
+                let write = StoreWrite::SandboxLatestKnown { height: new_latest_known.height };
+                self.client.store_audit_log.audited(write, || {
+                    self.client.chain.mut_store().save_latest_known(new_latest_known.clone())
+                })?;
                 self.client.sandbox_update_tip(new_latest_known.height)?;
             }
         }
@@ -1002,8 +1106,13 @@ impl ClientActor {
             debug!(target:"client", sync_status=format!("{:#?}", self.client.sync_status), "Syncing - block production disabled");
             return Ok(());
         }
+        if self.client.incompatible_protocol_version().is_some() {
+            debug!(target: "client", "Incompatible protocol version - block production disabled");
+            return Ok(());
+        }
 
-        let _ = self.client.check_and_update_doomslug_tip();
+        let head = self.client.chain.head()?;
+        let _ = self.client.check_and_update_doomslug_tip(&head);
 
         self.pre_block_production()?;
         let head = self.client.chain.head()?;
@@ -1077,6 +1186,7 @@ impl ClientActor {
                     if let Err(err) = self.produce_block(height) {
                         // If there is an error, report it and let it retry on the next loop step.
                         error!(target: "client", height, "Block production failed: {}", err);
+                        self.client.record_production_error(height, None, &err);
                     } else {
                         self.post_block_production();
                     }
@@ -1110,7 +1220,7 @@ impl ClientActor {
     fn check_triggers(&mut self, ctx: &mut Context<ClientActor>) -> Duration {
         let _span = tracing::debug_span!(target: "client", "check_triggers").entered();
         if let Some(config_updater) = &mut self.config_updater {
-            config_updater.try_update(&|updateable_client_config| {
+            config_updater.try_update(&mut |updateable_client_config| {
                 self.client.update_client_config(updateable_client_config)
             });
         }
@@ -1128,6 +1238,7 @@ impl ClientActor {
         }
 
         self.try_process_unfinished_blocks();
+        self.client.flush_chain_heads_update();
 
         let mut delay = Duration::from_secs(1);
         let now = Utc::now();
@@ -1214,6 +1325,9 @@ impl ClientActor {
     /// and we want to prioritize block processing.
     fn try_process_unfinished_blocks(&mut self) {
         let _span = debug_span!(target: "client", "try_process_unfinished_blocks").entered();
+        self.client.process_persisted_chunks(self.get_apply_chunks_done_callback());
+        self.client.chain.blocks_with_missing_chunks.prune_old_blocks();
+        self.request_evicted_blocks();
         let (accepted_blocks, errors) =
             self.client.postprocess_ready_blocks(self.get_apply_chunks_done_callback(), true);
         if !errors.is_empty() {
@@ -1222,6 +1336,23 @@ impl ClientActor {
         self.process_accepted_blocks(accepted_blocks);
     }
 
+    /// Requests again the blocks evicted from the pool of blocks with missing chunks one of whose
+    /// chunks has arrived since.
+    fn request_evicted_blocks(&mut self) {
+        let block_hashes = self.client.chain.blocks_with_missing_chunks.take_blocks_to_refetch();
+        if block_hashes.is_empty() {
+            return;
+        }
+        let Some(peer_info) = self.network_info.highest_height_peers.choose(&mut thread_rng())
+        else {
+            debug!(target: "client", ?block_hashes, "No peer to request evicted blocks from");
+            return;
+        };
+        for block_hash in block_hashes {
+            self.client.request_block(block_hash, peer_info.peer_info.id.clone());
+        }
+    }
+
     fn try_handle_block_production(&mut self) {
         let _span = debug_span!(target: "client", "try_handle_block_production").entered();
         if let Err(err) = self.handle_block_production() {
@@ -1231,18 +1362,25 @@ impl ClientActor {
 
     fn try_doomslug_timer(&mut self, _: &mut Context<ClientActor>) {
         let _span = tracing::debug_span!(target: "client", "try_doomslug_timer").entered();
-        let _ = self.client.check_and_update_doomslug_tip();
+        // The approvals are created and sent for the head read here, even if a block processed in
+        // the meantime changes it.
+        let head = unwrap_or_return!(self.client.chain.head());
+        let _ = self.client.check_and_update_doomslug_tip(&head);
         let approvals = self.client.doomslug.process_timer(StaticClock::instant());
 
         // Important to save the largest approval target height before sending approvals, so
         // that if the node crashes in the meantime, we cannot get slashed on recovery
+        let largest_target_height = self.client.doomslug.get_largest_target_height();
         let mut chain_store_update = self.client.chain.mut_store().store_update();
-        chain_store_update
-            .save_largest_target_height(self.client.doomslug.get_largest_target_height());
+        chain_store_update.save_largest_target_height(largest_target_height);
 
-        match chain_store_update.commit() {
+        match self
+            .client
+            .store_audit_log
+            .audited(StoreWrite::LargestTargetHeight { height: largest_target_height }, || {
+                chain_store_update.commit()
+            }) {
             Ok(_) => {
-                let head = unwrap_or_return!(self.client.chain.head());
                 if self.client.is_validator(&head.epoch_id, &head.last_block_hash)
                     || self.client.is_validator(&head.next_epoch_id, &head.last_block_hash)
                 {
@@ -1264,17 +1402,19 @@ impl ClientActor {
     fn produce_block(&mut self, next_height: BlockHeight) -> Result<(), Error> {
         let _span = tracing::debug_span!(target: "client", "produce_block", next_height).entered();
         if let Some(block) = self.client.produce_block(next_height)? {
-            // If we produced the block, send it out before we apply the block.
-            self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
-                NetworkRequests::Block { block: block.clone() },
-            ));
             // We’ve produced the block so that counts as validated block.
-            let block = MaybeValidated::from_validated(block);
             let res = self.client.start_process_block(
-                block,
+                MaybeValidated::from_validated(block.clone()),
                 Provenance::PRODUCED,
                 self.get_apply_chunks_done_callback(),
             );
+            // The chunks of the block are applied in the background, so the block is sent out
+            // before we apply it, unless the rest of the network would reject it.
+            if !matches!(res, Err(near_chain::Error::InvalidNextBPHash)) {
+                self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
+                    NetworkRequests::Block { block },
+                ));
+            }
             if let Err(e) = &res {
                 match e {
                     near_chain::Error::ChunksMissing(_) => {
@@ -1401,6 +1541,9 @@ impl ClientActor {
         if self.adv.disable_header_sync() {
             return Ok(SyncRequirement::AdvHeaderSyncDisabled);
         }
+        if self.genesis_mismatch.is_some() {
+            return Ok(SyncRequirement::GenesisMismatch);
+        }
 
         let head = self.client.chain.head()?;
         let is_syncing = self.client.sync_status.is_syncing();
@@ -1421,7 +1564,10 @@ impl ClientActor {
         };
 
         let peer_id = peer_info.peer_info.id.clone();
-        let highest_height = peer_info.highest_block_height;
+        let mut highest_height = peer_info.highest_block_height;
+        if let Some(sync_until_height) = self.client.config.sync_until_height {
+            highest_height = highest_height.min(sync_until_height);
+        }
 
         if is_syncing {
             if highest_height <= head.height {
@@ -1438,6 +1584,48 @@ impl ClientActor {
         }
     }
 
+    /// Checks whether a supermajority of the highest height peers advertise a genesis different
+    /// from ours, which most likely means that this node was initialized for a different chain.
+    fn check_genesis_mismatch(&mut self) {
+        let ours = GenesisId {
+            chain_id: self.client.config.chain_id.clone(),
+            hash: *self.client.chain.genesis().hash(),
+        };
+        let peers = &self.network_info.highest_height_peers;
+        let mismatched: Vec<_> = peers.iter().filter(|p| p.genesis_id != ours).collect();
+        let genesis_mismatch = if 3 * mismatched.len() > 2 * peers.len() {
+            // The mismatched peers need not agree on a genesis, report the most common one.
+            let count = |genesis_id: &GenesisId| {
+                mismatched.iter().filter(|p| &p.genesis_id == genesis_id).count()
+            };
+            mismatched.iter().map(|p| &p.genesis_id).max_by_key(|id| count(id)).map(|id| {
+                GenesisMismatch {
+                    peers_genesis_id: id.clone(),
+                    mismatched_peers: mismatched.iter().map(|p| p.peer_info.id.clone()).collect(),
+                }
+            })
+        } else {
+            None
+        };
+        match (&self.genesis_mismatch, &genesis_mismatch) {
+            (None, Some(mismatch)) => error!(
+                target: "client",
+                our_chain_id = ours.chain_id,
+                our_genesis_hash = %ours.hash,
+                peers_chain_id = mismatch.peers_genesis_id.chain_id,
+                peers_genesis_hash = %mismatch.peers_genesis_id.hash,
+                mismatched_peers = ?mismatch.mismatched_peers,
+                num_peers = peers.len(),
+                "Most peers are on a different chain. Check the chain id and genesis file this node was initialized with. Syncing is suspended."),
+            (Some(_), None) => {
+                info!(target: "client", "Peers are on our chain again, resuming syncing")
+            }
+            _ => {}
+        }
+        metrics::GENESIS_MISMATCH.set(genesis_mismatch.is_some() as i64);
+        self.genesis_mismatch = genesis_mismatch;
+    }
+
     fn start_flat_storage_creation(&mut self, ctx: &mut Context<ClientActor>) {
         if !self.client.config.flat_storage_creation_enabled {
             return;
@@ -1488,7 +1676,15 @@ impl ClientActor {
     /// <https://github.com/nearprotocol/nearcore/issues/2021#issuecomment-583039862>.
     fn find_sync_hash(&mut self) -> Result<CryptoHash, near_chain::Error> {
         let header_head = self.client.chain.header_head()?;
-        let sync_hash = header_head.last_block_hash;
+        let mut sync_hash = header_head.last_block_hash;
+        if let Some(sync_until_height) = self.client.config.sync_until_height {
+            // Headers above the target aren't synced, but may be left from before it was set.
+            let mut header = self.client.chain.get_block_header(&sync_hash)?;
+            while header.height() > sync_until_height {
+                header = self.client.chain.get_block_header(header.prev_hash())?;
+            }
+            sync_hash = *header.hash();
+        }
         let epoch_start_sync_hash =
             StateSync::get_epoch_start_sync_hash(&mut self.client.chain, &sync_hash)?;
 
@@ -1591,10 +1787,14 @@ impl ClientActor {
         match sync {
             SyncRequirement::AlreadyCaughtUp { .. }
             | SyncRequirement::NoPeers
-            | SyncRequirement::AdvHeaderSyncDisabled => {
+            | SyncRequirement::AdvHeaderSyncDisabled
+            | SyncRequirement::GenesisMismatch => {
                 if currently_syncing {
                     // Initial transition out of "syncing" state.
                     debug!(target: "sync", prev_sync_status = ?self.client.sync_status, "disabling sync");
+                    if unwrap_and_report!(self.client.synced_to_target()) {
+                        info!(target: "sync", sync_until_height = ?self.client.config.sync_until_height, "Synced to target");
+                    }
                     self.client.sync_status.update(SyncStatus::NoSync);
                     // Announce this client's account id if their epoch is coming up.
                     let head = unwrap_and_report!(self.client.chain.head());
@@ -1832,14 +2032,7 @@ impl Handler<WithSpanContext<BlockCatchUpResponse>> for ClientActor {
     ) -> Self::Result {
         let (_span, msg) = handler_debug_span!(target: "client", msg);
         tracing::debug!(target: "client", ?msg);
-        if let Some((_, _, blocks_catch_up_state)) =
-            self.client.catchup_state_syncs.get_mut(&msg.sync_hash)
-        {
-            assert!(blocks_catch_up_state.scheduled_blocks.remove(&msg.block_hash));
-            blocks_catch_up_state.processed_blocks.insert(msg.block_hash, msg.results);
-        } else {
-            panic!("block catch up processing result from unknown sync hash");
-        }
+        self.client.on_block_catch_up_done(msg);
     }
 }
 
@@ -1854,12 +2047,7 @@ impl Handler<WithSpanContext<StateSplitResponse>> for ClientActor {
     ) -> Self::Result {
         let (_span, msg) = handler_debug_span!(target: "client", msg);
         tracing::debug!(target: "client", ?msg);
-        if let Some((sync, _, _)) = self.client.catchup_state_syncs.get_mut(&msg.sync_hash) {
-            // We are doing catchup
-            sync.set_split_result(msg.shard_id, msg.new_state_roots);
-        } else {
-            self.client.state_sync.set_split_result(msg.shard_id, msg.new_state_roots);
-        }
+        self.client.on_state_split_done(msg);
     }
 }
 
@@ -1890,6 +2078,14 @@ impl Handler<WithSpanContext<ShardsManagerResponse>> for ClientActor {
             } => {
                 self.client.on_chunk_header_ready_for_inclusion(chunk_header, chunk_producer);
             }
+            ShardsManagerResponse::ChunksCompleted(chunks) => {
+                self.client.on_chunks_completed(chunks, self.get_apply_chunks_done_callback());
+            }
+            ShardsManagerResponse::ChunkHeadersReadyForInclusion(chunk_headers) => {
+                for (chunk_header, chunk_producer) in chunk_headers {
+                    self.client.on_chunk_header_ready_for_inclusion(chunk_header, chunk_producer);
+                }
+            }
         }
     }
 }
@@ -1946,6 +2142,7 @@ pub fn start_client(
     sender: Option<broadcast::Sender<()>>,
     adv: crate::adversarial::Controls,
     config_updater: Option<ConfigUpdater>,
+    tx_admission_policy: Option<Arc<dyn TxAdmissionPolicy>>,
 ) -> (Addr<ClientActor>, ArbiterHandle) {
     let client_arbiter = Arbiter::new();
     let client_arbiter_handle = client_arbiter.handle();
@@ -1964,6 +2161,7 @@ pub fn start_client(
         true,
         random_seed_from_thread(),
         snapshot_callbacks,
+        tx_admission_policy,
     )
     .unwrap();
     let client_addr = ClientActor::start_in_arbiter(&client_arbiter_handle, move |ctx| {
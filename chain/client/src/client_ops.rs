@@ -0,0 +1,189 @@
+//! Entry points of the client which are driven from outside of it.
+//!
+//! The `ClientActor` decides when blocks are produced, when the blocks which finished applying
+//! their chunks are postprocessed, when the catchup runs and so on, and calls into the `Client`
+//! accordingly. `ClientOps` captures these calls, so that other schedulers, e.g. simulations
+//! advancing the chain deterministically, can drive a client without depending on actix.
+use crate::adapter::ProcessTxResponse;
+use crate::Client;
+use near_async::futures::FutureSpawner;
+use near_chain::chain::{ApplyStatePartsRequest, BlockCatchUpRequest, BlockCatchUpResponse};
+use near_chain::resharding::{StateSplitRequest, StateSplitResponse};
+use near_chain::{DoneApplyChunkCallback, Provenance};
+use near_client_primitives::types::Error;
+use near_network::types::HighestHeightPeerInfo;
+use near_primitives::block::{Approval, Block};
+use near_primitives::block_header::ApprovalType;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use near_primitives::sharding::{PartialEncodedChunk, ShardChunk};
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::BlockHeight;
+use near_primitives::utils::MaybeValidated;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Operations a scheduler calls on the client. See the methods of the same names on `Client`.
+///
+/// Block processing is asynchronous: `start_process_block` and `receive_block` return once the
+/// chunks of the block are being applied, and `apply_chunks_done_callback` is called when that
+/// finishes. The scheduler is then expected to call `postprocess_ready_blocks` to accept them.
+pub trait ClientOps {
+    fn produce_block(&mut self, height: BlockHeight) -> Result<Option<Block>, Error>;
+
+    fn start_process_block(
+        &mut self,
+        block: MaybeValidated<Block>,
+        provenance: Provenance,
+        apply_chunks_done_callback: DoneApplyChunkCallback,
+    ) -> Result<(), near_chain::Error>;
+
+    fn receive_block(
+        &mut self,
+        block: Block,
+        peer_id: PeerId,
+        was_requested: bool,
+        apply_chunks_done_callback: DoneApplyChunkCallback,
+    );
+
+    fn postprocess_ready_blocks(
+        &mut self,
+        apply_chunks_done_callback: DoneApplyChunkCallback,
+        should_produce_chunk: bool,
+    ) -> (Vec<CryptoHash>, HashMap<CryptoHash, near_chain::Error>);
+
+    fn process_tx(
+        &mut self,
+        tx: SignedTransaction,
+        is_forwarded: bool,
+        check_only: bool,
+    ) -> ProcessTxResponse;
+
+    fn collect_block_approval(&mut self, approval: &Approval, approval_type: ApprovalType);
+
+    fn on_chunk_completed(
+        &mut self,
+        partial_chunk: PartialEncodedChunk,
+        shard_chunk: Option<ShardChunk>,
+        apply_chunks_done_callback: DoneApplyChunkCallback,
+    );
+
+    fn run_catchup(
+        &mut self,
+        highest_height_peers: &[HighestHeightPeerInfo],
+        state_parts_task_scheduler: &dyn Fn(ApplyStatePartsRequest),
+        block_catch_up_task_scheduler: &dyn Fn(BlockCatchUpRequest),
+        state_split_scheduler: &dyn Fn(StateSplitRequest),
+        apply_chunks_done_callback: DoneApplyChunkCallback,
+        state_parts_future_spawner: &dyn FutureSpawner,
+    ) -> Result<(), Error>;
+
+    /// Hands back the result of a `BlockCatchUpRequest` scheduled by `run_catchup`.
+    fn on_block_catch_up_done(&mut self, response: BlockCatchUpResponse);
+
+    /// Hands back the result of a `StateSplitRequest` scheduled by `run_catchup`.
+    fn on_state_split_done(&mut self, response: StateSplitResponse);
+
+    fn check_head_progress_stalled(&mut self, stall_timeout: Duration) -> Result<(), Error>;
+
+    /// Processes the chunks persisted in the background since the last call.
+    fn process_persisted_chunks(&mut self, apply_chunks_done_callback: DoneApplyChunkCallback);
+
+    /// Sends the chain heads changes held back during sync.
+    fn flush_chain_heads_update(&mut self);
+}
+
+impl ClientOps for Client {
+    fn produce_block(&mut self, height: BlockHeight) -> Result<Option<Block>, Error> {
+        Client::produce_block(self, height)
+    }
+
+    fn start_process_block(
+        &mut self,
+        block: MaybeValidated<Block>,
+        provenance: Provenance,
+        apply_chunks_done_callback: DoneApplyChunkCallback,
+    ) -> Result<(), near_chain::Error> {
+        Client::start_process_block(self, block, provenance, apply_chunks_done_callback)
+    }
+
+    fn receive_block(
+        &mut self,
+        block: Block,
+        peer_id: PeerId,
+        was_requested: bool,
+        apply_chunks_done_callback: DoneApplyChunkCallback,
+    ) {
+        Client::receive_block(self, block, peer_id, was_requested, apply_chunks_done_callback)
+    }
+
+    fn postprocess_ready_blocks(
+        &mut self,
+        apply_chunks_done_callback: DoneApplyChunkCallback,
+        should_produce_chunk: bool,
+    ) -> (Vec<CryptoHash>, HashMap<CryptoHash, near_chain::Error>) {
+        Client::postprocess_ready_blocks(self, apply_chunks_done_callback, should_produce_chunk)
+    }
+
+    fn process_tx(
+        &mut self,
+        tx: SignedTransaction,
+        is_forwarded: bool,
+        check_only: bool,
+    ) -> ProcessTxResponse {
+        Client::process_tx(self, tx, is_forwarded, check_only)
+    }
+
+    fn collect_block_approval(&mut self, approval: &Approval, approval_type: ApprovalType) {
+        Client::collect_block_approval(self, approval, approval_type)
+    }
+
+    fn on_chunk_completed(
+        &mut self,
+        partial_chunk: PartialEncodedChunk,
+        shard_chunk: Option<ShardChunk>,
+        apply_chunks_done_callback: DoneApplyChunkCallback,
+    ) {
+        Client::on_chunk_completed(self, partial_chunk, shard_chunk, apply_chunks_done_callback)
+    }
+
+    fn run_catchup(
+        &mut self,
+        highest_height_peers: &[HighestHeightPeerInfo],
+        state_parts_task_scheduler: &dyn Fn(ApplyStatePartsRequest),
+        block_catch_up_task_scheduler: &dyn Fn(BlockCatchUpRequest),
+        state_split_scheduler: &dyn Fn(StateSplitRequest),
+        apply_chunks_done_callback: DoneApplyChunkCallback,
+        state_parts_future_spawner: &dyn FutureSpawner,
+    ) -> Result<(), Error> {
+        Client::run_catchup(
+            self,
+            highest_height_peers,
+            state_parts_task_scheduler,
+            block_catch_up_task_scheduler,
+            state_split_scheduler,
+            apply_chunks_done_callback,
+            state_parts_future_spawner,
+        )
+    }
+
+    fn on_block_catch_up_done(&mut self, response: BlockCatchUpResponse) {
+        Client::on_block_catch_up_done(self, response)
+    }
+
+    fn on_state_split_done(&mut self, response: StateSplitResponse) {
+        Client::on_state_split_done(self, response)
+    }
+
+    fn check_head_progress_stalled(&mut self, stall_timeout: Duration) -> Result<(), Error> {
+        Client::check_head_progress_stalled(self, stall_timeout)
+    }
+
+    fn process_persisted_chunks(&mut self, apply_chunks_done_callback: DoneApplyChunkCallback) {
+        Client::process_persisted_chunks(self, apply_chunks_done_callback)
+    }
+
+    fn flush_chain_heads_update(&mut self) {
+        Client::flush_chain_heads_update(self)
+    }
+}
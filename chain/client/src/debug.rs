@@ -1,14 +1,16 @@
 //! Structs in this file are used for debug purposes, and might change at any time
 //! without backwards compatibility.
+use crate::metrics;
 use crate::ClientActor;
 use actix::{Context, Handler};
 
 use itertools::Itertools;
 use near_chain::crypto_hash_timer::CryptoHashTimer;
-use near_chain::{near_chain_primitives, Chain, ChainStoreAccess};
+use near_chain::{near_chain_primitives, resolve_shard_uid_at, Chain, ChainStoreAccess};
 use near_client_primitives::debug::{
-    ApprovalAtHeightStatus, BlockProduction, ChunkCollection, DebugBlockStatusData, DebugStatus,
-    DebugStatusResponse, MissedHeightInfo, ProductionAtHeight, ValidatorStatus,
+    ApprovalAtHeightStatus, BanHistoryEntry, BlockProduction, ChunkCollection, ChunkProduction,
+    DebugBlockStatusData, DebugStatus, DebugStatusResponse, MissedHeightInfo, ProductionAtHeight,
+    SyncStatusDebugView, ValidatorStatus,
 };
 use near_client_primitives::types::Error;
 use near_client_primitives::{
@@ -19,21 +21,29 @@ use near_epoch_manager::EpochManagerAdapter;
 use near_o11y::{handler_debug_span, log_assert, OpenTelemetrySpanExt, WithSpanContext};
 use near_performance_metrics_macros::perf;
 use near_primitives::state_sync::get_num_state_parts;
-use near_primitives::types::{AccountId, BlockHeight, ShardId, ValidatorInfoIdentifier};
+use near_primitives::types::{
+    AccountId, Balance, BlockHeight, BlockId, BlockReference, ShardId, ValidatorInfoIdentifier,
+};
 use near_primitives::{
     hash::CryptoHash,
     state_sync::{ShardStateSyncResponseHeader, StateHeaderKey},
     types::EpochId,
     views::ValidatorInfo,
 };
-use near_store::DBCol;
+use near_store::{DBCol, Store};
+use num_rational::Rational32;
 use std::cmp::{max, min};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
 
 use near_client_primitives::debug::{DebugBlockStatus, DebugChunkStatus};
-use near_network::types::{ConnectedPeerInfo, NetworkInfo, PeerType};
+use near_network::types::{ConnectedPeerInfo, NetworkInfo, PeerType, ReasonForBan};
+use near_primitives::block::Approval;
+use near_primitives::network::PeerId;
 use near_primitives::sharding::ShardChunkHeader;
 use near_primitives::static_clock::StaticClock;
+use near_primitives::utils::{from_timestamp, to_timestamp};
 use near_primitives::views::{
     AccountDataView, KnownProducerView, NetworkInfoView, PeerInfoView, Tier1ProxyView,
 };
@@ -54,14 +64,30 @@ pub const PRODUCTION_TIMES_CACHE_SIZE: usize = 1000;
 pub struct BlockProductionTracker(lru::LruCache<BlockHeight, BlockProduction>);
 
 impl BlockProductionTracker {
-    pub(crate) fn new() -> Self {
-        Self(lru::LruCache::new(PRODUCTION_TIMES_CACHE_SIZE))
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self(lru::LruCache::new(capacity))
     }
 
     pub(crate) fn get(&mut self, height: BlockHeight) -> BlockProduction {
         self.0.get(&height).cloned().unwrap_or_default()
     }
 
+    /// Copies the productions recorded at `heights`.
+    pub(crate) fn records_in(
+        &self,
+        heights: &RangeInclusive<BlockHeight>,
+    ) -> HashMap<BlockHeight, BlockProduction> {
+        self.0
+            .iter()
+            .filter(|(height, _)| heights.contains(height))
+            .map(|(height, production)| (*height, production.clone()))
+            .collect()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
     /// Record approvals received so far for this block. Must be called before block is produced.
     pub(crate) fn record_approvals(
         &mut self,
@@ -85,6 +111,7 @@ impl BlockProductionTracker {
                 height
             );
         }
+        metrics::BLOCK_PRODUCTION_INFO_SIZE.set(self.len() as i64);
     }
 
     /// Record block production info
@@ -144,6 +171,265 @@ impl BlockProductionTracker {
     }
 }
 
+/// Chunk production timings of the most recent heights of every shard. The heights are kept per
+/// shard rather than in a single LRU, so that the chunks of one shard don't evict the others.
+pub struct ChunkProductionTracker {
+    capacity_per_shard: usize,
+    shards: HashMap<ShardId, BTreeMap<BlockHeight, ChunkProduction>>,
+    len: usize,
+}
+
+impl ChunkProductionTracker {
+    pub(crate) fn new(capacity_per_shard: usize) -> Self {
+        Self { capacity_per_shard, shards: HashMap::new(), len: 0 }
+    }
+
+    /// Record the production of the chunk at `height` for `shard_id`. Drops the lowest height of
+    /// the shard once it has more than the capacity.
+    pub(crate) fn record(
+        &mut self,
+        height: BlockHeight,
+        shard_id: ShardId,
+        production: ChunkProduction,
+    ) {
+        let heights = self.shards.entry(shard_id).or_default();
+        if heights.insert(height, production).is_none() {
+            self.len += 1;
+        }
+        while heights.len() > self.capacity_per_shard {
+            heights.pop_first();
+            self.len -= 1;
+        }
+        metrics::CHUNK_PRODUCTION_INFO_SIZE.set(self.len() as i64);
+    }
+
+    pub(crate) fn get(&self, height: BlockHeight, shard_id: ShardId) -> Option<&ChunkProduction> {
+        self.shards.get(&shard_id)?.get(&height)
+    }
+
+    /// Copies the productions recorded at `heights`, across all shards.
+    pub(crate) fn records_in(
+        &self,
+        heights: &RangeInclusive<BlockHeight>,
+    ) -> HashMap<(BlockHeight, ShardId), ChunkProduction> {
+        self.shards
+            .iter()
+            .flat_map(|(shard_id, productions)| {
+                productions
+                    .range(heights.clone())
+                    .map(|(height, production)| ((*height, *shard_id), production.clone()))
+            })
+            .collect()
+    }
+
+    /// Number of chunks recorded across all shards.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Reasons why this node didn't produce a block or chunk at heights where it was the producer.
+pub struct ProductionSkipTracker(lru::LruCache<(BlockHeight, Option<ShardId>), String>);
+
+impl ProductionSkipTracker {
+    pub(crate) fn new() -> Self {
+        Self(lru::LruCache::new(PRODUCTION_TIMES_CACHE_SIZE))
+    }
+
+    /// Record why the block (`shard_id` is None) or chunk at `height` wasn't produced. Overwrites
+    /// the reason recorded by an earlier attempt.
+    pub(crate) fn record(
+        &mut self,
+        height: BlockHeight,
+        shard_id: Option<ShardId>,
+        reason: String,
+    ) {
+        self.0.put((height, shard_id), reason);
+    }
+
+    pub(crate) fn get(&self, height: BlockHeight, shard_id: Option<ShardId>) -> Option<&String> {
+        self.0.peek(&(height, shard_id))
+    }
+
+    /// Copies the reasons recorded at `heights`.
+    pub(crate) fn records_in(
+        &self,
+        heights: &RangeInclusive<BlockHeight>,
+    ) -> HashMap<(BlockHeight, Option<ShardId>), String> {
+        self.0
+            .iter()
+            .filter(|((height, _), _)| heights.contains(height))
+            .map(|(key, reason)| (*key, reason.clone()))
+            .collect()
+    }
+}
+
+/// Number of bans to remember for debug purposes.
+pub const BAN_HISTORY_SIZE: usize = 1000;
+
+/// A ban as stored in `DBCol::BanHistory`.
+#[derive(borsh::BorshSerialize, borsh::BorshDeserialize)]
+pub struct StoredBan {
+    pub peer_id: PeerId,
+    pub reason: String,
+    pub triggered_by: Option<CryptoHash>,
+    /// Time of the ban, in nanoseconds since the Unix epoch.
+    pub ban_time: u64,
+}
+
+struct BanHistoryInner {
+    /// Remembered bans with their index in the column, oldest first.
+    entries: VecDeque<(u64, BanHistoryEntry)>,
+    next_index: u64,
+}
+
+/// Log of the peers banned by this node, oldest first. The log is persisted in
+/// `DBCol::BanHistory` so that it survives restarts. Clones share the same log.
+#[derive(Clone)]
+pub struct BanHistory {
+    store: Store,
+    inner: Arc<Mutex<BanHistoryInner>>,
+}
+
+impl BanHistory {
+    /// Loads the bans persisted in `store`.
+    pub(crate) fn new(store: Store) -> Self {
+        let mut entries = VecDeque::new();
+        for item in store.iter_prefix_ser::<StoredBan>(DBCol::BanHistory, &[]) {
+            let (key, ban) = match item {
+                Ok(item) => item,
+                Err(err) => {
+                    tracing::warn!(target: "client", ?err, "Failed to read the ban history");
+                    continue;
+                }
+            };
+            let Ok(index) = <[u8; 8]>::try_from(key.as_ref()).map(u64::from_be_bytes) else {
+                continue;
+            };
+            entries.push_back((
+                index,
+                BanHistoryEntry {
+                    peer_id: ban.peer_id,
+                    reason: ban.reason,
+                    triggered_by: ban.triggered_by,
+                    ban_time: from_timestamp(ban.ban_time),
+                },
+            ));
+        }
+        let next_index = entries.back().map_or(0, |(index, _)| index + 1);
+        Self { store, inner: Arc::new(Mutex::new(BanHistoryInner { entries, next_index })) }
+    }
+
+    /// Record that `peer_id` was banned. Oldest entries are dropped once the log is full.
+    pub(crate) fn record(
+        &self,
+        peer_id: PeerId,
+        reason: ReasonForBan,
+        triggered_by: Option<CryptoHash>,
+    ) {
+        metrics::PEER_BANNED_TOTAL.with_label_values(&[&format!("{:?}", reason)]).inc();
+        let entry = BanHistoryEntry {
+            peer_id,
+            reason: format!("{:?}", reason),
+            triggered_by,
+            ban_time: StaticClock::utc(),
+        };
+        let mut inner = self.inner.lock().unwrap();
+        let index = inner.next_index;
+        inner.next_index += 1;
+        let mut store_update = self.store.store_update();
+        while inner.entries.len() >= BAN_HISTORY_SIZE {
+            let (oldest, _) = inner.entries.pop_front().unwrap();
+            store_update.delete(DBCol::BanHistory, &oldest.to_be_bytes());
+        }
+        let stored = StoredBan {
+            peer_id: entry.peer_id.clone(),
+            reason: entry.reason.clone(),
+            triggered_by: entry.triggered_by,
+            ban_time: to_timestamp(entry.ban_time),
+        };
+        let result = store_update
+            .set_ser(DBCol::BanHistory, &index.to_be_bytes(), &stored)
+            .and_then(|()| store_update.commit());
+        if let Err(err) = result {
+            tracing::warn!(target: "client", ?err, "Failed to persist a ban");
+        }
+        inner.entries.push_back((index, entry));
+    }
+
+    /// Returns bans issued at or after `since`, or all remembered bans if `since` is None.
+    pub(crate) fn get(&self, since: Option<chrono::DateTime<chrono::Utc>>) -> Vec<BanHistoryEntry> {
+        self.inner
+            .lock()
+            .unwrap()
+            .entries
+            .iter()
+            .map(|(_, entry)| entry)
+            .filter(|entry| since.map_or(true, |since| entry.ban_time >= since))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Number of heights below the latest produced block for which the block production inputs are
+/// kept in `DBCol::BlockProductionInputs`.
+pub const PRODUCED_BLOCK_INPUTS_HORIZON: BlockHeight = 100_000;
+
+/// Inputs to block production which can't be recovered from the chain. They are persisted for
+/// recently produced blocks so that the exact same block can be rebuilt when auditing block
+/// production.
+#[derive(borsh::BorshSerialize, borsh::BorshDeserialize, Clone, Debug)]
+pub struct BlockProductionInputs {
+    pub prev_hash: CryptoHash,
+    /// New chunks included into the block, by shard.
+    pub new_chunks: HashMap<ShardId, ShardChunkHeader>,
+    /// Chunks which were ready for inclusion but were not included, e.g. because their producer is
+    /// banned.
+    pub excluded_chunks: Vec<ShardChunkHeader>,
+    /// Approvals for the previous block known to doomslug at the time of production.
+    pub approvals: HashMap<AccountId, Approval>,
+    #[borsh(serialize_with = "serialize_time", deserialize_with = "deserialize_time")]
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    #[borsh(serialize_with = "serialize_ratio", deserialize_with = "deserialize_ratio")]
+    pub gas_price_adjustment_rate: Rational32,
+    pub min_gas_price: Balance,
+    pub max_gas_price: Balance,
+}
+
+/// Returns the hash of the block this node produced at `height` and the inputs it was produced
+/// from, if they are still kept.
+pub(crate) fn load_produced_block_inputs(
+    store: &Store,
+    height: BlockHeight,
+) -> std::io::Result<Option<(CryptoHash, BlockProductionInputs)>> {
+    store.get_ser(DBCol::BlockProductionInputs, &height.to_be_bytes())
+}
+
+fn serialize_time<W: std::io::Write>(
+    time: &chrono::DateTime<chrono::Utc>,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    borsh::BorshSerialize::serialize(&to_timestamp(*time), writer)
+}
+
+fn deserialize_time<R: std::io::Read>(
+    reader: &mut R,
+) -> std::io::Result<chrono::DateTime<chrono::Utc>> {
+    Ok(from_timestamp(borsh::BorshDeserialize::deserialize_reader(reader)?))
+}
+
+fn serialize_ratio<W: std::io::Write>(ratio: &Rational32, writer: &mut W) -> std::io::Result<()> {
+    borsh::BorshSerialize::serialize(&(*ratio.numer(), *ratio.denom()), writer)
+}
+
+fn deserialize_ratio<R: std::io::Read>(reader: &mut R) -> std::io::Result<Rational32> {
+    let (numer, denom): (i32, i32) = borsh::BorshDeserialize::deserialize_reader(reader)?;
+    if denom == 0 {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "zero denominator"));
+    }
+    Ok(Rational32::new_raw(numer, denom))
+}
+
 impl Handler<WithSpanContext<DebugStatus>> for ClientActor {
     type Result = Result<DebugStatusResponse, StatusError>;
 
@@ -155,9 +441,10 @@ impl Handler<WithSpanContext<DebugStatus>> for ClientActor {
     ) -> Self::Result {
         let (_span, msg) = handler_debug_span!(target: "client", msg);
         match msg {
-            DebugStatus::SyncStatus => {
-                Ok(DebugStatusResponse::SyncStatus(self.client.sync_status.clone().into()))
-            }
+            DebugStatus::SyncStatus => Ok(DebugStatusResponse::SyncStatus(SyncStatusDebugView {
+                status: self.client.sync_status.clone().into(),
+                log: self.client.sync_debug_log.entries(),
+            })),
             DebugStatus::TrackedShards => {
                 Ok(DebugStatusResponse::TrackedShards(self.get_tracked_shards_view()?))
             }
@@ -179,6 +466,25 @@ impl Handler<WithSpanContext<DebugStatus>> for ClientActor {
             DebugStatus::ChainProcessingStatus => Ok(DebugStatusResponse::ChainProcessingStatus(
                 self.client.chain.get_chain_processing_info(),
             )),
+            DebugStatus::BanHistory(since) => {
+                Ok(DebugStatusResponse::BanHistory(self.client.ban_history(since)))
+            }
+            DebugStatus::ProductionReport => Ok(DebugStatusResponse::ProductionReport(
+                self.client.last_epoch_production_report()?,
+            )),
+            DebugStatus::SimulatedBlockProduction => {
+                let simulated = self.client.simulate_block_production()?;
+                Ok(DebugStatusResponse::SimulatedBlockProduction(simulated))
+            }
+            DebugStatus::MetricsSnapshot => {
+                Ok(DebugStatusResponse::MetricsSnapshot(crate::metrics::snapshot()))
+            }
+            DebugStatus::ReshardingLog => {
+                Ok(DebugStatusResponse::ReshardingLog(self.client.resharding_log.entries()))
+            }
+            DebugStatus::ClientConfigState => {
+                Ok(DebugStatusResponse::ClientConfigState(self.client.config_state()))
+            }
         }
     }
 }
@@ -335,6 +641,18 @@ impl ClientActor {
         let fetch_hash = self.client.chain.header_head()?.last_block_hash;
         let me = self.client.validator_signer.as_ref().map(|x| x.validator_id().clone());
         let shard_ids = self.client.epoch_manager.shard_ids(&epoch_id).unwrap();
+        let block_reference = BlockReference::BlockId(BlockId::Hash(fetch_hash));
+        let shard_uids = shard_ids
+            .iter()
+            .map(|&shard_id| {
+                resolve_shard_uid_at(
+                    self.client.chain.store(),
+                    self.client.epoch_manager.as_ref(),
+                    shard_id,
+                    &block_reference,
+                )
+            })
+            .collect::<Result<_, _>>()?;
         let shards_tracked_this_epoch = shard_ids
             .iter()
             .map(|&shard_id| {
@@ -352,7 +670,7 @@ impl ClientActor {
                 )
             })
             .collect();
-        Ok(TrackedShardsView { shards_tracked_this_epoch, shards_tracked_next_epoch })
+        Ok(TrackedShardsView { shard_uids, shards_tracked_this_epoch, shards_tracked_next_epoch })
     }
 
     fn get_recent_epoch_info(
@@ -579,7 +897,7 @@ impl ClientActor {
                             shard_id,
                             self.client
                                 .chunk_production_info
-                                .get(&(height, shard_id))
+                                .get(height, shard_id)
                                 .cloned()
                                 .unwrap_or_default(),
                         );
@@ -625,11 +943,9 @@ impl ClientActor {
                 .client
                 .do_not_include_chunks_from
                 .iter()
-                .map(|(k, _)| k.clone())
-                .sorted()
-                .group_by(|(k, _)| k.clone())
-                .into_iter()
-                .map(|(k, vs)| (k, vs.map(|(_, v)| v).collect()))
+                .map(|(epoch_id, chunk_producers)| {
+                    (epoch_id.clone(), chunk_producers.iter().cloned().sorted().collect())
+                })
                 .collect(),
         })
     }
@@ -721,3 +1037,90 @@ pub(crate) fn new_network_info_view(chain: &Chain, network_info: &NetworkInfo) -
             .collect::<Vec<_>>(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockProductionTracker, ChunkProductionTracker};
+    use chrono::TimeZone;
+    use near_client_primitives::debug::{ChunkCollection, ChunkProduction};
+    use near_primitives::static_clock::MockClockGuard;
+
+    #[test]
+    fn test_chunk_production_tracker_retention() {
+        let num_shards = 8;
+        let mut tracker = ChunkProductionTracker::new(100);
+        for height in 1..=5000 {
+            for shard_id in 0..num_shards {
+                tracker.record(height, shard_id, ChunkProduction::default());
+            }
+        }
+        assert_eq!(tracker.len(), 800);
+        for shard_id in 0..num_shards {
+            assert!(tracker.get(4900, shard_id).is_none());
+            assert!((4901..=5000).all(|height| tracker.get(height, shard_id).is_some()));
+        }
+
+        // A busy shard doesn't evict the chunks of the others.
+        let mut tracker = ChunkProductionTracker::new(100);
+        for height in 1..=10 {
+            tracker.record(height, 1, ChunkProduction::default());
+        }
+        for height in 1..=5000 {
+            tracker.record(height, 0, ChunkProduction::default());
+        }
+        assert_eq!(tracker.len(), 110);
+        assert!((1..=10).all(|height| tracker.get(height, 1).is_some()));
+        assert!(tracker.get(4900, 0).is_none());
+
+        // Recording a height again replaces its chunk.
+        tracker.record(5000, 0, ChunkProduction::default());
+        assert_eq!(tracker.len(), 110);
+    }
+
+    #[test]
+    fn test_block_production_tracker_capacity() {
+        let mut tracker = BlockProductionTracker::new(100);
+        for height in 1..=5000 {
+            tracker.record_approvals(height, Default::default());
+        }
+        assert_eq!(tracker.len(), 100);
+    }
+
+    /// A chunk received after the block was produced is recorded with the time it arrived.
+    #[test]
+    fn test_block_production_tracker_late_chunk() {
+        let block_time = chrono::Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let mut tracker = BlockProductionTracker::new(100);
+        tracker.record_approvals(10, Default::default());
+        let chunk_collections = (0..2)
+            .map(|shard_id| ChunkCollection {
+                chunk_producer: format!("test{shard_id}").parse().unwrap(),
+                received_time: (shard_id == 0)
+                    .then(|| block_time - chrono::Duration::milliseconds(250)),
+                chunk_included: shard_id == 0,
+            })
+            .collect();
+
+        let mock_clock_guard = MockClockGuard::default();
+        mock_clock_guard.add_utc(block_time);
+        mock_clock_guard.add_utc(block_time + chrono::Duration::milliseconds(400));
+        tracker.record_block_production(10, chunk_collections);
+        tracker.record_chunk_collected(10, 1);
+        // The chunk included in the block isn't overwritten.
+        tracker.record_chunk_collected(10, 0);
+        assert_eq!(mock_clock_guard.utc_call_count(), 2);
+        drop(mock_clock_guard);
+
+        let block_production = tracker.get(10);
+        assert_eq!(block_production.block_production_time, Some(block_time));
+        let received_times: Vec<_> = block_production
+            .chunks_collection_time
+            .iter()
+            .map(|chunk_collection| chunk_collection.received_time.unwrap() - block_time)
+            .collect();
+        assert_eq!(
+            received_times,
+            vec![chrono::Duration::milliseconds(-250), chrono::Duration::milliseconds(400)]
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Health of the node reported by the status endpoint.
+//!
+//! The health summarizes whether the node follows the chain and fulfills its validator duties
+//! into a single state, for load balancers and alerting. The reported state changes only once
+//! the new one was observed on `health_hysteresis_blocks` consecutive new heads, so that a
+//! single late or missed block doesn't make it flap. Critical conditions are reported right
+//! away, and so is a stale head, as no new heads update the health while the chain is stalled.
+use near_primitives::types::{BlockHeight, BlockHeightDelta, ShardId};
+use near_primitives::views::{NodeHealthFactor, NodeHealthState, NodeHealthView};
+
+/// Multiplier on `max_block_time` to wait until deciding that chain stalled.
+pub(crate) const STATUS_WAIT_TIME_MULTIPLIER: u64 = 10;
+
+/// A validator which sent no approval targeting one of this many heights below the head isn't
+/// sending approvals.
+pub(crate) const APPROVAL_RECENCY_HEIGHTS: BlockHeightDelta = 5;
+
+pub(crate) struct HealthTracker {
+    hysteresis: u64,
+    /// Health reported, `None` until the first new head.
+    status: Option<NodeHealthView>,
+    /// State other than the reported one, with the number of consecutive new heads it was
+    /// observed on.
+    candidate: Option<(NodeHealthState, u64)>,
+    /// Last height this node was scheduled to produce a block at, and whether the block is on the
+    /// chain.
+    last_block_production: Option<(BlockHeight, bool)>,
+    /// Last height this node was scheduled to produce chunks at, with the shards whose chunk
+    /// is missing.
+    last_chunk_production: Option<(BlockHeight, Vec<ShardId>)>,
+    /// Target height of the latest approval sent.
+    last_approval_height: Option<BlockHeight>,
+}
+
+impl HealthTracker {
+    pub(crate) fn new(hysteresis: u64) -> Self {
+        Self {
+            hysteresis,
+            status: None,
+            candidate: None,
+            last_block_production: None,
+            last_chunk_production: None,
+            last_approval_height: None,
+        }
+    }
+
+    pub(crate) fn record_block_production(&mut self, height: BlockHeight, produced: bool) {
+        self.last_block_production = Some((height, produced));
+    }
+
+    pub(crate) fn record_chunk_production(
+        &mut self,
+        height: BlockHeight,
+        missed_shard_ids: Vec<ShardId>,
+    ) {
+        self.last_chunk_production = Some((height, missed_shard_ids));
+    }
+
+    pub(crate) fn record_approval(&mut self, target_height: BlockHeight) {
+        self.last_approval_height = self.last_approval_height.max(Some(target_height));
+    }
+
+    pub(crate) fn last_approval_height(&self) -> Option<BlockHeight> {
+        self.last_approval_height
+    }
+
+    /// Missed block and chunk productions of the last scheduled heights.
+    pub(crate) fn production_factors(&self) -> Vec<NodeHealthFactor> {
+        let mut factors = vec![];
+        if let Some((height, false)) = self.last_block_production {
+            factors.push(NodeHealthFactor::MissedBlock { height });
+        }
+        if let Some((height, shard_ids)) = &self.last_chunk_production {
+            if !shard_ids.is_empty() {
+                factors.push(NodeHealthFactor::MissedChunks {
+                    height: *height,
+                    shard_ids: shard_ids.clone(),
+                });
+            }
+        }
+        factors
+    }
+
+    /// Updates the reported health with the one observed on a new head.
+    pub(crate) fn update(&mut self, observed: NodeHealthView) {
+        let Some(status) = &self.status else {
+            self.status = Some(observed);
+            return;
+        };
+        if observed.state == status.state || observed.state == NodeHealthState::Critical {
+            self.status = Some(observed);
+            self.candidate = None;
+            return;
+        }
+        let count = match self.candidate {
+            Some((state, count)) if state == observed.state => count + 1,
+            _ => 1,
+        };
+        if count >= self.hysteresis {
+            self.status = Some(observed);
+            self.candidate = None;
+        } else {
+            self.candidate = Some((observed.state, count));
+        }
+    }
+
+    /// Health to report given the `current` one, observed outside of the updates.
+    pub(crate) fn report(&self, current: NodeHealthView) -> NodeHealthView {
+        let immediate = current.state == NodeHealthState::Critical
+            || current
+                .factors
+                .iter()
+                .any(|factor| matches!(factor, NodeHealthFactor::HeadStale { .. }));
+        match &self.status {
+            Some(status) if !immediate => status.clone(),
+            _ => current,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HealthTracker;
+    use near_primitives::views::{NodeHealthFactor, NodeHealthState, NodeHealthView};
+
+    #[test]
+    fn test_health_hysteresis() {
+        let validating = || NodeHealthView::new(vec![]);
+        let missed = || NodeHealthView::new(vec![NodeHealthFactor::MissedBlock { height: 7 }]);
+        let mut tracker = HealthTracker::new(3);
+        let reported = |tracker: &HealthTracker| tracker.report(validating()).state;
+
+        tracker.update(validating());
+        assert_eq!(reported(&tracker), NodeHealthState::Validating);
+
+        // A degraded state is reported once observed on 3 heads in a row.
+        tracker.update(missed());
+        tracker.update(missed());
+        tracker.update(validating());
+        tracker.update(missed());
+        tracker.update(missed());
+        assert_eq!(reported(&tracker), NodeHealthState::Validating);
+        tracker.update(missed());
+        assert_eq!(reported(&tracker), NodeHealthState::Degraded);
+        assert_eq!(tracker.report(validating()), missed());
+
+        // So is the recovery.
+        tracker.update(validating());
+        tracker.update(validating());
+        assert_eq!(reported(&tracker), NodeHealthState::Degraded);
+        tracker.update(validating());
+        assert_eq!(reported(&tracker), NodeHealthState::Validating);
+
+        // Critical conditions and a stale head are reported right away.
+        let slashed = NodeHealthView::new(vec![NodeHealthFactor::Slashed { context: "".into() }]);
+        assert_eq!(tracker.report(slashed.clone()), slashed);
+        let stale = NodeHealthView::new(vec![NodeHealthFactor::HeadStale { head_age_millis: 1 }]);
+        assert_eq!(tracker.report(stale.clone()), stale);
+        tracker.update(slashed.clone());
+        assert_eq!(reported(&tracker), NodeHealthState::Critical);
+    }
+}
@@ -1,9 +1,9 @@
 pub use near_client_primitives::types::{
-    Error, GetBlock, GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree, GetChunk,
-    GetClientConfig, GetExecutionOutcome, GetExecutionOutcomeResponse,
-    GetExecutionOutcomesForBlock, GetGasPrice, GetMaintenanceWindows, GetNetworkInfo,
-    GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetSplitStorageInfo, GetStateChanges,
-    GetStateChangesInBlock, GetStateChangesWithCauseInBlock,
+    BlockProducerErrorKind, ChunkProducerErrorKind, Error, ErrorSeverity, GetBlock, GetBlockProof,
+    GetBlockProofResponse, GetBlockWithMerkleTree, GetChunk, GetClientConfig, GetExecutionOutcome,
+    GetExecutionOutcomeResponse, GetExecutionOutcomesForBlock, GetGasPrice, GetMaintenanceWindows,
+    GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetSplitStorageInfo,
+    GetStateChanges, GetStateChangesInBlock, GetStateChangesWithCauseInBlock,
     GetStateChangesWithCauseInBlockForTrackedShards, GetValidatorInfo, GetValidatorOrdered, Query,
     QueryError, Status, StatusResponse, SyncStatus, TxStatus, TxStatusError,
 };
@@ -11,26 +11,45 @@ pub use near_client_primitives::types::{
 pub use crate::adapter::{
     BlockApproval, BlockResponse, ProcessTxRequest, ProcessTxResponse, SetNetworkInfo,
 };
-pub use crate::client::Client;
+pub use crate::block_provenance::BlockProvenanceRecord;
+pub use crate::client::{Client, ClientState, GcMode};
 #[cfg(feature = "test_features")]
 pub use crate::client_actor::NetworkAdversarialMessage;
 pub use crate::client_actor::{start_client, ClientActor};
+pub use crate::client_ops::ClientOps;
 pub use crate::config_updater::ConfigUpdater;
+pub use crate::metrics::snapshot as metrics_snapshot;
 pub use crate::sync::adapter::{SyncAdapter, SyncMessage};
+pub use crate::tx_admission_policy::{
+    NoopTxAdmissionPolicy, TxAdmissionContext, TxAdmissionPolicy, TxAdmissionStage,
+};
 pub use crate::view_client::{start_view_client, ViewClientActor};
 pub use near_client_primitives::debug::DebugStatus;
 
 pub mod adapter;
 pub mod adversarial;
+mod block_provenance;
+mod chain_heads_throttle;
+pub mod chunk_persister;
+pub mod chunk_producer_blocklist;
 mod client;
 mod client_actor;
+mod client_ops;
 mod config_updater;
 pub mod debug;
+mod health;
 mod info;
 mod metrics;
+mod production_report;
+pub mod resharding_log;
+pub mod store_audit;
 pub mod sync;
 mod sync_jobs_actor;
 pub mod test_utils;
 #[cfg(test)]
 mod tests;
+mod tx_admission_policy;
+mod tx_lanes;
+mod tx_reintroduction;
+mod verified_tx_cache;
 mod view_client;
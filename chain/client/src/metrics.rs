@@ -1,3 +1,5 @@
+use near_o11y::metrics::prometheus::core::Collector;
+use near_o11y::metrics::prometheus::proto::{LabelPair, MetricType};
 use near_o11y::metrics::{
     exponential_buckets, try_create_counter, try_create_gauge, try_create_histogram,
     try_create_histogram_vec, try_create_int_counter, try_create_int_counter_vec,
@@ -5,6 +7,7 @@ use near_o11y::metrics::{
     IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
 };
 use once_cell::sync::Lazy;
+use std::collections::HashMap;
 
 pub(crate) static BLOCK_PRODUCED_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
     try_create_int_counter(
@@ -14,6 +17,70 @@ pub(crate) static BLOCK_PRODUCED_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
     .unwrap()
 });
 
+pub(crate) static EMPTY_CHUNKS_SUBSTITUTED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_empty_chunks_substituted_total",
+        "Number of empty chunks produced in place of chunks whose production failed with a \
+         retryable error",
+        &["shard_id"],
+    )
+    .unwrap()
+});
+
+pub(crate) static RESHARDING_EVENTS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_resharding_events_total",
+        "Resharding steps taken by this node, by the kind of the step",
+        &["event"],
+    )
+    .unwrap()
+});
+
+pub(crate) static DROPPED_BELOW_GC_TAIL_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_dropped_below_gc_tail_total",
+        "Approvals and chunk headers dropped because they build on a block below the garbage \
+         collection tail, by whether they were received, or pending and purged by the garbage \
+         collection",
+        &["kind"],
+    )
+    .unwrap()
+});
+
+pub(crate) static BLOCK_ACCEPTED_BY_PROVENANCE: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_block_accepted_by_provenance_total",
+        "Blocks accepted by this node, by whether it produced them, received them unsolicited or \
+         received them in response to its request",
+        &["provenance"],
+    )
+    .unwrap()
+});
+
+pub(crate) static HEAD_EPOCH_MISMATCH_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_head_epoch_mismatch_total",
+        "Number of times the epoch of the chain head didn't match the epoch manager when producing a block",
+    )
+    .unwrap()
+});
+
+pub(crate) static NEXT_BP_HASH_MISMATCH_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_next_bp_hash_mismatch_total",
+        "Number of epoch-boundary blocks whose next block producers hash didn't match the epoch manager",
+    )
+    .unwrap()
+});
+
+pub(crate) static UPDATE_CHAIN_HEADS_SUPPRESSED_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_update_chain_heads_suppressed_total",
+        "Number of chain heads updates not sent to the ShardsManager because the heads didn't change or were batched during sync",
+    )
+    .unwrap()
+});
+
 pub(crate) static CHUNK_PRODUCED_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
     try_create_int_counter(
         "near_chunk_produced_total",
@@ -22,6 +89,16 @@ pub(crate) static CHUNK_PRODUCED_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
     .unwrap()
 });
 
+pub(crate) static CHUNK_EXTRA_MISMATCH: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_chunk_extra_mismatch_total",
+        "Number of times chunk production was refused because the chunk extra of the previous \
+         block didn't match the trie changes recorded when the shard was applied",
+        &["shard_id"],
+    )
+    .unwrap()
+});
+
 pub(crate) static IS_VALIDATOR: Lazy<IntGauge> = Lazy::new(|| {
     try_create_int_gauge(
         "near_is_validator",
@@ -47,6 +124,22 @@ pub(crate) static IS_CHUNK_PRODUCER_FOR_SHARD: Lazy<IntGaugeVec> = Lazy::new(||
     .unwrap()
 });
 
+pub(crate) static BLOCK_PRODUCTION_INFO_SIZE: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_block_production_info_size",
+        "Number of heights for which the block production timings are kept for debug purposes",
+    )
+    .unwrap()
+});
+
+pub(crate) static CHUNK_PRODUCTION_INFO_SIZE: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_chunk_production_info_size",
+        "Number of chunks across all shards for which the production timings are kept for debug purposes",
+    )
+    .unwrap()
+});
+
 pub(crate) static RECEIVED_BYTES_PER_SECOND: Lazy<IntGauge> = Lazy::new(|| {
     try_create_int_gauge(
         "near_received_bytes_per_second",
@@ -201,6 +294,23 @@ pub(crate) static PEERS_WITH_INVALID_HASH: Lazy<IntGauge> = Lazy::new(|| {
         .unwrap()
 });
 
+pub(crate) static GENESIS_MISMATCH: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_genesis_mismatch",
+        "Whether most of the peers advertise a genesis different from ours",
+    )
+    .unwrap()
+});
+
+pub(crate) static PEER_BANNED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_client_peer_banned_total",
+        "Number of peers banned by the client, by ban reason",
+        &["reason"],
+    )
+    .unwrap()
+});
+
 pub(crate) static CHUNK_SKIPPED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
     try_create_int_counter_vec(
         "near_chunk_skipped_total",
@@ -218,6 +328,23 @@ pub(crate) static CHUNK_PRODUCER_BANNED_FOR_EPOCH: Lazy<IntCounter> = Lazy::new(
     .unwrap()
 });
 
+pub(crate) static RECOVERY_BURST_BLOCKS_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_recovery_burst_blocks_total",
+        "Number of blocks broadcast again because the head progressed after a stall",
+    )
+    .unwrap()
+});
+
+pub(crate) static PRODUCTION_ERRORS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_client_production_errors_total",
+        "Number of failed block and chunk production attempts, by producer and error severity",
+        &["producer", "severity"],
+    )
+    .unwrap()
+});
+
 pub(crate) static CHUNK_DROPPED_BECAUSE_OF_BANNED_CHUNK_PRODUCER: Lazy<IntCounter> =
     Lazy::new(|| {
         try_create_int_counter(
@@ -338,6 +465,58 @@ pub(crate) static TRANSACTION_RECEIVED_NON_VALIDATOR_FORWARDED: Lazy<IntGauge> =
     .unwrap()
 });
 
+pub(crate) static TRANSACTION_NOT_CAUGHT_UP: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_transaction_not_caught_up_total",
+        "Forwarded transactions received for a shard the node hasn't caught up with yet, by \
+         whether they were buffered, dropped or processed again after the catchup",
+        &["shard_id", "status"],
+    )
+    .unwrap()
+});
+
+pub(crate) static TRANSACTION_FORWARD_REROUTED: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_transaction_forward_rerouted_total",
+        "Transactions forwarded to a later chunk producer of the shard because the scheduled one \
+         is banned for producing invalid chunks",
+        &["shard_id"],
+    )
+    .unwrap()
+});
+
+pub(crate) static TRANSACTION_LANE_ADMISSION: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_transaction_lane_admission_total",
+        "Transactions received by the validator, by whether they were submitted directly or \
+         forwarded, and by whether they were added to the pool or rejected because of the rate \
+         budget or the pool space of their lane",
+        &["lane", "result"],
+    )
+    .unwrap()
+});
+
+pub(crate) static TRANSACTION_REINTRODUCTION_SKIPPED: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_transaction_reintroduction_skipped_total",
+        "Transactions of the blocks abandoned by a reorg which weren't added back to the pool, by \
+         whether they were no longer valid on the new chain or didn't fit into the reintroduction \
+         budget",
+        &["reason"],
+    )
+    .unwrap()
+});
+
+pub(crate) static TRANSACTION_REJECTED_BY_POLICY: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_transaction_rejected_by_policy_total",
+        "Transactions rejected by the transaction admission policy, by whether they were about to \
+         be added to the pool or included in a chunk",
+        &["stage"],
+    )
+    .unwrap()
+});
+
 pub(crate) static NODE_PROTOCOL_VERSION: Lazy<IntGauge> = Lazy::new(|| {
     try_create_int_gauge("near_node_protocol_version", "Max protocol version supported by the node")
         .unwrap()
@@ -348,6 +527,14 @@ pub(crate) static CURRENT_PROTOCOL_VERSION: Lazy<IntGauge> = Lazy::new(|| {
         .unwrap()
 });
 
+pub(crate) static INCOMPATIBLE_NETWORK_PROTOCOL_VERSION: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_incompatible_network_protocol_version",
+        "Protocol version of the network if it is newer than the node supports, 0 otherwise",
+    )
+    .unwrap()
+});
+
 pub(crate) static NODE_PROTOCOL_UPGRADE_VOTING_START: Lazy<IntGauge> = Lazy::new(|| {
     try_create_int_gauge(
         "near_node_protocol_upgrade_voting_start",
@@ -365,6 +552,27 @@ pub(crate) static PRODUCE_CHUNK_TIME: Lazy<HistogramVec> = Lazy::new(|| {
     .unwrap()
 });
 
+#[cfg(feature = "protocol_feature_chunk_validation")]
+pub(crate) static CHUNK_STATE_WITNESS_SIZE: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_chunk_state_witness_size_bytes",
+        "Size of the trie nodes recorded while applying the previous chunk of a produced chunk",
+        &["shard_id"],
+        Some(exponential_buckets(1000.0, 2.0, 20).unwrap()),
+    )
+    .unwrap()
+});
+
+#[cfg(feature = "protocol_feature_chunk_validation")]
+pub(crate) static CHUNK_STATE_WITNESS_SIZE_OVER_SOFT_LIMIT: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_chunk_state_witness_size_over_soft_limit_total",
+        "Number of produced chunks whose state witness size exceeded the soft limit",
+        &["shard_id"],
+    )
+    .unwrap()
+});
+
 pub(crate) static VIEW_CLIENT_MESSAGE_TIME: Lazy<HistogramVec> = Lazy::new(|| {
     try_create_histogram_vec(
         "near_view_client_messages_processing_time",
@@ -404,6 +612,22 @@ pub(crate) fn export_version(neard_version: &near_primitives::version::Version)
         .inc();
 }
 
+pub(crate) static CATCHUP_STATE_SYNCS: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_catchup_state_syncs",
+        "Number of state syncs for the next epoch currently in progress",
+    )
+    .unwrap()
+});
+
+pub(crate) static CATCHUP_STATE_SYNCS_STALE: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_catchup_state_syncs_stale_total",
+        "Number of unfinished catchup state syncs dropped because they became stale",
+    )
+    .unwrap()
+});
+
 pub(crate) static STATE_SYNC_STAGE: Lazy<IntGaugeVec> = Lazy::new(|| {
     try_create_int_gauge_vec(
         "near_state_sync_stage",
@@ -524,3 +748,128 @@ pub(crate) static STATE_SYNC_DUMP_LIST_OBJECT_ELAPSED: Lazy<HistogramVec> = Lazy
     )
     .unwrap()
 });
+
+/// The metrics of this crate, which make up the snapshot.
+fn client_metrics() -> Vec<&'static dyn Collector> {
+    vec![
+        &*BLOCK_PRODUCED_TOTAL,
+        &*EMPTY_CHUNKS_SUBSTITUTED_TOTAL,
+        &*RESHARDING_EVENTS_TOTAL,
+        &*DROPPED_BELOW_GC_TAIL_TOTAL,
+        &*BLOCK_ACCEPTED_BY_PROVENANCE,
+        &*HEAD_EPOCH_MISMATCH_TOTAL,
+        &*NEXT_BP_HASH_MISMATCH_TOTAL,
+        &*UPDATE_CHAIN_HEADS_SUPPRESSED_TOTAL,
+        &*CHUNK_PRODUCED_TOTAL,
+        &*CHUNK_EXTRA_MISMATCH,
+        &*IS_VALIDATOR,
+        &*IS_BLOCK_PRODUCER,
+        &*IS_CHUNK_PRODUCER_FOR_SHARD,
+        &*BLOCK_PRODUCTION_INFO_SIZE,
+        &*CHUNK_PRODUCTION_INFO_SIZE,
+        &*RECEIVED_BYTES_PER_SECOND,
+        &*SENT_BYTES_PER_SECOND,
+        &*CPU_USAGE,
+        &*MEMORY_USAGE,
+        &*GC_TIME,
+        &*TGAS_USAGE_HIST,
+        &*VALIDATORS_CHUNKS_PRODUCED,
+        &*VALIDATORS_CHUNKS_EXPECTED,
+        &*VALIDATORS_CHUNKS_PRODUCED_BY_SHARD,
+        &*VALIDATORS_CHUNKS_EXPECTED_BY_SHARD,
+        &*VALIDATORS_CHUNKS_EXPECTED_IN_EPOCH,
+        &*VALIDATORS_BLOCKS_PRODUCED,
+        &*VALIDATORS_BLOCKS_EXPECTED,
+        &*VALIDATORS_BLOCKS_EXPECTED_IN_EPOCH,
+        &*BLOCK_PRODUCER_STAKE,
+        &*TRACKED_SHARDS,
+        &*SYNC_STATUS,
+        &*EPOCH_HEIGHT,
+        &*FINAL_BLOCK_HEIGHT_IN_EPOCH,
+        &*PROTOCOL_UPGRADE_BLOCK_HEIGHT,
+        &*PEERS_WITH_INVALID_HASH,
+        &*GENESIS_MISMATCH,
+        &*PEER_BANNED_TOTAL,
+        &*CHUNK_SKIPPED_TOTAL,
+        &*CHUNK_PRODUCER_BANNED_FOR_EPOCH,
+        &*RECOVERY_BURST_BLOCKS_TOTAL,
+        &*PRODUCTION_ERRORS_TOTAL,
+        &*CHUNK_DROPPED_BECAUSE_OF_BANNED_CHUNK_PRODUCER,
+        &*CLIENT_MESSAGES_COUNT,
+        &*CLIENT_MESSAGES_PROCESSING_TIME,
+        &*CHECK_TRIGGERS_TIME,
+        &*CLIENT_TRIGGER_TIME_BY_TYPE,
+        &*GAS_USED,
+        &*BLOCKS_PROCESSED,
+        &*CHUNKS_PROCESSED,
+        &*GAS_PRICE,
+        &*BALANCE_BURNT,
+        &*TOTAL_SUPPLY,
+        &*FINAL_BLOCK_HEIGHT,
+        &*FINAL_DOOMSLUG_BLOCK_HEIGHT,
+        &*NODE_DB_VERSION,
+        &*NODE_BUILD_INFO,
+        &*TRANSACTION_RECEIVED_VALIDATOR,
+        &*TRANSACTION_RECEIVED_NON_VALIDATOR,
+        &*TRANSACTION_RECEIVED_NON_VALIDATOR_FORWARDED,
+        &*TRANSACTION_NOT_CAUGHT_UP,
+        &*TRANSACTION_FORWARD_REROUTED,
+        &*TRANSACTION_LANE_ADMISSION,
+        &*TRANSACTION_REINTRODUCTION_SKIPPED,
+        &*TRANSACTION_REJECTED_BY_POLICY,
+        &*NODE_PROTOCOL_VERSION,
+        &*CURRENT_PROTOCOL_VERSION,
+        &*INCOMPATIBLE_NETWORK_PROTOCOL_VERSION,
+        &*NODE_PROTOCOL_UPGRADE_VOTING_START,
+        &*PRODUCE_CHUNK_TIME,
+        &*CHUNK_STATE_WITNESS_SIZE,
+        &*CHUNK_STATE_WITNESS_SIZE_OVER_SOFT_LIMIT,
+        &*VIEW_CLIENT_MESSAGE_TIME,
+        &*PRODUCE_AND_DISTRIBUTE_CHUNK_TIME,
+        &*CATCHUP_STATE_SYNCS,
+        &*CATCHUP_STATE_SYNCS_STALE,
+        &*STATE_SYNC_STAGE,
+        &*STATE_SYNC_RETRY_PART,
+        &*STATE_SYNC_HEADER_ERROR,
+        &*STATE_SYNC_HEADER_TIMEOUT,
+        &*STATE_SYNC_PARTS_DONE,
+        &*STATE_SYNC_PARTS_TOTAL,
+        &*STATE_SYNC_DISCARD_PARTS,
+        &*STATE_SYNC_EXTERNAL_PARTS_DONE,
+        &*STATE_SYNC_EXTERNAL_PARTS_FAILED,
+        &*STATE_SYNC_EXTERNAL_PARTS_REQUEST_DELAY,
+        &*STATE_SYNC_EXTERNAL_PARTS_SIZE_DOWNLOADED,
+        &*STATE_SYNC_DUMP_PUT_OBJECT_ELAPSED,
+        &*STATE_SYNC_DUMP_LIST_OBJECT_ELAPSED,
+    ]
+}
+
+/// Returns the current values of the metrics of this crate, keyed by the metric name followed by
+/// its labels as in the Prometheus text format, e.g. `near_client_tracked_shards{shard_id="0"}`.
+/// Counters and gauges map to their value, histograms to the sum of their observations.
+pub fn snapshot() -> HashMap<String, f64> {
+    let mut snapshot = HashMap::new();
+    for family in client_metrics().into_iter().flat_map(|collector| collector.collect()) {
+        for metric in family.get_metric() {
+            let value = match family.get_field_type() {
+                MetricType::COUNTER => metric.get_counter().get_value(),
+                MetricType::GAUGE => metric.get_gauge().get_value(),
+                MetricType::HISTOGRAM => metric.get_histogram().get_sample_sum(),
+                MetricType::SUMMARY | MetricType::UNTYPED => continue,
+            };
+            snapshot.insert(metric_key(family.get_name(), metric.get_label()), value);
+        }
+    }
+    snapshot
+}
+
+fn metric_key(name: &str, labels: &[LabelPair]) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    let labels: Vec<_> = labels
+        .iter()
+        .map(|label| format!("{}=\"{}\"", label.get_name(), label.get_value()))
+        .collect();
+    format!("{}{{{}}}", name, labels.join(","))
+}
@@ -0,0 +1,142 @@
+//! Block and chunk production report of an epoch this node was a validator in.
+//!
+//! Building a report looks up the canonical block and the producers of every height of the
+//! epoch, so it's not done on the client thread as part of block processing. The client takes a
+//! `ProductionReportJob`, a copy of what it recorded in memory about the heights of the epoch,
+//! and the report is built from it either on demand, for the debug endpoint, or on a background
+//! thread when the epoch ends.
+use crate::debug::load_produced_block_inputs;
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_client_primitives::debug::{
+    BlockProduction, ChunkProduction, EpochProductionReport, ProductionReportEntry,
+};
+use near_client_primitives::types::Error;
+use near_epoch_manager::EpochManagerAdapter;
+use near_primitives::types::{AccountId, BlockHeight, EpochId, ShardId};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// What the client knows about the production of the heights of an epoch, up to its last block.
+pub(crate) struct ProductionReportJob {
+    pub epoch_id: EpochId,
+    pub validator: AccountId,
+    pub first_height: BlockHeight,
+    pub last_height: BlockHeight,
+    pub is_final: bool,
+    pub blocks: HashMap<BlockHeight, BlockProduction>,
+    pub chunks: HashMap<(BlockHeight, ShardId), ChunkProduction>,
+    pub skip_reasons: HashMap<(BlockHeight, Option<ShardId>), String>,
+}
+
+impl ProductionReportJob {
+    /// Collects what this node produced at the heights of the epoch and whether it made it on
+    /// chain.
+    pub fn build(
+        &self,
+        chain_store: &ChainStore,
+        epoch_manager: &dyn EpochManagerAdapter,
+    ) -> Result<EpochProductionReport, Error> {
+        let epoch_id = &self.epoch_id;
+        let num_shards = epoch_manager.num_shards(epoch_id)?;
+        let mut entries = vec![];
+        for height in self.first_height..=self.last_height {
+            // The height is in our epoch, so a canonical block at the height is the one we're
+            // responsible for, if any.
+            let block = chain_store
+                .get_block_hash_by_height(height)
+                .and_then(|block_hash| chain_store.get_block(&block_hash))
+                .ok();
+            if epoch_manager.get_block_producer(epoch_id, height)? == self.validator {
+                let production = self.blocks.get(&height).cloned().unwrap_or_default();
+                let inputs = load_produced_block_inputs(chain_store.store(), height)
+                    .map_err(near_chain::Error::from)?;
+                let included = block.is_some();
+                let produced =
+                    included || inputs.is_some() || production.block_production_time.is_some();
+                let production_time = production
+                    .block_production_time
+                    .or_else(|| inputs.as_ref().map(|(_, inputs)| inputs.timestamp));
+                let production_duration_millis =
+                    match (production.approvals.ready_at, production.block_production_time) {
+                        (Some(ready_at), Some(produced_at)) => {
+                            Some((produced_at - ready_at).num_milliseconds().max(0) as u64)
+                        }
+                        _ => None,
+                    };
+                let missed_reason = if included {
+                    None
+                } else if produced {
+                    Some("Produced block is not on the canonical chain".to_string())
+                } else if let Some(reason) = self.skip_reasons.get(&(height, None)) {
+                    Some(reason.clone())
+                } else if !production.approvals.approvals.is_empty()
+                    && production.approvals.ready_at.is_none()
+                {
+                    Some("Not enough approvals".to_string())
+                } else {
+                    Some("Block was not produced".to_string())
+                };
+                entries.push(ProductionReportEntry {
+                    height,
+                    shard_id: None,
+                    produced,
+                    production_time,
+                    production_duration_millis,
+                    included,
+                    missed_reason,
+                });
+            }
+            for shard_id in 0..num_shards {
+                if epoch_manager.get_chunk_producer(epoch_id, height, shard_id)? != self.validator {
+                    continue;
+                }
+                let production = self.chunks.get(&(height, shard_id));
+                let included = block.as_ref().map_or(false, |block| {
+                    block
+                        .chunks()
+                        .get(shard_id as usize)
+                        .map_or(false, |chunk| chunk.height_included() == height)
+                });
+                let produced = included || production.is_some();
+                let missed_reason = if included {
+                    None
+                } else if produced && block.is_none() {
+                    Some("No block at this height".to_string())
+                } else if produced {
+                    Some("Produced chunk is not included in the block".to_string())
+                } else {
+                    let reason = self.skip_reasons.get(&(height, Some(shard_id)));
+                    Some(reason.cloned().unwrap_or_else(|| "Chunk was not produced".to_string()))
+                };
+                entries.push(ProductionReportEntry {
+                    height,
+                    shard_id: Some(shard_id),
+                    produced,
+                    production_time: production.and_then(|p| p.chunk_production_time),
+                    production_duration_millis: production
+                        .and_then(|p| p.chunk_production_duration_millis),
+                    included,
+                    missed_reason,
+                });
+            }
+        }
+        Ok(EpochProductionReport {
+            epoch_id: epoch_id.clone(),
+            validator: self.validator.clone(),
+            first_height: self.first_height,
+            last_height: self.last_height,
+            is_final: self.is_final,
+            entries,
+        })
+    }
+}
+
+/// Writes `report` to `path` as JSON, replacing the report written before.
+pub(crate) fn write_production_report(
+    path: &Path,
+    report: &EpochProductionReport,
+) -> Result<(), Error> {
+    let json = serde_json::to_vec_pretty(report).map_err(std::io::Error::from)?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
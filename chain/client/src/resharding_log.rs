@@ -0,0 +1,55 @@
+use crate::metrics;
+use near_client_primitives::debug::{ReshardingEvent, ReshardingLogEntry};
+use near_primitives::static_clock::StaticClock;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Number of resharding events kept by the client.
+pub const RESHARDING_LOG_CAPACITY: usize = 1000;
+
+fn event_label(event: &ReshardingEvent) -> &'static str {
+    match event {
+        ReshardingEvent::ShardLayoutChangeDetected { .. } => "layout_change_detected",
+        ReshardingEvent::PoolResharded { .. } => "pool_resharded",
+        ReshardingEvent::ShardLayoutLookupFailed { .. } => "layout_lookup_failed",
+        ReshardingEvent::SplitScheduled { .. } => "split_scheduled",
+        ReshardingEvent::SplitCompleted { .. } => "split_completed",
+        ReshardingEvent::SplitFailed { .. } => "split_failed",
+    }
+}
+
+/// Bounded log of the resharding steps taken by the client and by the state sync it runs, shown
+/// on the resharding debug page. Clones share the same log.
+#[derive(Clone)]
+pub struct ReshardingLog {
+    entries: Arc<Mutex<VecDeque<ReshardingLogEntry>>>,
+    capacity: usize,
+}
+
+impl ReshardingLog {
+    pub fn new(capacity: usize) -> Self {
+        Self { entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))), capacity }
+    }
+
+    /// Appends `event` and counts it in the metrics, dropping the oldest event if the log is
+    /// full.
+    pub fn record(&self, event: ReshardingEvent) {
+        metrics::RESHARDING_EVENTS_TOTAL.with_label_values(&[event_label(&event)]).inc();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(ReshardingLogEntry { time: StaticClock::utc(), event });
+    }
+
+    /// Returns the logged events, oldest first.
+    pub fn entries(&self) -> Vec<ReshardingLogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl Default for ReshardingLog {
+    fn default() -> Self {
+        Self::new(RESHARDING_LOG_CAPACITY)
+    }
+}
@@ -0,0 +1,103 @@
+//! Audit log of the writes to the store which the client makes directly rather than through the
+//! chain, to tell which component wrote what last when debugging a corrupted store.
+use chrono::{DateTime, Utc};
+use near_primitives::hash::CryptoHash;
+use near_primitives::sharding::ChunkHash;
+use near_primitives::static_clock::StaticClock;
+use near_primitives::types::{BlockHeight, ShardId};
+use near_store::DBCol;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use tracing::debug;
+
+/// Number of the latest writes kept in the audit log.
+pub const STORE_AUDIT_LOG_CAPACITY: usize = 1000;
+
+/// A write of the client to the store, with the block or chunk it was made for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreWrite {
+    /// The latest known height, saved once the block at that height is produced.
+    LatestKnown { height: BlockHeight, block_hash: CryptoHash },
+    /// The largest height endorsed by this node, saved before its approvals are sent.
+    LargestTargetHeight { height: BlockHeight },
+    /// A chunk which the ShardsManager found invalid.
+    InvalidChunk { chunk_hash: ChunkHash, shard_id: ShardId, height_created: BlockHeight },
+    /// A chunk produced by this node or completed by the ShardsManager.
+    Chunk { chunk_hash: ChunkHash, shard_id: ShardId, height_created: BlockHeight },
+    /// The inputs of a block produced by this node.
+    BlockProductionInputs { height: BlockHeight, block_hash: CryptoHash },
+    /// The latest known height, moved ahead by a fast forward of the sandbox.
+    SandboxLatestKnown { height: BlockHeight },
+    /// The largest target height, the latest known height and the head, all switched to the
+    /// same height by the adversarial controls.
+    AdvSwitchToHeight { height: BlockHeight },
+}
+
+impl StoreWrite {
+    /// Columns written.
+    pub fn columns(&self) -> &'static [DBCol] {
+        match self {
+            Self::LatestKnown { .. }
+            | Self::LargestTargetHeight { .. }
+            | Self::SandboxLatestKnown { .. }
+            | Self::AdvSwitchToHeight { .. } => &[DBCol::BlockMisc],
+            Self::InvalidChunk { .. } => &[DBCol::InvalidChunks],
+            Self::Chunk { .. } => &[DBCol::PartialChunks, DBCol::Chunks],
+            Self::BlockProductionInputs { .. } => &[DBCol::BlockProductionInputs],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreAuditEntry {
+    pub time: DateTime<Utc>,
+    pub write: StoreWrite,
+    /// Set if the write failed.
+    pub error: Option<String>,
+}
+
+/// Bounded log of the latest writes of the client to the store, oldest first.
+pub struct StoreAuditLog {
+    entries: VecDeque<StoreAuditEntry>,
+    capacity: usize,
+}
+
+impl StoreAuditLog {
+    pub fn new(capacity: usize) -> Self {
+        Self { entries: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    /// Runs `write_fn`, which makes the `write` to the store, and records the write along with
+    /// its outcome.
+    pub(crate) fn audited<T, E: Display>(
+        &mut self,
+        write: StoreWrite,
+        write_fn: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let result = write_fn();
+        self.record(write, result.as_ref().err());
+        result
+    }
+
+    /// Records a `write` made elsewhere, e.g. by the chunk writer threads, and the error it
+    /// failed with, if any.
+    pub(crate) fn record<E: Display>(&mut self, write: StoreWrite, error: Option<E>) {
+        let error = error.map(|err| err.to_string());
+        debug!(target: "client", ?write, columns = ?write.columns(), ?error, "Client wrote to the store");
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(StoreAuditEntry { time: StaticClock::utc(), write, error });
+    }
+
+    /// Returns the recorded writes, oldest first.
+    pub fn entries(&self) -> impl Iterator<Item = &StoreAuditEntry> {
+        self.entries.iter()
+    }
+}
+
+impl Default for StoreAuditLog {
+    fn default() -> Self {
+        Self::new(STORE_AUDIT_LOG_CAPACITY)
+    }
+}
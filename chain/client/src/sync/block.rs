@@ -1,7 +1,9 @@
+use crate::sync::debug_log::SyncDebugLog;
 use chrono::{DateTime, Duration, Utc};
 use near_async::messaging::CanSend;
 use near_chain::Chain;
 use near_chain::{check_known, ChainStoreAccess};
+use near_client_primitives::debug::SyncEvent;
 use near_client_primitives::types::SyncStatus;
 use near_network::types::PeerManagerMessageRequest;
 use near_network::types::{HighestHeightPeerInfo, NetworkRequests, PeerManagerAdapter};
@@ -34,6 +36,9 @@ pub struct BlockSync {
     archive: bool,
     /// Whether State Sync should be enabled when a node falls far enough behind.
     state_sync_enabled: bool,
+    /// Blocks above this height are not requested.
+    sync_until_height: Option<BlockHeight>,
+    debug_log: SyncDebugLog,
 }
 
 impl BlockSync {
@@ -42,6 +47,8 @@ impl BlockSync {
         block_fetch_horizon: BlockHeightDelta,
         archive: bool,
         state_sync_enabled: bool,
+        sync_until_height: Option<BlockHeight>,
+        debug_log: SyncDebugLog,
     ) -> Self {
         BlockSync {
             network_adapter,
@@ -49,6 +56,8 @@ impl BlockSync {
             block_fetch_horizon,
             archive,
             state_sync_enabled,
+            sync_until_height,
+            debug_log,
         }
     }
 
@@ -112,6 +121,10 @@ impl BlockSync {
                     state_sync_enabled = self.state_sync_enabled,
                     block_fetch_horizon = self.block_fetch_horizon,
                     "Switched from block sync to state sync");
+                self.debug_log.record(SyncEvent::StateSyncNeeded {
+                    head_height: head.height,
+                    header_head_height: header_head.height,
+                });
                 // Epochs are different and we are too far from horizon, State Sync is needed
                 return Ok(true);
             }
@@ -194,6 +207,9 @@ impl BlockSync {
             }
             if let Ok(()) = check_known(chain, &next_hash)? {
                 let next_height = chain.get_block_header(&next_hash)?.height();
+                if self.sync_until_height.map_or(false, |height| next_height > height) {
+                    break;
+                }
                 requests.push((next_height, next_hash));
             }
         }
@@ -216,12 +232,22 @@ impl BlockSync {
             if let Some(peer) = peer {
                 debug!(target: "sync", "Block sync: {}/{} requesting block {} at height {} from {} (out of {} peers)",
                        chain_head.height, header_head.height, hash, height, peer.peer_info.id, highest_height_peers.len());
+                self.debug_log.record(SyncEvent::BlockRequested {
+                    peer_id: peer.peer_info.id.clone(),
+                    height,
+                    hash,
+                });
                 self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
                     NetworkRequests::BlockRequest { hash, peer_id: peer.peer_info.id.clone() },
                 ));
             } else {
                 warn!(target: "sync", "Block sync: {}/{} No available {}peers to request block {} from",
                       chain_head.height, header_head.height, if request_from_archival { "archival " } else { "" }, hash);
+                self.debug_log.record(SyncEvent::NoPeerForBlock {
+                    height,
+                    hash,
+                    archival: request_from_archival,
+                });
             }
         }
 
@@ -306,8 +332,14 @@ mod test {
         let mut capture = TracingCapture::enable();
         let network_adapter = Arc::new(MockPeerManagerAdapter::default());
         let block_fetch_horizon = 10;
-        let mut block_sync =
-            BlockSync::new(network_adapter.clone().into(), block_fetch_horizon, false, true);
+        let mut block_sync = BlockSync::new(
+            network_adapter.clone().into(),
+            block_fetch_horizon,
+            false,
+            true,
+            None,
+            SyncDebugLog::default(),
+        );
         let mut chain_genesis = ChainGenesis::test();
         chain_genesis.epoch_length = 100;
         let mut env = TestEnv::builder(chain_genesis).clients_count(2).build();
@@ -386,8 +418,14 @@ mod test {
     fn test_block_sync_archival() {
         let network_adapter = Arc::new(MockPeerManagerAdapter::default());
         let block_fetch_horizon = 10;
-        let mut block_sync =
-            BlockSync::new(network_adapter.clone().into(), block_fetch_horizon, true, true);
+        let mut block_sync = BlockSync::new(
+            network_adapter.clone().into(),
+            block_fetch_horizon,
+            true,
+            true,
+            None,
+            SyncDebugLog::default(),
+        );
         let mut chain_genesis = ChainGenesis::test();
         chain_genesis.epoch_length = 5;
         let mut env = TestEnv::builder(chain_genesis).clients_count(2).build();
@@ -0,0 +1,41 @@
+use near_client_primitives::debug::{SyncEvent, SyncLogEntry};
+use near_primitives::static_clock::StaticClock;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Number of sync events kept by the client.
+pub const SYNC_DEBUG_LOG_CAPACITY: usize = 1000;
+
+/// Bounded log of the decisions taken by header, block and state sync, shown on the sync debug
+/// page. Clones share the same log.
+#[derive(Clone)]
+pub struct SyncDebugLog {
+    entries: Arc<Mutex<VecDeque<SyncLogEntry>>>,
+    capacity: usize,
+}
+
+impl SyncDebugLog {
+    pub fn new(capacity: usize) -> Self {
+        Self { entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))), capacity }
+    }
+
+    /// Appends `event`, dropping the oldest one if the log is full.
+    pub fn record(&self, event: SyncEvent) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(SyncLogEntry { time: StaticClock::utc(), event });
+    }
+
+    /// Returns the logged events, oldest first.
+    pub fn entries(&self) -> Vec<SyncLogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl Default for SyncDebugLog {
+    fn default() -> Self {
+        Self::new(SYNC_DEBUG_LOG_CAPACITY)
+    }
+}
@@ -1,9 +1,14 @@
+use crate::debug::BanHistory;
+use crate::sync::debug_log::SyncDebugLog;
 use chrono::{DateTime, Duration, Utc};
 use near_async::messaging::CanSend;
 use near_chain::{Chain, ChainStoreAccess};
+use near_client_primitives::debug::SyncEvent;
 use near_client_primitives::types::SyncStatus;
 use near_network::types::PeerManagerMessageRequest;
-use near_network::types::{HighestHeightPeerInfo, NetworkRequests, PeerManagerAdapter};
+use near_network::types::{
+    HighestHeightPeerInfo, NetworkRequests, PeerManagerAdapter, ReasonForBan,
+};
 use near_primitives::block::Tip;
 use near_primitives::hash::CryptoHash;
 use near_primitives::static_clock::StaticClock;
@@ -35,6 +40,11 @@ pub struct HeaderSync {
     progress_timeout: Duration,
     stall_ban_timeout: Duration,
     expected_height_per_second: u64,
+    /// Headers above this height are not requested.
+    sync_until_height: Option<BlockHeight>,
+
+    debug_log: SyncDebugLog,
+    ban_history: BanHistory,
 }
 
 impl HeaderSync {
@@ -44,6 +54,9 @@ impl HeaderSync {
         progress_timeout: TimeDuration,
         stall_ban_timeout: TimeDuration,
         expected_height_per_second: u64,
+        sync_until_height: Option<BlockHeight>,
+        debug_log: SyncDebugLog,
+        ban_history: BanHistory,
     ) -> Self {
         HeaderSync {
             network_adapter,
@@ -54,6 +67,9 @@ impl HeaderSync {
             progress_timeout: Duration::from_std(progress_timeout).unwrap(),
             stall_ban_timeout: Duration::from_std(stall_ban_timeout).unwrap(),
             expected_height_per_second,
+            sync_until_height,
+            debug_log,
+            ban_history,
         }
     }
 
@@ -95,9 +111,11 @@ impl HeaderSync {
                 highest_height,
             });
             self.syncing_peer = None;
+            let reached_target =
+                self.sync_until_height.map_or(false, |height| header_head.height >= height);
             if let Some(peer) = highest_height_peers.choose(&mut thread_rng()).cloned() {
-                if peer.highest_block_height > header_head.height {
-                    self.syncing_peer = self.request_headers(chain, peer);
+                if peer.highest_block_height > header_head.height && !reached_target {
+                    self.syncing_peer = self.request_headers(chain, header_head.height, peer);
                 }
             }
         }
@@ -148,6 +166,10 @@ impl HeaderSync {
             );
 
             if stalling {
+                self.debug_log.record(SyncEvent::HeaderSyncStalled {
+                    header_head_height: header_head.height,
+                    expected_height: old_expected_height,
+                });
                 if self.stalling_ts.is_none() {
                     self.stalling_ts = Some(now);
                 }
@@ -167,12 +189,20 @@ impl HeaderSync {
                                 {
                                     warn!(target: "sync", "Sync: ban a fraudulent peer: {}, claimed height: {}",
                                         peer.peer_info, peer.highest_block_height);
+                                    self.debug_log.record(SyncEvent::PeerBanned {
+                                        peer_id: peer.peer_info.id.clone(),
+                                        claimed_height: peer.highest_block_height,
+                                    });
+                                    self.ban_history.record(
+                                        peer.peer_info.id.clone(),
+                                        ReasonForBan::HeightFraud,
+                                        None,
+                                    );
                                     self.network_adapter.send(
                                         PeerManagerMessageRequest::NetworkRequests(
                                             NetworkRequests::BanPeer {
                                                 peer_id: peer.peer_info.id.clone(),
-                                                ban_reason:
-                                                    near_network::types::ReasonForBan::HeightFraud,
+                                                ban_reason: ReasonForBan::HeightFraud,
                                             },
                                         ),
                                     );
@@ -214,10 +244,17 @@ impl HeaderSync {
     fn request_headers(
         &mut self,
         chain: &Chain,
+        header_head_height: BlockHeight,
         peer: HighestHeightPeerInfo,
     ) -> Option<HighestHeightPeerInfo> {
         if let Ok(locator) = self.get_locator(chain) {
             debug!(target: "sync", "Sync: request headers: asking {} for headers, {:?}", peer.peer_info.id, locator);
+            self.debug_log.record(SyncEvent::HeadersRequested {
+                peer_id: peer.peer_info.id.clone(),
+                peer_height: peer.highest_block_height,
+                header_head_height,
+                locator_len: locator.len(),
+            });
             self.network_adapter.send(PeerManagerMessageRequest::NetworkRequests(
                 NetworkRequests::BlockHeadersRequest {
                     hashes: locator,
@@ -297,6 +334,7 @@ mod test {
     use near_primitives::merkle::PartialMerkleTree;
     use near_primitives::types::EpochId;
     use near_primitives::version::PROTOCOL_VERSION;
+    use near_store::test_utils::create_test_store;
     use num_rational::Ratio;
 
     #[test]
@@ -348,6 +386,9 @@ mod test {
             TimeDuration::from_secs(2),
             TimeDuration::from_secs(120),
             1_000_000_000,
+            None,
+            SyncDebugLog::default(),
+            BanHistory::new(create_test_store()),
         );
         let (mut chain, _, _, signer) = setup();
         for _ in 0..3 {
@@ -434,6 +475,9 @@ mod test {
             TimeDuration::from_secs(2),
             TimeDuration::from_secs(120),
             1_000_000_000,
+            None,
+            SyncDebugLog::default(),
+            BanHistory::new(create_test_store()),
         );
         let (mut chain, _, _, signer) = setup();
         let (mut chain2, _, _, signer2) = setup();
@@ -538,12 +582,16 @@ mod test {
         let highest_height = 1000;
 
         // Setup header_sync with expectation of 25 headers/second
+        let ban_history = BanHistory::new(create_test_store());
         let mut header_sync = HeaderSync::new(
             network_adapter.clone().into(),
             TimeDuration::from_secs(1),
             TimeDuration::from_secs(1),
             TimeDuration::from_secs(3),
             25,
+            None,
+            SyncDebugLog::default(),
+            ban_history.clone(),
         );
 
         let set_syncing_peer = |header_sync: &mut HeaderSync| {
@@ -627,17 +675,24 @@ mod test {
         } else {
             assert!(false);
         }
+        let bans = ban_history.get(None);
+        assert_eq!(bans.len(), 1);
+        assert_eq!(bans[0].reason, format!("{:?}", ReasonForBan::HeightFraud));
     }
 
     #[test]
     fn test_sync_from_very_behind() {
         let mock_adapter = Arc::new(MockPeerManagerAdapter::default());
+        let debug_log = SyncDebugLog::default();
         let mut header_sync = HeaderSync::new(
             mock_adapter.clone().into(),
             TimeDuration::from_secs(10),
             TimeDuration::from_secs(2),
             TimeDuration::from_secs(120),
             1_000_000_000,
+            None,
+            debug_log.clone(),
+            BanHistory::new(create_test_store()),
         );
 
         let vs = ValidatorSchedule::new()
@@ -725,6 +780,7 @@ mod test {
                 }),
             },
         };
+        let mut requested_from_heights = vec![];
         // It should be done in 5 iterations, but give it 10 iterations just in case it would
         // get into an infinite loop because of some bug and cause the test to hang.
         for _ in 0..10 {
@@ -741,6 +797,7 @@ mod test {
                     &[<FullPeerInfo as Into<Option<_>>>::into(peer1.clone()).unwrap()]
                 )
                 .is_ok());
+            requested_from_heights.push(header_head.height);
             match sync_status {
                 SyncStatus::HeaderSync { .. } => {}
                 _ => panic!("Unexpected sync status: {:?}", sync_status),
@@ -774,5 +831,27 @@ mod test {
         }
         let new_tip = chain.header_head().unwrap();
         assert_eq!(new_tip.last_block_hash, chain2.head().unwrap().last_block_hash);
+
+        // Every request went to the only peer, each one starting from the headers received in
+        // response to the previous one.
+        assert!(requested_from_heights.windows(2).all(|w| w[0] < w[1]));
+        let log = debug_log.entries();
+        assert_eq!(log.len(), requested_from_heights.len());
+        for (entry, height) in log.into_iter().zip(requested_from_heights) {
+            match entry.event {
+                SyncEvent::HeadersRequested {
+                    peer_id,
+                    peer_height,
+                    header_head_height,
+                    locator_len,
+                } => {
+                    assert_eq!(peer_id, peer1.peer_info.id);
+                    assert_eq!(peer_height, chain2.head().unwrap().height);
+                    assert_eq!(header_head_height, height);
+                    assert!(locator_len > 0);
+                }
+                event => panic!("Unexpected sync event: {:?}", event),
+            }
+        }
     }
 }
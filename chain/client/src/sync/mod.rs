@@ -1,5 +1,6 @@
 pub mod adapter;
 pub mod block;
+pub mod debug_log;
 pub mod epoch;
 pub mod external;
 pub mod header;
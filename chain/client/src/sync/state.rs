@@ -21,12 +21,14 @@
 //!
 
 use crate::metrics;
+use crate::resharding_log::ReshardingLog;
+use crate::sync::debug_log::SyncDebugLog;
 use crate::sync::external::{
     create_bucket_readonly, external_storage_location, ExternalConnection,
 };
-use actix_rt::ArbiterHandle;
 use chrono::{DateTime, Duration, Utc};
 use futures::{future, FutureExt};
+use near_async::futures::FutureSpawner;
 use near_async::messaging::CanSendAsync;
 use near_chain::chain::ApplyStatePartsRequest;
 use near_chain::near_chain_primitives;
@@ -34,6 +36,7 @@ use near_chain::resharding::StateSplitRequest;
 use near_chain::types::RuntimeAdapter;
 use near_chain::Chain;
 use near_chain_configs::{ExternalStorageConfig, ExternalStorageLocation, SyncConfig};
+use near_client_primitives::debug::{ReshardingEvent, SyncEvent};
 use near_client_primitives::types::{
     format_shard_sync_phase, DownloadStatus, ShardSyncDownload, ShardSyncStatus,
 };
@@ -151,6 +154,11 @@ pub struct StateSync {
     /// Message queue to process the received state parts.
     state_parts_mpsc_tx: Sender<StateSyncGetPartResult>,
     state_parts_mpsc_rx: Receiver<StateSyncGetPartResult>,
+
+    /// Where the requests and timeouts are logged for the sync debug page.
+    debug_log: SyncDebugLog,
+    /// Where the splits of the shards are logged for the resharding debug page.
+    resharding_log: ReshardingLog,
 }
 
 impl StateSync {
@@ -160,6 +168,8 @@ impl StateSync {
         chain_id: &str,
         sync_config: &SyncConfig,
         catchup: bool,
+        debug_log: SyncDebugLog,
+        resharding_log: ReshardingLog,
     ) -> Self {
         let inner = match sync_config {
             SyncConfig::Peers => StateSyncInner::Peers {
@@ -211,6 +221,8 @@ impl StateSync {
             split_state_roots: HashMap::new(),
             state_parts_mpsc_rx: rx,
             state_parts_mpsc_tx: tx,
+            debug_log,
+            resharding_log,
         }
     }
 
@@ -230,6 +242,9 @@ impl StateSync {
                             %prev_hash,
                             timeout_sec = self.timeout.num_seconds(),
                             "State sync: block request timed out");
+                        self.debug_log.record(SyncEvent::StateSyncBlockRequestTimedOut {
+                            prev_hash: *prev_hash,
+                        });
                         (true, false)
                     } else {
                         (false, false)
@@ -260,7 +275,7 @@ impl StateSync {
         now: DateTime<Utc>,
         state_parts_task_scheduler: &dyn Fn(ApplyStatePartsRequest),
         state_split_scheduler: &dyn Fn(StateSplitRequest),
-        state_parts_arbiter_handle: &ArbiterHandle,
+        future_spawner: &dyn FutureSpawner,
         use_colour: bool,
         runtime_adapter: Arc<dyn RuntimeAdapter>,
     ) -> Result<bool, near_chain::Error> {
@@ -369,6 +384,7 @@ impl StateSync {
                     %shard_id,
                     timeout_sec = self.timeout.num_seconds(),
                     "State sync didn't download the state, sending StateRequest again");
+                self.debug_log.record(SyncEvent::StateDownloadTimedOut { shard_id, sync_hash });
                 tracing::debug!(
                     target: "sync",
                     %shard_id,
@@ -387,7 +403,7 @@ impl StateSync {
                     shard_sync_download,
                     highest_height_peers,
                     runtime_adapter.clone(),
-                    state_parts_arbiter_handle,
+                    future_spawner,
                 )?;
             }
         }
@@ -537,7 +553,7 @@ impl StateSync {
         shard_sync_download: &mut ShardSyncDownload,
         highest_height_peers: &[HighestHeightPeerInfo],
         runtime_adapter: Arc<dyn RuntimeAdapter>,
-        state_parts_arbiter_handle: &ArbiterHandle,
+        future_spawner: &dyn FutureSpawner,
     ) -> Result<(), near_chain::Error> {
         let possible_targets = self.select_peers(highest_height_peers, shard_id)?;
 
@@ -555,6 +571,7 @@ impl StateSync {
                     sync_hash,
                     &possible_targets,
                     shard_sync_download,
+                    future_spawner,
                 );
             }
             ShardSyncStatus::StateDownloadParts => {
@@ -565,7 +582,7 @@ impl StateSync {
                     shard_sync_download,
                     chain,
                     runtime_adapter,
-                    state_parts_arbiter_handle,
+                    future_spawner,
                 );
             }
             _ => {}
@@ -581,6 +598,7 @@ impl StateSync {
         sync_hash: CryptoHash,
         possible_targets: &[PeerId],
         new_shard_sync_download: &mut ShardSyncDownload,
+        future_spawner: &dyn FutureSpawner,
     ) {
         let peer_id = possible_targets.choose(&mut thread_rng()).cloned().unwrap();
         tracing::debug!(target: "sync", ?peer_id, shard_id, ?sync_hash, ?possible_targets, "request_shard_header");
@@ -588,9 +606,14 @@ impl StateSync {
         new_shard_sync_download.downloads[0].run_me.store(false, Ordering::SeqCst);
         new_shard_sync_download.downloads[0].state_requests_count += 1;
         new_shard_sync_download.downloads[0].last_target = Some(peer_id.clone());
+        self.debug_log.record(SyncEvent::StateHeaderRequested {
+            shard_id,
+            sync_hash,
+            peer_id: peer_id.clone(),
+        });
         let run_me = new_shard_sync_download.downloads[0].run_me.clone();
-        near_performance_metrics::actix::spawn(
-            std::any::type_name::<Self>(),
+        future_spawner.spawn(
+            "request_state_header",
             self.network_adapter
                 .send_async(PeerManagerMessageRequest::NetworkRequests(
                     NetworkRequests::StateRequestHeader { shard_id, sync_hash, peer_id },
@@ -616,10 +639,11 @@ impl StateSync {
         new_shard_sync_download: &mut ShardSyncDownload,
         chain: &Chain,
         runtime_adapter: Arc<dyn RuntimeAdapter>,
-        state_parts_arbiter_handle: &ArbiterHandle,
+        future_spawner: &dyn FutureSpawner,
     ) {
         // Iterate over all parts that needs to be requested (i.e. download.run_me is true).
         // Parts are ordered such that its index match its part_id.
+        let mut num_parts = 0;
         match &mut self.inner {
             StateSyncInner::Peers { last_part_id_requested, requested_target } => {
                 // We'll select all the 'highest' peers + validators as candidates (excluding those that gave us timeout in the past).
@@ -644,6 +668,7 @@ impl StateSync {
                         requested_target,
                         self.timeout,
                     );
+                    num_parts += 1;
                     request_part_from_peers(
                         part_id,
                         target,
@@ -651,6 +676,7 @@ impl StateSync {
                         shard_id,
                         sync_hash,
                         &self.network_adapter,
+                        future_spawner,
                     );
                 }
             }
@@ -678,15 +704,23 @@ impl StateSync {
                         semaphore.clone(),
                         external.clone(),
                         runtime_adapter.clone(),
-                        state_parts_arbiter_handle,
+                        future_spawner,
                         self.state_parts_mpsc_tx.clone(),
                     );
+                    num_parts += 1;
                     if semaphore.available_permits() == 0 {
                         break;
                     }
                 }
             }
         }
+        if num_parts > 0 {
+            self.debug_log.record(SyncEvent::StatePartsRequested {
+                shard_id,
+                sync_hash,
+                num_parts,
+            });
+        }
     }
 
     /// The main 'step' function that should be called periodically to check and update the sync process.
@@ -705,7 +739,7 @@ impl StateSync {
         tracking_shards: Vec<ShardId>,
         state_parts_task_scheduler: &dyn Fn(ApplyStatePartsRequest),
         state_split_scheduler: &dyn Fn(StateSplitRequest),
-        state_parts_arbiter_handle: &ArbiterHandle,
+        future_spawner: &dyn FutureSpawner,
         use_colour: bool,
         runtime_adapter: Arc<dyn RuntimeAdapter>,
     ) -> Result<StateSyncResult, near_chain::Error> {
@@ -748,7 +782,7 @@ impl StateSync {
             now,
             state_parts_task_scheduler,
             state_split_scheduler,
-            state_parts_arbiter_handle,
+            future_spawner,
             use_colour,
             runtime_adapter,
         )?;
@@ -1042,6 +1076,7 @@ impl StateSync {
             state_split_scheduler,
         )?;
         tracing::debug!(target: "sync", %shard_id, %sync_hash, ?me, "resharding scheduled");
+        self.resharding_log.record(ReshardingEvent::SplitScheduled { sync_hash, shard_id });
         *shard_sync_download =
             ShardSyncDownload { downloads: vec![], status: ShardSyncStatus::StateSplitApplying };
         Ok(())
@@ -1058,11 +1093,24 @@ impl StateSync {
         let result = self.split_state_roots.remove(&shard_uid.shard_id());
         let mut shard_sync_done = false;
         if let Some(state_roots) = result {
-            chain.build_state_for_split_shards_postprocessing(
-                shard_uid,
-                &sync_hash,
-                state_roots?,
-            )?;
+            let shard_id = shard_uid.shard_id();
+            let split = state_roots.and_then(|state_roots| {
+                chain.build_state_for_split_shards_postprocessing(
+                    shard_uid,
+                    &sync_hash,
+                    state_roots,
+                )
+            });
+            if let Err(err) = &split {
+                let error = err.to_string();
+                self.resharding_log.record(ReshardingEvent::SplitFailed {
+                    sync_hash,
+                    shard_id,
+                    error,
+                });
+            }
+            split?;
+            self.resharding_log.record(ReshardingEvent::SplitCompleted { sync_hash, shard_id });
             *shard_sync_download =
                 ShardSyncDownload { downloads: vec![], status: ShardSyncStatus::StateSyncDone };
             shard_sync_done = true;
@@ -1097,7 +1145,7 @@ fn request_part_from_external_storage(
     semaphore: Arc<Semaphore>,
     external: ExternalConnection,
     runtime_adapter: Arc<dyn RuntimeAdapter>,
-    state_parts_arbiter_handle: &ArbiterHandle,
+    future_spawner: &dyn FutureSpawner,
     state_parts_mpsc_tx: Sender<StateSyncGetPartResult>,
 ) {
     if !download.run_me.swap(false, Ordering::SeqCst) {
@@ -1112,7 +1160,8 @@ fn request_part_from_external_storage(
 
     match semaphore.try_acquire_owned() {
         Ok(permit) => {
-            if state_parts_arbiter_handle.spawn({
+            future_spawner.spawn(
+                "download_state_part",
                 async move {
                     let result = external.get_part(shard_id, &location).await;
                     let part_id = PartId{ idx: part_id, total: num_parts };
@@ -1144,15 +1193,12 @@ fn request_part_from_external_storage(
                         },
                     }
                     drop(permit)
-                }
-            }) == false
-            {
-                tracing::error!(target: "sync", %shard_id, part_id, "Unable to spawn download. state_parts_arbiter has died.");
-            }
-        },
+                },
+            );
+        }
         Err(TryAcquireError::NoPermits) => {
             download.run_me.store(true, Ordering::SeqCst);
-        },
+        }
         Err(TryAcquireError::Closed) => {
             download.run_me.store(true, Ordering::SeqCst);
             tracing::warn!(target: "sync", %shard_id, part_id, "Failed to schedule download. Semaphore closed.");
@@ -1168,14 +1214,15 @@ fn request_part_from_peers(
     shard_id: ShardId,
     sync_hash: CryptoHash,
     network_adapter: &PeerManagerAdapter,
+    future_spawner: &dyn FutureSpawner,
 ) {
     download.run_me.store(false, Ordering::SeqCst);
     download.state_requests_count += 1;
     download.last_target = Some(peer_id.clone());
     let run_me = download.run_me.clone();
 
-    near_performance_metrics::actix::spawn(
-        "StateSync",
+    future_spawner.spawn(
+        "request_state_part",
         network_adapter
             .send_async(PeerManagerMessageRequest::NetworkRequests(
                 NetworkRequests::StateRequestPart { shard_id, sync_hash, part_id, peer_id },
@@ -1334,6 +1381,8 @@ mod test {
             "chain_id",
             &SyncConfig::Peers,
             false,
+            SyncDebugLog::default(),
+            ReshardingLog::default(),
         );
         let mut new_shard_sync = HashMap::new();
 
@@ -5,6 +5,7 @@ use std::time::{Duration, Instant};
 use near_primitives::block::Block;
 use near_primitives::hash::CryptoHash;
 use near_primitives::static_clock::StaticClock;
+use near_primitives::types::AccountId;
 use tracing::info;
 
 pub struct BlockStats {
@@ -15,6 +16,7 @@ pub struct BlockStats {
     max_divergence: u64,
     last_hash: Option<CryptoHash>,
     parent: HashMap<CryptoHash, CryptoHash>,
+    num_blocks_by_producer: HashMap<AccountId, u64>,
 }
 
 impl BlockStats {
@@ -27,6 +29,7 @@ impl BlockStats {
             max_divergence: 0,
             last_hash: None,
             parent: HashMap::new(),
+            num_blocks_by_producer: HashMap::new(),
         }
     }
 
@@ -53,10 +56,13 @@ impl BlockStats {
         result
     }
 
-    pub(crate) fn add_block(&mut self, block: &Block) {
+    /// Adds a block broadcast by `sender`. Nodes only relay the blocks they have received, so the
+    /// first node to broadcast a block is its producer.
+    pub(crate) fn add_block(&mut self, block: &Block, sender: &AccountId) {
         if self.hash2depth.contains_key(block.hash()) {
             return;
         }
+        *self.num_blocks_by_producer.entry(sender.clone()).or_default() += 1;
         let prev_height = self.hash2depth.get(block.header().prev_hash()).map(|v| *v).unwrap_or(0);
         self.hash2depth.insert(*block.hash(), prev_height + 1);
         self.num_blocks += 1;
@@ -71,6 +77,14 @@ impl BlockStats {
         self.last_hash = Some(*block.hash());
     }
 
+    pub fn num_blocks_produced_by(&self, account_id: &AccountId) -> u64 {
+        self.num_blocks_by_producer.get(account_id).copied().unwrap_or(0)
+    }
+
+    pub fn max_chain_length(&self) -> u64 {
+        self.max_chain_length
+    }
+
     pub fn check_stats(&mut self, force: bool) {
         let now = StaticClock::instant();
         let diff = now.duration_since(self.last_check);
@@ -2,16 +2,20 @@
 // code so we're in the clear.
 #![allow(clippy::arc_with_non_send_sync)]
 
+use std::collections::HashMap;
 use std::mem::swap;
 use std::sync::{Arc, RwLock};
 
-use crate::Client;
+use crate::debug::BlockProductionInputs;
+use crate::{Client, ClientOps};
 use actix_rt::{Arbiter, System};
-use near_chain::chain::{do_apply_chunks, BlockCatchUpRequest};
+use futures::future::BoxFuture;
+use near_async::futures::FutureSpawner;
+use near_chain::chain::{do_apply_chunks, BlockCatchUpRequest, BlockCatchUpResponse};
 use near_chain::resharding::StateSplitRequest;
 use near_chain::test_utils::{wait_for_all_blocks_in_processing, wait_for_block_in_processing};
 use near_chain::{Chain, ChainStoreAccess, Provenance};
-use near_client_primitives::types::Error;
+use near_client_primitives::types::{BlockProducerErrorKind, Error};
 use near_network::types::HighestHeightPeerInfo;
 use near_primitives::block::Block;
 use near_primitives::hash::CryptoHash;
@@ -80,6 +84,50 @@ impl Client {
         }
         vec![]
     }
+
+    /// Waits until all completed chunks are persisted and processes the blocks waiting for them.
+    pub fn finish_chunk_persistence(&mut self) {
+        let persisted_chunks = self.chunk_persister.wait_for_all();
+        self.on_chunks_persisted(persisted_chunks, Arc::new(|_| {}));
+    }
+
+    /// Waits until the production report of the last finished epoch is written to the configured
+    /// file.
+    pub fn finish_production_report_write(&mut self) {
+        if let Some(handle) = self.production_report_writer.take() {
+            handle.join().unwrap();
+        }
+    }
+
+    /// Rebuilds the block this client produced at `height` from the persisted production inputs
+    /// and checks that it has the same hash as the block that was produced. Catches
+    /// nondeterminism in the block production path.
+    pub fn rebuild_produced_block(&self, height: BlockHeight) -> Result<Block, Error> {
+        let (block_hash, inputs) =
+            self.produced_block_inputs(height)?.ok_or(Error::MissingProductionInputs(height))?;
+        self.rebuild_block(height, &block_hash, &inputs)
+    }
+
+    /// Rebuilds the block at `height` from `inputs` and checks that it has hash `block_hash`.
+    pub fn rebuild_block(
+        &self,
+        height: BlockHeight,
+        block_hash: &CryptoHash,
+        inputs: &BlockProductionInputs,
+    ) -> Result<Block, Error> {
+        let validator_signer = self.validator_signer.as_ref().ok_or_else(|| {
+            Error::block_producer(height, BlockProducerErrorKind::NoValidatorSigner)
+        })?;
+        let block = self.produce_block_from_inputs(height, inputs, validator_signer.as_ref())?;
+        if block.hash() != block_hash {
+            return Err(Error::RebuiltBlockMismatch {
+                height,
+                rebuilt_hash: *block.hash(),
+                produced_hash: *block_hash,
+            });
+        }
+        Ok(block)
+    }
 }
 
 fn create_chunk_on_height_for_shard(
@@ -210,13 +258,35 @@ pub fn create_chunk(
     (chunk, merkle_paths, receipts, block)
 }
 
+/// Runs the futures to completion on the calling thread as soon as they are spawned.
+/// Allows driving `Client` without any async runtime.
+pub struct SynchronousFutureSpawner;
+
+impl FutureSpawner for SynchronousFutureSpawner {
+    fn spawn_boxed(&self, _description: &'static str, f: BoxFuture<'static, ()>) {
+        futures::executor::block_on(f);
+    }
+}
+
 /// Keep running catchup until there is no more catchup work that can be done
 /// Note that this function does not necessarily mean that all blocks are caught up.
 /// It's possible that some blocks that need to be caught up are still being processed
 /// and the catchup process can't catch up on these blocks yet.
 pub fn run_catchup(
-    client: &mut Client,
+    client: &mut impl ClientOps,
+    highest_height_peers: &[HighestHeightPeerInfo],
+) -> Result<(), Error> {
+    let _ = System::new();
+    let state_parts_future_spawner = Arbiter::new().handle();
+    run_catchup_with_spawner(client, highest_height_peers, &state_parts_future_spawner)
+}
+
+/// Same as `run_catchup`, with the futures applying the state parts run by
+/// `state_parts_future_spawner`.
+pub fn run_catchup_with_spawner(
+    client: &mut impl ClientOps,
     highest_height_peers: &[HighestHeightPeerInfo],
+    state_parts_future_spawner: &dyn FutureSpawner,
 ) -> Result<(), Error> {
     let f = |_| {};
     let block_messages = Arc::new(RwLock::new(vec![]));
@@ -229,8 +299,6 @@ pub fn run_catchup(
     let state_split = move |msg: StateSplitRequest| {
         state_split_inside_messages.write().unwrap().push(msg);
     };
-    let _ = System::new();
-    let state_parts_arbiter_handle = Arbiter::new().handle();
     loop {
         client.run_catchup(
             highest_height_peers,
@@ -238,29 +306,20 @@ pub fn run_catchup(
             &block_catch_up,
             &state_split,
             Arc::new(|_| {}),
-            &state_parts_arbiter_handle,
+            state_parts_future_spawner,
         )?;
         let mut catchup_done = true;
         for msg in block_messages.write().unwrap().drain(..) {
             let results = do_apply_chunks(msg.block_hash, msg.block_height, msg.work);
-            if let Some((_, _, blocks_catch_up_state)) =
-                client.catchup_state_syncs.get_mut(&msg.sync_hash)
-            {
-                assert!(blocks_catch_up_state.scheduled_blocks.remove(&msg.block_hash));
-                blocks_catch_up_state.processed_blocks.insert(msg.block_hash, results);
-            } else {
-                panic!("block catch up processing result from unknown sync hash");
-            }
+            client.on_block_catch_up_done(BlockCatchUpResponse {
+                sync_hash: msg.sync_hash,
+                block_hash: msg.block_hash,
+                results,
+            });
             catchup_done = false;
         }
         for msg in state_split_messages.write().unwrap().drain(..) {
-            let response = Chain::build_state_for_split_shards(msg);
-            if let Some((sync, _, _)) = client.catchup_state_syncs.get_mut(&response.sync_hash) {
-                // We are doing catchup
-                sync.set_split_result(response.shard_id, response.new_state_roots);
-            } else {
-                client.state_sync.set_split_result(response.shard_id, response.new_state_roots);
-            }
+            client.on_state_split_done(Chain::build_state_for_split_shards(msg));
             catchup_done = false;
         }
         if catchup_done {
@@ -269,3 +328,21 @@ pub fn run_catchup(
     }
     Ok(())
 }
+
+/// Asserts that the metric `name` changed by `delta` between the `before` and `after` snapshots,
+/// see `crate::metrics::snapshot`. A metric missing from a snapshot counts as zero, since labeled
+/// metrics only show up once a value was recorded for their labels.
+pub fn assert_metric_delta(
+    name: &str,
+    before: &HashMap<String, f64>,
+    after: &HashMap<String, f64>,
+    delta: f64,
+) {
+    let before_value = before.get(name).copied().unwrap_or_default();
+    let after_value = after.get(name).copied().unwrap_or_default();
+    assert_eq!(
+        after_value - before_value,
+        delta,
+        "metric {name} changed from {before_value} to {after_value}"
+    );
+}
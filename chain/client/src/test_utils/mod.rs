@@ -1,6 +1,7 @@
 pub mod block_stats;
 pub mod client;
 pub mod peer_manager_mock;
+pub mod replay;
 pub mod setup;
 pub mod test_env;
 pub mod test_env_builder;
@@ -8,6 +9,7 @@ pub mod test_env_builder;
 pub use block_stats::*;
 pub use client::*;
 pub use peer_manager_mock::*;
+pub use replay::*;
 pub use setup::*;
 pub use test_env::*;
 pub use test_env_builder::*;
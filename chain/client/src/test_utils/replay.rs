@@ -0,0 +1,270 @@
+//! Deterministic replay of the inputs of a client, to reproduce the bugs in the handling of their
+//! interleavings.
+//!
+//! `ClientReplayHarness` records the blocks, completed chunks, approvals and transactions handed
+//! to a client, with the time of each, into a `ClientTrace`, along with the state the client ends
+//! up in. A trace is replayed against a fresh client on the same genesis: the inputs are handed
+//! to it in the time order, with `StaticClock` set to the time of each, and the resulting state
+//! is compared with the recorded one.
+use crate::adapter::ProcessTxResponse;
+use crate::Client;
+use borsh::{BorshDeserialize, BorshSerialize};
+use chrono::{DateTime, Utc};
+use near_primitives::block::{Approval, Block};
+use near_primitives::block_header::ApprovalType;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use near_primitives::serialize::{from_base64, to_base64};
+use near_primitives::sharding::{PartialEncodedChunk, ShardChunk};
+use near_primitives::static_clock::{MockClockGuard, StaticClock};
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{BlockHeight, ShardId};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// A value serialized with borsh and encoded with base64, for the types without serde support.
+#[derive(Debug, Clone)]
+pub struct Encoded<T>(pub T);
+
+impl<T: BorshSerialize> serde::Serialize for Encoded<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes = borsh::to_vec(&self.0).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&to_base64(&bytes))
+    }
+}
+
+impl<'de, T: BorshDeserialize> serde::Deserialize<'de> for Encoded<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = <String as serde::Deserialize>::deserialize(deserializer)?;
+        let bytes = from_base64(&encoded).map_err(serde::de::Error::custom)?;
+        T::try_from_slice(&bytes).map(Self).map_err(serde::de::Error::custom)
+    }
+}
+
+/// An input of the client.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientInput {
+    Block {
+        block: Encoded<Block>,
+        peer_id: PeerId,
+        was_requested: bool,
+    },
+    ChunkCompleted {
+        partial_chunk: Encoded<PartialEncodedChunk>,
+        shard_chunk: Option<Encoded<ShardChunk>>,
+    },
+    Approval {
+        approval: Encoded<Approval>,
+        /// The peer the approval came from, `None` for the approvals of the client itself.
+        peer_id: Option<PeerId>,
+    },
+    Transaction {
+        tx: Encoded<SignedTransaction>,
+        is_forwarded: bool,
+        check_only: bool,
+    },
+}
+
+impl ClientInput {
+    /// Hands the input to the client and runs the processing it starts to the end, so that the
+    /// next input finds the client in the same state however long the processing takes.
+    fn apply(&self, client: &mut Client) -> Option<ProcessTxResponse> {
+        let response = match self {
+            Self::Block { block, peer_id, was_requested } => {
+                client.receive_block(
+                    block.0.clone(),
+                    peer_id.clone(),
+                    *was_requested,
+                    Arc::new(|_| {}),
+                );
+                None
+            }
+            Self::ChunkCompleted { partial_chunk, shard_chunk } => {
+                client.on_chunk_completed(
+                    partial_chunk.0.clone(),
+                    shard_chunk.as_ref().map(|shard_chunk| shard_chunk.0.clone()),
+                    Arc::new(|_| {}),
+                );
+                None
+            }
+            Self::Approval { approval, peer_id } => {
+                let approval_type = match peer_id {
+                    Some(peer_id) => ApprovalType::PeerApproval(peer_id.clone()),
+                    None => ApprovalType::SelfApproval,
+                };
+                client.collect_block_approval(&approval.0, approval_type);
+                None
+            }
+            Self::Transaction { tx, is_forwarded, check_only } => {
+                Some(client.process_tx(tx.0.clone(), *is_forwarded, *check_only))
+            }
+        };
+        loop {
+            client.finish_chunk_persistence();
+            if client.finish_blocks_in_processing().is_empty() {
+                break;
+            }
+        }
+        response
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct TracedInput {
+    /// Time the client got the input at.
+    pub time: DateTime<Utc>,
+    pub input: ClientInput,
+}
+
+/// State of the client compared between a recording and its replay.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReplayState {
+    pub head_height: BlockHeight,
+    pub head_hash: CryptoHash,
+    /// Chunk masks of the blocks on the canonical chain, by height.
+    pub chunk_masks: BTreeMap<BlockHeight, Vec<bool>>,
+    /// Sorted hashes of the transactions in the pool, by shard.
+    pub pool: BTreeMap<ShardId, Vec<CryptoHash>>,
+}
+
+impl ReplayState {
+    pub fn of(client: &Client) -> Self {
+        let head = client.chain.head().unwrap();
+        let chunk_masks = (client.chain.genesis().height() + 1..=head.height)
+            .filter_map(|height| client.chain.get_block_by_height(height).ok())
+            .map(|block| (block.header().height(), block.header().chunk_mask().to_vec()))
+            .collect();
+        let epoch_id =
+            client.epoch_manager.get_epoch_id_from_prev_block(&head.last_block_hash).unwrap();
+        let pool = client
+            .epoch_manager
+            .shard_ids(&epoch_id)
+            .unwrap()
+            .into_iter()
+            .map(|shard_id| {
+                let shard_uid = client.epoch_manager.shard_id_to_uid(shard_id, &epoch_id).unwrap();
+                let mut tx_hashes: Vec<_> =
+                    client.sharded_tx_pool.transaction_hashes(shard_uid).copied().collect();
+                tx_hashes.sort();
+                (shard_id, tx_hashes)
+            })
+            .collect();
+        Self { head_height: head.height, head_hash: head.last_block_hash, chunk_masks, pool }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct ClientTrace {
+    /// Hash of the genesis block of the recorded client.
+    pub genesis_hash: CryptoHash,
+    pub inputs: Vec<TracedInput>,
+    /// State of the client at the end of the recording.
+    pub expected: ReplayState,
+}
+
+impl ClientTrace {
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()
+    }
+}
+
+/// Records the inputs handed to a client through it and replays the recorded traces.
+#[derive(Default)]
+pub struct ClientReplayHarness {
+    inputs: Vec<TracedInput>,
+}
+
+impl ClientReplayHarness {
+    fn record(&mut self, client: &mut Client, input: ClientInput) -> Option<ProcessTxResponse> {
+        let time = StaticClock::utc();
+        let response = input.apply(client);
+        self.inputs.push(TracedInput { time, input });
+        response
+    }
+
+    pub fn receive_block(
+        &mut self,
+        client: &mut Client,
+        block: Block,
+        peer_id: PeerId,
+        was_requested: bool,
+    ) {
+        self.record(client, ClientInput::Block { block: Encoded(block), peer_id, was_requested });
+    }
+
+    pub fn on_chunk_completed(
+        &mut self,
+        client: &mut Client,
+        partial_chunk: PartialEncodedChunk,
+        shard_chunk: Option<ShardChunk>,
+    ) {
+        self.record(
+            client,
+            ClientInput::ChunkCompleted {
+                partial_chunk: Encoded(partial_chunk),
+                shard_chunk: shard_chunk.map(Encoded),
+            },
+        );
+    }
+
+    pub fn collect_block_approval(
+        &mut self,
+        client: &mut Client,
+        approval: &Approval,
+        approval_type: ApprovalType,
+    ) {
+        let peer_id = match approval_type {
+            ApprovalType::PeerApproval(peer_id) => Some(peer_id),
+            ApprovalType::SelfApproval => None,
+        };
+        self.record(client, ClientInput::Approval { approval: Encoded(approval.clone()), peer_id });
+    }
+
+    pub fn process_tx(
+        &mut self,
+        client: &mut Client,
+        tx: SignedTransaction,
+        is_forwarded: bool,
+        check_only: bool,
+    ) -> ProcessTxResponse {
+        self.record(client, ClientInput::Transaction { tx: Encoded(tx), is_forwarded, check_only })
+            .unwrap()
+    }
+
+    /// Ends the recording, with the current state of `client` as the expected one.
+    pub fn finish(self, client: &Client) -> ClientTrace {
+        ClientTrace {
+            genesis_hash: *client.chain.genesis().hash(),
+            inputs: self.inputs,
+            expected: ReplayState::of(client),
+        }
+    }
+
+    /// Replays `trace` against `client`, which must not have got any inputs yet, and returns the
+    /// state it ends up in.
+    pub fn replay(client: &mut Client, trace: &ClientTrace) -> ReplayState {
+        assert_eq!(
+            client.chain.genesis().hash(),
+            &trace.genesis_hash,
+            "The trace was recorded on another genesis"
+        );
+        let mut inputs: Vec<_> = trace.inputs.iter().collect();
+        inputs.sort_by_key(|traced| traced.time);
+        let clock = MockClockGuard::default();
+        for TracedInput { time, input } in inputs {
+            clock.set_time(*time);
+            input.apply(client);
+        }
+        ReplayState::of(client)
+    }
+}
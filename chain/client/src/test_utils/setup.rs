@@ -4,11 +4,14 @@
 
 use super::block_stats::BlockStats;
 use super::peer_manager_mock::PeerManagerMock;
+#[cfg(feature = "protocol_feature_chunk_validation")]
+use crate::adapter::ChunkEndorsementMessage;
 use crate::adapter::{
     AnnounceAccountRequest, BlockApproval, BlockHeadersRequest, BlockHeadersResponse, BlockRequest,
     BlockResponse, SetNetworkInfo, StateRequestHeader, StateRequestPart,
 };
-use crate::{start_view_client, Client, ClientActor, SyncAdapter, SyncStatus, ViewClientActor};
+use crate::{start_view_client, ClientActor, ViewClientActor};
+use crate::{Client, SyncAdapter, SyncStatus};
 use actix::{Actor, Addr, AsyncContext, Context};
 use actix_rt::System;
 use chrono::DateTime;
@@ -17,11 +20,12 @@ use futures::{future, FutureExt};
 use near_async::actix::AddrWithAutoSpanContextExt;
 use near_async::messaging::{CanSend, IntoSender, LateBoundSender, Sender};
 use near_async::time;
+use near_chain::missing_chunks::MissingChunksPoolLimits;
 use near_chain::state_snapshot_actor::SnapshotCallbacks;
 use near_chain::test_utils::{KeyValueRuntime, MockEpochManager, ValidatorSchedule};
 use near_chain::types::{ChainConfig, RuntimeAdapter};
-use near_chain::{Chain, ChainGenesis, DoomslugThresholdMode};
-use near_chain_configs::{ClientConfig, StateSplitConfig};
+use near_chain::{Chain, ChainGenesis, DoomslugThresholdMode, Provenance};
+use near_chain_configs::{ClientConfig, GCConfig, StateSplitConfig};
 use near_chunks::adapter::ShardsManagerRequestFromClient;
 use near_chunks::client::ShardsManagerResponse;
 use near_chunks::shards_manager_actor::start_shards_manager;
@@ -31,6 +35,7 @@ use near_crypto::{KeyType, PublicKey};
 use near_epoch_manager::shard_tracker::{ShardTracker, TrackedConfig};
 use near_epoch_manager::EpochManagerAdapter;
 use near_network::shards_manager::ShardsManagerRequestFromNetwork;
+use near_network::test_utils::MockPeerManagerAdapter;
 use near_network::types::{BlockInfo, PeerChainInfo};
 use near_network::types::{
     ConnectedPeerInfo, FullPeerInfo, NetworkRequests, NetworkResponses, PeerManagerAdapter,
@@ -66,8 +71,61 @@ pub const MIN_BLOCK_PROD_TIME: Duration = Duration::from_millis(100);
 /// max block production time in milliseconds
 pub const MAX_BLOCK_PROD_TIME: Duration = Duration::from_millis(200);
 
-/// Sets up ClientActor and ViewClientActor viewing the same store/runtime.
+/// Settings of the `ClientConfig` of a test node which differ from the config the test setup
+/// would use otherwise. Unset fields are left as they are.
+#[derive(Clone, Debug, Default)]
+pub struct ClientConfigOverrides {
+    pub min_block_production_delay: Option<Duration>,
+    pub max_block_production_delay: Option<Duration>,
+    pub max_block_wait_delay: Option<Duration>,
+    pub block_production_tracking_delay: Option<Duration>,
+    pub transaction_pool_size_limit: Option<u64>,
+    pub gc: Option<GCConfig>,
+    pub block_fetch_horizon: Option<BlockHeightDelta>,
+    pub block_header_fetch_horizon: Option<BlockHeightDelta>,
+    pub chunk_request_retry_period: Option<Duration>,
+    pub max_block_with_missing_chunks_age: Option<Duration>,
+}
+
+impl ClientConfigOverrides {
+    pub fn apply(&self, config: &mut ClientConfig) {
+        if let Some(delay) = self.min_block_production_delay {
+            config.min_block_production_delay = delay;
+        }
+        if let Some(delay) = self.max_block_production_delay {
+            config.max_block_production_delay = delay;
+        }
+        if let Some(delay) = self.max_block_wait_delay {
+            config.max_block_wait_delay = delay;
+        }
+        if let Some(delay) = self.block_production_tracking_delay {
+            config.block_production_tracking_delay = delay;
+        }
+        if let Some(limit) = self.transaction_pool_size_limit {
+            config.transaction_pool_size_limit = Some(limit);
+        }
+        if let Some(gc) = &self.gc {
+            config.gc = gc.clone();
+        }
+        if let Some(horizon) = self.block_fetch_horizon {
+            config.block_fetch_horizon = horizon;
+        }
+        if let Some(horizon) = self.block_header_fetch_horizon {
+            config.block_header_fetch_horizon = horizon;
+        }
+        if let Some(period) = self.chunk_request_retry_period {
+            config.chunk_request_retry_period = period;
+        }
+        if let Some(age) = self.max_block_with_missing_chunks_age {
+            config.max_block_with_missing_chunks_age = age;
+        }
+    }
+}
+
+/// Sets up ClientActor and ViewClientActor viewing the same store/runtime. The store is created
+/// unless given, e.g. with a chain written by `seed_chain` with the same genesis, and returned.
 pub fn setup(
+    store: Option<Store>,
     vs: ValidatorSchedule,
     epoch_length: BlockHeightDelta,
     account_id: AccountId,
@@ -81,9 +139,10 @@ pub fn setup(
     network_adapter: PeerManagerAdapter,
     transaction_validity_period: NumBlocks,
     genesis_time: DateTime<Utc>,
+    config_overrides: &ClientConfigOverrides,
     ctx: &Context<ClientActor>,
-) -> (Block, ClientActor, Addr<ViewClientActor>, ShardsManagerAdapterForTest) {
-    let store = create_test_store();
+) -> (Block, ClientActor, Addr<ViewClientActor>, ShardsManagerAdapterForTest, Store) {
+    let store = store.unwrap_or_else(create_test_store);
     let num_validator_seats = vs.all_block_producers().count() as NumSeats;
     let epoch_manager = MockEpochManager::new_with_validators(store.clone(), vs, epoch_length);
     let shard_tracker = ShardTracker::new(TrackedConfig::AllShards, epoch_manager.clone());
@@ -115,6 +174,7 @@ pub fn setup(
             save_trie_changes: true,
             background_migration_threads: 1,
             state_split_config: StateSplitConfig::default(),
+            missing_chunks_pool_limits: MissingChunksPoolLimits::default(),
         },
         None,
     )
@@ -123,7 +183,7 @@ pub fn setup(
 
     let signer = Arc::new(create_test_signer(account_id.as_str()));
     let telemetry = TelemetryActor::default().start();
-    let config = ClientConfig::test(
+    let mut config = ClientConfig::test(
         skip_sync_wait,
         min_block_prod_time,
         max_block_prod_time,
@@ -133,6 +193,7 @@ pub fn setup(
         epoch_sync_enabled,
         state_sync_enabled,
     );
+    config_overrides.apply(&mut config);
 
     let adv = crate::adversarial::Controls::default();
 
@@ -153,8 +214,9 @@ pub fn setup(
         network_adapter.clone().into_sender(),
         ctx.address().with_auto_span_context().into_sender(),
         Some(account_id),
-        store,
+        store.clone(),
         config.chunk_request_retry_period,
+        config.chunk_request_retry_per_shard.clone(),
     );
     let shards_manager_adapter = Arc::new(shards_manager_addr.with_auto_span_context());
 
@@ -173,6 +235,7 @@ pub fn setup(
         enable_doomslug,
         TEST_SEED,
         None,
+        None,
     )
     .unwrap();
     let client_actor = ClientActor::new(
@@ -189,7 +252,7 @@ pub fn setup(
         None,
     )
     .unwrap();
-    (genesis_block, client_actor, view_client_addr, shards_manager_adapter.into())
+    (genesis_block, client_actor, view_client_addr, shards_manager_adapter.into(), store)
 }
 
 pub fn setup_only_view(
@@ -240,6 +303,7 @@ pub fn setup_only_view(
             save_trie_changes: true,
             background_migration_threads: 1,
             state_split_config: StateSplitConfig::default(),
+            missing_chunks_pool_limits: MissingChunksPoolLimits::default(),
         },
         None,
     )
@@ -315,7 +379,8 @@ pub fn setup_mock_with_validity_period_and_no_epoch_sync(
     let mut sma: Option<ShardsManagerAdapterForTest> = None;
     let client_addr = ClientActor::create(|ctx: &mut Context<ClientActor>| {
         let vs = ValidatorSchedule::new().block_producers_per_epoch(vec![validators]);
-        let (_, client, view_client_addr, shards_manager_adapter) = setup(
+        let (_, client, view_client_addr, shards_manager_adapter, _) = setup(
+            None,
             vs,
             10,
             account_id,
@@ -329,6 +394,7 @@ pub fn setup_mock_with_validity_period_and_no_epoch_sync(
             network_adapter.clone().into(),
             transaction_validity_period,
             StaticClock::utc(),
+            &ClientConfigOverrides::default(),
             ctx,
         );
         vca = Some(view_client_addr);
@@ -439,6 +505,50 @@ pub fn setup_mock_all_validators(
             &PeerManagerMessageRequest,
         ) -> (PeerManagerMessageResponse, /* perform default */ bool),
     >,
+) -> (Block, Vec<ActorHandlesForTesting>, Arc<RwLock<BlockStats>>) {
+    setup_mock_all_validators_with_config_overrides(
+        vs,
+        key_pairs,
+        skip_sync_wait,
+        block_prod_time,
+        drop_chunks,
+        tamper_with_fg,
+        epoch_length,
+        enable_doomslug,
+        archive,
+        epoch_sync_enabled,
+        check_block_stats,
+        peer_manager_mock,
+        vec![],
+    )
+}
+
+/// Same as `setup_mock_all_validators`, except that the `ClientConfig` of the node at every index
+/// of `config_overrides` is adjusted by the overrides at that index. The nodes past the end of
+/// `config_overrides` use the same config as in `setup_mock_all_validators`.
+pub fn setup_mock_all_validators_with_config_overrides(
+    vs: ValidatorSchedule,
+    key_pairs: Vec<PeerInfo>,
+    skip_sync_wait: bool,
+    block_prod_time: u64,
+    drop_chunks: bool,
+    tamper_with_fg: bool,
+    epoch_length: BlockHeightDelta,
+    enable_doomslug: bool,
+    archive: Vec<bool>,
+    epoch_sync_enabled: Vec<bool>,
+    check_block_stats: bool,
+    peer_manager_mock: Box<
+        dyn FnMut(
+            // Peer validators
+            &[ActorHandlesForTesting],
+            // Validator that sends the message
+            AccountId,
+            // The message itself
+            &PeerManagerMessageRequest,
+        ) -> (PeerManagerMessageResponse, /* perform default */ bool),
+    >,
+    config_overrides: Vec<ClientConfigOverrides>,
 ) -> (Block, Vec<ActorHandlesForTesting>, Arc<RwLock<BlockStats>>) {
     let peer_manager_mock = Arc::new(RwLock::new(peer_manager_mock));
     let validators = vs.all_validators().cloned().collect::<Vec<_>>();
@@ -466,6 +576,7 @@ pub fn setup_mock_all_validators(
         let mut shards_manager_adapter_slot = None;
         let validators_clone2 = validators.clone();
         let genesis_block1 = genesis_block.clone();
+        let genesis_block2 = genesis_block.clone();
         let key_pairs = key_pairs.clone();
         let key_pairs1 = key_pairs.clone();
         let addresses = addresses.clone();
@@ -479,6 +590,7 @@ pub fn setup_mock_all_validators(
         let hash_to_height1 = hash_to_height.clone();
         let archive1 = archive.clone();
         let epoch_sync_enabled1 = epoch_sync_enabled.clone();
+        let config_overrides = config_overrides.get(index).cloned().unwrap_or_default();
         let client_addr = ClientActor::create(|ctx| {
             let client_addr = ctx.address();
             let _account_id = account_id.clone();
@@ -497,6 +609,8 @@ pub fn setup_mock_all_validators(
 
                     {
                         let last_height2 = last_height2.read().unwrap();
+                        let genesis_hash =
+                            *genesis_block2.read().unwrap().as_ref().unwrap().hash();
                         let peers: Vec<_> = key_pairs1
                             .iter()
                             .take(connectors1.len())
@@ -507,7 +621,7 @@ pub fn setup_mock_all_validators(
                                     chain_info: PeerChainInfo {
                                         genesis_id: GenesisId {
                                             chain_id: "unittest".to_string(),
-                                            hash: Default::default(),
+                                            hash: genesis_hash,
                                         },
                                         // TODO: add the correct hash here
                                         last_block: Some(BlockInfo {
@@ -550,7 +664,7 @@ pub fn setup_mock_all_validators(
                         NetworkRequests::Block { block } => {
                             if check_block_stats {
                                 let block_stats2 = &mut *block_stats1.write().unwrap();
-                                block_stats2.add_block(block);
+                                block_stats2.add_block(block, &account_id);
                                 block_stats2.check_stats(false);
                             }
 
@@ -823,6 +937,17 @@ pub fn setup_mock_all_validators(
                                 }
                             };
                         }
+                        #[cfg(feature = "protocol_feature_chunk_validation")]
+                        NetworkRequests::ChunkEndorsement(target, endorsement) => {
+                            for (i, name) in validators_clone2.iter().enumerate() {
+                                if name == target {
+                                    connectors1[i].client_actor.do_send(
+                                        ChunkEndorsementMessage(endorsement.clone())
+                                            .with_span_context(),
+                                    );
+                                }
+                            }
+                        }
                         NetworkRequests::ForwardTx(_, _)
                         | NetworkRequests::BanPeer { .. }
                         | NetworkRequests::TxStatus(_, _, _)
@@ -833,7 +958,8 @@ pub fn setup_mock_all_validators(
                 resp
             })
                 .start();
-            let (block, client, view_client_addr, shards_manager_adapter) = setup(
+            let (block, client, view_client_addr, shards_manager_adapter, _) = setup(
+                None,
                 vs,
                 epoch_length,
                 _account_id,
@@ -847,6 +973,7 @@ pub fn setup_mock_all_validators(
                 Arc::new(pm).into(),
                 10000,
                 genesis_time,
+                &config_overrides,
                 ctx,
             );
             view_client_addr_slot = Some(view_client_addr);
@@ -948,6 +1075,7 @@ pub fn setup_client_with_runtime(
         enable_doomslug,
         rng_seed,
         snapshot_callbacks,
+        None,
     )
     .unwrap();
     client.sync_status = SyncStatus::NoSync;
@@ -1012,6 +1140,7 @@ pub fn setup_synchronous_shards_manager(
             save_trie_changes: true,
             background_migration_threads: 1,
             state_split_config: StateSplitConfig::default(),
+            missing_chunks_pool_limits: MissingChunksPoolLimits::default(),
         }, // irrelevant
         None,
     )
@@ -1078,6 +1207,40 @@ pub fn setup_client_with_synchronous_shards_manager(
     )
 }
 
+/// Writes a chain of `n_blocks` blocks on top of the head of `store`, i.e. of the genesis if the
+/// store is empty, as produced by the block producers of `vs`. A client set up afterwards over the store with the same genesis and
+/// validators adopts the chain and continues it. The chunks of the blocks are empty.
+pub fn seed_chain(
+    store: Store,
+    chain_genesis: ChainGenesis,
+    n_blocks: NumBlocks,
+    vs: ValidatorSchedule,
+) {
+    let account_id = vs.all_block_producers().next().unwrap().clone();
+    let mut client = setup_client_with_synchronous_shards_manager(
+        store,
+        vs,
+        Some(account_id),
+        false,
+        Arc::new(MockPeerManagerAdapter::default()).into(),
+        Sender::noop(),
+        chain_genesis,
+        TEST_SEED,
+        false,
+        true,
+    );
+    let start_height = client.chain.head().unwrap().height;
+    for height in start_height + 1..=start_height + n_blocks {
+        // Each block is produced by the block producer of its height.
+        let prev_hash = client.chain.head().unwrap().last_block_hash;
+        let epoch_id = client.epoch_manager.get_epoch_id_from_prev_block(&prev_hash).unwrap();
+        let block_producer = client.epoch_manager.get_block_producer(&epoch_id, height).unwrap();
+        client.validator_signer = Some(Arc::new(create_test_signer(block_producer.as_str())));
+        let block = client.produce_block(height).unwrap().unwrap();
+        client.process_block_test(block.into(), Provenance::PRODUCED).unwrap();
+    }
+}
+
 /// A combined trait bound for both the client side and network side of the ShardsManager API.
 #[derive(Clone, derive_more::AsRef)]
 pub struct ShardsManagerAdapterForTest {
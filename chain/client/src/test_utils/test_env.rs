@@ -171,6 +171,67 @@ impl TestEnv {
         }
     }
 
+    /// Like `process_partial_encoded_chunks`, but also delivers the requests for chunk parts and
+    /// the forwarded transactions, until the clients stop sending them. This keeps the clients
+    /// which don't own parts of the chunks, e.g. the validators of other epochs, in sync. Other
+    /// requests are dropped.
+    pub fn process_network_requests(&mut self) {
+        let network_adapters = self.network_adapters.clone();
+        let mut keep_going = true;
+        while keep_going {
+            keep_going = false;
+            for (i, network_adapter) in network_adapters.iter().enumerate() {
+                while let Some(request) = network_adapter.pop() {
+                    keep_going = true;
+                    match request {
+                        PeerManagerMessageRequest::NetworkRequests(
+                            NetworkRequests::PartialEncodedChunkMessage {
+                                account_id,
+                                partial_encoded_chunk,
+                            },
+                        ) => {
+                            self.shards_manager(&account_id).send(
+                                ShardsManagerRequestFromNetwork::ProcessPartialEncodedChunk(
+                                    PartialEncodedChunk::from(partial_encoded_chunk),
+                                ),
+                            );
+                        }
+                        PeerManagerMessageRequest::NetworkRequests(
+                            NetworkRequests::PartialEncodedChunkForward { account_id, forward },
+                        ) => {
+                            self.shards_manager(&account_id).send(
+                                ShardsManagerRequestFromNetwork::ProcessPartialEncodedChunkForward(
+                                    forward,
+                                ),
+                            );
+                        }
+                        request @ PeerManagerMessageRequest::NetworkRequests(
+                            NetworkRequests::PartialEncodedChunkRequest { .. },
+                        ) => {
+                            self.process_partial_encoded_chunk_request(i, request);
+                        }
+                        PeerManagerMessageRequest::NetworkRequests(NetworkRequests::ForwardTx(
+                            account_id,
+                            tx,
+                        )) => {
+                            let response = self.client(&account_id).process_tx(tx, true, false);
+                            tracing::debug!(target: "test", ?account_id, ?response, "forwarded transaction");
+                        }
+                        #[cfg(feature = "protocol_feature_chunk_validation")]
+                        PeerManagerMessageRequest::NetworkRequests(
+                            NetworkRequests::ChunkEndorsement(account_id, endorsement),
+                        ) => {
+                            self.client(&account_id).process_chunk_endorsement(endorsement);
+                        }
+                        _ => {
+                            tracing::debug!(target: "test", ?request, "skipping unsupported request type");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     /// Process all PartialEncodedChunkRequests in the network queue for a client
     /// `id`: id for the client
     pub fn process_partial_encoded_chunks_requests(&mut self, id: usize) {
@@ -253,9 +314,19 @@ impl TestEnv {
                     self.clients[id]
                         .on_chunk_header_ready_for_inclusion(chunk_header, chunk_producer);
                 }
+                ShardsManagerResponse::ChunksCompleted(chunks) => {
+                    self.clients[id].on_chunks_completed(chunks, Arc::new(|_| {}));
+                }
+                ShardsManagerResponse::ChunkHeadersReadyForInclusion(chunk_headers) => {
+                    for (chunk_header, chunk_producer) in chunk_headers {
+                        self.clients[id]
+                            .on_chunk_header_ready_for_inclusion(chunk_header, chunk_producer);
+                    }
+                }
             }
             any_processed = true;
         }
+        self.clients[id].finish_chunk_persistence();
         any_processed
     }
 
@@ -485,7 +556,10 @@ impl TestEnv {
             | ProcessTxResponse::RequestRouted
             | ProcessTxResponse::ValidTx => (),
             ProcessTxResponse::InvalidTx(e) => return Err(e),
-            ProcessTxResponse::DoesNotTrackShard => panic!("test setup is buggy"),
+            ProcessTxResponse::DoesNotTrackShard
+            | ProcessTxResponse::NotCaughtUp
+            | ProcessTxResponse::RejectedByPolicy(_)
+            | ProcessTxResponse::Throttled => panic!("test setup is buggy"),
         }
         let max_iters = 100;
         let tip = self.clients[0].chain.head().unwrap();
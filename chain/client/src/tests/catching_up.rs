@@ -1,30 +1,38 @@
 use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::{Arc, RwLock};
 
 use actix::{Addr, System};
 use borsh::{BorshDeserialize, BorshSerialize};
 use futures::{future, FutureExt};
 
-use crate::adapter::ProcessTxRequest;
-use crate::test_utils::{setup_mock_all_validators, ActorHandlesForTesting};
+use crate::adapter::{ProcessTxRequest, ProcessTxResponse};
+use crate::metrics;
+use crate::sync::state::StateSync;
+use crate::test_utils::{run_catchup, setup_mock_all_validators, ActorHandlesForTesting, TestEnv};
 use crate::{ClientActor, Query};
 use near_actix_test_utils::run_actix;
+use near_chain::chain::BlocksCatchUpState;
 use near_chain::test_utils::{account_id_to_shard_id, ValidatorSchedule};
-use near_chain_configs::TEST_STATE_SYNC_TIMEOUT;
+use near_chain::ChainGenesis;
+use near_chain_configs::{SyncConfig, TEST_STATE_SYNC_TIMEOUT};
+use near_client_primitives::types::{ShardSyncDownload, ShardSyncStatus};
 use near_crypto::{InMemorySigner, KeyType};
 use near_network::types::PeerInfo;
 use near_network::types::{NetworkRequests, NetworkResponses, PeerManagerMessageRequest};
-use near_o11y::testonly::init_integration_logger;
+use near_o11y::testonly::{init_integration_logger, init_test_logger};
 use near_o11y::WithSpanContextExt;
+use near_pool::InsertTransactionResult;
 use near_primitives::hash::{hash as hash_func, CryptoHash};
 use near_primitives::network::PeerId;
 use near_primitives::receipt::Receipt;
-use near_primitives::sharding::ChunkHash;
+use near_primitives::shard_layout::get_block_shard_uid;
+use near_primitives::sharding::{ChunkHash, ShardInfo, StateSyncInfo};
 use near_primitives::transaction::SignedTransaction;
-use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta, BlockReference};
+use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta, BlockReference, EpochId};
 use near_primitives::views::QueryRequest;
 use near_primitives::views::QueryResponseKind::ViewAccount;
+use near_store::DBCol;
 
 fn get_validators_and_key_pairs() -> (ValidatorSchedule, Vec<PeerInfo>) {
     let vs = ValidatorSchedule::new().num_shards(4).block_producers_per_epoch(vec![
@@ -805,3 +813,195 @@ fn test_all_chunks_accepted_common(
         near_network::test_utils::wait_or_panic(max_wait_ms);
     });
 }
+
+/// Catchup state syncs which nothing is going to finish anymore are dropped by the sweep, while
+/// the ones in progress are kept.
+#[test]
+fn test_sweep_stale_catchup_state_syncs() {
+    init_test_logger();
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    for height in 1..4 {
+        env.produce_block(0, height);
+    }
+    let network_adapter = env.network_adapters[0].clone();
+    let client = &mut env.clients[0];
+    let head = client.chain.head().unwrap();
+    let new_catchup = |sync_hash| {
+        (
+            StateSync::new(
+                network_adapter.clone().into(),
+                client.config.state_sync_timeout,
+                &client.config.chain_id,
+                &SyncConfig::Peers,
+                true,
+                client.sync_debug_log.clone(),
+                client.resharding_log.clone(),
+            ),
+            HashMap::new(),
+            BlocksCatchUpState::new(sync_hash, head.epoch_id.clone()),
+        )
+    };
+    // A catchup in progress has its StateSyncInfo in the store.
+    let in_progress_hash = *client.chain.get_block_by_height(1).unwrap().hash();
+    let in_progress_catchup = new_catchup(in_progress_hash);
+    // The StateSyncInfo of this one is gone, as it happens after a reorg.
+    let stale_hash = head.last_block_hash;
+    let stale_catchup = new_catchup(stale_hash);
+
+    let mut store_update = client.chain.mut_store().store_update();
+    store_update
+        .add_state_sync_info(StateSyncInfo { epoch_tail_hash: in_progress_hash, shards: vec![] });
+    store_update.commit().unwrap();
+    client.catchup_state_syncs.insert(in_progress_hash, in_progress_catchup);
+    client.catchup_state_syncs.insert(stale_hash, stale_catchup);
+
+    let num_stale_before = metrics::CATCHUP_STATE_SYNCS_STALE.get();
+    client.sweep_catchup_state_syncs().unwrap();
+    assert_eq!(client.catchup_state_syncs.keys().collect::<Vec<_>>(), vec![&in_progress_hash]);
+    assert_eq!(metrics::CATCHUP_STATE_SYNCS.get(), 1);
+    assert!(metrics::CATCHUP_STATE_SYNCS_STALE.get() > num_stale_before);
+
+    let mut store_update = client.chain.mut_store().store_update();
+    store_update.remove_state_sync_info(in_progress_hash);
+    store_update.commit().unwrap();
+    client.sweep_catchup_state_syncs().unwrap();
+    assert!(client.catchup_state_syncs.is_empty());
+    assert_eq!(metrics::CATCHUP_STATE_SYNCS.get(), 0);
+}
+
+/// Forwarded transactions received while the node hasn't caught up with their shard are buffered,
+/// up to the configured limit, and added to the pool once the catchup of the shard completes.
+#[test]
+fn test_not_caught_up_txs_processed_after_catchup() {
+    init_test_logger();
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    for height in 1..4 {
+        env.produce_block(0, height);
+    }
+    // Until the shard is caught up, the chunk extra of the head is missing. Restart the client,
+    // so that it isn't cached either.
+    let head = env.clients[0].chain.head().unwrap();
+    let shard_uid = env.clients[0].epoch_manager.shard_id_to_uid(0, &head.epoch_id).unwrap();
+    let chunk_extra_key = get_block_shard_uid(&head.last_block_hash, &shard_uid);
+    let store = env.clients[0].runtime_adapter.store().clone();
+    let chunk_extra = store.get(DBCol::ChunkExtra, &chunk_extra_key).unwrap().unwrap().to_vec();
+    let mut store_update = store.store_update();
+    store_update.delete(DBCol::ChunkExtra, &chunk_extra_key);
+    store_update.commit().unwrap();
+    env.restart(0);
+
+    let client = &mut env.clients[0];
+    client.config.not_caught_up_tx_buffer_size = 2;
+    let signer = InMemorySigner::from_seed("test".parse().unwrap(), KeyType::ED25519, "test");
+    let txs: Vec<_> = (1..=3)
+        .map(|nonce| {
+            SignedTransaction::send_money(
+                nonce,
+                "test".parse().unwrap(),
+                "test".parse().unwrap(),
+                &signer,
+                1,
+                head.last_block_hash,
+            )
+        })
+        .collect();
+    let count = |status| metrics::TRANSACTION_NOT_CAUGHT_UP.with_label_values(&["0", status]).get();
+    let (buffered, dropped, reprocessed) =
+        (count("buffered"), count("dropped"), count("reprocessed"));
+    for tx in &txs {
+        assert_eq!(client.process_tx(tx.clone(), true, false), ProcessTxResponse::NotCaughtUp);
+    }
+    // The last transaction doesn't fit into the buffer.
+    assert_eq!(
+        client.not_caught_up_txs[&0].1.iter().collect::<Vec<_>>(),
+        txs[..2].iter().collect::<Vec<_>>()
+    );
+    assert_eq!(count("buffered"), buffered + 2);
+    assert!(count("dropped") > dropped);
+    assert_eq!(count("reprocessed"), reprocessed);
+
+    // Emulate the state of the shard being downloaded, so that the catchup completes.
+    let mut store_update = store.store_update();
+    store_update.set(DBCol::ChunkExtra, &chunk_extra_key, &chunk_extra);
+    store_update.commit().unwrap();
+    let sync_hash = head.last_block_hash;
+    let chunk_hash = client.chain.get_block(&sync_hash).unwrap().chunks()[0].chunk_hash();
+    let mut store_update = client.chain.mut_store().store_update();
+    store_update.add_state_sync_info(StateSyncInfo {
+        epoch_tail_hash: sync_hash,
+        shards: vec![ShardInfo(0, chunk_hash)],
+    });
+    store_update.commit().unwrap();
+    let state_sync = StateSync::new(
+        env.network_adapters[0].clone().into(),
+        client.config.state_sync_timeout,
+        &client.config.chain_id,
+        &SyncConfig::Peers,
+        true,
+        client.sync_debug_log.clone(),
+        client.resharding_log.clone(),
+    );
+    let shard_sync = HashMap::from([(
+        0,
+        ShardSyncDownload { downloads: vec![], status: ShardSyncStatus::StateSyncDone },
+    )]);
+    client.catchup_state_syncs.insert(
+        sync_hash,
+        (state_sync, shard_sync, BlocksCatchUpState::new(sync_hash, head.epoch_id.clone())),
+    );
+    run_catchup(client, &[]).unwrap();
+
+    assert!(client.catchup_state_syncs.is_empty());
+    assert!(client.not_caught_up_txs.is_empty());
+    assert_eq!(count("reprocessed"), reprocessed + 2);
+    for tx in &txs[..2] {
+        assert_eq!(
+            client.sharded_tx_pool.insert_transaction(shard_uid, tx.clone()),
+            InsertTransactionResult::Duplicate
+        );
+    }
+}
+
+/// The forwarded transactions buffered until the catchup of a shard are dropped along with the
+/// catchup, and when the epoch they were received in ends.
+#[test]
+fn test_not_caught_up_txs_dropped() {
+    init_test_logger();
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    for height in 1..4 {
+        env.produce_block(0, height);
+    }
+    let head = env.clients[0].chain.head().unwrap();
+    let signer = InMemorySigner::from_seed("test".parse().unwrap(), KeyType::ED25519, "test");
+    let tx = SignedTransaction::send_money(
+        1,
+        "test".parse().unwrap(),
+        "test".parse().unwrap(),
+        &signer,
+        1,
+        head.last_block_hash,
+    );
+    let count = |status| metrics::TRANSACTION_NOT_CAUGHT_UP.with_label_values(&["0", status]).get();
+
+    // The catchup of the shard is stale, as its sync block was garbage collected.
+    let client = &mut env.clients[0];
+    let epoch_id =
+        client.epoch_manager.get_epoch_id_from_prev_block(&head.last_block_hash).unwrap();
+    client.not_caught_up_txs.insert(0, (epoch_id, VecDeque::from([tx.clone()])));
+    let mut store_update = client.chain.mut_store().store_update();
+    store_update.add_state_sync_info(StateSyncInfo {
+        epoch_tail_hash: hash_func(b"collected"),
+        shards: vec![ShardInfo(0, ChunkHash::default())],
+    });
+    store_update.commit().unwrap();
+    let dropped = count("dropped");
+    client.sweep_catchup_state_syncs().unwrap();
+    assert!(client.not_caught_up_txs.is_empty());
+    assert_eq!(count("dropped"), dropped + 1);
+
+    // The transactions buffered in the previous epoch.
+    client.not_caught_up_txs.insert(0, (EpochId(hash_func(b"previous")), VecDeque::from([tx])));
+    client.sweep_catchup_state_syncs().unwrap();
+    assert!(client.not_caught_up_txs.is_empty());
+    assert_eq!(count("dropped"), dropped + 2);
+}
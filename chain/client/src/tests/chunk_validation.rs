@@ -0,0 +1,71 @@
+use std::collections::HashSet;
+
+use actix::System;
+use near_actix_test_utils::run_actix;
+use near_chain::test_utils::{MockEpochManager, ValidatorSchedule};
+use near_epoch_manager::EpochManagerAdapter;
+use near_network::types::{NetworkRequests, NetworkResponses, PeerInfo, PeerManagerMessageRequest};
+use near_o11y::testonly::init_test_logger;
+use near_primitives::types::{AccountId, EpochId};
+use near_store::test_utils::create_test_store;
+
+use crate::test_utils::setup_mock_all_validators;
+
+/// Every chunk validator endorses the new chunks of the shards it was sampled for, signed with
+/// its own key, and sends the endorsements to the producer of the next block.
+#[test]
+fn test_chunk_endorsements_sent_to_next_block_producer() {
+    init_test_logger();
+    run_actix(async {
+        let validators: Vec<AccountId> =
+            ["test0", "test1", "test2", "test3"].iter().map(|id| id.parse().unwrap()).collect();
+        let epoch_length = 100;
+        let vs = ValidatorSchedule::new()
+            .num_shards(2)
+            .block_producers_per_epoch(vec![validators.clone()])
+            .validator_mandates_per_shard(3);
+        // Stays in the first epoch, so the assignments are the ones of the default epoch id.
+        let epoch_manager =
+            MockEpochManager::new_with_validators(create_test_store(), vs.clone(), epoch_length);
+        let key_pairs = validators.iter().map(|_| PeerInfo::random()).collect::<Vec<_>>();
+
+        let mut endorsed_shards = HashSet::new();
+        let (_, _conn, _) = setup_mock_all_validators(
+            vs,
+            key_pairs,
+            true,
+            100,
+            false,
+            false,
+            epoch_length,
+            false,
+            vec![false; validators.len()],
+            vec![true; validators.len()],
+            false,
+            Box::new(move |_, sender: AccountId, msg: &PeerManagerMessageRequest| {
+                if let NetworkRequests::ChunkEndorsement(target, endorsement) =
+                    msg.as_network_requests_ref()
+                {
+                    let inner = &endorsement.inner;
+                    assert_eq!(sender, endorsement.account_id);
+                    let chunk_validators = epoch_manager
+                        .sample_chunk_validators(&EpochId::default(), inner.height)
+                        .unwrap();
+                    assert!(chunk_validators[inner.shard_id as usize].contains_key(&sender));
+                    let block_producer = epoch_manager
+                        .get_block_producer(&EpochId::default(), inner.height + 1)
+                        .unwrap();
+                    assert_eq!(target, &block_producer);
+
+                    endorsed_shards.insert(inner.shard_id);
+                    if endorsed_shards.len() == 2 && inner.height >= 10 {
+                        System::current().stop();
+                    }
+                }
+                (NetworkResponses::NoResponse.into(), true)
+            }),
+        );
+
+        near_network::test_utils::wait_or_panic(60000);
+    });
+}
@@ -8,6 +8,8 @@ use near_network::types::NetworkRequests;
 use near_network::types::PartialEncodedChunkRequestMsg;
 use near_o11y::testonly::init_integration_logger;
 use near_primitives::hash::CryptoHash;
+use near_primitives::views::ShardChunkAvailabilityView;
+use near_store::DBCol;
 
 // TODO(#8269) Enable test after fixing the issue related to KeyValueRuntime. See env.restart()
 #[ignore]
@@ -49,3 +51,63 @@ fn test_request_chunk_restart() {
         assert!(false);
     }
 }
+
+/// A chunk missing from the store is reported as a gap in the chunk availability, which then
+/// starts right above it.
+#[test]
+fn test_chunk_availability_with_gap() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    for height in 1..=10 {
+        env.produce_block(0, height);
+    }
+    assert_eq!(
+        env.clients[0].chunk_availability().unwrap(),
+        vec![ShardChunkAvailabilityView {
+            shard_id: 0,
+            available_since_height: Some(0),
+            has_gap: false
+        }]
+    );
+
+    // Remove the chunk of height 5 from the store and restart the client, so that it isn't cached
+    // either, as if it was never persisted.
+    let chunk_header = env.clients[0].chain.get_block_by_height(5).unwrap().chunks()[0].clone();
+    assert_eq!(chunk_header.height_included(), 5);
+    let store = env.clients[0].runtime_adapter.store().clone();
+    let mut store_update = store.store_update();
+    store_update.delete(DBCol::Chunks, chunk_header.chunk_hash().as_ref());
+    store_update.commit().unwrap();
+    env.restart(0);
+    assert_eq!(
+        env.clients[0].chunk_availability().unwrap(),
+        vec![ShardChunkAvailabilityView {
+            shard_id: 0,
+            available_since_height: Some(6),
+            has_gap: true
+        }]
+    );
+
+    // The availability is updated from the blocks on top of the previous head, until the gap
+    // is out of the window.
+    env.produce_block(0, 11);
+    assert_eq!(
+        env.clients[0].chunk_availability().unwrap(),
+        vec![ShardChunkAvailabilityView {
+            shard_id: 0,
+            available_since_height: Some(6),
+            has_gap: true
+        }]
+    );
+    env.clients[0].config.chunk_availability_window = 5;
+    for height in 12..=15 {
+        env.produce_block(0, height);
+        assert_eq!(
+            env.clients[0].chunk_availability().unwrap(),
+            vec![ShardChunkAvailabilityView {
+                shard_id: 0,
+                available_since_height: Some(height - 5),
+                has_gap: false,
+            }]
+        );
+    }
+}
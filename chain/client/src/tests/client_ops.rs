@@ -0,0 +1,81 @@
+//! Drives a client through `ClientOps` alone, without actix.
+use crate::test_utils::TestEnv;
+use crate::ClientOps;
+use near_chain::{ChainGenesis, DoneApplyChunkCallback, Provenance};
+use near_client_primitives::types::Error;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::BlockHeight;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Produces and processes blocks at the given heights, one at a time. Like the `ClientActor` on
+/// `ApplyChunksDoneMessage`, it postprocesses the blocks once the client signals that their
+/// chunks were applied.
+struct SequentialDriver<'a, C: ClientOps> {
+    client: &'a mut C,
+    apply_chunks_done_sender: Sender<CryptoHash>,
+    apply_chunks_done_receiver: Receiver<CryptoHash>,
+}
+
+impl<'a, C: ClientOps> SequentialDriver<'a, C> {
+    fn new(client: &'a mut C) -> Self {
+        let (apply_chunks_done_sender, apply_chunks_done_receiver) = channel();
+        Self { client, apply_chunks_done_sender, apply_chunks_done_receiver }
+    }
+
+    fn apply_chunks_done_callback(&self) -> DoneApplyChunkCallback {
+        let sender = self.apply_chunks_done_sender.clone();
+        Arc::new(move |block_hash| {
+            let _ = sender.send(block_hash);
+        })
+    }
+
+    /// Produces the block at `height` and waits until it's accepted. Returns its hash, or `None`
+    /// if the client didn't produce a block.
+    fn produce_block(&mut self, height: BlockHeight) -> Result<Option<CryptoHash>, Error> {
+        let Some(block) = self.client.produce_block(height)? else {
+            return Ok(None);
+        };
+        let block_hash = *block.hash();
+        self.client.start_process_block(
+            block.into(),
+            Provenance::PRODUCED,
+            self.apply_chunks_done_callback(),
+        )?;
+        loop {
+            self.client.process_persisted_chunks(self.apply_chunks_done_callback());
+            let (accepted_blocks, errors) =
+                self.client.postprocess_ready_blocks(self.apply_chunks_done_callback(), true);
+            if let Some(err) = errors.into_values().next() {
+                return Err(err.into());
+            }
+            if accepted_blocks.contains(&block_hash) {
+                break;
+            }
+            self.apply_chunks_done_receiver
+                .recv_timeout(Duration::from_secs(10))
+                .expect("chunks of the block weren't applied in time");
+        }
+        self.client.flush_chain_heads_update();
+        Ok(Some(block_hash))
+    }
+}
+
+#[test]
+fn test_advance_chain_through_client_ops() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    let mut driver = SequentialDriver::new(&mut env.clients[0]);
+    let mut last_block_hash = None;
+    for height in 1..=10 {
+        last_block_hash = driver.produce_block(height).unwrap();
+        assert!(last_block_hash.is_some(), "no block produced at height {height}");
+    }
+
+    let head = env.clients[0].chain.head().unwrap();
+    assert_eq!(head.height, 10);
+    assert_eq!(Some(head.last_block_hash), last_block_hash);
+    // The chunks the client produced on accepting the blocks were included too.
+    let block = env.clients[0].chain.get_block(&head.last_block_hash).unwrap();
+    assert!(block.header().chunk_mask().iter().all(|included| *included));
+}
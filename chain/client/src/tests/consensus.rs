@@ -1,12 +1,17 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::{Arc, RwLock, RwLockWriteGuard};
+use std::time::Duration;
 
 use actix::System;
 use near_chain::test_utils::ValidatorSchedule;
+use once_cell::sync::OnceCell;
 use rand::{thread_rng, Rng};
 
 use crate::adapter::{BlockApproval, BlockResponse};
-use crate::test_utils::{setup_mock_all_validators, ActorHandlesForTesting};
+use crate::test_utils::{
+    setup_mock_all_validators, setup_mock_all_validators_with_config_overrides,
+    ActorHandlesForTesting, BlockStats, ClientConfigOverrides,
+};
 use near_actix_test_utils::run_actix;
 use near_chain::Block;
 use near_network::types::PeerInfo;
@@ -284,3 +289,74 @@ fn test_consensus_with_epoch_switches() {
         near_network::test_utils::wait_or_panic(3000 * (80 + HEIGHT_GOAL));
     });
 }
+
+/// Runs four validators, one of which produces blocks ten times slower than the others. The others
+/// skip its heights when it doesn't produce its blocks in time, so it produces fewer blocks than
+/// any of them, while the chain keeps growing without forking.
+#[test]
+fn test_slow_block_producer() {
+    init_integration_logger();
+
+    const BLOCK_PROD_TIME: u64 = 100;
+    const HEIGHT_GOAL: u64 = 60;
+
+    run_actix(async move {
+        let validators: Vec<AccountId> =
+            ["test1", "test2", "test3", "test4"].iter().map(|a| a.parse().unwrap()).collect();
+        let vs = ValidatorSchedule::new()
+            .num_shards(4)
+            .block_producers_per_epoch(vec![validators.clone()]);
+        let key_pairs = (0..4).map(|_| PeerInfo::random()).collect::<Vec<_>>();
+
+        // The slow node only checks whether it can produce a block once per its min block
+        // production delay, while the others skip its height after their max block production
+        // delay, three times the fast min block production delay.
+        let slow_block_prod_time = Duration::from_millis(10 * BLOCK_PROD_TIME);
+        let slow_node = ClientConfigOverrides {
+            min_block_production_delay: Some(slow_block_prod_time),
+            max_block_production_delay: Some(3 * slow_block_prod_time),
+            max_block_wait_delay: Some(3 * slow_block_prod_time),
+            block_production_tracking_delay: Some(slow_block_prod_time),
+            ..Default::default()
+        };
+
+        let block_stats: Arc<OnceCell<Arc<RwLock<BlockStats>>>> = Default::default();
+        let block_stats1 = block_stats.clone();
+        let (_, _, stats) = setup_mock_all_validators_with_config_overrides(
+            vs,
+            key_pairs,
+            true,
+            BLOCK_PROD_TIME,
+            false,
+            false,
+            20,
+            true,
+            vec![false; validators.len()],
+            vec![false; validators.len()],
+            true,
+            Box::new(move |_, _, msg: &PeerManagerMessageRequest| {
+                if let NetworkRequests::Block { block } = msg.as_network_requests_ref() {
+                    if block.header().height() >= HEIGHT_GOAL {
+                        let mut block_stats = block_stats1.get().unwrap().write().unwrap();
+                        block_stats.check_stats(true);
+                        block_stats.check_block_ratio(None, Some(1.2));
+                        let slow_blocks = block_stats.num_blocks_produced_by(&validators[0]);
+                        for validator in &validators[1..] {
+                            let blocks = block_stats.num_blocks_produced_by(validator);
+                            assert!(
+                                slow_blocks < blocks,
+                                "slow producer produced {slow_blocks} blocks, {validator} {blocks}"
+                            );
+                        }
+                        System::current().stop();
+                    }
+                }
+                (NetworkResponses::NoResponse.into(), true)
+            }),
+            vec![slow_node],
+        );
+        block_stats.set(stats).ok().unwrap();
+
+        near_network::test_utils::wait_or_panic(60000);
+    });
+}
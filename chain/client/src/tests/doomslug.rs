@@ -1,8 +1,10 @@
 use crate::test_utils::TestEnv;
 use near_chain::{ChainGenesis, Provenance};
 use near_crypto::KeyType;
+use near_network::test_utils::MockPeerManagerAdapter;
+use near_network::types::{NetworkRequests, PeerManagerMessageRequest};
 use near_o11y::testonly::init_test_logger;
-use near_primitives::block::{Approval, ApprovalType};
+use near_primitives::block::{Approval, ApprovalInner, ApprovalMessage, ApprovalType};
 use near_primitives::hash::CryptoHash;
 use near_primitives::validator_signer::InMemoryValidatorSigner;
 
@@ -32,3 +34,54 @@ fn test_processing_skips_on_forks() {
     env.clients[1].collect_block_approval(&approval, ApprovalType::SelfApproval);
     assert!(!env.clients[1].doomslug.approval_status_at_height(&3).approvals.is_empty());
 }
+
+/// Drains the requests sent to the network, returning the approvals among them.
+fn sent_approvals(network_adapter: &MockPeerManagerAdapter) -> Vec<ApprovalMessage> {
+    std::iter::from_fn(|| network_adapter.pop())
+        .filter_map(|request| match request {
+            PeerManagerMessageRequest::NetworkRequests(NetworkRequests::Approval {
+                approval_message,
+            }) => Some(approval_message),
+            _ => None,
+        })
+        .collect()
+}
+
+// An endorsement created for the head is dropped instead of sent if another head is processed
+// before it's sent, as doomslug already moved to the new head.
+#[test]
+fn test_no_endorsement_of_superseded_tip() {
+    init_test_logger();
+
+    let mut env =
+        TestEnv::builder(ChainGenesis::test()).clients_count(2).validator_seats(2).build();
+    // test1 produces at odd heights and test0 at even ones.
+    for height in 1..=2 {
+        let producer = (height % 2) as usize;
+        let block = env.clients[producer].produce_block(height).unwrap().unwrap();
+        env.process_block(producer, block.clone(), Provenance::PRODUCED);
+        env.process_block(1 - producer, block, Provenance::NONE);
+    }
+    let b2_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+    assert_eq!(env.clients[0].doomslug.get_tip().0, b2_hash);
+    let validator_signer =
+        InMemoryValidatorSigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let endorsement = Approval::new(b2_hash, 2, 3, &validator_signer);
+    sent_approvals(&env.network_adapters[0]);
+    env.clients[0].send_approval(&b2_hash, endorsement.clone()).unwrap();
+    assert_eq!(sent_approvals(&env.network_adapters[0]).len(), 1);
+
+    // Block 3 becomes the head before the endorsement of block 2 is sent again.
+    let b3 = env.clients[1].produce_block(3).unwrap().unwrap();
+    env.process_block(0, b3.clone(), Provenance::NONE);
+    assert_eq!(env.clients[0].doomslug.get_tip().0, *b3.hash());
+    sent_approvals(&env.network_adapters[0]);
+    env.clients[0].send_approval(&b2_hash, endorsement).unwrap();
+    assert_eq!(sent_approvals(&env.network_adapters[0]), vec![]);
+
+    // Skips aren't tied to the tip and are still sent.
+    let skip = Approval::new(b2_hash, 2, 5, &validator_signer);
+    assert!(matches!(skip.inner, ApprovalInner::Skip(2)));
+    env.clients[0].send_approval(&b2_hash, skip).unwrap();
+    assert_eq!(sent_approvals(&env.network_adapters[0]).len(), 1);
+}
@@ -1,9 +1,14 @@
 mod bug_repros;
 mod catching_up;
+#[cfg(feature = "protocol_feature_chunk_validation")]
+mod chunk_validation;
 mod chunks_management;
+mod client_ops;
 mod consensus;
 mod cross_shard_tx;
 mod doomslug;
 mod maintenance_windows;
 mod process_blocks;
 mod query_client;
+mod replay;
+mod synchronous_client;
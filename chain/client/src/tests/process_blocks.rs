@@ -1,16 +1,54 @@
-use crate::test_utils::TestEnv;
+use crate::block_provenance::BlockProvenanceTracker;
+use crate::chain_heads_throttle::ChainHeadsThrottle;
+use crate::debug::{BanHistory, BlockProductionTracker};
+use crate::metrics;
+use crate::test_utils::{
+    assert_metric_delta, create_chunk_on_height, seed_chain,
+    setup_client_with_synchronous_shards_manager, TestEnv, TEST_SEED,
+};
+use crate::tx_admission_policy::{TxAdmissionContext, TxAdmissionPolicy};
+use crate::tx_lanes::TxLanes;
+use crate::{ChunkProducerErrorKind, ErrorSeverity, ProcessTxResponse, SyncStatus};
 use assert_matches::assert_matches;
-use near_chain::{test_utils, ChainGenesis, Provenance};
+use chrono::TimeZone;
+use near_async::messaging::{CanSend, IntoSender, Sender};
+use near_chain::chain::TX_ROUTING_HEIGHT_HORIZON;
+use near_chain::test_utils::{KeyValueRuntime, MockEpochManager, ValidatorSchedule};
+use near_chain::types::RuntimeAdapter;
+use near_chain::{
+    test_utils, Chain, ChainGenesis, ChainStore, ChainStoreAccess, DoneApplyChunkCallback,
+    Provenance,
+};
+use near_chain_configs::{DumpConfig, ExternalStorageLocation, UpdateableClientConfig};
+use near_chunks::adapter::ShardsManagerRequestFromClient;
+use near_chunks::client::ShardedTransactionPool;
+use near_client_primitives::debug::ReshardingEvent;
 use near_crypto::vrf::Value;
-use near_crypto::{KeyType, PublicKey, Signature};
-use near_primitives::block::Block;
+use near_crypto::{InMemorySigner, KeyType, PublicKey, Signature};
+use near_epoch_manager::EpochManagerAdapter;
+use near_network::test_utils::MockPeerManagerAdapter;
+use near_network::types::{NetworkRequests, PeerManagerMessageRequest};
+use near_pool::types::PoolIterator;
+use near_primitives::block::{Approval, ApprovalInner, Block, Tip};
+use near_primitives::block_header::ApprovalType;
+use near_primitives::errors::InvalidTxError;
+use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::network::PeerId;
+use near_primitives::shard_layout::{account_id_to_shard_uid, get_block_shard_uid, ShardLayout};
 use near_primitives::sharding::ShardChunkHeader;
 use near_primitives::sharding::ShardChunkHeaderV3;
+use near_primitives::static_clock::MockClockGuard;
 use near_primitives::test_utils::create_test_signer;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::chunk_extra::ChunkExtra;
 use near_primitives::types::validator_stake::ValidatorStake;
-use near_primitives::utils::MaybeValidated;
-use std::sync::Arc;
+use near_primitives::types::{AccountId, EpochId};
+use near_primitives::utils::{get_block_shard_id, MaybeValidated};
+use near_store::test_utils::create_test_store;
+use near_store::{DBCol, StoreUpdate, TrieChanges, HEAD_KEY};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
 
 /// Only process one block per height
 /// Test that if a node receives two blocks at the same height, it doesn't process the second one
@@ -113,6 +151,41 @@ fn test_bad_block_content_vrf() {
         env.clients[0].process_block_test(MaybeValidated::from(block), Provenance::NONE).unwrap();
 }
 
+/// Test that banning a peer for an invalid block signature is recorded in the ban history.
+#[test]
+fn test_bad_block_signature_ban_history() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).num_shards(4).build();
+    let prev_block = env.clients[0].produce_block(1).unwrap().unwrap();
+    env.process_block(0, prev_block, Provenance::PRODUCED);
+    let mut bad_block = env.clients[0].produce_block(2).unwrap().unwrap();
+    bad_block.mut_header().get_mut().signature = Signature::default();
+    let bad_block_hash = *bad_block.hash();
+    assert!(env.clients[0].ban_history(None).is_empty());
+
+    let peer_id = PeerId::new(PublicKey::empty(KeyType::ED25519));
+    let err = env.clients[0]
+        .receive_block_impl(bad_block, peer_id.clone(), false, Arc::new(|_| {}))
+        .unwrap_err();
+    assert_matches!(err, near_chain::Error::InvalidSignature);
+
+    let history = env.clients[0].ban_history(None);
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].peer_id, peer_id);
+    assert_eq!(history[0].reason, "BadBlockHeader");
+    assert_eq!(history[0].triggered_by, Some(bad_block_hash));
+    assert_eq!(env.clients[0].ban_history(Some(history[0].ban_time)).len(), 1);
+    let later = history[0].ban_time + chrono::Duration::nanoseconds(1);
+    assert!(env.clients[0].ban_history(Some(later)).is_empty());
+
+    // The history is loaded back from the store after a restart.
+    let reloaded = BanHistory::new(env.clients[0].chain.store().store().clone()).get(None);
+    assert_eq!(reloaded.len(), 1);
+    assert_eq!(reloaded[0].peer_id, peer_id);
+    assert_eq!(reloaded[0].reason, "BadBlockHeader");
+    assert_eq!(reloaded[0].triggered_by, Some(bad_block_hash));
+    assert_eq!(reloaded[0].ban_time, history[0].ban_time);
+}
+
 /// Test that if a block's signature is corrupted, the invalid block will not affect the node's block processing
 #[test]
 fn test_bad_block_signature() {
@@ -136,3 +209,1897 @@ fn test_bad_block_signature() {
     let _ =
         env.clients[0].process_block_test(MaybeValidated::from(block), Provenance::NONE).unwrap();
 }
+
+/// A produced block can be rebuilt from the persisted production inputs, also after a restart, and
+/// a perturbed input is detected as a mismatch.
+#[test]
+fn test_rebuild_produced_block() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    for height in 1..=3 {
+        env.produce_block(0, height);
+    }
+    let block = env.clients[0].rebuild_produced_block(3).unwrap();
+    assert_eq!(block.hash(), env.clients[0].chain.get_block_by_height(3).unwrap().hash());
+
+    env.restart(0);
+    let block = env.clients[0].rebuild_produced_block(3).unwrap();
+    assert_eq!(block.hash(), env.clients[0].chain.get_block_by_height(3).unwrap().hash());
+
+    let (block_hash, mut inputs) = env.clients[0].produced_block_inputs(3).unwrap().unwrap();
+    inputs.timestamp = inputs.timestamp + chrono::Duration::seconds(1);
+    assert_matches!(
+        env.clients[0].rebuild_block(3, &block_hash, &inputs),
+        Err(crate::Error::RebuiltBlockMismatch { height: 3, .. })
+    );
+}
+
+/// Saves `head` both in the store and in the cache of the chain store.
+fn save_head(env: &mut TestEnv, head: &Tip) {
+    let mut store_update = env.clients[0].chain.mut_store().store_update();
+    store_update.save_body_head(head).unwrap();
+    store_update.commit().unwrap();
+}
+
+/// If the epoch of the chain head doesn't match the epoch manager, block production is skipped
+/// and the health check fails, instead of crashing the node. Production resumes once reloading
+/// the head makes them match again.
+#[test]
+fn test_produce_block_with_head_epoch_mismatch() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    for height in 1..=2 {
+        env.produce_block(0, height);
+    }
+    let head = env.clients[0].chain.head().unwrap();
+    let mut inconsistent_head = head.clone();
+    inconsistent_head.epoch_id = EpochId(hash(b"unknown epoch"));
+    let mismatches = metrics::HEAD_EPOCH_MISMATCH_TOTAL.get();
+
+    save_head(&mut env, &inconsistent_head);
+    assert!(env.clients[0].produce_block(3).unwrap().is_none());
+    assert!(env.clients[0].head_epoch_mismatch().is_some());
+    assert_eq!(metrics::HEAD_EPOCH_MISMATCH_TOTAL.get(), mismatches + 1);
+
+    // Only the store is fixed, the chain store keeps the inconsistent head until it's reloaded.
+    save_head(&mut env, &inconsistent_head);
+    let mut store_update = env.clients[0].chain.store().store().store_update();
+    store_update.set_ser(DBCol::BlockMisc, HEAD_KEY, &head).unwrap();
+    store_update.commit().unwrap();
+    env.produce_block(0, 3);
+    assert_eq!(env.clients[0].head_epoch_mismatch(), None);
+    assert_eq!(env.clients[0].chain.head().unwrap().height, 3);
+    assert_eq!(metrics::HEAD_EPOCH_MISMATCH_TOTAL.get(), mismatches + 2);
+}
+
+/// An epoch-boundary block whose next block producers hash doesn't match the epoch manager is
+/// detected when it's accepted. If this node produced it, it isn't processed at all.
+#[test]
+fn test_next_bp_hash_mismatch_detected_on_acceptance() {
+    let chain_genesis = ChainGenesis::test();
+    let store = create_test_store();
+    let epoch_manager = MockEpochManager::new(store.clone(), chain_genesis.epoch_length);
+    let mut env = TestEnv::builder(chain_genesis)
+        .stores(vec![store])
+        .mock_epoch_managers(vec![epoch_manager.clone()])
+        .build();
+    let mut height = 1;
+    let block = loop {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        let prev_header =
+            env.clients[0].chain.get_block_header(block.header().prev_hash()).unwrap();
+        if block.header().epoch_id() != prev_header.epoch_id() {
+            break block;
+        }
+        env.process_block(0, block, Provenance::PRODUCED);
+        assert!(height < 20, "no epoch-boundary block was produced");
+        height += 1;
+    };
+
+    let other_block_producer = ValidatorStake::new(
+        "other".parse().unwrap(),
+        PublicKey::empty(KeyType::ED25519),
+        1_000_000,
+    );
+    epoch_manager.override_epoch_block_producers(
+        block.header().next_epoch_id().clone(),
+        vec![other_block_producer],
+    );
+    let mismatches = metrics::NEXT_BP_HASH_MISMATCH_TOTAL.get();
+    assert_matches!(
+        env.clients[0].process_block_test(block.clone().into(), Provenance::NONE),
+        Err(near_chain::Error::InvalidNextBPHash)
+    );
+    assert_matches!(
+        env.clients[0].process_block_test(block.into(), Provenance::PRODUCED),
+        Err(near_chain::Error::InvalidNextBPHash)
+    );
+    assert_eq!(metrics::NEXT_BP_HASH_MISMATCH_TOTAL.get(), mismatches + 2);
+    assert_eq!(env.clients[0].chain.head().unwrap().height, height - 1);
+}
+
+/// When the number of chunk parts changes, e.g. because a protocol upgrade changed the number of
+/// seats from the next epoch on, the chunks are encoded with the new number of parts.
+#[test]
+fn test_produced_chunks_follow_num_parts_change() {
+    let chain_genesis = ChainGenesis::test();
+    let store = create_test_store();
+    let epoch_manager = MockEpochManager::new(store.clone(), chain_genesis.epoch_length);
+    let mut env = TestEnv::builder(chain_genesis)
+        .stores(vec![store])
+        .mock_epoch_managers(vec![epoch_manager.clone()])
+        .build();
+    let old_total_parts = epoch_manager.num_total_parts();
+    let (chunk, _, _) = create_chunk_on_height(&mut env.clients[0], 1);
+    assert_eq!(chunk.content().parts.len(), old_total_parts);
+
+    let genesis_epoch_id = env.clients[0].chain.head().unwrap().epoch_id;
+    for height in 1.. {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        let epoch_id = block.header().epoch_id().clone();
+        env.process_block(0, block, Provenance::PRODUCED);
+        if epoch_id != genesis_epoch_id {
+            break;
+        }
+        assert!(height < 20, "no epoch-boundary block was produced");
+    }
+
+    let new_total_parts = old_total_parts + 6;
+    epoch_manager.set_num_total_parts(new_total_parts);
+    let new_data_parts = epoch_manager.num_data_parts();
+    let next_height = env.clients[0].chain.head().unwrap().height + 1;
+    let (chunk, _, _) = create_chunk_on_height(&mut env.clients[0], next_height);
+    assert_eq!(env.clients[0].rs_for_chunk_production.data_shard_count(), new_data_parts);
+    assert_eq!(env.clients[0].rs_for_chunk_production.total_shard_count(), new_total_parts);
+    assert_eq!(chunk.content().parts.len(), new_total_parts);
+    let decoded = chunk.decode_chunk(new_data_parts).unwrap();
+    assert_eq!(decoded.chunk_hash(), chunk.chunk_hash());
+}
+
+/// A validator which finds itself slashed doesn't produce blocks or chunks nor sends approvals
+/// for the rest of the epoch, reports it in the health check, and resumes in the next epoch.
+#[test]
+fn test_no_production_while_slashed() {
+    let chain_genesis = ChainGenesis::test();
+    let validators: Vec<AccountId> = vec!["test0".parse().unwrap(), "test1".parse().unwrap()];
+    let stores = vec![create_test_store(), create_test_store()];
+    let epoch_managers: Vec<_> = stores
+        .iter()
+        .map(|store| {
+            let vs = ValidatorSchedule::new().block_producers_per_epoch(vec![validators.clone()]);
+            MockEpochManager::new_with_validators(store.clone(), vs, chain_genesis.epoch_length)
+        })
+        .collect();
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients_count(2)
+        .validator_seats(2)
+        .stores(stores)
+        .mock_epoch_managers(epoch_managers.clone())
+        .build();
+    let signer = create_test_signer("test0");
+    let send_approval = |env: &mut TestEnv| {
+        let head = env.clients[0].chain.head().unwrap();
+        let approval = Approval::new(head.last_block_hash, head.height, head.height + 1, &signer);
+        while env.network_adapters[0].pop().is_some() {}
+        env.clients[0].send_approval(&head.last_block_hash, approval).unwrap();
+        std::iter::from_fn(|| env.network_adapters[0].pop())
+            .filter(|request| {
+                matches!(
+                    request,
+                    PeerManagerMessageRequest::NetworkRequests(NetworkRequests::Approval { .. })
+                )
+            })
+            .count()
+    };
+    let produce_and_process = |env: &mut TestEnv, height| {
+        let producer = (0..2)
+            .find_map(|i| env.clients[i].produce_block(height).unwrap().map(|block| (i, block)));
+        if let Some((i, block)) = &producer {
+            env.process_block(*i, block.clone(), Provenance::PRODUCED);
+            env.process_block(1 - *i, block.clone(), Provenance::NONE);
+        }
+        producer.map(|(i, _)| i)
+    };
+
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let slashed_epoch_id =
+        env.clients[0].epoch_manager.get_epoch_id_from_prev_block(&genesis_hash).unwrap();
+    epoch_managers[0].set_slashed(slashed_epoch_id.clone(), "test0".parse().unwrap());
+    produce_and_process(&mut env, 1).unwrap();
+
+    let mut height = 1;
+    let mut skipped_heights = 0;
+    loop {
+        let head = env.clients[0].chain.head().unwrap();
+        if head.epoch_id != slashed_epoch_id {
+            break;
+        }
+        assert!(env.clients[0].slashed_in_epoch().unwrap().contains("test0"));
+        assert_eq!(send_approval(&mut env), 0);
+        let block = env.clients[0].chain.get_block(&head.last_block_hash).unwrap();
+        let chunk = env.clients[0]
+            .produce_chunk(
+                head.last_block_hash,
+                &head.epoch_id,
+                block.chunks()[0].clone(),
+                head.height + 1,
+                0,
+            )
+            .unwrap();
+        assert!(chunk.is_none());
+
+        height += 1;
+        assert!(height < 20, "no epoch-boundary block was produced");
+        match produce_and_process(&mut env, height) {
+            Some(producer) => {
+                let head = env.clients[0].chain.head().unwrap();
+                assert!(producer == 1 || head.epoch_id != slashed_epoch_id);
+            }
+            None => skipped_heights += 1,
+        }
+    }
+    // test0 is the block producer at some heights of the epoch.
+    assert!(skipped_heights > 0);
+
+    // test0 isn't slashed in the next epoch.
+    assert!(env.clients[0].slashed_in_epoch().is_none());
+    assert_eq!(send_approval(&mut env), 1);
+    let producers: Vec<_> = (height + 1..height + 5)
+        .filter_map(|height| produce_and_process(&mut env, height))
+        .collect();
+    assert!(producers.contains(&0));
+}
+
+/// Rejects the transactions sent to a given receiver.
+struct RejectReceiverPolicy(AccountId);
+
+impl TxAdmissionPolicy for RejectReceiverPolicy {
+    fn check(&self, tx: &SignedTransaction, _context: &TxAdmissionContext) -> Result<(), String> {
+        if tx.transaction.receiver_id == self.0 {
+            return Err(format!("{} doesn't accept transactions", self.0));
+        }
+        Ok(())
+    }
+}
+
+/// Transactions rejected by the admission policy are neither added to the pool nor included in
+/// produced chunks, the others are unaffected.
+#[test]
+fn test_tx_admission_policy() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    let client = &mut env.clients[0];
+    client.tx_admission_policy = Arc::new(RejectReceiverPolicy("test1".parse().unwrap()));
+    let genesis_hash = *client.chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let send_money = |nonce, receiver: &str| {
+        SignedTransaction::send_money(
+            nonce,
+            "test0".parse().unwrap(),
+            receiver.parse().unwrap(),
+            &signer,
+            100,
+            genesis_hash,
+        )
+    };
+    let rejected_in_pool =
+        metrics::TRANSACTION_REJECTED_BY_POLICY.with_label_values(&["pool"]).get();
+    let rejected_in_chunk =
+        metrics::TRANSACTION_REJECTED_BY_POLICY.with_label_values(&["chunk"]).get();
+
+    let rejected_tx = send_money(1, "test1");
+    assert_matches!(
+        client.process_tx(rejected_tx.clone(), false, false),
+        ProcessTxResponse::RejectedByPolicy(_)
+    );
+    let accepted_tx = send_money(2, "test0");
+    assert_eq!(client.process_tx(accepted_tx.clone(), false, false), ProcessTxResponse::ValidTx);
+    // A transaction already in the pool, e.g. added before the policy changed, isn't included.
+    let head = client.chain.head().unwrap();
+    let shard_uid = client.epoch_manager.shard_id_to_uid(0, &head.epoch_id).unwrap();
+    let pooled_rejected_tx = send_money(3, "test1");
+    client.sharded_tx_pool.insert_transaction(shard_uid, pooled_rejected_tx);
+
+    let (chunk, _, _) = create_chunk_on_height(client, head.height + 1);
+    let chunk = chunk.decode_chunk(client.epoch_manager.num_data_parts()).unwrap();
+    assert_eq!(chunk.transactions(), &[accepted_tx]);
+    assert_eq!(
+        metrics::TRANSACTION_REJECTED_BY_POLICY.with_label_values(&["pool"]).get(),
+        rejected_in_pool + 1
+    );
+    assert_eq!(
+        metrics::TRANSACTION_REJECTED_BY_POLICY.with_label_values(&["chunk"]).get(),
+        rejected_in_chunk + 1
+    );
+}
+
+/// The transactions of the previous chunk aren't included again, even while they are still in the
+/// pool because the block including the chunk isn't accepted yet.
+#[test]
+fn test_produce_chunk_excludes_prev_chunk_transactions() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let send_money = |nonce| {
+        SignedTransaction::send_money(
+            nonce,
+            "test0".parse().unwrap(),
+            "test1".parse().unwrap(),
+            &signer,
+            100,
+            genesis_hash,
+        )
+    };
+    let txs: Vec<_> = (1..=3).map(send_money).collect();
+    for tx in &txs {
+        assert_eq!(env.clients[0].process_tx(tx.clone(), false, false), ProcessTxResponse::ValidTx);
+    }
+    env.produce_block(0, 1);
+    env.produce_block(0, 2);
+
+    let client = &mut env.clients[0];
+    let head = client.chain.head().unwrap();
+    let block = client.chain.get_block(&head.last_block_hash).unwrap();
+    let prev_chunk = client.chain.get_chunk(&block.chunks()[0].chunk_hash()).unwrap();
+    assert_eq!(prev_chunk.transactions(), txs.as_slice());
+    // The transactions are back in the pool, as if the block wasn't accepted yet.
+    let shard_uid = client.epoch_manager.shard_id_to_uid(0, &head.epoch_id).unwrap();
+    client.sharded_tx_pool.reintroduce_transactions(shard_uid, &txs);
+    let new_tx = send_money(4);
+    assert_eq!(client.process_tx(new_tx.clone(), false, false), ProcessTxResponse::ValidTx);
+
+    let (chunk, _, _) = create_chunk_on_height(client, head.height + 1);
+    let chunk = chunk.decode_chunk(client.epoch_manager.num_data_parts()).unwrap();
+    assert_eq!(chunk.transactions(), &[new_tx]);
+}
+
+/// Directly submitted transactions can't fill the part of the pool reserved for forwarded ones. A
+/// transaction rejected because of the reservation is not admitted when submitted again, even if
+/// forwarded.
+#[test]
+fn test_forwarded_tx_pool_reservation() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    let client = &mut env.clients[0];
+    let genesis_hash = *client.chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let txs: Vec<_> = (1..=14)
+        .map(|nonce| {
+            SignedTransaction::send_money(
+                nonce,
+                "test0".parse().unwrap(),
+                "test0".parse().unwrap(),
+                &signer,
+                100,
+                genesis_hash,
+            )
+        })
+        .collect();
+    // All the transactions have the same size, 3 of them fit into the reserved part of the pool.
+    let tx_size = txs[0].get_size();
+    client.sharded_tx_pool = ShardedTransactionPool::new([0; 32], Some(10 * tx_size));
+    client.config.forwarded_tx_pool_reservation_percent = 30;
+    client.tx_lanes = TxLanes::new(&client.config);
+    let head = client.chain.head().unwrap();
+    let shard_uid = client.epoch_manager.shard_id_to_uid(0, &head.epoch_id).unwrap();
+    let pool_txs = |client: &mut crate::Client| {
+        let mut pool_txs = vec![];
+        let mut iter = client.sharded_tx_pool.get_pool_iterator(shard_uid).unwrap();
+        while let Some(group) = iter.next() {
+            while let Some(tx) = group.next() {
+                pool_txs.push(tx.get_hash());
+            }
+        }
+        drop(iter);
+        pool_txs.sort();
+        pool_txs
+    };
+    let hashes = |txs: &[SignedTransaction]| {
+        let mut hashes: Vec<_> = txs.iter().map(|tx| tx.get_hash()).collect();
+        hashes.sort();
+        hashes
+    };
+
+    for tx in &txs[..10] {
+        assert_eq!(client.process_tx(tx.clone(), false, false), ProcessTxResponse::ValidTx);
+    }
+    assert_eq!(pool_txs(client), hashes(&txs[..7]));
+
+    assert_eq!(client.process_tx(txs[7].clone(), true, false), ProcessTxResponse::ValidTx);
+    assert_eq!(pool_txs(client), hashes(&txs[..7]));
+
+    for tx in &txs[10..] {
+        assert_eq!(client.process_tx(tx.clone(), true, false), ProcessTxResponse::ValidTx);
+    }
+    let admitted: Vec<_> = txs[..7].iter().chain(&txs[10..13]).cloned().collect();
+    assert_eq!(pool_txs(client), hashes(&admitted));
+}
+
+/// After a deep reorg only as many transactions of the abandoned blocks as fit into the budget
+/// are added back to the pool, those of the most recent blocks first. Expired transactions don't
+/// take any of the budget, and the transactions submitted since stay in the pool.
+#[test]
+fn test_reorg_tx_reintroduction_budget() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let send_money = |nonce, block_hash| {
+        SignedTransaction::send_money(
+            nonce,
+            "test0".parse().unwrap(),
+            "test1".parse().unwrap(),
+            &signer,
+            1,
+            block_hash,
+        )
+    };
+    // The transactions submitted before producing a block are included in the next one, so the
+    // recent transactions anchored at height `i` are included at height `i + 2`.
+    let mut recent_txs = vec![];
+    let mut block_hashes = vec![];
+    for height in 1..=51 {
+        if height <= 50 {
+            let client = &mut env.clients[0];
+            let last_block_hash = client.chain.head().unwrap().last_block_hash;
+            let expiring_tx = send_money(2 * height - 1, genesis_hash);
+            let recent_tx = send_money(2 * height, last_block_hash);
+            for tx in [expiring_tx, recent_tx.clone()] {
+                assert_eq!(client.process_tx(tx, false, false), ProcessTxResponse::ValidTx);
+            }
+            recent_txs.push(recent_tx);
+        }
+        env.produce_block(0, height);
+        block_hashes.push(env.clients[0].chain.head().unwrap().last_block_hash);
+    }
+
+    // Treat the blocks as abandoned by a reorg to their head, with the transactions anchored at
+    // genesis expired by now and a new transaction submitted since.
+    let client = &mut env.clients[0];
+    client.sharded_tx_pool = ShardedTransactionPool::new([0; 32], None);
+    client.chain.transaction_validity_period = 40;
+    client.config.reorg_tx_reintroduction_limit = Some(20);
+    let head_header = client.chain.head_header().unwrap();
+    let new_tx = send_money(101, *head_header.hash());
+    assert_eq!(client.process_tx(new_tx.clone(), false, false), ProcessTxResponse::ValidTx);
+    let skipped =
+        |reason| metrics::TRANSACTION_REINTRODUCTION_SKIPPED.with_label_values(&[reason]).get();
+    let skipped_before = [skipped("invalid"), skipped("over_budget")];
+    block_hashes.reverse();
+    client.reintroduce_transactions_for_blocks(
+        "test0".parse().unwrap(),
+        &block_hashes,
+        &head_header,
+    );
+
+    let skipped_after = [skipped("invalid"), skipped("over_budget")];
+    // All the transactions anchored at genesis and the recent ones anchored below height 11 have
+    // expired, the budget runs out at the recent transaction anchored at height 29.
+    assert_eq!(skipped_after[0] - skipped_before[0], 50 + 11);
+    assert_eq!(skipped_after[1] - skipped_before[1], 19);
+    let shard_uid = client.epoch_manager.shard_id_to_uid(0, head_header.epoch_id()).unwrap();
+    let mut pool_txs = vec![];
+    let mut iter = client.sharded_tx_pool.get_pool_iterator(shard_uid).unwrap();
+    while let Some(group) = iter.next() {
+        while let Some(tx) = group.next() {
+            pool_txs.push(tx.get_hash());
+        }
+    }
+    drop(iter);
+    pool_txs.sort();
+    let mut expected: Vec<_> =
+        recent_txs[30..].iter().chain([&new_tx]).map(|tx| tx.get_hash()).collect();
+    expected.sort();
+    assert_eq!(pool_txs, expected);
+}
+
+/// Transactions above the rate budget of their lane are dropped, without affecting the other lane.
+/// Checked and invalid transactions don't take any of the budget.
+#[test]
+fn test_tx_lane_rate_budget() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    let client = &mut env.clients[0];
+    client.config.local_tx_rate_limit = Some(1);
+    client.tx_lanes = TxLanes::new(&client.config);
+    let genesis_hash = *client.chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let send_money = |nonce| {
+        SignedTransaction::send_money(
+            nonce,
+            "test0".parse().unwrap(),
+            "test0".parse().unwrap(),
+            &signer,
+            100,
+            genesis_hash,
+        )
+    };
+    let rate_limited =
+        metrics::TRANSACTION_LANE_ADMISSION.with_label_values(&["local", "rate_limited"]).get();
+
+    assert_eq!(client.process_tx(send_money(1), false, true), ProcessTxResponse::ValidTx);
+    let mut invalid_tx = send_money(1);
+    invalid_tx.transaction.block_hash = hash(b"unknown block");
+    assert_matches!(
+        client.process_tx(invalid_tx, false, false),
+        ProcessTxResponse::InvalidTx(InvalidTxError::Expired)
+    );
+    assert_eq!(client.process_tx(send_money(1), false, false), ProcessTxResponse::ValidTx);
+    assert_eq!(client.process_tx(send_money(2), false, false), ProcessTxResponse::Throttled);
+    assert_eq!(client.process_tx(send_money(3), true, false), ProcessTxResponse::ValidTx);
+    assert_eq!(
+        metrics::TRANSACTION_LANE_ADMISSION.with_label_values(&["local", "rate_limited"]).get(),
+        rate_limited + 1
+    );
+}
+
+/// A client set up over a store seeded with a chain adopts the chain and continues it.
+#[test]
+fn test_client_over_seeded_chain() {
+    let store = create_test_store();
+    let vs =
+        ValidatorSchedule::new().block_producers_per_epoch(vec![vec!["test0".parse().unwrap()]]);
+    seed_chain(store.clone(), ChainGenesis::test(), 50, vs.clone());
+
+    let mut client = setup_client_with_synchronous_shards_manager(
+        store,
+        vs,
+        Some("test0".parse().unwrap()),
+        false,
+        Arc::new(MockPeerManagerAdapter::default()).into(),
+        Sender::noop(),
+        ChainGenesis::test(),
+        TEST_SEED,
+        false,
+        true,
+    );
+    assert_eq!(client.chain.head().unwrap().height, 50);
+    for height in 51..=55 {
+        let block = client.produce_block(height).unwrap().unwrap();
+        client.process_block_test(block.into(), Provenance::PRODUCED).unwrap();
+        assert_eq!(client.chain.head().unwrap().height, height);
+    }
+}
+
+/// Processes three blocks on top of genesis at heights 2, 4 and 5 with a runtime which applies
+/// the chunks of one block at a time, when the test lets it. Only the block at height 5 precedes a block produced
+/// by this node, and with prioritization it's applied first, in time for the production.
+fn run_blocks_with_slow_runtime(prioritize_block_before_production: bool) -> (Vec<Block>, Tip) {
+    let accounts: Vec<AccountId> = vec!["test0".parse().unwrap(), "test1".parse().unwrap()];
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = 100;
+    let stores: Vec<_> = accounts.iter().map(|_| create_test_store()).collect();
+    let epoch_managers: Vec<_> = stores
+        .iter()
+        .map(|store| {
+            MockEpochManager::new_with_validators(
+                store.clone(),
+                ValidatorSchedule::new().block_producers_per_epoch(vec![accounts.clone()]),
+                chain_genesis.epoch_length,
+            )
+        })
+        .collect();
+    let runtimes: Vec<_> = stores
+        .iter()
+        .zip(epoch_managers.iter())
+        .map(|(store, epoch_manager)| KeyValueRuntime::new(store.clone(), epoch_manager))
+        .collect();
+    let apply_permits = runtimes[0].gate_apply_transactions();
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients(accounts.clone())
+        .validators(accounts)
+        .stores(stores)
+        .mock_epoch_managers(epoch_managers)
+        .runtimes(runtimes.into_iter().map(|runtime| runtime as Arc<dyn RuntimeAdapter>).collect())
+        .build();
+    env.clients[0].config.prioritize_block_before_production = prioritize_block_before_production;
+    // The producers alternate between test0 at even heights and test1 at odd heights.
+    let blocks = vec![
+        env.clients[0].produce_block(2).unwrap().unwrap(),
+        env.clients[0].produce_block(4).unwrap().unwrap(),
+        env.clients[1].produce_block(5).unwrap().unwrap(),
+    ];
+
+    let client = &mut env.clients[0];
+    let (applied_sender, applied_receiver) = mpsc::channel();
+    let apply_chunks_done_callback: DoneApplyChunkCallback = Arc::new(move |block_hash| {
+        let _ = applied_sender.send(block_hash);
+    });
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+    // The chunks are applied on the single thread of the pool once all blocks are started.
+    pool.install(|| {
+        for block in &blocks {
+            client
+                .start_process_block(
+                    block.clone().into(),
+                    Provenance::NONE,
+                    apply_chunks_done_callback.clone(),
+                )
+                .unwrap();
+        }
+    });
+    // The chunks of two blocks are applied by the time our slot comes, but not of three.
+    for _ in 0..2 {
+        apply_permits.send(()).unwrap();
+        applied_receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    }
+    client.postprocess_ready_blocks(apply_chunks_done_callback, false);
+    let head = client.chain.head().unwrap();
+    // Lets the last block be applied before the pool is dropped.
+    drop(apply_permits);
+    (blocks, head)
+}
+
+/// The block after which this node produces the next block is applied before the blocks
+/// received earlier, so that the production slot isn't missed.
+#[test]
+fn test_prioritize_block_before_production() {
+    let (blocks, head) = run_blocks_with_slow_runtime(true);
+    assert_eq!(head.last_block_hash, *blocks[2].hash());
+
+    let (blocks, head) = run_blocks_with_slow_runtime(false);
+    // The block at height 5 is still being applied when our slot at height 6 comes.
+    assert_eq!(head.last_block_hash, *blocks[1].hash());
+}
+
+/// Forwards the requests to the ShardsManager, recording the chain heads updates.
+struct RecordingShardsManagerAdapter {
+    inner: Sender<ShardsManagerRequestFromClient>,
+    chain_heads_updates: Arc<Mutex<Vec<(Tip, Tip)>>>,
+}
+
+impl CanSend<ShardsManagerRequestFromClient> for RecordingShardsManagerAdapter {
+    fn send(&self, message: ShardsManagerRequestFromClient) {
+        if let ShardsManagerRequestFromClient::UpdateChainHeads { head, header_head } = &message {
+            self.chain_heads_updates.lock().unwrap().push((head.clone(), header_head.clone()));
+        }
+        self.inner.send(message);
+    }
+}
+
+/// While syncing 500 blocks, the chain heads updates sent to the ShardsManager are batched, and
+/// the last one sent carries the final heads.
+#[test]
+fn test_chain_heads_updates_batched_during_sync() {
+    let mut env =
+        TestEnv::builder(ChainGenesis::test()).clients_count(2).validator_seats(1).build();
+    // The blocks have no chunks, so that the second client can process them on its own.
+    let mut blocks = vec![];
+    for height in 1..=500 {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        env.clients[0]
+            .process_block_test_no_produce_chunk(block.clone().into(), Provenance::PRODUCED)
+            .unwrap();
+        blocks.push(block);
+    }
+
+    let chain_heads_updates = Arc::new(Mutex::new(vec![]));
+    let client = &mut env.clients[1];
+    client.shards_manager_adapter = RecordingShardsManagerAdapter {
+        inner: client.shards_manager_adapter.clone(),
+        chain_heads_updates: chain_heads_updates.clone(),
+    }
+    .into_sender();
+    client.chain_heads_throttle = ChainHeadsThrottle::with_limits(
+        client.chain.head().unwrap(),
+        client.chain.header_head().unwrap(),
+        100,
+        Duration::from_secs(3600),
+    );
+    let suppressed = metrics::UPDATE_CHAIN_HEADS_SUPPRESSED_TOTAL.get();
+
+    client.sync_status =
+        SyncStatus::BodySync { start_height: 0, current_height: 0, highest_height: 500 };
+    for headers in blocks.chunks(100) {
+        client
+            .sync_block_headers(headers.iter().map(|block| block.header().clone()).collect())
+            .unwrap();
+    }
+    for block in blocks {
+        env.process_block(1, block, Provenance::NONE);
+    }
+    let client = &mut env.clients[1];
+    client.sync_status = SyncStatus::NoSync;
+    client.flush_chain_heads_update();
+
+    // 5 header batches and 500 blocks change the heads, which are sent once every 100 changes,
+    // plus the flush.
+    let chain_heads_updates = chain_heads_updates.lock().unwrap();
+    assert_eq!(chain_heads_updates.len(), 6);
+    assert!(metrics::UPDATE_CHAIN_HEADS_SUPPRESSED_TOTAL.get() >= suppressed + 499);
+    let (head, header_head) = chain_heads_updates.last().unwrap();
+    assert_eq!(head, &client.chain.head().unwrap());
+    assert_eq!(header_head, &client.chain.header_head().unwrap());
+    assert_eq!(head.height, 500);
+}
+
+/// The size of the state recorded while applying the previous chunk is tracked for every produced
+/// chunk, and chunks whose state witness exceeds the soft limit are reported.
+#[cfg(feature = "protocol_feature_chunk_validation")]
+#[test]
+fn test_state_witness_size_tracking() {
+    use crate::adapter::ProcessTxResponse;
+    use near_crypto::InMemorySigner;
+    use near_primitives::transaction::SignedTransaction;
+
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let mut nonce = 0;
+    let mut sizes = vec![];
+    for height in 1..=6 {
+        // Every transaction adds its nonce to the state, so the state witness keeps growing.
+        let last_block_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+        for _ in 0..10 {
+            nonce += 1;
+            let tx = SignedTransaction::send_money(
+                nonce,
+                "test0".parse().unwrap(),
+                "test1".parse().unwrap(),
+                &signer,
+                1,
+                last_block_hash,
+            );
+            assert_eq!(env.clients[0].process_tx(tx, false, false), ProcessTxResponse::ValidTx);
+        }
+        env.produce_block(0, height);
+        if height < 2 {
+            continue;
+        }
+        // The chunk for the next height was produced on top of the new block, which applied the
+        // previous chunk.
+        let block_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+        let size = env.clients[0].state_witness_size(height + 1, 0).unwrap();
+        assert_eq!(
+            Some(size as usize),
+            env.clients[0].runtime_adapter.get_recorded_storage_size(&block_hash, 0)
+        );
+        sizes.push(size);
+    }
+    assert!(sizes.windows(2).all(|w| w[0] < w[1]), "{sizes:?}");
+
+    let over_soft_limit =
+        metrics::CHUNK_STATE_WITNESS_SIZE_OVER_SOFT_LIMIT.with_label_values(&["0"]).get();
+    env.clients[0].config.state_witness_size_soft_limit = Some(*sizes.last().unwrap());
+    env.produce_block(0, 7);
+    assert!(env.clients[0].state_witness_size(8, 0).unwrap() > *sizes.last().unwrap());
+    assert_eq!(
+        metrics::CHUNK_STATE_WITNESS_SIZE_OVER_SOFT_LIMIT.with_label_values(&["0"]).get(),
+        over_soft_limit + 1
+    );
+}
+
+/// Only the chunk producer of the next chunk records the storage for its state witness.
+#[cfg(feature = "protocol_feature_chunk_validation")]
+#[test]
+fn test_storage_recorded_by_next_chunk_producer_only() {
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .clients(vec!["test0".parse().unwrap(), "test1".parse().unwrap()])
+        .validators(vec!["test0".parse().unwrap()])
+        .track_all_shards()
+        .build();
+    for height in 1..=3 {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        env.process_block(1, block, Provenance::NONE);
+    }
+    let block_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+    assert!(env.clients[0].runtime_adapter.get_recorded_storage_size(&block_hash, 0).is_some());
+    assert_eq!(env.clients[1].runtime_adapter.get_recorded_storage_size(&block_hash, 0), None);
+}
+
+/// The mock epoch manager splits the chunk validator mandates evenly between its validators, and
+/// samples the same validators for a height every time.
+#[cfg(feature = "protocol_feature_chunk_validation")]
+#[test]
+fn test_sample_chunk_validators() {
+    let chain_genesis = ChainGenesis::test();
+    let validators: Vec<AccountId> = (0..4).map(|i| format!("test{i}").parse().unwrap()).collect();
+    let store = create_test_store();
+    let vs = ValidatorSchedule::new()
+        .num_shards(2)
+        .block_producers_per_epoch(vec![validators.clone()])
+        .validator_mandates_per_shard(3);
+    let epoch_manager =
+        MockEpochManager::new_with_validators(store.clone(), vs, chain_genesis.epoch_length);
+    let env = TestEnv::builder(chain_genesis)
+        .stores(vec![store])
+        .mock_epoch_managers(vec![epoch_manager])
+        .build();
+    let client = &env.clients[0];
+    let epoch_id = client.chain.head().unwrap().epoch_id;
+
+    for height in 1..10 {
+        let assignments = client.epoch_manager.sample_chunk_validators(&epoch_id, height).unwrap();
+        assert_eq!(assignments.len(), 2);
+        // Every validator holds two mandates, so each shard gets four of the eight.
+        for shard_assignments in &assignments {
+            assert!(shard_assignments.keys().all(|account_id| validators.contains(account_id)));
+            assert_eq!(shard_assignments.values().map(|&m| m as usize).sum::<usize>(), 4);
+        }
+        assert_eq!(
+            assignments,
+            client.epoch_manager.sample_chunk_validators(&epoch_id, height).unwrap()
+        );
+    }
+
+    let env = TestEnv::builder(ChainGenesis::test()).build();
+    let epoch_id = env.clients[0].chain.head().unwrap().epoch_id;
+    assert!(env.clients[0].epoch_manager.sample_chunk_validators(&epoch_id, 1).unwrap().is_empty());
+}
+
+/// Every produced block is counted. The snapshot only has the metrics of the client, not e.g.
+/// the ones of the chain.
+#[test]
+fn test_block_produced_metric() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    let before = metrics::snapshot();
+    env.produce_block(0, 1);
+    let after = metrics::snapshot();
+    assert_metric_delta("near_block_produced_total", &before, &after, 1.0);
+    assert!(!after.contains_key("near_block_processed_total"));
+}
+
+/// A chunk of a chunk producer banned for the epoch is not included, and the dropped chunk is
+/// counted.
+#[test]
+fn test_chunk_of_banned_producer_dropped_metric() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    env.produce_block(0, 1);
+    let head = env.clients[0].chain.head().unwrap();
+    let (chunk, _, _) = create_chunk_on_height(&mut env.clients[0], 2);
+    let chunk_producer: AccountId = "test0".parse().unwrap();
+    let client = &mut env.clients[0];
+    client.on_chunk_header_ready_for_inclusion(chunk.cloned_header(), chunk_producer.clone());
+    let epoch_height =
+        client.epoch_manager.get_epoch_height_from_prev_block(&head.last_block_hash).unwrap();
+    client.do_not_include_chunks_from.ban(epoch_height, head.epoch_id.clone(), chunk_producer);
+
+    let before = metrics::snapshot();
+    let chunks =
+        client.get_chunk_headers_ready_for_inclusion(&head.epoch_id, &head.last_block_hash);
+    assert!(chunks.is_empty());
+    assert_metric_delta(
+        "near_chunk_dropped_because_of_banned_chunk_producer",
+        &before,
+        &metrics::snapshot(),
+        1.0,
+    );
+}
+
+/// Deletes `key` from `col` and reloads the chain store, so that the value isn't served from its
+/// caches either.
+fn delete_from_chain_store(env: &mut TestEnv, col: DBCol, key: &[u8]) {
+    update_chain_store(env, |store_update| store_update.delete(col, key));
+}
+
+/// Writes to the store of the first client directly, bypassing the caches of its chain store.
+fn update_chain_store(env: &mut TestEnv, update: impl FnOnce(&mut StoreUpdate)) {
+    let store = env.clients[0].chain.store().store().clone();
+    let mut store_update = store.store_update();
+    update(&mut store_update);
+    store_update.commit().unwrap();
+    let genesis_height = env.clients[0].chain.genesis().height();
+    *env.clients[0].chain.mut_store() = ChainStore::new(store, genesis_height, true);
+}
+
+/// Produces blocks until the head is the last block of an epoch, and returns the header of the
+/// last chunk included before the head, which the next chunk is produced on top of.
+fn produce_blocks_until_epoch_end(env: &mut TestEnv) -> (Tip, ShardChunkHeader) {
+    for height in 1.. {
+        env.produce_block(0, height);
+        let head = env.clients[0].chain.head().unwrap();
+        if env.clients[0].epoch_manager.is_next_block_epoch_start(&head.last_block_hash).unwrap() {
+            let block = env.clients[0].chain.get_block(&head.last_block_hash).unwrap();
+            let epoch_manager = env.clients[0].epoch_manager.as_ref();
+            let last_header = Chain::get_prev_chunk_header(epoch_manager, &block, 0).unwrap();
+            return (head, last_header);
+        }
+        assert!(height < 20, "no epoch boundary was reached");
+    }
+    unreachable!()
+}
+
+/// A node which started tracking a shard after the last chunk of the shard was applied doesn't
+/// have the outgoing receipts of that chunk. Chunk production is skipped with a reason saying so,
+/// instead of producing a chunk without the receipts.
+#[test]
+fn test_produce_chunk_without_outgoing_receipts_of_untracked_chunk() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    for height in 1..=3 {
+        env.produce_block(0, height);
+    }
+    let head = env.clients[0].chain.head().unwrap();
+    let block = env.clients[0].chain.get_block(&head.last_block_hash).unwrap();
+    let last_header =
+        Chain::get_prev_chunk_header(env.clients[0].epoch_manager.as_ref(), &block, 0).unwrap();
+    assert_eq!(last_header.height_included(), 3);
+
+    // Forget the receipts as if the chunk wasn't applied by this node.
+    let key = get_block_shard_id(&head.last_block_hash, 0);
+    delete_from_chain_store(&mut env, DBCol::OutgoingReceipts, &key);
+
+    let err = env.clients[0]
+        .produce_chunk(head.last_block_hash, &head.epoch_id, last_header, 4, 0)
+        .unwrap_err();
+    assert_eq!(err.severity(), ErrorSeverity::Retry);
+    assert_matches!(
+        err,
+        crate::Error::ChunkProducer {
+            shard_id: 0,
+            height: 4,
+            kind: ChunkProducerErrorKind::MissingOutgoingReceipts {
+                last_included_height: 3,
+                resume_height: None
+            },
+        }
+    );
+}
+
+/// Chunk production fails with a structured error when the chunk extra of the previous block is
+/// missing, when the state of the next epoch isn't caught up yet and when the node has no signer.
+#[test]
+fn test_produce_chunk_structured_errors() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    let (head, last_header) = produce_blocks_until_epoch_end(&mut env);
+    let epoch_manager = env.clients[0].epoch_manager.clone();
+    let next_epoch_id = epoch_manager.get_epoch_id_from_prev_block(&head.last_block_hash).unwrap();
+    let next_height = head.height + 1;
+    let produce_chunk = |env: &mut TestEnv| {
+        env.clients[0]
+            .produce_chunk(
+                head.last_block_hash,
+                &next_epoch_id,
+                last_header.clone(),
+                next_height,
+                0,
+            )
+            .unwrap_err()
+    };
+
+    let mut chain_store_update = env.clients[0].chain.mut_store().store_update();
+    chain_store_update.add_block_to_catchup(head.prev_block_hash, head.last_block_hash);
+    chain_store_update.commit().unwrap();
+    let err = produce_chunk(&mut env);
+    assert_eq!(err.severity(), ErrorSeverity::Retry);
+    assert_matches!(
+        err,
+        crate::Error::ChunkProducer {
+            shard_id: 0,
+            height,
+            kind: ChunkProducerErrorKind::PrevBlockNotCaughtUp,
+        } if height == next_height
+    );
+
+    let mut chain_store_update = env.clients[0].chain.mut_store().store_update();
+    chain_store_update.remove_block_to_catchup(head.prev_block_hash, head.last_block_hash);
+    chain_store_update.commit().unwrap();
+    let shard_uid = epoch_manager.shard_id_to_uid(0, &next_epoch_id).unwrap();
+    let key = get_block_shard_uid(&head.last_block_hash, &shard_uid);
+    delete_from_chain_store(&mut env, DBCol::ChunkExtra, &key);
+    let err = produce_chunk(&mut env);
+    assert_eq!(err.severity(), ErrorSeverity::Retry);
+    assert_matches!(
+        err,
+        crate::Error::ChunkProducer {
+            shard_id: 0,
+            kind: ChunkProducerErrorKind::MissingChunkExtra(near_chain::Error::DBNotFoundErr(_)),
+            ..
+        }
+    );
+
+    env.clients[0].validator_signer = None;
+    let err = produce_chunk(&mut env);
+    assert_eq!(err.severity(), ErrorSeverity::Alert);
+    assert_matches!(
+        err,
+        crate::Error::ChunkProducer {
+            shard_id: 0,
+            kind: ChunkProducerErrorKind::NoValidatorSigner,
+            ..
+        }
+    );
+}
+
+/// A production alert is only cleared by the production of a block, or of a chunk of the shard
+/// it was raised for.
+#[test]
+fn test_production_alert_cleared_per_shard() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    env.produce_block(0, 1);
+    let shard_err = crate::Error::chunk_producer(1, 2, ChunkProducerErrorKind::NoValidatorSigner);
+    env.clients[0].record_production_error(2, Some(1), &shard_err);
+    assert_eq!(env.clients[0].production_alert(), Some(shard_err.to_string().as_str()));
+
+    // Only the block and the chunk of shard 0 are produced.
+    env.produce_block(0, 2);
+    assert_eq!(env.clients[0].production_alert(), Some(shard_err.to_string().as_str()));
+
+    let kind = ChunkProducerErrorKind::InconsistentChunkExtra {
+        prev_block_hash: CryptoHash::default(),
+        field: "state root",
+        found: CryptoHash::default(),
+        expected: hash(b"state root"),
+    };
+    env.clients[0].record_production_error(3, Some(0), &crate::Error::chunk_producer(0, 3, kind));
+    assert_ne!(env.clients[0].production_alert(), Some(shard_err.to_string().as_str()));
+    env.produce_block(0, 3);
+    assert_eq!(env.clients[0].production_alert(), Some(shard_err.to_string().as_str()));
+}
+
+/// With `verify_chunk_extra_before_production`, chunk production is refused when the chunk extra of
+/// the previous block doesn't match the trie changes recorded when the shard was applied, or when
+/// both come from applying a chunk other than the one included in the previous block.
+#[test]
+fn test_produce_chunk_with_inconsistent_chunk_extra() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    env.clients[0].config.verify_chunk_extra_before_production = true;
+    for height in 1..=3 {
+        env.produce_block(0, height);
+    }
+    let head = env.clients[0].chain.head().unwrap();
+    let block = env.clients[0].chain.get_block(&head.last_block_hash).unwrap();
+    let last_header =
+        Chain::get_prev_chunk_header(env.clients[0].epoch_manager.as_ref(), &block, 0).unwrap();
+    assert_eq!(last_header.height_included(), 3);
+    let shard_uid = env.clients[0].epoch_manager.shard_id_to_uid(0, &head.epoch_id).unwrap();
+    let key = get_block_shard_uid(&head.last_block_hash, &shard_uid);
+    let chunk_extra =
+        env.clients[0].chain.get_chunk_extra(&head.last_block_hash, &shard_uid).unwrap();
+    let trie_changes =
+        env.clients[0].chain.store().get_trie_changes(&head.last_block_hash, &shard_uid).unwrap();
+    assert_eq!(trie_changes.unwrap().new_root, *chunk_extra.state_root());
+    let produce_chunk = |env: &mut TestEnv| {
+        env.clients[0]
+            .produce_chunk(head.last_block_hash, &head.epoch_id, last_header.clone(), 4, 0)
+            .unwrap_err()
+    };
+
+    let stale_root = hash(b"stale state root");
+    let mut stale_chunk_extra = ChunkExtra::clone(&chunk_extra);
+    *stale_chunk_extra.state_root_mut() = stale_root;
+    update_chain_store(&mut env, |store_update| {
+        store_update.set_ser(DBCol::ChunkExtra, &key, &stale_chunk_extra).unwrap()
+    });
+    let err = produce_chunk(&mut env);
+    assert_eq!(err.severity(), ErrorSeverity::Alert);
+    assert_matches!(
+        &err,
+        crate::Error::ChunkProducer {
+            shard_id: 0,
+            height: 4,
+            kind: ChunkProducerErrorKind::InconsistentChunkExtra {
+                prev_block_hash,
+                field: "state root",
+                found,
+                expected,
+            },
+        } if prev_block_hash == &head.last_block_hash
+            && found == &stale_root
+            && expected == chunk_extra.state_root()
+    );
+    assert!(err.to_string().contains(&stale_root.to_string()), "{}", err);
+
+    // The chunk extra and the trie changes agree, but the trie changes don't start from the prev
+    // state root of the chunk included in the head, as if they were recorded on another fork.
+    let other_fork_root = hash(b"other fork");
+    let mut other_fork_changes = TrieChanges::empty(other_fork_root);
+    other_fork_changes.new_root = stale_root;
+    update_chain_store(&mut env, |store_update| {
+        store_update.set_ser(DBCol::TrieChanges, &key, &other_fork_changes).unwrap()
+    });
+    let err = produce_chunk(&mut env);
+    assert_matches!(
+        err,
+        crate::Error::ChunkProducer {
+            kind: ChunkProducerErrorKind::InconsistentChunkExtra { found, expected, .. },
+            ..
+        } if found == other_fork_root && expected == last_header.prev_state_root()
+    );
+}
+
+/// Pops the requests sent by the client `id` and returns the blocks it broadcast.
+fn broadcast_blocks(env: &TestEnv, id: usize) -> Vec<Block> {
+    let mut blocks = vec![];
+    while let Some(request) = env.network_adapters[id].pop() {
+        if let PeerManagerMessageRequest::NetworkRequests(NetworkRequests::Block { block }) =
+            request
+        {
+            blocks.push(block);
+        }
+    }
+    blocks
+}
+
+/// When the head progresses after a stall, the blocks before the new head are broadcast again,
+/// so that a node which was isolated during the stall catches up without requesting them.
+#[test]
+fn test_recovery_burst_after_stall() {
+    let mut env =
+        TestEnv::builder(ChainGenesis::test()).clients_count(2).validator_seats(1).build();
+    env.clients[0].config.recovery_burst_blocks = 2;
+    env.clients[0].sync_status = SyncStatus::NoSync;
+    // The blocks have no chunks, so that the second client can process them on its own.
+    let produce_block = |env: &mut TestEnv, height| {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        env.clients[0]
+            .process_block_test_no_produce_chunk(block.clone().into(), Provenance::PRODUCED)
+            .unwrap();
+        block
+    };
+    let block = produce_block(&mut env, 1);
+    env.process_block(1, block, Provenance::NONE);
+    // The second client is isolated while the next blocks are produced and the head stalls.
+    produce_block(&mut env, 2);
+    produce_block(&mut env, 3);
+    env.clients[0].check_head_progress_stalled(Duration::ZERO).unwrap();
+    let stall_broadcast = broadcast_blocks(&env, 0);
+    assert_eq!(
+        stall_broadcast.iter().map(|block| block.header().height()).collect::<Vec<_>>(),
+        [3]
+    );
+
+    let head_block = produce_block(&mut env, 4);
+    let burst = broadcast_blocks(&env, 0);
+    let heights: Vec<_> = burst.iter().map(|block| block.header().height()).collect();
+    assert_eq!(heights, [2, 3]);
+    // Once the network heals, the burst and the new head are enough to catch up.
+    for block in burst.into_iter().chain(std::iter::once(head_block)) {
+        env.process_block(1, block, Provenance::NONE);
+    }
+    assert_eq!(env.clients[1].chain.head().unwrap().height, 4);
+
+    // The burst is sent once per stall.
+    produce_block(&mut env, 5);
+    assert!(broadcast_blocks(&env, 0).is_empty());
+
+    // Nor is it sent again before `recovery_burst_interval` passes.
+    env.clients[0].check_head_progress_stalled(Duration::ZERO).unwrap();
+    assert_eq!(broadcast_blocks(&env, 0).len(), 1);
+    produce_block(&mut env, 6);
+    assert!(broadcast_blocks(&env, 0).is_empty());
+
+    env.clients[0].config.recovery_burst_interval = Duration::ZERO;
+    env.clients[0].check_head_progress_stalled(Duration::ZERO).unwrap();
+    assert_eq!(broadcast_blocks(&env, 0).len(), 1);
+    produce_block(&mut env, 7);
+    let heights: Vec<_> =
+        broadcast_blocks(&env, 0).iter().map(|block| block.header().height()).collect();
+    assert_eq!(heights, [5, 6]);
+}
+
+/// A node configured to sync until a height parks its head there and doesn't produce blocks,
+/// while the other nodes continue past it.
+#[test]
+fn test_sync_until_height() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).clients_count(2).build();
+    let sync_until_height = 5;
+    env.clients[1].config.sync_until_height = Some(sync_until_height);
+    let peer_id = PeerId::new(PublicKey::empty(KeyType::ED25519));
+    let mut headers = vec![];
+    for height in 1..=10 {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        headers.push(block.header().clone());
+        env.clients[1].receive_block_impl(block, peer_id.clone(), true, Arc::new(|_| {})).unwrap();
+        env.clients[1].finish_blocks_in_processing();
+        assert_eq!(
+            env.clients[1].synced_to_target().unwrap(),
+            height >= sync_until_height,
+            "height {height}"
+        );
+    }
+    assert_eq!(env.clients[0].chain.head().unwrap().height, 10);
+    assert_eq!(env.clients[1].chain.head().unwrap().height, sync_until_height);
+
+    // Headers above the target are not synced either.
+    env.clients[1].sync_block_headers(headers).unwrap();
+    assert_eq!(env.clients[1].chain.header_head().unwrap().height, sync_until_height);
+    assert!(env.clients[1].produce_block(sync_until_height + 1).unwrap().is_none());
+}
+
+/// Accepted blocks are recorded with whether they were produced, received unsolicited or
+/// requested, with the peer which sent them and with the time since their timestamp.
+#[test]
+fn test_block_provenance() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).clients_count(2).build();
+    let peer_id = PeerId::new(PublicKey::empty(KeyType::ED25519));
+    let before = metrics::snapshot();
+    for height in 1..=4 {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        let was_requested = height % 2 == 0;
+        env.clients[1]
+            .receive_block_impl(block, peer_id.clone(), was_requested, Arc::new(|_| {}))
+            .unwrap();
+        env.clients[1].finish_blocks_in_processing();
+    }
+
+    let produced = env.clients[0].block_provenance(10);
+    assert_eq!(produced.iter().map(|record| record.height).collect::<Vec<_>>(), [1, 2, 3, 4]);
+    for record in &produced {
+        assert_eq!(record.provenance, Provenance::PRODUCED);
+        assert_eq!(record.peer_id, None);
+    }
+
+    let received = env.clients[1].block_provenance(10);
+    assert_eq!(
+        received
+            .iter()
+            .map(|record| (record.height, record.provenance.clone()))
+            .collect::<Vec<_>>(),
+        [
+            (1, Provenance::NONE),
+            (2, Provenance::SYNC),
+            (3, Provenance::NONE),
+            (4, Provenance::SYNC)
+        ]
+    );
+    for record in &received {
+        assert_eq!(record.peer_id.as_ref(), Some(&peer_id));
+        assert!(record.latency < Duration::from_secs(10), "{:?}", record.latency);
+    }
+    let last_heights: Vec<_> =
+        env.clients[1].block_provenance(2).iter().map(|record| record.height).collect();
+    assert_eq!(last_heights, [3, 4]);
+
+    // The records are loaded back from the store after a restart.
+    let reloaded = BlockProvenanceTracker::new(env.clients[1].chain.store().store().clone());
+    assert_eq!(reloaded.last(10), received);
+
+    let after = metrics::snapshot();
+    for (provenance, delta) in [("produced", 4.0), ("broadcast", 2.0), ("requested", 2.0)] {
+        assert_metric_delta(
+            &format!("near_block_accepted_by_provenance_total{{provenance=\"{provenance}\"}}"),
+            &before,
+            &after,
+            delta,
+        );
+    }
+}
+
+/// Approvals waiting for a block which is garbage collected are purged, and approvals and chunk
+/// headers building on garbage collected blocks are dropped right away.
+#[test]
+fn test_drop_below_gc_tail() {
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = 5;
+    let mut env = TestEnv::builder(chain_genesis).build();
+    let signer = create_test_signer("test0");
+    let before = metrics::snapshot();
+
+    env.produce_block(0, 1);
+    let block = env.clients[0].produce_block(2).unwrap().unwrap();
+    let old_chunk_header = block.chunks()[0].clone();
+    env.process_block(0, block, Provenance::PRODUCED);
+    // There is never a block at height 3, so the skip building on it waits forever.
+    let parked_approval = Approval::new(CryptoHash::default(), 3, 5, &signer);
+    env.clients[0].collect_block_approval(&parked_approval, ApprovalType::SelfApproval);
+    assert!(env.clients[0].pending_approvals.contains(&ApprovalInner::Skip(3)));
+
+    for height in 4..=100 {
+        env.produce_block(0, height);
+    }
+    assert!(env.clients[0].chain.tail().unwrap() > 3);
+    assert!(!env.clients[0].pending_approvals.contains(&ApprovalInner::Skip(3)));
+
+    let stale_approval = Approval::new(CryptoHash::default(), 2, 101, &signer);
+    env.clients[0].collect_block_approval(&stale_approval, ApprovalType::SelfApproval);
+    assert!(!env.clients[0].pending_approvals.contains(&ApprovalInner::Skip(2)));
+    env.clients[0]
+        .on_chunk_header_ready_for_inclusion(old_chunk_header.clone(), "test0".parse().unwrap());
+    let head = env.clients[0].chain.head().unwrap();
+    assert!(env.clients[0]
+        .get_chunk_headers_ready_for_inclusion(&head.epoch_id, old_chunk_header.prev_block_hash())
+        .is_empty());
+
+    let after = metrics::snapshot();
+    for kind in ["pending_approval", "approval", "chunk_header"] {
+        assert_metric_delta(
+            &format!("near_dropped_below_gc_tail_total{{kind=\"{kind}\"}}"),
+            &before,
+            &after,
+            1.0,
+        );
+    }
+}
+
+/// When the last block before a change of the shard layout is accepted, the change and the
+/// resharding of the transaction pool are logged, with the transactions moved into each shard.
+#[test]
+fn test_resharding_log_on_shard_layout_change() {
+    let chain_genesis = ChainGenesis::test();
+    let store = create_test_store();
+    let vs = ValidatorSchedule::new_with_shards(4)
+        .block_producers_per_epoch(vec![vec!["test0".parse().unwrap()]]);
+    let epoch_manager =
+        MockEpochManager::new_with_validators(store.clone(), vs, chain_genesis.epoch_length);
+    let mut env = TestEnv::builder(chain_genesis)
+        .stores(vec![store])
+        .mock_epoch_managers(vec![epoch_manager.clone()])
+        .build();
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let next_epoch_id =
+        env.clients[0].epoch_manager.get_next_epoch_id_from_prev_block(&genesis_hash).unwrap();
+    epoch_manager.set_shard_layout_version(next_epoch_id.clone(), 1);
+
+    // The chunks aren't produced below, so that the transactions stay in the pool.
+    let old_shard_layout = ShardLayout::v0(4, 0);
+    let new_shard_layout = ShardLayout::v0(4, 1);
+    let mut expected_moved = BTreeMap::new();
+    for (nonce, signer_id) in ["test0", "test1", "test2", "test3", "test4"].iter().enumerate() {
+        let signer_id: AccountId = signer_id.parse().unwrap();
+        let signer = InMemorySigner::from_seed(signer_id.clone(), KeyType::ED25519, "test");
+        let tx = SignedTransaction::send_money(
+            nonce as u64 + 1,
+            signer_id.clone(),
+            "test0".parse().unwrap(),
+            &signer,
+            100,
+            genesis_hash,
+        );
+        let shard_uid = account_id_to_shard_uid(&signer_id, &old_shard_layout);
+        env.clients[0].sharded_tx_pool.insert_transaction(shard_uid, tx);
+        let new_shard_id = account_id_to_shard_uid(&signer_id, &new_shard_layout).shard_id();
+        *expected_moved.entry(new_shard_id).or_insert(0) += 1;
+    }
+
+    // Blocks are processed up to the last one before the change.
+    let before = metrics::snapshot();
+    let mut height = 1;
+    while env.clients[0].resharding_log.entries().is_empty() {
+        assert!(height <= 20, "the shard layout change wasn't detected");
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        env.clients[0]
+            .process_block_test_no_produce_chunk(block.into(), Provenance::PRODUCED)
+            .unwrap();
+        height += 1;
+    }
+    let after = metrics::snapshot();
+
+    let head = env.clients[0].chain.head().unwrap();
+    assert_eq!(
+        env.clients[0].epoch_manager.get_epoch_id_from_prev_block(&head.last_block_hash).unwrap(),
+        next_epoch_id
+    );
+    let events: Vec<_> =
+        env.clients[0].resharding_log.entries().into_iter().map(|entry| entry.event).collect();
+    assert_eq!(
+        events,
+        vec![
+            ReshardingEvent::ShardLayoutChangeDetected {
+                block_hash: head.last_block_hash,
+                old_version: 0,
+                new_version: 1,
+            },
+            ReshardingEvent::PoolResharded { new_version: 1, moved_transactions: expected_moved },
+        ]
+    );
+    assert_metric_delta(
+        "near_resharding_events_total{event=\"layout_change_detected\"}",
+        &before,
+        &after,
+        1.0,
+    );
+    assert_metric_delta(
+        "near_resharding_events_total{event=\"pool_resharded\"}",
+        &before,
+        &after,
+        1.0,
+    );
+}
+
+/// A transient failure to look up the chunk producer only skips the production of that chunk, the
+/// chunks at the next heights are produced as usual.
+#[test]
+fn test_chunk_production_survives_failed_chunk_producer_lookup() {
+    let chain_genesis = ChainGenesis::test();
+    let store = create_test_store();
+    let vs =
+        ValidatorSchedule::new().block_producers_per_epoch(vec![vec!["test0".parse().unwrap()]]);
+    let epoch_manager =
+        MockEpochManager::new_with_validators(store.clone(), vs, chain_genesis.epoch_length);
+    let mut env = TestEnv::builder(chain_genesis)
+        .stores(vec![store])
+        .mock_epoch_managers(vec![epoch_manager.clone()])
+        .build();
+    for height in 1..=3 {
+        env.produce_block(0, height);
+    }
+
+    // The chunk for height 5 is produced once the block at height 4 is accepted.
+    epoch_manager.fail_next_chunk_producer_lookup(5);
+    let before = metrics::snapshot();
+    env.produce_block(0, 4);
+    let after = metrics::snapshot();
+    assert_metric_delta(
+        "near_client_production_errors_total{producer=\"chunk\",severity=\"drop\"}",
+        &before,
+        &after,
+        1.0,
+    );
+    for height in 5..=6 {
+        env.produce_block(0, height);
+    }
+
+    let new_chunk_included = |env: &TestEnv, height| {
+        let hash = env.clients[0].chain.get_block_hash_by_height(height).unwrap();
+        env.clients[0].chain.get_block(&hash).unwrap().header().chunk_mask()[0]
+    };
+    assert!(new_chunk_included(&env, 4));
+    assert!(!new_chunk_included(&env, 5));
+    assert!(new_chunk_included(&env, 6));
+}
+
+/// With `produce_empty_chunk_on_failure`, a chunk whose transactions can't be prepared is replaced
+/// by an empty one, which is included in the next block. The transactions stay in the pool for the
+/// next chunk.
+#[test]
+fn test_empty_chunk_substituted_on_retryable_failure() {
+    let chain_genesis = ChainGenesis::test();
+    let store = create_test_store();
+    let vs =
+        ValidatorSchedule::new().block_producers_per_epoch(vec![vec!["test0".parse().unwrap()]]);
+    let epoch_manager =
+        MockEpochManager::new_with_validators(store.clone(), vs, chain_genesis.epoch_length);
+    let runtime = KeyValueRuntime::new(store.clone(), &epoch_manager);
+    let mut env = TestEnv::builder(chain_genesis)
+        .stores(vec![store])
+        .mock_epoch_managers(vec![epoch_manager])
+        .runtimes(vec![runtime.clone() as Arc<dyn RuntimeAdapter>])
+        .build();
+    env.clients[0].config.produce_empty_chunk_on_failure = true;
+    for height in 1..=3 {
+        env.produce_block(0, height);
+    }
+
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let tx = SignedTransaction::send_money(
+        1,
+        "test0".parse().unwrap(),
+        "test1".parse().unwrap(),
+        &signer,
+        100,
+        genesis_hash,
+    );
+    let shard_uid = account_id_to_shard_uid(&tx.transaction.signer_id, &ShardLayout::v0(1, 0));
+    env.clients[0].sharded_tx_pool.insert_transaction(shard_uid, tx.clone());
+
+    // The chunk for height 5 is produced once the block at height 4 is accepted.
+    runtime.fail_next_prepare_transactions(5);
+    let before = metrics::snapshot();
+    env.produce_block(0, 4);
+    let after = metrics::snapshot();
+    assert_metric_delta(
+        "near_client_production_errors_total{producer=\"chunk\",severity=\"retry\"}",
+        &before,
+        &after,
+        1.0,
+    );
+    assert_metric_delta(
+        "near_empty_chunks_substituted_total{shard_id=\"0\"}",
+        &before,
+        &after,
+        1.0,
+    );
+    for height in 5..=6 {
+        env.produce_block(0, height);
+    }
+
+    let new_chunk = |env: &TestEnv, height| {
+        let hash = env.clients[0].chain.get_block_hash_by_height(height).unwrap();
+        let block = env.clients[0].chain.get_block(&hash).unwrap();
+        assert!(block.header().chunk_mask()[0], "no new chunk at height {height}");
+        env.clients[0].chain.get_chunk(&block.chunks()[0].chunk_hash()).unwrap()
+    };
+    assert!(new_chunk(&env, 5).transactions().is_empty());
+    assert_eq!(new_chunk(&env, 6).transactions(), &[tx]);
+}
+
+/// Without the chunk extra of the previous block there is no state root for an empty chunk, so
+/// with `produce_empty_chunk_on_failure` the chunk is skipped rather than substituted.
+#[test]
+fn test_empty_chunk_not_substituted_without_chunk_extra() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    env.clients[0].config.produce_empty_chunk_on_failure = true;
+    for height in 1..=3 {
+        env.produce_block(0, height);
+    }
+    let head = env.clients[0].chain.head().unwrap();
+    let block = env.clients[0].chain.get_block(&head.last_block_hash).unwrap();
+    let epoch_manager = env.clients[0].epoch_manager.clone();
+    let epoch_id = epoch_manager.get_epoch_id_from_prev_block(&head.last_block_hash).unwrap();
+    let shard_uid = epoch_manager.shard_id_to_uid(0, &epoch_id).unwrap();
+    let key = get_block_shard_uid(&head.last_block_hash, &shard_uid);
+    delete_from_chain_store(&mut env, DBCol::ChunkExtra, &key);
+
+    let before = metrics::snapshot();
+    env.clients[0].produce_chunks(&block, "test0".parse().unwrap());
+    let after = metrics::snapshot();
+    assert_metric_delta(
+        "near_empty_chunks_substituted_total{shard_id=\"0\"}",
+        &before,
+        &after,
+        0.0,
+    );
+    let last_header = Chain::get_prev_chunk_header(epoch_manager.as_ref(), &block, 0).unwrap();
+    let result = env.clients[0].produce_empty_chunk(
+        head.last_block_hash,
+        &epoch_id,
+        &last_header,
+        head.height + 1,
+        0,
+    );
+    assert_matches!(result, Ok(None));
+}
+
+/// Transactions submitted in the last blocks of an epoch reach the chunk producers of the next
+/// epoch and are included in its first chunks, even if none of the chunk producers stays.
+#[test]
+fn test_tx_routing_at_epoch_boundary() {
+    let chain_genesis = ChainGenesis::test();
+    let accounts: Vec<AccountId> = vec!["test0".parse().unwrap(), "test1".parse().unwrap()];
+    let stores: Vec<_> = accounts.iter().map(|_| create_test_store()).collect();
+    // test0 produces the blocks and chunks of the first epoch, test1 the ones of the second.
+    let epoch_managers: Vec<_> = stores
+        .iter()
+        .map(|store| {
+            MockEpochManager::new_with_validators(
+                store.clone(),
+                ValidatorSchedule::new().block_producers_per_epoch(vec![
+                    vec![accounts[0].clone()],
+                    vec![accounts[1].clone()],
+                ]),
+                chain_genesis.epoch_length,
+            )
+        })
+        .collect();
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients(accounts.clone())
+        .validators(accounts.clone())
+        .stores(stores)
+        .mock_epoch_managers(epoch_managers)
+        .track_all_shards()
+        .build();
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signers: Vec<_> = accounts
+        .iter()
+        .map(|account_id| {
+            InMemorySigner::from_seed(account_id.clone(), KeyType::ED25519, account_id.as_ref())
+        })
+        .collect();
+    let send_money = |sender: usize, nonce| {
+        SignedTransaction::send_money(
+            nonce,
+            accounts[sender].clone(),
+            accounts[1 - sender].clone(),
+            &signers[sender],
+            1,
+            genesis_hash,
+        )
+    };
+
+    let mut submitted = vec![];
+    let mut last_height_of_epoch = None;
+    for height in 1..=20 {
+        let head = env.clients[0].chain.head().unwrap();
+        let epoch_manager = env.clients[0].epoch_manager.clone();
+        let epoch_id = epoch_manager.get_epoch_id_from_prev_block(&head.last_block_hash).unwrap();
+        let block_producer = epoch_manager.get_block_producer(&epoch_id, height).unwrap();
+        let block = env.client(&block_producer).produce_block(height).unwrap().unwrap();
+        for i in 0..env.clients.len() {
+            let provenance = if env.get_client_id(i) == &block_producer {
+                Provenance::PRODUCED
+            } else {
+                Provenance::NONE
+            };
+            // The clients which don't own the parts of the chunks request them below.
+            let _ = env.clients[i].process_block_test(block.clone().into(), provenance);
+        }
+        env.process_network_requests();
+        for i in 0..env.clients.len() {
+            env.process_shards_manager_responses_and_finish_processing_blocks(i);
+            assert_eq!(env.clients[i].chain.head().unwrap().height, height);
+        }
+
+        if let Some(last_height) = last_height_of_epoch {
+            if height == last_height + 3 {
+                break;
+            }
+            continue;
+        }
+        // A transaction is submitted to test0 at every height of the first epoch, the last ones
+        // can only be included by test1.
+        let response = env.clients[0].process_tx(send_money(0, height), false, false);
+        if epoch_manager.is_next_block_epoch_start(block.hash()).unwrap() {
+            last_height_of_epoch = Some(height);
+            assert_eq!(response, ProcessTxResponse::RequestRouted);
+
+            // test1 produces the next chunks itself, so the transactions it receives aren't sent
+            // to the chunk producers of the epoch which ends.
+            let response = env.clients[1].process_tx(send_money(1, height), false, false);
+            assert_eq!(response, ProcessTxResponse::ValidTx);
+            let forwarded_to_test0 =
+                env.network_adapters[1].requests.read().unwrap().iter().any(|request| {
+                    matches!(
+                        request,
+                        PeerManagerMessageRequest::NetworkRequests(NetworkRequests::ForwardTx(
+                            account_id,
+                            _,
+                        )) if account_id == &accounts[0]
+                    )
+                });
+            assert!(!forwarded_to_test0);
+            submitted.push(send_money(1, height));
+        } else {
+            assert_eq!(response, ProcessTxResponse::ValidTx);
+        }
+        submitted.push(send_money(0, height));
+        env.process_network_requests();
+    }
+    let last_height_of_epoch = last_height_of_epoch.expect("the epoch didn't end");
+
+    let chain = &env.clients[0].chain;
+    let mut included = HashSet::new();
+    for height in 1..=last_height_of_epoch + 3 {
+        let block = chain.get_block(&chain.get_block_hash_by_height(height).unwrap()).unwrap();
+        let chunk_header = &block.chunks()[0];
+        if chunk_header.height_included() == height {
+            let chunk = chain.get_chunk(&chunk_header.chunk_hash()).unwrap();
+            included.extend(chunk.transactions().iter().map(|tx| tx.get_hash()));
+        }
+    }
+    for tx in &submitted {
+        assert!(included.contains(&tx.get_hash()), "{:?} wasn't included", tx.transaction);
+    }
+}
+
+/// A transaction submitted again isn't validated again, unless the state of its shard changed or
+/// it was included in the meantime.
+#[test]
+fn test_resubmitted_tx_not_revalidated() {
+    let chain_genesis = ChainGenesis::test();
+    let store = create_test_store();
+    let vs =
+        ValidatorSchedule::new().block_producers_per_epoch(vec![vec!["test0".parse().unwrap()]]);
+    let epoch_manager =
+        MockEpochManager::new_with_validators(store.clone(), vs, chain_genesis.epoch_length);
+    let runtime = KeyValueRuntime::new(store.clone(), &epoch_manager);
+    let mut env = TestEnv::builder(chain_genesis)
+        .stores(vec![store])
+        .mock_epoch_managers(vec![epoch_manager])
+        .runtimes(vec![runtime.clone() as Arc<dyn RuntimeAdapter>])
+        .build();
+    for height in 1..=3 {
+        env.produce_block(0, height);
+    }
+
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let [tx1, tx2] = [1, 2].map(|nonce| {
+        SignedTransaction::send_money(
+            nonce,
+            "test0".parse().unwrap(),
+            "test1".parse().unwrap(),
+            &signer,
+            100,
+            genesis_hash,
+        )
+    });
+    let shard_uid = account_id_to_shard_uid(&tx1.transaction.signer_id, &ShardLayout::v0(1, 0));
+    let state_root = |env: &TestEnv| {
+        let head = env.clients[0].chain.head().unwrap();
+        *env.clients[0]
+            .chain
+            .get_chunk_extra(&head.last_block_hash, &shard_uid)
+            .unwrap()
+            .state_root()
+    };
+    // Returns the number of validations needed to process the transaction.
+    let submit = |env: &mut TestEnv, tx: &SignedTransaction, check_only| {
+        let before = runtime.num_validate_tx_calls();
+        let response = env.clients[0].process_tx(tx.clone(), false, check_only);
+        assert_eq!(response, ProcessTxResponse::ValidTx);
+        runtime.num_validate_tx_calls() - before
+    };
+
+    assert_eq!(submit(&mut env, &tx1, true), 2);
+    assert_eq!(submit(&mut env, &tx1, true), 0);
+    assert_eq!(submit(&mut env, &tx1, true), 0);
+
+    // Including tx2 changes the state, so tx1 is validated against the new state once.
+    let old_state_root = state_root(&env);
+    assert_eq!(submit(&mut env, &tx2, false), 2);
+    for height in 4..=6 {
+        env.produce_block(0, height);
+    }
+    assert_ne!(state_root(&env), old_state_root);
+    assert_eq!(submit(&mut env, &tx1, true), 1);
+    assert_eq!(submit(&mut env, &tx1, true), 0);
+
+    // The verdicts of the included transaction are dropped.
+    assert_eq!(submit(&mut env, &tx2, true), 2);
+}
+
+/// Transactions aren't forwarded to a banned chunk producer, but to the producer of a later height
+/// instead, unless every candidate is banned.
+#[test]
+fn test_forward_tx_skips_banned_chunk_producer() {
+    let chain_genesis = ChainGenesis::test();
+    let store = create_test_store();
+    let validators: Vec<AccountId> =
+        ["test0", "test1", "test2", "test3"].iter().map(|v| v.parse().unwrap()).collect();
+    let vs = ValidatorSchedule::new().block_producers_per_epoch(vec![validators.clone()]);
+    let epoch_manager =
+        MockEpochManager::new_with_validators(store.clone(), vs, chain_genesis.epoch_length);
+    // The client doesn't track the shard, so it forwards every transaction it receives.
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients(vec!["rpc".parse().unwrap()])
+        .stores(vec![store])
+        .mock_epoch_managers(vec![epoch_manager.clone()])
+        .build();
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let epoch_id = epoch_manager.get_epoch_id_from_prev_block(&genesis_hash).unwrap();
+    let epoch_height = epoch_manager.get_epoch_height_from_prev_block(&genesis_hash).unwrap();
+    let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    let forward_targets = |env: &mut TestEnv, nonce| {
+        let tx = SignedTransaction::send_money(
+            nonce,
+            "test0".parse().unwrap(),
+            "test1".parse().unwrap(),
+            &signer,
+            100,
+            genesis_hash,
+        );
+        let response = env.clients[0].process_tx(tx, false, false);
+        assert_eq!(response, ProcessTxResponse::RequestRouted);
+        let mut targets = HashSet::new();
+        for request in env.network_adapters[0].requests.write().unwrap().drain(..) {
+            if let PeerManagerMessageRequest::NetworkRequests(NetworkRequests::ForwardTx(
+                account_id,
+                _,
+            )) = request
+            {
+                targets.insert(account_id);
+            }
+        }
+        targets
+    };
+
+    // The chunk producers of the next heights rotate, test1 isn't needed to cover the routing
+    // horizons.
+    let targets = forward_targets(&mut env, 1);
+    assert!(targets.contains(&validators[0]));
+    assert!(!targets.contains(&validators[1]));
+
+    env.clients[0].do_not_include_chunks_from.ban(
+        epoch_height,
+        epoch_id.clone(),
+        validators[0].clone(),
+    );
+    // Every routing horizon at which a banned producer produces the chunk is rerouted, including
+    // the horizons in the next epoch if it may start within them.
+    let client = &env.clients[0];
+    let head = client.chain.head().unwrap();
+    let epoch_ids: Vec<_> = std::iter::once(epoch_id.clone())
+        .chain(client.get_next_epoch_id_if_at_boundary(&head).unwrap())
+        .collect();
+    let banned_horizons = (2..=TX_ROUTING_HEIGHT_HORIZON)
+        .chain([TX_ROUTING_HEIGHT_HORIZON * 2])
+        .flat_map(|horizon| epoch_ids.iter().map(move |epoch_id| (epoch_id, horizon)))
+        .filter(|(epoch_id, horizon)| {
+            let chunk_producer =
+                client.chain.find_chunk_producer_for_forwarding(epoch_id, 0, *horizon).unwrap();
+            client.do_not_include_chunks_from.is_banned(epoch_id, &chunk_producer)
+        })
+        .count();
+    let before = metrics::snapshot();
+    let rerouted_targets = forward_targets(&mut env, 2);
+    let after = metrics::snapshot();
+    assert_metric_delta(
+        "near_transaction_forward_rerouted_total{shard_id=\"0\"}",
+        &before,
+        &after,
+        banned_horizons as f64,
+    );
+    assert!(!rerouted_targets.contains(&validators[0]));
+    assert_eq!(rerouted_targets.len(), targets.len());
+
+    // With every chunk producer banned, the transactions are forwarded as before.
+    for validator in &validators[1..] {
+        env.clients[0].do_not_include_chunks_from.ban(
+            epoch_height,
+            epoch_id.clone(),
+            validator.clone(),
+        );
+    }
+    assert_eq!(forward_targets(&mut env, 3), targets);
+}
+
+/// The config state reports the dynamic updates of the client config along with the config the
+/// node started with.
+#[test]
+fn test_client_config_state() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    let startup = env.clients[0].config_state().startup;
+    assert_eq!(env.clients[0].config_state().effective, startup);
+
+    for expected_shutdown in [100, 200] {
+        env.clients[0].update_client_config(UpdateableClientConfig {
+            expected_shutdown: Some(expected_shutdown),
+        });
+    }
+    let state = env.clients[0].config_state();
+    assert_eq!(state.startup, startup);
+    let changed_fields: Vec<_> =
+        state.updates.iter().map(|update| update.changed_fields.clone()).collect();
+    assert_eq!(
+        changed_fields,
+        vec![
+            BTreeMap::from([("expected_shutdown".to_string(), serde_json::json!("100"))]),
+            BTreeMap::from([("expected_shutdown".to_string(), serde_json::json!("200"))]),
+        ]
+    );
+    assert!(state.updates[0].time <= state.updates[1].time);
+    let (serde_json::Value::Object(effective), serde_json::Value::Object(startup)) =
+        (state.effective, startup)
+    else {
+        panic!("the config isn't serialized as an object");
+    };
+    assert_eq!(effective["expected_shutdown"], serde_json::json!("200"));
+    for (field, value) in &startup {
+        if field != "expected_shutdown" {
+            assert_eq!(&effective[field], value, "{field} changed");
+        }
+    }
+
+    // The location of the credentials doesn't leave the node.
+    let mut config = env.clients[0].config.clone();
+    config.state_sync.dump = Some(DumpConfig {
+        location: ExternalStorageLocation::Filesystem { root_dir: "/tmp/dump".into() },
+        restart_dump_for_shards: None,
+        iteration_delay: None,
+        credentials_file: Some("/secret/credentials.json".into()),
+    });
+    let redacted = config.to_redacted_json().to_string();
+    assert!(!redacted.contains("/secret/credentials.json"));
+    assert!(redacted.contains("/tmp/dump"));
+}
+
+/// The time a chunk became ready for inclusion is taken from the static clock, so that the time
+/// by which it preceded the block production can be checked exactly.
+#[test]
+fn test_chunk_ready_for_inclusion_time() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    env.produce_block(0, 1);
+    let (chunk, _, _) = create_chunk_on_height(&mut env.clients[0], 2);
+    let chunk_time = chrono::Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+    let block_time = chunk_time + chrono::Duration::milliseconds(250);
+
+    let client = &mut env.clients[0];
+    let mock_clock_guard = MockClockGuard::default();
+    mock_clock_guard.add_utc(chunk_time);
+    client.on_chunk_header_ready_for_inclusion(chunk.cloned_header(), "test0".parse().unwrap());
+    drop(mock_clock_guard);
+
+    let prev_hash = *chunk.cloned_header().prev_block_hash();
+    let epoch_id = client.epoch_manager.get_epoch_id_from_prev_block(&prev_hash).unwrap();
+    let new_chunks = client.get_chunk_headers_ready_for_inclusion(&epoch_id, &prev_hash);
+    assert_eq!(new_chunks[&0].1, chunk_time);
+
+    client.block_production_info.record_approvals(2, Default::default());
+    let chunk_collections = BlockProductionTracker::construct_chunk_collection_info(
+        2,
+        &epoch_id,
+        1,
+        &new_chunks,
+        client.epoch_manager.as_ref(),
+    )
+    .unwrap();
+    let mock_clock_guard = MockClockGuard::default();
+    mock_clock_guard.add_utc(block_time);
+    client.block_production_info.record_block_production(2, chunk_collections);
+    drop(mock_clock_guard);
+
+    let block_production = client.block_production_info.get(2);
+    let chunk_collection = &block_production.chunks_collection_time[0];
+    assert!(chunk_collection.chunk_included);
+    assert_eq!(
+        block_production.block_production_time.unwrap() - chunk_collection.received_time.unwrap(),
+        chrono::Duration::milliseconds(250)
+    );
+}
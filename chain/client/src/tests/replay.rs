@@ -0,0 +1,146 @@
+use crate::test_utils::{ClientReplayHarness, ClientTrace, TestEnv};
+use chrono::TimeZone;
+use near_chain::ChainGenesis;
+use near_chunks::client::ShardsManagerResponse;
+use near_crypto::{InMemorySigner, KeyType};
+use near_network::types::{NetworkRequests, PeerManagerMessageRequest};
+use near_primitives::block::Approval;
+use near_primitives::block_header::ApprovalType;
+use near_primitives::network::PeerId;
+use near_primitives::sharding::{PartialEncodedChunk, ShardChunk};
+use near_primitives::test_utils::create_test_signer;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::AccountId;
+use std::path::Path;
+
+/// test0 produces the blocks and test1, which isn't a validator, follows the chain. The genesis
+/// time is fixed so that the traces of test1 can be replayed on a later run.
+fn replay_env() -> TestEnv {
+    let chain_genesis = ChainGenesis {
+        time: chrono::Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+        ..ChainGenesis::test()
+    };
+    TestEnv::builder(chain_genesis)
+        .clients(vec!["test0".parse().unwrap(), "test1".parse().unwrap()])
+        .validators(vec!["test0".parse().unwrap()])
+        .track_all_shards()
+        .build()
+}
+
+/// Delivers the requests test1 sent to the network: the parts of the chunks it requested are sent
+/// back to its ShardsManager and the transactions it forwarded are handed to test0.
+fn route_network_requests(env: &mut TestEnv) {
+    while let Some(request) = env.network_adapters[1].pop() {
+        match request {
+            PeerManagerMessageRequest::NetworkRequests(NetworkRequests::ForwardTx(_, tx)) => {
+                env.clients[0].process_tx(tx, true, false);
+            }
+            request @ PeerManagerMessageRequest::NetworkRequests(
+                NetworkRequests::PartialEncodedChunkRequest { .. },
+            ) => env.process_partial_encoded_chunk_request(1, request),
+            _ => {}
+        }
+    }
+}
+
+/// Takes the chunks the ShardsManager of test1 completed, without handing them to the client.
+fn take_completed_chunks(env: &mut TestEnv) -> Vec<(PartialEncodedChunk, Option<ShardChunk>)> {
+    let mut chunks = vec![];
+    while let Some(response) = env.client_adapters[1].pop() {
+        match response {
+            ShardsManagerResponse::ChunkCompleted { partial_chunk, shard_chunk } => {
+                chunks.push((partial_chunk, shard_chunk))
+            }
+            ShardsManagerResponse::ChunksCompleted(completed) => chunks.extend(completed),
+            _ => {}
+        }
+    }
+    chunks
+}
+
+/// The trace of `test_replay_blocks_ahead_of_chunks`, checked in so that the replay of the
+/// same inputs is checked across the changes of the client. Rewritten by running the test with
+/// `UPDATE_REPLAY_TRACES=1`.
+const BLOCKS_AHEAD_OF_CHUNKS_TRACE: &str =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/res/replay/blocks_ahead_of_chunks.json");
+
+/// Every block reaches test1 before the chunks of its parent are complete, so the block is an
+/// orphan until they are, and its own chunks are only requested then.
+fn record_blocks_ahead_of_chunks() -> ClientTrace {
+    let mut env = replay_env();
+    let test0: AccountId = "test0".parse().unwrap();
+    let test1: AccountId = "test1".parse().unwrap();
+    let signer = InMemorySigner::from_seed(test0.clone(), KeyType::ED25519, test0.as_ref());
+    let validator_signer = create_test_signer("test0");
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    let send_money = |nonce| {
+        SignedTransaction::send_money(nonce, test0.clone(), test1.clone(), &signer, 1, genesis_hash)
+    };
+    let peer_id = PeerId::random();
+
+    let mut harness = ClientReplayHarness::default();
+    let mut completed_chunks = vec![];
+    for height in 1..=8 {
+        harness.process_tx(&mut env.clients[1], send_money(height), false, false);
+        route_network_requests(&mut env);
+
+        env.produce_block(0, height);
+        let block = env.clients[0].chain.get_block_by_height(height).unwrap();
+        harness.receive_block(&mut env.clients[1], block.clone(), peer_id.clone(), false);
+        for (partial_chunk, shard_chunk) in completed_chunks.drain(..) {
+            harness.on_chunk_completed(&mut env.clients[1], partial_chunk, shard_chunk);
+        }
+        route_network_requests(&mut env);
+        completed_chunks = take_completed_chunks(&mut env);
+
+        let approval = Approval::new(*block.hash(), height, height + 1, &validator_signer);
+        harness.collect_block_approval(
+            &mut env.clients[1],
+            &approval,
+            ApprovalType::PeerApproval(peer_id.clone()),
+        );
+    }
+    for (partial_chunk, shard_chunk) in completed_chunks {
+        harness.on_chunk_completed(&mut env.clients[1], partial_chunk, shard_chunk);
+    }
+    let last_tx = send_money(9);
+    harness.process_tx(&mut env.clients[1], last_tx.clone(), false, false);
+
+    let trace = harness.finish(&env.clients[1]);
+    assert_eq!(trace.expected.head_height, 8);
+    assert_eq!(trace.expected.chunk_masks.len(), 8);
+    assert!(trace.expected.pool[&0].contains(&last_tx.get_hash()));
+    trace
+}
+
+/// The trace of test1 replayed against a fresh client leads to the same head, chunk masks and
+/// pool, also once saved and loaded.
+#[test]
+fn test_record_and_replay_blocks_ahead_of_chunks() {
+    let trace = record_blocks_ahead_of_chunks();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("trace.json");
+    trace.save(&path).unwrap();
+    let trace = ClientTrace::load(&path).unwrap();
+    let mut replay_env = replay_env();
+    let state = ClientReplayHarness::replay(&mut replay_env.clients[1], &trace);
+    assert_eq!(state, trace.expected);
+}
+
+/// The checked-in trace replayed against a fresh client leads to the recorded state.
+#[test]
+fn test_replay_blocks_ahead_of_chunks() {
+    let path = Path::new(BLOCKS_AHEAD_OF_CHUNKS_TRACE);
+    if std::env::var_os("UPDATE_REPLAY_TRACES").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        record_blocks_ahead_of_chunks().save(path).unwrap();
+    }
+    let trace = ClientTrace::load(path).unwrap_or_else(|err| {
+        panic!(
+            "Failed to load {path:?}: {err}, run the test with UPDATE_REPLAY_TRACES=1 to record it"
+        )
+    });
+    let mut replay_env = replay_env();
+    let state = ClientReplayHarness::replay(&mut replay_env.clients[1], &trace);
+    assert_eq!(state, trace.expected);
+}
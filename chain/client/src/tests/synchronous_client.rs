@@ -0,0 +1,23 @@
+use crate::adapter::ProcessTxResponse;
+use crate::test_utils::{run_catchup_with_spawner, SynchronousFutureSpawner, TestEnv};
+use near_chain::ChainGenesis;
+use near_o11y::testonly::init_test_logger;
+
+/// Drives `Client` through block production, transaction processing and catchup synchronously,
+/// with the futures applying the state parts run on the calling thread rather than on an arbiter.
+#[test]
+fn test_client_driven_synchronously() {
+    init_test_logger();
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    for height in 1..5 {
+        env.produce_block(0, height);
+    }
+    assert_eq!(env.send_money(0), ProcessTxResponse::ValidTx);
+    for height in 5..10 {
+        env.produce_block(0, height);
+    }
+
+    let client = &mut env.clients[0];
+    assert_eq!(client.chain.head().unwrap().height, 9);
+    run_catchup_with_spawner(client, &[], &SynchronousFutureSpawner).unwrap();
+}
@@ -0,0 +1,51 @@
+//! Chain-specific rules for admitting transactions.
+//!
+//! Private deployments may want to restrict which transactions their chunk producers accept,
+//! e.g. to allowlisted signers or with quotas per method, on top of the protocol rules. The
+//! policy is checked when a transaction is about to be added to the pool and again when the
+//! transactions of a new chunk are selected from it.
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{BlockHeight, EpochId, ShardId};
+
+/// Where a transaction is checked by the admission policy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxAdmissionStage {
+    /// Before adding a received transaction to the pool.
+    Pool,
+    /// Before including a transaction from the pool in a produced chunk.
+    Chunk,
+}
+
+impl TxAdmissionStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pool => "pool",
+            Self::Chunk => "chunk",
+        }
+    }
+}
+
+/// Chain context of the admission check.
+#[derive(Debug)]
+pub struct TxAdmissionContext<'a> {
+    pub stage: TxAdmissionStage,
+    /// Shard the transaction belongs to.
+    pub shard_id: ShardId,
+    pub epoch_id: &'a EpochId,
+    /// Height of the chunk the transaction would be included in at the earliest.
+    pub height: BlockHeight,
+}
+
+pub trait TxAdmissionPolicy: Send + Sync {
+    /// Returns the reason why `tx` isn't admitted, if it isn't.
+    fn check(&self, tx: &SignedTransaction, context: &TxAdmissionContext) -> Result<(), String>;
+}
+
+/// Admits every transaction. Used unless a policy is given to the client.
+pub struct NoopTxAdmissionPolicy;
+
+impl TxAdmissionPolicy for NoopTxAdmissionPolicy {
+    fn check(&self, _tx: &SignedTransaction, _context: &TxAdmissionContext) -> Result<(), String> {
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+//! Admission lanes of the transactions received by the client.
+//!
+//! Transactions submitted to this node by its RPC users and transactions forwarded to it by
+//! other nodes are admitted separately. Forwarded transactions were already routed by another
+//! node on behalf of its users, so under load they are preferred: each lane has its own rate
+//! budget, and part of the pool can only be filled by forwarded transactions.
+use near_chain_configs::ClientConfig;
+use near_primitives::static_clock::StaticClock;
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TxLane {
+    /// Transactions submitted to this node directly.
+    Local,
+    /// Transactions forwarded by other nodes.
+    Forwarded,
+}
+
+impl TxLane {
+    pub(crate) fn new(is_forwarded: bool) -> Self {
+        if is_forwarded {
+            Self::Forwarded
+        } else {
+            Self::Local
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Forwarded => "forwarded",
+        }
+    }
+}
+
+/// Token bucket allowing `rate` transactions per second, in bursts of at most `rate`.
+struct TxRateBudget {
+    rate: u64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TxRateBudget {
+    fn new(rate: u64) -> Self {
+        Self { rate, tokens: rate as f64, last_refill: StaticClock::instant() }
+    }
+
+    fn try_take(&mut self) -> bool {
+        let now = StaticClock::instant();
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        self.last_refill = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+pub(crate) struct TxLanes {
+    local_budget: Option<TxRateBudget>,
+    forwarded_budget: Option<TxRateBudget>,
+    /// Percentage of the pool size limit only forwarded transactions can use.
+    forwarded_pool_reservation_percent: u64,
+}
+
+impl TxLanes {
+    pub(crate) fn new(config: &ClientConfig) -> Self {
+        Self {
+            local_budget: config.local_tx_rate_limit.map(TxRateBudget::new),
+            forwarded_budget: config.forwarded_tx_rate_limit.map(TxRateBudget::new),
+            forwarded_pool_reservation_percent: config
+                .forwarded_tx_pool_reservation_percent
+                .min(100),
+        }
+    }
+
+    /// Takes one transaction from the rate budget of `lane`. Returns false if the budget is
+    /// exhausted.
+    pub(crate) fn try_take_budget(&mut self, lane: TxLane) -> bool {
+        let budget = match lane {
+            TxLane::Local => &mut self.local_budget,
+            TxLane::Forwarded => &mut self.forwarded_budget,
+        };
+        budget.as_mut().map_or(true, |budget| budget.try_take())
+    }
+
+    /// Number of bytes below `pool_size_limit` which transactions of `lane` must leave free.
+    pub(crate) fn reserved_pool_size(&self, lane: TxLane, pool_size_limit: Option<u64>) -> u64 {
+        match (lane, pool_size_limit) {
+            (TxLane::Local, Some(limit)) => {
+                (limit as u128 * self.forwarded_pool_reservation_percent as u128 / 100) as u64
+            }
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TxLane, TxLanes};
+    use near_chain_configs::ClientConfig;
+
+    #[test]
+    fn test_tx_lanes() {
+        let mut config = ClientConfig::test(true, 10, 20, 1, false, true, true, true);
+        config.local_tx_rate_limit = Some(2);
+        config.forwarded_tx_rate_limit = None;
+        config.forwarded_tx_pool_reservation_percent = 30;
+        let mut lanes = TxLanes::new(&config);
+
+        // The budget refills by 2 per second, not noticeably while the test runs.
+        assert!(lanes.try_take_budget(TxLane::Local));
+        assert!(lanes.try_take_budget(TxLane::Local));
+        assert!(!lanes.try_take_budget(TxLane::Local));
+        for _ in 0..10 {
+            assert!(lanes.try_take_budget(TxLane::Forwarded));
+        }
+
+        assert_eq!(lanes.reserved_pool_size(TxLane::Local, Some(1000)), 300);
+        assert_eq!(lanes.reserved_pool_size(TxLane::Local, None), 0);
+        assert_eq!(lanes.reserved_pool_size(TxLane::Forwarded, Some(1000)), 0);
+    }
+}
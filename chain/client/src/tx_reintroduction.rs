@@ -0,0 +1,86 @@
+//! Budget of the transactions reintroduced to the pool after a reorg.
+//!
+//! The transactions of the blocks abandoned by a reorg are added back to the pool, so that they
+//! are included on the new chain. After a deep reorg these can be tens of thousands of
+//! transactions, which would fill the size-limited pool ahead of the transactions submitted
+//! since and stall the client while they are inserted. The number and total size of the
+//! transactions reintroduced per reorg are therefore bounded, and the blocks closest to the
+//! abandoned head are reintroduced first.
+use near_chain_configs::ClientConfig;
+use near_primitives::transaction::SignedTransaction;
+
+pub(crate) struct TxReintroductionBudget {
+    remaining_count: Option<usize>,
+    remaining_size: Option<u64>,
+    /// Set once a transaction didn't fit, so that the transactions of older blocks don't take
+    /// what is left of the budget.
+    exhausted: bool,
+}
+
+impl TxReintroductionBudget {
+    pub(crate) fn new(config: &ClientConfig) -> Self {
+        Self {
+            remaining_count: config.reorg_tx_reintroduction_limit,
+            remaining_size: config.reorg_tx_reintroduction_size_limit,
+            exhausted: false,
+        }
+    }
+
+    /// Takes `tx` from the budget. Returns false if it doesn't fit into what is left of it.
+    pub(crate) fn try_take(&mut self, tx: &SignedTransaction) -> bool {
+        if self.exhausted
+            || self.remaining_count == Some(0)
+            || self.remaining_size.map_or(false, |size| size < tx.get_size())
+        {
+            self.exhausted = true;
+            return false;
+        }
+        if let Some(count) = &mut self.remaining_count {
+            *count -= 1;
+        }
+        if let Some(size) = &mut self.remaining_size {
+            *size -= tx.get_size();
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TxReintroductionBudget;
+    use near_chain_configs::ClientConfig;
+    use near_crypto::{InMemorySigner, KeyType};
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::transaction::SignedTransaction;
+
+    #[test]
+    fn test_tx_reintroduction_budget() {
+        let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+        let txs: Vec<_> = (1..=5)
+            .map(|nonce| {
+                SignedTransaction::send_money(
+                    nonce,
+                    "test0".parse().unwrap(),
+                    "test1".parse().unwrap(),
+                    &signer,
+                    1,
+                    CryptoHash::default(),
+                )
+            })
+            .collect();
+        let mut config = ClientConfig::test(true, 10, 20, 1, false, true, true, true);
+
+        config.reorg_tx_reintroduction_limit = Some(3);
+        config.reorg_tx_reintroduction_size_limit = None;
+        let mut budget = TxReintroductionBudget::new(&config);
+        let taken: Vec<_> = txs.iter().map(|tx| budget.try_take(tx)).collect();
+        assert_eq!(taken, vec![true, true, true, false, false]);
+
+        // All the transactions have the same size.
+        config.reorg_tx_reintroduction_limit = None;
+        config.reorg_tx_reintroduction_size_limit = Some(2 * txs[0].get_size() + 1);
+        let mut budget = TxReintroductionBudget::new(&config);
+        let taken: Vec<_> = txs.iter().map(|tx| budget.try_take(tx)).collect();
+        assert_eq!(taken, vec![true, true, false, false, false]);
+    }
+}
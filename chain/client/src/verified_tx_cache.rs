@@ -0,0 +1,225 @@
+//! Verdicts of the recent validations of the submitted transactions.
+//!
+//! Relayers submit the same transaction to several nodes and resubmit it on timeouts, and every
+//! submission is validated twice: without the state, which checks the signature, and against the
+//! state of the shard at the head. The verdicts are therefore kept for a while, per transaction
+//! and signature, as the hash of a transaction doesn't cover its signature. The verdict of the
+//! validation without the state holds for as long as it is kept, the one against the state only
+//! for the same state root, gas price, epoch and protocol version.
+use lru::LruCache;
+use near_crypto::Signature;
+use near_primitives::errors::InvalidTxError;
+use near_primitives::hash::CryptoHash;
+use near_primitives::static_clock::StaticClock;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{Balance, EpochId, StateRoot};
+use near_primitives::version::ProtocolVersion;
+use std::time::{Duration, Instant};
+
+/// Number of transactions whose verdicts are kept.
+const VERIFIED_TX_CACHE_SIZE: usize = 10_000;
+/// Time for which a verdict is reused.
+const VERIFIED_TX_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Result of a validation, `None` if the transaction is valid.
+pub(crate) type Verdict = Option<InvalidTxError>;
+
+/// What the validation of a transaction against the state depends on besides the transaction.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct StatefulValidationContext {
+    pub state_root: StateRoot,
+    pub gas_price: Balance,
+    pub epoch_id: EpochId,
+    pub protocol_version: ProtocolVersion,
+}
+
+struct VerifiedTx {
+    verified_at: Instant,
+    basic: Verdict,
+    /// Verdict of the validation against the state and the context it was validated in.
+    stateful: Option<(StatefulValidationContext, Verdict)>,
+}
+
+pub(crate) struct VerifiedTxCache {
+    entries: LruCache<(CryptoHash, Signature), VerifiedTx>,
+    max_age: Duration,
+}
+
+fn key(tx: &SignedTransaction) -> (CryptoHash, Signature) {
+    (tx.get_hash(), tx.signature.clone())
+}
+
+impl VerifiedTxCache {
+    pub(crate) fn new() -> Self {
+        Self::with_limits(VERIFIED_TX_CACHE_SIZE, VERIFIED_TX_MAX_AGE)
+    }
+
+    pub(crate) fn with_limits(capacity: usize, max_age: Duration) -> Self {
+        Self { entries: LruCache::new(capacity), max_age }
+    }
+
+    fn get(&mut self, tx: &SignedTransaction) -> Option<&mut VerifiedTx> {
+        let key = key(tx);
+        let verified_at = self.entries.peek(&key)?.verified_at;
+        let expired = StaticClock::instant().saturating_duration_since(verified_at) > self.max_age;
+        if expired {
+            self.entries.pop(&key);
+            return None;
+        }
+        self.entries.get_mut(&key)
+    }
+
+    /// The verdict of the validation of the transaction without the state, if it's known.
+    pub(crate) fn basic_verdict(&mut self, tx: &SignedTransaction) -> Option<Verdict> {
+        self.get(tx).map(|verified| verified.basic.clone())
+    }
+
+    /// The verdict of the validation of the transaction against the state in `context`, if it's
+    /// known.
+    pub(crate) fn stateful_verdict(
+        &mut self,
+        tx: &SignedTransaction,
+        context: &StatefulValidationContext,
+    ) -> Option<Verdict> {
+        match &self.get(tx)?.stateful {
+            Some((verified_context, verdict)) if verified_context == context => {
+                Some(verdict.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Records the verdict of the validation without the state, replacing everything known about
+    /// the transaction.
+    pub(crate) fn record_basic(&mut self, tx: &SignedTransaction, verdict: Verdict) {
+        self.entries.put(
+            key(tx),
+            VerifiedTx { verified_at: StaticClock::instant(), basic: verdict, stateful: None },
+        );
+    }
+
+    /// Records the verdict of the validation against the state in `context`. Ignored unless the
+    /// verdict of the validation without the state is known.
+    pub(crate) fn record_stateful(
+        &mut self,
+        tx: &SignedTransaction,
+        context: StatefulValidationContext,
+        verdict: Verdict,
+    ) {
+        if let Some(verified) = self.get(tx) {
+            verified.stateful = Some((context, verdict));
+        }
+    }
+
+    /// Forgets the transactions, e.g. because they were included in a chunk.
+    pub(crate) fn remove<'a>(&mut self, txs: impl IntoIterator<Item = &'a SignedTransaction>) {
+        for tx in txs {
+            self.entries.pop(&key(tx));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StatefulValidationContext, VerifiedTxCache};
+    use chrono::TimeZone;
+    use near_crypto::{InMemorySigner, KeyType};
+    use near_primitives::errors::InvalidTxError;
+    use near_primitives::hash::{hash, CryptoHash};
+    use near_primitives::static_clock::MockClockGuard;
+    use near_primitives::transaction::SignedTransaction;
+    use near_primitives::types::EpochId;
+    use near_primitives::version::PROTOCOL_VERSION;
+    use std::time::Duration;
+
+    fn tx(nonce: u64) -> SignedTransaction {
+        let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+        SignedTransaction::send_money(
+            nonce,
+            "test0".parse().unwrap(),
+            "test1".parse().unwrap(),
+            &signer,
+            1,
+            CryptoHash::default(),
+        )
+    }
+
+    fn context(state_root: CryptoHash) -> StatefulValidationContext {
+        StatefulValidationContext {
+            state_root,
+            gas_price: 100,
+            epoch_id: EpochId::default(),
+            protocol_version: PROTOCOL_VERSION,
+        }
+    }
+
+    #[test]
+    fn test_verified_tx_cache() {
+        let mut cache = VerifiedTxCache::with_limits(2, Duration::from_secs(3600));
+        let [tx0, tx1, tx2] = [tx(0), tx(1), tx(2)];
+        let [context0, context1] = [context(hash(b"root0")), context(hash(b"root1"))];
+        assert_eq!(cache.basic_verdict(&tx0), None);
+
+        cache.record_basic(&tx0, None);
+        cache.record_stateful(&tx0, context0.clone(), Some(InvalidTxError::InvalidSignature));
+        assert_eq!(cache.basic_verdict(&tx0), Some(None));
+        assert_eq!(
+            cache.stateful_verdict(&tx0, &context0),
+            Some(Some(InvalidTxError::InvalidSignature))
+        );
+        assert_eq!(cache.stateful_verdict(&tx0, &context1), None);
+        let other_gas_price = StatefulValidationContext { gas_price: 200, ..context0.clone() };
+        assert_eq!(cache.stateful_verdict(&tx0, &other_gas_price), None);
+        let other_protocol_version = StatefulValidationContext {
+            protocol_version: PROTOCOL_VERSION - 1,
+            ..context0.clone()
+        };
+        assert_eq!(cache.stateful_verdict(&tx0, &other_protocol_version), None);
+        let other_epoch =
+            StatefulValidationContext { epoch_id: EpochId(hash(b"epoch")), ..context0.clone() };
+        assert_eq!(cache.stateful_verdict(&tx0, &other_epoch), None);
+
+        // Without the basic verdict the stateful one isn't kept.
+        cache.record_stateful(&tx1, context0.clone(), None);
+        assert_eq!(cache.stateful_verdict(&tx1, &context0), None);
+
+        cache.record_basic(&tx1, Some(InvalidTxError::InvalidSignature));
+        cache.remove([&tx1]);
+        assert_eq!(cache.basic_verdict(&tx1), None);
+
+        // The least recently used transaction is evicted.
+        cache.record_basic(&tx1, None);
+        cache.record_basic(&tx2, None);
+        assert_eq!(cache.basic_verdict(&tx0), None);
+        assert_eq!(cache.basic_verdict(&tx2), Some(None));
+    }
+
+    /// The verdict of a transaction isn't reused for the same transaction with another signature.
+    #[test]
+    fn test_verified_tx_cache_signature() {
+        let mut cache = VerifiedTxCache::with_limits(10, Duration::from_secs(3600));
+        let tx = tx(0);
+        let mut forged_tx = tx.clone();
+        forged_tx.signature = tx(1).signature;
+        assert_eq!(tx.get_hash(), forged_tx.get_hash());
+
+        cache.record_basic(&tx, None);
+        assert_eq!(cache.basic_verdict(&tx), Some(None));
+        assert_eq!(cache.basic_verdict(&forged_tx), None);
+    }
+
+    #[test]
+    fn test_verified_tx_cache_expiry() {
+        let clock = MockClockGuard::default();
+        let start = chrono::Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        clock.set_time(start);
+        let mut cache = VerifiedTxCache::with_limits(10, Duration::from_secs(60));
+        let tx = tx(0);
+        cache.record_basic(&tx, None);
+
+        clock.set_time(start + chrono::Duration::seconds(60));
+        assert_eq!(cache.basic_verdict(&tx), Some(None));
+        clock.set_time(start + chrono::Duration::seconds(61));
+        assert_eq!(cache.basic_verdict(&tx), None);
+    }
+}
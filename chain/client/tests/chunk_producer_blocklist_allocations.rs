@@ -0,0 +1,72 @@
+//! Allocations of the chunk producer blocklist lookups.
+//!
+//! The allocations are counted by a global allocator, so the test is a target of its own, to not
+//! replace the allocator of the crate's other tests.
+use lru::LruCache;
+use near_client::chunk_producer_blocklist::ChunkProducerBlocklist;
+use near_primitives::hash::hash;
+use near_primitives::types::{AccountId, EpochId};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    static COUNT_ALLOCATIONS: Cell<bool> = const { Cell::new(false) };
+    static NUM_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts the allocations of the current thread while `count_allocations` runs.
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNT_ALLOCATIONS.with(|count| count.get()) {
+            NUM_ALLOCATIONS.with(|num| num.set(num.get() + 1));
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn count_allocations(f: impl FnOnce()) -> usize {
+    NUM_ALLOCATIONS.with(|num| num.set(0));
+    COUNT_ALLOCATIONS.with(|count| count.set(true));
+    f();
+    COUNT_ALLOCATIONS.with(|count| count.set(false));
+    NUM_ALLOCATIONS.with(|num| num.get())
+}
+
+fn epoch_id(epoch_height: u64) -> EpochId {
+    EpochId(hash(&epoch_height.to_le_bytes()))
+}
+
+/// Checking a chunk producer against the blocklist allocates nothing, unlike the lookup by a key
+/// owning clones of the epoch id and the account id.
+#[test]
+fn test_lookup_allocations() {
+    const NUM_LOOKUPS: usize = 1000;
+    let chunk_producer: AccountId = "test0".parse().unwrap();
+    let other: AccountId = "test1".parse().unwrap();
+    let mut blocklist = ChunkProducerBlocklist::default();
+    blocklist.ban(1, epoch_id(1), chunk_producer.clone());
+    let mut cache = LruCache::new(1000);
+    cache.put((epoch_id(1), chunk_producer), ());
+
+    let num_cloned_key_allocations = count_allocations(|| {
+        for _ in 0..NUM_LOOKUPS {
+            assert!(!cache.contains(&(epoch_id(1), other.clone())));
+        }
+    });
+    let num_allocations = count_allocations(|| {
+        for _ in 0..NUM_LOOKUPS {
+            assert!(!blocklist.is_banned(&epoch_id(1), &other));
+        }
+    });
+    assert!(num_cloned_key_allocations >= NUM_LOOKUPS, "{num_cloned_key_allocations}");
+    assert_eq!(num_allocations, 0);
+}
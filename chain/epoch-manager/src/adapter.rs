@@ -1,4 +1,4 @@
-use crate::types::BlockHeaderInfo;
+use crate::types::{BlockHeaderInfo, EpochTransitionStats, ShardAssignmentsView};
 #[cfg(feature = "new_epoch_sync")]
 use crate::EpochInfoAggregator;
 use crate::EpochManagerHandle;
@@ -11,18 +11,20 @@ use near_primitives::epoch_manager::EpochConfig;
 use near_primitives::epoch_manager::ShardConfig;
 use near_primitives::errors::EpochError;
 use near_primitives::hash::CryptoHash;
-use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout, ShardLayoutError};
+use near_primitives::shard_layout::{
+    account_id_to_shard_id, ShardLayout, ShardLayoutDiff, ShardLayoutError,
+};
 use near_primitives::sharding::{ChunkHash, ShardChunkHeader};
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{
     AccountId, ApprovalStake, Balance, BlockHeight, EpochHeight, EpochId, NumShards, ShardId,
-    ValidatorInfoIdentifier,
+    ValidatorInfoIdentifier, ValidatorKickoutReason,
 };
 use near_primitives::version::ProtocolVersion;
 use near_primitives::views::EpochValidatorInfo;
 use near_store::{ShardUId, StoreUpdate};
 use std::cmp::Ordering;
-#[cfg(feature = "new_epoch_sync")]
+#[cfg(any(feature = "new_epoch_sync", feature = "protocol_feature_chunk_validation"))]
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -190,6 +192,30 @@ pub trait EpochManagerAdapter: Send + Sync {
         shard_id: ShardId,
     ) -> Result<AccountId, EpochError>;
 
+    /// Chunk producers of every shard in the given epoch, with their stakes. The view is shared
+    /// between the callers for the same epoch, so it is cheap to hold on to for the whole epoch.
+    fn get_shard_validator_assignments(
+        &self,
+        epoch_id: &EpochId,
+    ) -> Result<Arc<ShardAssignmentsView>, EpochError>;
+
+    /// Shards for which `account_id` is in the chunk producer settlement of the given epoch, i.e.
+    /// the only shards for which `get_chunk_producer` may return `account_id` in that epoch.
+    fn get_chunk_producer_shards(
+        &self,
+        epoch_id: &EpochId,
+        account_id: &AccountId,
+    ) -> Result<Vec<ShardId>, EpochError>;
+
+    /// Chunk validators assigned to each shard at `height`, with the number of mandates each of
+    /// them holds in the shard. Indexed by shard id, empty if the epoch has no validator mandates.
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    fn sample_chunk_validators(
+        &self,
+        epoch_id: &EpochId,
+        height: BlockHeight,
+    ) -> Result<Vec<HashMap<AccountId, u16>>, EpochError>;
+
     fn get_validator_by_account_id(
         &self,
         epoch_id: &EpochId,
@@ -218,6 +244,21 @@ pub trait EpochManagerAdapter: Send + Sync {
         block_header_info: BlockHeaderInfo,
     ) -> Result<StoreUpdate, EpochError>;
 
+    /// How the validators and the chunk producer assignment of the epoch changed from the previous
+    /// epoch.
+    fn get_epoch_transition_stats(
+        &self,
+        epoch_id: &EpochId,
+    ) -> Result<EpochTransitionStats, EpochError>;
+
+    /// Which validators of the epoch would be kicked out at its end, and why, if they kept
+    /// producing at the rates they had up to the given block of the epoch.
+    fn project_kickouts(
+        &self,
+        epoch_id: &EpochId,
+        as_of_block_hash: &CryptoHash,
+    ) -> Result<HashMap<AccountId, Option<ValidatorKickoutReason>>, EpochError>;
+
     /// Amount of tokens minted in given epoch.
     fn get_epoch_minted_amount(&self, epoch_id: &EpochId) -> Result<Balance, EpochError>;
 
@@ -390,6 +431,23 @@ pub trait EpochManagerAdapter: Send + Sync {
 
     fn will_shard_layout_change(&self, parent_hash: &CryptoHash) -> Result<bool, EpochError>;
 
+    /// Returns how the shard layout changes between the epoch of `prev_hash` and the epoch of the
+    /// block after it. None unless that block starts an epoch with a different shard layout.
+    fn shard_layout_diff(
+        &self,
+        prev_hash: &CryptoHash,
+    ) -> Result<Option<ShardLayoutDiff>, EpochError> {
+        if !self.is_next_block_epoch_start(prev_hash)? {
+            return Ok(None);
+        }
+        let old_shard_layout = self.get_shard_layout(&self.get_epoch_id(prev_hash)?)?;
+        let new_shard_layout = self.get_shard_layout_from_prev_block(prev_hash)?;
+        Ok(ShardLayoutDiff::new(old_shard_layout, new_shard_layout))
+    }
+
+    /// Drops the cached epoch and block infos, so that they are read from the store again.
+    fn clear_caches(&self);
+
     /// Returns a vector of all hashes in the epoch ending with `last_block_info`.
     /// Only return blocks on chain of `last_block_info`.
     /// Hashes are returned in the order from the last block to the first block.
@@ -650,6 +708,51 @@ impl EpochManagerAdapter for EpochManagerHandle {
         Ok(epoch_manager.get_chunk_producer_info(epoch_id, height, shard_id)?.take_account_id())
     }
 
+    fn get_shard_validator_assignments(
+        &self,
+        epoch_id: &EpochId,
+    ) -> Result<Arc<ShardAssignmentsView>, EpochError> {
+        let epoch_manager = self.read();
+        epoch_manager.get_shard_validator_assignments(epoch_id)
+    }
+
+    fn get_chunk_producer_shards(
+        &self,
+        epoch_id: &EpochId,
+        account_id: &AccountId,
+    ) -> Result<Vec<ShardId>, EpochError> {
+        let assignments = self.get_shard_validator_assignments(epoch_id)?;
+        Ok(assignments
+            .iter()
+            .filter(|(_, chunk_producers)| {
+                chunk_producers.iter().any(|producer| producer.account_id() == account_id)
+            })
+            .map(|(shard_id, _)| shard_id)
+            .collect())
+    }
+
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    fn sample_chunk_validators(
+        &self,
+        epoch_id: &EpochId,
+        height: BlockHeight,
+    ) -> Result<Vec<HashMap<AccountId, u16>>, EpochError> {
+        let epoch_manager = self.read();
+        let epoch_info = epoch_manager.get_epoch_info(epoch_id)?;
+        Ok(epoch_info
+            .sample_chunk_validators(height)
+            .into_iter()
+            .map(|assignments| {
+                assignments
+                    .into_iter()
+                    .map(|(validator_id, mandates)| {
+                        (epoch_info.get_validator(validator_id).take_account_id(), mandates)
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
     fn get_validator_by_account_id(
         &self,
         epoch_id: &EpochId,
@@ -692,6 +795,23 @@ impl EpochManagerAdapter for EpochManagerHandle {
         epoch_manager.add_validator_proposals(block_header_info)
     }
 
+    fn get_epoch_transition_stats(
+        &self,
+        epoch_id: &EpochId,
+    ) -> Result<EpochTransitionStats, EpochError> {
+        let epoch_manager = self.read();
+        epoch_manager.get_epoch_transition_stats(epoch_id)
+    }
+
+    fn project_kickouts(
+        &self,
+        epoch_id: &EpochId,
+        as_of_block_hash: &CryptoHash,
+    ) -> Result<HashMap<AccountId, Option<ValidatorKickoutReason>>, EpochError> {
+        let epoch_manager = self.read();
+        epoch_manager.project_kickouts(epoch_id, as_of_block_hash)
+    }
+
     fn get_epoch_minted_amount(&self, epoch_id: &EpochId) -> Result<Balance, EpochError> {
         let epoch_manager = self.read();
         Ok(epoch_manager.get_epoch_info(epoch_id)?.minted_amount())
@@ -974,6 +1094,11 @@ impl EpochManagerAdapter for EpochManagerHandle {
         epoch_manager.will_shard_layout_change(parent_hash)
     }
 
+    fn clear_caches(&self) {
+        let epoch_manager = self.read();
+        epoch_manager.clear_caches();
+    }
+
     #[cfg(feature = "new_epoch_sync")]
     fn get_all_epoch_hashes(
         &self,
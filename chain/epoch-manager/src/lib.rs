@@ -1,5 +1,7 @@
 use crate::proposals::proposals_to_epoch_info;
-use crate::types::EpochInfoAggregator;
+use crate::types::{
+    EpochInfoAggregator, EpochTransitionStats, ShardAssignmentsView, SlashDiscounts,
+};
 use near_cache::SyncLruCache;
 use near_chain_configs::GenesisConfig;
 use near_primitives::checked_feature;
@@ -23,7 +25,7 @@ use near_primitives::views::{
     CurrentEpochValidatorInfo, EpochValidatorInfo, NextEpochValidatorInfo, ValidatorKickoutView,
 };
 use near_store::{DBCol, Store, StoreUpdate};
-use num_rational::Rational64;
+use num_rational::{Ratio, Rational64};
 use primitive_types::U256;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap, HashSet};
@@ -133,6 +135,8 @@ pub struct EpochManager {
 
     /// Unique chunk producers.
     epoch_chunk_producers_unique: SyncLruCache<EpochId, Arc<[ValidatorStake]>>,
+    /// Chunk producers of every shard, ordered by `chunk_producers_settlement`.
+    epoch_shard_assignments: SyncLruCache<EpochId, Arc<ShardAssignmentsView>>,
     /// Aggregator that keeps statistics about the current epoch.  It’s data are
     /// synced up to the last final block.  The information are updated by
     /// [`Self::update_epoch_info_aggregator_upto_final`] method.  To get
@@ -234,6 +238,7 @@ impl EpochManager {
             epoch_validators_ordered: SyncLruCache::new(EPOCH_CACHE_SIZE),
             epoch_validators_ordered_unique: SyncLruCache::new(EPOCH_CACHE_SIZE),
             epoch_chunk_producers_unique: SyncLruCache::new(EPOCH_CACHE_SIZE),
+            epoch_shard_assignments: SyncLruCache::new(EPOCH_CACHE_SIZE),
             epoch_info_aggregator,
             #[cfg(test)]
             epoch_info_aggregator_loop_counter: Default::default(),
@@ -254,6 +259,7 @@ impl EpochManager {
                 0,
                 genesis_protocol_version,
                 genesis_protocol_version,
+                &HashMap::new(),
             )?;
             // Dummy block info.
             // Artificial block we add to simplify implementation: dummy block is the
@@ -278,6 +284,38 @@ impl EpochManager {
         EpochManagerHandle { inner }
     }
 
+    /// Sets the fractions of the stake of validators which were slashed, by which the validator
+    /// selection made at the end of `epoch_id` discounts their stake, see
+    /// [`validator_selection::proposals_to_epoch_info_with_slash_discounts`]. Unless set again,
+    /// the discounts carry over to the following epochs. The discounts affect consensus, so all
+    /// nodes must set the same ones for the same epoch.
+    #[cfg(feature = "slash_stake_discount")]
+    pub fn set_slash_discounts(
+        &mut self,
+        epoch_id: &EpochId,
+        slash_discounts: &HashMap<AccountId, Ratio<u64>>,
+    ) -> Result<StoreUpdate, EpochError> {
+        let mut store_update = self.store.store_update();
+        store_update.set_ser(
+            DBCol::EpochSlashDiscounts,
+            epoch_id.as_ref(),
+            &SlashDiscounts::from_ratios(slash_discounts),
+        )?;
+        Ok(store_update)
+    }
+
+    /// Returns the fractions of the stake of slashed validators by which the validator selection
+    /// made at the end of `epoch_id` discounts their stake.
+    pub fn get_slash_discounts(
+        &self,
+        epoch_id: &EpochId,
+    ) -> Result<HashMap<AccountId, Ratio<u64>>, EpochError> {
+        match self.store.get_ser::<SlashDiscounts>(DBCol::EpochSlashDiscounts, epoch_id.as_ref())? {
+            Some(slash_discounts) => slash_discounts.to_ratios(),
+            None => Ok(HashMap::new()),
+        }
+    }
+
     /// Only used in mock node
     /// Copy the necessary epoch info related to `block_hash` from `source_epoch_manager` to
     /// the current epoch manager.
@@ -694,6 +732,20 @@ impl EpochManager {
                 epoch_duration,
             )
         };
+        let slash_discounts = self.get_slash_discounts(block_info.epoch_id())?;
+        if !slash_discounts.is_empty()
+            && self
+                .store
+                .get_ser::<SlashDiscounts>(DBCol::EpochSlashDiscounts, next_epoch_id.as_ref())?
+                .is_none()
+        {
+            // The discounts carry over to the next epoch unless it has its own.
+            store_update.set_ser(
+                DBCol::EpochSlashDiscounts,
+                next_epoch_id.as_ref(),
+                &SlashDiscounts::from_ratios(&slash_discounts),
+            )?;
+        }
         let next_next_epoch_config = self.config.for_protocol_version(next_version);
         let next_next_epoch_info = match proposals_to_epoch_info(
             &next_next_epoch_config,
@@ -705,6 +757,7 @@ impl EpochManager {
             minted_amount,
             next_version,
             epoch_protocol_version,
+            &slash_discounts,
         ) {
             Ok(next_next_epoch_info) => next_next_epoch_info,
             Err(EpochError::ThresholdError { stake_sum, num_seats }) => {
@@ -728,6 +781,13 @@ impl EpochManager {
                next_next_epoch_info.protocol_version(),
                self.config.for_protocol_version(next_next_epoch_info.protocol_version()).shard_layout,
             self.config.for_protocol_version(next_next_epoch_info.protocol_version()));
+        let transition_stats = EpochTransitionStats::new(&next_epoch_info, &next_next_epoch_info);
+        debug!(target: "epoch_manager", next_next_epoch_id = ?next_next_epoch_id, ?transition_stats, "Epoch transition stats");
+        store_update.set_ser(
+            DBCol::EpochTransitionStats,
+            next_next_epoch_id.as_ref(),
+            &transition_stats,
+        )?;
         // This epoch info is computed for the epoch after next (T+2),
         // where epoch_id of it is the hash of last block in this epoch (T).
         self.save_epoch_info(store_update, &next_next_epoch_id, Arc::new(next_next_epoch_info))?;
@@ -916,6 +976,28 @@ impl EpochManager {
         })
     }
 
+    /// Returns the chunk producers of every shard in the given epoch. The view is cached, so the
+    /// callers for the same epoch share it.
+    pub fn get_shard_validator_assignments(
+        &self,
+        epoch_id: &EpochId,
+    ) -> Result<Arc<ShardAssignmentsView>, EpochError> {
+        self.epoch_shard_assignments.get_or_try_put(epoch_id.clone(), |epoch_id| {
+            let epoch_info = self.get_epoch_info(epoch_id)?;
+            let shards = epoch_info
+                .chunk_producers_settlement()
+                .iter()
+                .map(|chunk_producers| {
+                    chunk_producers
+                        .iter()
+                        .map(|producer_id| epoch_info.get_validator(*producer_id))
+                        .collect()
+                })
+                .collect();
+            Ok(Arc::new(ShardAssignmentsView::new(shards)))
+        })
+    }
+
     /// get_heuristic_block_approvers_ordered: block producers for epoch
     /// get_all_block_producers_ordered: block producers for epoch, slashing info
     /// get_all_block_approvers_ordered: block producers for epoch, slashing info, sometimes block producers for next epoch
@@ -1623,6 +1705,89 @@ impl EpochManager {
             .ok_or_else(|| EpochError::EpochOutOfBounds(epoch_id.clone()))
     }
 
+    /// How the validators and the chunk producer assignment of the epoch differ from the ones of
+    /// the previous epoch. Not known for the first two epochs, which aren't computed from
+    /// proposals.
+    pub fn get_epoch_transition_stats(
+        &self,
+        epoch_id: &EpochId,
+    ) -> Result<EpochTransitionStats, EpochError> {
+        self.store
+            .get_ser(DBCol::EpochTransitionStats, epoch_id.as_ref())?
+            .ok_or_else(|| EpochError::EpochOutOfBounds(epoch_id.clone()))
+    }
+
+    /// Projects the kickouts decided at the end of the epoch, assuming the validators keep
+    /// producing blocks and chunks at the rates they had up to `as_of_block_hash`. Every validator
+    /// of the epoch is returned, with the reason it would be kicked out for, if any.
+    pub fn project_kickouts(
+        &self,
+        epoch_id: &EpochId,
+        as_of_block_hash: &CryptoHash,
+    ) -> Result<HashMap<AccountId, Option<ValidatorKickoutReason>>, EpochError> {
+        let block_info = self.get_block_info(as_of_block_hash)?;
+        if block_info.epoch_id() != epoch_id {
+            return Err(EpochError::EpochOutOfBounds(epoch_id.clone()));
+        }
+        let epoch_info = self.get_epoch_info(epoch_id)?;
+        let next_epoch_info = self.get_epoch_info(&self.get_next_epoch_id(as_of_block_hash)?)?;
+        let EpochInfoAggregator { block_tracker, shard_tracker, all_proposals, .. } =
+            self.get_epoch_info_aggregator_upto_last(as_of_block_hash)?;
+        let config = self.config.for_protocol_version(epoch_info.protocol_version());
+
+        let elapsed_heights =
+            block_info.height() - self.get_epoch_start_height(as_of_block_hash)? + 1;
+        let extrapolate = |stats: &ValidatorStats| {
+            if elapsed_heights >= config.epoch_length {
+                return stats.clone();
+            }
+            ValidatorStats {
+                produced: stats.produced * config.epoch_length / elapsed_heights,
+                expected: stats.expected * config.epoch_length / elapsed_heights,
+            }
+        };
+        let block_tracker: HashMap<_, _> =
+            block_tracker.iter().map(|(id, stats)| (*id, extrapolate(stats))).collect();
+        let shard_tracker: HashMap<_, HashMap<_, _>> = shard_tracker
+            .iter()
+            .map(|(shard_id, tracker)| {
+                (*shard_id, tracker.iter().map(|(id, stats)| (*id, extrapolate(stats))).collect())
+            })
+            .collect();
+
+        // Same rules as in `collect_blocks_info`.
+        let slashed_validators = block_info.slashed();
+        let mut kickouts: HashMap<_, _> = slashed_validators
+            .keys()
+            .map(|account_id| (account_id.clone(), ValidatorKickoutReason::Slashed))
+            .collect();
+        for (account_id, proposal) in all_proposals {
+            if !slashed_validators.contains_key(&account_id)
+                && proposal.stake() == 0
+                && *next_epoch_info.stake_change().get(&account_id).unwrap_or(&0) != 0
+            {
+                kickouts.insert(account_id, ValidatorKickoutReason::Unstaked);
+            }
+        }
+        let (performance_kickouts, _) = Self::compute_kickout_info(
+            &config,
+            &epoch_info,
+            &block_tracker,
+            &shard_tracker,
+            slashed_validators,
+            next_epoch_info.validator_kickout(),
+        );
+        kickouts.extend(performance_kickouts);
+        Ok(epoch_info
+            .validators_iter()
+            .map(|validator| {
+                let account_id = validator.take_account_id();
+                let kickout = kickouts.remove(&account_id);
+                (account_id, kickout)
+            })
+            .collect())
+    }
+
     // Note(#6572): beware, after calling `save_epoch_validator_info`,
     // `get_epoch_validator_info` will return stale results.
     fn save_epoch_validator_info(
@@ -1644,6 +1809,18 @@ impl EpochManager {
         }
     }
 
+    /// Drops all the cached epoch and block infos. They are read from the store again when
+    /// needed.
+    pub fn clear_caches(&self) {
+        self.epochs_info.clear();
+        self.blocks_info.clear();
+        self.epoch_id_to_start.clear();
+        self.epoch_validators_ordered.clear();
+        self.epoch_validators_ordered_unique.clear();
+        self.epoch_chunk_producers_unique.clear();
+        self.epoch_shard_assignments.clear();
+    }
+
     /// Get BlockInfo for a block
     /// # Errors
     /// EpochError::IOErr if storage returned an error
@@ -8,6 +8,7 @@ use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{
     AccountId, Balance, NumSeats, ProtocolVersion, ValidatorKickoutReason,
 };
+use num_rational::Ratio;
 
 /// Find threshold of stake per seat, given provided stakes and required number of seats.
 pub(crate) fn find_threshold(
@@ -37,6 +38,7 @@ pub(crate) fn find_threshold(
 }
 
 /// Calculates new seat assignments based on current seat assignments and proposals.
+/// `slash_discounts` are only taken into account by the current validator selection algorithm.
 pub fn proposals_to_epoch_info(
     epoch_config: &EpochConfig,
     rng_seed: RngSeed,
@@ -47,9 +49,10 @@ pub fn proposals_to_epoch_info(
     minted_amount: Balance,
     next_version: ProtocolVersion,
     last_epoch_version: ProtocolVersion,
+    slash_discounts: &HashMap<AccountId, Ratio<u64>>,
 ) -> Result<EpochInfo, EpochError> {
     if checked_feature!("stable", AliasValidatorSelectionAlgorithm, last_epoch_version) {
-        return crate::validator_selection::proposals_to_epoch_info(
+        return crate::validator_selection::proposals_to_epoch_info_with_slash_discounts(
             epoch_config,
             rng_seed,
             prev_epoch_info,
@@ -59,6 +62,7 @@ pub fn proposals_to_epoch_info(
             minted_amount,
             next_version,
             last_epoch_version,
+            slash_discounts,
         );
     } else {
         return old_validator_selection::proposals_to_epoch_info(
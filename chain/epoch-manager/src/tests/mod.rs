@@ -13,7 +13,7 @@ use near_primitives::account::id::AccountIdRef;
 use near_primitives::challenge::SlashedValidator;
 use near_primitives::epoch_manager::EpochConfig;
 use near_primitives::hash::hash;
-use near_primitives::shard_layout::ShardLayout;
+use near_primitives::shard_layout::{ShardLayout, ShardUId};
 use near_primitives::types::ValidatorKickoutReason::{NotEnoughBlocks, NotEnoughChunks};
 use near_primitives::version::ProtocolFeature::SimpleNightshade;
 use near_primitives::version::PROTOCOL_VERSION;
@@ -2190,6 +2190,19 @@ fn test_protocol_version_switch_with_shard_layout_change() {
         assert_eq!(epoch_manager.will_shard_layout_change(&h[i]).unwrap(), true);
     }
     assert_eq!(epoch_manager.will_shard_layout_change(&h[6]).unwrap(), false);
+
+    // The diff is only reported for the last block before the shard layout changes.
+    let epoch_manager = epoch_manager.into_handle();
+    for i in (0..8).filter(|&i| i != 6) {
+        assert_eq!(epoch_manager.shard_layout_diff(&h[i]).unwrap(), None, "{}", i);
+    }
+    let diff = epoch_manager.shard_layout_diff(&h[6]).unwrap().unwrap();
+    assert_eq!(diff.old_shard_layout, ShardLayout::v0_single_shard());
+    assert_eq!(diff.new_shard_layout, ShardLayout::get_simple_nightshade_layout());
+    assert_eq!(
+        diff.children,
+        [(ShardUId::single_shard(), diff.new_shard_uids())].into_iter().collect()
+    );
 }
 
 #[test]
@@ -2424,6 +2437,50 @@ fn test_chunk_producers() {
     );
 }
 
+#[test]
+fn test_shard_validator_assignments() {
+    let validators = (0..6)
+        .map(|i| (format!("test{}", i).parse().unwrap(), 1_000_000))
+        .collect::<Vec<(AccountId, Balance)>>();
+    let mut epoch_manager = setup_default_epoch_manager(validators, 2, 2, 6, 0, 90, 60);
+    let h = hash_range(10);
+    record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+    for i in 1..=4 {
+        record_block(&mut epoch_manager, h[i - 1], h[i], i as u64, vec![]);
+    }
+    let epoch_id = EpochId(h[2]);
+    let epoch_info = epoch_manager.get_epoch_info(&epoch_id).unwrap();
+    let epoch_manager = epoch_manager.into_handle();
+
+    let assignments = epoch_manager.get_shard_validator_assignments(&epoch_id).unwrap();
+    assert_eq!(assignments.num_shards(), 2);
+    for (shard_id, settlement) in epoch_info.chunk_producers_settlement().iter().enumerate() {
+        let expected: Vec<_> = settlement.iter().map(|id| epoch_info.get_validator(*id)).collect();
+        assert_eq!(assignments.shard(shard_id as ShardId), expected.as_slice());
+    }
+    assert!(assignments.shard(2).is_empty());
+
+    // All the producers of a shard have the same stake, so sampling enough heights hits all of
+    // them, and only them.
+    for (shard_id, chunk_producers) in assignments.iter() {
+        let sampled: HashSet<AccountId> = (0..1000)
+            .map(|height| epoch_manager.get_chunk_producer(&epoch_id, height, shard_id).unwrap())
+            .collect();
+        let assigned: HashSet<AccountId> =
+            chunk_producers.iter().map(|producer| producer.account_id().clone()).collect();
+        assert_eq!(sampled, assigned, "shard {}", shard_id);
+        for producer in chunk_producers {
+            let shards =
+                epoch_manager.get_chunk_producer_shards(&epoch_id, producer.account_id()).unwrap();
+            assert!(shards.contains(&shard_id));
+        }
+    }
+
+    // The view is cached, not recomputed or cloned for every caller.
+    let again = epoch_manager.get_shard_validator_assignments(&epoch_id).unwrap();
+    assert!(Arc::ptr_eq(&assignments, &again));
+}
+
 /// A sanity test for the compute_kickout_info function, tests that
 /// the validators that don't meet the block/chunk producer kickout threshold is kicked out
 #[test]
@@ -2653,3 +2710,259 @@ fn test_max_kickout_stake_ratio() {
         ])
     );
 }
+
+/// The transition stats of an epoch compare its validators and their stakes and shards with the
+/// ones of the previous epoch.
+#[test]
+fn test_epoch_transition_stats() {
+    let prev_epoch_info = epoch_info(
+        1,
+        vec![
+            ("test1".parse().unwrap(), 100),
+            ("test2".parse().unwrap(), 100),
+            ("test3".parse().unwrap(), 100),
+        ],
+        vec![0, 1, 2],
+        vec![vec![0, 1], vec![2]],
+        vec![],
+        vec![],
+        BTreeMap::new(),
+        vec![],
+        HashMap::new(),
+        0,
+    );
+    // test1 leaves, test4 joins, test2 increases its stake and test2 and test3 swap shards.
+    let epoch_info = epoch_info(
+        2,
+        vec![
+            ("test2".parse().unwrap(), 150),
+            ("test3".parse().unwrap(), 100),
+            ("test4".parse().unwrap(), 50),
+        ],
+        vec![0, 1, 2],
+        vec![vec![1], vec![0, 2]],
+        vec![],
+        vec![],
+        BTreeMap::new(),
+        vec![],
+        HashMap::new(),
+        0,
+    );
+    let stats = EpochTransitionStats::new(&prev_epoch_info, &epoch_info);
+    assert_eq!(
+        stats,
+        EpochTransitionStats {
+            num_retained_chunk_producers: 2,
+            num_chunk_producers_changed_shards: 2,
+            num_new_validators: 1,
+            num_dropped_validators: 1,
+            stake_churn: 50 + 50 + 100,
+            total_stake: 300 + 300,
+        }
+    );
+    assert_eq!(stats.chunk_producer_shard_change_ratio(), 1.0);
+    assert_eq!(stats.stake_weighted_churn(), 1.0 / 3.0);
+
+    let stats = EpochTransitionStats::new(&epoch_info, &epoch_info);
+    assert_eq!(stats.num_chunk_producers_changed_shards, 0);
+    assert_eq!(stats.stake_weighted_churn(), 0.0);
+}
+
+/// The transition stats are stored when the epoch info is computed from the proposals.
+#[test]
+fn test_epoch_transition_stats_over_epochs() {
+    let amount_staked = 1_000_000;
+    let validators =
+        vec![("test1".parse().unwrap(), amount_staked), ("test2".parse().unwrap(), amount_staked)];
+    let mut epoch_manager = setup_default_epoch_manager(validators, 2, 1, 2, 0, 0, 0);
+
+    let h = hash_range(6);
+    record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+    // test1 unstakes and test3 takes its seat.
+    record_block(
+        &mut epoch_manager,
+        h[0],
+        h[1],
+        1,
+        vec![stake("test1".parse().unwrap(), 0), stake("test3".parse().unwrap(), amount_staked)],
+    );
+    record_block(&mut epoch_manager, h[1], h[2], 2, vec![]);
+    // New epoch starts here.
+    record_block(&mut epoch_manager, h[2], h[3], 3, vec![]);
+    // The genesis validators are used in the first epochs, without proposals.
+    let epoch_id = epoch_manager.get_epoch_id(&h[3]).unwrap();
+    assert_eq!(
+        epoch_manager.get_epoch_transition_stats(&epoch_id),
+        Err(EpochError::EpochOutOfBounds(epoch_id))
+    );
+    let epoch_id = epoch_manager.get_next_epoch_id(&h[3]).unwrap();
+    check_validators(
+        &epoch_manager.get_epoch_info(&epoch_id).unwrap(),
+        &[("test2", amount_staked), ("test3", amount_staked)],
+    );
+    assert_eq!(
+        epoch_manager.get_epoch_transition_stats(&epoch_id).unwrap(),
+        EpochTransitionStats {
+            num_retained_chunk_producers: 1,
+            num_chunk_producers_changed_shards: 0,
+            num_new_validators: 1,
+            num_dropped_validators: 1,
+            stake_churn: 2 * amount_staked,
+            total_stake: 4 * amount_staked,
+        }
+    );
+
+    record_block(&mut epoch_manager, h[3], h[4], 4, vec![]);
+    // New epoch starts here.
+    record_block(&mut epoch_manager, h[4], h[5], 5, vec![]);
+    // Without proposals, the validators of the next epoch stay the same.
+    let epoch_id = epoch_manager.get_next_epoch_id(&h[5]).unwrap();
+    let stats = epoch_manager.get_epoch_transition_stats(&epoch_id).unwrap();
+    assert_eq!(stats.num_retained_chunk_producers, 2);
+    assert_eq!(stats.num_new_validators, 0);
+    assert_eq!(stats.num_dropped_validators, 0);
+    assert_eq!(stats.stake_weighted_churn(), 0.0);
+}
+
+type KickoutKinds = HashMap<AccountId, std::mem::Discriminant<ValidatorKickoutReason>>;
+
+/// Runs the first epoch, in which validators skip the blocks for which `is_offline` returns true,
+/// and returns the kickouts projected at 90% of the epoch and the ones decided at its end.
+fn projected_and_actual_kickouts(
+    epoch_manager: &mut EpochManager,
+    epoch_length: BlockHeight,
+    is_offline: impl Fn(&AccountId, BlockHeight) -> bool,
+) -> (KickoutKinds, KickoutKinds) {
+    let h = hash_range(2 * epoch_length as usize);
+    record_block(epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+    let epoch_id = epoch_manager.get_epoch_id_from_prev_block(&h[0]).unwrap();
+    let mut prev_block = h[0];
+    let mut projected = None;
+    for (height, block_hash) in h.iter().enumerate().skip(1) {
+        let height = height as BlockHeight;
+        if epoch_manager.is_next_block_epoch_start(&prev_block).unwrap() {
+            break;
+        }
+        if projected.is_none() && height * 10 > epoch_length * 9 {
+            projected = Some(epoch_manager.project_kickouts(&epoch_id, &prev_block).unwrap());
+        }
+        let block_producer = epoch_manager.get_block_producer_info(&epoch_id, height).unwrap();
+        if is_offline(block_producer.account_id(), height) {
+            continue;
+        }
+        record_block(epoch_manager, prev_block, *block_hash, height, vec![]);
+        prev_block = *block_hash;
+    }
+    let projected = projected
+        .unwrap()
+        .into_iter()
+        .filter_map(|(account_id, kickout)| Some((account_id, std::mem::discriminant(&kickout?))))
+        .collect();
+    let actual = epoch_manager
+        .get_epoch_validator_info(&epoch_id)
+        .unwrap()
+        .validator_kickout
+        .into_iter()
+        .map(|(account_id, kickout)| (account_id, std::mem::discriminant(&kickout)))
+        .collect();
+    (projected, actual)
+}
+
+#[test]
+fn test_project_kickouts() {
+    let amount_staked = 1_000_000;
+    let validators =
+        vec![("test1".parse().unwrap(), amount_staked), ("test2".parse().unwrap(), amount_staked)];
+    let epoch_length = 20;
+    let not_enough_blocks = std::mem::discriminant(&NotEnoughBlocks { produced: 0, expected: 0 });
+    let scenarios: Vec<(Box<dyn Fn(&AccountId, BlockHeight) -> bool>, KickoutKinds)> = vec![
+        (Box::new(|_, _| false), HashMap::new()),
+        (
+            Box::new(|account_id, _| account_id == "test2"),
+            HashMap::from([("test2".parse().unwrap(), not_enough_blocks)]),
+        ),
+        // test2 misses about half of its blocks.
+        (
+            Box::new(|account_id, height| account_id == "test2" && height % 2 == 0),
+            HashMap::from([("test2".parse().unwrap(), not_enough_blocks)]),
+        ),
+    ];
+    for (is_offline, expected) in scenarios {
+        let mut epoch_manager =
+            setup_default_epoch_manager(validators.clone(), epoch_length, 1, 2, 0, 90, 60);
+        let (projected, actual) =
+            projected_and_actual_kickouts(&mut epoch_manager, epoch_length, is_offline);
+        assert_eq!(actual, expected);
+        assert_eq!(projected, actual);
+    }
+}
+
+/// A validator holding most of the stake isn't kicked out, however poorly it performs, when the
+/// stake that can be kicked out is capped.
+#[test]
+fn test_project_kickouts_max_kickout_stake() {
+    let validators = vec![
+        stake("test1".parse().unwrap(), 3_000_000),
+        stake("test2".parse().unwrap(), 1_000_000),
+    ];
+    let epoch_length = 20;
+    let mut config =
+        epoch_config(epoch_length, 1, 2, 0, 90, 60, 0).for_protocol_version(PROTOCOL_VERSION);
+    config.validator_max_kickout_stake_perc = 50;
+    let mut epoch_manager = EpochManager::new(
+        create_test_store(),
+        AllEpochConfig::new(false, config, "test-chain"),
+        PROTOCOL_VERSION,
+        default_reward_calculator(),
+        validators,
+    )
+    .unwrap();
+    let (projected, actual) =
+        projected_and_actual_kickouts(&mut epoch_manager, epoch_length, |account_id, _| {
+            account_id == "test1"
+        });
+    assert_eq!(actual, HashMap::new());
+    assert_eq!(projected, actual);
+}
+
+/// The slash discounts of an epoch are stored, carried over to the following epochs and applied
+/// by their validator selection, which still records the full stake.
+#[test]
+#[cfg(feature = "slash_stake_discount")]
+fn test_slash_discounts_carried_over() {
+    let test1: AccountId = "test1".parse().unwrap();
+    let validators = vec![(test1.clone(), 2000), ("test2".parse().unwrap(), 1500)];
+    let mut epoch_manager = setup_default_epoch_manager(validators.clone(), 1, 1, 2, 0, 90, 60);
+    let slash_discounts = HashMap::from([(test1.clone(), Ratio::new(1, 2))]);
+    epoch_manager
+        .set_slash_discounts(&EpochId::default(), &slash_discounts)
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    let h = hash_range(5);
+    record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+    for i in 1..h.len() {
+        record_block(&mut epoch_manager, h[i - 1], h[i], i as BlockHeight, vec![]);
+    }
+
+    let epoch_id = epoch_manager.get_epoch_id(&h[4]).unwrap();
+    let epoch_info = epoch_manager.get_epoch_info(&epoch_id).unwrap();
+    // test1 is ranked behind test2, as if it staked 1000, but keeps its full stake.
+    assert_eq!(epoch_info.get_validator_id(&test1), Some(&1));
+    assert_eq!(epoch_info.get_validator(1).stake(), 2000);
+
+    // Another epoch manager on the same store applies the same discounts.
+    let epoch_manager2 = EpochManager::new(
+        epoch_manager.store.clone(),
+        epoch_manager.config.clone(),
+        PROTOCOL_VERSION,
+        epoch_manager.reward_calculator,
+        validators
+            .iter()
+            .map(|(account_id, balance)| stake(account_id.clone(), *balance))
+            .collect(),
+    )
+    .unwrap();
+    assert_eq!(epoch_manager2.get_slash_discounts(&epoch_id).unwrap(), slash_discounts);
+}
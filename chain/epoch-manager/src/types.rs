@@ -3,13 +3,15 @@ use near_primitives::block_header::BlockHeader;
 use near_primitives::challenge::SlashedValidator;
 use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
+use near_primitives::errors::EpochError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{
     AccountId, Balance, BlockHeight, EpochId, ShardId, ValidatorId, ValidatorStats,
 };
 use near_primitives::version::ProtocolVersion;
-use std::collections::{BTreeMap, HashMap};
+use num_rational::Ratio;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use tracing::{debug, debug_span};
 
 use crate::EpochManager;
@@ -273,3 +275,157 @@ impl EpochInfoAggregator {
         }
     }
 }
+
+/// Fractions of the stake of slashed validators by which the validator selection made at the end
+/// of an epoch discounts their stake, as `(numerator, denominator)` pairs. Stored under the id of
+/// the epoch.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Debug, Default, PartialEq, Eq)]
+pub struct SlashDiscounts(pub BTreeMap<AccountId, (u64, u64)>);
+
+impl SlashDiscounts {
+    pub fn from_ratios(slash_discounts: &HashMap<AccountId, Ratio<u64>>) -> Self {
+        Self(
+            slash_discounts
+                .iter()
+                .map(|(account_id, fraction)| {
+                    (account_id.clone(), (*fraction.numer(), *fraction.denom()))
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the discounts, or an error if one of them has a zero denominator.
+    pub fn to_ratios(&self) -> Result<HashMap<AccountId, Ratio<u64>>, EpochError> {
+        self.0
+            .iter()
+            .map(|(account_id, (numer, denom))| {
+                if *denom == 0 {
+                    return Err(EpochError::IOErr(format!(
+                        "slash discount of {} has a zero denominator",
+                        account_id
+                    )));
+                }
+                Ok((account_id.clone(), Ratio::new(*numer, *denom)))
+            })
+            .collect()
+    }
+}
+
+/// How the validators and the shard assignment of the chunk producers change from an epoch to the
+/// next one. Computed when the epoch info of the next epoch is, and stored under its id.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Debug, Default, PartialEq, Eq)]
+pub struct EpochTransitionStats {
+    /// Number of chunk producers of both epochs.
+    pub num_retained_chunk_producers: u64,
+    /// Number of chunk producers of both epochs which aren't assigned the same set of shards.
+    pub num_chunk_producers_changed_shards: u64,
+    /// Number of validators of the next epoch which aren't validators of the previous one.
+    pub num_new_validators: u64,
+    /// Number of validators of the previous epoch which aren't validators of the next one.
+    pub num_dropped_validators: u64,
+    /// Sum over the validators of both epochs of the difference between their stakes in the two
+    /// epochs, where a validator of a single epoch has no stake in the other.
+    pub stake_churn: Balance,
+    /// Total stake of the validators of the previous epoch plus the one of the next epoch.
+    pub total_stake: Balance,
+}
+
+impl EpochTransitionStats {
+    pub fn new(prev_epoch_info: &EpochInfo, epoch_info: &EpochInfo) -> Self {
+        let mut stats = Self::default();
+        let prev_stakes: HashMap<_, _> =
+            prev_epoch_info.validators_iter().map(|v| v.account_and_stake()).collect();
+        let stakes: HashMap<_, _> =
+            epoch_info.validators_iter().map(|v| v.account_and_stake()).collect();
+        for (account_id, stake) in &stakes {
+            match prev_stakes.get(account_id) {
+                Some(prev_stake) => stats.stake_churn += stake.abs_diff(*prev_stake),
+                None => {
+                    stats.num_new_validators += 1;
+                    stats.stake_churn += stake;
+                }
+            }
+        }
+        for (account_id, prev_stake) in &prev_stakes {
+            if !stakes.contains_key(account_id) {
+                stats.num_dropped_validators += 1;
+                stats.stake_churn += prev_stake;
+            }
+        }
+        stats.total_stake = prev_stakes.values().chain(stakes.values()).sum();
+
+        let prev_shards = chunk_producer_shards(prev_epoch_info);
+        for (account_id, shards) in chunk_producer_shards(epoch_info) {
+            if let Some(prev_shards) = prev_shards.get(&account_id) {
+                stats.num_retained_chunk_producers += 1;
+                if prev_shards != &shards {
+                    stats.num_chunk_producers_changed_shards += 1;
+                }
+            }
+        }
+        stats
+    }
+
+    /// Fraction of the chunk producers of both epochs whose shards changed.
+    pub fn chunk_producer_shard_change_ratio(&self) -> f64 {
+        if self.num_retained_chunk_producers == 0 {
+            return 0.0;
+        }
+        self.num_chunk_producers_changed_shards as f64 / self.num_retained_chunk_producers as f64
+    }
+
+    /// Fraction of the stake of both epochs which changed hands, from 0 when the validators and
+    /// their stakes are the same to 1 when no validator stays.
+    pub fn stake_weighted_churn(&self) -> f64 {
+        if self.total_stake == 0 {
+            return 0.0;
+        }
+        self.stake_churn as f64 / self.total_stake as f64
+    }
+}
+
+/// Shards assigned to each chunk producer of the epoch.
+fn chunk_producer_shards(epoch_info: &EpochInfo) -> HashMap<AccountId, BTreeSet<ShardId>> {
+    let mut shards: HashMap<AccountId, BTreeSet<ShardId>> = HashMap::new();
+    for (shard_id, chunk_producers) in epoch_info.chunk_producers_settlement().iter().enumerate() {
+        for validator_id in chunk_producers {
+            shards
+                .entry(epoch_info.validator_account_id(*validator_id).clone())
+                .or_default()
+                .insert(shard_id as ShardId);
+        }
+    }
+    shards
+}
+
+/// Chunk producers assigned to each shard of an epoch, with their stakes.
+///
+/// The producers of a shard are in the order of the chunk producer settlement of the epoch, and
+/// `EpochManagerAdapter::get_chunk_producer` only ever returns one of them for that shard.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShardAssignmentsView {
+    shards: Vec<Vec<ValidatorStake>>,
+}
+
+impl ShardAssignmentsView {
+    pub fn new(shards: Vec<Vec<ValidatorStake>>) -> Self {
+        Self { shards }
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Producers assigned to `shard_id`, empty if the epoch has no such shard.
+    pub fn shard(&self, shard_id: ShardId) -> &[ValidatorStake] {
+        self.shards.get(shard_id as usize).map_or(&[], Vec::as_slice)
+    }
+
+    /// Shard ids with their producers, in the order of the shard ids.
+    pub fn iter(&self) -> impl Iterator<Item = (ShardId, &[ValidatorStake])> {
+        self.shards
+            .iter()
+            .enumerate()
+            .map(|(shard_id, producers)| (shard_id as ShardId, producers.as_slice()))
+    }
+}
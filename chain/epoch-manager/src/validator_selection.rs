@@ -13,6 +13,7 @@ use num_rational::Ratio;
 use std::cmp::{self, Ordering};
 use std::collections::hash_map;
 use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use tracing::debug;
 
 /// Select validators for next epoch and generate epoch info
 pub fn proposals_to_epoch_info(
@@ -20,12 +21,98 @@ pub fn proposals_to_epoch_info(
     rng_seed: RngSeed,
     prev_epoch_info: &EpochInfo,
     proposals: Vec<ValidatorStake>,
-    mut validator_kickout: HashMap<AccountId, ValidatorKickoutReason>,
+    validator_kickout: HashMap<AccountId, ValidatorKickoutReason>,
     validator_reward: HashMap<AccountId, Balance>,
     minted_amount: Balance,
     next_version: ProtocolVersion,
     last_version: ProtocolVersion,
 ) -> Result<EpochInfo, EpochError> {
+    proposals_to_epoch_info_with_slash_discounts(
+        epoch_config,
+        rng_seed,
+        prev_epoch_info,
+        proposals,
+        validator_kickout,
+        validator_reward,
+        minted_amount,
+        next_version,
+        last_version,
+        &HashMap::new(),
+    )
+}
+
+/// Select validators for next epoch and generate epoch info, discounting the stake of the
+/// validators in `slash_discounts` by the recorded fraction of their stake which was slashed.
+///
+/// Only the selection sees the discounted stake: it is used to order the proposals, to compute
+/// the seat price and kickouts and to balance the shards. The epoch info records the full stake
+/// of the validators, as does `stake_change`, so the rewards of the epoch are computed from the
+/// full stake and roll over into it in the next epoch. A slash therefore has to stay in
+/// `slash_discounts` for as long as it should affect the selection; the epoch manager stores the
+/// discounts of each epoch and carries them over to the next one.
+pub fn proposals_to_epoch_info_with_slash_discounts(
+    epoch_config: &EpochConfig,
+    rng_seed: RngSeed,
+    prev_epoch_info: &EpochInfo,
+    proposals: Vec<ValidatorStake>,
+    validator_kickout: HashMap<AccountId, ValidatorKickoutReason>,
+    validator_reward: HashMap<AccountId, Balance>,
+    minted_amount: Balance,
+    next_version: ProtocolVersion,
+    last_version: ProtocolVersion,
+    slash_discounts: &HashMap<AccountId, Ratio<u64>>,
+) -> Result<EpochInfo, EpochError> {
+    let (epoch_info, debug_info) = proposals_to_epoch_info_with_debug_info(
+        epoch_config,
+        rng_seed,
+        prev_epoch_info,
+        proposals,
+        validator_kickout,
+        validator_reward,
+        minted_amount,
+        next_version,
+        last_version,
+        slash_discounts,
+    )?;
+    for reproposal in &debug_info.reproposals {
+        debug!(target: "epoch_manager", ?reproposal, "Resolved proposal of kicked out validator");
+    }
+    Ok(epoch_info)
+}
+
+/// Decisions taken by the validator selection which aren't recorded in the epoch info.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ValidatorSelectionDebugInfo {
+    /// Accounts which were kicked out and made a new proposal in the same epoch, in the order of
+    /// the proposals.
+    pub reproposals: Vec<ReproposalResolution>,
+}
+
+/// How a proposal of an account which is also being kicked out was resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReproposalResolution {
+    pub account_id: AccountId,
+    pub kickout: ValidatorKickoutReason,
+    pub stake: Balance,
+    /// Whether the proposal overrode the kickout. If it did the kickout is dropped, otherwise the
+    /// proposal is.
+    pub proposal_accepted: bool,
+}
+
+/// Same as [`proposals_to_epoch_info_with_slash_discounts`], but also returns how the proposals
+/// of kicked out accounts were resolved.
+pub fn proposals_to_epoch_info_with_debug_info(
+    epoch_config: &EpochConfig,
+    rng_seed: RngSeed,
+    prev_epoch_info: &EpochInfo,
+    proposals: Vec<ValidatorStake>,
+    mut validator_kickout: HashMap<AccountId, ValidatorKickoutReason>,
+    validator_reward: HashMap<AccountId, Balance>,
+    minted_amount: Balance,
+    next_version: ProtocolVersion,
+    last_version: ProtocolVersion,
+    slash_discounts: &HashMap<AccountId, Ratio<u64>>,
+) -> Result<(EpochInfo, ValidatorSelectionDebugInfo), EpochError> {
     debug_assert!(
         proposals.iter().map(|stake| stake.account_id()).collect::<HashSet<_>>().len()
             == proposals.len(),
@@ -40,14 +127,18 @@ pub fn proposals_to_epoch_info(
     let max_bp_selected = epoch_config.num_block_producer_seats as usize;
     let mut stake_change = BTreeMap::new();
     let mut fishermen = vec![];
-    let proposals = proposals_with_rollover(
+    let mut debug_info = ValidatorSelectionDebugInfo::default();
+    let mut proposals = proposals_with_rollover(
         proposals,
         prev_epoch_info,
         &validator_reward,
-        &validator_kickout,
+        &mut validator_kickout,
         &mut stake_change,
         &mut fishermen,
+        &mut debug_info.reproposals,
+        next_version,
     );
+    let undiscounted_stakes = apply_slash_discounts(&mut proposals, slash_discounts);
     let mut block_producer_proposals = order_proposals(proposals.values().cloned());
     let (block_producers, bp_stake_threshold) = select_block_producers(
         &mut block_producer_proposals,
@@ -76,8 +167,10 @@ pub fn proposals_to_epoch_info(
     // is the smaller of the two thresholds
     let threshold = cmp::min(bp_stake_threshold, cp_stake_threshold);
 
-    // process remaining chunk_producer_proposals that were not selected for either role
-    for OrderedValidatorStake(p) in chunk_producer_proposals {
+    // process remaining chunk_producer_proposals that were not selected for either role, highest
+    // stake first; iterating the heap directly would make the order of the fishermen depend on the
+    // order in which the proposals were inserted
+    for OrderedValidatorStake(p) in chunk_producer_proposals.into_sorted_vec().into_iter().rev() {
         let stake = p.stake();
         let account_id = p.account_id();
         if stake >= epoch_config.fishermen_threshold {
@@ -192,13 +285,20 @@ pub fn proposals_to_epoch_info(
         ValidatorMandates::new(validator_mandates_config, &all_validators)
     };
 
+    // The discounted stakes were only needed for the selection.
+    for validator in all_validators.iter_mut().chain(fishermen.iter_mut()) {
+        if let Some(stake) = undiscounted_stakes.get(validator.account_id()) {
+            *validator.stake_mut() = *stake;
+        }
+    }
+
     let fishermen_to_index = fishermen
         .iter()
         .enumerate()
         .map(|(index, s)| (s.account_id().clone(), index as ValidatorId))
         .collect::<HashMap<_, _>>();
 
-    Ok(EpochInfo::new(
+    let epoch_info = EpochInfo::new(
         prev_epoch_info.epoch_height() + 1,
         all_validators,
         validator_to_index,
@@ -216,7 +316,8 @@ pub fn proposals_to_epoch_info(
         rng_seed,
         #[cfg(feature = "protocol_feature_chunk_validation")]
         validator_mandates,
-    ))
+    );
+    Ok((epoch_info, debug_info))
 }
 
 /// Generates proposals based on new proposals, last epoch validators/fishermen and validator
@@ -224,26 +325,55 @@ pub fn proposals_to_epoch_info(
 /// For each account that was validator or fisherman in last epoch or made stake action last epoch
 /// we apply the following in the order of priority
 /// 1. If account is in validator_kickout it cannot be validator or fisherman for the next epoch,
-///        we will not include it in proposals or fishermen
+///        we will not include it in proposals or fishermen. With `RestakeAfterKickout`, a
+///        proposal with non-zero stake overrides an `Unstaked` or `NotEnoughStake` kickout
+///        instead: the kickout is removed and the account is handled as in 2. Other kickouts,
+///        slashing in particular, always win over a proposal. Every conflict between a proposal
+///        and a kickout is recorded in `reproposals`.
 /// 2. If account made staking action last epoch, it will be included in proposals with stake
 ///        adjusted by rewards from last epoch, if any
 /// 3. If account was validator last epoch, it will be included in proposals with the same stake
 ///        as last epoch, adjusted by rewards from last epoch, if any
 /// 4. If account was fisherman last epoch, it is included in fishermen
+///
+/// The proposals are keyed by account id in a `BTreeMap`, so that nothing derived from them can
+/// depend on the order of a `HashMap`.
 fn proposals_with_rollover(
     proposals: Vec<ValidatorStake>,
     prev_epoch_info: &EpochInfo,
     validator_reward: &HashMap<AccountId, Balance>,
-    validator_kickout: &HashMap<AccountId, ValidatorKickoutReason>,
+    validator_kickout: &mut HashMap<AccountId, ValidatorKickoutReason>,
     stake_change: &mut BTreeMap<AccountId, Balance>,
     fishermen: &mut Vec<ValidatorStake>,
-) -> HashMap<AccountId, ValidatorStake> {
-    let mut proposals_by_account = HashMap::new();
+    reproposals: &mut Vec<ReproposalResolution>,
+    next_version: ProtocolVersion,
+) -> BTreeMap<AccountId, ValidatorStake> {
+    let restake_overrides_kickout = checked_feature!("stable", RestakeAfterKickout, next_version);
+    let mut proposals_by_account = BTreeMap::new();
     for p in proposals {
         let account_id = p.account_id();
-        if validator_kickout.contains_key(account_id) {
-            let account_id = p.take_account_id();
-            stake_change.insert(account_id, 0);
+        if let Some(kickout) = validator_kickout.get(account_id) {
+            let proposal_accepted = restake_overrides_kickout
+                && p.stake() > 0
+                && matches!(
+                    kickout,
+                    ValidatorKickoutReason::Unstaked
+                        | ValidatorKickoutReason::NotEnoughStake { .. }
+                );
+            reproposals.push(ReproposalResolution {
+                account_id: account_id.clone(),
+                kickout: kickout.clone(),
+                stake: p.stake(),
+                proposal_accepted,
+            });
+            if proposal_accepted {
+                validator_kickout.remove(account_id);
+                stake_change.insert(account_id.clone(), p.stake());
+                proposals_by_account.insert(account_id.clone(), p);
+            } else {
+                let account_id = p.take_account_id();
+                stake_change.insert(account_id, 0);
+            }
         } else {
             stake_change.insert(account_id.clone(), p.stake());
             proposals_by_account.insert(account_id.clone(), p);
@@ -280,6 +410,29 @@ fn proposals_with_rollover(
     proposals_by_account
 }
 
+/// Reduces the stake of the proposals by the fraction of it which was slashed. Returns the stakes
+/// of the discounted proposals before the discount.
+fn apply_slash_discounts(
+    proposals: &mut BTreeMap<AccountId, ValidatorStake>,
+    slash_discounts: &HashMap<AccountId, Ratio<u64>>,
+) -> HashMap<AccountId, Balance> {
+    let mut undiscounted_stakes = HashMap::new();
+    for (account_id, slashed_fraction) in slash_discounts {
+        if let Some(proposal) = proposals.get_mut(account_id) {
+            undiscounted_stakes.insert(account_id.clone(), proposal.stake());
+            *proposal.stake_mut() = discounted_stake(proposal.stake(), *slashed_fraction);
+        }
+    }
+    undiscounted_stakes
+}
+
+fn discounted_stake(stake: Balance, slashed_fraction: Ratio<u64>) -> Balance {
+    let denom = *slashed_fraction.denom() as u128;
+    let kept = denom.saturating_sub(*slashed_fraction.numer() as u128);
+    // Split the multiplication so that it can't overflow.
+    stake / denom * kept + stake % denom * kept / denom
+}
+
 fn order_proposals<I: IntoIterator<Item = ValidatorStake>>(
     proposals: I,
 ) -> BinaryHeap<OrderedValidatorStake> {
@@ -389,9 +542,10 @@ mod tests {
     use near_primitives::account::id::AccountIdRef;
     use near_primitives::epoch_manager::epoch_info::{EpochInfo, EpochInfoV3};
     use near_primitives::epoch_manager::ValidatorSelectionConfig;
+    use near_primitives::hash::CryptoHash;
     use near_primitives::shard_layout::ShardLayout;
-    use near_primitives::types::validator_stake::ValidatorStake;
-    use near_primitives::version::PROTOCOL_VERSION;
+    use near_primitives::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
+    use near_primitives::version::{ProtocolFeature, PROTOCOL_VERSION};
     use num_rational::Ratio;
 
     #[test]
@@ -832,6 +986,70 @@ mod tests {
         assert_eq!(epoch_info.get_validator_id(&"test1".parse().unwrap()), None);
     }
 
+    #[test]
+    fn test_reproposal_of_kicked_out_validator() {
+        let epoch_config = create_epoch_config(1, 100, 0, Default::default());
+        let prev_epoch_info =
+            create_prev_epoch_info(7, &[("test1", 10_000), ("test2", 2000), ("test3", 3000)], &[]);
+        let test1: AccountId = "test1".parse().unwrap();
+        let restake_version = ProtocolFeature::RestakeAfterKickout.protocol_version();
+        let kickouts = [
+            (ValidatorKickoutReason::Slashed, false),
+            (ValidatorKickoutReason::NotEnoughBlocks { produced: 1, expected: 10 }, false),
+            (ValidatorKickoutReason::NotEnoughChunks { produced: 1, expected: 10 }, false),
+            (ValidatorKickoutReason::DidNotGetASeat, false),
+            (ValidatorKickoutReason::Unstaked, true),
+            (ValidatorKickoutReason::NotEnoughStake { stake: 10, threshold: 1000 }, true),
+        ];
+        for (kickout, overridable) in kickouts {
+            for (stake, next_version) in
+                [(4000, restake_version), (4000, restake_version - 1), (0, restake_version)]
+            {
+                let (epoch_info, debug_info) = proposals_to_epoch_info_with_debug_info(
+                    &epoch_config,
+                    [0; 32],
+                    &prev_epoch_info,
+                    create_proposals(&[("test1", stake)]),
+                    HashMap::from([(test1.clone(), kickout.clone())]),
+                    Default::default(),
+                    0,
+                    next_version,
+                    PROTOCOL_VERSION,
+                    &HashMap::new(),
+                )
+                .unwrap();
+
+                let accepted = overridable && stake > 0 && next_version == restake_version;
+                let context = format!("{kickout:?} stake={stake} version={next_version}");
+                assert_eq!(
+                    debug_info.reproposals,
+                    vec![ReproposalResolution {
+                        account_id: test1.clone(),
+                        kickout: kickout.clone(),
+                        stake,
+                        proposal_accepted: accepted,
+                    }],
+                    "{context}"
+                );
+                assert_eq!(epoch_info.get_validator_id(&test1).is_some(), accepted, "{context}");
+                assert_eq!(
+                    epoch_info.validator_kickout().contains_key(&test1),
+                    !accepted,
+                    "{context}"
+                );
+                let expected_stake_change = if accepted { stake } else { 0 };
+                assert_eq!(
+                    epoch_info.stake_change().get(&test1),
+                    Some(&expected_stake_change),
+                    "{context}"
+                );
+                if accepted {
+                    assert_eq!(epoch_info.get_validator_by_account(&test1).unwrap().stake(), stake);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_validator_assignment_with_rewards() {
         // validator balances are updated based on their rewards
@@ -865,6 +1083,229 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_validator_assignment_with_slash_discount() {
+        // test1 has the largest stake, but half of it was slashed, so it is selected as if it
+        // staked 1500, while keeping its full stake locked.
+        let epoch_config = create_epoch_config(2, 100, 0, Default::default());
+        let prev_epoch_info = create_prev_epoch_info(7, &["test1", "test2"], &[]);
+        let stakes = [("test1", 3000), ("test2", 2000), ("test3", 1600), ("test4", 1000)];
+        let test1: AccountId = "test1".parse().unwrap();
+        let slash_discounts = HashMap::from([(test1.clone(), Ratio::new(1, 2))]);
+        let epoch_info = proposals_to_epoch_info_with_slash_discounts(
+            &epoch_config,
+            [0; 32],
+            &prev_epoch_info,
+            create_proposals(&stakes),
+            Default::default(),
+            Default::default(),
+            0,
+            PROTOCOL_VERSION,
+            PROTOCOL_VERSION,
+            &slash_discounts,
+        )
+        .unwrap();
+
+        let mut discounted_stakes = stakes;
+        discounted_stakes[0].1 = 1500;
+        let expected_epoch_info = proposals_to_epoch_info(
+            &epoch_config,
+            [0; 32],
+            &prev_epoch_info,
+            create_proposals(&discounted_stakes),
+            Default::default(),
+            Default::default(),
+            0,
+            PROTOCOL_VERSION,
+            PROTOCOL_VERSION,
+        )
+        .unwrap();
+
+        // test1 is ranked third, behind test2 and test3.
+        assert_eq!(epoch_info.get_validator_id(&test1), Some(&2));
+        let account_ids = |epoch_info: &EpochInfo| {
+            epoch_info.validators_iter().map(|v| v.take_account_id()).collect::<Vec<_>>()
+        };
+        assert_eq!(account_ids(&epoch_info), account_ids(&expected_epoch_info));
+        assert_eq!(
+            epoch_info.block_producers_settlement(),
+            expected_epoch_info.block_producers_settlement()
+        );
+        // The shards are balanced according to the discounted stake.
+        assert_eq!(
+            epoch_info.chunk_producers_settlement(),
+            expected_epoch_info.chunk_producers_settlement()
+        );
+        assert_eq!(epoch_info.seat_price(), expected_epoch_info.seat_price());
+
+        // The full stake stays locked and recorded.
+        assert_eq!(epoch_info.stake_change().get(&test1), Some(&3000));
+        assert_eq!(epoch_info.get_validator_by_account(&test1).unwrap().stake(), 3000);
+    }
+
+    #[test]
+    fn test_discounted_stake() {
+        assert_eq!(discounted_stake(3000, Ratio::new(1, 2)), 1500);
+        assert_eq!(discounted_stake(1000, Ratio::new(0, 1)), 1000);
+        assert_eq!(discounted_stake(1000, Ratio::new(3, 2)), 0);
+        assert_eq!(discounted_stake(u128::MAX, Ratio::new(1, 2)), u128::MAX / 2);
+    }
+
+    /// Canned input of the selection and the epoch info expected from it. The expectations are
+    /// part of the protocol: nodes must agree on them, so they may only change together with a
+    /// protocol version.
+    struct GoldenSelection {
+        epoch_config: EpochConfig,
+        prev_epoch_info: EpochInfo,
+        proposals: Vec<ValidatorStake>,
+        kickout: Vec<(&'static str, ValidatorKickoutReason)>,
+        rewards: Vec<(&'static str, Balance)>,
+        validators: Vec<(&'static str, Balance)>,
+        block_producers_settlement: Vec<ValidatorId>,
+        chunk_producers_settlement: Vec<Vec<ValidatorId>>,
+        fishermen: Vec<(&'static str, Balance)>,
+        seat_price: Balance,
+    }
+
+    impl GoldenSelection {
+        fn select(&self, proposals: Vec<ValidatorStake>) -> EpochInfo {
+            proposals_to_epoch_info(
+                &self.epoch_config,
+                [0; 32],
+                &self.prev_epoch_info,
+                proposals,
+                self.kickout.iter().map(|(a, r)| (a.parse().unwrap(), r.clone())).collect(),
+                self.rewards.iter().map(|(a, r)| (a.parse().unwrap(), *r)).collect(),
+                0,
+                PROTOCOL_VERSION,
+                PROTOCOL_VERSION,
+            )
+            .unwrap()
+        }
+    }
+
+    fn golden_selections() -> Vec<GoldenSelection> {
+        vec![
+            // Chunk-only producers, ties in stake, and fishermen.
+            GoldenSelection {
+                epoch_config: create_epoch_config(
+                    2,
+                    2,
+                    10,
+                    ValidatorSelectionConfig {
+                        num_chunk_only_producer_seats: 2,
+                        minimum_validators_per_shard: 1,
+                        minimum_stake_ratio: Ratio::new(160, 1_000_000),
+                    },
+                ),
+                prev_epoch_info: create_prev_epoch_info(7, &["alice", "bob"], &[]),
+                proposals: create_proposals(&[
+                    ("alice", 1000),
+                    ("bob", 900),
+                    ("carol", 500),
+                    ("dave", 500),
+                    ("erin", 20),
+                    ("eve", 20),
+                    ("frank", 15),
+                    ("grace", 5),
+                ]),
+                kickout: vec![],
+                rewards: vec![],
+                validators: vec![("alice", 1000), ("bob", 900), ("dave", 500), ("carol", 500)],
+                block_producers_settlement: vec![0, 1],
+                chunk_producers_settlement: vec![vec![0, 2], vec![1, 3]],
+                fishermen: vec![("erin", 20), ("eve", 20), ("frank", 15)],
+                seat_price: 501,
+            },
+            // Rollover of the previous validators and fishermen, with kickouts and rewards.
+            GoldenSelection {
+                epoch_config: create_epoch_config(
+                    1,
+                    2,
+                    100,
+                    ValidatorSelectionConfig {
+                        num_chunk_only_producer_seats: 1,
+                        minimum_validators_per_shard: 1,
+                        minimum_stake_ratio: Ratio::new(160, 1_000_000),
+                    },
+                ),
+                prev_epoch_info: create_prev_epoch_info(
+                    7,
+                    &[("xavier", 300), ("yara", 200), ("zoe", 100)],
+                    &[("ursula", 150)],
+                ),
+                proposals: create_proposals(&[("walter", 250), ("yara", 50)]),
+                kickout: vec![("zoe", ValidatorKickoutReason::Unstaked)],
+                rewards: vec![("xavier", 30)],
+                validators: vec![("xavier", 330), ("walter", 250), ("yara", 50)],
+                block_producers_settlement: vec![0, 1],
+                chunk_producers_settlement: vec![vec![0, 1, 2]],
+                fishermen: vec![("ursula", 150)],
+                seat_price: 51,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_golden_validator_selection() {
+        fn stakes(iter: ValidatorStakeIter) -> Vec<(String, Balance)> {
+            iter.map(|v| (v.account_id().to_string(), v.stake())).collect()
+        }
+        fn expected_stakes(stakes: &[(&str, Balance)]) -> Vec<(String, Balance)> {
+            stakes.iter().map(|(a, s)| (a.to_string(), *s)).collect()
+        }
+        for (i, golden) in golden_selections().into_iter().enumerate() {
+            let epoch_info = golden.select(golden.proposals.clone());
+            assert_eq!(
+                stakes(epoch_info.validators_iter()),
+                expected_stakes(&golden.validators),
+                "case {i}"
+            );
+            for (id, v) in epoch_info.validators_iter().enumerate() {
+                assert_eq!(epoch_info.get_validator_id(v.account_id()), Some(&(id as u64)));
+            }
+            assert_eq!(
+                epoch_info.block_producers_settlement(),
+                golden.block_producers_settlement,
+                "case {i}"
+            );
+            assert_eq!(
+                epoch_info.chunk_producers_settlement(),
+                golden.chunk_producers_settlement,
+                "case {i}"
+            );
+            assert_eq!(
+                stakes(epoch_info.fishermen_iter()),
+                expected_stakes(&golden.fishermen),
+                "case {i}"
+            );
+            assert_eq!(epoch_info.seat_price(), golden.seat_price, "case {i}");
+        }
+    }
+
+    #[test]
+    fn test_validator_selection_independent_of_proposal_order() {
+        for (i, golden) in golden_selections().into_iter().enumerate() {
+            let expected = CryptoHash::hash_borsh(golden.select(golden.proposals.clone()));
+            let n = golden.proposals.len();
+            for rotation in 0..n {
+                for reverse in [false, true] {
+                    let mut proposals = golden.proposals.clone();
+                    proposals.rotate_left(rotation);
+                    if reverse {
+                        proposals.reverse();
+                    }
+                    let epoch_info = golden.select(proposals);
+                    assert_eq!(
+                        CryptoHash::hash_borsh(epoch_info),
+                        expected,
+                        "case {i}, rotation {rotation}, reverse {reverse}"
+                    );
+                }
+            }
+        }
+    }
+
     fn stake_sum<'a, I: IntoIterator<Item = &'a u64>>(
         epoch_info: &EpochInfo,
         validator_ids: I,
@@ -1,12 +1,14 @@
 #[cfg(feature = "debug_types")]
 use near_client_primitives::debug::{
-    DebugBlockStatusData, EpochInfoView, TrackedShardsView, ValidatorStatus,
+    BanHistoryEntry, ClientConfigState, DebugBlockStatusData, EpochInfoView, EpochProductionReport,
+    ReshardingLogEntry, SimulatedBlockProduction, SyncStatusDebugView, TrackedShardsView,
+    ValidatorStatus,
 };
 #[cfg(feature = "debug_types")]
 use near_primitives::views::{
     CatchupStatusView, ChainProcessingInfo, NetworkGraphView, NetworkRoutesView, PeerStoreView,
     RecentOutboundConnectionsView, RequestedStatePartsView, SnapshotHostsView,
-    SplitStorageInfoView, SyncStatusView,
+    SplitStorageInfoView,
 };
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -18,7 +20,7 @@ pub struct RpcStatusResponse {
 #[cfg(feature = "debug_types")]
 #[derive(serde::Serialize, Debug)]
 pub enum DebugStatusResponse {
-    SyncStatus(SyncStatusView),
+    SyncStatus(SyncStatusDebugView),
     CatchupStatus(Vec<CatchupStatusView>),
     TrackedShards(TrackedShardsView),
     // List of epochs - in descending order (next epoch is first).
@@ -36,11 +38,19 @@ pub enum DebugStatusResponse {
     Routes(NetworkRoutesView),
     SnapshotHosts(SnapshotHostsView),
     SplitStoreStatus(SplitStorageInfoView),
+    BanHistory(Vec<BanHistoryEntry>),
+    ProductionReport(EpochProductionReport),
+    SimulatedBlockProduction(SimulatedBlockProduction),
+    MetricsSnapshot(std::collections::HashMap<String, f64>),
+    ReshardingLog(Vec<ReshardingLogEntry>),
+    ClientConfigState(ClientConfigState),
 }
 
 #[cfg(feature = "debug_types")]
 #[derive(Debug, serde::Serialize)]
 pub struct RpcDebugStatusResponse {
+    /// Version of the encoding of `status_response`, see `near_client_primitives::debug`.
+    pub version: u32,
     pub status_response: DebugStatusResponse,
 }
 
@@ -56,6 +66,21 @@ pub enum RpcStatusError {
     NoNewBlocks { elapsed: std::time::Duration },
     #[error("Epoch Out Of Bounds {epoch_id:?}")]
     EpochOutOfBounds { epoch_id: near_primitives::types::EpochId },
+    #[error("Network protocol version {network} is newer than node protocol version {ours}")]
+    IncompatibleProtocolVersion {
+        network: near_primitives::version::ProtocolVersion,
+        ours: near_primitives::version::ProtocolVersion,
+    },
+    #[error("Most peers are on chain {peers_chain_id} with genesis {peers_genesis_hash}, while this node is on chain {chain_id} with genesis {genesis_hash}. Peers on a different chain: {mismatched_peers:?}")]
+    GenesisMismatch {
+        chain_id: String,
+        genesis_hash: near_primitives::hash::CryptoHash,
+        /// Genesis advertised by most of the peers on a different chain.
+        peers_chain_id: String,
+        peers_genesis_hash: near_primitives::hash::CryptoHash,
+        /// All the highest height peers which advertise a genesis different from ours.
+        mismatched_peers: Vec<near_primitives::network::PeerId>,
+    },
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
     InternalError { error_message: String },
 }
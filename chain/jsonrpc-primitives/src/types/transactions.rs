@@ -41,6 +41,12 @@ pub enum RpcTransactionError {
     },
     #[error("Node doesn't track this shard. Cannot determine whether the transaction is valid")]
     DoesNotTrackShard,
+    #[error("Node hasn't caught up with the state of the shard yet")]
+    NotCaughtUp,
+    #[error("Transaction was rejected by the transaction admission policy of the node: {reason}")]
+    RejectedByPolicy { reason: String },
+    #[error("Node received more transactions than it accepts. Try again later")]
+    Throttled,
     #[error("Transaction with hash {transaction_hash} was routed")]
     RequestRouted { transaction_hash: near_primitives::hash::CryptoHash },
     #[error("Transaction {requested_transaction_hash} doesn't exist")]
@@ -49,6 +49,26 @@ impl RpcFrom<near_client_primitives::debug::DebugStatusResponse>
                     x,
                 )
             }
+            near_client_primitives::debug::DebugStatusResponse::BanHistory(x) => {
+                near_jsonrpc_primitives::types::status::DebugStatusResponse::BanHistory(x)
+            }
+            near_client_primitives::debug::DebugStatusResponse::ProductionReport(x) => {
+                near_jsonrpc_primitives::types::status::DebugStatusResponse::ProductionReport(x)
+            }
+            near_client_primitives::debug::DebugStatusResponse::SimulatedBlockProduction(x) => {
+                near_jsonrpc_primitives::types::status::DebugStatusResponse::SimulatedBlockProduction(
+                    x,
+                )
+            }
+            near_client_primitives::debug::DebugStatusResponse::MetricsSnapshot(x) => {
+                near_jsonrpc_primitives::types::status::DebugStatusResponse::MetricsSnapshot(x)
+            }
+            near_client_primitives::debug::DebugStatusResponse::ReshardingLog(x) => {
+                near_jsonrpc_primitives::types::status::DebugStatusResponse::ReshardingLog(x)
+            }
+            near_client_primitives::debug::DebugStatusResponse::ClientConfigState(x) => {
+                near_jsonrpc_primitives::types::status::DebugStatusResponse::ClientConfigState(x)
+            }
         }
     }
 }
@@ -90,6 +110,22 @@ impl RpcFrom<StatusError> for RpcStatusError {
             StatusError::NodeIsSyncing => Self::NodeIsSyncing,
             StatusError::NoNewBlocks { elapsed } => Self::NoNewBlocks { elapsed },
             StatusError::EpochOutOfBounds { epoch_id } => Self::EpochOutOfBounds { epoch_id },
+            StatusError::IncompatibleProtocolVersion { network, ours } => {
+                Self::IncompatibleProtocolVersion { network, ours }
+            }
+            StatusError::GenesisMismatch {
+                chain_id,
+                genesis_hash,
+                peers_chain_id,
+                peers_genesis_hash,
+                mismatched_peers,
+            } => Self::GenesisMismatch {
+                chain_id,
+                genesis_hash,
+                peers_chain_id,
+                peers_genesis_hash,
+                mismatched_peers,
+            },
             StatusError::Unreachable { ref error_message } => {
                 tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", error_message);
                 crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
@@ -17,6 +17,7 @@ use near_client::{
     GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered, ProcessTxRequest,
     ProcessTxResponse, Query, Status, TxStatus, ViewClientActor,
 };
+use near_client_primitives::debug::DEBUG_VIEWS_VERSION;
 use near_client_primitives::types::GetSplitStorageInfo;
 pub use near_jsonrpc_client as client;
 use near_jsonrpc_primitives::errors::RpcError;
@@ -153,7 +154,12 @@ impl near_jsonrpc_primitives::types::transactions::RpcTransactionError {
             ProcessTxResponse::DoesNotTrackShard | ProcessTxResponse::RequestRouted => {
                 Self::DoesNotTrackShard
             }
-            internal_error => Self::InternalError { debug_info: format!("{:?}", internal_error) },
+            ProcessTxResponse::NotCaughtUp => Self::NotCaughtUp,
+            ProcessTxResponse::RejectedByPolicy(reason) => Self::RejectedByPolicy { reason },
+            ProcessTxResponse::Throttled => Self::Throttled,
+            internal_error @ ProcessTxResponse::ValidTx => {
+                Self::InternalError { debug_info: format!("{:?}", internal_error) }
+            }
         }
     }
 }
@@ -751,6 +757,24 @@ impl JsonRpcHandler {
                     "/debug/api/requested_state_parts" => {
                         self.client_send(DebugStatus::RequestedStateParts).await?.rpc_into()
                     }
+                    "/debug/api/ban_history" => {
+                        self.client_send(DebugStatus::BanHistory(None)).await?.rpc_into()
+                    }
+                    "/debug/api/production_report" => {
+                        self.client_send(DebugStatus::ProductionReport).await?.rpc_into()
+                    }
+                    "/debug/api/simulate_block_production" => {
+                        self.client_send(DebugStatus::SimulatedBlockProduction).await?.rpc_into()
+                    }
+                    "/debug/api/metrics_snapshot" => {
+                        self.client_send(DebugStatus::MetricsSnapshot).await?.rpc_into()
+                    }
+                    "/debug/api/resharding_log" => {
+                        self.client_send(DebugStatus::ReshardingLog).await?.rpc_into()
+                    }
+                    "/debug/api/client_config" => {
+                        self.client_send(DebugStatus::ClientConfigState).await?.rpc_into()
+                    }
                     "/debug/api/peer_store" => self
                         .peer_manager_send(near_network::debug::GetDebugStatus::PeerStore)
                         .await?
@@ -783,6 +807,7 @@ impl JsonRpcHandler {
                     _ => return Ok(None),
                 };
             Ok(Some(near_jsonrpc_primitives::types::status::RpcDebugStatusResponse {
+                version: DEBUG_VIEWS_VERSION,
                 status_response: debug_status,
             }))
         } else {
@@ -801,6 +826,29 @@ impl JsonRpcHandler {
             let debug_status =
                 self.client_send(DebugStatus::BlockStatus(starting_height)).await?.rpc_into();
             Ok(Some(near_jsonrpc_primitives::types::status::RpcDebugStatusResponse {
+                version: DEBUG_VIEWS_VERSION,
+                status_response: debug_status,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the peers banned by this node at or after `since`, in seconds since the Unix
+    /// epoch.
+    pub async fn debug_ban_history(
+        &self,
+        since: u64,
+    ) -> Result<
+        Option<near_jsonrpc_primitives::types::status::RpcDebugStatusResponse>,
+        near_jsonrpc_primitives::types::status::RpcStatusError,
+    > {
+        if self.enable_debug_rpc {
+            let since = near_primitives::utils::from_timestamp(since.saturating_mul(1_000_000_000));
+            let debug_status =
+                self.client_send(DebugStatus::BanHistory(Some(since))).await?.rpc_into();
+            Ok(Some(near_jsonrpc_primitives::types::status::RpcDebugStatusResponse {
+                version: DEBUG_VIEWS_VERSION,
                 status_response: debug_status,
             }))
         } else {
@@ -1353,6 +1401,17 @@ async fn debug_block_status_handler(
     }
 }
 
+async fn debug_ban_history_handler(
+    path: web::Path<u64>,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    match handler.debug_ban_history(*path).await {
+        Ok(Some(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
 fn health_handler(
     handler: web::Data<JsonRpcHandler>,
 ) -> impl Future<Output = Result<HttpResponse, HttpError>> {
@@ -1531,6 +1590,10 @@ pub fn start_http(
                 web::resource("/debug/api/block_status/{starting_height}")
                     .route(web::get().to(debug_block_status_handler)),
             )
+            .service(
+                web::resource("/debug/api/ban_history/{since}")
+                    .route(web::get().to(debug_ban_history_handler)),
+            )
             .service(
                 web::resource("/debug/client_config").route(web::get().to(client_config_handler)),
             )
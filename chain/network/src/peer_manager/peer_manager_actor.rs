@@ -940,6 +940,12 @@ impl PeerManagerActor {
                 self.state.tier2.broadcast_message(Arc::new(PeerMessage::Challenge(challenge)));
                 NetworkResponses::NoResponse
             }
+            #[cfg(feature = "protocol_feature_chunk_validation")]
+            NetworkRequests::ChunkEndorsement(_, _) => {
+                // TODO: route the chunk endorsements once they are part of the network protocol.
+                // Until then they are only exchanged between the clients of the test harnesses.
+                NetworkResponses::RouteNotFound
+            }
         }
     }
 
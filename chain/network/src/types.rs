@@ -18,6 +18,8 @@ use near_async::time;
 use near_crypto::PublicKey;
 use near_primitives::block::{ApprovalMessage, Block, GenesisId};
 use near_primitives::challenge::Challenge;
+#[cfg(feature = "protocol_feature_chunk_validation")]
+use near_primitives::chunk_validation::ChunkEndorsement;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::sharding::PartialEncodedChunkWithArcReceipts;
@@ -256,6 +258,9 @@ pub enum NetworkRequests {
     TxStatus(AccountId, AccountId, CryptoHash),
     /// A challenge to invalidate a block.
     Challenge(Challenge),
+    /// Sends the endorsement of a chunk to the given block producer.
+    #[cfg(feature = "protocol_feature_chunk_validation")]
+    ChunkEndorsement(AccountId, ChunkEndorsement),
 }
 
 /// Combines peer address info, chain.
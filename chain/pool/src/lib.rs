@@ -83,6 +83,19 @@ impl TransactionPool {
     pub fn insert_transaction(
         &mut self,
         signed_transaction: SignedTransaction,
+    ) -> InsertTransactionResult {
+        self.insert_transaction_with_reserve(signed_transaction, 0)
+    }
+
+    /// Same as `insert_transaction`, but the transaction is only inserted if at least
+    /// `reserved_size` bytes below the size limit of the pool stay free after it is. As with a full
+    /// pool, the hash of a transaction rejected because of the reserve is recorded, and the
+    /// transaction is a duplicate when inserted again.
+    #[must_use]
+    pub fn insert_transaction_with_reserve(
+        &mut self,
+        signed_transaction: SignedTransaction,
+        reserved_size: u64,
     ) -> InsertTransactionResult {
         if !self.unique_transactions.insert(signed_transaction.get_hash()) {
             // The hash of this transaction was already seen, skip it.
@@ -96,7 +109,7 @@ impl TransactionPool {
             .checked_add(signed_transaction.get_size())
             .expect("Total transaction size is too large");
         if let Some(limit) = self.total_transaction_size_limit {
-            if new_total_transaction_size > limit {
+            if new_total_transaction_size > limit.saturating_sub(reserved_size) {
                 return InsertTransactionResult::NoSpaceLeft;
             }
         }
@@ -171,6 +184,11 @@ impl TransactionPool {
         self.unique_transactions.len()
     }
 
+    /// Returns the hashes of the transactions in the pool, in no particular order.
+    pub fn transaction_hashes(&self) -> impl Iterator<Item = &CryptoHash> {
+        self.unique_transactions.iter()
+    }
+
     /// Returns the total size of transactions in the pool in bytes.
     pub fn transaction_size(&self) -> u64 {
         self.total_transaction_size
@@ -594,4 +612,32 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_transaction_pool_size_reserve() {
+        let transactions = generate_transactions("alice.near", "alice.near", 1, 4);
+        let pool_size_limit = transactions.iter().map(|tx| tx.get_size()).sum::<u64>();
+        let reserved_size = transactions[2].get_size() + transactions[3].get_size();
+        let mut pool = TransactionPool::new(TEST_SEED, Some(pool_size_limit), "");
+        for tx in &transactions[..2] {
+            assert_eq!(
+                pool.insert_transaction_with_reserve(tx.clone(), reserved_size),
+                InsertTransactionResult::Success
+            );
+        }
+        assert_eq!(
+            pool.insert_transaction_with_reserve(transactions[2].clone(), reserved_size),
+            InsertTransactionResult::NoSpaceLeft
+        );
+        // Like a transaction rejected because the pool is full, the transaction rejected because of
+        // the reserve is deduplicated, while the reserve stays available to the others.
+        assert_eq!(
+            pool.insert_transaction(transactions[2].clone()),
+            InsertTransactionResult::Duplicate
+        );
+        for tx in &transactions[3..] {
+            assert_eq!(pool.insert_transaction(tx.clone()), InsertTransactionResult::Success);
+        }
+        assert_eq!(pool.transaction_size(), pool_size_limit - transactions[2].get_size());
+    }
 }
@@ -31,3 +31,16 @@ impl FutureSpawner for ActixFutureSpawner {
         near_performance_metrics::actix::spawn(description, f);
     }
 }
+
+/// Hands over the future to an Actix arbiter, which runs it on its own thread.
+impl FutureSpawner for actix::ArbiterHandle {
+    fn spawn_boxed(&self, description: &'static str, f: BoxFuture<'static, ()>) {
+        if !self.spawn(f) {
+            near_o11y::tracing::error!(
+                target: "future_spawner",
+                description,
+                "Unable to spawn future, the arbiter has stopped"
+            );
+        }
+    }
+}
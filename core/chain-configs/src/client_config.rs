@@ -5,11 +5,15 @@ use near_primitives::types::{
 };
 use near_primitives::version::Version;
 use std::cmp::{max, min};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::time::Duration;
 
 pub const TEST_STATE_SYNC_TIMEOUT: u64 = 5;
 
+/// Replaces the redacted values of the config, see `ClientConfig::to_redacted_json`.
+const REDACTED: &str = "<redacted>";
+
 #[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub enum LogSummaryStyle {
     #[serde(rename = "plain")]
@@ -28,6 +32,9 @@ pub const DEFAULT_GC_NUM_EPOCHS_TO_KEEP: u64 = 5;
 pub const DEFAULT_STATE_SYNC_NUM_CONCURRENT_REQUESTS_EXTERNAL: u32 = 25;
 pub const DEFAULT_STATE_SYNC_NUM_CONCURRENT_REQUESTS_ON_CATCHUP_EXTERNAL: u32 = 5;
 
+/// Default number of heights for which the block and chunk production timings are kept.
+pub const DEFAULT_PRODUCTION_INFO_CAPACITY: usize = 1000;
+
 /// Configuration for garbage collection.
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, PartialEq)]
 #[serde(default)]
@@ -192,6 +199,67 @@ impl Default for StateSplitConfig {
     }
 }
 
+/// Timings of the requests for the parts of a chunk which haven't arrived yet.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkRequestRetryConfig {
+    /// Time after which the missing parts are requested again.
+    pub retry_period: Duration,
+    /// Time since the first request after which the parts are requested from other peers than
+    /// their owners.
+    pub switch_to_others: Duration,
+    /// Time since the first request after which all the parts of the chunk are requested, not
+    /// only the ones this node needs.
+    pub switch_to_full_fetch: Duration,
+}
+
+/// Same as the timings of the shards manager started without a config.
+impl Default for ChunkRequestRetryConfig {
+    fn default() -> Self {
+        Self {
+            retry_period: Duration::from_millis(100),
+            switch_to_others: Duration::from_millis(400),
+            switch_to_full_fetch: Duration::from_secs(3),
+        }
+    }
+}
+
+impl ChunkRequestRetryConfig {
+    /// Default timings of the shards without an entry in `chunk_request_retry_per_shard`: the
+    /// requests are retried every time they are checked.
+    pub fn with_retry_period(retry_period: Duration) -> Self {
+        Self { retry_period, ..Self::default() }
+    }
+}
+
+/// Configures how the client checks the validity window of delegate actions
+/// (NEP-366 meta-transactions) before admitting a transaction to the pool or
+/// including it in a chunk.
+///
+/// The outer transaction is always checked against the chain's
+/// `transaction_validity_period`. This only affects the inner anchor of each
+/// `SignedDelegateAction`, i.e. its `max_block_height`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DelegateActionValidity {
+    /// Rejects the transaction if any of its delegate actions expires before
+    /// the outer transaction does, so that the transaction can't fail for an
+    /// expired delegate action whenever it is included.
+    Strict,
+    /// Rejects the transaction if any of its delegate actions expires before
+    /// the height at which the transaction could be included, but accepts
+    /// delegate actions which expire before the outer transaction does.
+    Lenient,
+    /// Doesn't check delegate actions in the client and leaves it to the
+    /// runtime, which fails expired delegate actions when applying them.
+    /// This is how the client always behaved.
+    Runtime,
+}
+
+impl Default for DelegateActionValidity {
+    fn default() -> Self {
+        Self::Runtime
+    }
+}
+
 /// ClientConfig where some fields can be updated at runtime.
 #[derive(Clone, serde::Serialize)]
 pub struct ClientConfig {
@@ -229,6 +297,9 @@ pub struct ClientConfig {
     pub header_sync_expected_height_per_second: u64,
     /// How long to wait for a response during state sync
     pub state_sync_timeout: Duration,
+    /// Number of epochs after which an unfinished catchup state sync is considered stale and
+    /// dropped.
+    pub catchup_state_sync_max_age_epochs: u64,
     /// Minimum number of peers to start syncing.
     pub min_num_peers: usize,
     /// Period between logging summary information.
@@ -249,6 +320,9 @@ pub struct ClientConfig {
     pub catchup_step_period: Duration,
     /// Time between checking to re-request chunks.
     pub chunk_request_retry_period: Duration,
+    /// Timings of the chunk requests of specific shards. The shards manager defaults are used
+    /// for the other shards.
+    pub chunk_request_retry_per_shard: HashMap<ShardId, ChunkRequestRetryConfig>,
     /// Time between running doomslug timer.
     pub doosmslug_step_period: Duration,
     /// Behind this horizon header fetch kicks in.
@@ -302,9 +376,89 @@ pub struct ClientConfig {
     pub enable_multiline_logging: bool,
     // Configuration for resharding.
     pub state_split_config: StateSplitConfig,
+    /// How to check the validity window of delegate actions in meta-transactions.
+    pub delegate_action_validity: DelegateActionValidity,
+    /// If set, the block and chunk production report of each epoch this node was a validator in
+    /// is written to this file when the epoch ends, replacing the report of the previous epoch.
+    pub production_report_path: Option<PathBuf>,
+    /// Size in bytes of the state witness of a produced chunk above which a warning is logged.
+    /// If not set, the size is only tracked.
+    pub state_witness_size_soft_limit: Option<u64>,
+    /// Max number of forwarded transactions per shard kept while the node hasn't caught up with
+    /// the shard, to be processed again once it does. If 0, such transactions are dropped.
+    pub not_caught_up_tx_buffer_size: usize,
+    /// Number of blocks below the head scanned to find since when the node holds every chunk of
+    /// the shards it tracks, as reported in the node status.
+    pub chunk_availability_window: BlockHeightDelta,
+    /// If set, when this node is the producer of the block after a block which is still being
+    /// processed, that block's chunks are applied and the block is postprocessed ahead of the
+    /// other blocks in processing, so that the production slot isn't missed.
+    pub prioritize_block_before_production: bool,
+    /// Max number of blocks kept while waiting for their chunks. Above it, the highest blocks are
+    /// evicted.
+    pub max_blocks_with_missing_chunks: usize,
+    /// Blocks waiting for their chunks for longer than this are evicted. An evicted block is
+    /// requested again if one of its chunks arrives later.
+    pub max_block_with_missing_chunks_age: Duration,
+    /// Max number of blocks whose missing chunks arrived started at once. The rest are started
+    /// as the processing of these finishes.
+    pub max_blocks_with_missing_chunks_started: usize,
+    /// Number of threads the completed chunks are written to the store on, off the client
+    /// thread. All chunks of a shard are written by the same thread.
+    pub chunk_writer_threads: usize,
+    /// Delay before a failed chunk write is retried, multiplied by the number of the failed
+    /// attempt. The writes of the other chunks go on in the meantime.
+    pub chunk_persist_retry_delay: Duration,
+    /// Number of blocks up to the head broadcast again once the head progresses after a stall,
+    /// so that peers which missed them don't have to request them one by one.
+    pub recovery_burst_blocks: usize,
+    /// Whether the recovery burst is sent also when this node didn't produce the block which
+    /// resumed the head progress.
+    pub relay_recovery_burst: bool,
+    /// Min time between two recovery bursts. A burst due sooner is not sent.
+    pub recovery_burst_interval: Duration,
+    /// Max number of directly submitted transactions processed per second. Unlimited if not set.
+    pub local_tx_rate_limit: Option<u64>,
+    /// Max number of forwarded transactions processed per second. Unlimited if not set.
+    pub forwarded_tx_rate_limit: Option<u64>,
+    /// Percentage of the transaction pool size limit reserved for forwarded transactions.
+    pub forwarded_tx_pool_reservation_percent: u64,
+    /// Max number of transactions of the abandoned blocks reintroduced to the pool after a reorg.
+    /// Unlimited if not set.
+    pub reorg_tx_reintroduction_limit: Option<usize>,
+    /// Max total size in bytes of the transactions reintroduced to the pool after a reorg.
+    /// Unlimited if not set.
+    pub reorg_tx_reintroduction_size_limit: Option<u64>,
+    /// If set, the node syncs up to this height and stops there: headers and blocks above it are
+    /// neither requested nor processed, and block production is disabled.
+    pub sync_until_height: Option<BlockHeight>,
+    /// Number of most recent heights for which the block production timings are kept for debug
+    /// purposes.
+    pub block_production_info_capacity: usize,
+    /// Number of most recent heights for which the chunk production timings are kept for debug
+    /// purposes, separately for every shard.
+    pub chunk_production_info_capacity_per_shard: usize,
+    /// Number of consecutive new heads for which the health of the node must be the same before
+    /// it is reported. Critical conditions are reported right away.
+    pub health_hysteresis_blocks: u64,
+    /// If set, when the production of a chunk fails with a retryable error, e.g. because the
+    /// flat storage of the shard isn't ready, a chunk without transactions is produced on top of
+    /// the chunk extra of the previous block instead.
+    pub produce_empty_chunk_on_failure: bool,
+    /// Whether the chunk extra of the previous block is checked against the trie changes recorded
+    /// for it before producing a chunk on top of it. Reads the trie changes on every chunk
+    /// production, so it's meant for debugging.
+    pub verify_chunk_extra_before_production: bool,
 }
 
 impl ClientConfig {
+    /// Timings of the chunk requests of `shard_id`, as applied by the shards manager.
+    pub fn chunk_request_retry(&self, shard_id: ShardId) -> ChunkRequestRetryConfig {
+        self.chunk_request_retry_per_shard.get(&shard_id).copied().unwrap_or_else(|| {
+            ChunkRequestRetryConfig::with_retry_period(self.chunk_request_retry_period)
+        })
+    }
+
     pub fn test(
         skip_sync_wait: bool,
         min_block_prod_time: u64,
@@ -341,6 +495,7 @@ impl ClientConfig {
             header_sync_progress_timeout: Duration::from_secs(2),
             header_sync_stall_ban_timeout: Duration::from_secs(30),
             state_sync_timeout: Duration::from_secs(TEST_STATE_SYNC_TIMEOUT),
+            catchup_state_sync_max_age_epochs: 2,
             header_sync_expected_height_per_second: 1,
             min_num_peers: 1,
             log_summary_period: Duration::from_secs(10),
@@ -354,6 +509,7 @@ impl ClientConfig {
                 Duration::from_millis(100),
                 Duration::from_millis(min_block_prod_time / 5),
             ),
+            chunk_request_retry_per_shard: HashMap::new(),
             doosmslug_step_period: Duration::from_millis(100),
             block_header_fetch_horizon: 50,
             gc: GCConfig { gc_blocks_limit: 100, ..GCConfig::default() },
@@ -377,6 +533,43 @@ impl ClientConfig {
             transaction_pool_size_limit: None,
             enable_multiline_logging: false,
             state_split_config: StateSplitConfig::default(),
+            delegate_action_validity: DelegateActionValidity::default(),
+            production_report_path: None,
+            state_witness_size_soft_limit: None,
+            not_caught_up_tx_buffer_size: 0,
+            chunk_availability_window: 100,
+            prioritize_block_before_production: false,
+            max_blocks_with_missing_chunks: 1024,
+            max_block_with_missing_chunks_age: Duration::from_secs(120),
+            max_blocks_with_missing_chunks_started: 3,
+            chunk_writer_threads: 1,
+            chunk_persist_retry_delay: Duration::ZERO,
+            recovery_burst_blocks: 3,
+            relay_recovery_burst: false,
+            recovery_burst_interval: Duration::from_secs(60),
+            local_tx_rate_limit: None,
+            forwarded_tx_rate_limit: None,
+            forwarded_tx_pool_reservation_percent: 0,
+            reorg_tx_reintroduction_limit: None,
+            reorg_tx_reintroduction_size_limit: None,
+            sync_until_height: None,
+            block_production_info_capacity: DEFAULT_PRODUCTION_INFO_CAPACITY,
+            chunk_production_info_capacity_per_shard: DEFAULT_PRODUCTION_INFO_CAPACITY,
+            health_hysteresis_blocks: 3,
+            produce_empty_chunk_on_failure: false,
+            verify_chunk_extra_before_production: false,
+        }
+    }
+
+    /// The config as JSON, with the values which mustn't leave the node, like the location of the
+    /// credentials, replaced by a placeholder.
+    pub fn to_redacted_json(&self) -> serde_json::Value {
+        let mut config = self.clone();
+        if let Some(dump) = &mut config.state_sync.dump {
+            if dump.credentials_file.is_some() {
+                dump.credentials_file = Some(PathBuf::from(REDACTED));
+            }
         }
+        serde_json::to_value(&config).expect("the client config is serializable")
     }
 }
@@ -6,8 +6,9 @@ mod metrics;
 mod updateable_config;
 
 pub use client_config::{
-    ClientConfig, DumpConfig, ExternalStorageConfig, ExternalStorageLocation, GCConfig,
-    LogSummaryStyle, StateSplitConfig, StateSyncConfig, SyncConfig, DEFAULT_GC_NUM_EPOCHS_TO_KEEP,
+    ChunkRequestRetryConfig, ClientConfig, DelegateActionValidity, DumpConfig,
+    ExternalStorageConfig, ExternalStorageLocation, GCConfig, LogSummaryStyle, StateSplitConfig,
+    StateSyncConfig, SyncConfig, DEFAULT_GC_NUM_EPOCHS_TO_KEEP, DEFAULT_PRODUCTION_INFO_CAPACITY,
     MIN_GC_NUM_EPOCHS_TO_KEEP, TEST_STATE_SYNC_TIMEOUT,
 };
 pub use genesis_config::{
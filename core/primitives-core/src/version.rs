@@ -129,6 +129,9 @@ pub enum ProtocolFeature {
     #[cfg(feature = "protocol_feature_chunk_validation")]
     ChunkValidation,
     EthImplicitAccounts,
+    /// A fresh staking proposal of an account kicked out for unstaking or for not having enough
+    /// stake overrides the kickout in the validator selection, instead of being dropped.
+    RestakeAfterKickout,
 }
 
 impl ProtocolFeature {
@@ -185,6 +188,7 @@ impl ProtocolFeature {
             #[cfg(feature = "protocol_feature_chunk_validation")]
             ProtocolFeature::ChunkValidation => 137,
             ProtocolFeature::EthImplicitAccounts => 138,
+            ProtocolFeature::RestakeAfterKickout => 139,
         }
     }
 }
@@ -22,6 +22,12 @@ impl PartialState {
         let Self::TrieValues(values) = self;
         values.len()
     }
+
+    /// Total size in bytes of the trie values.
+    pub fn size_in_bytes(&self) -> usize {
+        let Self::TrieValues(values) = self;
+        values.iter().map(|value| value.len()).sum()
+    }
 }
 
 /// Double signed block.
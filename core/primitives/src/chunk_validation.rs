@@ -0,0 +1,42 @@
+use crate::sharding::ChunkHash;
+use crate::types::{AccountId, BlockHeight, ShardId};
+use crate::validator_signer::ValidatorSigner;
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_crypto::{PublicKey, Signature};
+
+/// The chunk endorsed by a chunk validator.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChunkEndorsementInner {
+    pub chunk_hash: ChunkHash,
+    /// Height of the block the chunk is included in.
+    pub height: BlockHeight,
+    pub shard_id: ShardId,
+}
+
+impl ChunkEndorsementInner {
+    /// Data signed by the chunk validator. It's prefixed, so that the signature of an endorsement
+    /// can't be taken for the signature of the chunk header by its producer.
+    pub fn get_data_for_sig(&self) -> Vec<u8> {
+        [b"chunk_endorsement".as_ref(), borsh::to_vec(self).unwrap().as_ref()].concat()
+    }
+}
+
+/// Endorsement of a chunk by one of the chunk validators sampled for its shard at the height of
+/// the block it's included in, sent to the block producer of the next height.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChunkEndorsement {
+    pub inner: ChunkEndorsementInner,
+    pub account_id: AccountId,
+    pub signature: Signature,
+}
+
+impl ChunkEndorsement {
+    pub fn new(inner: ChunkEndorsementInner, signer: &dyn ValidatorSigner) -> Self {
+        let signature = signer.sign_chunk_endorsement(&inner);
+        Self { inner, account_id: signer.validator_id().clone(), signature }
+    }
+
+    pub fn verify(&self, public_key: &PublicKey) -> bool {
+        self.signature.verify(&self.inner.get_data_for_sig(), public_key)
+    }
+}
@@ -3,7 +3,7 @@ use crate::types::{AccountId, NumShards};
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_primitives_core::types::ShardId;
 use std::cmp::Ordering::Greater;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::{fmt, str};
 
 /// This file implements two data structure `ShardLayout` and `ShardUId`
@@ -235,6 +235,58 @@ impl ShardLayout {
     }
 }
 
+/// Difference between the shard layouts of two consecutive epochs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShardLayoutDiff {
+    pub old_shard_layout: ShardLayout,
+    pub new_shard_layout: ShardLayout,
+    /// Maps each shard of the old layout to the shards of the new layout that it's split into.
+    /// A shard mapped to a single shard is kept as is, only its uid changes with the version.
+    /// Empty if the new layout doesn't record the shards it was derived from.
+    pub children: BTreeMap<ShardUId, Vec<ShardUId>>,
+}
+
+impl ShardLayoutDiff {
+    /// Returns None if the layouts are the same.
+    pub fn new(old_shard_layout: ShardLayout, new_shard_layout: ShardLayout) -> Option<Self> {
+        if old_shard_layout == new_shard_layout {
+            return None;
+        }
+        let children = old_shard_layout
+            .shard_ids()
+            .filter_map(|shard_id| {
+                let children = new_shard_layout.get_split_shard_uids(shard_id)?;
+                Some((ShardUId::from_shard_id_and_layout(shard_id, &old_shard_layout), children))
+            })
+            .collect();
+        Some(Self { old_shard_layout, new_shard_layout, children })
+    }
+
+    /// Shard uids of the new layout.
+    pub fn new_shard_uids(&self) -> Vec<ShardUId> {
+        self.new_shard_layout.get_shard_uids()
+    }
+
+    /// Shard of the old layout that the given shard of the new layout is derived from.
+    pub fn parent(&self, shard_uid: &ShardUId) -> Option<ShardUId> {
+        self.children
+            .iter()
+            .find(|(_, children)| children.contains(shard_uid))
+            .map(|(parent, _)| *parent)
+    }
+
+    /// Shards of the old layout for which `cares_about_shard` returns true, and which are split
+    /// into several shards, or whose children are unknown.
+    pub fn affected_shards(&self, cares_about_shard: impl Fn(ShardId) -> bool) -> Vec<ShardUId> {
+        self.old_shard_layout
+            .get_shard_uids()
+            .into_iter()
+            .filter(|shard_uid| cares_about_shard(shard_uid.shard_id()))
+            .filter(|shard_uid| self.children.get(shard_uid).map_or(true, |c| c.len() > 1))
+            .collect()
+    }
+}
+
 /// Maps an account to the shard that it belongs to given a shard_layout
 /// For V0, maps according to hash of account id
 /// For V1, accounts are divided to ranges, each range of account is mapped to a shard.
@@ -452,7 +504,9 @@ impl<'de> serde::de::Visitor<'de> for ShardUIdVisitor {
 
 #[cfg(test)]
 mod tests {
-    use crate::shard_layout::{account_id_to_shard_id, ShardLayout, ShardLayoutV1, ShardUId};
+    use crate::shard_layout::{
+        account_id_to_shard_id, ShardLayout, ShardLayoutDiff, ShardLayoutV1, ShardUId,
+    };
     use near_primitives_core::types::{AccountId, ShardId};
     use rand::distributions::Alphanumeric;
     use rand::rngs::StdRng;
@@ -538,6 +592,33 @@ mod tests {
         assert_eq!(account_id_to_shard_id(&"zoo".parse().unwrap(), &shard_layout), 5);
     }
 
+    #[test]
+    fn test_shard_layout_diff() {
+        let old_shard_layout = ShardLayout::v1(parse_account_ids(&["foo"]), None, 1);
+        let new_shard_layout = ShardLayout::v1(
+            parse_account_ids(&["bar", "foo", "paz"]),
+            Some(vec![vec![0, 1], vec![2, 3]]),
+            2,
+        );
+        assert_eq!(ShardLayoutDiff::new(old_shard_layout.clone(), old_shard_layout.clone()), None);
+
+        let diff = ShardLayoutDiff::new(old_shard_layout, new_shard_layout).unwrap();
+        let old = |shard_id| ShardUId { version: 1, shard_id };
+        let new = |shard_id| ShardUId { version: 2, shard_id };
+        assert_eq!(
+            diff.children,
+            [(old(0), vec![new(0), new(1)]), (old(1), vec![new(2), new(3)])].into_iter().collect()
+        );
+        assert_eq!(diff.new_shard_uids(), (0..4).map(new).collect::<Vec<_>>());
+        for shard_id in 0..4 {
+            assert_eq!(diff.parent(&new(shard_id)), Some(old(shard_id / 2)));
+        }
+        assert_eq!(diff.parent(&old(0)), None);
+        assert_eq!(diff.affected_shards(|shard_id| shard_id == 1), vec![old(1)]);
+        assert_eq!(diff.affected_shards(|_| true), vec![old(0), old(1)]);
+        assert_eq!(diff.affected_shards(|_| false), vec![]);
+    }
+
     // check that after removing the fixed shards from the shard layout v1
     // the fixed shards are skipped in deserialization
     // this should be the default as long as serde(deny_unknown_fields) is not set
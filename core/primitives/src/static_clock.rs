@@ -45,6 +45,11 @@ struct MockClockPerState {
     utc_call_count: u64,
     /// Number of times `Clock::instant()` method was called since we started mocking.
     instant_call_count: u64,
+    /// Time returned once the lists run out, with the matching instant.
+    time: Option<(DateTime<Utc>, Instant)>,
+    /// The first time set and the instant it maps to, the instants of the later times are
+    /// offset from it.
+    time_origin: Option<(DateTime<Utc>, Instant)>,
 }
 
 /// Stores the mocking state.
@@ -92,6 +97,23 @@ impl MockClockGuard {
         });
     }
 
+    /// Sets the time returned by `StaticClock::utc()`, and the matching instant returned by
+    /// `StaticClock::instant()`, once the queued timestamps run out. The time stays until it's
+    /// set again. Times earlier than the first one set map to its instant.
+    pub fn set_time(&self, time: DateTime<chrono::Utc>) {
+        MockClockPerThread::with(|clock| match &mut clock.mock {
+            Some(clock) => {
+                let (origin_time, origin_instant) =
+                    *clock.time_origin.get_or_insert_with(|| (time, Instant::now()));
+                let instant = origin_instant + (time - origin_time).to_std().unwrap_or_default();
+                clock.time = Some((time, instant));
+            }
+            None => {
+                panic!("Use MockClockGuard in your test");
+            }
+        });
+    }
+
     /// Returns number of calls  to `Self::utc` since `Self::mock()` was called.
     pub fn utc_call_count(&self) -> u64 {
         MockClockPerThread::with(|clock| match &mut clock.mock {
@@ -144,12 +166,13 @@ impl StaticClock {
     }
 
     /// This methods gets current time as `std::Instant`
-    /// unless it's mocked, then returns time added by `Self::add_utc(...)`
+    /// unless it's mocked, then returns time added by `Self::add_utc(...)` or set by
+    /// `MockClockGuard::set_time(...)`
     pub fn instant() -> Instant {
         MockClockPerThread::with(|clock| match &mut clock.mock {
             Some(clock) => {
                 clock.instant_call_count += 1;
-                let x = clock.instant_list.pop_front();
+                let x = clock.instant_list.pop_front().or(clock.time.map(|(_, instant)| instant));
                 match x {
                     Some(t) => t,
                     None => {
@@ -162,12 +185,13 @@ impl StaticClock {
     }
 
     /// This methods gets current time as `std::Instant`
-    /// unless it's mocked, then returns time added by `Self::add_instant(...)`
+    /// unless it's mocked, then returns time added by `Self::add_instant(...)` or set by
+    /// `MockClockGuard::set_time(...)`
     pub fn utc() -> DateTime<chrono::Utc> {
         MockClockPerThread::with(|clock| match &mut clock.mock {
             Some(clock) => {
                 clock.utc_call_count += 1;
-                let x = clock.utc_list.pop_front();
+                let x = clock.utc_list.pop_front().or(clock.time.map(|(time, _)| time));
                 match x {
                     Some(t) => t,
                     None => {
@@ -5,6 +5,7 @@ use near_crypto::{InMemorySigner, KeyType, PublicKey, Signature, Signer};
 
 use crate::block::{Approval, ApprovalInner, BlockHeader};
 use crate::challenge::ChallengeBody;
+use crate::chunk_validation::ChunkEndorsementInner;
 use crate::hash::CryptoHash;
 use crate::network::{AnnounceAccount, PeerId};
 use crate::sharding::ChunkHash;
@@ -36,6 +37,9 @@ pub trait ValidatorSigner: Sync + Send {
     /// Signs approval of given parent hash and reference hash.
     fn sign_approval(&self, inner: &ApprovalInner, target_height: BlockHeight) -> Signature;
 
+    /// Signs the endorsement of a chunk.
+    fn sign_chunk_endorsement(&self, inner: &ChunkEndorsementInner) -> Signature;
+
     /// Signs challenge body.
     fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature);
 
@@ -108,6 +112,10 @@ impl ValidatorSigner for EmptyValidatorSigner {
         Signature::default()
     }
 
+    fn sign_chunk_endorsement(&self, _inner: &ChunkEndorsementInner) -> Signature {
+        Signature::default()
+    }
+
     fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature) {
         (CryptoHash::hash_borsh(challenge_body), Signature::default())
     }
@@ -199,6 +207,10 @@ impl ValidatorSigner for InMemoryValidatorSigner {
         self.signer.sign(&Approval::get_data_for_sig(inner, target_height))
     }
 
+    fn sign_chunk_endorsement(&self, inner: &ChunkEndorsementInner) -> Signature {
+        self.signer.sign(&inner.get_data_for_sig())
+    }
+
     fn sign_challenge(&self, challenge_body: &ChallengeBody) -> (CryptoHash, Signature) {
         let hash = CryptoHash::hash_borsh(challenge_body);
         let signature = self.signer.sign(hash.as_ref());
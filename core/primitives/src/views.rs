@@ -48,7 +48,7 @@ use near_vm_runner::ContractCode;
 use num_rational::Rational32;
 use serde_with::base64::Base64;
 use serde_with::serde_as;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::ops::Range;
 use std::sync::Arc;
@@ -358,6 +358,12 @@ pub struct StatusSyncInfo {
     pub earliest_block_time: Option<DateTime<chrono::Utc>>,
     pub epoch_id: Option<EpochId>,
     pub epoch_start_height: Option<BlockHeight>,
+    /// Height the node is configured to stop syncing at, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_until_height: Option<BlockHeight>,
+    /// Whether the head reached `sync_until_height`.
+    #[serde(default)]
+    pub synced_to_target: bool,
 }
 
 // TODO: add more information to ValidatorInfo
@@ -438,7 +444,7 @@ pub enum SyncStatusView {
         highest_height: BlockHeight,
     },
     /// State sync, with different states of state sync for different shards.
-    StateSync(CryptoHash, HashMap<ShardId, ShardSyncDownloadView>),
+    StateSync(CryptoHash, BTreeMap<ShardId, ShardSyncDownloadView>),
     /// Sync state across all shards is done.
     StateSyncDone,
     /// Catch up on blocks.
@@ -512,13 +518,16 @@ pub struct DownloadStatusView {
     pub done: bool,
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+/// Served by the debug API, see `near_client_primitives::debug` for the stability of its
+/// encoding. Fields missing from an older encoding take their default values.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
 pub struct CatchupStatusView {
     // This is the first block of the epoch that we are catching up
     pub sync_block_hash: CryptoHash,
     pub sync_block_height: BlockHeight,
-    // Status of all shards that need to sync
-    pub shard_sync_status: HashMap<ShardId, String>,
+    // Status of all shards that need to sync, ordered by shard id
+    pub shard_sync_status: BTreeMap<ShardId, String>,
     // Blocks that we need to catchup, if it is empty, it means catching up is done
     pub blocks_to_catchup: Vec<BlockStatusView>,
 }
@@ -638,6 +647,8 @@ pub enum DroppedReason {
     HeightProcessed,
     // If the block processing pool is full
     TooManyProcessingBlocks,
+    // If the block is above the height the node is configured to sync until
+    AboveSyncTarget,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -709,6 +720,9 @@ pub struct StatusResponse {
     pub validator_account_id: Option<AccountId>,
     /// Public key of the validator.
     pub validator_public_key: Option<PublicKey>,
+    /// Shards the validator may produce chunks for in the current epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_producer_shards: Option<Vec<ShardId>>,
     /// Public key of the node.
     pub node_public_key: PublicKey,
     /// Deprecated; same as `validator_public_key` which you should use instead.
@@ -718,6 +732,131 @@ pub struct StatusResponse {
     /// Information about last blocks, network, epoch and chain & chunk info.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub detailed_debug_status: Option<DetailedDebugStatus>,
+    /// Recent heights for which the node holds every chunk of the shards it tracks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_availability: Option<Vec<ShardChunkAvailabilityView>>,
+    /// Timings of the chunk requests of the shards of the current epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_request_retry: Option<Vec<ShardChunkRequestRetryView>>,
+    /// Summarized health of the node, with the conditions it is derived from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<NodeHealthView>,
+}
+
+/// Health of the node, from the healthiest to the least healthy.
+#[derive(
+    serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum NodeHealthState {
+    /// Synced, and produces its blocks and chunks and sends its approvals.
+    Validating,
+    /// Synced, but not a validator of the current epoch.
+    Idle,
+    /// Synced, but misses some of its validator duties.
+    Degraded,
+    /// Syncing, or not receiving new blocks.
+    Behind,
+    /// Can't follow or validate the chain until an operator steps in.
+    Critical,
+}
+
+/// A condition contributing to the health of the node.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum NodeHealthFactor {
+    /// Not a validator of the epoch of the head.
+    NotValidator,
+    Syncing,
+    /// The head is older than the expected block time allows.
+    HeadStale {
+        head_age_millis: u64,
+    },
+    /// The last block this node was scheduled to produce isn't on the chain.
+    MissedBlock {
+        height: BlockHeight,
+    },
+    /// Chunks of the last height this node was scheduled to produce chunks at are missing.
+    MissedChunks {
+        height: BlockHeight,
+        shard_ids: Vec<ShardId>,
+    },
+    /// No approval was sent for the recent heights.
+    NoRecentApprovals {
+        last_approval_height: Option<BlockHeight>,
+    },
+    /// The state of the shards tracked in the next epoch is still being downloaded.
+    CatchupPending,
+    IncompatibleProtocolVersion {
+        network: ProtocolVersion,
+        ours: ProtocolVersion,
+    },
+    /// The head was produced further ahead of the local clock than the expected block time.
+    ClockSkew {
+        skew_millis: u64,
+    },
+    Slashed {
+        context: String,
+    },
+    /// Error reported by a component of the client, e.g. a failed chunk write.
+    InternalError {
+        message: String,
+    },
+}
+
+impl NodeHealthFactor {
+    /// The least healthy state the node can be in with this condition.
+    pub fn state(&self) -> NodeHealthState {
+        match self {
+            Self::NotValidator => NodeHealthState::Idle,
+            Self::MissedBlock { .. }
+            | Self::MissedChunks { .. }
+            | Self::NoRecentApprovals { .. }
+            | Self::CatchupPending => NodeHealthState::Degraded,
+            Self::Syncing | Self::HeadStale { .. } => NodeHealthState::Behind,
+            Self::IncompatibleProtocolVersion { .. }
+            | Self::ClockSkew { .. }
+            | Self::Slashed { .. }
+            | Self::InternalError { .. } => NodeHealthState::Critical,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NodeHealthView {
+    pub state: NodeHealthState,
+    pub factors: Vec<NodeHealthFactor>,
+}
+
+impl NodeHealthView {
+    /// The state is the one of the least healthy of `factors`, `Validating` if there are none.
+    pub fn new(factors: Vec<NodeHealthFactor>) -> Self {
+        let state = factors
+            .iter()
+            .map(NodeHealthFactor::state)
+            .max()
+            .unwrap_or(NodeHealthState::Validating);
+        Self { state, factors }
+    }
+}
+
+/// Recent chunks of a tracked shard held by the node, within the scanned window below the head.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShardChunkAvailabilityView {
+    pub shard_id: ShardId,
+    /// Lowest height since which every chunk of the shard up to the head is held. `None` if even
+    /// the latest chunk is missing.
+    pub available_since_height: Option<BlockHeight>,
+    /// Whether a chunk of the shard is missing within the window, right below
+    /// `available_since_height`.
+    pub has_gap: bool,
+}
+
+/// Effective timings of the requests for the missing parts of the chunks of a shard.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShardChunkRequestRetryView {
+    pub shard_id: ShardId,
+    pub retry_period_millis: u64,
+    pub switch_to_others_millis: u64,
+    pub switch_to_full_fetch_millis: u64,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
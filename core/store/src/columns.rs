@@ -277,6 +277,29 @@ pub enum DBCol {
     /// - *Rows*: arbitrary string, see `crate::db::FLAT_STATE_VALUES_INLINING_MIGRATION_STATUS_KEY` for example
     /// - *Column type*: arbitrary bytes
     Misc,
+    /// How the validators and the chunk producer assignment changed from the previous epoch.
+    /// - *Rows*: epoch id (CryptoHash)
+    /// - *Column type*: `near_epoch_manager::types::EpochTransitionStats`
+    EpochTransitionStats,
+    /// Fractions of the stake of slashed validators by which the validator selection made at the
+    /// end of the epoch discounts their stake.
+    /// - *Rows*: epoch id (CryptoHash)
+    /// - *Column type*: `near_epoch_manager::types::SlashDiscounts`
+    EpochSlashDiscounts,
+    /// Peers banned by this node, for debug purposes. Only the most recent bans are kept.
+    /// - *Rows*: index of the ban (u64, big endian)
+    /// - *Column type*: `near_client::debug::StoredBan`
+    BanHistory,
+    /// Inputs of the blocks produced by this node which can't be recovered from the chain, kept
+    /// so that the blocks can be rebuilt when auditing block production.
+    /// - *Rows*: height of the block (u64, big endian)
+    /// - *Column type*: `(CryptoHash, near_client::debug::BlockProductionInputs)`
+    BlockProductionInputs,
+    /// Provenance of the blocks recently accepted by this node, for debug purposes. Only the
+    /// records of the most recent blocks are kept.
+    /// - *Rows*: index of the record (u64, big endian)
+    /// - *Column type*: `near_client::block_provenance::StoredBlockProvenance`
+    BlockProvenance,
     /// Column to store data for Epoch Sync.
     /// Does not contain data for genesis epoch.
     /// - *Rows*: `epoch_id`
@@ -316,6 +339,9 @@ pub enum DBKeyType {
     ContractCacheKey,
     PartId,
     ColumnId,
+    /// Sequential index of an entry in a debug log. Used in DBCol::BanHistory and
+    /// DBCol::BlockProvenance.
+    LogIndex,
 }
 
 impl DBCol {
@@ -465,6 +491,11 @@ impl DBCol {
             | DBCol::EpochInfo
             | DBCol::EpochStart
             | DBCol::EpochValidatorInfo
+            | DBCol::EpochTransitionStats
+            | DBCol::EpochSlashDiscounts
+            | DBCol::BanHistory
+            | DBCol::BlockProductionInputs
+            | DBCol::BlockProvenance
             | DBCol::BlockOrdinal
             | DBCol::_ChunkPerHeightShard
             | DBCol::_NextBlockWithNewChunk
@@ -547,6 +578,11 @@ impl DBCol {
             DBCol::FlatStateChanges => &[DBKeyType::ShardUId, DBKeyType::BlockHash],
             DBCol::FlatStateDeltaMetadata => &[DBKeyType::ShardUId, DBKeyType::BlockHash],
             DBCol::FlatStorageStatus => &[DBKeyType::ShardUId],
+            DBCol::EpochTransitionStats => &[DBKeyType::EpochId],
+            DBCol::EpochSlashDiscounts => &[DBKeyType::EpochId],
+            DBCol::BanHistory => &[DBKeyType::LogIndex],
+            DBCol::BlockProductionInputs => &[DBKeyType::BlockHeight],
+            DBCol::BlockProvenance => &[DBKeyType::LogIndex],
             #[cfg(feature = "new_epoch_sync")]
             DBCol::EpochSyncInfo => &[DBKeyType::EpochId],
         }
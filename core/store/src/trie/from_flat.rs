@@ -1,7 +1,31 @@
 use crate::flat::{store_helper, FlatStorageError, FlatStorageManager};
 use crate::{ShardTries, StateSnapshotConfig, Store, Trie, TrieConfig, TrieDBStorage, TrieStorage};
+use near_primitives::hash::CryptoHash;
+use near_primitives::trie_key::col;
 use near_primitives::{shard_layout::ShardUId, state::FlatStateValue};
-use std::time::Instant;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Options of `construct_trie_from_flat`. They only affect the speed and memory usage of the
+/// construction, the resulting trie is the same.
+pub struct ConstructTrieFromFlatConfig {
+    /// With more than one thread, the flat state entries of the next batch are read on a separate
+    /// thread, and their values are fetched from `State` on `num_threads` threads, while the trie
+    /// nodes of the current batch are written. With one thread, everything is done sequentially.
+    pub num_threads: usize,
+    /// Total size of the keys and values applied to the trie at once. With more than one thread,
+    /// up to three batches are held in memory.
+    pub batch_size_bytes: usize,
+}
+
+impl Default for ConstructTrieFromFlatConfig {
+    fn default() -> Self {
+        Self { num_threads: 1, batch_size_bytes: 500_000_000 } // 500 MB
+    }
+}
+
+type TrieUpdateBatch = Vec<(Vec<u8>, Option<Vec<u8>>)>;
 
 // This function creates a new trie from flat storage for a given shard_uid
 // store: location of RocksDB store from where we read flatstore
@@ -10,62 +34,257 @@ use std::time::Instant;
 //
 // Please note that the trie is created for the block state with height equal to flat_head
 // flat state can comtain deltas after flat_head and can be different from tip of the blockchain.
-pub fn construct_trie_from_flat(store: Store, write_store: Store, shard_uid: ShardUId) {
-    let trie_storage = TrieDBStorage::new(store.clone(), shard_uid);
-    let flat_state_to_trie_kv =
-        |entry: Result<(Vec<u8>, FlatStateValue), FlatStorageError>| -> (Vec<u8>, Vec<u8>) {
-            let (key, value) = entry.unwrap();
-            let value = match value {
-                FlatStateValue::Ref(ref_value) => {
-                    trie_storage.retrieve_raw_bytes(&ref_value.hash).unwrap().to_vec()
-                }
-                FlatStateValue::Inlined(inline_value) => inline_value,
-            };
-            (key, value)
-        };
-
-    let mut iter = store_helper::iter_flat_state_entries(shard_uid, &store, None, None)
-        .map(flat_state_to_trie_kv);
-
-    // new ShardTries for write storage location
-    let tries = ShardTries::new(
-        write_store.clone(),
-        TrieConfig::default(),
-        &[shard_uid],
-        FlatStorageManager::new(write_store),
-        StateSnapshotConfig::default(),
-    );
-    let mut trie_root = Trie::EMPTY_ROOT;
-
+pub fn construct_trie_from_flat(
+    store: Store,
+    write_store: Store,
+    shard_uid: ShardUId,
+    config: &ConstructTrieFromFlatConfig,
+) -> CryptoHash {
     let timer = Instant::now();
-    while let Some(batch) = get_trie_update_batch(&mut iter) {
-        // Apply and commit changes
-        let batch_size = batch.len();
-        let new_trie = tries.get_trie_for_shard(shard_uid, trie_root);
-        let trie_changes = new_trie.update(batch).unwrap();
-        let mut store_update = tries.store_update();
-        tries.apply_all(&trie_changes, shard_uid, &mut store_update);
-        store_update.commit().unwrap();
-        trie_root = trie_changes.new_root;
+    let mut writer = TrieWriter::new(write_store, shard_uid);
+    if config.num_threads <= 1 {
+        let trie_storage = TrieDBStorage::new(store.clone(), shard_uid);
+        let mut iter = store_helper::iter_flat_state_entries(shard_uid, &store, None, None)
+            .map(|entry| flat_state_to_trie_kv(&trie_storage, entry));
+        while let Some(batch) =
+            get_batch(&mut iter, config.batch_size_bytes, |(key, value)| key.len() + value.len())
+        {
+            writer.apply(batch.into_iter().map(|(key, value)| (key, Some(value))).collect());
+        }
+    } else {
+        // Holds a single batch, so that the reader stays at most one batch ahead of the writer.
+        let (sender, receiver) = mpsc::sync_channel(1);
+        std::thread::scope(|scope| {
+            scope.spawn(|| read_batches(&store, shard_uid, config, sender));
+            for batch in receiver {
+                writer.apply(batch);
+            }
+        });
+    }
+
+    println!("{:.2?} : Completed building trie with root {}", timer.elapsed(), writer.trie_root);
+    writer.trie_root
+}
 
-        println!("{:.2?} : Processed {} entries", timer.elapsed(), batch_size);
+fn flat_state_to_trie_kv(
+    trie_storage: &TrieDBStorage,
+    entry: Result<(Vec<u8>, FlatStateValue), FlatStorageError>,
+) -> (Vec<u8>, Vec<u8>) {
+    let (key, value) = entry.unwrap();
+    (key, get_value(trie_storage, value))
+}
+
+fn get_value(trie_storage: &TrieDBStorage, value: FlatStateValue) -> Vec<u8> {
+    match value {
+        FlatStateValue::Ref(ref_value) => {
+            trie_storage.retrieve_raw_bytes(&ref_value.hash).unwrap().to_vec()
+        }
+        FlatStateValue::Inlined(inline_value) => inline_value,
     }
+}
 
-    println!("{:.2?} : Completed building trie with root {}", timer.elapsed(), trie_root);
+/// Reads the flat state entries in batches, fetching the values stored in `State` in parallel,
+/// and sends the batches in the order of the keys. Stops early if the receiver is gone.
+fn read_batches(
+    store: &Store,
+    shard_uid: ShardUId,
+    config: &ConstructTrieFromFlatConfig,
+    sender: mpsc::SyncSender<TrieUpdateBatch>,
+) {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(config.num_threads).build().unwrap();
+    let trie_storage = TrieDBStorage::new(store.clone(), shard_uid);
+    let mut iter =
+        store_helper::iter_flat_state_entries(shard_uid, store, None, None).map(Result::unwrap);
+    while let Some(entries) =
+        get_batch(&mut iter, config.batch_size_bytes, |(key, value)| key.len() + value.value_len())
+    {
+        let batch = pool.install(|| {
+            entries
+                .into_par_iter()
+                .map(|(key, value)| (key, Some(get_value(&trie_storage, value))))
+                .collect()
+        });
+        if sender.send(batch).is_err() {
+            return;
+        }
+    }
 }
 
-fn get_trie_update_batch(
-    iter: &mut impl Iterator<Item = (Vec<u8>, Vec<u8>)>,
-) -> Option<Vec<(Vec<u8>, Option<Vec<u8>>)>> {
-    let size_limit = 500 * 1000_000; // 500 MB
+/// Takes the entries from `iter` until their total size, as given by `entry_size`, exceeds
+/// `size_limit`.
+fn get_batch<T>(
+    iter: &mut impl Iterator<Item = T>,
+    size_limit: usize,
+    entry_size: impl Fn(&T) -> usize,
+) -> Option<Vec<T>> {
     let mut size = 0;
     let mut entries = Vec::new();
-    while let Some((key, value)) = iter.next() {
-        size += key.len() + value.len();
-        entries.push((key, Some(value)));
+    for entry in iter {
+        size += entry_size(&entry);
+        entries.push(entry);
         if size > size_limit {
             break;
         }
     }
     (!entries.is_empty()).then_some(entries)
 }
+
+/// Position of `key` in the range of the trie keys, from 0 to 1. Only the first bytes of the key
+/// are taken into account, which is enough to estimate the progress of the construction without
+/// counting the entries beforehand.
+fn key_position(key: &[u8]) -> f64 {
+    let mut prefix = [0u8; 8];
+    let len = key.len().min(prefix.len());
+    prefix[..len].copy_from_slice(&key[..len]);
+    // The first byte of a trie key is its column, `CONTRACT_DATA` being the last one.
+    let end = ((col::CONTRACT_DATA as u64 + 1) << 56) as f64;
+    (u64::from_be_bytes(prefix) as f64 / end).min(1.0)
+}
+
+/// Applies the batches of flat state entries to the trie being constructed, reporting the
+/// progress.
+struct TrieWriter {
+    tries: ShardTries,
+    shard_uid: ShardUId,
+    trie_root: CryptoHash,
+    timer: Instant,
+    /// Position of the first key applied to the trie, see `key_position`.
+    first_key_position: Option<f64>,
+    processed_entries: usize,
+    processed_bytes: usize,
+}
+
+impl TrieWriter {
+    fn new(write_store: Store, shard_uid: ShardUId) -> Self {
+        // new ShardTries for write storage location
+        let tries = ShardTries::new(
+            write_store.clone(),
+            TrieConfig::default(),
+            &[shard_uid],
+            FlatStorageManager::new(write_store),
+            StateSnapshotConfig::default(),
+        );
+        Self {
+            tries,
+            shard_uid,
+            trie_root: Trie::EMPTY_ROOT,
+            timer: Instant::now(),
+            first_key_position: None,
+            processed_entries: 0,
+            processed_bytes: 0,
+        }
+    }
+
+    fn apply(&mut self, batch: TrieUpdateBatch) {
+        let batch_entries = batch.len();
+        let first_key_position =
+            *self.first_key_position.get_or_insert_with(|| key_position(&batch[0].0));
+        let last_key_position = key_position(&batch[batch_entries - 1].0);
+        let batch_bytes: usize = batch
+            .iter()
+            .map(|(key, value)| key.len() + value.as_ref().map_or(0, |value| value.len()))
+            .sum();
+
+        // Apply and commit changes
+        let new_trie = self.tries.get_trie_for_shard(self.shard_uid, self.trie_root);
+        let trie_changes = new_trie.update(batch).unwrap();
+        let mut store_update = self.tries.store_update();
+        self.tries.apply_all(&trie_changes, self.shard_uid, &mut store_update);
+        store_update.commit().unwrap();
+        self.trie_root = trie_changes.new_root;
+
+        self.processed_entries += batch_entries;
+        self.processed_bytes += batch_bytes;
+        let elapsed = self.timer.elapsed().as_secs_f64();
+        // The keys are applied in order, so the part of the key range covered so far tells how
+        // much of the construction is done.
+        let progress =
+            (last_key_position - first_key_position) / (1.0 - first_key_position).max(f64::EPSILON);
+        let eta =
+            Duration::try_from_secs_f64(elapsed * (1.0 - progress) / progress).unwrap_or_default();
+        println!(
+            "{:.2?} : Processed {} entries, {:.1}% ({:.0} entries/s, {:.2} MB/s), ETA {:.0?}",
+            self.timer.elapsed(),
+            self.processed_entries,
+            progress * 100.0,
+            self.processed_entries as f64 / elapsed,
+            self.processed_bytes as f64 / elapsed / 1e6,
+            eta,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{construct_trie_from_flat, key_position, ConstructTrieFromFlatConfig};
+    use crate::test_utils::{
+        create_test_store, simplify_changes, test_populate_flat_storage, test_populate_trie,
+        TestTriesBuilder,
+    };
+    use crate::Trie;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::shard_layout::ShardUId;
+    use near_primitives::state::FlatStateValue;
+    use near_primitives::trie_key::col;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// The trie constructed by the parallel path has the same root as the one constructed
+    /// sequentially and as the original trie, and holds exactly the entries of the flat state.
+    #[test]
+    fn test_construct_trie_from_flat_parallel() {
+        let tries = TestTriesBuilder::new().with_flat_storage().build();
+        let shard_uid = ShardUId::single_shard();
+        let mut rng = StdRng::seed_from_u64(42);
+        let changes: Vec<_> = (0..5_000)
+            .map(|_| {
+                let key: Vec<u8> = (0..rng.gen_range(1..40)).map(|_| rng.gen()).collect();
+                // Some values are big enough to be stored in `State` instead of being inlined.
+                let value_len = rng.gen_range(0..2 * FlatStateValue::INLINE_DISK_VALUE_THRESHOLD);
+                let value: Vec<u8> = (0..value_len).map(|_| rng.gen()).collect();
+                (key, Some(value))
+            })
+            .collect();
+        let changes = simplify_changes(&changes);
+        test_populate_flat_storage(
+            &tries,
+            shard_uid,
+            &CryptoHash::default(),
+            &CryptoHash::default(),
+            &changes,
+        );
+        let expected_root =
+            test_populate_trie(&tries, &Trie::EMPTY_ROOT, shard_uid, changes.clone());
+        let mut expected_entries: Vec<_> =
+            changes.into_iter().map(|(key, value)| (key, value.unwrap())).collect();
+        expected_entries.sort();
+
+        for num_threads in [1, 4] {
+            // Small batches, so that the construction takes many of them.
+            let config = ConstructTrieFromFlatConfig { num_threads, batch_size_bytes: 1_000_000 };
+            let write_store = create_test_store();
+            let root = construct_trie_from_flat(
+                tries.get_store(),
+                write_store.clone(),
+                shard_uid,
+                &config,
+            );
+            assert_eq!(root, expected_root, "num_threads: {num_threads}");
+
+            let constructed_tries = TestTriesBuilder::new().with_store(write_store).build();
+            let trie = constructed_tries.get_trie_for_shard(shard_uid, root);
+            let entries: Vec<_> = trie.iter().unwrap().map(Result::unwrap).collect();
+            assert_eq!(entries, expected_entries, "num_threads: {num_threads}");
+        }
+    }
+
+    #[test]
+    fn test_key_position() {
+        assert_eq!(key_position(&[]), 0.0);
+        assert!(key_position(&[col::ACCOUNT, b'a']) < key_position(&[col::CONTRACT_CODE]));
+        let contract_data = key_position(&[col::CONTRACT_DATA]);
+        assert!(key_position(&[col::ACCESS_KEY, 0xff]) < contract_data);
+        assert!(contract_data < key_position(&[col::CONTRACT_DATA, b'a']));
+        assert_eq!(key_position(&[0xff; 10]), 1.0);
+    }
+}
@@ -18,7 +18,7 @@ pub use crate::trie::state_snapshot::{SnapshotError, StateSnapshot, StateSnapsho
 pub use crate::trie::trie_storage::{TrieCache, TrieCachingStorage, TrieDBStorage, TrieStorage};
 use crate::StorageError;
 use borsh::{BorshDeserialize, BorshSerialize};
-pub use from_flat::construct_trie_from_flat;
+pub use from_flat::{construct_trie_from_flat, ConstructTrieFromFlatConfig};
 use near_primitives::challenge::PartialState;
 use near_primitives::hash::{hash, CryptoHash};
 pub use near_primitives::shard_layout::ShardUId;
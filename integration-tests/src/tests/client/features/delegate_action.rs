@@ -6,12 +6,14 @@
 use crate::node::{Node, RuntimeNode};
 use crate::tests::standard_cases::fee_helper;
 use near_chain::ChainGenesis;
-use near_chain_configs::Genesis;
+use near_chain_configs::{DelegateActionValidity, Genesis};
 use near_client::test_utils::TestEnv;
-use near_crypto::{KeyType, PublicKey, Signer};
+use near_client::ProcessTxResponse;
+use near_crypto::{InMemorySigner, KeyType, PublicKey, Signer};
 use near_primitives::account::{
     id::AccountType, AccessKey, AccessKeyPermission, FunctionCallPermission,
 };
+use near_primitives::action::delegate::{DelegateAction, SignedDelegateAction};
 use near_primitives::checked_feature;
 use near_primitives::config::ActionCosts;
 use near_primitives::errors::{
@@ -23,7 +25,7 @@ use near_primitives::test_utils::{
 };
 use near_primitives::transaction::{
     Action, AddKeyAction, CreateAccountAction, DeleteAccountAction, DeleteKeyAction,
-    DeployContractAction, FunctionCallAction, StakeAction, TransferAction,
+    DeployContractAction, FunctionCallAction, SignedTransaction, StakeAction, TransferAction,
 };
 use near_primitives::types::{AccountId, Balance};
 use near_primitives::version::{ProtocolFeature, ProtocolVersion, PROTOCOL_VERSION};
@@ -111,6 +113,92 @@ fn reject_valid_meta_tx_in_older_versions() {
     );
 }
 
+/// Sends a meta transaction whose outer transaction points at the current head
+/// and whose delegate action expires `expires_in` blocks after it (before it if
+/// negative), and returns the client's response under the given rule.
+fn process_meta_tx_with_delegate_action_expiring_in(
+    delegate_action_validity: DelegateActionValidity,
+    expires_in: i64,
+) -> ProcessTxResponse {
+    let validator: AccountId = "test0".parse().unwrap();
+    let user: AccountId = "alice.near".parse().unwrap();
+    let receiver: AccountId = "bob.near".parse().unwrap();
+    let relayer: AccountId = "relayer.near".parse().unwrap();
+    let mut genesis =
+        Genesis::test(vec![validator, user.clone(), receiver.clone(), relayer.clone()], 1);
+    genesis.config.epoch_length = 1000;
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .real_epoch_managers(&genesis.config)
+        .nightshade_runtimes(&genesis)
+        .build();
+    env.clients[0].config.delegate_action_validity = delegate_action_validity;
+    for height in 1..=10 {
+        env.produce_block(0, height);
+    }
+
+    let tip = env.clients[0].chain.head().unwrap();
+    let inner_signer = InMemorySigner::from_seed(user.clone(), KeyType::ED25519, user.as_str());
+    let relayer_signer =
+        InMemorySigner::from_seed(relayer.clone(), KeyType::ED25519, relayer.as_str());
+    let delegate_action = DelegateAction {
+        sender_id: user.clone(),
+        receiver_id: receiver,
+        actions: vec![],
+        nonce: tip.height + 1,
+        max_block_height: tip.height.checked_add_signed(expires_in).unwrap(),
+        public_key: inner_signer.public_key(),
+    };
+    let signature = inner_signer.sign(delegate_action.get_nep461_hash().as_bytes());
+    let signed_delegate_action = SignedDelegateAction { delegate_action, signature };
+    let tx = SignedTransaction::from_actions(
+        tip.height + 1,
+        relayer,
+        user,
+        &relayer_signer,
+        vec![Action::Delegate(Box::new(signed_delegate_action))],
+        tip.last_block_hash,
+    );
+    env.clients[0].process_tx(tx, false, false)
+}
+
+/// By default the client leaves the check of delegate actions to the runtime, as it always did,
+/// and accepts a meta transaction whose delegate action has expired.
+#[test]
+fn accept_meta_tx_with_expired_delegate_action_by_default() {
+    let response =
+        process_meta_tx_with_delegate_action_expiring_in(DelegateActionValidity::default(), -5);
+    assert_eq!(response, ProcessTxResponse::ValidTx);
+}
+
+/// Both client side rules reject a meta transaction whose delegate action has
+/// expired, even if the outer transaction is fresh.
+#[test]
+fn reject_meta_tx_with_expired_delegate_action() {
+    for rule in [DelegateActionValidity::Strict, DelegateActionValidity::Lenient] {
+        let response = process_meta_tx_with_delegate_action_expiring_in(rule, -5);
+        assert_eq!(response, ProcessTxResponse::InvalidTx(InvalidTxError::Expired), "{rule:?}");
+    }
+}
+
+/// A delegate action which is still valid but expires before the outer
+/// transaction does is only accepted by the lenient rule.
+#[test]
+fn meta_tx_with_delegate_action_expiring_before_outer_tx() {
+    let response =
+        process_meta_tx_with_delegate_action_expiring_in(DelegateActionValidity::Lenient, 5);
+    assert_eq!(response, ProcessTxResponse::ValidTx);
+    let response =
+        process_meta_tx_with_delegate_action_expiring_in(DelegateActionValidity::Strict, 5);
+    assert_eq!(response, ProcessTxResponse::InvalidTx(InvalidTxError::Expired));
+    // The strict rule accepts it once it outlives the transaction validity period.
+    let validity_period = ChainGenesis::test().transaction_validity_period as i64;
+    let response = process_meta_tx_with_delegate_action_expiring_in(
+        DelegateActionValidity::Strict,
+        validity_period,
+    );
+    assert_eq!(response, ProcessTxResponse::ValidTx);
+}
+
 /// Take a list of actions and execute them as a meta transaction, check
 /// everything executes successfully, return balance differences for the sender,
 /// relayer, and receiver.
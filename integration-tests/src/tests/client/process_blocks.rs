@@ -1,7 +1,8 @@
 use std::collections::{HashSet, VecDeque};
 use std::str::FromStr;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::Duration;
 
 use actix::System;
 use assert_matches::assert_matches;
@@ -9,21 +10,29 @@ use futures::{future, FutureExt};
 use near_actix_test_utils::run_actix;
 use near_async::messaging::IntoSender;
 use near_chain::chain::ApplyStatePartsRequest;
-use near_chain::test_utils::ValidatorSchedule;
+use near_chain::test_utils::{wait_for_all_blocks_in_processing, ValidatorSchedule};
 use near_chain::types::{LatestKnown, RuntimeAdapter};
 use near_chain::validate::validate_chunk_with_chunk_extra;
 use near_chain::{
-    Block, BlockProcessingArtifact, ChainGenesis, ChainStore, ChainStoreAccess, Error, Provenance,
+    Block, BlockProcessingArtifact, ChainGenesis, ChainStore, ChainStoreAccess, Doomslug,
+    DoomslugThresholdMode, Error, Provenance,
 };
 use near_chain_configs::{Genesis, DEFAULT_GC_NUM_EPOCHS_TO_KEEP};
+use near_chunks::client::ShardsManagerResponse;
+use near_chunks::logic::persist_chunk;
 use near_chunks::test_utils::MockClientAdapterForShardsManager;
+use near_client::chunk_persister::{ChunkPersister, PersistChunkFn};
+use near_client::store_audit::StoreWrite;
 use near_client::test_utils::{
     create_chunk_on_height, setup_client_with_synchronous_shards_manager, setup_mock,
     setup_mock_all_validators, TestEnv,
 };
 use near_client::{
-    BlockApproval, BlockResponse, Client, GetBlockWithMerkleTree, ProcessTxResponse, SetNetworkInfo,
+    BlockApproval, BlockResponse, Client, ClientState, GcMode, GetBlock, GetBlockWithMerkleTree,
+    ProcessTxResponse, SetNetworkInfo, Status, SyncStatus,
 };
+use near_client_primitives::debug::{EpochProductionReport, SimulatedBlockProduction};
+use near_client_primitives::types::StatusError;
 use near_crypto::{InMemorySigner, KeyType, PublicKey, Signature, Signer};
 use near_network::test_utils::{wait_or_panic, MockPeerManagerAdapter};
 use near_network::types::{
@@ -34,18 +43,21 @@ use near_network::types::{FullPeerInfo, NetworkRequests, NetworkResponses};
 use near_network::types::{PeerInfo, ReasonForBan};
 use near_o11y::testonly::{init_integration_logger, init_test_logger};
 use near_o11y::WithSpanContextExt;
-use near_primitives::block::Approval;
-use near_primitives::block_header::BlockHeader;
+use near_primitives::block::{Approval, GenesisId};
+use near_primitives::block_header::{ApprovalType, BlockHeader};
 use near_primitives::epoch_manager::RngSeed;
 use near_primitives::errors::TxExecutionError;
 use near_primitives::errors::{ActionError, ActionErrorKind, InvalidTxError};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{verify_hash, PartialMerkleTree};
+use near_primitives::network::PeerId;
 use near_primitives::receipt::DelayedReceiptIndices;
 use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::runtime::config_store::RuntimeConfigStore;
 use near_primitives::shard_layout::{get_block_shard_uid, ShardUId};
-use near_primitives::sharding::{ShardChunkHeader, ShardChunkHeaderInner, ShardChunkHeaderV3};
+use near_primitives::sharding::{
+    ShardChunkHeader, ShardChunkHeaderInner, ShardChunkHeaderV3, StateSyncInfo,
+};
 use near_primitives::state_part::PartId;
 use near_primitives::state_sync::StatePartKey;
 use near_primitives::test_utils::create_test_signer;
@@ -56,12 +68,15 @@ use near_primitives::transaction::{
 };
 use near_primitives::trie_key::TrieKey;
 use near_primitives::types::validator_stake::ValidatorStake;
-use near_primitives::types::{AccountId, BlockHeight, EpochId, NumBlocks, ProtocolVersion};
+use near_primitives::types::{
+    AccountId, BlockHeight, BlockId, BlockReference, EpochId, NumBlocks, ProtocolVersion,
+};
 use near_primitives::utils::to_timestamp;
 use near_primitives::validator_signer::ValidatorSigner;
 use near_primitives::version::PROTOCOL_VERSION;
 use near_primitives::views::{
-    BlockHeaderView, FinalExecutionStatus, QueryRequest, QueryResponseKind,
+    BlockHeaderView, FinalExecutionStatus, NodeHealthFactor, NodeHealthState, QueryRequest,
+    QueryResponseKind,
 };
 use near_primitives_core::config::{ActionCosts, ExtCosts};
 use near_primitives_core::num_rational::{Ratio, Rational32};
@@ -943,6 +958,46 @@ fn skip_block_production() {
     });
 }
 
+/// Network info with a single peer at height 5 advertising `genesis_id`.
+fn network_info_with_one_peer(peer_info: PeerInfo, genesis_id: GenesisId) -> NetworkInfo {
+    NetworkInfo {
+        connected_peers: vec![ConnectedPeerInfo {
+            full_peer_info: FullPeerInfo {
+                peer_info: peer_info.clone(),
+                chain_info: PeerChainInfo {
+                    genesis_id: genesis_id.clone(),
+                    last_block: Some(BlockInfo { height: 5, hash: hash(&[5]) }),
+                    tracked_shards: vec![],
+                    archival: false,
+                },
+            },
+            received_bytes_per_sec: 0,
+            sent_bytes_per_sec: 0,
+            last_time_peer_requested: near_async::time::Instant::now(),
+            last_time_received_message: near_async::time::Instant::now(),
+            connection_established_time: near_async::time::Instant::now(),
+            peer_type: PeerType::Outbound,
+            nonce: 1,
+        }],
+        num_connected_peers: 1,
+        peer_max_count: 1,
+        highest_height_peers: vec![HighestHeightPeerInfo {
+            peer_info,
+            genesis_id,
+            highest_block_height: 5,
+            highest_block_hash: hash(&[5]),
+            tracked_shards: vec![],
+            archival: false,
+        }],
+        sent_bytes_per_sec: 0,
+        received_bytes_per_sec: 0,
+        known_producers: vec![],
+        tier1_connections: vec![],
+        tier1_accounts_keys: vec![],
+        tier1_accounts_data: vec![],
+    }
+}
+
 /// Runs client that requests syncing headers from peers.
 #[test]
 fn client_sync_headers() {
@@ -967,46 +1022,71 @@ fn client_sync_headers() {
                 _ => PeerManagerMessageResponse::NetworkResponses(NetworkResponses::NoResponse),
             }),
         );
+        actix::spawn(async move {
+            let genesis = actor_handles
+                .view_client_actor
+                .send(GetBlock(BlockReference::BlockId(BlockId::Height(0))).with_span_context())
+                .await
+                .unwrap()
+                .unwrap();
+            let genesis_id =
+                GenesisId { chain_id: "unittest".to_string(), hash: genesis.header.hash };
+            actor_handles.client_actor.do_send(
+                SetNetworkInfo(network_info_with_one_peer(peer_info2, genesis_id))
+                    .with_span_context(),
+            );
+        });
+        wait_or_panic(2000);
+    });
+}
+
+/// If peers are on a different chain, the client reports a genesis mismatch in its health
+/// check and doesn't request headers from them.
+#[test]
+fn client_sync_headers_genesis_mismatch() {
+    init_test_logger();
+    run_actix(async {
+        let peer_info = PeerInfo::random();
+        let peer_id = peer_info.id.clone();
+        let actor_handles = setup_mock(
+            vec!["test".parse().unwrap()],
+            "other".parse().unwrap(),
+            false,
+            false,
+            Box::new(move |msg, _ctx, _client_actor| match msg.as_network_requests_ref() {
+                NetworkRequests::BlockHeadersRequest { .. } => {
+                    panic!("Requested headers from peers on a different chain")
+                }
+                _ => PeerManagerMessageResponse::NetworkResponses(NetworkResponses::NoResponse),
+            }),
+        );
+        let peers_genesis_id = GenesisId { chain_id: "otherchain".to_string(), hash: hash(&[1]) };
         actor_handles.client_actor.do_send(
-            SetNetworkInfo(NetworkInfo {
-                connected_peers: vec![ConnectedPeerInfo {
-                    full_peer_info: FullPeerInfo {
-                        peer_info: peer_info2.clone(),
-                        chain_info: PeerChainInfo {
-                            genesis_id: Default::default(),
-                            last_block: Some(BlockInfo { height: 5, hash: hash(&[5]) }),
-                            tracked_shards: vec![],
-                            archival: false,
-                        },
-                    },
-                    received_bytes_per_sec: 0,
-                    sent_bytes_per_sec: 0,
-                    last_time_peer_requested: near_async::time::Instant::now(),
-                    last_time_received_message: near_async::time::Instant::now(),
-                    connection_established_time: near_async::time::Instant::now(),
-                    peer_type: PeerType::Outbound,
-                    nonce: 1,
-                }],
-                num_connected_peers: 1,
-                peer_max_count: 1,
-                highest_height_peers: vec![HighestHeightPeerInfo {
-                    peer_info: peer_info2,
-                    genesis_id: Default::default(),
-                    highest_block_height: 5,
-                    highest_block_hash: hash(&[5]),
-                    tracked_shards: vec![],
-                    archival: false,
-                }],
-                sent_bytes_per_sec: 0,
-                received_bytes_per_sec: 0,
-                known_producers: vec![],
-                tier1_connections: vec![],
-                tier1_accounts_keys: vec![],
-                tier1_accounts_data: vec![],
-            })
-            .with_span_context(),
+            SetNetworkInfo(network_info_with_one_peer(peer_info, peers_genesis_id))
+                .with_span_context(),
         );
-        wait_or_panic(2000);
+        actix::spawn(async move {
+            // Give the client a few sync steps to (not) request headers.
+            actix::clock::sleep(std::time::Duration::from_millis(500)).await;
+            let res = actor_handles
+                .client_actor
+                .send(Status { is_health_check: true, detailed: false }.with_span_context())
+                .await
+                .unwrap();
+            assert_matches!(
+                res,
+                Err(StatusError::GenesisMismatch {
+                    peers_chain_id,
+                    peers_genesis_hash,
+                    mismatched_peers,
+                    ..
+                }) if peers_chain_id == "otherchain"
+                    && peers_genesis_hash == hash(&[1])
+                    && mismatched_peers == vec![peer_id]
+            );
+            System::current().stop();
+        });
+        wait_or_panic(5000);
     });
 }
 
@@ -1471,6 +1551,57 @@ fn test_archival_save_trie_changes() {
     }
 }
 
+/// Test that an archival node switches from clearing only the forks to the garbage collection of
+/// the hot storage once the migration to split storage finishes, without a restart.
+#[test]
+fn test_archival_gc_after_split_storage_migration() {
+    let epoch_length = 10;
+
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let mut chain_genesis = ChainGenesis::test();
+    chain_genesis.epoch_length = epoch_length;
+    let mut env = TestEnv::builder(chain_genesis)
+        .real_epoch_managers(&genesis.config)
+        .nightshade_runtimes(&genesis)
+        .archive(true)
+        .save_trie_changes(true)
+        .build();
+    env.clients[0].chain.store().store().set_db_kind(DbKind::Archive).unwrap();
+
+    let mut blocks = vec![env.clients[0].chain.get_block_by_height(0).unwrap()];
+    let num_blocks = epoch_length * (DEFAULT_GC_NUM_EPOCHS_TO_KEEP + 1);
+    for i in 1..=num_blocks {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        blocks.push(block);
+    }
+    assert_eq!(env.clients[0].gc_mode(), GcMode::Archive);
+    for block in &blocks {
+        assert!(env.clients[0].chain.get_block(block.hash()).is_ok());
+    }
+
+    // The migration to split storage finished.
+    env.clients[0].chain.store().store().set_db_kind(DbKind::Hot).unwrap();
+    for i in num_blocks + 1..=2 * num_blocks {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        blocks.push(block);
+    }
+    assert_eq!(env.clients[0].gc_mode(), GcMode::SplitStorageHot);
+    // The first epoch was garbage collected, the epochs to keep were not.
+    let first_kept_height = 2 * num_blocks - epoch_length * DEFAULT_GC_NUM_EPOCHS_TO_KEEP;
+    for (height, block) in blocks.iter().enumerate() {
+        let height = height as BlockHeight;
+        let exists = env.clients[0].chain.get_block(block.hash()).is_ok();
+        if height < epoch_length {
+            assert!(!exists, "height {height}");
+        } else if height >= first_kept_height {
+            assert!(exists, "height {height}");
+        }
+    }
+}
+
 fn test_archival_gc_common(
     storage: NodeStorage,
     epoch_length: u64,
@@ -3227,29 +3358,164 @@ fn test_header_version_downgrade() {
     assert!(!res.is_ok());
 }
 
+/// Once the network switches to a protocol version newer than ours, a validator stops
+/// producing blocks, and a node without a validator key stops processing new blocks while
+/// still serving the data it already has. Neither of them crashes.
 #[test]
-#[should_panic(
-    expected = "The client protocol version is older than the protocol version of the network"
-)]
-fn test_node_shutdown_with_old_protocol_version() {
+fn test_node_stops_with_old_protocol_version() {
     let epoch_length = 5;
     let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
     genesis.config.epoch_length = epoch_length;
     let mut env = TestEnv::builder(ChainGenesis::test())
+        .clients_count(3)
         .real_epoch_managers(&genesis.config)
         .nightshade_runtimes(&genesis)
         .build();
+    env.clients[1].validator_signer = None;
+    env.clients[2].validator_signer = None;
     let validator_signer = create_test_signer("test0");
-    for i in 1..=5 {
+    let mut last_block = None;
+    for i in 1..=10 {
         let mut block = env.clients[0].produce_block(i).unwrap().unwrap();
-        block.mut_header().get_mut().inner_rest.latest_protocol_version = PROTOCOL_VERSION + 1;
-        block.mut_header().resign(&validator_signer);
-        env.process_block(0, block, Provenance::NONE);
+        if i <= 5 {
+            block.mut_header().get_mut().inner_rest.latest_protocol_version = PROTOCOL_VERSION + 1;
+            block.mut_header().resign(&validator_signer);
+        }
+        env.process_block(0, block.clone(), Provenance::NONE);
+        env.process_block(1, block.clone(), Provenance::NONE);
+        // Client 2 doesn't get the last block.
+        if i < 10 {
+            env.process_block(2, block.clone(), Provenance::NONE);
+            for client in &env.clients {
+                assert_eq!(client.client_state, ClientState::Running);
+            }
+        }
+        last_block = Some(block);
     }
-    for i in 6..=10 {
-        env.produce_block(0, i);
+    let last_block = last_block.unwrap();
+
+    let expected = ClientState::IncompatibleProtocolVersion {
+        network: PROTOCOL_VERSION + 1,
+        ours: PROTOCOL_VERSION,
+    };
+    for client in &env.clients[..2] {
+        assert_eq!(client.client_state, expected);
+        assert_eq!(client.chain.head().unwrap().height, 10);
+        assert!(client.chain.get_block_by_height(10).is_ok());
+    }
+    assert_matches!(
+        env.clients[0].produce_block(11),
+        Err(near_client::Error::BlockProducer {
+            height: 11,
+            kind: near_client::BlockProducerErrorKind::IncompatibleProtocolVersion { .. },
+        })
+    );
+
+    // A parked node drops the new blocks it receives instead of adding them to the chain, but
+    // keeps serving the blocks it already has.
+    assert_eq!(env.clients[2].client_state, ClientState::Running);
+    env.clients[2].client_state = expected;
+    env.clients[2]
+        .start_process_block(last_block.clone().into(), Provenance::NONE, Arc::new(|_| {}))
+        .unwrap();
+    env.clients[2].finish_blocks_in_processing();
+    assert_eq!(env.clients[2].chain.head().unwrap().height, 9);
+    assert!(env.clients[2].chain.get_block(last_block.hash()).is_err());
+    assert!(env.clients[2].chain.get_block_by_height(9).is_ok());
+}
+
+/// Test that the shards manager completes all chunks of a block in a single batch once the
+/// previous block is accepted, that the batch lets the client process the block that was waiting
+/// for them, and that the client ends up in the same state as completing the chunks one at a time.
+#[test]
+fn test_chunks_completed_in_one_batch() {
+    init_test_logger();
+    let num_shards = 50;
+    let accounts: Vec<AccountId> = (0..3).map(|i| format!("test{}", i).parse().unwrap()).collect();
+    let genesis = Genesis::test_sharded_new_version(accounts, 1, vec![1; num_shards]);
+    let chain_genesis = ChainGenesis::new(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients_count(3)
+        .validator_seats(1)
+        .real_epoch_managers(&genesis.config)
+        .track_all_shards()
+        .nightshade_runtimes(&genesis)
+        .build();
+
+    let mut blocks = vec![];
+    for i in 1..=3 {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        blocks.push(block.clone());
+        env.process_block(0, block, Provenance::PRODUCED);
+    }
+    let last_block = blocks.last().unwrap().clone();
+    let new_chunks = last_block
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.height_included() == last_block.header().height())
+        .count();
+    assert_eq!(new_chunks, num_shards);
+
+    // Client 1 requests the chunks of each block once it gets the block, so the chunks are
+    // completed one at a time.
+    for block in &blocks {
+        let res = env.clients[1].process_block_test(block.clone().into(), Provenance::NONE);
+        if let Err(err) = res {
+            assert_matches!(err, near_chain::Error::ChunksMissing(_));
+        }
+        env.process_partial_encoded_chunks_requests(1);
+        env.process_shards_manager_responses_and_finish_processing_blocks(1);
+        assert_eq!(&env.clients[1].chain.head().unwrap().last_block_hash, block.hash());
+    }
+
+    // Client 2 gets the last block, an orphan, before the chunks of its parent. The shards manager
+    // can only complete the chunks of the last block once the parent is accepted, and then
+    // completes all of them in the same pass.
+    let res = env.clients[2].process_block_test(blocks[0].clone().into(), Provenance::NONE);
+    if let Err(err) = res {
+        assert_matches!(err, near_chain::Error::ChunksMissing(_));
+    }
+    env.process_partial_encoded_chunks_requests(2);
+    env.process_shards_manager_responses_and_finish_processing_blocks(2);
+    let res = env.clients[2].process_block_test(blocks[1].clone().into(), Provenance::NONE);
+    assert_matches!(res, Err(near_chain::Error::ChunksMissing(_)));
+    let res = env.clients[2].process_block_test(last_block.clone().into(), Provenance::NONE);
+    assert_matches!(res, Err(near_chain::Error::Orphan));
+    env.process_partial_encoded_chunks_requests(2);
+    let mut batch_sizes = vec![];
+    loop {
+        while let Some(msg) = env.client_adapters[2].pop() {
+            match msg {
+                ShardsManagerResponse::ChunkCompleted { partial_chunk, shard_chunk } => {
+                    env.clients[2].on_chunk_completed(partial_chunk, shard_chunk, Arc::new(|_| {}))
+                }
+                ShardsManagerResponse::ChunksCompleted(chunks) => {
+                    batch_sizes.push(chunks.len());
+                    env.clients[2].on_chunks_completed(chunks, Arc::new(|_| {}));
+                }
+                ShardsManagerResponse::ChunkHeaderReadyForInclusion { .. }
+                | ShardsManagerResponse::ChunkHeadersReadyForInclusion(_) => {}
+                ShardsManagerResponse::InvalidChunk(_) => panic!("unexpected invalid chunk"),
+            }
+        }
+        env.clients[2].finish_chunk_persistence();
+        if env.clients[2].finish_blocks_in_processing().is_empty() {
+            break;
+        }
+    }
+    assert_eq!(batch_sizes, vec![num_shards]);
+    assert_eq!(&env.clients[2].chain.head().unwrap().last_block_hash, last_block.hash());
+
+    let shard_layout =
+        env.clients[0].epoch_manager.get_shard_layout(last_block.header().epoch_id()).unwrap();
+    for shard_uid in shard_layout.get_shard_uids() {
+        let expected = env.clients[0].chain.get_chunk_extra(last_block.hash(), &shard_uid).unwrap();
+        for idx in 1..3 {
+            let chunk_extra =
+                env.clients[idx].chain.get_chunk_extra(last_block.hash(), &shard_uid).unwrap();
+            assert_eq!(chunk_extra, expected);
+        }
     }
-    env.produce_block(0, 11);
 }
 
 #[test]
@@ -3761,3 +4027,692 @@ mod contract_precompilation_tests {
         assert!(caches[1].get(&contract_key).unwrap().is_none());
     }
 }
+
+/// The chunk producer assignment resolved once per epoch covers every shard the node is the chunk
+/// producer for at any height of the epoch.
+#[test]
+fn test_chunk_producer_assignment_matches_per_height_lookups() {
+    init_test_logger();
+    let epoch_length = 10;
+    let num_shards = 4;
+    let accounts: Vec<AccountId> = (0..4).map(|i| format!("test{}", i).parse().unwrap()).collect();
+    let mut genesis = Genesis::test_sharded_new_version(accounts, 4, vec![2; num_shards]);
+    genesis.config.epoch_length = epoch_length;
+    let chain_genesis = ChainGenesis::new(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients_count(4)
+        .validator_seats(4)
+        .real_epoch_managers(&genesis.config)
+        .nightshade_runtimes(&genesis)
+        .build();
+
+    let epoch_id = env.clients[0].chain.head().unwrap().epoch_id;
+    let mut all_assigned_shards = HashSet::new();
+    for client in env.clients.iter_mut() {
+        let account_id = client.validator_signer.as_ref().unwrap().validator_id().clone();
+        let assigned_shards = client.chunk_producer_shards(&epoch_id).unwrap();
+        assert_eq!(client.chunk_producer_shards(&epoch_id).unwrap(), assigned_shards);
+        for height in 1..=epoch_length {
+            for shard_id in 0..num_shards as ShardId {
+                let chunk_producer =
+                    client.epoch_manager.get_chunk_producer(&epoch_id, height, shard_id).unwrap();
+                if chunk_producer == account_id {
+                    assert!(
+                        assigned_shards.contains(&shard_id),
+                        "{account_id} produces a chunk for shard {shard_id} at height {height}, \
+                        but is only assigned to shards {assigned_shards:?}"
+                    );
+                }
+            }
+        }
+        all_assigned_shards.extend(assigned_shards);
+    }
+    assert_eq!(all_assigned_shards.len(), num_shards);
+}
+
+/// Sets up two clients where the second one is missing the chunks of the third block, and writes
+/// completed chunks through `persist_fn`. Returns the blocks produced by the first client.
+fn setup_chunk_persistence_env(
+    num_shards: usize,
+    persist_fn: PersistChunkFn,
+) -> (TestEnv, Vec<Block>) {
+    let accounts = vec!["test0".parse().unwrap(), "test1".parse().unwrap()];
+    let genesis = Genesis::test_sharded_new_version(accounts, 1, vec![1; num_shards]);
+    let chain_genesis = ChainGenesis::new(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients_count(2)
+        .real_epoch_managers(&genesis.config)
+        .track_all_shards()
+        .nightshade_runtimes(&genesis)
+        .build();
+
+    let mut blocks = vec![];
+    for i in 1..=3 {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        blocks.push(block.clone());
+        env.process_block(0, block, Provenance::PRODUCED);
+    }
+    for block in &blocks[..2] {
+        let _ = env.clients[1].process_block_test(block.clone().into(), Provenance::NONE);
+        env.process_partial_encoded_chunks_requests(1);
+        env.process_shards_manager_responses_and_finish_processing_blocks(1);
+    }
+    assert_eq!(env.clients[1].chain.head().unwrap().height, 2);
+
+    let store = env.clients[1].chain.store().store().clone();
+    // One writer thread per shard, so that the chunks of different shards are written concurrently.
+    let mut config = env.clients[1].config.clone();
+    config.chunk_writer_threads = num_shards;
+    env.clients[1].chunk_persister = ChunkPersister::new_with_persist_fn(
+        store,
+        genesis.config.genesis_height,
+        &config,
+        persist_fn,
+    );
+    let res = env.clients[1].process_block_test(blocks[2].clone().into(), Provenance::NONE);
+    assert_matches!(res.unwrap_err(), near_chain::Error::ChunksMissing(_));
+    env.process_partial_encoded_chunks_requests(1);
+    (env, blocks)
+}
+
+/// Hands the chunks completed by the shards manager of the client over to the client, without
+/// waiting for them to be persisted.
+fn complete_chunks_without_waiting(env: &mut TestEnv, id: usize) {
+    while let Some(msg) = env.client_adapters[id].pop() {
+        match msg {
+            ShardsManagerResponse::ChunkCompleted { partial_chunk, shard_chunk } => {
+                env.clients[id].on_chunk_completed(partial_chunk, shard_chunk, Arc::new(|_| {}));
+            }
+            ShardsManagerResponse::ChunksCompleted(chunks) => {
+                env.clients[id].on_chunks_completed(chunks, Arc::new(|_| {}));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Completed chunks are written off the client thread: the client keeps handling approvals while
+/// the store is slow, and the block waiting for the chunk is only processed once it's written.
+#[test]
+fn test_chunk_persisted_in_background() {
+    init_test_logger();
+    let (release_sender, release_receiver) = std::sync::mpsc::channel::<()>();
+    let release_receiver = Arc::new(Mutex::new(release_receiver));
+    let (mut env, blocks) = setup_chunk_persistence_env(
+        1,
+        Arc::new(move |partial_chunk, shard_chunk, chain_store| {
+            release_receiver.lock().unwrap().recv().unwrap();
+            persist_chunk(partial_chunk, shard_chunk, chain_store)
+        }),
+    );
+
+    complete_chunks_without_waiting(&mut env, 1);
+    assert_eq!(env.clients[1].chunk_persister.num_pending(), 1);
+
+    // The approval for the next height is handled while the chunk is being written. The block it
+    // endorses isn't processed yet, so it's kept until the block is.
+    let signer = create_test_signer("test0");
+    let approval = Approval::new(*blocks[2].hash(), 3, 4, &signer);
+    env.clients[1].collect_block_approval(&approval, ApprovalType::PeerApproval(PeerId::random()));
+    assert!(env.clients[1].pending_approvals.contains(&approval.inner));
+    env.clients[1].process_persisted_chunks(Arc::new(|_| {}));
+    assert_eq!(env.clients[1].chain.head().unwrap().height, 2);
+
+    release_sender.send(()).unwrap();
+    env.clients[1].finish_chunk_persistence();
+    env.clients[1].finish_blocks_in_processing();
+    assert_eq!(env.clients[1].chunk_persister.num_pending(), 0);
+    assert_eq!(env.clients[1].chain.head().unwrap().last_block_hash, *blocks[2].hash());
+    assert!(!env.clients[1].pending_approvals.contains(&approval.inner));
+    assert_eq!(env.clients[1].chunk_persistence_error(), None);
+}
+
+/// Chunks of different shards are written at the same time: a write only goes through once the
+/// writes of all the shards have started, which never happens if they are written one by one.
+#[test]
+fn test_chunks_of_different_shards_persisted_concurrently() {
+    init_test_logger();
+    let num_shards = 4;
+    let started_writes = Arc::new((Mutex::new(HashSet::new()), Condvar::new()));
+    let (mut env, blocks) = setup_chunk_persistence_env(num_shards, {
+        let started_writes = started_writes.clone();
+        Arc::new(move |partial_chunk, shard_chunk, chain_store| {
+            let (started_shards, all_started) = &*started_writes;
+            let mut started_shards = started_shards.lock().unwrap();
+            started_shards.insert(partial_chunk.shard_id());
+            all_started.notify_all();
+            let (started_shards, wait_result) = all_started
+                .wait_timeout_while(started_shards, Duration::from_secs(10), |started_shards| {
+                    started_shards.len() < num_shards
+                })
+                .unwrap();
+            drop(started_shards);
+            if wait_result.timed_out() {
+                return Err(near_chunks::Error::ChainError(near_chain::Error::Other(
+                    "chunks written one by one".to_string(),
+                )));
+            }
+            persist_chunk(partial_chunk, shard_chunk, chain_store)
+        })
+    });
+
+    complete_chunks_without_waiting(&mut env, 1);
+    assert_eq!(env.clients[1].chunk_persister.num_pending(), num_shards);
+    assert_eq!(env.clients[1].chain.head().unwrap().height, 2);
+
+    env.clients[1].finish_chunk_persistence();
+    env.clients[1].finish_blocks_in_processing();
+    assert_eq!(env.clients[1].chunk_persistence_error(), None);
+    assert_eq!(started_writes.0.lock().unwrap().len(), num_shards);
+    assert_eq!(env.clients[1].chain.head().unwrap().last_block_hash, *blocks[2].hash());
+}
+
+/// A chunk which can't be written is retried, then reported as a persistence error, and the block
+/// waiting for it is not processed.
+#[test]
+fn test_chunk_persistence_failure() {
+    init_test_logger();
+    let num_attempts = Arc::new(AtomicUsize::new(0));
+    let (mut env, _blocks) = setup_chunk_persistence_env(1, {
+        let num_attempts = num_attempts.clone();
+        Arc::new(move |_, _, _| {
+            num_attempts.fetch_add(1, Ordering::SeqCst);
+            Err(near_chunks::Error::ChainError(near_chain::Error::Other("disk full".to_string())))
+        })
+    });
+
+    complete_chunks_without_waiting(&mut env, 1);
+    env.clients[1].finish_chunk_persistence();
+    env.clients[1].finish_blocks_in_processing();
+    assert!(num_attempts.load(Ordering::SeqCst) > 1);
+    assert_eq!(env.clients[1].chain.head().unwrap().height, 2);
+    assert!(env.clients[1].chunk_persistence_error().unwrap().contains("disk full"));
+}
+
+/// A write which fails once is retried, and the chunk is accepted once the retry succeeds.
+#[test]
+fn test_chunk_persistence_transient_failure() {
+    init_test_logger();
+    let num_attempts = Arc::new(AtomicUsize::new(0));
+    let (mut env, blocks) = setup_chunk_persistence_env(1, {
+        let num_attempts = num_attempts.clone();
+        Arc::new(move |partial_chunk, shard_chunk, chain_store| {
+            if num_attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(near_chunks::Error::ChainError(near_chain::Error::Other(
+                    "transient".to_string(),
+                )));
+            }
+            persist_chunk(partial_chunk, shard_chunk, chain_store)
+        })
+    });
+
+    complete_chunks_without_waiting(&mut env, 1);
+    env.clients[1].finish_chunk_persistence();
+    env.clients[1].finish_blocks_in_processing();
+    assert_eq!(num_attempts.load(Ordering::SeqCst), 2);
+    assert_eq!(env.clients[1].chunk_persistence_error(), None);
+    assert_eq!(env.clients[1].chain.head().unwrap().last_block_hash, *blocks[2].hash());
+}
+
+/// The writes of the clients to the store are recorded with the block or chunk they were made for:
+/// the latest known height of the produced blocks, the produced chunks, and the chunks completed
+/// by the shards manager and written in the background.
+#[test]
+fn test_store_audit_log() {
+    init_test_logger();
+    let (mut env, blocks) = setup_chunk_persistence_env(1, Arc::new(persist_chunk));
+    complete_chunks_without_waiting(&mut env, 1);
+    env.clients[1].finish_chunk_persistence();
+
+    let produced_writes: Vec<_> = env.clients[0]
+        .store_audit_log()
+        .entries()
+        .filter(|entry| entry.error.is_none())
+        .map(|entry| entry.write.clone())
+        .collect();
+    for block in &blocks {
+        let write =
+            StoreWrite::LatestKnown { height: block.header().height(), block_hash: *block.hash() };
+        assert!(produced_writes.contains(&write), "{write:?} wasn't audited");
+    }
+    let chunk_header = &blocks[2].chunks()[0];
+    let chunk_write = StoreWrite::Chunk {
+        chunk_hash: chunk_header.chunk_hash(),
+        shard_id: chunk_header.shard_id(),
+        height_created: chunk_header.height_created(),
+    };
+    assert!(produced_writes.contains(&chunk_write));
+
+    let persisted = env.clients[1].store_audit_log().entries().last().unwrap();
+    assert_eq!(persisted.write, chunk_write);
+    assert_eq!(persisted.error, None);
+    assert_eq!(persisted.write.columns(), &[DBCol::PartialChunks, DBCol::Chunks]);
+}
+
+/// When the chunk many blocks were waiting for arrives, the blocks are started in ascending
+/// height order, at most `max_blocks_with_missing_chunks_started` at once, and the rest are
+/// started as these finish.
+#[test]
+fn test_blocks_with_missing_chunks_started_within_budget() {
+    init_test_logger();
+    let accounts = vec!["test0".parse().unwrap(), "test1".parse().unwrap()];
+    let genesis = Genesis::test(accounts, 1);
+    let chain_genesis = ChainGenesis::new(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .clients_count(2)
+        .real_epoch_managers(&genesis.config)
+        .track_all_shards()
+        .nightshade_runtimes(&genesis)
+        .build();
+
+    let mut blocks = vec![];
+    for i in 1..=3 {
+        let block = env.clients[0].produce_block(i).unwrap().unwrap();
+        blocks.push(block.clone());
+        env.process_block(0, block, Provenance::PRODUCED);
+    }
+    for block in &blocks {
+        let _ = env.clients[1].process_block_test(block.clone().into(), Provenance::NONE);
+        env.process_partial_encoded_chunks_requests(1);
+        env.process_shards_manager_responses_and_finish_processing_blocks(1);
+    }
+    assert_eq!(env.clients[1].chain.head().unwrap().height, 3);
+
+    // 30 forks on top of the head, all including the same chunk, which client 1 doesn't have.
+    let forks: Vec<Block> = (4..34)
+        .map(|height| env.clients[0].produce_block_on(height, *blocks[2].hash()).unwrap().unwrap())
+        .collect();
+    for block in &forks {
+        let res = env.clients[1].process_block_test(block.clone().into(), Provenance::NONE);
+        assert_matches!(res.unwrap_err(), near_chain::Error::ChunksMissing(_));
+    }
+    assert_eq!(env.clients[1].chain.blocks_with_missing_chunks.len(), forks.len());
+
+    let budget = env.clients[1].config.max_blocks_with_missing_chunks_started;
+    env.process_partial_encoded_chunks_requests(1);
+    complete_chunks_without_waiting(&mut env, 1);
+    env.clients[1].finish_chunk_persistence();
+    let mut num_started = 0;
+    while num_started < forks.len() {
+        let in_processing: Vec<usize> = (0..forks.len())
+            .filter(|&i| env.clients[1].chain.is_in_processing(forks[i].hash()))
+            .collect();
+        assert!(!in_processing.is_empty() && in_processing.len() <= budget, "{in_processing:?}");
+        assert_eq!(
+            in_processing,
+            (num_started..num_started + in_processing.len()).collect::<Vec<_>>()
+        );
+        num_started += in_processing.len();
+        assert_eq!(
+            env.clients[1].chain.blocks_with_missing_chunks.num_ready_blocks(),
+            forks.len() - num_started
+        );
+        // Postprocessing the started blocks starts the next ones.
+        wait_for_all_blocks_in_processing(&env.clients[1].chain);
+        let (accepted_blocks, errors) =
+            env.clients[1].postprocess_ready_blocks(Arc::new(|_| {}), true);
+        assert_eq!(accepted_blocks.len(), in_processing.len());
+        assert!(errors.is_empty());
+    }
+    assert_eq!(env.clients[1].chain.blocks_in_processing_len(), 0);
+    assert_eq!(env.clients[1].chain.head().unwrap().last_block_hash, *forks[29].hash());
+    for block in &forks {
+        assert!(env.clients[1].chain.get_block(block.hash()).is_ok());
+    }
+}
+
+/// The production report of a finished epoch flags the block and chunks the node didn't produce
+/// on time, with the reason, and is written to the configured file when the epoch ends.
+#[test]
+fn test_epoch_production_report() {
+    init_test_logger();
+    let epoch_length = 10;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let chain_genesis = ChainGenesis::new(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .real_epoch_managers(&genesis.config)
+        .nightshade_runtimes(&genesis)
+        .build();
+    let dir = tempfile::tempdir().unwrap();
+    let report_path = dir.path().join("production_report.json");
+    env.clients[0].config.production_report_path = Some(report_path.clone());
+
+    // Miss a slot in the middle of the second epoch: no chunk is produced on top of the block
+    // before it, so with empty blocks disabled there is nothing to produce a block with. Stop
+    // once that epoch is over.
+    let missed_height = epoch_length + 5;
+    let mut missed_epoch_id = None;
+    for height in 1.. {
+        if height == missed_height {
+            env.clients[0].config.produce_empty_blocks = false;
+            assert!(env.clients[0].produce_block(height).unwrap().is_none());
+            env.clients[0].config.produce_empty_blocks = true;
+            continue;
+        }
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        let epoch_id = block.header().epoch_id().clone();
+        if height == missed_height - 1 {
+            env.clients[0]
+                .process_block_test_no_produce_chunk(block.into(), Provenance::PRODUCED)
+                .unwrap();
+        } else {
+            env.process_block(0, block, Provenance::PRODUCED);
+        }
+        if height == missed_height + 1 {
+            missed_epoch_id = Some(epoch_id.clone());
+        }
+        if missed_epoch_id.as_ref().map_or(false, |missed_epoch_id| missed_epoch_id != &epoch_id) {
+            break;
+        }
+    }
+
+    let report = env.clients[0].last_epoch_production_report().unwrap();
+    assert_eq!(Some(report.epoch_id.clone()), missed_epoch_id);
+    assert!(report.is_final);
+    assert!(report.first_height < missed_height && missed_height < report.last_height);
+    let num_heights = (report.first_height..=report.last_height).count();
+    assert_eq!(report.entries.len(), 2 * num_heights);
+    let missed: Vec<_> = report
+        .entries
+        .iter()
+        .filter(|entry| !entry.included)
+        .map(|entry| (entry.height, entry.shard_id, entry.produced, entry.missed_reason.clone()))
+        .collect();
+    assert_eq!(
+        missed,
+        vec![
+            (
+                missed_height,
+                None,
+                false,
+                Some("No new chunks and empty blocks are disabled".to_string())
+            ),
+            (missed_height, Some(0), false, Some("Chunk was not produced".to_string())),
+            (missed_height + 1, Some(0), false, Some("Chunk was not produced".to_string())),
+        ]
+    );
+    for entry in report.entries.iter().filter(|entry| entry.included) {
+        assert!(entry.produced);
+        assert!(entry.production_time.is_some(), "{:?}", entry);
+    }
+
+    // The report was written in the background when the epoch ended.
+    env.clients[0].finish_production_report_write();
+    let written: EpochProductionReport =
+        serde_json::from_slice(&std::fs::read(&report_path).unwrap()).unwrap();
+    assert_eq!(written, report);
+}
+
+/// Simulating the production of the next block doesn't change anything, and predicts the block
+/// which is then actually produced, or why it isn't.
+#[test]
+fn test_simulate_block_production() {
+    init_test_logger();
+    let genesis = Genesis::test(vec!["test0".parse().unwrap()], 1);
+    let chain_genesis = ChainGenesis::new(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .real_epoch_managers(&genesis.config)
+        .nightshade_runtimes(&genesis)
+        .build();
+
+    // No chunk is produced on top of blocks 3 and 5, so blocks 4 and 6 have no new chunks.
+    for height in 1..=5 {
+        let latest_known = env.clients[0].chain.store().get_latest_known().unwrap();
+        let simulated = match env.clients[0].simulate_block_production().unwrap() {
+            SimulatedBlockProduction::Produced(simulated) => simulated,
+            skipped => panic!("Unexpected simulation result {:?}", skipped),
+        };
+        let latest_known_after = env.clients[0].chain.store().get_latest_known().unwrap();
+        assert_eq!(
+            (latest_known_after.height, latest_known_after.seen),
+            (latest_known.height, latest_known.seen)
+        );
+
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        assert_eq!(simulated.height, height);
+        assert_eq!(&simulated.prev_hash, block.header().prev_hash());
+        assert_eq!(simulated.chunk_mask, block.header().chunk_mask());
+        if height == 4 {
+            assert_eq!(simulated.chunk_mask, vec![false]);
+        }
+        let approvers = env.clients[0]
+            .epoch_manager
+            .get_epoch_block_approvers_ordered(block.header().prev_hash())
+            .unwrap();
+        let mut approvals: Vec<_> = approvers
+            .into_iter()
+            .zip(block.header().approvals())
+            .filter(|(_, approval)| approval.is_some())
+            .map(|((approver, _), _)| approver.account_id)
+            .collect();
+        approvals.sort();
+        assert_eq!(simulated.approvals, approvals);
+        assert_eq!(simulated.gas_price, block.header().next_gas_price());
+        assert_eq!(simulated.estimated_size_bytes, borsh::object_length(&block).unwrap() as u64);
+
+        if height == 3 || height == 5 {
+            env.clients[0]
+                .process_block_test_no_produce_chunk(block.into(), Provenance::PRODUCED)
+                .unwrap();
+        } else {
+            env.process_block(0, block, Provenance::PRODUCED);
+        }
+    }
+
+    env.clients[0].config.produce_empty_blocks = false;
+    assert_eq!(
+        env.clients[0].simulate_block_production().unwrap(),
+        SimulatedBlockProduction::Skipped {
+            height: 6,
+            reason: "No new chunks and empty blocks are disabled".to_string()
+        }
+    );
+    assert!(env.clients[0].produce_block(6).unwrap().is_none());
+}
+
+/// Produces the block at `height` on client 0 without producing the chunks of the next height.
+fn produce_block_without_chunks(env: &mut TestEnv, height: BlockHeight) {
+    let block = env.clients[0].produce_block(height).unwrap().unwrap();
+    env.clients[0].process_block_test_no_produce_chunk(block.into(), Provenance::PRODUCED).unwrap();
+}
+
+/// A TestEnv whose client doesn't consider the head stale while the test runs.
+fn health_test_env() -> TestEnv {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    env.clients[0].config.max_block_production_delay = std::time::Duration::from_secs(60);
+    env
+}
+
+#[test]
+fn test_health_missed_chunks() {
+    init_test_logger();
+    let mut env = health_test_env();
+    for height in 1..=6 {
+        env.produce_block(0, height);
+    }
+    let health = env.clients[0].health();
+    assert_eq!(health.state, NodeHealthState::Validating);
+    assert_eq!(health.factors, vec![]);
+
+    // A single missed chunk doesn't change the health.
+    produce_block_without_chunks(&mut env, 7);
+    env.produce_block(0, 8);
+    assert_eq!(env.clients[0].health().state, NodeHealthState::Validating);
+    env.produce_block(0, 9);
+    assert_eq!(env.clients[0].health().state, NodeHealthState::Validating);
+
+    // Chunks missed on 3 new heads in a row do.
+    for height in 10..=12 {
+        produce_block_without_chunks(&mut env, height);
+    }
+    env.produce_block(0, 13);
+    let health = env.clients[0].health();
+    assert_eq!(health.state, NodeHealthState::Degraded);
+    assert_eq!(
+        health.factors,
+        vec![NodeHealthFactor::MissedChunks { height: 13, shard_ids: vec![0] }]
+    );
+
+    // The recovery is reported once the chunks were included on 3 new heads in a row.
+    env.produce_block(0, 14);
+    env.produce_block(0, 15);
+    assert_eq!(env.clients[0].health().state, NodeHealthState::Degraded);
+    env.produce_block(0, 16);
+    assert_eq!(env.clients[0].health().state, NodeHealthState::Validating);
+}
+
+#[test]
+fn test_health_behind_and_critical() {
+    init_test_logger();
+    let mut env = health_test_env();
+    for height in 1..=5 {
+        env.produce_block(0, height);
+    }
+    assert_eq!(env.clients[0].health().state, NodeHealthState::Validating);
+
+    // A stale head and a clock behind the chain are reported right away.
+    let head_time = env.clients[0].chain.head_header().unwrap().timestamp();
+    let health = env.clients[0].health_at(head_time + chrono::Duration::hours(1));
+    assert_eq!(health.state, NodeHealthState::Behind);
+    assert_matches!(health.factors.as_slice(), [NodeHealthFactor::HeadStale { .. }]);
+    let health = env.clients[0].health_at(head_time - chrono::Duration::hours(1));
+    assert_eq!(health.state, NodeHealthState::Critical);
+    assert_matches!(health.factors.as_slice(), [NodeHealthFactor::ClockSkew { .. }]);
+
+    // Syncing is reported once observed on 3 new heads. No chunks are produced while syncing.
+    env.clients[0].sync_status =
+        SyncStatus::BodySync { start_height: 5, current_height: 5, highest_height: 100 };
+    for height in 6..=7 {
+        env.produce_block(0, height);
+    }
+    assert_eq!(env.clients[0].health().state, NodeHealthState::Validating);
+    env.produce_block(0, 8);
+    let health = env.clients[0].health();
+    assert_eq!(health.state, NodeHealthState::Behind);
+    assert!(health.factors.contains(&NodeHealthFactor::Syncing));
+
+    env.clients[0].client_state = ClientState::IncompatibleProtocolVersion {
+        network: PROTOCOL_VERSION + 1,
+        ours: PROTOCOL_VERSION,
+    };
+    let health = env.clients[0].health();
+    assert_eq!(health.state, NodeHealthState::Critical);
+    assert!(health.factors.contains(&NodeHealthFactor::IncompatibleProtocolVersion {
+        network: PROTOCOL_VERSION + 1,
+        ours: PROTOCOL_VERSION
+    }));
+}
+
+#[test]
+fn test_health_idle() {
+    init_test_logger();
+    let env = TestEnv::builder(ChainGenesis::test()).clients_count(2).validator_seats(1).build();
+    let head_time = env.clients[1].chain.head_header().unwrap().timestamp();
+    let health = env.clients[1].health_at(head_time);
+    assert_eq!(health.state, NodeHealthState::Idle);
+    assert_eq!(health.factors, vec![NodeHealthFactor::NotValidator]);
+    assert_eq!(env.clients[0].health_at(head_time).state, NodeHealthState::Validating);
+}
+
+#[test]
+fn test_health_catchup_pending() {
+    init_test_logger();
+    let mut env = health_test_env();
+    for height in 1..=5 {
+        env.produce_block(0, height);
+    }
+    let epoch_tail_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+    let mut store_update = env.clients[0].chain.mut_store().store_update();
+    store_update.add_state_sync_info(StateSyncInfo { epoch_tail_hash, shards: vec![] });
+    store_update.commit().unwrap();
+    for height in 6..=8 {
+        env.produce_block(0, height);
+    }
+    let health = env.clients[0].health();
+    assert_eq!(health.state, NodeHealthState::Degraded);
+    assert_eq!(health.factors, vec![NodeHealthFactor::CatchupPending]);
+
+    let mut store_update = env.clients[0].chain.mut_store().store_update();
+    store_update.remove_state_sync_info(epoch_tail_hash);
+    store_update.commit().unwrap();
+    for height in 9..=11 {
+        env.produce_block(0, height);
+    }
+    assert_eq!(env.clients[0].health().state, NodeHealthState::Validating);
+}
+
+#[test]
+fn test_health_no_recent_approvals() {
+    init_test_logger();
+    let mut env = health_test_env();
+    // Approvals are only expected when the block production waits for them.
+    let signer: Arc<dyn ValidatorSigner> = Arc::new(create_test_signer("test0"));
+    let config = env.clients[0].config.clone();
+    env.clients[0].doomslug = Doomslug::new(
+        env.clients[0].chain.store().largest_target_height().unwrap(),
+        config.min_block_production_delay,
+        config.max_block_production_delay,
+        config.max_block_production_delay / 10,
+        config.max_block_wait_delay,
+        Some(signer.clone()),
+        DoomslugThresholdMode::TwoThirds,
+    );
+    for height in 1..=6 {
+        env.produce_block(0, height);
+    }
+    let health = env.clients[0].health();
+    assert_eq!(health.state, NodeHealthState::Degraded);
+    assert_eq!(
+        health.factors,
+        vec![NodeHealthFactor::NoRecentApprovals { last_approval_height: None }]
+    );
+
+    let head = env.clients[0].chain.head().unwrap();
+    let approval = Approval::new(head.last_block_hash, head.height, head.height + 1, &*signer);
+    env.clients[0].send_approval(&head.last_block_hash, approval).unwrap();
+    for height in 7..=9 {
+        env.produce_block(0, height);
+    }
+    let health = env.clients[0].health();
+    assert_eq!(health.state, NodeHealthState::Validating);
+    assert_eq!(health.factors, vec![]);
+}
+
+/// The sweep deletes the StateSyncInfo of a catchup for an epoch too far behind the head, so that
+/// it's not started again, and keeps the one of a recent epoch.
+#[test]
+fn test_sweep_deletes_stale_state_sync_info() {
+    init_test_logger();
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    let chain_genesis = ChainGenesis::new(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .real_epoch_managers(&genesis.config)
+        .nightshade_runtimes(&genesis)
+        .build();
+    for height in 1..=5 * epoch_length {
+        env.produce_block(0, height);
+    }
+    let client = &mut env.clients[0];
+    client.config.catchup_state_sync_max_age_epochs = 2;
+    let stale_hash = *client.chain.get_block_by_height(1).unwrap().hash();
+    let recent_hash = client.chain.head().unwrap().last_block_hash;
+    let mut store_update = client.chain.mut_store().store_update();
+    for epoch_tail_hash in [stale_hash, recent_hash] {
+        store_update.add_state_sync_info(StateSyncInfo { epoch_tail_hash, shards: vec![] });
+    }
+    store_update.commit().unwrap();
+
+    client.sweep_catchup_state_syncs().unwrap();
+    let sync_hashes: Vec<_> = client
+        .chain
+        .store()
+        .iterate_state_sync_infos()
+        .unwrap()
+        .into_iter()
+        .map(|(sync_hash, _)| sync_hash)
+        .collect();
+    assert_eq!(sync_hashes, vec![recent_hash]);
+}
@@ -6,6 +6,7 @@ use near_chain::{ChainGenesis, ChainStoreAccess, Provenance};
 use near_chain_configs::Genesis;
 use near_client::test_utils::{run_catchup, TestEnv};
 use near_client::{Client, ProcessTxResponse};
+use near_client_primitives::debug::ReshardingEvent;
 use near_crypto::{InMemorySigner, KeyType, Signer};
 use near_o11y::testonly::init_test_logger;
 use near_primitives::account::id::AccountId;
@@ -630,6 +631,51 @@ impl TestReshardingEnv {
         }
     }
 
+    /// Checks that every client logged scheduling and then completing the split of each of the
+    /// parent shards, exactly once and for the same sync hash.
+    fn check_resharding_log(&self, resharding_type: &ReshardingType) {
+        tracing::debug!(target: "test", "checking resharding log");
+        for client in &self.env.clients {
+            let events: Vec<_> =
+                client.resharding_log.entries().into_iter().map(|entry| entry.event).collect();
+            for shard_uid in get_parent_shard_uids(resharding_type) {
+                let shard_id = shard_uid.shard_id();
+                let scheduled: Vec<_> = events
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, event)| match event {
+                        ReshardingEvent::SplitScheduled { sync_hash, shard_id: id }
+                            if *id == shard_id =>
+                        {
+                            Some((i, *sync_hash))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                let completed: Vec<_> = events
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, event)| match event {
+                        ReshardingEvent::SplitCompleted { sync_hash, shard_id: id }
+                            if *id == shard_id =>
+                        {
+                            Some((i, *sync_hash))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                assert_eq!(scheduled.len(), 1, "shard {shard_id}: {events:?}");
+                assert_eq!(completed.len(), 1, "shard {shard_id}: {events:?}");
+                assert!(scheduled[0].0 < completed[0].0, "shard {shard_id}: {events:?}");
+                assert_eq!(scheduled[0].1, completed[0].1, "shard {shard_id}: {events:?}");
+            }
+            assert!(
+                !events.iter().any(|event| matches!(event, ReshardingEvent::SplitFailed { .. })),
+                "{events:?}"
+            );
+        }
+    }
+
     fn check_outgoing_receipts_reassigned(&self, resharding_type: &ReshardingType) {
         tracing::debug!(target: "test", "checking outgoing receipts reassigned");
         let env = &self.env;
@@ -983,6 +1029,7 @@ fn test_shard_layout_upgrade_simple_impl(
     test_env.check_accounts(accounts_to_check.iter().collect());
     test_env.check_split_states_artifacts();
     test_env.check_outgoing_receipts_reassigned(&resharding_type);
+    test_env.check_resharding_log(&resharding_type);
     tracing::info!(target: "test", "test_shard_layout_upgrade_simple_impl finished");
 }
 
@@ -91,6 +91,7 @@ fn setup_network_node(
         None,
         adv.clone(),
         None,
+        None,
     )
     .0;
     let view_client_actor = start_view_client(
@@ -111,6 +112,7 @@ fn setup_network_node(
         Some(signer.validator_id().clone()),
         runtime.store().clone(),
         client_config.chunk_request_retry_period,
+        client_config.chunk_request_retry_per_shard.clone(),
     );
     shards_manager_adapter.bind(shards_manager_actor.with_auto_span_context());
     let peer_manager = PeerManagerActor::spawn(
@@ -2,8 +2,9 @@ use crate::download_file::{run_download_file, FileDownloadError};
 use crate::dyn_config::LOG_CONFIG_FILENAME;
 use anyhow::{anyhow, bail, Context};
 use near_chain_configs::{
-    get_initial_supply, ClientConfig, GCConfig, Genesis, GenesisConfig, GenesisValidationMode,
-    LogSummaryStyle, MutableConfigValue, StateSplitConfig, StateSyncConfig,
+    get_initial_supply, ChunkRequestRetryConfig, ClientConfig, DelegateActionValidity, GCConfig,
+    Genesis, GenesisConfig, GenesisValidationMode, LogSummaryStyle, MutableConfigValue,
+    StateSplitConfig, StateSyncConfig, DEFAULT_PRODUCTION_INFO_CAPACITY,
 };
 use near_config_utils::{ValidationError, ValidationErrors};
 use near_crypto::{InMemorySigner, KeyFile, KeyType, PublicKey, Signer};
@@ -31,10 +32,11 @@ use near_primitives::version::PROTOCOL_VERSION;
 use near_rosetta_rpc::RosettaRpcConfig;
 use near_telemetry::TelemetryConfig;
 use num_rational::Rational32;
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -154,6 +156,10 @@ fn default_state_sync_timeout() -> Duration {
     Duration::from_secs(60)
 }
 
+fn default_catchup_state_sync_max_age_epochs() -> u64 {
+    2
+}
+
 fn default_header_sync_expected_height_per_second() -> u64 {
     10
 }
@@ -194,6 +200,62 @@ fn default_transaction_pool_size_limit() -> Option<u64> {
     Some(100_000_000) // 100 MB.
 }
 
+fn default_state_witness_size_soft_limit() -> Option<u64> {
+    Some(16_000_000) // 16 MB.
+}
+
+fn default_not_caught_up_tx_buffer_size() -> usize {
+    0
+}
+
+fn default_chunk_availability_window() -> BlockHeightDelta {
+    100
+}
+
+fn default_prioritize_block_before_production() -> bool {
+    false
+}
+
+fn default_max_blocks_with_missing_chunks() -> usize {
+    1024
+}
+
+fn default_max_block_with_missing_chunks_age() -> Duration {
+    Duration::from_secs(120)
+}
+
+fn default_max_blocks_with_missing_chunks_started() -> usize {
+    3
+}
+
+fn default_chunk_writer_threads() -> usize {
+    4
+}
+
+fn default_chunk_persist_retry_delay() -> Duration {
+    Duration::from_millis(100)
+}
+
+fn default_health_hysteresis_blocks() -> u64 {
+    3
+}
+
+fn default_recovery_burst_blocks() -> usize {
+    3
+}
+
+fn default_recovery_burst_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_reorg_tx_reintroduction_limit() -> Option<usize> {
+    Some(10_000)
+}
+
+fn default_reorg_tx_reintroduction_size_limit() -> Option<u64> {
+    Some(10_000_000) // 10 MB
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct Consensus {
     /// Minimum number of peers to start syncing.
@@ -216,6 +278,10 @@ pub struct Consensus {
     pub catchup_step_period: Duration,
     /// Time between checking to re-request chunks.
     pub chunk_request_retry_period: Duration,
+    /// Timings of the chunk requests of specific shards, for example shorter ones for the shards
+    /// with small chunks.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub chunk_request_retry_per_shard: HashMap<ShardId, ChunkRequestRetryConfig>,
     /// How much time to wait after initial header sync
     #[serde(default = "default_header_sync_initial_timeout")]
     pub header_sync_initial_timeout: Duration,
@@ -228,6 +294,9 @@ pub struct Consensus {
     /// How much to wait for a state sync response before re-requesting
     #[serde(default = "default_state_sync_timeout")]
     pub state_sync_timeout: Duration,
+    /// Number of epochs after which an unfinished catchup state sync is dropped
+    #[serde(default = "default_catchup_state_sync_max_age_epochs")]
+    pub catchup_state_sync_max_age_epochs: u64,
     /// Expected increase of header head weight per second during header sync
     #[serde(default = "default_header_sync_expected_height_per_second")]
     pub header_sync_expected_height_per_second: u64,
@@ -257,10 +326,12 @@ impl Default for Consensus {
             block_header_fetch_horizon: BLOCK_HEADER_FETCH_HORIZON,
             catchup_step_period: Duration::from_millis(CATCHUP_STEP_PERIOD),
             chunk_request_retry_period: Duration::from_millis(CHUNK_REQUEST_RETRY_PERIOD),
+            chunk_request_retry_per_shard: HashMap::new(),
             header_sync_initial_timeout: default_header_sync_initial_timeout(),
             header_sync_progress_timeout: default_header_sync_progress_timeout(),
             header_sync_stall_ban_timeout: default_header_sync_stall_ban_timeout(),
             state_sync_timeout: default_state_sync_timeout(),
+            catchup_state_sync_max_age_epochs: default_catchup_state_sync_max_age_epochs(),
             header_sync_expected_height_per_second: default_header_sync_expected_height_per_second(
             ),
             sync_check_period: default_sync_check_period(),
@@ -342,6 +413,97 @@ pub struct Config {
     /// chunks and underutilizing the capacity of the network.
     pub transaction_pool_size_limit: Option<u64>,
     pub state_split_config: StateSplitConfig,
+    /// How to check the `max_block_height` of delegate actions in meta-transactions. By default
+    /// the check is left to the runtime.
+    pub delegate_action_validity: DelegateActionValidity,
+    /// If set, the node writes the block and chunk production report of the last epoch to this
+    /// file at the end of every epoch in which it was a validator.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub production_report_path: Option<PathBuf>,
+    /// A warning is logged when the state witness of a chunk produced by this node, i.e. the trie
+    /// nodes recorded while applying the previous chunk, is larger than this many bytes.
+    /// Only used with the `protocol_feature_chunk_validation` feature.
+    pub state_witness_size_soft_limit: Option<u64>,
+    /// Forwarded transactions for a shard the node will track but hasn't caught up with yet are
+    /// kept, up to this many per shard, and processed once the catchup of the shard completes.
+    /// Disabled by default, i.e. such transactions are dropped.
+    pub not_caught_up_tx_buffer_size: usize,
+    /// Number of blocks below the head checked for missing chunks of the tracked shards when
+    /// reporting since which height the node can serve every chunk of them.
+    pub chunk_availability_window: BlockHeightDelta,
+    /// Whether a block whose chunks are being applied is processed before the other blocks when
+    /// this node produces the next block on top of it.
+    pub prioritize_block_before_production: bool,
+    /// Max number of blocks received before their chunks which are kept until the chunks arrive.
+    /// When there are more, the blocks with the highest heights are dropped.
+    pub max_blocks_with_missing_chunks: usize,
+    /// How long a block received before its chunks is kept waiting for them. A dropped block is
+    /// requested again from a peer if one of the chunks it was missing arrives later.
+    pub max_block_with_missing_chunks_age: Duration,
+    /// Max number of blocks received before their chunks which are processed at once when the
+    /// chunks arrive. The other blocks are processed as these finish, in height order, so that
+    /// a burst of chunks doesn't delay the processing of new blocks.
+    pub max_blocks_with_missing_chunks_started: usize,
+    /// Number of threads writing the completed chunks to the store. The chunks of a shard are
+    /// always written by the same thread.
+    pub chunk_writer_threads: usize,
+    /// Delay before retrying a failed write of a completed chunk, multiplied by the number of the
+    /// failed attempt.
+    pub chunk_persist_retry_delay: Duration,
+    /// Number of the latest blocks broadcast again when the head progresses after a stall. Peers
+    /// which missed them get them without requesting them. 0 disables it.
+    pub recovery_burst_blocks: usize,
+    /// Send the recovery burst also when the block which resumed progress was produced by
+    /// another node.
+    pub relay_recovery_burst: bool,
+    /// Min time between two recovery bursts, so that a node whose head keeps stalling doesn't
+    /// flood its peers with blocks.
+    pub recovery_burst_interval: Duration,
+    /// Max number of transactions per second submitted to this node directly, e.g. over RPC,
+    /// which are processed. Unlimited if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_tx_rate_limit: Option<u64>,
+    /// Max number of transactions per second forwarded by other nodes which are processed.
+    /// Unlimited if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forwarded_tx_rate_limit: Option<u64>,
+    /// Percentage of `transaction_pool_size_limit` which only transactions forwarded by other
+    /// nodes can fill, so that they are still accepted when directly submitted transactions fill
+    /// the pool. Nothing is reserved by default.
+    pub forwarded_tx_pool_reservation_percent: u64,
+    /// Max number of transactions of the blocks abandoned by a reorg which are added back to the
+    /// pool. The transactions of the blocks closest to the abandoned head are added first.
+    /// Unlimited if null.
+    pub reorg_tx_reintroduction_limit: Option<usize>,
+    /// Max total size in bytes of the transactions added back to the pool after a reorg.
+    /// Unlimited if null.
+    pub reorg_tx_reintroduction_size_limit: Option<u64>,
+    /// If set, the node syncs up to this height and stops there: blocks above it are neither
+    /// requested nor processed and the node doesn't produce blocks. For inspecting the state of
+    /// the chain at a given height.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync_until_height: Option<BlockHeight>,
+    /// Number of most recent heights for which the block production timings shown on the debug
+    /// page are kept.
+    pub block_production_info_capacity: usize,
+    /// Number of most recent heights for which the chunk production timings shown on the debug
+    /// page are kept, for every shard.
+    pub chunk_production_info_capacity_per_shard: usize,
+    /// Number of consecutive new heads for which a health state must be observed before the
+    /// status endpoint reports it, so that a single late or missed block doesn't change it.
+    /// Critical conditions, such as an incompatible protocol version, are reported right away.
+    pub health_hysteresis_blocks: u64,
+    /// Produce an empty chunk, without transactions and with the state of the previous chunk
+    /// carried over, when the production of a chunk fails because the state of the shard is
+    /// temporarily unavailable. Keeps the blocks full on chains which prefer empty chunks to
+    /// missing ones.
+    pub produce_empty_chunk_on_failure: bool,
+    /// Before producing a chunk, check that the chunk extra of the previous block matches the
+    /// trie changes recorded when the shard was applied at that block, and refuse to produce the
+    /// chunk if it doesn't. Reads the trie changes of the shard on every chunk production, so
+    /// it's meant for debugging invalid state root challenges.
+    #[serde(skip_serializing_if = "is_false")]
+    pub verify_chunk_extra_before_production: bool,
 }
 
 fn is_false(value: &bool) -> bool {
@@ -383,6 +545,32 @@ impl Default for Config {
             transaction_pool_size_limit: default_transaction_pool_size_limit(),
             enable_multiline_logging: None,
             state_split_config: StateSplitConfig::default(),
+            delegate_action_validity: DelegateActionValidity::default(),
+            production_report_path: None,
+            state_witness_size_soft_limit: default_state_witness_size_soft_limit(),
+            not_caught_up_tx_buffer_size: default_not_caught_up_tx_buffer_size(),
+            chunk_availability_window: default_chunk_availability_window(),
+            prioritize_block_before_production: default_prioritize_block_before_production(),
+            max_blocks_with_missing_chunks: default_max_blocks_with_missing_chunks(),
+            max_block_with_missing_chunks_age: default_max_block_with_missing_chunks_age(),
+            max_blocks_with_missing_chunks_started: default_max_blocks_with_missing_chunks_started(
+            ),
+            chunk_writer_threads: default_chunk_writer_threads(),
+            chunk_persist_retry_delay: default_chunk_persist_retry_delay(),
+            recovery_burst_blocks: default_recovery_burst_blocks(),
+            relay_recovery_burst: false,
+            recovery_burst_interval: default_recovery_burst_interval(),
+            local_tx_rate_limit: None,
+            forwarded_tx_rate_limit: None,
+            forwarded_tx_pool_reservation_percent: 0,
+            reorg_tx_reintroduction_limit: default_reorg_tx_reintroduction_limit(),
+            reorg_tx_reintroduction_size_limit: default_reorg_tx_reintroduction_size_limit(),
+            sync_until_height: None,
+            block_production_info_capacity: DEFAULT_PRODUCTION_INFO_CAPACITY,
+            chunk_production_info_capacity_per_shard: DEFAULT_PRODUCTION_INFO_CAPACITY,
+            health_hysteresis_blocks: default_health_hysteresis_blocks(),
+            produce_empty_chunk_on_failure: false,
+            verify_chunk_extra_before_production: false,
         }
     }
 }
@@ -647,6 +835,9 @@ impl NearConfig {
                     .consensus
                     .header_sync_expected_height_per_second,
                 state_sync_timeout: config.consensus.state_sync_timeout,
+                catchup_state_sync_max_age_epochs: config
+                    .consensus
+                    .catchup_state_sync_max_age_epochs,
                 min_num_peers: config.consensus.min_num_peers,
                 log_summary_period: config.log_summary_period,
                 produce_empty_blocks: config.consensus.produce_empty_blocks,
@@ -658,6 +849,7 @@ impl NearConfig {
                 block_header_fetch_horizon: config.consensus.block_header_fetch_horizon,
                 catchup_step_period: config.consensus.catchup_step_period,
                 chunk_request_retry_period: config.consensus.chunk_request_retry_period,
+                chunk_request_retry_per_shard: config.consensus.chunk_request_retry_per_shard,
                 doosmslug_step_period: config.consensus.doomslug_step_period,
                 tracked_accounts: config.tracked_accounts,
                 tracked_shards: config.tracked_shards,
@@ -680,6 +872,33 @@ impl NearConfig {
                 transaction_pool_size_limit: config.transaction_pool_size_limit,
                 enable_multiline_logging: config.enable_multiline_logging.unwrap_or(true),
                 state_split_config: config.state_split_config,
+                delegate_action_validity: config.delegate_action_validity,
+                production_report_path: config.production_report_path,
+                state_witness_size_soft_limit: config.state_witness_size_soft_limit,
+                not_caught_up_tx_buffer_size: config.not_caught_up_tx_buffer_size,
+                chunk_availability_window: config.chunk_availability_window,
+                prioritize_block_before_production: config.prioritize_block_before_production,
+                max_blocks_with_missing_chunks: config.max_blocks_with_missing_chunks,
+                max_block_with_missing_chunks_age: config.max_block_with_missing_chunks_age,
+                max_blocks_with_missing_chunks_started: config
+                    .max_blocks_with_missing_chunks_started,
+                chunk_writer_threads: config.chunk_writer_threads,
+                chunk_persist_retry_delay: config.chunk_persist_retry_delay,
+                recovery_burst_blocks: config.recovery_burst_blocks,
+                relay_recovery_burst: config.relay_recovery_burst,
+                recovery_burst_interval: config.recovery_burst_interval,
+                local_tx_rate_limit: config.local_tx_rate_limit,
+                forwarded_tx_rate_limit: config.forwarded_tx_rate_limit,
+                forwarded_tx_pool_reservation_percent: config.forwarded_tx_pool_reservation_percent,
+                reorg_tx_reintroduction_limit: config.reorg_tx_reintroduction_limit,
+                reorg_tx_reintroduction_size_limit: config.reorg_tx_reintroduction_size_limit,
+                sync_until_height: config.sync_until_height,
+                block_production_info_capacity: config.block_production_info_capacity,
+                chunk_production_info_capacity_per_shard: config
+                    .chunk_production_info_capacity_per_shard,
+                health_hysteresis_blocks: config.health_hysteresis_blocks,
+                produce_empty_chunk_on_failure: config.produce_empty_chunk_on_failure,
+                verify_chunk_extra_before_production: config.verify_chunk_extra_before_production,
             },
             network_config: NetworkConfig::new(
                 config.network,
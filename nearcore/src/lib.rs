@@ -345,6 +345,7 @@ pub fn start_with_config_and_synchronization(
         shutdown_signal,
         adv,
         config_updater,
+        None,
     );
     if let SyncConfig::Peers = config.client_config.state_sync.sync {
         client_adapter_for_sync.bind(client_actor.clone().with_auto_span_context())
@@ -358,6 +359,7 @@ pub fn start_with_config_and_synchronization(
         config.validator_signer.as_ref().map(|signer| signer.validator_id().clone()),
         split_store.unwrap_or(storage.get_hot_store()),
         config.client_config.chunk_request_retry_period,
+        config.client_config.chunk_request_retry_per_shard.clone(),
     );
     shards_manager_adapter.bind(shards_manager_actor.with_auto_span_context());
 
@@ -59,12 +59,15 @@ use node_runtime::{
 };
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use tracing::{debug, error, info};
 
 pub mod errors;
 
+/// Number of (block, shard) pairs for which the size of the recorded storage is remembered.
+const RECORDED_STORAGE_SIZES_CACHE_SIZE: usize = 1024;
+
 /// Defines Nightshade state transition and validator rotation.
 /// TODO: this possibly should be merged with the runtime cargo or at least reconciled on the interfaces.
 pub struct NightshadeRuntime {
@@ -78,6 +81,8 @@ pub struct NightshadeRuntime {
     epoch_manager: Arc<EpochManagerHandle>,
     migration_data: Arc<MigrationData>,
     gc_num_epochs_to_keep: u64,
+    /// Sizes of the storage recorded while applying chunks, by block hash and shard id.
+    recorded_storage_sizes: Mutex<lru::LruCache<(CryptoHash, ShardId), usize>>,
 }
 
 impl NightshadeRuntime {
@@ -162,6 +167,9 @@ impl NightshadeRuntime {
             epoch_manager,
             migration_data,
             gc_num_epochs_to_keep: gc_num_epochs_to_keep.max(MIN_GC_NUM_EPOCHS_TO_KEEP),
+            recorded_storage_sizes: Mutex::new(lru::LruCache::new(
+                RECORDED_STORAGE_SIZES_CACHE_SIZE,
+            )),
         })
     }
 
@@ -878,7 +886,15 @@ impl RuntimeAdapter for NightshadeRuntime {
             is_first_block_with_chunk_of_version,
             storage_config.state_patch,
         ) {
-            Ok(result) => Ok(result),
+            Ok(result) => {
+                if let Some(proof) = &result.proof {
+                    self.recorded_storage_sizes
+                        .lock()
+                        .unwrap()
+                        .put((*block_hash, shard_id), proof.nodes.size_in_bytes());
+                }
+                Ok(result)
+            }
             Err(e) => match e {
                 Error::StorageError(err) => match &err {
                     StorageError::FlatStorageBlockNotSupported(_) => Err(err.into()),
@@ -889,6 +905,14 @@ impl RuntimeAdapter for NightshadeRuntime {
         }
     }
 
+    fn get_recorded_storage_size(
+        &self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Option<usize> {
+        self.recorded_storage_sizes.lock().unwrap().get(&(*block_hash, shard_id)).copied()
+    }
+
     fn query(
         &self,
         shard_uid: ShardUId,
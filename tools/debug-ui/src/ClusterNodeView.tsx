@@ -94,7 +94,7 @@ export const ClusterNodeView = ({
 };
 
 function syncStatusToText(syncStatus: SyncStatusResponse): string {
-    const status = syncStatus.status_response.SyncStatus;
+    const status = syncStatus.status_response.SyncStatus.status;
     if (status == null) {
         return 'No sync status??';
     }
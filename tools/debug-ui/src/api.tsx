@@ -245,9 +245,18 @@ export interface KnownPeerStateView {
     last_attempt: [number, string] | null;
 }
 
+export interface SyncLogEntry {
+    time: string;
+    // Externally tagged, e.g. { HeadersRequested: { peer_id, ... } }.
+    event: Record<string, unknown>;
+}
+
 export interface SyncStatusResponse {
     status_response: {
-        SyncStatus: SyncStatusView;
+        SyncStatus: {
+            status: SyncStatusView;
+            log: SyncLogEntry[];
+        };
     };
 }
 
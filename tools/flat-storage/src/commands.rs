@@ -1,18 +1,22 @@
 /// Tools for modifying flat storage - should be used only for experimentation & debugging.
-use borsh::BorshDeserialize;
+use crate::serve::{self, flat_storage_statuses};
 use clap::Parser;
 use near_chain::flat_storage_creator::FlatStorageShardCreator;
 use near_chain::types::RuntimeAdapter;
-use near_chain::{ChainStore, ChainStoreAccess};
+use near_chain::{resolve_block_header, resolve_shard_uid_at, ChainStore, ChainStoreAccess};
 use near_chain_configs::GenesisValidationMode;
 use near_epoch_manager::{EpochManager, EpochManagerAdapter, EpochManagerHandle};
+use near_primitives::hash::CryptoHash;
 use near_primitives::shard_layout::ShardVersion;
-use near_primitives::types::{BlockHeight, ShardId};
+use near_primitives::types::{
+    BlockHeight, BlockId, BlockReference, Finality, ShardId, SyncCheckpoint,
+};
 use near_store::flat::{
     inline_flat_state_values, store_helper, FlatStateDelta, FlatStateDeltaMetadata,
     FlatStorageManager, FlatStorageStatus,
 };
-use near_store::{DBCol, Mode, NodeStorage, ShardUId, Store, StoreOpener};
+use near_store::trie::ConstructTrieFromFlatConfig;
+use near_store::{Mode, NodeStorage, ShardUId, Store, StoreOpener};
 use nearcore::{load_config, NearConfig, NightshadeRuntime};
 use std::sync::atomic::AtomicBool;
 use std::{path::PathBuf, sync::Arc, time::Duration};
@@ -52,6 +56,10 @@ enum SubCommand {
 
     /// Move flat head forward.
     MoveFlatHead(MoveFlatHeadCmd),
+
+    /// Serve the output of `view`, the stats of the shards and the history of a key as JSON over
+    /// HTTP, opening the store read-only
+    Serve(ServeCmd),
 }
 
 #[derive(Parser)]
@@ -60,6 +68,15 @@ pub struct ViewCmd {
     shard_id: Option<ShardId>,
 }
 
+#[derive(Parser)]
+pub struct ServeCmd {
+    /// Address to listen on. The view and the stats of a shard are served at
+    /// `/view?shard_id=<shard_id>` and `/stats?shard_id=<shard_id>`, of all shards without the
+    /// `shard_id`. The history of a key is served at `/key_history?shard_id=<shard_id>&key=<hex>`.
+    #[clap(long, default_value = "127.0.0.1:3040")]
+    addr: String,
+}
+
 #[derive(Parser)]
 pub struct ConstructTriedFromFlatCmd {
     #[clap(long)]
@@ -68,6 +85,17 @@ pub struct ConstructTriedFromFlatCmd {
     /// existing DB in the path provided.
     #[clap(long)]
     write_store_path: PathBuf,
+    /// Number of threads reading the flat state while the trie is written. With 1, the trie is
+    /// constructed on a single thread.
+    #[clap(long, default_value = "8")]
+    num_threads: usize,
+    /// Size in MB of the flat state entries applied to the trie at once.
+    #[clap(long, default_value = "500")]
+    batch_size_mb: usize,
+    /// Block whose epoch determines the shard layout: "final", "doomslug", "head", "genesis",
+    /// "earliest", a height or a block hash.
+    #[clap(long, default_value = "final", value_parser = parse_block_reference)]
+    block_ref: BlockReference,
 }
 
 #[derive(Parser)]
@@ -78,6 +106,11 @@ pub struct SetStoreVersionCmd {
 #[derive(Parser)]
 pub struct ResetCmd {
     shard_id: ShardId,
+
+    /// Block whose epoch determines the shard layout: "final", "doomslug", "head", "genesis",
+    /// "earliest", a height or a block hash.
+    #[clap(long, default_value = "final", value_parser = parse_block_reference)]
+    block_ref: BlockReference,
 }
 
 #[derive(Parser)]
@@ -86,11 +119,21 @@ pub struct InitCmd {
 
     #[clap(default_value = "3")]
     num_threads: usize,
+
+    /// Block to create the flat storage at, which also determines the shard layout: "final",
+    /// "doomslug", "head", "genesis", "earliest", a height or a block hash.
+    #[clap(long, default_value = "final", value_parser = parse_block_reference)]
+    block_ref: BlockReference,
 }
 
 #[derive(Parser)]
 pub struct VerifyCmd {
     shard_id: ShardId,
+
+    /// Block whose epoch determines the shard layout: "final", "doomslug", "head", "genesis",
+    /// "earliest", a height or a block hash.
+    #[clap(long, default_value = "final", value_parser = parse_block_reference)]
+    block_ref: BlockReference,
 }
 
 #[derive(Parser)]
@@ -112,6 +155,24 @@ pub struct MoveFlatHeadCmd {
     new_flat_head_height: BlockHeight,
 }
 
+fn parse_block_reference(s: &str) -> Result<BlockReference, String> {
+    let block_reference = match s {
+        "final" => Finality::Final.into(),
+        "doomslug" => Finality::DoomSlug.into(),
+        "head" => Finality::None.into(),
+        "genesis" => BlockReference::SyncCheckpoint(SyncCheckpoint::Genesis),
+        "earliest" => BlockReference::SyncCheckpoint(SyncCheckpoint::EarliestAvailable),
+        _ => match s.parse::<BlockHeight>() {
+            Ok(height) => BlockId::Height(height).into(),
+            Err(_) => BlockId::Hash(
+                s.parse::<CryptoHash>().map_err(|_| format!("invalid block reference: {s}"))?,
+            )
+            .into(),
+        },
+    };
+    Ok(block_reference)
+}
+
 fn print_delta(store: &Store, shard_uid: ShardUId, metadata: FlatStateDeltaMetadata) {
     let changes =
         store_helper::get_delta_changes(store, shard_uid, metadata.block.hash).unwrap().unwrap();
@@ -172,16 +233,7 @@ impl FlatStorageCommand {
         let (.., hot_store) =
             Self::get_db(&opener, home_dir, &near_config, near_store::Mode::ReadOnly);
         println!("DB version: {:?}", hot_store.get_db_version()?);
-        for item in hot_store.iter(DBCol::FlatStorageStatus) {
-            let (bytes_shard_uid, status) = item?;
-            let shard_uid = ShardUId::try_from(bytes_shard_uid.as_ref()).unwrap();
-            let status = FlatStorageStatus::try_from_slice(&status)?;
-            if let Some(shard_id) = cmd.shard_id {
-                if shard_id != shard_uid.shard_id as ShardId {
-                    continue;
-                }
-            }
-
+        for (shard_uid, status) in flat_storage_statuses(&hot_store, cmd.shard_id)? {
             match status {
                 FlatStorageStatus::Ready(ready_status) => {
                     println!(
@@ -198,6 +250,16 @@ impl FlatStorageCommand {
         Ok(())
     }
 
+    fn serve(&self, cmd: &ServeCmd, opener: StoreOpener) -> anyhow::Result<()> {
+        let store = opener.open_in_mode(Mode::ReadOnly)?.get_hot_store();
+        actix::System::new().block_on(async move {
+            let (server, addr) = serve::bind(&cmd.addr, store)?;
+            println!("Serving flat storage at http://{addr}");
+            server.await?;
+            anyhow::Ok(())
+        })
+    }
+
     fn set_store_version(
         &self,
         cmd: &SetStoreVersionCmd,
@@ -219,10 +281,13 @@ impl FlatStorageCommand {
     ) -> anyhow::Result<()> {
         let (_, epoch_manager, rw_hot_runtime, rw_chain_store, store) =
             Self::get_db(&opener, home_dir, &near_config, near_store::Mode::ReadWriteExisting);
-        let tip = rw_chain_store.final_head()?;
-
         // TODO: there should be a method that 'loads' the current flat storage state based on Storage.
-        let shard_uid = epoch_manager.shard_id_to_uid(cmd.shard_id, &tip.epoch_id)?;
+        let shard_uid = resolve_shard_uid_at(
+            &rw_chain_store,
+            epoch_manager.as_ref(),
+            cmd.shard_id,
+            &cmd.block_ref,
+        )?;
         let flat_storage_manager = rw_hot_runtime.get_flat_storage_manager();
         flat_storage_manager.create_flat_storage_for_shard(shard_uid)?;
         let mut store_update = store.store_update();
@@ -241,10 +306,14 @@ impl FlatStorageCommand {
         let (_, epoch_manager, rw_hot_runtime, rw_chain_store, rw_hot_store) =
             Self::get_db(&opener, home_dir, &near_config, near_store::Mode::ReadWriteExisting);
 
-        let tip = rw_chain_store.final_head()?;
-        let shard_uid = epoch_manager.shard_id_to_uid(cmd.shard_id, &tip.epoch_id)?;
-        let mut creator =
-            FlatStorageShardCreator::new(shard_uid, tip.height - 1, epoch_manager, rw_hot_runtime);
+        let header = resolve_block_header(&rw_chain_store, &cmd.block_ref)?;
+        let shard_uid = epoch_manager.shard_id_to_uid(cmd.shard_id, header.epoch_id())?;
+        let mut creator = FlatStorageShardCreator::new(
+            shard_uid,
+            header.height() - 1,
+            epoch_manager,
+            rw_hot_runtime,
+        );
         let pool = rayon::ThreadPoolBuilder::new().num_threads(cmd.num_threads).build()?;
 
         loop {
@@ -271,8 +340,12 @@ impl FlatStorageCommand {
     ) -> anyhow::Result<()> {
         let (_, epoch_manager, hot_runtime, chain_store, hot_store) =
             Self::get_db(&opener, home_dir, &near_config, near_store::Mode::ReadOnly);
-        let tip = chain_store.final_head()?;
-        let shard_uid = epoch_manager.shard_id_to_uid(cmd.shard_id, &tip.epoch_id)?;
+        let shard_uid = resolve_shard_uid_at(
+            &chain_store,
+            epoch_manager.as_ref(),
+            cmd.shard_id,
+            &cmd.block_ref,
+        )?;
 
         let head_hash = match store_helper::get_flat_storage_status(&hot_store, shard_uid)
             .expect("falied to read flat storage status")
@@ -300,9 +373,6 @@ impl FlatStorageCommand {
         let state_root = chunk_extra.state_root();
 
         println!("Verifying using the {:?} as state_root", state_root);
-        let tip = chain_store.final_head()?;
-
-        let shard_uid = epoch_manager.shard_id_to_uid(cmd.shard_id, &tip.epoch_id)?;
         hot_runtime.get_flat_storage_manager().create_flat_storage_for_shard(shard_uid)?;
 
         let trie = hot_runtime.get_view_trie_for_shard(cmd.shard_id, &head_hash, *state_root)?;
@@ -392,10 +462,18 @@ impl FlatStorageCommand {
         let write_node_storage = write_opener.open_in_mode(Mode::Create)?;
         let write_store = write_node_storage.get_hot_store();
 
-        let tip = chain_store.final_head()?;
-        let shard_uid = epoch_manager.shard_id_to_uid(cmd.shard_id, &tip.epoch_id)?;
+        let shard_uid = resolve_shard_uid_at(
+            &chain_store,
+            epoch_manager.as_ref(),
+            cmd.shard_id,
+            &cmd.block_ref,
+        )?;
 
-        near_store::trie::construct_trie_from_flat(store, write_store, shard_uid);
+        let config = ConstructTrieFromFlatConfig {
+            num_threads: cmd.num_threads,
+            batch_size_bytes: cmd.batch_size_mb * 1_000_000,
+        };
+        near_store::trie::construct_trie_from_flat(store, write_store, shard_uid, &config);
         Ok(())
     }
 
@@ -447,6 +525,7 @@ impl FlatStorageCommand {
             SubCommand::MoveFlatHead(cmd) => {
                 self.move_flat_head(cmd, home_dir, &near_config, opener)
             }
+            SubCommand::Serve(cmd) => self.serve(cmd, opener),
         }
     }
 }
@@ -1 +1,2 @@
 pub mod commands;
+mod serve;
@@ -0,0 +1,343 @@
+//! Read-only HTTP endpoint exposing the flat storage state as JSON, for operators which can reach
+//! the node over the network but can't run the tool on its host.
+use actix_web::dev::Server;
+use actix_web::{web, App, HttpServer};
+use near_primitives::hash::CryptoHash;
+use near_primitives::state::FlatStateValue;
+use near_primitives::types::{BlockHeight, ShardId};
+use near_store::flat::{store_helper, FlatStateDeltaMetadata, FlatStorageStatus};
+use near_store::metadata::DbVersion;
+use near_store::{DBCol, ShardUId, Store};
+use std::net::SocketAddr;
+
+/// Same contents as the output of the `view` command, without the changes of the deltas.
+#[derive(serde::Serialize)]
+pub(crate) struct FlatStorageView {
+    pub db_version: Option<DbVersion>,
+    pub shards: Vec<FlatStorageShardView>,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct FlatStorageShardView {
+    pub shard_uid: ShardUId,
+    pub status: FlatStorageStatus,
+    /// Deltas of the flat storage if it is ready, ordered by height.
+    pub deltas: Vec<FlatStateDeltaMetadata>,
+}
+
+/// Size of the flat storage of a shard, in deltas and in the changes they hold.
+#[derive(serde::Serialize)]
+pub(crate) struct FlatStorageShardStats {
+    pub shard_uid: ShardUId,
+    pub status: FlatStorageStatus,
+    pub flat_head_height: Option<BlockHeight>,
+    pub num_deltas: usize,
+    pub num_delta_changes: usize,
+}
+
+/// Value of a key in the flat state, or `None` if it isn't set.
+#[derive(serde::Serialize, PartialEq, Eq, Debug)]
+pub(crate) struct FlatStateValueView {
+    pub hash: CryptoHash,
+    pub length: u32,
+}
+
+/// Change of a key by the delta of a block.
+#[derive(serde::Serialize)]
+pub(crate) struct KeyChange {
+    pub height: BlockHeight,
+    pub block_hash: CryptoHash,
+    pub value: Option<FlatStateValueView>,
+}
+
+/// Value of a key at the flat head of a shard, followed by its changes in the deltas, ordered by
+/// height.
+#[derive(serde::Serialize)]
+pub(crate) struct KeyHistory {
+    pub shard_uid: ShardUId,
+    pub flat_head_value: Option<FlatStateValueView>,
+    pub changes: Vec<KeyChange>,
+}
+
+#[derive(serde::Deserialize)]
+struct ShardQuery {
+    shard_id: Option<ShardId>,
+}
+
+#[derive(serde::Deserialize)]
+struct KeyHistoryQuery {
+    shard_id: ShardId,
+    /// Trie key, hex encoded.
+    key: String,
+}
+
+impl From<FlatStateValue> for FlatStateValueView {
+    fn from(value: FlatStateValue) -> Self {
+        let value_ref = value.to_value_ref();
+        Self { hash: value_ref.hash, length: value_ref.length }
+    }
+}
+
+/// Returns the status of the flat storage of every shard in `store`, or only of `shard_id`.
+pub(crate) fn flat_storage_statuses(
+    store: &Store,
+    shard_id: Option<ShardId>,
+) -> anyhow::Result<Vec<(ShardUId, FlatStorageStatus)>> {
+    let mut statuses = vec![];
+    for item in store.iter(DBCol::FlatStorageStatus) {
+        let (bytes_shard_uid, status) = item?;
+        let shard_uid =
+            ShardUId::try_from(bytes_shard_uid.as_ref()).map_err(|err| anyhow::anyhow!(err))?;
+        if shard_id.map_or(false, |shard_id| shard_id != shard_uid.shard_id as ShardId) {
+            continue;
+        }
+        statuses.push((shard_uid, borsh::BorshDeserialize::try_from_slice(&status)?));
+    }
+    Ok(statuses)
+}
+
+pub(crate) fn flat_storage_view(
+    store: &Store,
+    shard_id: Option<ShardId>,
+) -> anyhow::Result<FlatStorageView> {
+    let mut shards = vec![];
+    for (shard_uid, status) in flat_storage_statuses(store, shard_id)? {
+        let mut deltas = match status {
+            FlatStorageStatus::Ready(_) => store_helper::get_all_deltas_metadata(store, shard_uid)?,
+            _ => vec![],
+        };
+        deltas.sort_by_key(|metadata| metadata.block.height);
+        shards.push(FlatStorageShardView { shard_uid, status, deltas });
+    }
+    Ok(FlatStorageView { db_version: store.get_db_version()?, shards })
+}
+
+pub(crate) fn flat_storage_stats(
+    store: &Store,
+    shard_id: Option<ShardId>,
+) -> anyhow::Result<Vec<FlatStorageShardStats>> {
+    let mut stats = vec![];
+    for (shard_uid, status) in flat_storage_statuses(store, shard_id)? {
+        let (flat_head_height, deltas) = match &status {
+            FlatStorageStatus::Ready(ready_status) => (
+                Some(ready_status.flat_head.height),
+                store_helper::get_all_deltas_metadata(store, shard_uid)?,
+            ),
+            _ => (None, vec![]),
+        };
+        let mut num_delta_changes = 0;
+        for metadata in &deltas {
+            let changes = store_helper::get_delta_changes(store, shard_uid, metadata.block.hash)?;
+            num_delta_changes += changes.map_or(0, |changes| changes.len());
+        }
+        stats.push(FlatStorageShardStats {
+            shard_uid,
+            status,
+            flat_head_height,
+            num_deltas: deltas.len(),
+            num_delta_changes,
+        });
+    }
+    Ok(stats)
+}
+
+/// Returns the history of `key` in the flat storage of `shard_id`, for every shard layout version
+/// the store has a ready flat storage of the shard for.
+pub(crate) fn key_history(
+    store: &Store,
+    shard_id: ShardId,
+    key: &[u8],
+) -> anyhow::Result<Vec<KeyHistory>> {
+    let mut histories = vec![];
+    for (shard_uid, status) in flat_storage_statuses(store, Some(shard_id))? {
+        if !matches!(status, FlatStorageStatus::Ready(_)) {
+            continue;
+        }
+        let db_key = store_helper::encode_flat_state_db_key(shard_uid, key);
+        let flat_head_value =
+            store.get_ser::<FlatStateValue>(DBCol::FlatState, &db_key)?.map(Into::into);
+        let mut deltas = store_helper::get_all_deltas_metadata(store, shard_uid)?;
+        deltas.sort_by_key(|metadata| metadata.block.height);
+        let mut changes = vec![];
+        for metadata in deltas {
+            let delta_changes =
+                store_helper::get_delta_changes(store, shard_uid, metadata.block.hash)?;
+            if let Some(value) = delta_changes.and_then(|changes| changes.get(key)) {
+                changes.push(KeyChange {
+                    height: metadata.block.height,
+                    block_hash: metadata.block.hash,
+                    value: value.map(Into::into),
+                });
+            }
+        }
+        histories.push(KeyHistory { shard_uid, flat_head_value, changes });
+    }
+    Ok(histories)
+}
+
+/// Runs `f` against the store on a blocking thread, turning its error into a server error.
+async fn with_store<T: Send + 'static>(
+    store: web::Data<Store>,
+    f: impl FnOnce(&Store) -> anyhow::Result<T> + Send + 'static,
+) -> actix_web::Result<web::Json<T>> {
+    let result = web::block(move || f(store.get_ref()))
+        .await?
+        .map_err(|err| actix_web::error::ErrorInternalServerError(err.to_string()))?;
+    Ok(web::Json(result))
+}
+
+async fn view_handler(
+    store: web::Data<Store>,
+    query: web::Query<ShardQuery>,
+) -> actix_web::Result<web::Json<FlatStorageView>> {
+    let shard_id = query.shard_id;
+    with_store(store, move |store| flat_storage_view(store, shard_id)).await
+}
+
+async fn stats_handler(
+    store: web::Data<Store>,
+    query: web::Query<ShardQuery>,
+) -> actix_web::Result<web::Json<Vec<FlatStorageShardStats>>> {
+    let shard_id = query.shard_id;
+    with_store(store, move |store| flat_storage_stats(store, shard_id)).await
+}
+
+async fn key_history_handler(
+    store: web::Data<Store>,
+    query: web::Query<KeyHistoryQuery>,
+) -> actix_web::Result<web::Json<Vec<KeyHistory>>> {
+    let KeyHistoryQuery { shard_id, key } = query.into_inner();
+    let key = hex::decode(&key).map_err(actix_web::error::ErrorBadRequest)?;
+    with_store(store, move |store| key_history(store, shard_id, &key)).await
+}
+
+/// Binds the server to `addr`, serving from `store`, which is shared by all the requests. Returns
+/// the server, to be run in an actix system, and the address it is bound to.
+pub(crate) fn bind(addr: &str, store: Store) -> anyhow::Result<(Server, SocketAddr)> {
+    let store = web::Data::new(store);
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(store.clone())
+            .service(web::resource("/view").route(web::get().to(view_handler)))
+            .service(web::resource("/stats").route(web::get().to(stats_handler)))
+            .service(web::resource("/key_history").route(web::get().to(key_history_handler)))
+    })
+    .bind(addr)?
+    .workers(1);
+    let bound_addr = server.addrs()[0];
+    Ok((server.run(), bound_addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bind, flat_storage_view, FlatStateValueView};
+    use near_primitives::hash::hash;
+    use near_primitives::state::FlatStateValue;
+    use near_store::flat::{
+        store_helper, BlockInfo, FlatStateChanges, FlatStateDelta, FlatStateDeltaMetadata,
+        FlatStorageReadyStatus, FlatStorageStatus,
+    };
+    use near_store::test_utils::create_test_store;
+    use near_store::ShardUId;
+
+    #[test]
+    fn test_serve() {
+        let store = create_test_store();
+        let ready_shard_uid = ShardUId { version: 1, shard_id: 0 };
+        let empty_shard_uid = ShardUId { version: 1, shard_id: 1 };
+        let block = |height: u64| BlockInfo {
+            hash: hash(&height.to_le_bytes()),
+            height,
+            prev_hash: hash(&(height - 1).to_le_bytes()),
+        };
+        let mut store_update = store.store_update();
+        store_helper::set_flat_storage_status(
+            &mut store_update,
+            ready_shard_uid,
+            FlatStorageStatus::Ready(FlatStorageReadyStatus { flat_head: block(10) }),
+        );
+        let key = b"key".to_vec();
+        store_helper::set_flat_state_value(
+            &mut store_update,
+            ready_shard_uid,
+            key.clone(),
+            Some(FlatStateValue::on_disk(b"value10")),
+        );
+        for (height, value) in [(12, None), (11, Some(b"value11".as_ref()))] {
+            let metadata =
+                FlatStateDeltaMetadata { block: block(height), prev_block_with_changes: None };
+            let changes = FlatStateChanges::from_raw_key_value(&[(
+                key.clone(),
+                value.map(|value| value.to_vec()),
+            )]);
+            let delta = FlatStateDelta { metadata, changes };
+            store_helper::set_delta(&mut store_update, ready_shard_uid, &delta);
+        }
+        store_helper::set_flat_storage_status(
+            &mut store_update,
+            empty_shard_uid,
+            FlatStorageStatus::Empty,
+        );
+        store_update.commit().unwrap();
+        let view = flat_storage_view(&store, None).unwrap();
+        let deltas: Vec<_> = view.shards[0].deltas.iter().map(|d| d.block.height).collect();
+        assert_eq!(deltas, vec![11, 12]);
+
+        let value_view = |value: &[u8]| {
+            serde_json::to_value(FlatStateValueView::from(FlatStateValue::on_disk(value))).unwrap()
+        };
+        actix::System::new().block_on(async move {
+            let (server, addr) = bind("127.0.0.1:0", store).unwrap();
+            let handle = server.handle();
+            actix::spawn(server);
+            let client = awc::Client::new();
+            let get = |path: &str| {
+                let request = client.get(format!("http://{addr}{path}"));
+                async move {
+                    request.send().await.unwrap().json::<serde_json::Value>().await.unwrap()
+                }
+            };
+
+            assert_eq!(get("/view").await, serde_json::to_value(&view).unwrap());
+            let shard_view = get("/view?shard_id=1").await;
+            assert_eq!(
+                shard_view["shards"],
+                serde_json::json!([{ "shard_uid": "s1.v1", "status": "Empty", "deltas": [] }])
+            );
+
+            let stats = get("/stats").await;
+            assert_eq!(stats[0]["shard_uid"], "s0.v1");
+            assert_eq!(stats[0]["flat_head_height"], 10);
+            assert_eq!(stats[0]["num_deltas"], 2);
+            assert_eq!(stats[0]["num_delta_changes"], 2);
+            assert_eq!(
+                get("/stats?shard_id=1").await,
+                serde_json::json!([{
+                    "shard_uid": "s1.v1",
+                    "status": "Empty",
+                    "flat_head_height": null,
+                    "num_deltas": 0,
+                    "num_delta_changes": 0,
+                }])
+            );
+
+            let history = get(&format!("/key_history?shard_id=0&key={}", hex::encode(&key))).await;
+            assert_eq!(history[0]["flat_head_value"], value_view(b"value10"));
+            let changes = &history[0]["changes"];
+            assert_eq!(changes[0]["height"], 11);
+            assert_eq!(changes[0]["value"], value_view(b"value11"));
+            assert_eq!(changes[1]["height"], 12);
+            assert_eq!(changes[1]["value"], serde_json::Value::Null);
+            let missing_key = get("/key_history?shard_id=0&key=00").await;
+            assert_eq!(missing_key[0]["changes"], serde_json::json!([]));
+            let response =
+                client.get(format!("http://{addr}/key_history?shard_id=0&key=xyz")).send().await;
+            assert!(response.unwrap().status().is_client_error());
+
+            // The server is read-only.
+            let response = client.post(format!("http://{addr}/view")).send().await.unwrap();
+            assert!(!response.status().is_success());
+            handle.stop(true).await;
+        });
+    }
+}
@@ -1,4 +1,5 @@
 use borsh::{BorshDeserialize, BorshSerialize};
+use near_chain::missing_chunks::MissingChunksPoolLimits;
 use near_chain::types::{ChainConfig, Tip};
 use near_chain::{Chain, ChainGenesis, DoomslugThresholdMode};
 use near_chain_configs::{GenesisValidationMode, StateSplitConfig};
@@ -244,6 +245,7 @@ fn load_snapshot(load_cmd: LoadCmd) {
             save_trie_changes: config.client_config.save_trie_changes,
             background_migration_threads: 1,
             state_split_config: StateSplitConfig::default(),
+            missing_chunks_pool_limits: MissingChunksPoolLimits::default(),
         },
         None,
     )